@@ -0,0 +1,45 @@
+use percent_encoding::percent_decode_str;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Error, Debug, Clone)]
+pub enum CanonicalError {
+	#[error("invalid UTF-8 in name")]
+	InvalidUtf8,
+
+	#[error("double encoded name")]
+	DoubleEncoded,
+}
+
+/// Canonicalize a namespace, so alternate encodings of the same name share a claim, cache entry and policy decision.
+///
+/// The name is percent-decoded, normalized to Unicode NFC, and repeated slashes are collapsed.
+/// Names that are invalid UTF-8 once decoded, or still contain an escape (ex. double encoded), are rejected.
+pub fn canonical_namespace(namespace: &str) -> Result<String, CanonicalError> {
+	let decoded = decode(namespace)?;
+	Ok(collapse_slashes(&decoded.nfc().collect::<String>()))
+}
+
+fn decode(name: &str) -> Result<String, CanonicalError> {
+	let decoded = percent_decode_str(name)
+		.decode_utf8()
+		.map_err(|_| CanonicalError::InvalidUtf8)?;
+
+	// Otherwise decoding twice would produce a different name.
+	if percent_decode_str(&decoded).decode_utf8_lossy() != decoded {
+		return Err(CanonicalError::DoubleEncoded);
+	}
+
+	Ok(decoded.into_owned())
+}
+
+fn collapse_slashes(name: &str) -> String {
+	let mut out = String::with_capacity(name.len());
+	for c in name.chars() {
+		if c == '/' && out.ends_with('/') {
+			continue;
+		}
+		out.push(c);
+	}
+	out
+}
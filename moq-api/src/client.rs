@@ -1,6 +1,6 @@
 use url::Url;
 
-use crate::{ApiError, Origin};
+use crate::{ApiError, KeySignature, NamespaceKey, Origin, RelayLoad};
 
 #[derive(Clone)]
 pub struct Client {
@@ -53,4 +53,73 @@ impl Client {
 
 		Ok(())
 	}
+
+	/// Return the least loaded relay, or None if no relay has reported recently.
+	pub async fn get_relay(&self) -> Result<Option<RelayLoad>, ApiError> {
+		let url = self.url.join("relay")?;
+		let resp = self.client.get(url).send().await?;
+		if resp.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+
+		let relay: RelayLoad = resp.error_for_status()?.json().await?;
+		Ok(Some(relay))
+	}
+
+	/// Report the relay's load, which must be repeated before it expires to keep receiving publishers.
+	pub async fn set_relay(&self, relay: &RelayLoad) -> Result<(), ApiError> {
+		let url = self.url.join("relay")?;
+
+		let resp = self.client.post(url).json(relay).send().await?;
+		resp.error_for_status()?;
+
+		Ok(())
+	}
+
+	pub async fn get_key(&self, namespace: &str) -> Result<Option<NamespaceKey>, ApiError> {
+		let url = self.url.join("key/")?.join(namespace)?;
+		let resp = self.client.get(url).send().await?;
+		if resp.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+
+		let key: NamespaceKey = resp.error_for_status()?.json().await?;
+		Ok(Some(key))
+	}
+
+	/// Claim the namespace for the key, failing if it's already claimed by another key.
+	///
+	/// An existing key can only be replaced with a [KeySignature] made by it.
+	pub async fn set_key(
+		&self,
+		namespace: &str,
+		key: NamespaceKey,
+		signature: Option<&KeySignature>,
+	) -> Result<(), ApiError> {
+		let url = self.url.join("key/")?.join(namespace)?;
+
+		let mut req = self.client.post(url).json(&key);
+		if let Some(signature) = signature {
+			req = req.header(KeySignature::HEADER, signature.to_header());
+		}
+
+		req.send().await?.error_for_status()?;
+
+		Ok(())
+	}
+
+	/// Release the namespace, with a [KeySignature] made by the key claiming it.
+	pub async fn delete_key(&self, namespace: &str, signature: &KeySignature) -> Result<(), ApiError> {
+		let url = self.url.join("key/")?.join(namespace)?;
+
+		let resp = self
+			.client
+			.delete(url)
+			.header(KeySignature::HEADER, signature.to_header())
+			.send()
+			.await?;
+		resp.error_for_status()?;
+
+		Ok(())
+	}
 }
@@ -1,7 +1,9 @@
+mod canonical;
 mod client;
 mod error;
 mod model;
 
+pub use canonical::*;
 pub use client::*;
 pub use error::*;
 pub use model::*;
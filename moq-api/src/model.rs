@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use serde::{Deserialize, Serialize};
 
 use url::Url;
@@ -6,3 +8,79 @@ use url::Url;
 pub struct Origin {
 	pub url: Url,
 }
+
+/// The public key that must sign announces for a namespace, claiming it for the key's owner.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct NamespaceKey {
+	/// A hex encoded Ed25519 public key.
+	pub public_key: String,
+}
+
+/// Authorizes replacing or deleting the key claiming a namespace, signed by the key currently registered.
+///
+/// Sent in the [KeySignature::HEADER] header as `<timestamp>.<hex encoded signature>`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct KeySignature {
+	/// When the change was signed, in milliseconds since the Unix epoch, so old signatures can be refused.
+	pub timestamp: u64,
+
+	/// The Ed25519 signature over [Self::payload].
+	pub signature: Vec<u8>,
+}
+
+impl KeySignature {
+	pub const HEADER: &'static str = "moq-key-signature";
+
+	// Prevents a signature made for something else, ex. an announce, from authorizing a change.
+	const CONTEXT: &'static [u8] = b"moq-key-v1";
+
+	/// Sign the change with the current time, where `public_key` is the replacement or None to delete the key.
+	pub fn new(namespace: &str, public_key: Option<&str>, sign: impl FnOnce(&[u8]) -> Vec<u8>) -> Self {
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_millis() as u64;
+
+		Self {
+			timestamp,
+			signature: sign(&Self::payload(namespace, public_key, timestamp)),
+		}
+	}
+
+	/// The bytes that are signed: a fixed context, the namespace, the replacement key if any, and the timestamp.
+	pub fn payload(namespace: &str, public_key: Option<&str>, timestamp: u64) -> Vec<u8> {
+		let public_key = public_key.unwrap_or_default();
+
+		let mut payload = Self::CONTEXT.to_vec();
+		for field in [namespace, public_key] {
+			payload.extend_from_slice(&(field.len() as u64).to_be_bytes());
+			payload.extend_from_slice(field.as_bytes());
+		}
+		payload.extend_from_slice(&timestamp.to_be_bytes());
+		payload
+	}
+
+	pub fn to_header(&self) -> String {
+		format!("{}.{}", self.timestamp, hex::encode(&self.signature))
+	}
+
+	/// Returns None if the header is malformed.
+	pub fn from_header(value: &str) -> Option<Self> {
+		let (timestamp, signature) = value.split_once('.')?;
+
+		Some(Self {
+			timestamp: timestamp.parse().ok()?,
+			signature: hex::decode(signature).ok()?,
+		})
+	}
+}
+
+/// The load reported periodically by each relay, used to send publishers to the least loaded one.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct RelayLoad {
+	/// The URL that clients use to connect to the relay.
+	pub url: Url,
+
+	/// The number of active sessions.
+	pub sessions: u64,
+}
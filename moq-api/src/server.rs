@@ -1,8 +1,11 @@
-use std::net;
+use std::{
+	net,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use axum::{
 	extract::{Path, State},
-	http::StatusCode,
+	http::{HeaderMap, StatusCode},
 	response::{IntoResponse, Response},
 	routing::get,
 	Json, Router,
@@ -12,7 +15,7 @@ use clap::Parser;
 
 use redis::{aio::ConnectionManager, AsyncCommands};
 
-use moq_api::{ApiError, Origin};
+use moq_api::{canonical_namespace, ApiError, KeySignature, NamespaceKey, Origin, RelayLoad};
 
 /// Runs a HTTP API to create/get origins for broadcasts.
 #[derive(Parser, Debug)]
@@ -51,6 +54,8 @@ impl Server {
 					.delete(delete_origin)
 					.patch(patch_origin),
 			)
+			.route("/key/*namespace", get(get_key).post(set_key).delete(delete_key))
+			.route("/relay", get(get_relay).post(set_relay))
 			.with_state(redis);
 
 		log::info!("serving requests: bind={}", self.config.bind);
@@ -155,6 +160,211 @@ fn origin_key(namespace: &str) -> String {
 	format!("origin.{}", namespace)
 }
 
+// A relay is forgotten unless it reports again within this many seconds.
+const RELAY_TTL: u64 = 30;
+
+// The relays by load, lowest first.
+const RELAYS_KEY: &str = "relays";
+
+// Return the least loaded relay that reported recently.
+//
+// Its load is bumped, so relays with the same load are handed out in turn until they report again.
+async fn get_relay(State(mut redis): State<ConnectionManager>) -> Result<Json<RelayLoad>, AppError> {
+	loop {
+		let least: Vec<(String, f64)> = redis.zrange_withscores(RELAYS_KEY, 0, 0).await?;
+		let (url, sessions) = least.into_iter().next().ok_or(AppError::NotFound)?;
+
+		// Skip relays that stopped reporting, removing them for good.
+		let alive: bool = redis.exists(relay_key(&url)).await?;
+		let parsed = match alive {
+			true => url::Url::parse(&url).ok(),
+			false => None,
+		};
+
+		let url = match parsed {
+			Some(parsed) => parsed,
+			None => {
+				let _: u64 = redis.zrem(RELAYS_KEY, &url).await?;
+				continue;
+			}
+		};
+
+		let _: f64 = redis.zincr(RELAYS_KEY, url.as_str(), 1).await?;
+
+		return Ok(Json(RelayLoad {
+			url,
+			sessions: sessions as u64,
+		}));
+	}
+}
+
+async fn set_relay(State(mut redis): State<ConnectionManager>, Json(relay): Json<RelayLoad>) -> Result<(), AppError> {
+	let url = relay.url.to_string();
+
+	let _: () = redis.set_ex(relay_key(&url), relay.sessions, RELAY_TTL).await?;
+	let _: u64 = redis.zadd(RELAYS_KEY, &url, relay.sessions).await?;
+
+	Ok(())
+}
+
+fn relay_key(url: &str) -> String {
+	format!("relay.{}", url)
+}
+
+async fn get_key(
+	Path(namespace): Path<String>,
+	State(mut redis): State<ConnectionManager>,
+) -> Result<Json<NamespaceKey>, AppError> {
+	let payload: Option<String> = redis.get(namespace_key(&namespace)).await?;
+	let payload = payload.ok_or(AppError::NotFound)?;
+	let key: NamespaceKey = serde_json::from_str(&payload)?;
+
+	Ok(Json(key))
+}
+
+// Claim the namespace; unlike origins, the key doesn't expire.
+// Replacing an existing key requires a signature from it, so a namespace can't be taken over.
+async fn set_key(
+	State(mut redis): State<ConnectionManager>,
+	Path(namespace): Path<String>,
+	headers: HeaderMap,
+	Json(claim): Json<NamespaceKey>,
+) -> Result<(), AppError> {
+	check_namespace(&namespace)?;
+	check_public_key(&claim.public_key)?;
+
+	let key = namespace_key(&namespace);
+	let payload = serde_json::to_string(&claim)?;
+
+	let current: Option<String> = redis.get(&key).await?;
+	let current = match current {
+		// Registering the same key again is fine.
+		Some(current) if current == payload => return Ok(()),
+		Some(current) => current,
+		None => {
+			let res: Option<String> = redis::cmd("SET")
+				.arg(key)
+				.arg(payload)
+				.arg("NX")
+				.query_async(&mut redis)
+				.await?;
+
+			return res.map(|_| ()).ok_or(AppError::Duplicate);
+		}
+	};
+
+	let existing: NamespaceKey = serde_json::from_str(&current)?;
+	authorize(&headers, &namespace, &existing, Some(&claim.public_key), now())?;
+
+	// Fail if the key changed since it was checked.
+	let replaced: Option<String> = redis::Script::new(REPLACE_SCRIPT)
+		.key(key)
+		.arg(current)
+		.arg(payload)
+		.invoke_async(&mut redis)
+		.await?;
+
+	replaced.map(|_| ()).ok_or(AppError::Duplicate)
+}
+
+// The relay looks up claims by the canonical namespace, so a claim stored under another spelling wouldn't protect it.
+fn check_namespace(namespace: &str) -> Result<(), AppError> {
+	match canonical_namespace(namespace) {
+		Ok(canonical) if canonical == namespace => Ok(()),
+		_ => Err(AppError::InvalidNamespace),
+	}
+}
+
+// Otherwise nobody could sign for the claim, including the publisher that made it.
+fn check_public_key(public_key: &str) -> Result<(), AppError> {
+	match hex::decode(public_key) {
+		Ok(key) if key.len() == 32 => Ok(()),
+		_ => Err(AppError::InvalidKey),
+	}
+}
+
+// Release the namespace, which requires a signature from the key claiming it.
+async fn delete_key(
+	Path(namespace): Path<String>,
+	State(mut redis): State<ConnectionManager>,
+	headers: HeaderMap,
+) -> Result<(), AppError> {
+	let key = namespace_key(&namespace);
+
+	let current: Option<String> = redis.get(&key).await?;
+	let current = current.ok_or(AppError::NotFound)?;
+
+	let existing: NamespaceKey = serde_json::from_str(&current)?;
+	authorize(&headers, &namespace, &existing, None, now())?;
+
+	let deleted: u64 = redis::Script::new(DELETE_SCRIPT)
+		.key(key)
+		.arg(current)
+		.invoke_async(&mut redis)
+		.await?;
+
+	match deleted {
+		0 => Err(AppError::Duplicate),
+		_ => Ok(()),
+	}
+}
+
+// Replace KEYS[1] with ARGV[2] only if it's still ARGV[1].
+const REPLACE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+	return redis.call('SET', KEYS[1], ARGV[2])
+end
+return false
+";
+
+// Delete KEYS[1] only if it's still ARGV[1].
+const DELETE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+	return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+// Reject signatures made longer ago than this, or this far in the future, so a captured one can't be replayed later.
+const KEY_SIGNATURE_MAX_AGE: Duration = Duration::from_secs(30);
+
+// Check the change to the namespace was signed by the existing key.
+fn authorize(
+	headers: &HeaderMap,
+	namespace: &str,
+	existing: &NamespaceKey,
+	replacement: Option<&str>,
+	now: Duration,
+) -> Result<(), AppError> {
+	let header = headers.get(KeySignature::HEADER).ok_or(AppError::Unauthorized)?;
+	let signature = header
+		.to_str()
+		.ok()
+		.and_then(KeySignature::from_header)
+		.ok_or(AppError::Forbidden)?;
+
+	let signed = Duration::from_millis(signature.timestamp);
+	let age = now.checked_sub(signed).unwrap_or_else(|| signed - now);
+	if age > KEY_SIGNATURE_MAX_AGE {
+		return Err(AppError::Forbidden);
+	}
+
+	let public_key = hex::decode(&existing.public_key).map_err(|_| AppError::Forbidden)?;
+	let payload = KeySignature::payload(namespace, replacement, signature.timestamp);
+
+	ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key)
+		.verify(&payload, &signature.signature)
+		.map_err(|_| AppError::Forbidden)
+}
+
+fn now() -> Duration {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+fn namespace_key(namespace: &str) -> String {
+	format!("key.{}", namespace)
+}
+
 #[derive(thiserror::Error, Debug)]
 enum AppError {
 	#[error("redis error")]
@@ -168,6 +378,18 @@ enum AppError {
 
 	#[error("duplicate ID")]
 	Duplicate,
+
+	#[error("namespace is not canonical")]
+	InvalidNamespace,
+
+	#[error("invalid public key")]
+	InvalidKey,
+
+	#[error("signature required")]
+	Unauthorized,
+
+	#[error("invalid signature")]
+	Forbidden,
 }
 
 // Tell axum how to convert `AppError` into a response.
@@ -178,6 +400,79 @@ impl IntoResponse for AppError {
 			AppError::Json(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("json error: {}", e)).into_response(),
 			AppError::NotFound => StatusCode::NOT_FOUND.into_response(),
 			AppError::Duplicate => StatusCode::CONFLICT.into_response(),
+			AppError::InvalidNamespace => (StatusCode::BAD_REQUEST, "namespace is not canonical").into_response(),
+			AppError::InvalidKey => (StatusCode::BAD_REQUEST, "public key must be 32 bytes of hex").into_response(),
+			AppError::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
+			AppError::Forbidden => StatusCode::FORBIDDEN.into_response(),
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ring::signature::{Ed25519KeyPair, KeyPair};
+
+	fn generate() -> Ed25519KeyPair {
+		let rng = ring::rand::SystemRandom::new();
+		let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+		Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+	}
+
+	fn headers(signature: &KeySignature) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		headers.insert(KeySignature::HEADER, signature.to_header().parse().unwrap());
+		headers
+	}
+
+	#[test]
+	fn authorize_change() {
+		let owner = generate();
+		let existing = NamespaceKey {
+			public_key: hex::encode(owner.public_key()),
+		};
+		let sign = |pair: &Ed25519KeyPair, replacement: Option<&str>| {
+			KeySignature::new("live/alice", replacement, |payload| {
+				pair.sign(payload).as_ref().to_vec()
+			})
+		};
+
+		// Unsigned changes are refused outright.
+		assert!(matches!(
+			authorize(&HeaderMap::new(), "live/alice", &existing, None, now()),
+			Err(AppError::Unauthorized)
+		));
+
+		let delete = sign(&owner, None);
+		authorize(&headers(&delete), "live/alice", &existing, None, now()).unwrap();
+
+		// The signature only covers the change it was made for.
+		assert!(authorize(&headers(&delete), "live/alice", &existing, Some("beef"), now()).is_err());
+		assert!(authorize(&headers(&delete), "live/mallory", &existing, None, now()).is_err());
+
+		let replace = sign(&owner, Some("beef"));
+		authorize(&headers(&replace), "live/alice", &existing, Some("beef"), now()).unwrap();
+
+		// Another key can't sign for the owner, and old signatures can't be replayed.
+		let mallory = sign(&generate(), None);
+		assert!(authorize(&headers(&mallory), "live/alice", &existing, None, now()).is_err());
+
+		let later = now() + KEY_SIGNATURE_MAX_AGE * 2;
+		assert!(authorize(&headers(&delete), "live/alice", &existing, None, later).is_err());
+	}
+
+	#[test]
+	fn check_claim() {
+		check_namespace("live/alice").unwrap();
+		check_namespace("café").unwrap();
+
+		// Other spellings of the same namespace would be stored under a key the relay never reads.
+		assert!(matches!(check_namespace("caf%C3%A9"), Err(AppError::InvalidNamespace)));
+		assert!(matches!(check_namespace("live//alice"), Err(AppError::InvalidNamespace)));
+		assert!(matches!(check_namespace("cafe\u{301}"), Err(AppError::InvalidNamespace)));
+
+		check_public_key(&hex::encode(generate().public_key())).unwrap();
+		assert!(matches!(check_public_key("beef"), Err(AppError::InvalidKey)));
+		assert!(matches!(check_public_key("not hex"), Err(AppError::InvalidKey)));
+	}
+}
@@ -0,0 +1,207 @@
+//! Delta updates for the catalog track, so a change doesn't require re-sending and re-parsing the full catalog.
+//!
+//! Each group starts with the full catalog as object 0.
+//! Any further objects in the group are JSON Patch (RFC 6902) documents, each applied to the result of the previous one.
+//! A new group replaces the catalog entirely, so a subscriber joining at the latest group can always rebuild it.
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::Root;
+
+/// The catalog schema version produced and understood by this crate.
+pub const CATALOG_VERSION: u16 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CatalogError {
+	#[error("json error: {0}")]
+	Json(#[from] serde_json::Error),
+
+	#[error("patch error: {0}")]
+	Patch(#[from] json_patch::PatchError),
+
+	#[error("unsupported catalog version: {0}")]
+	Version(u16),
+
+	#[error("delta update without a full catalog")]
+	MissingFull,
+}
+
+/// An encoded catalog update.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatalogUpdate {
+	/// The full catalog, which must start a new group.
+	Full(Vec<u8>),
+
+	/// A JSON Patch, which must be appended to the current group.
+	Delta(Vec<u8>),
+}
+
+/// Encodes each new version of the catalog as a delta from the previous version, when it's worthwhile.
+pub struct CatalogEncoder {
+	current: Option<Value>,
+	deltas: usize,
+	max_deltas: usize,
+}
+
+impl Default for CatalogEncoder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl CatalogEncoder {
+	pub fn new() -> Self {
+		Self {
+			current: None,
+			deltas: 0,
+			max_deltas: 16,
+		}
+	}
+
+	/// Send the full catalog after this many deltas, so late joiners don't have to apply a long chain.
+	pub fn with_max_deltas(mut self, max_deltas: usize) -> Self {
+		self.max_deltas = max_deltas;
+		self
+	}
+
+	/// Returns the update to publish, or None if the catalog is unchanged.
+	///
+	/// A delta is only used when it's smaller than the full catalog.
+	pub fn encode(&mut self, root: &Root) -> Result<Option<CatalogUpdate>, CatalogError> {
+		let value = serde_json::to_value(root)?;
+		let full = serde_json::to_vec(&value)?;
+
+		let prev = match self.current.replace(value.clone()) {
+			Some(prev) if prev == value => return Ok(None),
+			Some(prev) if self.deltas < self.max_deltas => prev,
+			_ => {
+				self.deltas = 0;
+				return Ok(Some(CatalogUpdate::Full(full)));
+			}
+		};
+
+		let delta = serde_json::to_vec(&json_patch::diff(&prev, &value))?;
+		if delta.len() >= full.len() {
+			self.deltas = 0;
+			return Ok(Some(CatalogUpdate::Full(full)));
+		}
+
+		self.deltas += 1;
+		Ok(Some(CatalogUpdate::Delta(delta)))
+	}
+}
+
+/// Rebuilds the catalog from the objects in the catalog track.
+#[derive(Default)]
+pub struct CatalogDecoder {
+	current: Option<Value>,
+}
+
+impl CatalogDecoder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Decode the next object in a group, where object 0 is the full catalog and the rest are deltas.
+	pub fn decode(&mut self, object_id: u64, payload: &[u8]) -> Result<Root, CatalogError> {
+		match object_id {
+			0 => self.full(payload),
+			_ => self.delta(payload),
+		}
+	}
+
+	/// Replace the catalog, ex. at the start of a group.
+	pub fn full(&mut self, payload: &[u8]) -> Result<Root, CatalogError> {
+		let value: Value = serde_json::from_slice(payload)?;
+		let root = Self::parse(&value)?;
+		self.current = Some(value);
+		Ok(root)
+	}
+
+	/// Apply a JSON Patch to the current catalog.
+	///
+	/// The catalog is left unchanged if the patch fails.
+	pub fn delta(&mut self, payload: &[u8]) -> Result<Root, CatalogError> {
+		let patch: json_patch::Patch = serde_json::from_slice(payload)?;
+
+		let mut value = self.current.clone().ok_or(CatalogError::MissingFull)?;
+		json_patch::patch(&mut value, &patch)?;
+
+		let root = Self::parse(&value)?;
+		self.current = Some(value);
+		Ok(root)
+	}
+
+	fn parse(value: &Value) -> Result<Root, CatalogError> {
+		let root = Root::deserialize(value)?;
+		if root.version != CATALOG_VERSION {
+			return Err(CatalogError::Version(root.version));
+		}
+
+		Ok(root)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{CommonTrackFields, Track};
+
+	fn catalog(tracks: &[&str]) -> Root {
+		Root {
+			version: CATALOG_VERSION,
+			streaming_format: 1,
+			streaming_format_version: "0.2".to_string(),
+			streaming_delta_updates: true,
+			common_track_fields: CommonTrackFields::default(),
+			tracks: tracks
+				.iter()
+				.map(|name| Track {
+					name: name.to_string(),
+					..Default::default()
+				})
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn delta() {
+		let mut encoder = CatalogEncoder::new();
+		let mut decoder = CatalogDecoder::new();
+
+		let first = catalog(&["1.m4s", "2.m4s", "3.m4s"]);
+		let full = match encoder.encode(&first).unwrap() {
+			Some(CatalogUpdate::Full(full)) => full,
+			other => panic!("expected full catalog: {:?}", other),
+		};
+		assert_eq!(decoder.decode(0, &full).unwrap().tracks.len(), 3);
+		assert_eq!(encoder.encode(&first).unwrap(), None);
+
+		let second = catalog(&["1.m4s", "2.m4s", "3.m4s", "4.m4s"]);
+		let delta = match encoder.encode(&second).unwrap() {
+			Some(CatalogUpdate::Delta(delta)) => delta,
+			other => panic!("expected delta: {:?}", other),
+		};
+
+		let root = decoder.decode(1, &delta).unwrap();
+		assert_eq!(root.tracks.last().unwrap().name, "4.m4s");
+
+		// A delta can't be applied without a full catalog.
+		assert!(matches!(
+			CatalogDecoder::new().decode(1, &delta),
+			Err(CatalogError::MissingFull)
+		));
+	}
+
+	#[test]
+	fn version() {
+		let mut root = catalog(&["1.m4s"]);
+		root.version = 2;
+
+		let full = serde_json::to_vec(&root).unwrap();
+		assert!(matches!(
+			CatalogDecoder::new().full(&full),
+			Err(CatalogError::Version(2))
+		));
+	}
+}
@@ -0,0 +1,185 @@
+//! A compact index of each track's groups, so a DVR player can seek without downloading any media.
+//!
+//! The publisher periodically writes the full [GroupIndex] as a JSON object to the [INDEX_TRACK] of the broadcast.
+//! Each index is its own group, so a new subscriber only receives the latest one.
+//! A player looks up the groups around a seek time and asks the relay's cache for that range with FETCH.
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::CatalogError;
+
+/// The name of the track carrying the group index.
+pub const INDEX_TRACK: &str = ".index";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupIndex {
+	/// The groups of each media track.
+	pub tracks: Vec<TrackIndex>,
+}
+
+impl GroupIndex {
+	pub fn track(&self, name: &str) -> Option<&TrackIndex> {
+		self.tracks.iter().find(|track| track.name == name)
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrackIndex {
+	pub name: String,
+
+	/// Each group ID and the media timestamp of its first object in microseconds, in group order.
+	/// Encoded as `[group, timestamp]` pairs to keep the index small.
+	pub groups: Vec<(u64, u64)>,
+}
+
+impl TrackIndex {
+	/// The group containing the media timestamp, clamped to the first and last groups.
+	pub fn seek(&self, timestamp: u64) -> Option<u64> {
+		let after = self.groups.partition_point(|&(_, start)| start <= timestamp);
+		let (group_id, _) = self.groups.get(after.saturating_sub(1))?;
+		Some(*group_id)
+	}
+
+	/// The groups to FETCH to cover the media timestamps from start to end, inclusive.
+	pub fn range(&self, start: u64, end: u64) -> Option<(u64, u64)> {
+		Some((self.seek(start)?, self.seek(end.max(start))?))
+	}
+}
+
+/// Collects the start of each group and encodes the index whenever it changes.
+pub struct IndexWriter {
+	tracks: BTreeMap<String, VecDeque<(u64, u64)>>,
+	max_groups: usize,
+	changed: bool,
+}
+
+impl Default for IndexWriter {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl IndexWriter {
+	pub fn new() -> Self {
+		Self {
+			tracks: BTreeMap::new(),
+			max_groups: 1024,
+			changed: false,
+		}
+	}
+
+	/// Only keep this many of the latest groups per track, since older ones have likely left the relay's cache.
+	pub fn with_max_groups(mut self, max_groups: usize) -> Self {
+		self.max_groups = max_groups.max(1);
+		self
+	}
+
+	/// Record the start of a group, given the media timestamp of its first object in microseconds.
+	pub fn record(&mut self, track: &str, group_id: u64, timestamp: u64) {
+		let groups = self.tracks.entry(track.to_string()).or_default();
+
+		// The publisher restarted numbering, so the earlier groups no longer exist.
+		if groups.back().is_some_and(|&(last, _)| last >= group_id) {
+			groups.clear();
+		}
+
+		groups.push_back((group_id, timestamp));
+		while groups.len() > self.max_groups {
+			groups.pop_front();
+		}
+
+		self.changed = true;
+	}
+
+	/// Returns the index to publish as a new group, or None if nothing was recorded since the last one.
+	pub fn encode(&mut self) -> Result<Option<Vec<u8>>, CatalogError> {
+		if !std::mem::take(&mut self.changed) {
+			return Ok(None);
+		}
+
+		let index = GroupIndex {
+			tracks: self
+				.tracks
+				.iter()
+				.map(|(name, groups)| TrackIndex {
+					name: name.clone(),
+					groups: groups.iter().copied().collect(),
+				})
+				.collect(),
+		};
+
+		Ok(Some(serde_json::to_vec(&index)?))
+	}
+}
+
+/// Keeps the latest index received on the index track.
+#[derive(Default)]
+pub struct IndexReader {
+	current: GroupIndex,
+}
+
+impl IndexReader {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Replace the index with the payload of a new group.
+	pub fn decode(&mut self, payload: &[u8]) -> Result<&GroupIndex, CatalogError> {
+		self.current = serde_json::from_slice(payload)?;
+		Ok(&self.current)
+	}
+
+	pub fn index(&self) -> &GroupIndex {
+		&self.current
+	}
+
+	/// The group of the track containing the media timestamp in microseconds, see [TrackIndex::seek].
+	pub fn seek(&self, track: &str, timestamp: u64) -> Option<u64> {
+		self.current.track(track)?.seek(timestamp)
+	}
+
+	/// The groups of the track covering the media timestamps in microseconds, see [TrackIndex::range].
+	pub fn range(&self, track: &str, start: u64, end: u64) -> Option<(u64, u64)> {
+		self.current.track(track)?.range(start, end)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn seek() {
+		let mut writer = IndexWriter::new().with_max_groups(3);
+		for group_id in 0..5 {
+			writer.record("video", group_id, group_id * 2_000_000);
+		}
+
+		let payload = writer.encode().unwrap().unwrap();
+		assert_eq!(writer.encode().unwrap(), None);
+
+		let mut reader = IndexReader::new();
+		let track = reader.decode(&payload).unwrap().track("video").unwrap();
+		assert_eq!(track.groups, vec![(2, 4_000_000), (3, 6_000_000), (4, 8_000_000)]);
+
+		assert_eq!(reader.seek("video", 5_000_000), Some(2));
+		assert_eq!(reader.seek("video", 6_000_000), Some(3));
+
+		// Seeking outside of the index is clamped to the groups that remain.
+		assert_eq!(reader.seek("video", 0), Some(2));
+		assert_eq!(reader.range("video", 7_000_000, 60_000_000), Some((3, 4)));
+		assert_eq!(reader.seek("audio", 0), None);
+	}
+
+	#[test]
+	fn restart() {
+		let mut writer = IndexWriter::new();
+		writer.record("video", 7, 14_000_000);
+		writer.record("video", 0, 0);
+
+		let payload = writer.encode().unwrap().unwrap();
+		let mut reader = IndexReader::new();
+		assert_eq!(reader.decode(&payload).unwrap().tracks[0].groups, vec![(0, 0)]);
+	}
+}
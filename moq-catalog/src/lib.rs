@@ -5,6 +5,14 @@
 /// https://www.ietf.org/archive/id/draft-ietf-moq-catalogformat-01.html
 use serde::{Deserialize, Serialize};
 
+mod delta;
+mod index;
+mod report;
+
+pub use delta::*;
+pub use index::*;
+pub use report::*;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Root {
 	pub version: u16,
@@ -0,0 +1,33 @@
+//! Sender reports, an RTCP-like mechanism for measuring delivery within MoQ.
+//!
+//! The publisher periodically writes a [SenderReport] as a JSON object to the [REPORT_TRACK] of the broadcast.
+//! Each report is its own group, so a new subscriber only receives the latest one.
+//! Subscribers compare the change between reports with what they received to estimate loss and lag.
+use serde::{Deserialize, Serialize};
+
+/// The name of the track carrying sender reports.
+pub const REPORT_TRACK: &str = ".reports";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SenderReport {
+	/// The wall clock time when the report was sent, in microseconds since the UNIX epoch.
+	pub timestamp: u64,
+
+	/// The cumulative counters for each media track.
+	pub tracks: Vec<TrackReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrackReport {
+	pub name: String,
+
+	/// The number of objects sent since the publisher started.
+	pub objects: u64,
+
+	/// The number of payload bytes sent since the publisher started.
+	pub bytes: u64,
+
+	/// The sequence number of the latest group, if any.
+	#[serde(rename = "lastGroup", skip_serializing_if = "Option::is_none")]
+	pub last_group: Option<u64>,
+}
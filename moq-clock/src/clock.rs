@@ -1,7 +1,7 @@
 use anyhow::Context;
 use moq_transport::serve::{
-	DatagramsReader, Group, GroupWriter, GroupsReader, GroupsWriter, ObjectsReader, StreamReader, TrackReader,
-	TrackReaderMode,
+	DatagramsReader, Group, GroupTimestamp, GroupWriter, GroupsReader, GroupsWriter, ObjectsReader, StreamReader,
+	TrackReader, TrackReaderMode,
 };
 
 use chrono::prelude::*;
@@ -28,6 +28,13 @@ impl Publisher {
 				.create(Group {
 					group_id: sequence as u64,
 					priority: 0,
+					timestamp: GroupTimestamp {
+						wall: now.timestamp_micros().try_into().ok(),
+						..Default::default()
+					},
+					annotations: Default::default(),
+					subgroups: 0,
+					transit: None,
 				})
 				.context("failed to create minute segment")?;
 
@@ -10,6 +10,7 @@ mod clock;
 use moq_transport::{
 	serve,
 	session::{Publisher, Subscriber},
+	setup,
 };
 
 #[derive(Parser, Clone)]
@@ -26,6 +27,18 @@ pub struct Cli {
 	#[command(flatten)]
 	pub tls: moq_native::tls::Args,
 
+	/// The log sampling configuration.
+	#[command(flatten)]
+	pub log: moq_native::log::Args,
+
+	/// Sign the announce when publishing, to prove the namespace belongs to us.
+	#[command(flatten)]
+	pub sign: moq_native::sign::Args,
+
+	/// Identify the broadcast across relays when publishing.
+	#[command(flatten)]
+	pub broadcast: moq_native::broadcast::Args,
+
 	/// Publish the current time to the relay, otherwise only subscribe.
 	#[arg(long)]
 	pub publish: bool,
@@ -50,34 +63,55 @@ async fn main() -> anyhow::Result<()> {
 	tracing::subscriber::set_global_default(tracer).unwrap();
 
 	let config = Cli::parse();
+	config.log.init();
 	let tls = config.tls.load()?;
+	let sign = config.sign.load()?;
 
-	let quic = quic::Endpoint::new(quic::Config { bind: config.bind, tls })?;
+	let quic = quic::Endpoint::new(quic::Config {
+		bind: config.bind,
+		tls,
+		congestion: Default::default(),
+	})?;
 
 	log::info!("connecting to server: url={}", config.url);
 
 	let session = quic.client.connect(&config.url).await?;
 
 	if config.publish {
-		let (session, mut publisher) = Publisher::connect(session)
+		let options = quic::setup_options(&config.url, setup::Role::Publisher);
+		let (session, mut publisher) = Publisher::connect_with(session, options)
 			.await
 			.context("failed to create MoQ Transport session")?;
 
-		let (mut writer, _, reader) = serve::Tracks {
-			namespace: config.namespace.clone(),
-		}
-		.produce();
-
-		let track = writer.create(&config.track).unwrap();
-		let clock = clock::Publisher::new(track.groups()?);
+		let (mut broadcast, _, reader) = serve::Broadcast::builder()
+			.namespace(config.namespace.clone())
+			.broadcast_id(config.broadcast.load())
+			.produce()?;
+
+		let track = broadcast
+			.track(config.track.as_str())
+			.mode(serve::mode::Groups)
+			.create()?;
+		let clock = clock::Publisher::new(track);
+
+		let announce = async {
+			match sign {
+				Some(key) => {
+					let signature = key.sign(&reader.namespace);
+					publisher.announce_signed(reader, signature).await
+				}
+				None => publisher.announce(reader).await,
+			}
+		};
 
 		tokio::select! {
 			res = session.run() => res.context("session error")?,
 			res = clock.run() => res.context("clock error")?,
-			res = publisher.announce(reader) => res.context("failed to serve tracks")?,
+			res = announce => res.context("failed to serve tracks")?,
 		}
 	} else {
-		let (session, mut subscriber) = Subscriber::connect(session)
+		let options = quic::setup_options(&config.url, setup::Role::Subscriber);
+		let (session, mut subscriber) = Subscriber::connect_with(session, options)
 			.await
 			.context("failed to create MoQ Transport session")?;
 
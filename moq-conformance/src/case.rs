@@ -0,0 +1,409 @@
+use std::time::Duration;
+
+use anyhow::Context as _;
+use moq_native::quic;
+use moq_transport::{
+	coding::Encode,
+	data,
+	message::{self, Message},
+	serve,
+	session::Subscriber,
+};
+use url::Url;
+
+use crate::peer::{subscribe, Peer};
+
+/// Shared by every case, each of which opens its own sessions since many end by closing them.
+pub struct Context {
+	pub client: quic::Client,
+	pub url: Url,
+	pub namespace: String,
+	pub timeout: Duration,
+}
+
+pub enum Outcome {
+	Pass,
+
+	/// The relay didn't negotiate the capability needed to run the case.
+	Skip(&'static str),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Case {
+	Setup,
+	Announce,
+	Unannounce,
+	SubscribeNotFound,
+	SubscribeDuplicateId,
+	UnsubscribeUnknown,
+	SubscribeUpdateUnknown,
+	SubscribeAckUnknown,
+	Batch,
+	FetchNotFound,
+	FetchCancelUnknown,
+	AnnounceUnsolicited,
+	SubscribeUnsolicited,
+	UnknownMessage,
+	MalformedSubscribe,
+	ObjectOutOfOrder,
+}
+
+impl Case {
+	pub const ALL: [Case; 16] = [
+		Self::Setup,
+		Self::Announce,
+		Self::Unannounce,
+		Self::SubscribeNotFound,
+		Self::SubscribeDuplicateId,
+		Self::UnsubscribeUnknown,
+		Self::SubscribeUpdateUnknown,
+		Self::SubscribeAckUnknown,
+		Self::Batch,
+		Self::FetchNotFound,
+		Self::FetchCancelUnknown,
+		Self::AnnounceUnsolicited,
+		Self::SubscribeUnsolicited,
+		Self::UnknownMessage,
+		Self::MalformedSubscribe,
+		Self::ObjectOutOfOrder,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Setup => "setup",
+			Self::Announce => "announce",
+			Self::Unannounce => "unannounce",
+			Self::SubscribeNotFound => "subscribe-not-found",
+			Self::SubscribeDuplicateId => "subscribe-duplicate-id",
+			Self::UnsubscribeUnknown => "unsubscribe-unknown",
+			Self::SubscribeUpdateUnknown => "subscribe-update-unknown",
+			Self::SubscribeAckUnknown => "subscribe-ack-unknown",
+			Self::Batch => "batch",
+			Self::FetchNotFound => "fetch-not-found",
+			Self::FetchCancelUnknown => "fetch-cancel-unknown",
+			Self::AnnounceUnsolicited => "announce-unsolicited",
+			Self::SubscribeUnsolicited => "subscribe-unsolicited",
+			Self::UnknownMessage => "unknown-message",
+			Self::MalformedSubscribe => "malformed-subscribe",
+			Self::ObjectOutOfOrder => "object-out-of-order",
+		}
+	}
+
+	pub fn description(&self) -> &'static str {
+		match self {
+			Self::Setup => "SETUP negotiates a version we offered",
+			Self::Announce => "ANNOUNCE is answered with ANNOUNCE_OK",
+			Self::Unannounce => "UNANNOUNCE leaves the session usable",
+			Self::SubscribeNotFound => "SUBSCRIBE for a missing namespace is answered with SUBSCRIBE_ERROR",
+			Self::SubscribeDuplicateId => "reusing an active subscribe ID closes the session with PROTOCOL_VIOLATION",
+			Self::UnsubscribeUnknown => "UNSUBSCRIBE for an unknown ID is tolerated",
+			Self::SubscribeUpdateUnknown => "SUBSCRIBE_UPDATE for an unknown ID is tolerated",
+			Self::SubscribeAckUnknown => "SUBSCRIBE_ACK for an unknown ID is tolerated",
+			Self::Batch => "each SUBSCRIBE in a BATCH is answered",
+			Self::FetchNotFound => "FETCH for a missing namespace is answered with FETCH_ERROR",
+			Self::FetchCancelUnknown => "FETCH_CANCEL for an unknown ID is tolerated",
+			Self::AnnounceUnsolicited => {
+				"ANNOUNCE_OK, ANNOUNCE_ERROR and ANNOUNCE_CANCEL for an unknown namespace are tolerated"
+			}
+			Self::SubscribeUnsolicited => {
+				"SUBSCRIBE_OK, SUBSCRIBE_ERROR and SUBSCRIBE_DONE for an unknown ID are tolerated"
+			}
+			Self::UnknownMessage => "an unknown message type closes the session with PROTOCOL_VIOLATION",
+			Self::MalformedSubscribe => "a SUBSCRIBE with an invalid range closes the session with PROTOCOL_VIOLATION",
+			Self::ObjectOutOfOrder => "a group stream with objects out of order doesn't break the subscription",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|case| case.name() == name)
+	}
+
+	pub async fn run(self, context: &Context) -> anyhow::Result<Outcome> {
+		let mut peer = Peer::connect(context).await?;
+		let namespace = format!("{}/{}", context.namespace, self.name());
+		let capabilities = peer.capabilities();
+
+		log::info!(
+			"running case: name={} version={} capabilities={:?}",
+			self.name(),
+			peer.version(),
+			capabilities
+		);
+
+		match self {
+			// The version is checked by the setup itself.
+			Self::Setup => peer.probe().await?,
+			Self::Announce => peer.announce(&namespace).await?,
+			Self::Unannounce => {
+				peer.announce(&namespace).await?;
+				peer.send(message::Unannounce { namespace }).await?;
+				peer.probe().await?;
+			}
+			Self::SubscribeNotFound => {
+				let id = peer.id();
+				peer.send(subscribe(id, &namespace, "missing")).await?;
+				peer.subscribe_error(id).await?;
+			}
+			Self::SubscribeDuplicateId => {
+				// Subscribe to our own announce, so the subscription stays active until we answer it.
+				peer.announce(&namespace).await?;
+
+				let id = peer.id();
+				peer.send(subscribe(id, &namespace, "track")).await?;
+				peer.expect("SUBSCRIBE forwarded by the relay", |msg| match msg {
+					Message::Subscribe(msg) if msg.track_namespace == namespace && msg.track_name == "track" => {
+						Some(Ok(()))
+					}
+					_ => None,
+				})
+				.await?;
+
+				peer.send(subscribe(id, &namespace, "track")).await?;
+				peer.violation().await?;
+			}
+			Self::UnsubscribeUnknown => {
+				let id = peer.id();
+				peer.send(message::Unsubscribe { id }).await?;
+				peer.tolerated().await?;
+			}
+			Self::SubscribeUpdateUnknown => {
+				if !capabilities.update {
+					return Ok(Outcome::Skip("update capability not negotiated"));
+				}
+
+				let id = peer.id();
+				let range = subscribe(id, &namespace, "missing");
+				peer.send(message::SubscribeUpdate {
+					id,
+					start: range.start,
+					end: range.end,
+					priority: None,
+					params: Default::default(),
+				})
+				.await?;
+				peer.tolerated().await?;
+			}
+			Self::SubscribeAckUnknown => {
+				if !capabilities.window {
+					return Ok(Outcome::Skip("window capability not negotiated"));
+				}
+
+				let id = peer.id();
+				peer.send(message::SubscribeAck { id, group_id: 0 }).await?;
+				peer.tolerated().await?;
+			}
+			Self::Batch => {
+				if !capabilities.batch {
+					return Ok(Outcome::Skip("batch capability not negotiated"));
+				}
+
+				let ids = [peer.id(), peer.id()];
+				let messages = ids
+					.iter()
+					.map(|id| subscribe(*id, &namespace, "missing").into())
+					.collect();
+
+				peer.send(message::Batch { messages }).await?;
+				for id in ids {
+					peer.subscribe_error(id).await?;
+				}
+			}
+			Self::FetchNotFound => {
+				if !capabilities.fetch {
+					return Ok(Outcome::Skip("fetch capability not negotiated"));
+				}
+
+				let id = peer.id();
+				peer.send(message::Fetch {
+					id,
+					track_namespace: namespace,
+					track_name: "missing".to_string(),
+					start_group: 0,
+					end_group: None,
+					params: Default::default(),
+				})
+				.await?;
+
+				peer.expect("FETCH_ERROR", |msg| match msg {
+					Message::FetchError(msg) if msg.id == id => Some(Ok(())),
+					Message::FetchOk(msg) if msg.id == id => Some(Err(anyhow::anyhow!("received FETCH_OK"))),
+					_ => None,
+				})
+				.await?;
+			}
+			Self::FetchCancelUnknown => {
+				if !capabilities.fetch {
+					return Ok(Outcome::Skip("fetch capability not negotiated"));
+				}
+
+				let id = peer.id();
+				peer.send(message::FetchCancel { id }).await?;
+				peer.tolerated().await?;
+			}
+			Self::AnnounceUnsolicited => {
+				peer.send(message::AnnounceOk {
+					namespace: namespace.clone(),
+				})
+				.await?;
+				peer.send(message::AnnounceError {
+					namespace: namespace.clone(),
+					code: 404,
+					reason: "not found".to_string(),
+				})
+				.await?;
+				peer.send(message::AnnounceCancel {
+					namespace,
+					code: 404,
+					reason: "not found".to_string(),
+				})
+				.await?;
+				peer.tolerated().await?;
+			}
+			Self::SubscribeUnsolicited => {
+				// The relay never subscribed to us, so any ID is unknown.
+				peer.send(message::SubscribeOk {
+					id: 0,
+					expires: None,
+					latest: None,
+					epoch: None,
+				})
+				.await?;
+				peer.send(message::SubscribeError {
+					id: 1,
+					code: 404,
+					reason: "not found".to_string(),
+					alias: 1,
+				})
+				.await?;
+				peer.send(message::SubscribeDone {
+					id: 2,
+					code: 0,
+					reason: "ended".to_string(),
+					last: None,
+				})
+				.await?;
+				peer.tolerated().await?;
+			}
+			Self::UnknownMessage => {
+				// Control messages aren't length prefixed, so there's no way to skip an unknown type.
+				peer.send_bytes(&[0x2f]).await?;
+				peer.violation().await?;
+			}
+			Self::MalformedSubscribe => {
+				let mut buf = Vec::new();
+				Message::Subscribe(subscribe(0, "", "")).id().encode(&mut buf)?;
+				peer.id().encode(&mut buf)?;
+				0u64.encode(&mut buf)?;
+				namespace.encode(&mut buf)?;
+				"track".to_string().encode(&mut buf)?;
+
+				// Neither a valid location nor a valid draft-04 filter type.
+				9u64.encode(&mut buf)?;
+
+				peer.send_bytes(&buf).await?;
+				peer.violation().await?;
+			}
+			Self::ObjectOutOfOrder => object_out_of_order(context, peer, namespace).await?,
+		}
+
+		Ok(Outcome::Pass)
+	}
+}
+
+// Publish a group with its objects out of order, then a valid group that must still reach another subscriber.
+async fn object_out_of_order(context: &Context, mut publisher: Peer, namespace: String) -> anyhow::Result<()> {
+	publisher.announce(&namespace).await?;
+
+	let session = context.client.connect(&context.url).await?;
+	let (session, mut subscriber) = Subscriber::connect(session).await.context("failed SETUP")?;
+	let (writer, reader) = serve::Track::new(namespace.clone(), "track".to_string()).produce();
+
+	let task = tokio::spawn(async move {
+		tokio::select! {
+			res = session.run() => res.map_err(anyhow::Error::from),
+			res = subscriber.subscribe(writer) => res.map_err(anyhow::Error::from),
+		}
+	});
+
+	let res = async {
+		let (id, alias) = publisher
+			.expect("SUBSCRIBE forwarded by the relay", |msg| match msg {
+				// The relay may also subscribe to other tracks, ex. to sync clocks.
+				Message::Subscribe(msg) if msg.track_namespace == namespace && msg.track_name == "track" => {
+					Some(Ok((msg.id, msg.track_alias)))
+				}
+				_ => None,
+			})
+			.await?;
+
+		publisher
+			.send(message::SubscribeOk {
+				id,
+				expires: None,
+				latest: None,
+				epoch: None,
+			})
+			.await?;
+
+		// The gap is never filled, so the objects can't be delivered in order.
+		let mut stream = publisher.raw().open_uni().await?;
+		stream.encode(&group(id, alias, 0)).await?;
+		for object_id in [2, 1] {
+			stream
+				.object(&data::GroupObject {
+					object_id,
+					size: 3,
+					extensions: Default::default(),
+				})
+				.await?;
+			stream.write(b"bad").await?;
+		}
+		drop(stream);
+
+		let mut stream = publisher.raw().open_uni().await?;
+		stream.encode(&group(id, alias, 1)).await?;
+		stream
+			.object(&data::GroupObject {
+				object_id: 0,
+				size: 2,
+				extensions: Default::default(),
+			})
+			.await?;
+		stream.write(b"ok").await?;
+		drop(stream);
+
+		tokio::time::timeout(context.timeout, async {
+			let mut groups = match reader.mode().await? {
+				serve::TrackReaderMode::Groups(groups) => groups,
+				_ => anyhow::bail!("expected groups"),
+			};
+
+			loop {
+				let mut group = groups.next().await?.context("track ended before the valid group")?;
+				if group.group_id == 1 {
+					let payload = group.read_next().await?.context("valid group was empty")?;
+					anyhow::ensure!(payload == "ok", "valid group was corrupted");
+					return Ok(());
+				}
+			}
+		})
+		.await
+		.context("timed out waiting for the valid group")??;
+
+		publisher.probe().await
+	}
+	.await;
+
+	task.abort();
+	res
+}
+
+fn group(subscribe_id: u64, track_alias: u64, group_id: u64) -> data::Header {
+	data::GroupHeader {
+		subscribe_id,
+		track_alias,
+		group_id,
+		send_order: 0,
+	}
+	.into()
+}
@@ -0,0 +1,111 @@
+use moq_native::quic;
+use std::{net, time::Duration};
+use url::Url;
+
+use anyhow::Context;
+use clap::Parser;
+
+mod case;
+mod peer;
+
+use case::{Case, Outcome};
+
+#[derive(Parser, Clone)]
+pub struct Cli {
+	/// Listen for UDP packets on the given address.
+	#[arg(long, default_value = "[::]:0")]
+	pub bind: net::SocketAddr,
+
+	/// Connect to the relay at the given URL, starting with moqt:// to check close codes, or https://
+	#[arg(required_unless_present = "list")]
+	pub url: Option<Url>,
+
+	/// The TLS configuration.
+	#[command(flatten)]
+	pub tls: moq_native::tls::Args,
+
+	/// The log sampling configuration.
+	#[command(flatten)]
+	pub log: moq_native::log::Args,
+
+	/// Only run these cases, see --list. Repeatable.
+	#[arg(long = "case")]
+	pub cases: Vec<String>,
+
+	/// Print the name of each case and exit.
+	#[arg(long)]
+	pub list: bool,
+
+	/// The prefix of each namespace announced, suffixed to avoid colliding with other runs.
+	#[arg(long, default_value = "conformance")]
+	pub namespace: String,
+
+	/// How long to wait for each expected response.
+	#[arg(long, default_value = "5000")]
+	pub timeout_ms: u64,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+	env_logger::init();
+
+	// Disable tracing so we don't get a bunch of Quinn spam.
+	let tracer = tracing_subscriber::FmtSubscriber::builder()
+		.with_max_level(tracing::Level::WARN)
+		.finish();
+	tracing::subscriber::set_global_default(tracer).unwrap();
+
+	let config = Cli::parse();
+	config.log.init();
+
+	if config.list {
+		for case in Case::ALL {
+			println!("{}: {}", case.name(), case.description());
+		}
+
+		return Ok(());
+	}
+
+	let cases: Vec<Case> = match config.cases.is_empty() {
+		true => Case::ALL.to_vec(),
+		false => config
+			.cases
+			.iter()
+			.map(|name| Case::from_name(name).ok_or_else(|| anyhow::anyhow!("unknown case: {}", name)))
+			.collect::<anyhow::Result<_>>()?,
+	};
+
+	let url = config.url.context("missing url")?;
+	let tls = config.tls.load()?;
+	let quic = quic::Endpoint::new(quic::Config {
+		bind: config.bind,
+		tls,
+		congestion: Default::default(),
+	})?;
+
+	let context = case::Context {
+		client: quic.client,
+		url: url.clone(),
+		namespace: format!("{}-{}", config.namespace, std::process::id()),
+		timeout: Duration::from_millis(config.timeout_ms),
+	};
+
+	log::info!("testing relay: url={} cases={}", url, cases.len());
+
+	let mut failed = Vec::new();
+
+	for case in cases {
+		match case.run(&context).await {
+			Ok(Outcome::Pass) => println!("PASS {}", case.name()),
+			Ok(Outcome::Skip(reason)) => println!("SKIP {}: {}", case.name(), reason),
+			Err(err) => {
+				println!("FAIL {}: {:#}", case.name(), err);
+				failed.push(case.name());
+			}
+		}
+	}
+
+	anyhow::ensure!(failed.is_empty(), "failed cases: {}", failed.join(", "));
+
+	Ok(())
+}
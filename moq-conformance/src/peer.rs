@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use anyhow::Context as _;
+use moq_transport::{
+	message::{self, Message},
+	session::{CloseCode, RawSession, Session, SessionError},
+	setup,
+};
+use tokio::time::{timeout, timeout_at, Instant};
+
+use crate::case::Context;
+
+/// A session to the relay that sends messages verbatim, with helpers to check the responses against the draft.
+pub struct Peer {
+	raw: RawSession,
+	timeout: Duration,
+	namespace: String,
+
+	// WebTransport closes the connection with its own code, hiding the one used to close the session.
+	webtransport: bool,
+
+	// The next unused subscribe ID.
+	next: u64,
+
+	// Set once the session is closed, to the code used by the relay if any.
+	closed: Option<Option<CloseCode>>,
+}
+
+impl Peer {
+	pub async fn connect(context: &Context) -> anyhow::Result<Self> {
+		let session = context.client.connect(&context.url).await?;
+		let (session, _, _) = Session::connect(session).await.context("failed SETUP")?;
+
+		Ok(Self {
+			raw: session.into_raw(),
+			timeout: context.timeout,
+			namespace: context.namespace.clone(),
+			webtransport: context.url.scheme() == "https",
+			next: 0,
+			closed: None,
+		})
+	}
+
+	pub fn version(&self) -> setup::Version {
+		self.raw.version()
+	}
+
+	pub fn capabilities(&self) -> setup::Capabilities {
+		self.raw.capabilities()
+	}
+
+	pub fn raw(&mut self) -> &mut RawSession {
+		&mut self.raw
+	}
+
+	/// A subscribe ID not yet used on this session.
+	pub fn id(&mut self) -> u64 {
+		let id = self.next;
+		self.next += 1;
+		id
+	}
+
+	pub async fn send(&mut self, msg: impl Into<Message>) -> anyhow::Result<()> {
+		let msg = msg.into();
+		let name = msg.name();
+		self.raw
+			.send(msg)
+			.await
+			.with_context(|| format!("failed to send {}", name))
+	}
+
+	pub async fn send_bytes(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+		self.raw.send_bytes(buf).await.context("failed to send bytes")
+	}
+
+	/// Wait for the message accepted by `f`, which returns None for any unrelated message.
+	///
+	/// Unrelated messages are ignored, ex. announcements forwarded by the relay.
+	pub async fn expect<T, F>(&mut self, what: &str, mut f: F) -> anyhow::Result<T>
+	where
+		F: FnMut(&Message) -> Option<anyhow::Result<T>>,
+	{
+		let deadline = Instant::now() + self.timeout;
+
+		loop {
+			let msg = match timeout_at(deadline, self.raw.recv()).await {
+				Ok(Ok(msg)) => msg,
+				Ok(Err(err)) => return Err(self.failed(err).await).with_context(|| format!("expected {}", what)),
+				Err(_) => anyhow::bail!("timed out waiting for {}", what),
+			};
+
+			match f(&msg) {
+				Some(res) => return res.with_context(|| format!("invalid {}", what)),
+				None => log::debug!("ignoring message: {:?}", msg),
+			}
+		}
+	}
+
+	/// The relay must close the session with PROTOCOL_VIOLATION.
+	pub async fn violation(&mut self) -> anyhow::Result<()> {
+		let deadline = Instant::now() + self.timeout;
+
+		loop {
+			let err = match timeout_at(deadline, self.raw.recv()).await {
+				Ok(Ok(msg)) => {
+					log::debug!("ignoring message: {:?}", msg);
+					continue;
+				}
+				Ok(Err(err)) => err,
+				Err(_) => anyhow::bail!("session wasn't closed with PROTOCOL_VIOLATION"),
+			};
+
+			self.failed(err).await;
+
+			return match self.closed.flatten() {
+				Some(CloseCode::ProtocolViolation) => Ok(()),
+				Some(CloseCode::NoError) if self.webtransport => {
+					anyhow::bail!("closed, but WebTransport hides the code; use a moqt:// URL to check it")
+				}
+				Some(code) => anyhow::bail!("closed with {:?} instead of PROTOCOL_VIOLATION", code),
+				None => anyhow::bail!("session failed without a close code"),
+			};
+		}
+	}
+
+	/// The session must still be usable: a SUBSCRIBE for a missing namespace gets a SUBSCRIBE_ERROR.
+	pub async fn probe(&mut self) -> anyhow::Result<()> {
+		let id = self.id();
+		let namespace = format!("{}/missing", self.namespace);
+
+		self.send(subscribe(id, &namespace, "probe")).await?;
+		self.subscribe_error(id).await.context("session is no longer usable")
+	}
+
+	/// The relay may ignore the message or close the session with PROTOCOL_VIOLATION, but nothing else.
+	pub async fn tolerated(&mut self) -> anyhow::Result<()> {
+		match self.probe().await {
+			Ok(()) => Ok(()),
+			Err(_) if self.closed == Some(Some(CloseCode::ProtocolViolation)) => Ok(()),
+			Err(err) => Err(err),
+		}
+	}
+
+	/// The subscription must be rejected, ex. because the track doesn't exist.
+	pub async fn subscribe_error(&mut self, id: u64) -> anyhow::Result<()> {
+		self.expect("SUBSCRIBE_ERROR", |msg| match msg {
+			Message::SubscribeError(msg) if msg.id == id => Some(Ok(())),
+			Message::SubscribeOk(msg) if msg.id == id => Some(Err(anyhow::anyhow!("received SUBSCRIBE_OK"))),
+			Message::SubscribeDone(msg) if msg.id == id => Some(Err(anyhow::anyhow!("received SUBSCRIBE_DONE"))),
+			_ => None,
+		})
+		.await
+	}
+
+	/// The announce must be accepted.
+	pub async fn announce(&mut self, namespace: &str) -> anyhow::Result<()> {
+		self.send(message::Announce {
+			namespace: namespace.to_string(),
+			params: Default::default(),
+		})
+		.await?;
+
+		self.expect("ANNOUNCE_OK", |msg| match msg {
+			Message::AnnounceOk(msg) if msg.namespace == namespace => Some(Ok(())),
+			Message::AnnounceError(msg) if msg.namespace == namespace => {
+				Some(Err(anyhow::anyhow!("received ANNOUNCE_ERROR: {}", msg.reason)))
+			}
+			_ => None,
+		})
+		.await
+	}
+
+	// Record how the session was closed, returning an error describing it.
+	async fn failed(&mut self, err: SessionError) -> anyhow::Error {
+		// The control stream may fail before the CONNECTION_CLOSE arrives, so wait briefly for it.
+		let code = match err.peer_closed() {
+			Some((code, _)) => Some(code),
+			None => timeout(self.timeout, self.raw.closed())
+				.await
+				.ok()
+				.and_then(|err| err.peer_closed())
+				.map(|(code, _)| code),
+		};
+
+		self.closed = Some(code);
+
+		match code {
+			Some(code) => anyhow::anyhow!("session closed by relay: code={:?}", code),
+			None => anyhow::Error::new(err).context("session failed"),
+		}
+	}
+}
+
+/// A SUBSCRIBE starting at the latest group.
+pub fn subscribe(id: u64, namespace: &str, name: &str) -> message::Subscribe {
+	message::Subscribe {
+		id,
+		track_alias: id,
+		track_namespace: namespace.to_string(),
+		track_name: name.to_string(),
+		start: message::SubscribePair {
+			group: message::SubscribeLocation::Latest(0),
+			object: message::SubscribeLocation::Absolute(0),
+		},
+		end: message::SubscribePair {
+			group: message::SubscribeLocation::None,
+			object: message::SubscribeLocation::None,
+		},
+		group_order: Default::default(),
+		params: Default::default(),
+	}
+}
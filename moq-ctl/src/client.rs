@@ -0,0 +1,292 @@
+use reqwest::{
+	header::{self, HeaderMap, HeaderValue},
+	StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A session accepted by the relay, as returned by `GET /sessions`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Session {
+	pub id: u64,
+	pub version: String,
+	pub transport: String,
+	pub publisher: bool,
+	pub subscriber: bool,
+	pub sticky: Option<String>,
+	pub path: Option<String>,
+	pub connected_ms: u64,
+	pub draining: bool,
+}
+
+/// A message or stream event of a session, as returned by `GET /sessions/<id>/history`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SessionEvent {
+	pub elapsed_ms: u64,
+	pub kind: String,
+	pub detail: String,
+}
+
+/// A session that failed with a protocol violation, as returned by `GET /failures`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SessionFailure {
+	#[serde(flatten)]
+	pub session: Session,
+	pub error: String,
+	pub failed_ms: u64,
+	pub events: Vec<SessionEvent>,
+}
+
+/// A namespace announced to the relay, as returned by `GET /namespaces`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Namespace {
+	pub namespace: String,
+	pub bytes: u64,
+	pub max_bytes: Option<u64>,
+	pub written: u64,
+	pub broadcast_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Quota<'a> {
+	namespace: &'a str,
+	max_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Drain<'a> {
+	url: Option<&'a str>,
+}
+
+/// A client for the relay's admin API, served on `--admin-bind`.
+pub struct Client {
+	url: Url,
+	client: reqwest::Client,
+}
+
+impl Client {
+	/// Send the bearer token with every request, as configured by the relay's --admin-token-file.
+	pub fn new(mut url: Url, token: Option<&str>) -> anyhow::Result<Self> {
+		// Resolve routes relative to the URL, even if it has a path prefix.
+		if !url.path().ends_with('/') {
+			url.set_path(&format!("{}/", url.path()));
+		}
+
+		let mut headers = HeaderMap::new();
+		if let Some(token) = token {
+			let mut value = HeaderValue::from_str(&format!("Bearer {}", token))?;
+			value.set_sensitive(true);
+			headers.insert(header::AUTHORIZATION, value);
+		}
+
+		let client = reqwest::Client::builder().default_headers(headers).build()?;
+		Ok(Self { url, client })
+	}
+
+	pub async fn sessions(&self) -> anyhow::Result<Vec<Session>> {
+		let res = self.client.get(self.url.join("sessions")?).send().await?;
+		Ok(check(res).await?.json().await?)
+	}
+
+	pub async fn namespaces(&self) -> anyhow::Result<Vec<Namespace>> {
+		let res = self.client.get(self.url.join("namespaces")?).send().await?;
+		Ok(check(res).await?.json().await?)
+	}
+
+	/// The latest messages and stream events of the session.
+	pub async fn history(&self, id: u64) -> anyhow::Result<Vec<SessionEvent>> {
+		let res = self
+			.client
+			.get(self.url.join(&format!("sessions/{}/history", id))?)
+			.send()
+			.await?;
+		if res.status() == StatusCode::NOT_FOUND {
+			anyhow::bail!("unknown session: {}", id);
+		}
+
+		Ok(check(res).await?.json().await?)
+	}
+
+	/// The latest sessions that failed with a protocol violation.
+	pub async fn failures(&self) -> anyhow::Result<Vec<SessionFailure>> {
+		let res = self.client.get(self.url.join("failures")?).send().await?;
+		Ok(check(res).await?.json().await?)
+	}
+
+	/// Close the session immediately.
+	pub async fn kick(&self, id: u64) -> anyhow::Result<()> {
+		let res = self
+			.client
+			.delete(self.url.join(&format!("sessions/{}", id))?)
+			.send()
+			.await?;
+		if res.status() == StatusCode::NOT_FOUND {
+			anyhow::bail!("unknown session: {}", id);
+		}
+
+		check(res).await?;
+		Ok(())
+	}
+
+	/// Stop serving the namespace, as if it was unannounced.
+	pub async fn unannounce(&self, namespace: &str) -> anyhow::Result<()> {
+		// Encode the namespace as a single segment, even if it contains slashes.
+		let mut url = self.url.join("namespaces/")?;
+		url.path_segments_mut()
+			.map_err(|_| anyhow::anyhow!("invalid admin URL"))?
+			.pop_if_empty()
+			.push(namespace);
+
+		let res = self.client.delete(url).send().await?;
+		if res.status() == StatusCode::NOT_FOUND {
+			anyhow::bail!("unknown namespace: {}", namespace);
+		}
+
+		check(res).await?;
+		Ok(())
+	}
+
+	/// Send GOAWAY to every session, redirecting them to the URL or the relay's --go-away-url.
+	pub async fn drain(&self, url: Option<&Url>) -> anyhow::Result<()> {
+		let res = self
+			.client
+			.post(self.url.join("drain")?)
+			.json(&Drain {
+				url: url.map(Url::as_str),
+			})
+			.send()
+			.await?;
+
+		check(res).await?;
+		Ok(())
+	}
+
+	/// Change the byte limit of a namespace, or remove it with None.
+	pub async fn quota(&self, namespace: &str, max_bytes: Option<u64>) -> anyhow::Result<Namespace> {
+		let res = self
+			.client
+			.put(self.url.join("quota")?)
+			.json(&Quota { namespace, max_bytes })
+			.send()
+			.await?;
+
+		Ok(check(res).await?.json().await?)
+	}
+}
+
+// Return an error including the response body, which explains why the request was rejected.
+async fn check(res: reqwest::Response) -> anyhow::Result<reqwest::Response> {
+	let status = res.status();
+	if status.is_success() {
+		return Ok(res);
+	}
+
+	let body = res.text().await.unwrap_or_default();
+	anyhow::bail!("{}: {}", status, body.trim())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{
+		extract::Path,
+		http::HeaderMap,
+		routing::{delete, post},
+		Json, Router,
+	};
+
+	// Echo the authorization header and JSON body, like an admin API that accepts everything.
+	async fn serve() -> Url {
+		let app = Router::new()
+			.route(
+				"/admin/drain",
+				post(|headers: HeaderMap, Json(body): Json<serde_json::Value>| async move {
+					let auth = headers
+						.get("authorization")
+						.map(|value| value.to_str().unwrap().to_string());
+					(
+						StatusCode::ACCEPTED,
+						Json(serde_json::json!({ "auth": auth, "body": body })),
+					)
+				}),
+			)
+			.route(
+				"/admin/sessions/:id",
+				delete(|Path(id): Path<u64>| async move {
+					match id {
+						1 => StatusCode::NO_CONTENT,
+						_ => StatusCode::NOT_FOUND,
+					}
+				}),
+			)
+			.route(
+				"/admin/namespaces/*namespace",
+				delete(|Path(namespace): Path<String>| async move {
+					match namespace.as_str() {
+						"live/event" => StatusCode::NO_CONTENT,
+						_ => StatusCode::NOT_FOUND,
+					}
+				}),
+			)
+			.route(
+				"/admin/quota",
+				axum::routing::put(|Json(quota): Json<serde_json::Value>| async move {
+					match quota["namespace"].as_str() {
+						Some("live") => Ok(Json(serde_json::json!({
+							"namespace": "live",
+							"bytes": 10,
+							"max_bytes": quota["max_bytes"],
+							"written": 20,
+							"broadcast_id": null,
+						}))),
+						_ => Err((StatusCode::NOT_FOUND, "unknown namespace: other".to_string())),
+					}
+				}),
+			);
+
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/admin", listener.local_addr().unwrap())
+			.parse()
+			.unwrap();
+		tokio::spawn(async move { axum::serve(listener, app).await });
+
+		url
+	}
+
+	#[tokio::test]
+	async fn requests() {
+		let url = serve().await;
+		let client = Client::new(url, Some("secret")).unwrap();
+
+		// The drain URL is sent as JSON with the token, resolved under the path prefix.
+		let target: Url = "https://backup.example.com".parse().unwrap();
+		let res = client
+			.client
+			.post(client.url.join("drain").unwrap())
+			.json(&Drain {
+				url: Some(target.as_str()),
+			})
+			.send()
+			.await
+			.unwrap();
+		let echo: serde_json::Value = res.json().await.unwrap();
+		assert_eq!(echo["auth"], "Bearer secret");
+		assert_eq!(echo["body"]["url"], "https://backup.example.com/");
+		client.drain(None).await.unwrap();
+
+		client.kick(1).await.unwrap();
+		let err = client.kick(2).await.unwrap_err();
+		assert_eq!(err.to_string(), "unknown session: 2");
+
+		client.unannounce("live/event").await.unwrap();
+		let err = client.unannounce("live").await.unwrap_err();
+		assert_eq!(err.to_string(), "unknown namespace: live");
+
+		let namespace = client.quota("live", Some(100)).await.unwrap();
+		assert_eq!(namespace.max_bytes, Some(100));
+
+		// Errors include the body explaining why.
+		let err = client.quota("other", None).await.unwrap_err();
+		assert_eq!(err.to_string(), "404 Not Found: unknown namespace: other");
+	}
+}
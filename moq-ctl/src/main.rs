@@ -0,0 +1,336 @@
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use url::Url;
+
+mod client;
+
+use client::Client;
+
+#[derive(Parser, Clone)]
+pub struct Cli {
+	/// The relay's admin API, as served by its --admin-bind.
+	#[arg(long, default_value = "http://127.0.0.1:9090")]
+	pub admin: Url,
+
+	/// Read the bearer token from this file, as configured by the relay's --admin-token-file.
+	#[arg(long)]
+	pub token_file: Option<PathBuf>,
+
+	/// Print the raw JSON responses instead of a table.
+	#[arg(long)]
+	pub json: bool,
+
+	#[command(subcommand)]
+	pub command: Command,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+	/// List the sessions accepted by the relay.
+	Sessions,
+
+	/// List the namespaces announced to the relay, with their cache usage.
+	Namespaces,
+
+	/// Print the latest control messages and stream events of a session, by the ID from `sessions`.
+	History { id: u64 },
+
+	/// Print the latest sessions that failed with a protocol violation, and the events leading up to each.
+	Failures,
+
+	/// Close a session immediately, by the ID from `sessions`.
+	Kick { id: u64 },
+
+	/// Stop serving an announced namespace, as if it was unannounced.
+	Unannounce { namespace: String },
+
+	/// Send GOAWAY to every session, including any accepted afterwards, before taking the relay out of service.
+	Drain {
+		/// Redirect sessions to this URL, instead of the relay's --go-away-url.
+		#[arg(long)]
+		url: Option<Url>,
+	},
+
+	/// Change the byte limit of an announced namespace, until it's announced again.
+	Quota {
+		namespace: String,
+
+		/// The approximate bytes cached before new groups are rejected.
+		#[arg(long, required_unless_present = "unlimited")]
+		max_bytes: Option<u64>,
+
+		/// Remove the limit instead.
+		#[arg(long, conflicts_with = "max_bytes")]
+		unlimited: bool,
+	},
+
+	/// Print the sessions and the ingest rate of each namespace, until interrupted.
+	Stats {
+		/// How often to poll the relay.
+		#[arg(long, default_value = "1000")]
+		interval_ms: u64,
+	},
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+	env_logger::init();
+
+	let cli = Cli::parse();
+	let token = match &cli.token_file {
+		Some(path) => {
+			Some(std::fs::read_to_string(path).with_context(|| format!("failed to read token: {}", path.display()))?)
+		}
+		None => None,
+	};
+	let client = Client::new(cli.admin, token.as_deref().map(str::trim))?;
+
+	match cli.command {
+		Command::Sessions => {
+			let sessions = client.sessions().await?;
+			if cli.json {
+				return print_json(&sessions);
+			}
+
+			let now = now_ms();
+			println!(
+				"{:>6}  {:<10} {:<5} {:<10} {:>8}  {:<16} STICKY",
+				"ID", "VERSION", "VIA", "ROLE", "AGE", "PATH"
+			);
+			for session in sessions {
+				let role = match (session.publisher, session.subscriber) {
+					(true, true) => "both",
+					(true, false) => "publisher",
+					(false, true) => "subscriber",
+					(false, false) => "none",
+				};
+
+				let age = Duration::from_millis(now.saturating_sub(session.connected_ms)).as_secs();
+				println!(
+					"{:>6}  {:<10} {:<5} {:<10} {:>7}s  {:<16} {}{}",
+					session.id,
+					session.version,
+					session.transport,
+					role,
+					age,
+					session.path.as_deref().unwrap_or("-"),
+					session.sticky.as_deref().unwrap_or("-"),
+					if session.draining { " (draining)" } else { "" }
+				);
+			}
+		}
+		Command::Namespaces => {
+			let mut namespaces = client.namespaces().await?;
+			if cli.json {
+				return print_json(&namespaces);
+			}
+
+			namespaces.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+			println!("{:<32} {:>10} {:>10}  BROADCAST", "NAMESPACE", "CACHED", "LIMIT");
+			for namespace in namespaces {
+				println!(
+					"{:<32} {:>10} {:>10}  {}",
+					namespace.namespace,
+					format_bytes(namespace.bytes),
+					namespace.max_bytes.map(format_bytes).unwrap_or_else(|| "-".to_string()),
+					namespace.broadcast_id.as_deref().unwrap_or("-")
+				);
+			}
+		}
+		Command::History { id } => {
+			let events = client.history(id).await?;
+			if cli.json {
+				return print_json(&events);
+			}
+
+			print_events(&events);
+		}
+		Command::Failures => {
+			let failures = client.failures().await?;
+			if cli.json {
+				return print_json(&failures);
+			}
+
+			for failure in failures {
+				let age = Duration::from_millis(now_ms().saturating_sub(failure.failed_ms)).as_secs();
+				println!(
+					"session {} ({} via {}) failed {}s ago: {}",
+					failure.session.id, failure.session.version, failure.session.transport, age, failure.error
+				);
+				print_events(&failure.events);
+				println!();
+			}
+		}
+		Command::Kick { id } => {
+			client.kick(id).await?;
+			println!("closed session {}", id);
+		}
+		Command::Unannounce { namespace } => {
+			client.unannounce(&namespace).await?;
+			println!("unannounced {}", namespace);
+		}
+		Command::Drain { url } => {
+			client.drain(url.as_ref()).await?;
+			println!("draining");
+		}
+		Command::Quota {
+			namespace, max_bytes, ..
+		} => {
+			let namespace = client.quota(&namespace, max_bytes).await?;
+			if cli.json {
+				return print_json(&namespace);
+			}
+
+			println!(
+				"{}: limit={} cached={}",
+				namespace.namespace,
+				namespace.max_bytes.map(format_bytes).unwrap_or_else(|| "-".to_string()),
+				format_bytes(namespace.bytes)
+			);
+		}
+		Command::Stats { interval_ms } => stats(&client, Duration::from_millis(interval_ms), cli.json).await?,
+	}
+
+	Ok(())
+}
+
+// Poll the relay, printing the number of sessions and the rate each namespace is being written.
+async fn stats(client: &Client, interval: Duration, json: bool) -> anyhow::Result<()> {
+	#[derive(Serialize)]
+	struct Sample {
+		timestamp_ms: u64,
+		sessions: usize,
+		draining: usize,
+		namespaces: Vec<Rate>,
+	}
+
+	#[derive(Serialize)]
+	struct Rate {
+		namespace: String,
+		bytes: u64,
+		max_bytes: Option<u64>,
+		bytes_per_second: Option<u64>,
+	}
+
+	let mut interval = tokio::time::interval(interval);
+	let mut last: Option<(u64, HashMap<String, u64>)> = None;
+
+	loop {
+		interval.tick().await;
+
+		let (sessions, mut namespaces) = tokio::try_join!(client.sessions(), client.namespaces())?;
+		namespaces.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+
+		let now = now_ms();
+		let sample = Sample {
+			timestamp_ms: now,
+			sessions: sessions.len(),
+			draining: sessions.iter().filter(|session| session.draining).count(),
+			namespaces: namespaces
+				.iter()
+				.map(|namespace| {
+					// Unknown until the namespace has been seen twice.
+					let bytes_per_second = last.as_ref().and_then(|(then, written)| {
+						let elapsed = now.saturating_sub(*then).max(1);
+						let delta = namespace.written.checked_sub(*written.get(&namespace.namespace)?)?;
+						Some(delta * 1000 / elapsed)
+					});
+
+					Rate {
+						namespace: namespace.namespace.clone(),
+						bytes: namespace.bytes,
+						max_bytes: namespace.max_bytes,
+						bytes_per_second,
+					}
+				})
+				.collect(),
+		};
+
+		last = Some((
+			now,
+			namespaces
+				.into_iter()
+				.map(|namespace| (namespace.namespace, namespace.written))
+				.collect(),
+		));
+
+		if json {
+			println!("{}", serde_json::to_string(&sample)?);
+			continue;
+		}
+
+		println!("sessions={} draining={}", sample.sessions, sample.draining);
+		for rate in sample.namespaces {
+			println!(
+				"  {:<32} {:>10}/s {:>10} cached {:>10} limit",
+				rate.namespace,
+				rate.bytes_per_second
+					.map(format_bytes)
+					.unwrap_or_else(|| "-".to_string()),
+				format_bytes(rate.bytes),
+				rate.max_bytes.map(format_bytes).unwrap_or_else(|| "-".to_string()),
+			);
+		}
+	}
+}
+
+fn print_events(events: &[client::SessionEvent]) {
+	for event in events {
+		println!(
+			"  +{}.{:03}s {:<6} {}",
+			event.elapsed_ms / 1000,
+			event.elapsed_ms % 1000,
+			event.kind,
+			event.detail
+		);
+	}
+}
+
+fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+	println!("{}", serde_json::to_string_pretty(value)?);
+	Ok(())
+}
+
+fn now_ms() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|now| now.as_millis() as u64)
+		.unwrap_or_default()
+}
+
+fn format_bytes(bytes: u64) -> String {
+	const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+	let mut value = bytes as f64;
+	let mut unit = 0;
+	while value >= 1024.0 && unit + 1 < UNITS.len() {
+		value /= 1024.0;
+		unit += 1;
+	}
+
+	match unit {
+		0 => format!("{} B", bytes),
+		_ => format!("{:.1} {}", value, UNITS[unit]),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bytes() {
+		assert_eq!(format_bytes(0), "0 B");
+		assert_eq!(format_bytes(1023), "1023 B");
+		assert_eq!(format_bytes(1536), "1.5 KiB");
+		assert_eq!(format_bytes(64 * 1024 * 1024), "64.0 MiB");
+		assert_eq!(format_bytes(3 << 40), "3072.0 GiB");
+	}
+}
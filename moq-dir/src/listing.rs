@@ -1,17 +1,67 @@
 use anyhow::Context;
 use bytes::BytesMut;
-use std::collections::{HashSet, VecDeque};
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt,
+	str::FromStr,
+};
 
 use moq_transport::serve::{
 	GroupReader, GroupWriter, GroupsReader, GroupsWriter, ServeError, TrackReader, TrackReaderMode, TrackWriter,
 };
 
+/// The lifecycle of a broadcast in a listing, driven by the relay's status track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListingState {
+	/// Announced, but no objects have been received yet.
+	#[default]
+	Announced,
+
+	/// Objects are flowing.
+	Live,
+
+	/// Objects stopped flowing, or the relay stopped reporting.
+	Stalled,
+
+	/// Unannounced, and about to be removed.
+	Ended,
+}
+
+impl fmt::Display for ListingState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Announced => "announced",
+			Self::Live => "live",
+			Self::Stalled => "stalled",
+			Self::Ended => "ended",
+		})
+	}
+}
+
+impl FromStr for ListingState {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"announced" => Self::Announced,
+			"live" => Self::Live,
+			"stalled" => Self::Stalled,
+			"ended" => Self::Ended,
+			_ => anyhow::bail!("unknown state: {}", s),
+		})
+	}
+}
+
+/// Writes the names in a listing, along with their state.
+///
+/// Each group starts with a snapshot: one `name` per line, followed by a tab and the state unless it's announced.
+/// Further objects are deltas: `+name` to add, `-name` to remove, or `~name` followed by a tab and the new state.
 pub struct ListingWriter {
 	track: Option<TrackWriter>,
 	groups: Option<GroupsWriter>,
 	group: Option<GroupWriter>,
 
-	current: HashSet<String>,
+	current: HashMap<String, ListingState>,
 }
 
 impl ListingWriter {
@@ -20,38 +70,46 @@ impl ListingWriter {
 			track: Some(track),
 			groups: None,
 			group: None,
-			current: HashSet::new(),
+			current: HashMap::new(),
 		}
 	}
 
 	pub fn insert(&mut self, name: String) -> Result<(), ServeError> {
-		if !self.current.insert(name.clone()) {
+		if self.current.contains_key(&name) {
 			return Err(ServeError::Duplicate);
 		}
 
-		match self.group {
-			// Create a delta if the current group is small enough.
-			Some(ref mut group) if self.current.len() < 2 * group.len() => {
-				let msg = format!("+{}", name);
-				group.write(msg.into())?;
-			}
-			// Otherwise create a snapshot with every element.
-			_ => self.group = Some(self.snapshot()?),
-		}
-
-		Ok(())
+		self.current.insert(name.clone(), ListingState::Announced);
+		self.update(format!("+{}", name))
 	}
 
 	pub fn remove(&mut self, name: &str) -> Result<(), ServeError> {
-		if !self.current.remove(name) {
+		if self.current.remove(name).is_none() {
 			return Err(ServeError::NotFound);
 		}
 
+		self.update(format!("-{}", name))
+	}
+
+	pub fn set_state(&mut self, name: &str, state: ListingState) -> Result<(), ServeError> {
+		let current = self.current.get_mut(name).ok_or(ServeError::NotFound)?;
+		if *current == state {
+			return Ok(());
+		}
+
+		*current = state;
+		self.update(format!("~{}\t{}", name, state))
+	}
+
+	pub fn state(&self, name: &str) -> Option<ListingState> {
+		self.current.get(name).copied()
+	}
+
+	fn update(&mut self, delta: String) -> Result<(), ServeError> {
 		match self.group {
 			// Create a delta if the current group is small enough.
 			Some(ref mut group) if self.current.len() < 2 * group.len() => {
-				let msg = format!("-{}", name);
-				group.write(msg.into())?;
+				group.write(delta.into())?;
 			}
 			// Otherwise create a snapshot with every element.
 			_ => self.group = Some(self.snapshot()?),
@@ -70,8 +128,11 @@ impl ListingWriter {
 		let mut group = groups.append(priority)?;
 
 		let mut msg = BytesMut::new();
-		for name in &self.current {
+		for (name, state) in &self.current {
 			msg.extend_from_slice(name.as_bytes());
+			if *state != ListingState::Announced {
+				msg.extend_from_slice(format!("\t{}", state).as_bytes());
+			}
 			msg.extend_from_slice(b"\n");
 		}
 
@@ -94,6 +155,7 @@ impl ListingWriter {
 pub enum ListingDelta {
 	Add(String),
 	Rem(String),
+	State(String, ListingState),
 }
 
 #[derive(Clone)]
@@ -105,7 +167,7 @@ pub struct ListingReader {
 	group: Option<GroupReader>,
 
 	// The current state of the listing.
-	current: HashSet<String>,
+	current: HashMap<String, ListingState>,
 
 	// A list of deltas we need to return
 	deltas: VecDeque<ListingDelta>,
@@ -118,7 +180,7 @@ impl ListingReader {
 			groups: None,
 			group: None,
 
-			current: HashSet::new(),
+			current: HashMap::new(),
 			deltas: VecDeque::new(),
 		}
 	}
@@ -165,25 +227,50 @@ impl ListingReader {
 						anyhow::bail!("empty payload");
 					} else if self.group.as_mut().unwrap().pos() == 1 {
 						// This is a full snapshot, not a delta
-						let set = HashSet::from_iter(payload.split(|&b| b == b'\n').map(|s| String::from_utf8_lossy(s).to_string()));
-
-						for name in set.difference(&self.current) {
-							self.deltas.push_back(ListingDelta::Add(name.clone()));
+						let snapshot = payload
+							.split(|&b| b == b'\n')
+							.filter(|line| !line.is_empty())
+							.map(|line| parse_entry(&String::from_utf8_lossy(line)))
+							.collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+						for (name, state) in &snapshot {
+							match self.current.get(name) {
+								None => {
+									self.deltas.push_back(ListingDelta::Add(name.clone()));
+									if *state != ListingState::Announced {
+										self.deltas.push_back(ListingDelta::State(name.clone(), *state));
+									}
+								}
+								Some(current) if current != state => {
+									self.deltas.push_back(ListingDelta::State(name.clone(), *state));
+								}
+								Some(_) => {}
+							}
 						}
 
-						for name in self.current.difference(&set) {
-							self.deltas.push_back(ListingDelta::Rem(name.clone()));
+						for name in self.current.keys() {
+							if !snapshot.contains_key(name) {
+								self.deltas.push_back(ListingDelta::Rem(name.clone()));
+							}
 						}
 
-						self.current = set;
+						self.current = snapshot;
 
 						if let Some(delta) = self.deltas.pop_front() {
 							return Ok(Some(delta));
 						}
 					} else if payload[0] == b'+' {
-						return Ok(Some(ListingDelta::Add(String::from_utf8_lossy(&payload[1..]).to_string())));
+						let name = String::from_utf8_lossy(&payload[1..]).to_string();
+						self.current.insert(name.clone(), ListingState::Announced);
+						return Ok(Some(ListingDelta::Add(name)));
 					} else if payload[0] == b'-' {
-						return Ok(Some(ListingDelta::Rem(String::from_utf8_lossy(&payload[1..]).to_string())));
+						let name = String::from_utf8_lossy(&payload[1..]).to_string();
+						self.current.remove(&name);
+						return Ok(Some(ListingDelta::Rem(name)));
+					} else if payload[0] == b'~' {
+						let (name, state) = parse_entry(&String::from_utf8_lossy(&payload[1..]))?;
+						self.current.insert(name.clone(), state);
+						return Ok(Some(ListingDelta::State(name, state)));
 					} else {
 						anyhow::bail!("invalid delta: {:?}", payload);
 					}
@@ -198,3 +285,11 @@ impl ListingReader {
 		self.track
 	}
 }
+
+// Parse a `name` or `name\tstate` entry.
+fn parse_entry(entry: &str) -> anyhow::Result<(String, ListingState)> {
+	match entry.rsplit_once('\t') {
+		Some((name, state)) => Ok((name.to_string(), state.parse()?)),
+		None => Ok((entry.to_string(), ListingState::Announced)),
+	}
+}
@@ -5,11 +5,15 @@ use std::{
 
 use moq_transport::serve::{ServeError, Tracks, TracksReader, TracksWriter};
 
-use crate::{ListingReader, ListingWriter};
+use crate::{ListingReader, ListingState, ListingWriter};
 
 struct State {
 	writer: TracksWriter,
 	active: HashMap<String, ListingWriter>,
+
+	// The registration that owns each (prefix, base), so an ended entry can be taken over by a new announce.
+	owners: HashMap<(String, String), u64>,
+	next: u64,
 }
 
 #[derive(Clone)]
@@ -25,6 +29,8 @@ impl Listings {
 		let state = State {
 			writer,
 			active: HashMap::new(),
+			owners: HashMap::new(),
+			next: 0,
 		};
 
 		Self {
@@ -47,7 +53,11 @@ impl Listings {
 
 		let mut state = self.state.lock().unwrap();
 		if let Some(listing) = state.active.get_mut(prefix) {
-			listing.insert(base.to_string())?;
+			match listing.state(base) {
+				// Replace an entry that ended but hasn't been removed yet.
+				Some(ListingState::Ended) => listing.set_state(base, ListingState::Announced)?,
+				_ => listing.insert(base.to_string())?,
+			}
 		} else {
 			log::info!("creating prefix: {}", prefix);
 			let track = state.writer.create(prefix).unwrap();
@@ -59,16 +69,42 @@ impl Listings {
 
 		log::info!("added listing: {} {}", prefix, base);
 
+		let id = state.next;
+		state.next += 1;
+		state.owners.insert((prefix.to_string(), base.to_string()), id);
+
 		Ok(Some(Registration {
 			listing: self.clone(),
 			prefix: prefix.to_string(),
 			base: base.to_string(),
+			id,
 		}))
 	}
 
-	fn remove(&mut self, prefix: &str, base: &str) -> Result<(), ServeError> {
+	fn set_state(&mut self, prefix: &str, base: &str, id: u64, listing_state: ListingState) -> Result<(), ServeError> {
+		let mut state = self.state.lock().unwrap();
+		if state.owners.get(&(prefix.to_string(), base.to_string())) != Some(&id) {
+			return Ok(());
+		}
+
+		let listing = state.active.get_mut(prefix).ok_or(ServeError::NotFound)?;
+		listing.set_state(base, listing_state)?;
+
+		log::info!("updated listing: {} {} state={}", prefix, base, listing_state);
+
+		Ok(())
+	}
+
+	fn remove(&mut self, prefix: &str, base: &str, id: u64) -> Result<(), ServeError> {
 		let mut state = self.state.lock().unwrap();
 
+		// Another registration took over the entry.
+		let key = (prefix.to_string(), base.to_string());
+		if state.owners.get(&key) != Some(&id) {
+			return Ok(());
+		}
+		state.owners.remove(&key);
+
 		let listing = state.active.get_mut(prefix).ok_or(ServeError::NotFound)?;
 		listing.remove(base)?;
 
@@ -108,11 +144,19 @@ pub struct Registration {
 	listing: Listings,
 	prefix: String,
 	base: String,
+	id: u64,
+}
+
+impl Registration {
+	/// Update the state of the entry, unless it was taken over by a newer registration.
+	pub fn set_state(&mut self, state: ListingState) -> Result<(), ServeError> {
+		self.listing.set_state(&self.prefix, &self.base, self.id, state)
+	}
 }
 
 impl Drop for Registration {
 	fn drop(&mut self) {
-		self.listing.remove(&self.prefix, &self.base).ok();
+		self.listing.remove(&self.prefix, &self.base, self.id).ok();
 	}
 }
 
@@ -138,4 +182,25 @@ mod tests {
 		assert!(Listings::prefix("foo.bar.baz") == ("foo.bar.", "baz"));
 		assert!(Listings::prefix("foo.bar.baz.") == ("foo.bar.baz.", ""));
 	}
+
+	#[test]
+	fn test_takeover() {
+		let mut listings = Listings::new(".".to_string());
+
+		let mut old = listings.register(".foo.bar").unwrap().unwrap();
+		old.set_state(ListingState::Ended).unwrap();
+
+		// A new announce replaces the ended entry, and the old registration no longer touches it.
+		let new = listings.register(".foo.bar").unwrap().unwrap();
+		old.set_state(ListingState::Stalled).unwrap();
+		drop(old);
+
+		let state = listings.state.lock().unwrap();
+		assert_eq!(state.active["foo."].state("bar"), Some(ListingState::Announced));
+		drop(state);
+
+		drop(new);
+		let state = listings.state.lock().unwrap();
+		assert!(!state.active.contains_key("foo."));
+	}
 }
@@ -2,7 +2,7 @@ use anyhow::Context;
 use clap::Parser;
 use futures::{stream::FuturesUnordered, StreamExt};
 
-use std::net;
+use std::{net, time::Duration};
 
 use moq_native::{quic, tls};
 
@@ -33,6 +33,10 @@ pub struct Cli {
 	/// Any announcements that don't match are ignored.
 	#[arg(long, default_value = ".")]
 	pub namespace: String,
+
+	/// Keep ended broadcasts in the listing for this long before removing them.
+	#[arg(long, default_value = "10000")]
+	pub ended_ttl_ms: u64,
 }
 
 #[tokio::main]
@@ -48,10 +52,15 @@ async fn main() -> anyhow::Result<()> {
 	let cli = Cli::parse();
 	let tls = cli.tls.load()?;
 
-	let quic = quic::Endpoint::new(quic::Config { bind: cli.bind, tls })?;
+	let quic = quic::Endpoint::new(quic::Config {
+		bind: cli.bind,
+		tls,
+		congestion: Default::default(),
+	})?;
 	let mut quic = quic.server.context("missing server certificate")?;
 
 	let listings = Listings::new(cli.namespace);
+	let ended_ttl = Duration::from_millis(cli.ended_ttl_ms);
 
 	let mut tasks = FuturesUnordered::new();
 
@@ -61,7 +70,7 @@ async fn main() -> anyhow::Result<()> {
 		tokio::select! {
 			res = quic.accept() => {
 				let session = res.context("failed to accept QUIC connection")?;
-				let session = Session::new(session, listings.clone());
+				let session = Session::new(session, listings.clone(), ended_ttl);
 
 				tasks.push(async move {
 					if let Err(err) = session.run().await {
@@ -1,17 +1,33 @@
+use std::time::Duration;
+
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
-use moq_transport::session::{Announced, Publisher, Subscriber};
+use moq_transport::{
+	serve::{Track, TrackReaderMode},
+	session::{Announced, Publisher, Subscriber},
+};
+
+use crate::{ListingState, Listings, Registration};
 
-use crate::Listings;
+/// The track the relay uses to report the state of each broadcast.
+const STATUS_TRACK: &str = ".status";
+
+/// Mark a broadcast as stalled if the relay doesn't send a status within this time.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(3);
 
 #[derive(Clone)]
 pub struct Session {
 	session: web_transport::Session,
 	listings: Listings,
+	ended_ttl: Duration,
 }
 
 impl Session {
-	pub fn new(session: web_transport::Session, listings: Listings) -> Self {
-		Self { session, listings }
+	pub fn new(session: web_transport::Session, listings: Listings, ended_ttl: Duration) -> Self {
+		Self {
+			session,
+			listings,
+			ended_ttl,
+		}
 	}
 
 	pub async fn run(self) -> anyhow::Result<()> {
@@ -49,12 +65,13 @@ impl Session {
 			tokio::select! {
 				Some(announce) = remote.announced() => {
 					let this = self.clone();
+					let remote = remote.clone();
 
 					tasks.push(async move {
 						let info = announce.clone();
 						log::info!("serving announce: {:?}", info);
 
-						if let Err(err) = this.serve_announce(announce).await {
+						if let Err(err) = this.serve_announce(announce, remote).await {
 							log::warn!("failed serving announce: {:?}, error: {}", info, err)
 						}
 					});
@@ -65,16 +82,78 @@ impl Session {
 		}
 	}
 
-	async fn serve_announce(mut self, mut announce: Announced) -> anyhow::Result<()> {
+	async fn serve_announce(mut self, mut announce: Announced, remote: Subscriber) -> anyhow::Result<()> {
 		announce.ok()?;
 
-		match self.listings.register(&announce.namespace) {
-			Ok(_) => announce.closed().await?,
+		let mut registration = match self.listings.register(&announce.namespace) {
+			Ok(Some(registration)) => registration,
+			Ok(None) => return Ok(announce.closed().await?),
 			Err(err) => {
 				announce.close(err.clone())?;
 				return Err(err.into());
 			}
-		}
+		};
+
+		let namespace = announce.namespace.clone();
+		let res = tokio::select! {
+			res = announce.closed() => res,
+			res = Self::watch_status(remote, namespace.clone(), &mut registration) => {
+				// Older relays don't serve the status track, so the entry stays announced.
+				if let Err(err) = res {
+					log::debug!("no status for {}: {}", namespace, err);
+				}
+
+				announce.closed().await
+			}
+		};
+
+		// Keep the ended entry around for a while so viewers can tell it apart from a typo.
+		registration.set_state(ListingState::Ended).ok();
+		tokio::time::sleep(self.ended_ttl).await;
+
+		Ok(res?)
+	}
+
+	async fn watch_status(
+		mut remote: Subscriber,
+		namespace: String,
+		registration: &mut Registration,
+	) -> anyhow::Result<()> {
+		let (writer, reader) = Track::new(namespace, STATUS_TRACK.to_string()).produce();
+
+		let read = async {
+			let mut groups = match reader.mode().await? {
+				TrackReaderMode::Groups(groups) => groups,
+				_ => anyhow::bail!("unexpected status mode"),
+			};
+
+			loop {
+				let mut group = match tokio::time::timeout(STATUS_TIMEOUT, groups.next()).await {
+					Ok(res) => match res? {
+						Some(group) => group,
+						None => return Ok(()),
+					},
+					Err(_) => {
+						registration.set_state(ListingState::Stalled)?;
+						continue;
+					}
+				};
+
+				let Some(payload) = group.read_next().await? else {
+					continue;
+				};
+
+				match std::str::from_utf8(&payload)?.parse::<ListingState>() {
+					Ok(state) => registration.set_state(state)?,
+					Err(_) => log::warn!("unknown status: {:?}", payload),
+				}
+			}
+		};
+
+		tokio::select! {
+			res = remote.subscribe(writer) => res?,
+			res = read => res?,
+		};
 
 		Ok(())
 	}
@@ -0,0 +1,24 @@
+use clap::Parser;
+
+/// Identify the broadcast across relays, even if they rewrite its namespace.
+#[derive(Parser, Clone, Default)]
+#[group(id = "broadcast")]
+pub struct Args {
+	/// The broadcast ID sent with the announce and forwarded by every relay, defaulting to a random UUID.
+	/// Reuse the same ID after a restart to keep tracing it as the same broadcast.
+	#[arg(long = "broadcast-id")]
+	pub id: Option<String>,
+}
+
+impl Args {
+	pub fn load(&self) -> String {
+		let id = self.id.clone().unwrap_or_else(generate_id);
+		log::info!("broadcast id: {}", id);
+		id
+	}
+}
+
+/// Generate a random broadcast ID.
+pub fn generate_id() -> String {
+	uuid::Uuid::new_v4().to_string()
+}
@@ -0,0 +1,91 @@
+use std::{sync::Arc, time::Duration};
+
+use clap::Parser;
+
+/// Starting points for how quickly a connection ramps up, before the congestion controller has measured the path.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Profile {
+	/// The QUIC defaults: a 240KB initial window with BBR, paced out over an assumed 333ms RTT.
+	#[default]
+	Default,
+
+	/// Send the first keyframes in a burst, for publishers on links with a large bandwidth-delay product.
+	/// Otherwise pacing over the assumed RTT, then slow start, delays the first group and so the stream starting.
+	Contribution,
+}
+
+impl Profile {
+	pub fn config(&self) -> Config {
+		match self {
+			Self::Default => Config::default(),
+			Self::Contribution => Config {
+				initial_window: Some(512 * 1024),
+				initial_rtt: Some(Duration::from_millis(50)),
+			},
+		}
+	}
+}
+
+/// Tune the start of each QUIC connection.
+#[derive(Parser, Clone, Default)]
+#[group(id = "congestion")]
+pub struct Args {
+	/// Start from the given profile, overridden by any flags below.
+	#[arg(long = "quic-profile", value_enum)]
+	pub profile: Option<Profile>,
+
+	/// The congestion window in bytes before any acknowledgements are received.
+	#[arg(long = "quic-initial-window")]
+	pub initial_window: Option<u64>,
+
+	/// The RTT assumed until it's measured, in milliseconds.
+	/// QUIC always paces, sending the initial window over this long, so a lower value sends the first burst faster.
+	#[arg(long = "quic-initial-rtt-ms")]
+	pub initial_rtt_ms: Option<u64>,
+}
+
+impl Args {
+	pub fn load(&self) -> Config {
+		self.load_or(Profile::Default)
+	}
+
+	/// Like [Self::load], but using the given profile unless one was provided, ex. when a binary knows its use case.
+	pub fn load_or(&self, profile: Profile) -> Config {
+		let mut config = self.profile.unwrap_or(profile).config();
+
+		if let Some(window) = self.initial_window {
+			config.initial_window = Some(window);
+		}
+
+		if let Some(rtt) = self.initial_rtt_ms {
+			config.initial_rtt = Some(Duration::from_millis(rtt));
+		}
+
+		config
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Config {
+	/// The initial congestion window in bytes, or the QUIC default.
+	pub initial_window: Option<u64>,
+
+	/// The RTT assumed until it's measured, or the QUIC default.
+	pub initial_rtt: Option<Duration>,
+}
+
+impl Config {
+	pub(crate) fn apply(&self, transport: &mut quinn::TransportConfig) {
+		// Enable BBR congestion control
+		// TODO validate the implementation
+		let mut bbr = quinn::congestion::BbrConfig::default();
+		if let Some(window) = self.initial_window {
+			bbr.initial_window(window);
+		}
+		transport.congestion_controller_factory(Arc::new(bbr));
+
+		if let Some(rtt) = self.initial_rtt {
+			transport.initial_rtt(rtt);
+		}
+	}
+}
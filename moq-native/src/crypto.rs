@@ -0,0 +1,394 @@
+//! End-to-end encryption of object payloads, so relays forward media without being able to decode it.
+//!
+//! The publisher encrypts each object with a [ContentKey]. Subscribers get the key by subscribing to a key request
+//! track, [KEY_TRACK] with a [KeyRequest] in the query, which the relay forwards like any other track. The request
+//! contains a single-use X25519 public key signed by the subscriber's Ed25519 identity. The publisher checks the
+//! identity is authorized and serves the content key sealed to the X25519 key, so the relay only sees ciphertext.
+use std::{collections::HashSet, path, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use clap::Parser;
+use moq_transport::serve::{ServeError, TrackName, TrackWriter, TracksRequest};
+use ring::{
+	aead::{self, Aad, LessSafeKey, Nonce, UnboundKey},
+	agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519},
+	hkdf,
+	rand::{SecureRandom, SystemRandom},
+};
+
+use crate::sign::{self, SigningKey};
+
+/// The path of the track used to request a content key, followed by the [KeyRequest] parameters.
+pub const KEY_TRACK: &str = ".keys";
+
+// Distinguishes the signed request from any other payload signed by the same key, ex. an announce.
+const REQUEST_CONTEXT: &[u8] = b"moq-key-request";
+
+// Binds the key used to seal the response to this protocol.
+const RESPONSE_INFO: &[u8] = b"moq-key-response";
+
+// How long the response is kept available for the relay to forward.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Request the content key for encrypted broadcasts.
+#[derive(Parser, Clone, Default)]
+#[group(id = "crypto")]
+pub struct Args {
+	/// Request content keys with the Ed25519 private key at this path, encoded as PEM, and decrypt each object.
+	///
+	/// Generate one with `openssl genpkey -algorithm ed25519`.
+	/// The public key is logged on startup, to be authorized by the publisher.
+	#[arg(long = "e2ee-identity")]
+	pub identity: Option<path::PathBuf>,
+}
+
+impl Args {
+	/// Returns None if no identity was configured.
+	pub fn load(&self) -> anyhow::Result<Option<SigningKey>> {
+		let path = match &self.identity {
+			Some(path) => path,
+			None => return Ok(None),
+		};
+
+		let pem = std::fs::read(path).with_context(|| format!("failed to read identity: {}", path.display()))?;
+		let key = SigningKey::from_pem(&pem).with_context(|| format!("invalid identity: {}", path.display()))?;
+
+		log::info!("requesting content keys: identity={}", key.public_key());
+
+		Ok(Some(key))
+	}
+}
+
+/// A symmetric key used to encrypt object payloads with AES-256-GCM.
+///
+/// Each encrypted payload starts with the key ID, so subscribers can tell when the key was rotated.
+#[derive(Clone)]
+pub struct ContentKey {
+	id: u64,
+	secret: [u8; KEY_LEN],
+}
+
+impl ContentKey {
+	/// Generate a random key with the given ID, which should be unique within the broadcast.
+	pub fn generate(id: u64) -> anyhow::Result<Self> {
+		let mut secret = [0; KEY_LEN];
+		SystemRandom::new()
+			.fill(&mut secret)
+			.map_err(|_| anyhow::anyhow!("failed to generate key"))?;
+
+		Ok(Self { id, secret })
+	}
+
+	pub fn id(&self) -> u64 {
+		self.id
+	}
+
+	fn aead(&self) -> LessSafeKey {
+		// Can't fail, since the secret is always the right length.
+		LessSafeKey::new(UnboundKey::new(&aead::AES_256_GCM, &self.secret).unwrap())
+	}
+
+	/// Encrypt a payload, prefixed by the key ID and a random nonce.
+	pub fn encrypt(&self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+		let mut nonce = [0; NONCE_LEN];
+		SystemRandom::new()
+			.fill(&mut nonce)
+			.map_err(|_| anyhow::anyhow!("failed to generate nonce"))?;
+
+		let id = self.id.to_be_bytes();
+
+		let mut sealed = payload.to_vec();
+		self.aead()
+			.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(id), &mut sealed)
+			.map_err(|_| anyhow::anyhow!("failed to encrypt"))?;
+
+		Ok([&id[..], &nonce[..], &sealed].concat())
+	}
+
+	/// Decrypt a payload produced by [Self::encrypt], failing if it used a different key or was modified.
+	pub fn decrypt(&self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+		let id = key_id(payload).context("truncated payload")?;
+		anyhow::ensure!(id == self.id, "wrong key: expected={} actual={}", self.id, id);
+
+		let nonce: [u8; NONCE_LEN] = payload.get(8..8 + NONCE_LEN).context("truncated payload")?.try_into()?;
+
+		let mut sealed = payload[8 + NONCE_LEN..].to_vec();
+		let opened = self
+			.aead()
+			.open_in_place(
+				Nonce::assume_unique_for_key(nonce),
+				Aad::from(id.to_be_bytes()),
+				&mut sealed,
+			)
+			.map_err(|_| anyhow::anyhow!("failed to decrypt"))?;
+
+		Ok(opened.to_vec())
+	}
+}
+
+/// Returns the ID of the key used to encrypt the payload, or None if it's too short.
+pub fn key_id(payload: &[u8]) -> Option<u64> {
+	Some(u64::from_be_bytes(payload.get(..8)?.try_into().ok()?))
+}
+
+/// A request for the content key of a namespace, encoded in the query of the [KEY_TRACK] name.
+pub struct KeyRequest {
+	/// The hex encoded Ed25519 public key of the subscriber.
+	pub identity: String,
+
+	/// The X25519 public key the response is sealed to.
+	pub public: Vec<u8>,
+
+	/// The identity's signature over the namespace and X25519 public key.
+	pub signature: Vec<u8>,
+}
+
+impl KeyRequest {
+	fn payload(namespace: &str, public: &[u8]) -> Vec<u8> {
+		[
+			REQUEST_CONTEXT,
+			&(namespace.len() as u64).to_be_bytes(),
+			namespace.as_bytes(),
+			public,
+		]
+		.concat()
+	}
+
+	/// Parse the request from a track name, returning None if it isn't a [KEY_TRACK].
+	pub fn parse(name: &str) -> Option<anyhow::Result<Self>> {
+		let name = TrackName::parse(name);
+		if name.path != KEY_TRACK {
+			return None;
+		}
+
+		let param = |key: &str| -> anyhow::Result<Vec<u8>> {
+			let value = name.get(key).with_context(|| format!("missing {}", key))?;
+			hex::decode(value).with_context(|| format!("invalid {}", key))
+		};
+
+		let request = || {
+			Ok(Self {
+				identity: hex::encode(param("identity")?),
+				public: param("public")?,
+				signature: param("signature")?,
+			})
+		};
+
+		Some(request())
+	}
+
+	/// The name of the track to subscribe to.
+	pub fn track_name(&self) -> String {
+		TrackName::new(KEY_TRACK)
+			.with("identity", &self.identity)
+			.with("public", hex::encode(&self.public))
+			.with("signature", hex::encode(&self.signature))
+			.to_string()
+	}
+
+	/// Check that the identity signed the request for this namespace.
+	pub fn verify(&self, namespace: &str) -> anyhow::Result<()> {
+		let identity = hex::decode(&self.identity).context("invalid identity")?;
+		let payload = Self::payload(namespace, &self.public);
+		sign::verify_payload(&identity, &payload, &self.signature)
+	}
+}
+
+/// The subscriber's half of a key request, holding the private key needed to open the response.
+///
+/// Each request can only be opened once; make a new one to get a rotated key.
+pub struct KeyRequester {
+	private: EphemeralPrivateKey,
+	request: KeyRequest,
+}
+
+impl KeyRequester {
+	/// Create a request for the namespace's content key, signed by our identity.
+	pub fn new(identity: &SigningKey, namespace: &str) -> anyhow::Result<Self> {
+		let private = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new())
+			.map_err(|_| anyhow::anyhow!("failed to generate key"))?;
+		let public = private
+			.compute_public_key()
+			.map_err(|_| anyhow::anyhow!("failed to compute public key"))?
+			.as_ref()
+			.to_vec();
+
+		let signature = identity.sign_payload(&KeyRequest::payload(namespace, &public));
+
+		let request = KeyRequest {
+			identity: identity.public_key(),
+			public,
+			signature,
+		};
+
+		Ok(Self { private, request })
+	}
+
+	/// The name of the track to subscribe to, see [KeyRequest::track_name].
+	pub fn track_name(&self) -> String {
+		self.request.track_name()
+	}
+
+	/// Open the publisher's response, the first object on the key request track.
+	pub fn open(self, response: &[u8]) -> anyhow::Result<ContentKey> {
+		anyhow::ensure!(response.len() > KEY_LEN, "truncated response");
+		let (peer, sealed) = response.split_at(KEY_LEN);
+
+		let salt = [&self.request.public, peer].concat();
+		let wrap = agree(self.private, peer, &salt)?;
+
+		let mut sealed = sealed.to_vec();
+		let opened = wrap
+			.open_in_place(Nonce::assume_unique_for_key([0; NONCE_LEN]), Aad::empty(), &mut sealed)
+			.map_err(|_| anyhow::anyhow!("failed to open response"))?;
+
+		anyhow::ensure!(opened.len() == 8 + KEY_LEN, "invalid response");
+
+		Ok(ContentKey {
+			id: u64::from_be_bytes(opened[..8].try_into()?),
+			secret: opened[8..].try_into()?,
+		})
+	}
+}
+
+// Derive the key that seals the response, salted with both X25519 public keys, subscriber then publisher.
+fn agree(private: EphemeralPrivateKey, peer: &[u8], salt: &[u8]) -> anyhow::Result<LessSafeKey> {
+	let peer = UnparsedPublicKey::new(&X25519, peer);
+	agreement::agree_ephemeral(private, &peer, |secret| {
+		let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(secret);
+		let okm = prk
+			.expand(&[RESPONSE_INFO], &aead::AES_256_GCM)
+			.map_err(|_| anyhow::anyhow!("failed to derive key"))?;
+
+		Ok(LessSafeKey::new(UnboundKey::from(okm)))
+	})
+	.map_err(|_| anyhow::anyhow!("invalid public key"))?
+}
+
+/// Answers key requests for a broadcast, giving the content key to authorized subscribers.
+///
+/// Only the publisher, or a key server it trusts, should run this; relays forward the requests untouched.
+#[derive(Clone)]
+pub struct KeyServer {
+	key: ContentKey,
+	authorized: Arc<HashSet<String>>,
+}
+
+impl KeyServer {
+	pub fn new(key: ContentKey) -> Self {
+		Self {
+			key,
+			authorized: Default::default(),
+		}
+	}
+
+	/// Give the key to this hex encoded Ed25519 public key.
+	pub fn with_authorized(mut self, identity: &str) -> Self {
+		Arc::make_mut(&mut self.authorized).insert(identity.to_lowercase());
+		self
+	}
+
+	/// Return the response to a request for the namespace, or an error if it's invalid or unauthorized.
+	pub fn respond(&self, namespace: &str, request: &KeyRequest) -> anyhow::Result<Vec<u8>> {
+		request.verify(namespace)?;
+		anyhow::ensure!(
+			self.authorized.contains(&request.identity),
+			"unauthorized identity: {}",
+			request.identity
+		);
+
+		let private = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new())
+			.map_err(|_| anyhow::anyhow!("failed to generate key"))?;
+		let public = private
+			.compute_public_key()
+			.map_err(|_| anyhow::anyhow!("failed to compute public key"))?;
+		let public = public.as_ref().to_vec();
+
+		let salt = [&request.public[..], &public].concat();
+		let wrap = agree(private, &request.public, &salt)?;
+
+		// The wrapping key is only used once, so a fixed nonce is safe.
+		let mut sealed = [&self.key.id.to_be_bytes()[..], &self.key.secret].concat();
+		wrap.seal_in_place_append_tag(Nonce::assume_unique_for_key([0; NONCE_LEN]), Aad::empty(), &mut sealed)
+			.map_err(|_| anyhow::anyhow!("failed to seal response"))?;
+
+		Ok([public, sealed].concat())
+	}
+
+	/// Answer each request for a [KEY_TRACK], rejecting any other unknown track with a 404.
+	pub async fn serve(self, mut request: TracksRequest) {
+		while let Some(track) = request.next().await {
+			let server = self.clone();
+			tokio::spawn(async move {
+				let name = track.name.clone();
+				if let Err(err) = server.serve_track(track).await {
+					log::warn!("failed to serve key request: name={} err={}", name, err);
+				}
+			});
+		}
+	}
+
+	async fn serve_track(&self, track: TrackWriter) -> anyhow::Result<()> {
+		let request = match KeyRequest::parse(&track.name) {
+			Some(request) => request,
+			None => {
+				track.close(ServeError::NotFound)?;
+				return Ok(());
+			}
+		};
+
+		let response = match request.and_then(|request| self.respond(&track.namespace, &request)) {
+			Ok(response) => response,
+			Err(err) => {
+				track.close(ServeError::Rejected(403, err.to_string()))?;
+				return Err(err);
+			}
+		};
+
+		let mut groups = track.groups()?;
+		groups.append(0)?.write(response.into())?;
+
+		// Keep the track open until the response is forwarded, since closing it drops the group.
+		tokio::time::timeout(RESPONSE_TIMEOUT, groups.unused()).await.ok();
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn request_key() {
+		let key = ContentKey::generate(7).unwrap();
+		let identity = SigningKey::generate().unwrap();
+		let server = KeyServer::new(key.clone()).with_authorized(&identity.public_key());
+
+		let requester = KeyRequester::new(&identity, "live/alice").unwrap();
+		let request = KeyRequest::parse(&requester.track_name()).unwrap().unwrap();
+
+		// The request is bound to the namespace.
+		assert!(server.respond("live/mallory", &request).is_err());
+
+		let response = server.respond("live/alice", &request).unwrap();
+		let opened = requester.open(&response).unwrap();
+		assert_eq!(opened.id(), 7);
+
+		let encrypted = key.encrypt(b"hello").unwrap();
+		assert_eq!(key_id(&encrypted), Some(7));
+		assert_eq!(opened.decrypt(&encrypted).unwrap(), b"hello");
+
+		// Only authorized identities get the key.
+		let other = SigningKey::generate().unwrap();
+		let requester = KeyRequester::new(&other, "live/alice").unwrap();
+		let request = KeyRequest::parse(&requester.track_name()).unwrap().unwrap();
+		assert!(server.respond("live/alice", &request).is_err());
+
+		// Other tracks aren't key requests.
+		assert!(KeyRequest::parse("1.m4s").is_none());
+	}
+}
@@ -1,2 +1,11 @@
+pub mod broadcast;
+pub mod congestion;
+pub mod crypto;
+pub mod log;
+#[cfg(feature = "netem")]
+pub mod netem;
+pub mod preset;
 pub mod quic;
+pub mod sign;
+pub mod tcp;
 pub mod tls;
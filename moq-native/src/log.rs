@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use clap::Parser;
+use moq_transport::sample::{self, SampleConfig};
+
+/// Sample high-volume log lines, ex. one per object, so logs stay readable under load.
+#[derive(Parser, Clone, Default)]
+#[group(id = "log")]
+pub struct Args {
+	/// Only log every Nth occurrence of each per-object log line.
+	#[arg(long = "log-sample-every", default_value = "1")]
+	pub every: u64,
+
+	/// Log each per-object log line at most once per interval, in milliseconds.
+	#[arg(long = "log-sample-interval-ms")]
+	pub interval_ms: Option<u64>,
+}
+
+impl Args {
+	/// Apply the sampling configuration for the process; only the first call has any effect.
+	pub fn init(&self) {
+		sample::configure(SampleConfig {
+			every: self.every.max(1),
+			interval: self.interval_ms.map(Duration::from_millis),
+		});
+	}
+}
@@ -0,0 +1,178 @@
+use std::{
+	collections::VecDeque,
+	fmt, io,
+	net::{IpAddr, SocketAddr},
+	pin::Pin,
+	sync::Arc,
+	task::{Context, Poll},
+	time::Duration,
+};
+
+use clap::Parser;
+use quinn::{
+	udp::{EcnCodepoint, RecvMeta, Transmit},
+	AsyncUdpSocket, UdpPoller,
+};
+use tokio::{sync::mpsc, time::Instant};
+
+/// Simulate a degraded network by dropping and delaying QUIC packets, for platforms without `tc netem`.
+#[derive(Parser, Clone, Default)]
+#[group(id = "netem")]
+pub struct Args {
+	/// Drop this percentage of packets, in both directions.
+	#[arg(long = "netem-loss", default_value = "0")]
+	pub loss: f64,
+
+	/// Delay each sent packet by this long, adding to the round-trip time.
+	#[arg(long = "netem-delay-ms", default_value = "0")]
+	pub delay_ms: u64,
+}
+
+impl Args {
+	pub fn is_enabled(&self) -> bool {
+		self.loss > 0.0 || self.delay_ms > 0
+	}
+}
+
+// A packet waiting to be sent.
+struct Delayed {
+	deadline: Instant,
+	destination: SocketAddr,
+	ecn: Option<EcnCodepoint>,
+	src_ip: Option<IpAddr>,
+	contents: Vec<u8>,
+}
+
+/// Wraps a UDP socket, randomly dropping received and sent packets and delaying sent packets.
+///
+/// Delayed packets are sent in order by a background task, and are dropped if the socket isn't writable by then.
+pub struct Netem {
+	inner: Arc<dyn AsyncUdpSocket>,
+	loss: f64,
+	delay: Duration,
+	delayed: mpsc::UnboundedSender<Delayed>,
+}
+
+impl Netem {
+	/// Must be called from within a tokio runtime.
+	pub fn new(inner: Arc<dyn AsyncUdpSocket>, args: &Args) -> anyhow::Result<Self> {
+		anyhow::ensure!((0.0..=100.0).contains(&args.loss), "--netem-loss must be a percentage");
+
+		let (delayed, queue) = mpsc::unbounded_channel();
+		tokio::spawn(Self::run_delayed(inner.clone(), queue));
+
+		Ok(Self {
+			inner,
+			loss: args.loss / 100.0,
+			delay: Duration::from_millis(args.delay_ms),
+			delayed,
+		})
+	}
+
+	fn drop_packet(&self) -> bool {
+		self.loss > 0.0 && rand::random::<f64>() < self.loss
+	}
+
+	async fn run_delayed(inner: Arc<dyn AsyncUdpSocket>, mut queue: mpsc::UnboundedReceiver<Delayed>) {
+		// Every packet has the same delay, so the queue is already sorted by deadline.
+		let mut pending = VecDeque::new();
+
+		while let Some(packet) = queue.recv().await {
+			pending.push_back(packet);
+
+			while let Some(packet) = pending.pop_front() {
+				tokio::time::sleep_until(packet.deadline).await;
+
+				let transmit = Transmit {
+					destination: packet.destination,
+					ecn: packet.ecn,
+					contents: &packet.contents,
+					segment_size: None,
+					src_ip: packet.src_ip,
+				};
+
+				// A congested socket behaves like any other lossy network.
+				if let Err(err) = inner.try_send(&transmit) {
+					log::trace!("dropped delayed packet: {}", err);
+				}
+
+				while let Ok(packet) = queue.try_recv() {
+					pending.push_back(packet);
+				}
+			}
+		}
+	}
+}
+
+impl fmt::Debug for Netem {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Netem")
+			.field("inner", &self.inner)
+			.field("loss", &self.loss)
+			.field("delay", &self.delay)
+			.finish()
+	}
+}
+
+impl AsyncUdpSocket for Netem {
+	fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+		self.inner.clone().create_io_poller()
+	}
+
+	fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+		if self.drop_packet() {
+			return Ok(());
+		}
+
+		if self.delay.is_zero() {
+			return self.inner.try_send(transmit);
+		}
+
+		let packet = Delayed {
+			deadline: Instant::now() + self.delay,
+			destination: transmit.destination,
+			ecn: transmit.ecn,
+			src_ip: transmit.src_ip,
+			contents: transmit.contents.to_vec(),
+		};
+
+		// The task only exits when the runtime shuts down, at which point it doesn't matter.
+		let _ = self.delayed.send(packet);
+		Ok(())
+	}
+
+	fn poll_recv(
+		&self,
+		cx: &mut Context,
+		bufs: &mut [io::IoSliceMut<'_>],
+		meta: &mut [RecvMeta],
+	) -> Poll<io::Result<usize>> {
+		let count = std::task::ready!(self.inner.poll_recv(cx, bufs, meta))?;
+
+		// Quinn skips empty datagrams, so zeroing the length drops them.
+		for meta in meta.iter_mut().take(count) {
+			if self.drop_packet() {
+				meta.len = 0;
+			}
+		}
+
+		Poll::Ready(Ok(count))
+	}
+
+	fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.inner.local_addr()
+	}
+
+	// Disable GSO, so each transmit is a single packet that can be dropped or delayed on its own.
+	fn max_transmit_segments(&self) -> usize {
+		1
+	}
+
+	fn max_receive_segments(&self) -> usize {
+		self.inner.max_receive_segments()
+	}
+
+	fn may_fragment(&self) -> bool {
+		self.inner.may_fragment()
+	}
+}
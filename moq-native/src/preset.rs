@@ -0,0 +1,27 @@
+use moq_transport::serve::GroupSkip;
+
+/// Coherent defaults for trading latency against quality, so new users don't need to tune each knob.
+///
+/// Each binary maps the preset onto its own settings, and any flag provided explicitly takes precedence.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+	/// Skip to the newest group whenever behind, with small buffers.
+	Latency,
+
+	/// Tolerate a couple of groups of lag before skipping.
+	Balanced,
+
+	/// Never skip groups, with larger buffers and caches.
+	Quality,
+}
+
+impl Preset {
+	/// What a reader does when it falls behind.
+	pub fn skip(&self) -> GroupSkip {
+		match self {
+			Self::Latency => GroupSkip::Latest,
+			Self::Balanced => GroupSkip::Lag(2),
+			Self::Quality => GroupSkip::Never,
+		}
+	}
+}
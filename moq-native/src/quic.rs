@@ -4,7 +4,9 @@ use anyhow::Context;
 use clap::Parser;
 use url::Url;
 
-use crate::tls;
+use moq_transport::{session::SetupOptions, setup, transport};
+
+use crate::{congestion, tls};
 
 use futures::future::BoxFuture;
 use futures::stream::{FuturesUnordered, StreamExt};
@@ -18,6 +20,9 @@ pub struct Args {
 
 	#[command(flatten)]
 	pub tls: tls::Args,
+
+	#[command(flatten)]
+	pub congestion: congestion::Args,
 }
 
 impl Default for Args {
@@ -25,6 +30,7 @@ impl Default for Args {
 		Self {
 			bind: "[::]:0".parse().unwrap(),
 			tls: Default::default(),
+			congestion: Default::default(),
 		}
 	}
 }
@@ -32,13 +38,20 @@ impl Default for Args {
 impl Args {
 	pub fn load(&self) -> anyhow::Result<Config> {
 		let tls = self.tls.load()?;
-		Ok(Config { bind: self.bind, tls })
+		Ok(Config {
+			bind: self.bind,
+			tls,
+			congestion: self.congestion.load(),
+		})
 	}
 }
 
 pub struct Config {
 	pub bind: net::SocketAddr,
 	pub tls: tls::Config,
+
+	/// How quickly each connection ramps up, see [congestion::Profile].
+	pub congestion: congestion::Config,
 }
 
 pub struct Endpoint {
@@ -48,13 +61,40 @@ pub struct Endpoint {
 
 impl Endpoint {
 	pub fn new(config: Config) -> anyhow::Result<Self> {
-		// Enable BBR congestion control
-		// TODO validate the implementation
+		let socket = std::net::UdpSocket::bind(config.bind).context("failed to bind UDP socket")?;
+		Self::with_socket(config, socket, Ok)
+	}
+
+	/// Like [Endpoint::new], but using an existing UDP socket instead of binding to `config.bind`.
+	///
+	/// This is useful for a socket inherited from the service manager, ex. systemd socket activation.
+	pub fn with_udp_socket(config: Config, socket: std::net::UdpSocket) -> anyhow::Result<Self> {
+		Self::with_socket(config, socket, Ok)
+	}
+
+	/// Like [Endpoint::new], but drops and delays packets to simulate a degraded network.
+	#[cfg(feature = "netem")]
+	pub fn with_netem(config: Config, netem: &crate::netem::Args) -> anyhow::Result<Self> {
+		if !netem.is_enabled() {
+			return Self::new(config);
+		}
+
+		log::warn!("simulating network: loss={}% delay={}ms", netem.loss, netem.delay_ms);
+		let socket = std::net::UdpSocket::bind(config.bind).context("failed to bind UDP socket")?;
+		Self::with_socket(config, socket, |socket| {
+			Ok(Arc::new(crate::netem::Netem::new(socket, netem)?))
+		})
+	}
+
+	fn with_socket<F>(config: Config, socket: std::net::UdpSocket, wrap: F) -> anyhow::Result<Self>
+	where
+		F: FnOnce(Arc<dyn quinn::AsyncUdpSocket>) -> anyhow::Result<Arc<dyn quinn::AsyncUdpSocket>>,
+	{
 		let mut transport = quinn::TransportConfig::default();
 		transport.max_idle_timeout(Some(time::Duration::from_secs(10).try_into().unwrap()));
 		transport.keep_alive_interval(Some(time::Duration::from_secs(4))); // TODO make this smarter
-		transport.congestion_controller_factory(Arc::new(quinn::congestion::BbrConfig::default()));
 		transport.mtu_discovery_config(None); // Disable MTU discovery
+		config.congestion.apply(&mut transport);
 		let transport = Arc::new(transport);
 
 		let mut server_config = None;
@@ -73,10 +113,13 @@ impl Endpoint {
 		// There's a bit more boilerplate to make a generic endpoint.
 		let runtime = quinn::default_runtime().context("no async runtime")?;
 		let endpoint_config = quinn::EndpointConfig::default();
-		let socket = std::net::UdpSocket::bind(config.bind).context("failed to bind UDP socket")?;
+
+		// An inherited socket may be blocking, which the runtime doesn't expect.
+		socket.set_nonblocking(true)?;
+		let socket = wrap(runtime.wrap_udp_socket(socket)?)?;
 
 		// Create the generic QUIC endpoint.
-		let quic = quinn::Endpoint::new(endpoint_config, server_config.clone(), socket, runtime)
+		let quic = quinn::Endpoint::new_with_abstract_socket(endpoint_config, server_config.clone(), socket, runtime)
 			.context("failed to create QUIC endpoint")?;
 
 		let server = server_config.is_some().then(|| Server {
@@ -182,14 +225,18 @@ pub struct Client {
 }
 
 impl Client {
-	pub async fn connect(&self, url: &Url) -> anyhow::Result<web_transport::Session> {
+	pub async fn connect(&self, url: &Url) -> anyhow::Result<transport::Session> {
+		if url.scheme() == "moqt+tcp" {
+			return self.connect_tcp(url).await;
+		}
+
 		let mut config = self.config.clone();
 
 		// TODO support connecting to both ALPNs at the same time
 		config.alpn_protocols = vec![match url.scheme() {
 			"https" => web_transport_quinn::ALPN.to_vec(),
 			"moqt" => moq_transport::setup::ALPN.to_vec(),
-			_ => anyhow::bail!("url scheme must be 'https' or 'moqt'"),
+			_ => anyhow::bail!("url scheme must be 'https', 'moqt', or 'moqt+tcp'"),
 		}];
 
 		config.key_log = Arc::new(rustls::KeyLogFile::new());
@@ -198,24 +245,290 @@ impl Client {
 		let mut config = quinn::ClientConfig::new(Arc::new(config));
 		config.transport_config(self.transport.clone());
 
+		let (host, addrs) = Self::lookup(url).await?;
+
+		let connection = Self::failover(url, addrs, |addr| {
+			let connecting = self.quic.connect_with(config.clone(), addr, &host);
+			async move { Ok(connecting?.await?) }
+		})
+		.await?;
+
+		let session = match url.scheme() {
+			"https" => web_transport_quinn::connect_with(connection, url).await?,
+			"moqt" => connection.into(),
+			_ => unreachable!(),
+		};
+
+		Ok(web_transport::Session::from(session).into())
+	}
+
+	// Fallback for networks that block UDP, multiplexing streams over a single TLS connection.
+	async fn connect_tcp(&self, url: &Url) -> anyhow::Result<transport::Session> {
+		let mut config = self.config.clone();
+		config.alpn_protocols = vec![moq_transport::setup::ALPN.to_vec()];
+		config.key_log = Arc::new(rustls::KeyLogFile::new());
+
+		let (host, addrs) = Self::lookup(url).await?;
+
+		let name = rustls::pki_types::ServerName::try_from(host).context("invalid DNS name")?;
+		let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+
+		let tls = Self::failover(url, addrs, |addr| {
+			let name = name.clone();
+			let connector = connector.clone();
+
+			async move {
+				let tcp = tokio::net::TcpStream::connect(addr)
+					.await
+					.context("failed to connect TCP")?;
+				tcp.set_nodelay(true)?;
+
+				connector.connect(name, tcp).await.context("failed TLS handshake")
+			}
+		})
+		.await?;
+
+		Ok(transport::mux::Session::new(tls, false).into())
+	}
+
+	// Resolve every A/AAAA record for the host.
+	//
+	// This runs on each connect rather than caching the result, so a long-lived reconnect loop follows DNS changes
+	// as soon as the system resolver's TTL expires, ex. when an anycast region is drained.
+	async fn lookup(url: &Url) -> anyhow::Result<(String, Vec<net::SocketAddr>)> {
 		let host = url.host().context("invalid DNS name")?.to_string();
 		let port = url.port().unwrap_or(443);
 
-		// Look up the DNS entry.
-		let addr = tokio::net::lookup_host((host.clone(), port))
+		let addrs: Vec<_> = tokio::net::lookup_host((host.clone(), port))
 			.await
 			.context("failed DNS lookup")?
-			.next()
-			.context("no DNS entries")?;
+			.collect();
+		anyhow::ensure!(!addrs.is_empty(), "no DNS entries");
 
-		let connection = self.quic.connect_with(config, addr, &host)?.await?;
+		let addrs = interleave(addrs);
+		log::debug!("resolved DNS: host={} addrs={:?}", host, addrs);
 
-		let session = match url.scheme() {
-			"https" => web_transport_quinn::connect_with(connection, url).await?,
-			"moqt" => connection.into(),
-			_ => unreachable!(),
+		Ok((host, addrs))
+	}
+
+	// Try each address in order until one connects, logging which was used.
+	//
+	// Like Happy Eyeballs (RFC 8305), the next address is tried if an attempt hasn't finished within ATTEMPT_DELAY,
+	// without cancelling it, so a blackholed address only delays the connection rather than blocking it.
+	async fn failover<T, F, Fut>(url: &Url, addrs: Vec<net::SocketAddr>, mut connect: F) -> anyhow::Result<T>
+	where
+		F: FnMut(net::SocketAddr) -> Fut,
+		Fut: std::future::Future<Output = anyhow::Result<T>>,
+	{
+		let mut attempt = |addr| {
+			let connecting = tokio::time::timeout(ATTEMPT_TIMEOUT, connect(addr));
+			async move {
+				let res = connecting.await.unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+				(addr, res)
+			}
 		};
 
-		Ok(session.into())
+		let mut addrs = addrs.into_iter();
+		let mut pending = FuturesUnordered::new();
+		let mut last = None;
+
+		loop {
+			if pending.is_empty() {
+				match addrs.next() {
+					Some(addr) => pending.push(attempt(addr)),
+					None => break,
+				}
+			}
+
+			tokio::select! {
+				Some((addr, res)) = pending.next() => match res {
+					Ok(res) => {
+						log::info!("connected: url={} addr={}", url, addr);
+						return Ok(res);
+					}
+					Err(err) => {
+						log::warn!("failed to connect: url={} addr={} error={:#}", url, addr, err);
+						last = Some(err);
+
+						// Don't wait for the delay when an attempt fails outright.
+						if let Some(addr) = addrs.next() {
+							pending.push(attempt(addr));
+						}
+					}
+				},
+				_ = tokio::time::sleep(ATTEMPT_DELAY), if !addrs.as_slice().is_empty() => {
+					let addr = addrs.next().unwrap();
+					log::debug!("still connecting, also trying: url={} addr={}", url, addr);
+					pending.push(attempt(addr));
+				}
+			}
+		}
+
+		let err = last.context("no DNS entries")?;
+		Err(err.context(format!("failed to connect to any address: {}", url)))
+	}
+}
+
+// How long to wait for a connection attempt before also trying the next address, as recommended by RFC 8305.
+const ATTEMPT_DELAY: time::Duration = time::Duration::from_millis(250);
+
+// Give up on an address after this long, ex. the OS may take minutes to time out a blackholed TCP connection.
+const ATTEMPT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// The SETUP options for connecting to the URL with [Client::connect], sending its path unless WebTransport already did.
+///
+/// A bare `/` isn't sent, since older servers reject the PATH parameter.
+pub fn setup_options(url: &Url, role: setup::Role) -> SetupOptions {
+	let options = SetupOptions::new(role);
+	if url.scheme() == "https" {
+		return options;
+	}
+
+	// Unlike https, the path of a moqt URL may be empty.
+	let path = match url.path() {
+		"" => "/",
+		path => path,
+	};
+
+	let path = match url.query() {
+		Some(query) => format!("{}?{}", path, query),
+		None if path == "/" => return options,
+		None => path.to_string(),
+	};
+
+	match setup::Path::new(path) {
+		Some(path) => options.with_path(path),
+		None => options,
+	}
+}
+
+// Alternate between IPv6 and IPv4 addresses, starting with the family the resolver preferred (RFC 8305).
+// A broken path for one family then only costs a single attempt before the other is tried.
+fn interleave(addrs: Vec<net::SocketAddr>) -> Vec<net::SocketAddr> {
+	let first = match addrs.first() {
+		Some(addr) => addr.is_ipv6(),
+		None => return addrs,
+	};
+
+	let (preferred, other): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|addr| addr.is_ipv6() == first);
+	let mut preferred = preferred.into_iter();
+	let mut other = other.into_iter();
+
+	let mut addrs = Vec::new();
+	loop {
+		match (preferred.next(), other.next()) {
+			(None, None) => return addrs,
+			(a, b) => addrs.extend(a.into_iter().chain(b)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn setup_path() {
+		let path = |url: &str| {
+			let options = setup_options(&url.parse().unwrap(), setup::Role::Both);
+			options.path.map(|path| path.as_str().to_string())
+		};
+
+		assert_eq!(
+			path("moqt://relay.example.com/live?region=eu").as_deref(),
+			Some("/live?region=eu")
+		);
+		assert_eq!(path("moqt+tcp://relay.example.com/live").as_deref(), Some("/live"));
+		assert_eq!(
+			path("moqt://relay.example.com?region=eu").as_deref(),
+			Some("/?region=eu")
+		);
+		assert_eq!(path("moqt://relay.example.com"), None);
+		assert_eq!(path("https://relay.example.com/live"), None);
+	}
+
+	#[test]
+	fn interleave_families() {
+		let addrs: Vec<net::SocketAddr> = [
+			"[2001:db8::1]:443",
+			"[2001:db8::2]:443",
+			"[2001:db8::3]:443",
+			"192.0.2.1:443",
+		]
+		.iter()
+		.map(|addr| addr.parse().unwrap())
+		.collect();
+
+		let ordered: Vec<String> = interleave(addrs).iter().map(ToString::to_string).collect();
+		assert_eq!(
+			ordered,
+			[
+				"[2001:db8::1]:443",
+				"192.0.2.1:443",
+				"[2001:db8::2]:443",
+				"[2001:db8::3]:443"
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn failover_unroutable() {
+		let url: Url = "moqt://relay.example.com".parse().unwrap();
+		let unroutable: net::SocketAddr = "192.0.2.1:443".parse().unwrap();
+		let working: net::SocketAddr = "192.0.2.2:443".parse().unwrap();
+
+		// The first address never answers, like a blackholed route, but the second is still used promptly.
+		let start = time::Instant::now();
+		let addr = Client::failover(&url, vec![unroutable, working], |addr| async move {
+			if addr == unroutable {
+				std::future::pending::<()>().await;
+			}
+			Ok(addr)
+		})
+		.await
+		.unwrap();
+
+		assert_eq!(addr, working);
+		assert!(start.elapsed() < ATTEMPT_TIMEOUT / 2, "{:?}", start.elapsed());
+
+		// A failure moves on to the next address without waiting for the delay.
+		let start = time::Instant::now();
+		let addr = Client::failover(&url, vec![unroutable, working], |addr| async move {
+			anyhow::ensure!(addr != unroutable, "connection refused");
+			Ok(addr)
+		})
+		.await
+		.unwrap();
+
+		assert_eq!(addr, working);
+		assert!(start.elapsed() < ATTEMPT_DELAY, "{:?}", start.elapsed());
+	}
+
+	#[test]
+	fn peer_closed() {
+		use moq_transport::session::{CloseCode, SessionError};
+
+		let closed = |code: u64, reason: &'static str| {
+			let close = quinn::ApplicationClose {
+				error_code: quinn::VarInt::from_u64(code).unwrap(),
+				reason: reason.into(),
+			};
+
+			let err = web_transport::SessionError::from(quinn::ConnectionError::ApplicationClosed(close));
+			SessionError::from(web_transport::ReadError::from(err)).peer_closed()
+		};
+
+		// moq-transport reads the close from the error message, so this breaks if quinn changes the format.
+		assert_eq!(
+			closed(0x3, "bad message"),
+			Some((CloseCode::ProtocolViolation, "bad message".to_string()))
+		);
+		assert_eq!(closed(0x10, ""), Some((CloseCode::GoawayTimeout, String::new())));
+
+		// WebTransport sessions close with the code mapped into the HTTP/3 error space.
+		assert_eq!(
+			closed(0x52e4a40fa8db + 0x1, "oops"),
+			Some((CloseCode::Internal, "oops".to_string()))
+		);
 	}
 }
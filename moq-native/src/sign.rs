@@ -0,0 +1,155 @@
+use std::{
+	fs,
+	io::Cursor,
+	path,
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use moq_transport::message::AnnounceSignature;
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use rustls::pki_types::PrivateKeyDer;
+
+/// Sign each announce, so relays can check the namespace belongs to us.
+#[derive(Parser, Clone, Default)]
+#[group(id = "sign")]
+pub struct Args {
+	/// Sign announces with the Ed25519 private key at this path, encoded as PEM.
+	///
+	/// Generate one with `openssl genpkey -algorithm ed25519`.
+	/// The public key is logged on startup, to be registered for the namespace with moq-api.
+	#[arg(id = "sign-key", long = "sign-key")]
+	pub key: Option<path::PathBuf>,
+}
+
+impl Args {
+	/// Returns None if no key was configured.
+	pub fn load(&self) -> anyhow::Result<Option<SigningKey>> {
+		let path = match &self.key {
+			Some(path) => path,
+			None => return Ok(None),
+		};
+
+		let pem = fs::read(path).with_context(|| format!("failed to read signing key: {}", path.display()))?;
+		let key = SigningKey::from_pem(&pem).with_context(|| format!("invalid signing key: {}", path.display()))?;
+
+		log::info!("signing announces: public_key={}", key.public_key());
+
+		Ok(Some(key))
+	}
+}
+
+/// An Ed25519 key used to sign announces, see [moq_transport::session::Publisher::announce_signed].
+#[derive(Clone)]
+pub struct SigningKey {
+	pair: Arc<Ed25519KeyPair>,
+}
+
+impl SigningKey {
+	/// Parse a PKCS#8 private key encoded as PEM, as produced by `openssl genpkey -algorithm ed25519`.
+	pub fn from_pem(pem: &[u8]) -> anyhow::Result<Self> {
+		let key = rustls_pemfile::private_key(&mut Cursor::new(pem))?.context("no private key found")?;
+
+		let der = match key {
+			PrivateKeyDer::Pkcs8(der) => der,
+			_ => anyhow::bail!("expected a PKCS#8 private key"),
+		};
+
+		// OpenSSL omits the public key from the PKCS#8 document, so it can't be checked.
+		let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.secret_pkcs8_der())
+			.map_err(|err| anyhow::anyhow!("expected an Ed25519 key: {}", err))?;
+
+		Ok(Self { pair: Arc::new(pair) })
+	}
+
+	/// Generate a new random key that isn't persisted, ex. for tests.
+	pub fn generate() -> anyhow::Result<Self> {
+		let rng = ring::rand::SystemRandom::new();
+		let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).map_err(|_| anyhow::anyhow!("failed to generate key"))?;
+		let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(|_| anyhow::anyhow!("failed to parse key"))?;
+
+		Ok(Self { pair: Arc::new(pair) })
+	}
+
+	/// The hex encoded public key, which is registered for the namespace.
+	pub fn public_key(&self) -> String {
+		hex::encode(self.pair.public_key().as_ref())
+	}
+
+	/// Sign an announce for the namespace, timestamped with the current time.
+	pub fn sign(&self, namespace: &str) -> AnnounceSignature {
+		let timestamp = now().as_millis() as u64;
+		let payload = AnnounceSignature::payload(namespace, timestamp);
+
+		AnnounceSignature {
+			timestamp,
+			signature: self.sign_payload(&payload),
+		}
+	}
+
+	/// Sign an arbitrary payload, which must be unambiguous from the announce payload.
+	///
+	/// Used with `moq_api::KeySignature::new` to replace or delete the key claiming a namespace.
+	pub fn sign_payload(&self, payload: &[u8]) -> Vec<u8> {
+		self.pair.sign(payload).as_ref().to_vec()
+	}
+}
+
+/// Check that the announce for the namespace was signed by the hex encoded public key within `max_age`.
+///
+/// The age is checked in both directions, allowing for the same amount of clock skew.
+pub fn verify(
+	public_key: &str,
+	namespace: &str,
+	signature: &AnnounceSignature,
+	max_age: Duration,
+) -> anyhow::Result<()> {
+	let public_key = hex::decode(public_key).context("invalid public key")?;
+
+	let signed = Duration::from_millis(signature.timestamp);
+	let now = now();
+	let age = now.checked_sub(signed).unwrap_or_else(|| signed - now);
+	anyhow::ensure!(age <= max_age, "signature expired: age={:?}", age);
+
+	let payload = AnnounceSignature::payload(namespace, signature.timestamp);
+	verify_payload(&public_key, &payload, &signature.signature)
+}
+
+// Check the signature of an arbitrary payload, see [SigningKey::sign_payload].
+pub(crate) fn verify_payload(public_key: &[u8], payload: &[u8], signature: &[u8]) -> anyhow::Result<()> {
+	signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+		.verify(payload, signature)
+		.map_err(|_| anyhow::anyhow!("invalid signature"))
+}
+
+fn now() -> Duration {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sign_verify() {
+		let key = SigningKey::generate().unwrap();
+		let max_age = Duration::from_secs(60);
+
+		let signature = key.sign("live/alice");
+		verify(&key.public_key(), "live/alice", &signature, max_age).unwrap();
+
+		// The signature can't be used for another namespace, key, or long after it was made.
+		assert!(verify(&key.public_key(), "live/mallory", &signature, max_age).is_err());
+
+		let other = SigningKey::generate().unwrap();
+		assert!(verify(&other.public_key(), "live/alice", &signature, max_age).is_err());
+
+		let stale = AnnounceSignature {
+			timestamp: signature.timestamp - 120_000,
+			..signature
+		};
+		assert!(verify(&key.public_key(), "live/alice", &stale, max_age).is_err());
+	}
+}
@@ -0,0 +1,82 @@
+use std::{net, sync::Arc};
+
+use anyhow::Context;
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::FutureExt;
+
+use moq_transport::transport;
+
+use crate::tls;
+
+/// Accepts MoQ sessions multiplexed over TCP+TLS, for clients that can't use UDP.
+pub struct Server {
+	listener: tokio::net::TcpListener,
+	acceptor: tokio_rustls::TlsAcceptor,
+	accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<transport::Session>>>,
+}
+
+impl Server {
+	pub async fn bind(addr: net::SocketAddr, tls: tls::Config) -> anyhow::Result<Self> {
+		let mut config = tls.server.context("missing TLS certificate")?;
+		config.alpn_protocols = vec![moq_transport::setup::ALPN.to_vec()];
+		config.key_log = Arc::new(rustls::KeyLogFile::new());
+
+		let listener = tokio::net::TcpListener::bind(addr)
+			.await
+			.context("failed to bind TCP socket")?;
+
+		Ok(Self {
+			listener,
+			acceptor: Arc::new(config).into(),
+			accept: Default::default(),
+		})
+	}
+
+	pub async fn accept(&mut self) -> Option<transport::Session> {
+		loop {
+			tokio::select! {
+				res = self.listener.accept() => {
+					let (tcp, addr) = match res {
+						Ok(res) => res,
+						Err(err) => {
+							log::warn!("failed to accept TCP connection: {}", err);
+							continue;
+						}
+					};
+
+					self.accept.push(Self::accept_session(self.acceptor.clone(), tcp, addr).boxed());
+				}
+				res = self.accept.next(), if !self.accept.is_empty() => {
+					match res.unwrap() {
+						Ok(session) => return Some(session),
+						Err(err) => log::warn!("failed to accept TCP connection: {}", err),
+					}
+				}
+			}
+		}
+	}
+
+	async fn accept_session(
+		acceptor: tokio_rustls::TlsAcceptor,
+		tcp: tokio::net::TcpStream,
+		addr: net::SocketAddr,
+	) -> anyhow::Result<transport::Session> {
+		tcp.set_nodelay(true)?;
+
+		let tls = acceptor.accept(tcp).await.context("failed TLS handshake")?;
+
+		let (_, conn) = tls.get_ref();
+		log::debug!(
+			"established TCP connection: ip={} server={}",
+			addr,
+			conn.server_name().unwrap_or_default(),
+		);
+
+		Ok(transport::mux::Session::new(tls, true).into())
+	}
+
+	pub fn local_addr(&self) -> anyhow::Result<net::SocketAddr> {
+		self.listener.local_addr().context("failed to get local address")
+	}
+}
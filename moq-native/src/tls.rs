@@ -5,10 +5,11 @@ use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
 use rustls::server::{ClientHello, ResolvesServerCert};
 use rustls::sign::CertifiedKey;
 use rustls::RootCertStore;
+use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Cursor, Read};
 use std::path;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 #[derive(Parser, Clone, Default)]
 #[group(id = "tls")]
@@ -27,13 +28,20 @@ pub struct Args {
 	#[arg(long = "tls-key")]
 	pub key: Vec<path::PathBuf>,
 
-	/// Use the TLS root at this path, encoded as PEM.
+	/// Use the TLS roots at this path, encoded as PEM.
 	///
+	/// The file may be a bundle containing multiple certificates.
 	/// This value can be provided multiple times for multiple roots.
-	/// If this is empty, system roots will be used instead
+	/// If this is empty, the platform's trust store will be used instead.
 	#[arg(long = "tls-root")]
 	pub root: Vec<path::PathBuf>,
 
+	/// Also trust the platform's trust store when `--tls-root` is provided.
+	///
+	/// This is the system keychain on macOS, the certificate store on Windows, and the OpenSSL roots on Linux.
+	#[arg(long = "tls-native-roots")]
+	pub native_roots: bool,
+
 	/// Danger: Disable TLS certificate verification.
 	///
 	/// Fine for local development and between relays, but should be used in caution in production.
@@ -46,6 +54,34 @@ pub struct Config {
 	pub client: rustls::ClientConfig,
 	pub server: Option<rustls::ServerConfig>,
 	pub fingerprints: Vec<String>,
+
+	/// Selects the certificate by SNI, used by [Config::server].
+	pub certs: Arc<ServeCerts>,
+}
+
+impl Config {
+	/// Returns the fingerprint of the certificate that would be served for the given hostname.
+	pub fn fingerprint(&self, host: Option<&str>) -> Option<String> {
+		self.certs.find(host).map(|ck| ServeCerts::fingerprint(&ck))
+	}
+
+	/// Create the server configuration even without certificates, ex. when they will be provisioned via ACME.
+	pub fn enable_server(&mut self) -> anyhow::Result<()> {
+		if self.server.is_none() {
+			self.server = Some(server_config(self.certs.clone())?);
+		}
+
+		Ok(())
+	}
+}
+
+fn server_config(certs: Arc<ServeCerts>) -> anyhow::Result<rustls::ServerConfig> {
+	let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+	Ok(rustls::ServerConfig::builder_with_provider(provider)
+		.with_protocol_versions(&[&rustls::version::TLS13])?
+		.with_no_client_auth()
+		.with_cert_resolver(certs))
 }
 
 impl Args {
@@ -65,23 +101,26 @@ impl Args {
 		// Create a list of acceptable root certificates.
 		let mut roots = RootCertStore::empty();
 
-		if self.root.is_empty() {
+		if self.root.is_empty() || self.native_roots {
 			// Add the platform's native root certificates.
 			for cert in rustls_native_certs::load_native_certs().context("could not load platform certs")? {
 				roots.add(cert).context("failed to add root cert")?;
 			}
-		} else {
-			// Add the specified root certificates.
-			for root in &self.root {
-				let root = fs::File::open(root).context("failed to open root cert file")?;
-				let mut root = io::BufReader::new(root);
+		}
+
+		// Add the specified root certificates.
+		for root in &self.root {
+			let file = fs::File::open(root).context("failed to open root cert file")?;
+			let mut file = io::BufReader::new(file);
 
-				let root = rustls_pemfile::certs(&mut root)
-					.next()
-					.context("no roots found")?
-					.context("failed to read root cert")?;
+			let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut file)
+				.collect::<Result<_, _>>()
+				.context("failed to read root certs")?;
 
-				roots.add(root).context("failed to add root cert")?;
+			anyhow::ensure!(!certs.is_empty(), "no roots found: {}", root.display());
+
+			for cert in certs {
+				roots.add(cert).context("failed to add root cert")?;
 			}
 		}
 
@@ -98,15 +137,11 @@ impl Args {
 		}
 
 		let fingerprints = serve.fingerprints();
+		let certs = Arc::new(serve);
 
 		// Create the TLS configuration we'll use as a server (relay <- browser)
 		let server = if !self.key.is_empty() {
-			Some(
-				rustls::ServerConfig::builder_with_provider(provider)
-					.with_protocol_versions(&[&rustls::version::TLS13])?
-					.with_no_client_auth()
-					.with_cert_resolver(Arc::new(serve)),
-			)
+			Some(server_config(certs.clone())?)
 		} else {
 			None
 		};
@@ -115,62 +150,132 @@ impl Args {
 			server,
 			client,
 			fingerprints,
+			certs,
 		})
 	}
 }
 
+/// The ALPN used by ACME TLS-ALPN-01 challenges (RFC 8737).
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// A list of certificates, selected based on the SNI so one server can serve multiple domains.
+///
+/// Certificates can be replaced at runtime, ex. when renewed via ACME.
 #[derive(Default, Debug)]
-struct ServeCerts {
-	list: Vec<Arc<CertifiedKey>>,
+pub struct ServeCerts {
+	list: RwLock<Vec<Arc<CertifiedKey>>>,
+
+	// Self-signed certificates used to answer ACME TLS-ALPN-01 challenges, keyed by domain.
+	challenges: RwLock<HashMap<String, Arc<CertifiedKey>>>,
 }
 
 impl ServeCerts {
 	// Load a certificate and cooresponding key from a file
 	pub fn load(&mut self, chain: &path::PathBuf, key: &path::PathBuf) -> anyhow::Result<()> {
 		// Read the PEM certificate chain
-		let chain = fs::File::open(chain).context("failed to open cert file")?;
-		let mut chain = io::BufReader::new(chain);
+		let mut chain = fs::File::open(chain).context("failed to open cert file")?;
+		let mut chain_buf = Vec::new();
+		chain.read_to_end(&mut chain_buf)?;
+
+		// Read the PEM private key
+		let mut keys = fs::File::open(key).context("failed to open key file")?;
+		let mut key_buf = Vec::new();
+		keys.read_to_end(&mut key_buf)?;
 
-		let chain: Vec<CertificateDer> = rustls_pemfile::certs(&mut chain)
+		let certified = Self::parse(&chain_buf, &key_buf)?;
+		self.list.get_mut().unwrap().push(certified);
+
+		Ok(())
+	}
+
+	/// Parse a PEM certificate chain and private key.
+	pub fn parse(chain: &[u8], key: &[u8]) -> anyhow::Result<Arc<CertifiedKey>> {
+		let chain: Vec<CertificateDer> = rustls_pemfile::certs(&mut Cursor::new(chain))
 			.collect::<Result<_, _>>()
 			.context("failed to read certs")?;
 
 		anyhow::ensure!(!chain.is_empty(), "could not find certificate");
 
-		// Read the PEM private key
-		let mut keys = fs::File::open(key).context("failed to open key file")?;
+		let key = rustls_pemfile::private_key(&mut Cursor::new(key))?.context("missing private key")?;
+		let key = rustls::crypto::ring::sign::any_supported_type(&key)?;
 
-		// Read the keys into a Vec so we can parse it twice.
-		let mut buf = Vec::new();
-		keys.read_to_end(&mut buf)?;
+		let certified = CertifiedKey::new(chain, key);
+		Self::check_key(&certified)?;
 
-		let key = rustls_pemfile::private_key(&mut Cursor::new(&buf))?.context("missing private key")?;
-		let key = rustls::crypto::ring::sign::any_supported_type(&key)?;
+		Ok(Arc::new(certified))
+	}
 
-		let certified = Arc::new(CertifiedKey::new(chain, key));
-		self.list.push(certified);
+	// The certificate and key are loaded from separate files, so make sure they belong together.
+	// There's no way to compare the public keys directly, so sign a message with the key and verify it using the certificate.
+	fn check_key(ck: &CertifiedKey) -> anyhow::Result<()> {
+		use rustls::SignatureScheme as Scheme;
+
+		let algorithms = [
+			(Scheme::ECDSA_NISTP256_SHA256, &webpki::ECDSA_P256_SHA256),
+			(Scheme::ECDSA_NISTP384_SHA384, &webpki::ECDSA_P384_SHA384),
+			(Scheme::ED25519, &webpki::ED25519),
+			(Scheme::RSA_PSS_SHA256, &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY),
+		];
+
+		let schemes: Vec<_> = algorithms.iter().map(|(scheme, _)| *scheme).collect();
+		let signer = ck.key.choose_scheme(&schemes).context("unsupported private key")?;
+		let (_, algorithm) = algorithms
+			.iter()
+			.find(|(scheme, _)| *scheme == signer.scheme())
+			.context("unsupported private key")?;
 
-		Ok(())
+		let message = b"moq-native key check";
+		let signature = signer.sign(message)?;
+
+		let leaf = ck.end_entity_cert()?;
+		let leaf = webpki::EndEntityCert::try_from(leaf.as_ref())
+			.map_err(|err| anyhow::anyhow!("failed to parse certificate: {:?}", err))?;
+
+		leaf.verify_signature(algorithm, message, &signature)
+			.map_err(|_| anyhow::anyhow!("private key doesn't match the certificate"))
+	}
+
+	/// Add a certificate, replacing `old` if provided, ex. when a certificate is renewed.
+	/// New certificates take precedence over existing certificates valid for the same name.
+	pub fn replace(&self, old: Option<&Arc<CertifiedKey>>, new: Arc<CertifiedKey>) {
+		let mut list = self.list.write().unwrap();
+		if let Some(old) = old {
+			list.retain(|ck| !Arc::ptr_eq(ck, old));
+		}
+		list.insert(0, new);
+	}
+
+	/// Set or clear the certificate used to answer an ACME TLS-ALPN-01 challenge for the domain.
+	pub fn set_challenge(&self, domain: &str, cert: Option<Arc<CertifiedKey>>) {
+		let mut challenges = self.challenges.write().unwrap();
+		match cert {
+			Some(cert) => challenges.insert(domain.to_string(), cert),
+			None => challenges.remove(domain),
+		};
 	}
 
 	// Return the SHA256 fingerprint of our certificates.
 	pub fn fingerprints(&self) -> Vec<String> {
 		self.list
+			.read()
+			.unwrap()
 			.iter()
-			.map(|ck| {
-				let fingerprint = digest(&SHA256, ck.cert[0].as_ref());
-				let fingerprint = hex::encode(fingerprint.as_ref());
-				fingerprint
-			})
+			.map(|ck| Self::fingerprint(ck))
 			.collect()
 	}
-}
 
-impl ResolvesServerCert for ServeCerts {
-	fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
-		if let Some(name) = client_hello.server_name() {
+	fn fingerprint(ck: &CertifiedKey) -> String {
+		let fingerprint = digest(&SHA256, ck.cert[0].as_ref());
+		hex::encode(fingerprint.as_ref())
+	}
+
+	/// Return the first certificate valid for the given name, otherwise the last certificate.
+	pub fn find(&self, name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+		let list = self.list.read().unwrap();
+
+		if let Some(name) = name {
 			if let Ok(dns_name) = webpki::DnsNameRef::try_from_ascii_str(name) {
-				for ck in &self.list {
+				for ck in list.iter() {
 					// TODO I gave up on caching the parsed result because of lifetime hell.
 					// If this shows up on benchmarks, somebody should fix it.
 					let leaf = ck.end_entity_cert().expect("missing certificate");
@@ -184,7 +289,22 @@ impl ResolvesServerCert for ServeCerts {
 		}
 
 		// Default to the last certificate if we couldn't find one.
-		self.list.last().cloned()
+		list.last().cloned()
+	}
+}
+
+impl ResolvesServerCert for ServeCerts {
+	fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+		let acme = client_hello
+			.alpn()
+			.is_some_and(|mut alpn| alpn.any(|proto| proto == ACME_TLS_ALPN));
+
+		if acme {
+			let name = client_hello.server_name()?;
+			return self.challenges.read().unwrap().get(name).cloned();
+		}
+
+		self.find(client_hello.server_name())
 	}
 }
 
@@ -1,14 +1,17 @@
 use bytes::BytesMut;
-use std::net;
+use std::{collections::HashMap, net, time};
 use url::Url;
 
 use anyhow::Context;
 use clap::Parser;
-use tokio::io::AsyncReadExt;
+use tokio::{
+	io::{AsyncRead, AsyncReadExt},
+	task::JoinSet,
+};
 
-use moq_native::quic;
+use moq_native::{congestion::Profile, preset::Preset, quic};
 use moq_pub::Media;
-use moq_transport::{serve, session::Publisher};
+use moq_transport::{serve, session::Publisher, setup};
 
 #[derive(Parser, Clone)]
 pub struct Cli {
@@ -27,16 +30,141 @@ pub struct Cli {
 	pub bitrate: u32,
 
 	/// Connect to the given URL starting with https://
-	#[arg()]
-	pub url: Url,
+	#[arg(required_unless_present = "discover", conflicts_with = "discover")]
+	pub url: Option<Url>,
 
-	/// The name of the broadcast
+	/// Ask the moq-api at this URL for the least loaded relay, instead of providing one.
 	#[arg(long)]
-	pub name: String,
+	pub discover: Option<Url>,
+
+	/// The name of the broadcast.
+	/// Repeat with --input to publish several broadcasts over one connection, ex. one per camera.
+	#[arg(long, required = true)]
+	pub name: Vec<String>,
+
+	/// Read the broadcast from this file instead of stdin, ex. `/dev/fd/3` for an inherited file descriptor.
+	/// Required once per --name when publishing several broadcasts, in the same order; `-` is stdin.
+	#[arg(long)]
+	pub input: Vec<String>,
 
 	/// The TLS configuration.
 	#[command(flatten)]
 	pub tls: moq_native::tls::Args,
+
+	/// The log sampling configuration.
+	#[command(flatten)]
+	pub log: moq_native::log::Args,
+
+	/// Sign the announce to prove the broadcast name belongs to us.
+	#[command(flatten)]
+	pub sign: moq_native::sign::Args,
+
+	/// Identify the broadcast across relays.
+	#[command(flatten)]
+	pub broadcast: moq_native::broadcast::Args,
+
+	/// How quickly the connection ramps up, defaulting to the contribution profile.
+	#[command(flatten)]
+	pub congestion: moq_native::congestion::Args,
+
+	/// Simulate a degraded network, for testing.
+	#[cfg(feature = "netem")]
+	#[command(flatten)]
+	pub netem: moq_native::netem::Args,
+
+	/// Print a line of per-track stats to stderr every second.
+	#[arg(long)]
+	pub stats: bool,
+
+	/// Like --stats, but print each line as a JSON object.
+	#[arg(long)]
+	pub stats_json: bool,
+
+	/// Start from coherent defaults for latency or quality, overridden by any flags provided explicitly.
+	#[arg(long, value_enum)]
+	pub preset: Option<Preset>,
+
+	/// Publish a sender report for each track on the `.reports` track this often, or never if 0.
+	/// [default: 1000, or 250 with --preset latency]
+	#[arg(long)]
+	pub report_interval_ms: Option<u64>,
+
+	/// Publish an index of each track's groups on the `.index` track this often, or never if 0.
+	/// DVR players use it to translate a seek time into a group range to FETCH, ex. every 2000ms.
+	#[arg(long, default_value = "0")]
+	pub index_interval_ms: u64,
+
+	/// When the input ends, wait this long for pending groups to be delivered before closing the connection.
+	/// [default: 5000, or 1000/15000 with --preset latency/quality]
+	#[arg(long)]
+	pub shutdown_timeout_ms: Option<u64>,
+
+	/// Don't send the catalog and init tracks reliably at the highest priority, ahead of media.
+	#[arg(long)]
+	pub no_bootstrap_priority: bool,
+}
+
+impl Cli {
+	// Pair each broadcast name with its input.
+	fn inputs(&self) -> anyhow::Result<Vec<(String, String)>> {
+		let inputs = match (self.name.len(), self.input.is_empty()) {
+			(1, true) => vec!["-".to_string()],
+			(_, true) => anyhow::bail!("--input is required for each --name when publishing several broadcasts"),
+			(names, false) if names != self.input.len() => anyhow::bail!("--input must be given once per --name"),
+			_ => self.input.clone(),
+		};
+
+		anyhow::ensure!(
+			inputs.iter().filter(|input| *input == "-").count() <= 1,
+			"only one broadcast can read from stdin"
+		);
+
+		Ok(self.name.iter().cloned().zip(inputs).collect())
+	}
+
+	// Each broadcast needs a unique ID, so an explicit one is suffixed with the name when there are several.
+	fn broadcast_id(&self, name: &str) -> String {
+		match (&self.broadcast.id, self.name.len()) {
+			(Some(id), count) if count > 1 => {
+				let id = format!("{}/{}", id, name);
+				log::info!("broadcast id: {}", id);
+				id
+			}
+			_ => self.broadcast.load(),
+		}
+	}
+
+	// The relay URL, asking moq-api for one with --discover.
+	async fn relay(&self) -> anyhow::Result<Url> {
+		let api = match &self.discover {
+			Some(api) => moq_api::Client::new(api.clone()),
+			None => return self.url.clone().context("missing relay URL"),
+		};
+
+		let relay = api
+			.get_relay()
+			.await
+			.context("failed to discover relay")?
+			.context("no relays available")?;
+
+		log::info!("discovered relay: url={} sessions={}", relay.url, relay.sessions);
+		Ok(relay.url)
+	}
+
+	fn report_interval_ms(&self) -> u64 {
+		self.report_interval_ms.unwrap_or(match self.preset {
+			Some(Preset::Latency) => 250,
+			_ => 1000,
+		})
+	}
+
+	fn shutdown_timeout_ms(&self) -> u64 {
+		self.shutdown_timeout_ms.unwrap_or(match self.preset {
+			Some(Preset::Latency) => 1000,
+			Some(Preset::Quality) => 15000,
+			_ => 5000,
+		})
+	}
 }
 
 #[tokio::main]
@@ -50,39 +178,323 @@ async fn main() -> anyhow::Result<()> {
 	tracing::subscriber::set_global_default(tracer).unwrap();
 
 	let cli = Cli::parse();
+	cli.log.init();
+
+	let report_interval_ms = cli.report_interval_ms();
+	let shutdown_timeout_ms = cli.shutdown_timeout_ms();
+	let index_interval_ms = cli.index_interval_ms;
 
-	let (writer, _, reader) = serve::Tracks::new(cli.name).produce();
-	let media = Media::new(writer)?;
+	let stats = match (cli.stats, cli.stats_json) {
+		(_, true) => Some(StatsFormat::Json),
+		(true, false) => Some(StatsFormat::Text),
+		(false, false) => None,
+	};
+
+	let inputs = cli.inputs()?;
+	let labeled = inputs.len() > 1;
+
+	// Use the start time as the epoch, since it increases each time we restart.
+	let epoch = time::SystemTime::now()
+		.duration_since(time::UNIX_EPOCH)
+		.context("clock went backwards")?
+		.as_millis() as u64;
+
+	// Open every input before connecting, so a bad path fails immediately.
+	let mut broadcasts = Vec::with_capacity(inputs.len());
+	for (name, input) in inputs {
+		let (writer, _, reader) = serve::Broadcast::builder()
+			.namespace(name.clone())
+			.broadcast_id(cli.broadcast_id(&name))
+			.epoch(epoch)
+			.produce()?;
+		let media = match cli.index_interval_ms {
+			0 => Media::new(writer)?,
+			_ => Media::new(writer)?.with_index()?,
+		};
+		let input = open_input(&input).await?;
+		broadcasts.push((name, reader, media, input));
+	}
 
 	let tls = cli.tls.load()?;
+	let sign = cli.sign.load()?;
 
-	let quic = quic::Endpoint::new(moq_native::quic::Config {
+	let quic_config = moq_native::quic::Config {
 		bind: cli.bind,
 		tls: tls.clone(),
-	})?;
+		congestion: cli.congestion.load_or(Profile::Contribution),
+	};
+
+	#[cfg(feature = "netem")]
+	let quic = quic::Endpoint::with_netem(quic_config, &cli.netem)?;
+	#[cfg(not(feature = "netem"))]
+	let quic = quic::Endpoint::new(quic_config)?;
 
-	log::info!("connecting to relay: url={}", cli.url);
-	let session = quic.client.connect(&cli.url).await?;
+	let url = cli.relay().await?;
+	log::info!("connecting to relay: url={}", url);
+	let transport = quic.client.connect(&url).await?;
 
-	let (session, mut publisher) = Publisher::connect(session)
+	let options = quic::setup_options(&url, setup::Role::Publisher);
+	let (session, publisher) = Publisher::connect_with(transport.clone(), options)
 		.await
 		.context("failed to create MoQ Transport publisher")?;
+	let publisher = publisher.with_bootstrap_priority(!cli.no_bootstrap_priority);
+
+	let run = session.run();
+	tokio::pin!(run);
+
+	// Every broadcast shares the session, each announced separately with its own tracks.
+	let mut announces = JoinSet::new();
+	let mut medias = JoinSet::new();
+
+	for (name, reader, media, input) in broadcasts {
+		let mut publisher = publisher.clone();
+		let sign = sign.clone();
+		let announce_name = name.clone();
+		announces.spawn(async move {
+			let res = match sign {
+				Some(key) => {
+					let signature = key.sign(&reader.namespace);
+					publisher.announce_signed(reader, signature).await
+				}
+				None => publisher.announce(reader).await,
+			};
+			res.with_context(|| format!("broadcast: {}", announce_name))
+		});
+
+		let label = labeled.then(|| name.clone());
+		medias.spawn(async move {
+			run_media(media, input, label, stats, report_interval_ms, index_interval_ms)
+				.await
+				.with_context(|| format!("broadcast: {}", name))
+		});
+	}
+
+	let announce = join_all(&mut announces);
+	tokio::pin!(announce);
 
 	tokio::select! {
-		res = session.run() => res.context("session error")?,
-		res = run_media(media) => res.context("media error")?,
-		res = publisher.announce(reader) => res.context("publisher error")?,
+		res = &mut run => res.context("session error")?,
+		res = &mut announce => res.context("publisher error")?,
+		res = join_all(&mut medias) => res.context("media error")?,
+	}
+
+	log::info!("input ended, finishing broadcast");
+
+	// The media was dropped, so each announce finishes once the pending groups are served and UNANNOUNCE is queued.
+	// Keep the session running for the remainder of the timeout so it can be delivered, unless the relay closes first.
+	let deadline = tokio::time::sleep(time::Duration::from_millis(shutdown_timeout_ms));
+	tokio::pin!(deadline);
+
+	tokio::select! {
+		res = &mut announce => {
+			res.context("publisher error")?;
+			tokio::select! {
+				_ = &mut run => {},
+				_ = &mut deadline => {},
+			}
+		},
+		res = &mut run => res.context("session error")?,
+		_ = &mut deadline => log::warn!("timed out waiting for subscribers to finish"),
+	}
+
+	transport.close(0, "end of input");
+
+	Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum StatsFormat {
+	Text,
+	Json,
+}
+
+const STATS_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+type Input = Box<dyn AsyncRead + Unpin + Send>;
+
+// Open the input for a broadcast, where `-` is stdin.
+async fn open_input(path: &str) -> anyhow::Result<Input> {
+	if path == "-" {
+		return Ok(Box::new(tokio::io::stdin()));
+	}
+
+	let file = tokio::fs::File::open(path)
+		.await
+		.with_context(|| format!("failed to open input: {}", path))?;
+	Ok(Box::new(file))
+}
+
+// Wait for every task to finish, returning the first error.
+async fn join_all(tasks: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+	while let Some(res) = tasks.join_next().await {
+		res??;
 	}
 
 	Ok(())
 }
 
-async fn run_media(mut media: Media) -> anyhow::Result<()> {
-	let mut input = tokio::io::stdin();
+async fn run_media(
+	mut media: Media,
+	mut input: Input,
+	label: Option<String>,
+	stats: Option<StatsFormat>,
+	report_interval_ms: u64,
+	index_interval_ms: u64,
+) -> anyhow::Result<()> {
 	let mut buf = BytesMut::new();
 
+	let mut interval = tokio::time::interval(STATS_INTERVAL);
+	let mut prev = (time::Instant::now(), HashMap::new());
+
+	// The interval can't be zero, even when disabled.
+	let mut report = tokio::time::interval(time::Duration::from_millis(report_interval_ms.max(1)));
+	let mut index = tokio::time::interval(time::Duration::from_millis(index_interval_ms.max(1)));
+
 	loop {
-		input.read_buf(&mut buf).await.context("failed to read from stdin")?;
-		media.parse(&mut buf).context("failed to parse media")?;
+		tokio::select! {
+			res = input.read_buf(&mut buf) => {
+				if res.context("failed to read input")? == 0 {
+					if !buf.is_empty() {
+						log::warn!("ignoring {} bytes of trailing input", buf.len());
+					}
+
+					// Include the last groups in the index, so it covers the whole broadcast.
+					media.index().context("failed to publish index")?;
+
+					// Dropping the media closes each track, ending the broadcast.
+					return Ok(());
+				}
+
+				media.parse(&mut buf).context("failed to parse media")?;
+			}
+			_ = interval.tick(), if stats.is_some() => {
+				print_stats(&media, label.as_deref(), stats.unwrap(), &mut prev);
+			}
+			_ = report.tick(), if report_interval_ms > 0 => {
+				media.report().context("failed to publish report")?;
+			}
+			_ = index.tick(), if index_interval_ms > 0 => {
+				media.index().context("failed to publish index")?;
+			}
+		}
+	}
+}
+
+// Queued groups and bytes haven't been written to the relay yet, so growing queues mean we're not keeping up.
+// The bitrate covers the time since the previous line, which may be longer than the interval if we're busy.
+// The broadcast name is only included when publishing several.
+fn print_stats(
+	media: &Media,
+	broadcast: Option<&str>,
+	format: StatsFormat,
+	prev: &mut (time::Instant, HashMap<String, u64>),
+) {
+	let now = time::Instant::now();
+	let elapsed = now.duration_since(prev.0).as_secs_f64();
+	prev.0 = now;
+
+	let tracks: Vec<_> = media
+		.stats()
+		.into_iter()
+		.map(|track| {
+			let last = prev.1.insert(track.name.clone(), track.bytes).unwrap_or_default();
+			let bitrate = match elapsed > 0.0 {
+				true => ((track.bytes - last) as f64 * 8.0 / elapsed) as u64,
+				false => 0,
+			};
+			(track, bitrate)
+		})
+		.collect();
+
+	match format {
+		StatsFormat::Text => {
+			let line: Vec<_> = tracks
+				.iter()
+				.map(|(track, bitrate)| {
+					format!(
+						"{} groups={} bytes={} bitrate={}kbps queued_groups={} queued_bytes={}",
+						track.name,
+						track.groups,
+						track.bytes,
+						bitrate / 1000,
+						track.queued_groups,
+						track.queued_bytes
+					)
+				})
+				.collect();
+
+			match broadcast {
+				Some(broadcast) => eprintln!("stats: {}: {}", broadcast, line.join(" | ")),
+				None => eprintln!("stats: {}", line.join(" | ")),
+			}
+		}
+		StatsFormat::Json => {
+			let tracks: Vec<_> = tracks
+				.iter()
+				.map(|(track, bitrate)| {
+					serde_json::json!({
+						"name": track.name,
+						"groups": track.groups,
+						"bytes": track.bytes,
+						"bitrate": bitrate,
+						"queued_groups": track.queued_groups,
+						"queued_bytes": track.queued_bytes,
+					})
+				})
+				.collect();
+
+			let mut line = serde_json::json!({ "tracks": tracks });
+			if let Some(broadcast) = broadcast {
+				line["broadcast"] = broadcast.into();
+			}
+
+			eprintln!("{}", line);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn inputs() {
+		let cli = Cli::parse_from(["moq-pub", "https://localhost", "--name", "cam1"]);
+		assert_eq!(cli.inputs().unwrap(), vec![("cam1".to_string(), "-".to_string())]);
+
+		let cli = Cli::parse_from([
+			"moq-pub",
+			"https://localhost",
+			"--name",
+			"cam1",
+			"--input",
+			"-",
+			"--name",
+			"cam2",
+			"--input",
+			"/dev/fd/3",
+			"--broadcast-id",
+			"event",
+		]);
+		assert_eq!(cli.inputs().unwrap()[1], ("cam2".to_string(), "/dev/fd/3".to_string()));
+		assert_eq!(cli.broadcast_id("cam2"), "event/cam2");
+
+		// Each broadcast needs its own input, and stdin can only be read once.
+		let cli = Cli::parse_from(["moq-pub", "https://localhost", "--name", "cam1", "--name", "cam2"]);
+		assert!(cli.inputs().is_err());
+
+		let cli = Cli::parse_from([
+			"moq-pub",
+			"https://localhost",
+			"--name",
+			"cam1",
+			"--input",
+			"-",
+			"--name",
+			"cam2",
+			"--input",
+			"-",
+		]);
+		assert!(cli.inputs().is_err());
 	}
 }
@@ -1,23 +1,59 @@
 use anyhow::{self, Context};
 use bytes::{Buf, Bytes};
-use moq_transport::serve::{GroupWriter, GroupsWriter, TrackWriter, TracksWriter};
+use moq_transport::serve::{
+	mode, Broadcast, GroupBacklog, GroupTimestamp, GroupWriter, GroupsWriter, BOOTSTRAP_PRIORITY, CATALOG_TRACK,
+};
 use mp4::{self, ReadBox, TrackType};
 use std::cmp::max;
 use std::collections::HashMap;
 use std::io::Cursor;
 use std::time;
 
+/// Counters for a single published track.
+#[derive(Debug, Clone)]
+pub struct TrackStats {
+	pub name: String,
+
+	/// The number of groups published.
+	pub groups: u64,
+
+	/// The number of objects published.
+	pub objects: u64,
+
+	/// The sequence number of the latest group, if any.
+	pub last_group: Option<u64>,
+
+	/// The number of payload bytes published.
+	pub bytes: u64,
+
+	/// The number of groups with objects not yet written to a transport stream, ex. when the connection is congested.
+	pub queued_groups: u64,
+
+	/// The number of payload bytes not yet written to a transport stream.
+	pub queued_bytes: u64,
+}
+
 pub struct Media {
 	// Tracks based on their track ID.
 	tracks: HashMap<u32, Track>,
 
 	// The full broadcast of tracks
-	broadcast: TracksWriter,
+	broadcast: Broadcast,
 
 	// The init and catalog tracks
 	init: GroupsWriter,
 	catalog: GroupsWriter,
 
+	// The current catalog group, so later changes can be sent as deltas.
+	catalog_encoder: moq_catalog::CatalogEncoder,
+	catalog_group: Option<GroupWriter>,
+
+	// Periodic sender reports for each media track.
+	reports: GroupsWriter,
+
+	// The optional index of each track's groups, published periodically so players can seek.
+	index: Option<(GroupsWriter, moq_catalog::IndexWriter)>,
+
 	// The ftyp and moov atoms at the start of the file.
 	ftyp: Option<Bytes>,
 	moov: Option<mp4::MoovBox>,
@@ -27,21 +63,103 @@ pub struct Media {
 }
 
 impl Media {
-	pub fn new(mut broadcast: TracksWriter) -> anyhow::Result<Self> {
-		let catalog = broadcast.create(".catalog").context("broadcast closed")?.groups()?;
-		let init = broadcast.create("0.mp4").context("broadcast closed")?.groups()?;
+	/// Publish to the given broadcast, which should set an epoch so subscribers can tell when we restarted.
+	pub fn new(mut broadcast: Broadcast) -> anyhow::Result<Self> {
+		let catalog = broadcast.track(CATALOG_TRACK).mode(mode::Groups).create()?;
+		let init = broadcast.track("0.mp4").mode(mode::Groups).create()?;
+		let reports = broadcast.track(moq_catalog::REPORT_TRACK).mode(mode::Groups).create()?;
 
 		Ok(Media {
 			tracks: Default::default(),
 			broadcast,
 			catalog,
+			catalog_encoder: Default::default(),
+			catalog_group: None,
 			init,
+			reports,
+			index: None,
 			ftyp: None,
 			moov: None,
 			current: None,
 		})
 	}
 
+	/// Record the start of each group on the `.index` track, published by [Self::index].
+	pub fn with_index(mut self) -> anyhow::Result<Self> {
+		let index = self
+			.broadcast
+			.track(moq_catalog::INDEX_TRACK)
+			.mode(mode::Groups)
+			.create()?;
+		self.index = Some((index, moq_catalog::IndexWriter::new()));
+		Ok(self)
+	}
+
+	/// Returns the counters for each media track, sorted by name.
+	pub fn stats(&self) -> Vec<TrackStats> {
+		let mut stats: Vec<_> = self
+			.tracks
+			.values()
+			.map(|track| {
+				let queued: Vec<_> = track
+					.backlog
+					.iter()
+					.filter_map(GroupBacklog::unwritten)
+					.filter(|(objects, _)| *objects > 0)
+					.collect();
+
+				TrackStats {
+					name: track.track.name.clone(),
+					groups: track.groups,
+					objects: track.objects,
+					last_group: track.last_group,
+					bytes: track.bytes,
+					queued_groups: queued.len() as u64,
+					queued_bytes: queued.iter().map(|(_, bytes)| *bytes as u64).sum(),
+				}
+			})
+			.collect();
+
+		stats.sort_by(|a, b| a.name.cmp(&b.name));
+		stats
+	}
+
+	/// Publish a sender report with the current counters for each media track, as a new group.
+	pub fn report(&mut self) -> anyhow::Result<()> {
+		let timestamp = time::SystemTime::now()
+			.duration_since(time::UNIX_EPOCH)
+			.context("clock went backwards")?
+			.as_micros()
+			.try_into()?;
+
+		let tracks = self
+			.stats()
+			.into_iter()
+			.map(|track| moq_catalog::TrackReport {
+				name: track.name,
+				objects: track.objects,
+				bytes: track.bytes,
+				last_group: track.last_group,
+			})
+			.collect();
+
+		let report = moq_catalog::SenderReport { timestamp, tracks };
+		self.reports.append(0)?.write(serde_json::to_vec(&report)?.into())?;
+
+		Ok(())
+	}
+
+	/// Publish the group index as a new group if any groups started since the last one, see [Self::with_index].
+	pub fn index(&mut self) -> anyhow::Result<()> {
+		if let Some((track, writer)) = self.index.as_mut() {
+			if let Some(index) = writer.encode()? {
+				track.append(0)?.write(index.into())?;
+			}
+		}
+
+		Ok(())
+	}
+
 	// Parse the input buffer, reading any full atoms we can find.
 	// Keep appending more data and calling parse.
 	pub fn parse<B: Buf>(&mut self, buf: &mut B) -> anyhow::Result<()> {
@@ -107,7 +225,11 @@ impl Media {
 				self.current.replace(fragment.track);
 
 				// Publish the moof header, creating a new segment if it's a keyframe.
-				track.header(atom, fragment).context("failed to publish moof")?;
+				let start = track.header(atom, fragment).context("failed to publish moof")?;
+
+				if let (Some((group_id, timestamp)), Some((_, index))) = (start, self.index.as_mut()) {
+					index.record(&track.track.name, group_id, timestamp);
+				}
 			}
 			mp4::BoxType::MdatBox => {
 				// Get the track ID from the previous moof.
@@ -132,7 +254,7 @@ impl Media {
 		init.extend_from_slice(&raw);
 
 		// Create the catalog track with a single segment.
-		self.init.append(0)?.write(init.into())?;
+		self.init.append(BOOTSTRAP_PRIORITY)?.write(init.into())?;
 
 		let mut tracks = Vec::new();
 
@@ -217,7 +339,7 @@ impl Media {
 			tracks.push(track);
 
 			// Store the track publisher in a map so we can update it later.
-			let track = self.broadcast.create(&name).context("broadcast closed")?;
+			let track = self.broadcast.track(name.as_str()).mode(mode::Groups).create()?;
 			let track = Track::new(track, handler, timescale);
 			self.tracks.insert(id, track);
 		}
@@ -231,12 +353,24 @@ impl Media {
 			tracks,
 		};
 
-		let catalog_str = serde_json::to_string_pretty(&catalog)?;
-
-		log::info!("catalog: {}", catalog_str);
+		log::info!("catalog: {}", serde_json::to_string_pretty(&catalog)?);
+		self.publish_catalog(&catalog)
+	}
 
-		// Create a single fragment for the segment.
-		self.catalog.append(0)?.write(catalog_str.into())?;
+	// Publish the catalog, as a delta in the current group if possible.
+	fn publish_catalog(&mut self, catalog: &moq_catalog::Root) -> anyhow::Result<()> {
+		match self.catalog_encoder.encode(catalog)? {
+			Some(moq_catalog::CatalogUpdate::Full(full)) => {
+				let mut group = self.catalog.append(BOOTSTRAP_PRIORITY)?;
+				group.write(full.into())?;
+				self.catalog_group = Some(group);
+			}
+			Some(moq_catalog::CatalogUpdate::Delta(delta)) => {
+				let group = self.catalog_group.as_mut().context("missing catalog group")?;
+				group.write(delta.into())?;
+			}
+			None => {}
+		}
 
 		Ok(())
 	}
@@ -299,23 +433,44 @@ struct Track {
 
 	// The type of track, ex. "vide" or "soun"
 	handler: TrackType,
+
+	// Counters reported by stats.
+	groups: u64,
+	objects: u64,
+	bytes: u64,
+	last_group: Option<u64>,
+
+	// The groups that may still have objects waiting to be sent, including the current one.
+	backlog: Vec<GroupBacklog>,
 }
 
 impl Track {
-	fn new(track: TrackWriter, handler: TrackType, timescale: u64) -> Self {
+	fn new(track: GroupsWriter, handler: TrackType, timescale: u64) -> Self {
 		Self {
-			track: track.groups().unwrap(),
+			track,
 			current: None,
 			timescale,
 			handler,
+			groups: 0,
+			objects: 0,
+			bytes: 0,
+			last_group: None,
+			backlog: Vec::new(),
 		}
 	}
 
-	pub fn header(&mut self, raw: Bytes, fragment: Fragment) -> anyhow::Result<()> {
+	// Returns the group ID and media timestamp in microseconds when a new group is started.
+	pub fn header(&mut self, raw: Bytes, fragment: Fragment) -> anyhow::Result<Option<(u64, u64)>> {
+		self.bytes += raw.len() as u64;
+		self.objects += 1;
+
+		// Mark keyframes, so subscribers joining mid-group know where they can start decoding.
+		let independent = fragment.keyframe;
+
 		if let Some(current) = self.current.as_mut() {
 			// Use the existing segment
-			current.write(raw)?;
-			return Ok(());
+			current.write_with(raw, independent, Default::default())?;
+			return Ok(None);
 		}
 
 		// Otherwise make a new segment
@@ -330,20 +485,40 @@ impl Track {
 
 		let priority = u32::MAX.checked_sub(timestamp).context("priority too large")?.into();
 
+		// Include the timestamps in the group header so subscribers don't need to parse the fragment.
+		// We don't know when the media was captured, so we use the time it was received instead.
+		let timestamp = GroupTimestamp {
+			media: fragment.timestamp_micros(self.timescale),
+			wall: time::SystemTime::now()
+				.duration_since(time::UNIX_EPOCH)
+				.ok()
+				.and_then(|now| now.as_micros().try_into().ok()),
+		};
+
 		// Create a new segment.
-		let mut segment = self.track.append(priority)?;
+		let mut segment = self.track.append_timed(priority, timestamp)?;
 
 		// Write the fragment in it's own object.
-		segment.write(raw)?;
+		segment.write_with(raw, independent, Default::default())?;
+
+		// Older groups can't get any new objects, so they're done once everything was sent or they were released.
+		self.backlog
+			.retain(|group| group.unwritten().is_some_and(|(objects, _)| objects > 0));
+		self.backlog.push(segment.backlog());
 
 		// Save for the next iteration
+		let start = timestamp.media.map(|media| (segment.group_id, media));
+		self.last_group = Some(segment.group_id);
 		self.current = Some(segment);
+		self.groups += 1;
 
-		Ok(())
+		Ok(start)
 	}
 
 	pub fn data(&mut self, raw: Bytes) -> anyhow::Result<()> {
 		let segment = self.current.as_mut().context("missing current fragment")?;
+		self.bytes += raw.len() as u64;
+		self.objects += 1;
 		segment.write(raw)?;
 
 		Ok(())
@@ -388,6 +563,11 @@ impl Fragment {
 	fn timestamp(&self, timescale: u64) -> time::Duration {
 		time::Duration::from_millis(1000 * self.timestamp / timescale)
 	}
+
+	// Convert from timescale units to microseconds, avoiding overflow.
+	fn timestamp_micros(&self, timescale: u64) -> Option<u64> {
+		(self.timestamp as u128 * 1_000_000 / timescale as u128).try_into().ok()
+	}
 }
 
 fn sample_timestamp(moof: &mp4::MoofBox) -> Option<u64> {
@@ -409,8 +589,10 @@ fn sample_keyframe(moof: &mp4::MoofBox) -> bool {
 				None => default_flags,
 			};
 
-			if i == 0 && trun.first_sample_flags.is_some() {
-				flags = trun.first_sample_flags.unwrap();
+			if i == 0 {
+				if let Some(first) = trun.first_sample_flags {
+					flags = first;
+				}
 			}
 
 			// https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
@@ -0,0 +1,842 @@
+use std::{
+	collections::BTreeSet,
+	io, net,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time,
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use clap::Parser;
+use ring::{
+	digest::{digest, SHA256},
+	rand::SystemRandom,
+	signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use rustls::sign::CertifiedKey;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use url::Url;
+use yasna::{models::ObjectIdentifier, ASN1Result, BERReader, BERReaderSeq, Tag};
+
+use moq_native::tls::{ServeCerts, ACME_TLS_ALPN};
+
+#[derive(Parser, Clone)]
+#[group(id = "acme")]
+pub struct AcmeArgs {
+	/// Provision a certificate for this domain via ACME (ex. Let's Encrypt) and renew it automatically.
+	///
+	/// This value can be provided multiple times; a single certificate covers every domain.
+	/// The relay must be reachable on TCP port 443 to answer TLS-ALPN-01 challenges.
+	#[arg(long = "acme-domain")]
+	pub domain: Vec<String>,
+
+	/// The ACME directory URL.
+	#[arg(
+		long = "acme-directory",
+		default_value = "https://acme-v02.api.letsencrypt.org/directory"
+	)]
+	pub directory: Url,
+
+	/// An email address the CA can use to contact you about the certificate.
+	#[arg(long = "acme-contact")]
+	pub contact: Option<String>,
+
+	/// Store the ACME account key and certificate in this directory.
+	#[arg(long = "acme-cache", default_value = "acme")]
+	pub cache: PathBuf,
+
+	/// Answer TLS-ALPN-01 challenges on this TCP address.
+	#[arg(
+		id = "acme-bind",
+		long = "acme-bind",
+		value_name = "ACME_BIND",
+		default_value = "[::]:443"
+	)]
+	pub bind: net::SocketAddr,
+}
+
+impl AcmeArgs {
+	/// Returns an error if another TCP listener uses the challenge address, ex. `--tcp-bind` on port 443.
+	///
+	/// Challenges are answered on a new listener for each renewal, which would fail while the other one is running.
+	pub fn check_bind(&self, listeners: &[(&str, net::SocketAddr)]) -> anyhow::Result<()> {
+		for (name, addr) in listeners {
+			let overlaps = addr.ip() == self.bind.ip() || addr.ip().is_unspecified() || self.bind.ip().is_unspecified();
+			anyhow::ensure!(
+				!overlaps || addr.port() != self.bind.port(),
+				"--acme-bind {} conflicts with {} {}, so renewals would fail; use a different port for either",
+				self.bind,
+				name,
+				addr
+			);
+		}
+
+		Ok(())
+	}
+}
+
+const RENEW_CHECK: time::Duration = time::Duration::from_secs(12 * 60 * 60);
+
+// How long to wait between polling a pending authorization or order.
+const POLL_INTERVAL: time::Duration = time::Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 30;
+
+/// Provisions and renews certificates via ACME, installing them into the server's [ServeCerts].
+pub struct Acme {
+	args: AcmeArgs,
+	certs: Arc<ServeCerts>,
+	http: reqwest::Client,
+	current: Option<Arc<CertifiedKey>>,
+}
+
+impl Acme {
+	pub fn new(args: AcmeArgs, certs: Arc<ServeCerts>) -> Self {
+		Self {
+			args,
+			certs,
+			http: reqwest::Client::new(),
+			current: None,
+		}
+	}
+
+	/// Install the cached certificate, provisioning a new one if it's missing or due for renewal.
+	///
+	/// If renewal fails, the cached certificate is used until [Self::run] tries again.
+	pub async fn init(&mut self) -> anyhow::Result<()> {
+		tokio::fs::create_dir_all(&self.args.cache)
+			.await
+			.context("failed to create ACME cache")?;
+
+		let cached = match self.load().await {
+			Ok(cached) => cached,
+			// ex. a crash while replacing the files left a key that doesn't match the certificate.
+			Err(err) => {
+				log::warn!("invalid cached ACME certificate, renewing: {:?}", err);
+				None
+			}
+		};
+
+		if let Some((cert, due)) = cached {
+			self.install(cert);
+
+			if !due {
+				log::info!("using cached ACME certificate: domains={:?}", self.args.domain);
+				return Ok(());
+			}
+
+			if let Err(err) = self.renew().await {
+				log::warn!("failed to renew ACME certificate, using the cached one: {:?}", err);
+			}
+
+			return Ok(());
+		}
+
+		self.renew().await
+	}
+
+	// Load the cached certificate if there is one, making sure the key matches, and whether it's due for renewal.
+	async fn load(&self) -> anyhow::Result<Option<(Arc<CertifiedKey>, bool)>> {
+		let chain = match tokio::fs::read(self.args.cache.join("cert.pem")).await {
+			Ok(chain) => chain,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err.into()),
+		};
+
+		let key = tokio::fs::read(self.args.cache.join("key.pem")).await?;
+		let cert = ServeCerts::parse(&chain, &key)?;
+
+		let leaf = cert.cert.first().context("empty certificate chain")?;
+		let due = renew_due(leaf, &self.args.domain, time::SystemTime::now())?;
+
+		Ok(Some((cert, due)))
+	}
+
+	/// Periodically renew the certificate, logging any failures and trying again later.
+	pub async fn run(mut self) -> anyhow::Result<()> {
+		loop {
+			tokio::time::sleep(RENEW_CHECK).await;
+			self.check().await;
+		}
+	}
+
+	// Renew the certificate if it's missing or due, logging any failure so it's retried at the next check.
+	async fn check(&mut self) {
+		let due = match self.load().await {
+			Ok(cached) => cached.is_none_or(|(_, due)| due),
+			Err(err) => {
+				log::warn!("failed to check ACME certificate: {:?}", err);
+				return;
+			}
+		};
+
+		if due {
+			if let Err(err) = self.renew().await {
+				log::warn!("failed to renew ACME certificate: {:?}", err);
+			}
+		}
+	}
+
+	async fn renew(&mut self) -> anyhow::Result<()> {
+		log::info!("requesting ACME certificate: domains={:?}", self.args.domain);
+
+		// Answer challenges while the order is in progress.
+		let (chain, key) = tokio::select! {
+			res = self.order() => res?,
+			res = self.serve_challenges() => return res,
+		};
+
+		// Write both files before replacing either, so a crash can't leave a partially written file.
+		// A crash between the renames leaves a key that doesn't match the certificate, which is renewed on startup.
+		let (key_path, cert_path) = (self.args.cache.join("key.pem"), self.args.cache.join("cert.pem"));
+		let (key_tmp, cert_tmp) = (key_path.with_extension("pem.tmp"), cert_path.with_extension("pem.tmp"));
+
+		write_private(&key_tmp, key.as_bytes()).await?;
+		tokio::fs::write(&cert_tmp, &chain).await?;
+		tokio::fs::rename(&key_tmp, &key_path).await?;
+		tokio::fs::rename(&cert_tmp, &cert_path).await?;
+
+		self.install(ServeCerts::parse(chain.as_bytes(), key.as_bytes())?);
+		log::info!("installed ACME certificate: domains={:?}", self.args.domain);
+
+		Ok(())
+	}
+
+	fn install(&mut self, cert: Arc<CertifiedKey>) {
+		self.certs.replace(self.current.as_ref(), cert.clone());
+		self.current = Some(cert);
+	}
+
+	// Run the ACME order, returning the PEM certificate chain and private key.
+	async fn order(&self) -> anyhow::Result<(String, String)> {
+		let mut account = Account::new(&self.http, &self.args).await?;
+
+		let identifiers: Vec<_> = self
+			.args
+			.domain
+			.iter()
+			.map(|domain| json!({ "type": "dns", "value": domain }))
+			.collect();
+
+		let new_order = account.directory.new_order.clone();
+		let res = account
+			.post(&new_order, Some(json!({ "identifiers": identifiers })))
+			.await?;
+
+		let order_url = location(&res)?;
+		let order: Order = res.json().await?;
+
+		for url in &order.authorizations {
+			let authz: Authorization = account.post(url, None).await?.json().await?;
+			if authz.status == "valid" {
+				continue;
+			}
+
+			let domain = authz.identifier.value;
+			let challenge = authz
+				.challenges
+				.iter()
+				.find(|challenge| challenge.kind == "tls-alpn-01")
+				.context("no tls-alpn-01 challenge offered")?;
+
+			let key_authorization = format!("{}.{}", challenge.token, account.thumbprint());
+			self.certs
+				.set_challenge(&domain, Some(challenge_cert(&domain, &key_authorization)?));
+
+			// Tell the server we're ready, then wait for it to connect to us.
+			let res = async {
+				account.post(&challenge.url, Some(json!({}))).await?;
+				account.poll::<Authorization>(url, "valid").await
+			}
+			.await;
+
+			self.certs.set_challenge(&domain, None);
+			res.with_context(|| format!("failed to validate {}", domain))?;
+		}
+
+		let key = rcgen::KeyPair::generate()?;
+		let csr = rcgen::CertificateParams::new(self.args.domain.clone())?.serialize_request(&key)?;
+
+		account
+			.post(
+				&order.finalize,
+				Some(json!({ "csr": URL_SAFE_NO_PAD.encode(csr.der()) })),
+			)
+			.await?;
+
+		let order: Order = account.poll(&order_url, "valid").await?;
+		let url = order.certificate.context("missing certificate URL")?;
+		let chain = account.post(&url, None).await?.text().await?;
+
+		Ok((chain, key.serialize_pem()))
+	}
+
+	// Accept TLS connections with the acme-tls/1 ALPN, which are answered with the challenge certificate.
+	async fn serve_challenges(&self) -> anyhow::Result<()> {
+		let provider = Arc::new(rustls::crypto::ring::default_provider());
+		let mut config = rustls::ServerConfig::builder_with_provider(provider)
+			.with_protocol_versions(&[&rustls::version::TLS12, &rustls::version::TLS13])?
+			.with_no_client_auth()
+			.with_cert_resolver(self.certs.clone());
+		config.alpn_protocols = vec![ACME_TLS_ALPN.to_vec()];
+
+		let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+		let listener = tokio::net::TcpListener::bind(self.args.bind)
+			.await
+			.context("failed to bind ACME challenge listener")?;
+
+		loop {
+			let (tcp, addr) = listener.accept().await?;
+			let acceptor = acceptor.clone();
+
+			// The handshake is the entire challenge, so we don't do anything with the connection.
+			tokio::spawn(async move {
+				if let Err(err) = acceptor.accept(tcp).await {
+					log::debug!("failed ACME challenge handshake: ip={} err={}", addr, err);
+				}
+			});
+		}
+	}
+}
+
+// Renew once a third of the certificate's lifetime remains, ex. 30 days for Let's Encrypt's 90 day certificates.
+// The validity is read from the certificate itself, so a touched file or a CA issuing shorter-lived ones doesn't matter.
+// Changing --acme-domain also renews, since the cached certificate wouldn't cover the new domains.
+fn renew_due(der: &[u8], domains: &[String], now: time::SystemTime) -> anyhow::Result<bool> {
+	let leaf = Leaf::parse(der).context("failed to parse cached ACME certificate")?;
+
+	let wanted: BTreeSet<_> = domains.iter().map(|domain| domain.to_ascii_lowercase()).collect();
+	if leaf.domains != wanted {
+		log::info!("ACME domains changed: cached={:?} wanted={:?}", leaf.domains, wanted);
+		return Ok(true);
+	}
+
+	let lifetime = leaf.not_after.duration_since(leaf.not_before).unwrap_or_default();
+	Ok(now + lifetime / 3 >= leaf.not_after)
+}
+
+// The parts of an X.509 certificate needed to decide when to renew it.
+struct Leaf {
+	domains: BTreeSet<String>,
+	not_before: time::SystemTime,
+	not_after: time::SystemTime,
+}
+
+impl Leaf {
+	// The OID of the subjectAltName extension.
+	const SUBJECT_ALT_NAME: [u64; 4] = [2, 5, 29, 17];
+
+	fn parse(der: &[u8]) -> ASN1Result<Self> {
+		yasna::parse_der(der, |r| {
+			r.read_sequence(|r| {
+				let leaf = r.next().read_sequence(Self::parse_tbs)?;
+
+				r.next().read_der()?; // signatureAlgorithm
+				r.next().read_der()?; // signatureValue
+
+				Ok(leaf)
+			})
+		})
+	}
+
+	fn parse_tbs(r: &mut BERReaderSeq) -> ASN1Result<Self> {
+		r.read_optional(|r| r.read_tagged(Tag::context(0), |r| r.read_i64()))?; // version
+		r.next().read_der()?; // serialNumber
+		r.next().read_der()?; // signature
+		r.next().read_der()?; // issuer
+
+		let (not_before, not_after) = r
+			.next()
+			.read_sequence(|r| Ok((Self::parse_time(r.next())?, Self::parse_time(r.next())?)))?;
+
+		r.next().read_der()?; // subject
+		r.next().read_der()?; // subjectPublicKeyInfo
+		r.read_optional(|r| r.read_tagged_implicit(Tag::context(1), |r| r.read_bitvec_bytes()))?; // issuerUniqueID
+		r.read_optional(|r| r.read_tagged_implicit(Tag::context(2), |r| r.read_bitvec_bytes()))?; // subjectUniqueID
+
+		let domains = r
+			.read_optional(|r| r.read_tagged(Tag::context(3), Self::parse_extensions))?
+			.unwrap_or_default();
+
+		Ok(Self {
+			domains,
+			not_before,
+			not_after,
+		})
+	}
+
+	fn parse_time(r: BERReader) -> ASN1Result<time::SystemTime> {
+		let datetime = match r.lookahead_tag()? {
+			yasna::tags::TAG_UTCTIME => *r.read_utctime()?.datetime(),
+			_ => *r.read_generalized_time()?.datetime(),
+		};
+
+		let secs = u64::try_from(datetime.unix_timestamp()).unwrap_or_default();
+		Ok(time::UNIX_EPOCH + time::Duration::from_secs(secs))
+	}
+
+	// Returns the DNS names in the subjectAltName extension.
+	fn parse_extensions(r: BERReader) -> ASN1Result<BTreeSet<String>> {
+		let mut domains = BTreeSet::new();
+
+		r.read_sequence_of(|r| {
+			r.read_sequence(|r| {
+				let id = r.next().read_oid()?;
+				r.read_optional(|r| r.read_bool())?; // critical
+				let value = r.next().read_bytes()?;
+
+				if id == ObjectIdentifier::from_slice(&Self::SUBJECT_ALT_NAME) {
+					domains = yasna::parse_der(&value, Self::parse_names)?;
+				}
+
+				Ok(())
+			})
+		})?;
+
+		Ok(domains)
+	}
+
+	fn parse_names(r: BERReader) -> ASN1Result<BTreeSet<String>> {
+		let mut names = BTreeSet::new();
+
+		r.read_sequence_of(|r| {
+			// Only dNSName matters, skipping any other kinds of names.
+			match r.lookahead_tag()? {
+				tag if tag == Tag::context(2) => {
+					let name = r.read_tagged_implicit(tag, |r| r.read_ia5_string())?;
+					names.insert(name.to_ascii_lowercase());
+				}
+				_ => {
+					r.read_der()?;
+				}
+			}
+
+			Ok(())
+		})?;
+
+		Ok(names)
+	}
+}
+
+// Write a private key, only readable by the owner, including when replacing an existing file.
+async fn write_private(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+	let mut options = tokio::fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	options.mode(0o600);
+
+	let mut file = options
+		.open(path)
+		.await
+		.with_context(|| format!("failed to create {}", path.display()))?;
+
+	// The mode only applies to new files.
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		file.set_permissions(std::fs::Permissions::from_mode(0o600)).await?;
+	}
+
+	file.write_all(contents).await?;
+	file.flush().await?;
+
+	Ok(())
+}
+
+// A self-signed certificate containing the key authorization digest, as defined by RFC 8737.
+fn challenge_cert(domain: &str, key_authorization: &str) -> anyhow::Result<Arc<CertifiedKey>> {
+	let hash = digest(&SHA256, key_authorization.as_bytes());
+
+	let mut params = rcgen::CertificateParams::new(vec![domain.to_string()])?;
+	params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(hash.as_ref())];
+
+	let key = rcgen::KeyPair::generate()?;
+	let cert = params.self_signed(&key)?;
+
+	ServeCerts::parse(cert.pem().as_bytes(), key.serialize_pem().as_bytes())
+}
+
+fn location(res: &reqwest::Response) -> anyhow::Result<String> {
+	let location = res
+		.headers()
+		.get(reqwest::header::LOCATION)
+		.context("missing location")?;
+	Ok(location.to_str()?.to_string())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+	new_nonce: String,
+	new_account: String,
+	new_order: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+	status: String,
+	#[serde(default)]
+	authorizations: Vec<String>,
+	finalize: String,
+	certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+	status: String,
+	identifier: Identifier,
+	#[serde(default)]
+	challenges: Vec<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Identifier {
+	value: String,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+	#[serde(rename = "type")]
+	kind: String,
+	url: String,
+	token: String,
+}
+
+trait Status {
+	fn status(&self) -> &str;
+}
+
+impl Status for Order {
+	fn status(&self) -> &str {
+		&self.status
+	}
+}
+
+impl Status for Authorization {
+	fn status(&self) -> &str {
+		&self.status
+	}
+}
+
+// An ACME account, signing each request with its key.
+struct Account {
+	http: reqwest::Client,
+	directory: Directory,
+	key: EcdsaKeyPair,
+	rng: SystemRandom,
+	kid: Option<String>,
+	nonce: Option<String>,
+}
+
+impl Account {
+	// Load or generate the account key, then register it (which is a no-op for an existing account).
+	async fn new(http: &reqwest::Client, args: &AcmeArgs) -> anyhow::Result<Self> {
+		let directory: Directory = http
+			.get(args.directory.clone())
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await
+			.context("failed to fetch ACME directory")?;
+
+		let rng = SystemRandom::new();
+		let path = args.cache.join("account.key");
+
+		let pkcs8 = match tokio::fs::read(&path).await {
+			Ok(pkcs8) => pkcs8,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => {
+				let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+					.map_err(|_| anyhow::anyhow!("failed to generate account key"))?;
+				write_private(&path, pkcs8.as_ref()).await?;
+				pkcs8.as_ref().to_vec()
+			}
+			Err(err) => return Err(err).context("failed to read account key"),
+		};
+
+		let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng)
+			.map_err(|err| anyhow::anyhow!("invalid account key: {}", err))?;
+
+		let mut account = Self {
+			http: http.clone(),
+			directory,
+			key,
+			rng,
+			kid: None,
+			nonce: None,
+		};
+
+		let contact: Vec<_> = args.contact.iter().map(|email| format!("mailto:{}", email)).collect();
+		let new_account = account.directory.new_account.clone();
+		let res = account
+			.post(
+				&new_account,
+				Some(json!({ "termsOfServiceAgreed": true, "contact": contact })),
+			)
+			.await
+			.context("failed to register ACME account")?;
+
+		account.kid = Some(location(&res)?);
+
+		Ok(account)
+	}
+
+	fn jwk_coordinates(&self) -> (String, String) {
+		// The public key is uncompressed: 0x04 || x || y
+		let public = self.key.public_key().as_ref();
+		(
+			URL_SAFE_NO_PAD.encode(&public[1..33]),
+			URL_SAFE_NO_PAD.encode(&public[33..65]),
+		)
+	}
+
+	// The JWK thumbprint (RFC 7638), which requires the members in lexicographic order without whitespace.
+	fn thumbprint(&self) -> String {
+		let (x, y) = self.jwk_coordinates();
+		let jwk = format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, x, y);
+		URL_SAFE_NO_PAD.encode(digest(&SHA256, jwk.as_bytes()))
+	}
+
+	async fn nonce(&mut self) -> anyhow::Result<String> {
+		if let Some(nonce) = self.nonce.take() {
+			return Ok(nonce);
+		}
+
+		let res = self.http.head(&self.directory.new_nonce).send().await?;
+		let nonce = res.headers().get("replay-nonce").context("missing nonce")?;
+		Ok(nonce.to_str()?.to_string())
+	}
+
+	// Send a signed request, or a POST-as-GET when there's no payload.
+	async fn post(&mut self, url: &str, payload: Option<serde_json::Value>) -> anyhow::Result<reqwest::Response> {
+		let payload = match payload {
+			Some(payload) => URL_SAFE_NO_PAD.encode(payload.to_string()),
+			None => String::new(),
+		};
+
+		// The server may reject our nonce, in which case we retry once with the new one.
+		for attempt in 0..2 {
+			let mut protected = json!({ "alg": "ES256", "nonce": self.nonce().await?, "url": url });
+			match &self.kid {
+				Some(kid) => protected["kid"] = json!(kid),
+				None => {
+					let (x, y) = self.jwk_coordinates();
+					protected["jwk"] = json!({ "crv": "P-256", "kty": "EC", "x": x, "y": y });
+				}
+			}
+
+			let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+			let signature = self
+				.key
+				.sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+				.map_err(|_| anyhow::anyhow!("failed to sign ACME request"))?;
+
+			let body = json!({
+				"protected": protected,
+				"payload": payload,
+				"signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+			});
+
+			let res = self
+				.http
+				.post(url)
+				.header(reqwest::header::CONTENT_TYPE, "application/jose+json")
+				.body(body.to_string())
+				.send()
+				.await?;
+
+			if let Some(nonce) = res.headers().get("replay-nonce") {
+				self.nonce = Some(nonce.to_str()?.to_string());
+			}
+
+			if res.status().is_success() {
+				return Ok(res);
+			}
+
+			let err: serde_json::Value = res.json().await.unwrap_or_default();
+			if attempt == 0 && err["type"] == "urn:ietf:params:acme:error:badNonce" {
+				continue;
+			}
+
+			anyhow::bail!("ACME request failed: url={} error={}", url, err);
+		}
+
+		unreachable!()
+	}
+
+	// Poll the resource until it has the desired status, failing if it becomes anything other than pending.
+	async fn poll<T: Status + for<'de> Deserialize<'de>>(&mut self, url: &str, want: &str) -> anyhow::Result<T> {
+		for _ in 0..POLL_ATTEMPTS {
+			let res: T = self.post(url, None).await?.json().await?;
+			match res.status() {
+				status if status == want => return Ok(res),
+				"pending" | "processing" | "ready" => tokio::time::sleep(POLL_INTERVAL).await,
+				status => anyhow::bail!("unexpected ACME status: {}", status),
+			}
+		}
+
+		anyhow::bail!("timed out waiting for ACME status: {}", want)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(cache: PathBuf) -> AcmeArgs {
+		AcmeArgs {
+			domain: vec!["relay.example.com".to_string()],
+			// Nothing listens here, so renewing fails immediately.
+			directory: "http://127.0.0.1:1/directory".parse().unwrap(),
+			contact: None,
+			cache,
+			bind: "127.0.0.1:0".parse().unwrap(),
+		}
+	}
+
+	// A self-signed certificate for the domains, valid between the given dates.
+	fn generate(domains: &[&str], not_before: (i32, u8, u8), not_after: (i32, u8, u8)) -> (String, String) {
+		let domains: Vec<_> = domains.iter().map(|domain| domain.to_string()).collect();
+		let mut params = rcgen::CertificateParams::new(domains).unwrap();
+		params.not_before = rcgen::date_time_ymd(not_before.0, not_before.1, not_before.2);
+		params.not_after = rcgen::date_time_ymd(not_after.0, not_after.1, not_after.2);
+
+		let key = rcgen::KeyPair::generate().unwrap();
+		let cert = params.self_signed(&key).unwrap();
+		(cert.pem(), key.serialize_pem())
+	}
+
+	// Write a self-signed certificate to the cache, which has expired unless it's fresh.
+	fn cache(dir: &Path, fresh: bool) -> Arc<CertifiedKey> {
+		let not_after = match fresh {
+			true => (4000, 1, 1),
+			false => (2000, 1, 1),
+		};
+
+		let (chain, key) = generate(&["relay.example.com"], (1999, 1, 1), not_after);
+		std::fs::write(dir.join("cert.pem"), &chain).unwrap();
+		std::fs::write(dir.join("key.pem"), &key).unwrap();
+
+		ServeCerts::parse(chain.as_bytes(), key.as_bytes()).unwrap()
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn private() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("key.pem");
+
+		write_private(&path, b"secret").await.unwrap();
+		let metadata = std::fs::metadata(&path).unwrap();
+		assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+		// Replacing a file that was readable by others also restricts it.
+		std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+		write_private(&path, b"new").await.unwrap();
+		let metadata = std::fs::metadata(&path).unwrap();
+		assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+		assert_eq!(std::fs::read(&path).unwrap(), b"new");
+	}
+
+	#[tokio::test]
+	async fn cached() {
+		let dir = tempfile::tempdir().unwrap();
+		let certs = Arc::new(ServeCerts::default());
+
+		// Without a cached certificate, a failed renewal is fatal.
+		let mut acme = Acme::new(args(dir.path().to_path_buf()), certs.clone());
+		assert!(acme.init().await.is_err());
+		assert!(certs.find(None).is_none());
+
+		// A certificate due for renewal is still used if renewing fails.
+		let cached = cache(dir.path(), false);
+		let mut acme = Acme::new(args(dir.path().to_path_buf()), certs.clone());
+		acme.init().await.unwrap();
+		assert_eq!(certs.find(None).unwrap().cert, cached.cert);
+
+		// A fresh certificate is used without renewing.
+		let fresh = cache(dir.path(), true);
+		let mut acme = Acme::new(args(dir.path().to_path_buf()), certs.clone());
+		acme.init().await.unwrap();
+		assert_eq!(certs.find(Some("relay.example.com")).unwrap().cert, fresh.cert);
+
+		// Failing to renew or even check the cache is logged, leaving the certificate in place.
+		acme.check().await;
+		acme.args.cache = dir.path().join("cert.pem");
+		assert!(acme.load().await.is_err());
+		acme.check().await;
+		assert_eq!(certs.find(Some("relay.example.com")).unwrap().cert, fresh.cert);
+	}
+
+	#[tokio::test]
+	async fn mismatched() {
+		let dir = tempfile::tempdir().unwrap();
+		cache(dir.path(), true);
+
+		// ex. a crash between replacing the key and the certificate.
+		let other = rcgen::KeyPair::generate().unwrap();
+		std::fs::write(dir.path().join("key.pem"), other.serialize_pem()).unwrap();
+
+		// The pair isn't served even though it's fresh; it's renewed instead, which fails here.
+		let certs = Arc::new(ServeCerts::default());
+		let mut acme = Acme::new(args(dir.path().to_path_buf()), certs.clone());
+		assert!(acme.init().await.is_err());
+		assert!(certs.find(None).is_none());
+	}
+
+	#[tokio::test]
+	async fn domains() {
+		let dir = tempfile::tempdir().unwrap();
+		cache(dir.path(), true);
+
+		let mut args = args(dir.path().to_path_buf());
+		let acme = Acme::new(args.clone(), Arc::new(ServeCerts::default()));
+		assert!(!acme.load().await.unwrap().unwrap().1);
+
+		// Adding a domain renews the fresh certificate, since it doesn't cover the new one.
+		args.domain.push("other.example.com".to_string());
+		let acme = Acme::new(args.clone(), Arc::new(ServeCerts::default()));
+		assert!(acme.load().await.unwrap().unwrap().1);
+
+		// As does replacing it.
+		args.domain = vec!["other.example.com".to_string()];
+		let acme = Acme::new(args, Arc::new(ServeCerts::default()));
+		assert!(acme.load().await.unwrap().unwrap().1);
+	}
+
+	#[test]
+	fn lifetime() {
+		// A 90 day certificate, from 2030-01-01.
+		let (chain, key) = generate(&["relay.example.com"], (2030, 1, 1), (2030, 4, 1));
+		let cert = ServeCerts::parse(chain.as_bytes(), key.as_bytes()).unwrap();
+		let der = cert.cert[0].as_ref();
+
+		let day = |day: u64| time::UNIX_EPOCH + time::Duration::from_secs(1893456000 + day * 24 * 60 * 60);
+		let domains = ["Relay.Example.com".to_string()];
+
+		// It's renewed once a third of its lifetime remains, regardless of when the file was written.
+		assert!(!renew_due(der, &domains, day(0)).unwrap());
+		assert!(!renew_due(der, &domains, day(59)).unwrap());
+		assert!(renew_due(der, &domains, day(61)).unwrap());
+		assert!(renew_due(der, &domains, day(120)).unwrap());
+	}
+
+	#[test]
+	fn check_bind() {
+		let mut args = args(PathBuf::new());
+		args.bind = "[::]:443".parse().unwrap();
+
+		assert!(args.check_bind(&[("--tcp-bind", "127.0.0.1:443".parse().unwrap())]).is_err());
+		assert!(args.check_bind(&[("--bind", "[::]:443".parse().unwrap())]).is_err());
+		assert!(args.check_bind(&[("--tcp-bind", "[::]:4443".parse().unwrap())]).is_ok());
+
+		// Specific addresses only conflict with the same address.
+		args.bind = "10.0.0.1:443".parse().unwrap();
+		assert!(args.check_bind(&[("--tcp-bind", "10.0.0.2:443".parse().unwrap())]).is_ok());
+		assert!(args.check_bind(&[("--tcp-bind", "0.0.0.0:443".parse().unwrap())]).is_err());
+	}
+}
@@ -0,0 +1,520 @@
+use std::{fmt::Write, net, sync::Arc};
+
+use axum::{
+	extract::{FromRef, Path, Query, Request, State},
+	http::{header, Method, StatusCode},
+	middleware::{self, Next},
+	response::{IntoResponse, Response},
+	routing::{delete, get, post, put},
+	Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{ClipParams, Clips, Cors, GoAway, Locals, SessionEvent, SessionFailure, SessionInfo, Sessions};
+
+pub struct AdminConfig {
+	/// Listen for plain HTTP on this address, which should not be publicly reachable.
+	pub bind: net::SocketAddr,
+	pub locals: Locals,
+
+	/// The sessions that can be listed and closed, from [crate::Relay::sessions].
+	pub sessions: Sessions,
+
+	/// Used to drain the relay, from [crate::Relay::go_away].
+	pub go_away: GoAway,
+
+	/// The URL sessions are redirected to when draining, unless another one is provided.
+	pub go_away_url: Option<Url>,
+
+	/// Require `Authorization: Bearer <token>` on every route.
+	/// Without one, only the read-only routes are served; changing the relay always requires the token.
+	pub token: Option<String>,
+
+	/// The CORS headers for the admin API, from [Cors::admin]; none are sent by default.
+	pub cors: Option<Cors>,
+
+	/// Read groups that are no longer cached from the archive when assembling clips.
+	#[cfg(feature = "archive")]
+	pub archive: Option<crate::Archive>,
+
+	/// Export the watermark's counters, which should be the same one passed to [crate::RelayConfig::watermark].
+	#[cfg(feature = "watermark")]
+	pub watermark: Option<crate::Watermarker>,
+}
+
+#[derive(Clone)]
+struct AdminState {
+	locals: Locals,
+	sessions: Sessions,
+	go_away: GoAway,
+	go_away_url: Option<Url>,
+	clips: Clips,
+	#[cfg(feature = "watermark")]
+	watermark: Option<crate::Watermarker>,
+}
+
+impl FromRef<AdminState> for Locals {
+	fn from_ref(state: &AdminState) -> Self {
+		state.locals.clone()
+	}
+}
+
+impl FromRef<AdminState> for Sessions {
+	fn from_ref(state: &AdminState) -> Self {
+		state.sessions.clone()
+	}
+}
+
+impl FromRef<AdminState> for Clips {
+	fn from_ref(state: &AdminState) -> Self {
+		state.clips.clone()
+	}
+}
+
+/// An HTTP server used to inspect the relay, ex. by Prometheus.
+pub struct Admin {
+	app: Router,
+	bind: net::SocketAddr,
+}
+
+#[derive(Serialize)]
+struct Namespace {
+	namespace: String,
+	bytes: u64,
+	max_bytes: Option<u64>,
+
+	/// The total bytes received, used to compute the ingest rate.
+	written: u64,
+	broadcast_id: Option<String>,
+}
+
+impl Namespace {
+	fn new(tracks: &moq_transport::serve::Tracks) -> Self {
+		Self {
+			namespace: tracks.namespace.clone(),
+			bytes: tracks.usage.bytes(),
+			max_bytes: tracks.usage.max(),
+			written: tracks.usage.written(),
+			broadcast_id: tracks.broadcast_id.clone(),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct QuotaParams {
+	namespace: String,
+
+	/// The new limit, or null to remove it.
+	max_bytes: Option<u64>,
+}
+
+// Sent as JSON rather than a query, so a browser can't send it cross-origin without a preflight.
+#[derive(Deserialize)]
+struct DrainParams {
+	url: Option<String>,
+}
+
+impl Admin {
+	pub fn new(config: AdminConfig) -> Self {
+		let app = Router::new()
+			.route("/metrics", get(serve_metrics))
+			.route("/namespaces", get(serve_namespaces))
+			.route("/namespaces/*namespace", delete(serve_unannounce))
+			.route("/quota", put(serve_quota))
+			.route("/sessions", get(serve_sessions))
+			.route("/sessions/:id", delete(serve_kick))
+			.route("/sessions/:id/history", get(serve_history))
+			.route("/failures", get(serve_failures))
+			.route("/drain", post(serve_drain))
+			.route("/clip", get(serve_clip));
+
+		#[cfg(feature = "profiling")]
+		let app = app.merge(crate::profile_routes());
+
+		let clips = Clips::new(config.locals.clone());
+		#[cfg(feature = "archive")]
+		let clips = clips.with_archive(config.archive);
+
+		let token = config.token.map(Arc::from);
+		let mut app = app.layer(middleware::from_fn_with_state(token, authorize));
+
+		// Unless configured, there's no CORS layer, so browsers on other origins can't read responses or preflight writes.
+		// It's applied outside of authorization so preflight requests are answered without the token.
+		if let Some(cors) = &config.cors {
+			app = cors.apply(app);
+		}
+
+		let app = app.with_state(AdminState {
+			locals: config.locals,
+			sessions: config.sessions,
+			go_away: config.go_away,
+			go_away_url: config.go_away_url,
+			clips,
+			#[cfg(feature = "watermark")]
+			watermark: config.watermark,
+		});
+
+		Self { app, bind: config.bind }
+	}
+
+	pub async fn run(self) -> anyhow::Result<()> {
+		let listener = tokio::net::TcpListener::bind(self.bind).await?;
+		log::info!("admin listening on {}", listener.local_addr()?);
+
+		axum::serve(listener, self.app).await?;
+		Ok(())
+	}
+}
+
+async fn serve_namespaces(State(locals): State<Locals>) -> Json<Vec<Namespace>> {
+	let namespaces = locals
+		.broadcasts()
+		.iter()
+		.map(|tracks| Namespace::new(tracks))
+		.collect();
+	Json(namespaces)
+}
+
+// Change the byte limit of an announced namespace, until it's announced again.
+async fn serve_quota(
+	State(locals): State<Locals>,
+	Json(params): Json<QuotaParams>,
+) -> Result<Json<Namespace>, (StatusCode, String)> {
+	let local = locals.route(&params.namespace).ok_or_else(|| {
+		(
+			StatusCode::NOT_FOUND,
+			format!("unknown namespace: {}", params.namespace),
+		)
+	})?;
+
+	local.tracks.usage.set_max(params.max_bytes);
+	log::info!(
+		"set namespace quota: namespace={} max_bytes={:?}",
+		params.namespace,
+		params.max_bytes
+	);
+
+	Ok(Json(Namespace::new(&local.tracks)))
+}
+
+async fn serve_sessions(State(sessions): State<Sessions>) -> Json<Vec<SessionInfo>> {
+	Json(sessions.list())
+}
+
+// The latest messages and stream events of a session, ex. to see why a subscription failed.
+async fn serve_history(
+	State(sessions): State<Sessions>,
+	Path(id): Path<u64>,
+) -> Result<Json<Vec<SessionEvent>>, StatusCode> {
+	sessions.history(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+// The latest sessions that failed with a protocol violation, with the events leading up to each.
+async fn serve_failures(State(sessions): State<Sessions>) -> Json<Vec<SessionFailure>> {
+	Json(sessions.failures())
+}
+
+// Close a session immediately, ex. a misbehaving publisher.
+async fn serve_kick(State(sessions): State<Sessions>, Path(id): Path<u64>) -> StatusCode {
+	match sessions.close(id, "closed by operator") {
+		true => StatusCode::NO_CONTENT,
+		false => StatusCode::NOT_FOUND,
+	}
+}
+
+// Stop serving the namespace as if it was unannounced, ex. to take down a misbehaving broadcast.
+async fn serve_unannounce(State(locals): State<Locals>, Path(namespace): Path<String>) -> StatusCode {
+	match locals.kill(&namespace) {
+		true => StatusCode::NO_CONTENT,
+		false => StatusCode::NOT_FOUND,
+	}
+}
+
+// Send GOAWAY to every session, including any accepted afterwards, ex. `{"url": "https://anycast.example.com"}`.
+async fn serve_drain(
+	State(state): State<AdminState>,
+	Json(params): Json<DrainParams>,
+) -> Result<StatusCode, (StatusCode, String)> {
+	let url = match params.url {
+		Some(url) => Url::parse(&url).map_err(|err| (StatusCode::BAD_REQUEST, format!("invalid url: {}", err)))?,
+		None => state.go_away_url.ok_or_else(|| {
+			(
+				StatusCode::BAD_REQUEST,
+				"missing url, and no --go-away-url configured".to_string(),
+			)
+		})?,
+	};
+
+	state.go_away.send(&url);
+	Ok(StatusCode::ACCEPTED)
+}
+
+// Session histories contain every control message sent by the peer, so they're as sensitive as changing the relay.
+fn is_history(path: &str) -> bool {
+	path == "/failures" || (path.starts_with("/sessions/") && path.ends_with("/history"))
+}
+
+// Check the bearer token, if configured. Requests that change the relay or read session histories are refused without one.
+async fn authorize(State(token): State<Option<Arc<str>>>, req: Request, next: Next) -> Response {
+	let read_only = matches!(*req.method(), Method::GET | Method::HEAD) && !is_history(req.uri().path());
+
+	let token = match token {
+		Some(token) => token,
+		None if read_only => return next.run(req).await,
+		None => {
+			return (
+				StatusCode::FORBIDDEN,
+				"changing the relay or reading session histories requires --admin-token-file".to_string(),
+			)
+				.into_response()
+		}
+	};
+
+	let provided = req
+		.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+
+	match provided {
+		Some(provided)
+			if ring::constant_time::verify_slices_are_equal(provided.as_bytes(), token.as_bytes()).is_ok() =>
+		{
+			next.run(req).await
+		}
+		_ => (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")]).into_response(),
+	}
+}
+
+// Serve an MP4 of the requested groups, ex. `/clip?namespace=live&start=10&end=20`.
+async fn serve_clip(State(clips): State<Clips>, Query(params): Query<ClipParams>) -> impl IntoResponse {
+	let clip = clips.assemble(params).await?;
+
+	Ok::<_, (axum::http::StatusCode, String)>((
+		[
+			(header::CONTENT_TYPE, "video/mp4"),
+			(header::CONTENT_DISPOSITION, "attachment; filename=\"clip.mp4\""),
+		],
+		clip,
+	))
+}
+
+// Serve metrics in the Prometheus text format.
+async fn serve_metrics(State(state): State<AdminState>) -> impl IntoResponse {
+	let locals = state.locals;
+	let mut out = String::new();
+
+	out.push_str("# HELP moq_relay_namespace_bytes Approximate bytes retained by each namespace.\n");
+	out.push_str("# TYPE moq_relay_namespace_bytes gauge\n");
+	for (namespace, usage) in locals.usage() {
+		let namespace = namespace
+			.replace('\\', "\\\\")
+			.replace('"', "\\\"")
+			.replace('\n', "\\n");
+		writeln!(
+			out,
+			"moq_relay_namespace_bytes{{namespace=\"{}\"}} {}",
+			namespace,
+			usage.bytes()
+		)
+		.unwrap();
+	}
+
+	out.push_str("# HELP moq_session_panics_total Panics caught in per-stream and per-subscription tasks.\n");
+	out.push_str("# TYPE moq_session_panics_total counter\n");
+	writeln!(out, "moq_session_panics_total {}", moq_transport::session::panics()).unwrap();
+
+	#[cfg(feature = "watermark")]
+	if let Some(watermark) = &state.watermark {
+		watermark.write_metrics(&mut out);
+	}
+
+	([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	async fn serve(token: Option<&str>, cors: Option<Cors>) -> (Url, GoAway) {
+		serve_locals(token, cors, Locals::new()).await
+	}
+
+	async fn serve_locals(token: Option<&str>, cors: Option<Cors>, locals: Locals) -> (Url, GoAway) {
+		let go_away = GoAway::new(None);
+		let admin = Admin::new(AdminConfig {
+			bind: "127.0.0.1:0".parse().unwrap(),
+			locals,
+			sessions: Sessions::new(),
+			go_away: go_away.clone(),
+			go_away_url: None,
+			token: token.map(str::to_string),
+			cors,
+			#[cfg(feature = "archive")]
+			archive: None,
+			#[cfg(feature = "watermark")]
+			watermark: None,
+		});
+
+		let listener = tokio::net::TcpListener::bind(admin.bind).await.unwrap();
+		let url = format!("http://{}/", listener.local_addr().unwrap()).parse().unwrap();
+		tokio::spawn(async move { axum::serve(listener, admin.app).await });
+
+		(url, go_away)
+	}
+
+	#[tokio::test]
+	async fn token() {
+		let (url, go_away) = serve(Some("secret"), None).await;
+		let mut redirect = go_away.subscribe();
+		let client = reqwest::Client::new();
+		let drain = url.join("drain").unwrap();
+		let body = serde_json::json!({ "url": "https://backup.example.com" });
+
+		let res = client.get(url.join("namespaces").unwrap()).send().await.unwrap();
+		assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+		let res = client
+			.post(drain.clone())
+			.json(&body)
+			.bearer_auth("wrong")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+		assert!(redirect.borrow_and_update().is_none());
+
+		// A form or query can't carry the URL, since a browser could send those cross-origin without a preflight.
+		let res = client
+			.post(drain.clone())
+			.query(&[("url", "https://attacker.example.com")])
+			.bearer_auth("secret")
+			.send()
+			.await
+			.unwrap();
+		assert!(res.status().is_client_error());
+		assert!(redirect.borrow_and_update().is_none());
+
+		let res = client
+			.post(drain)
+			.json(&body)
+			.bearer_auth("secret")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::ACCEPTED);
+		assert_eq!(
+			redirect.borrow_and_update().as_ref().map(Url::as_str),
+			Some("https://backup.example.com/")
+		);
+
+		// There's no CORS layer, so browsers on other origins can't read responses.
+		let res = client
+			.get(url.join("metrics").unwrap())
+			.bearer_auth("secret")
+			.header(header::ORIGIN, "https://example.com")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::OK);
+		assert!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+	}
+
+	#[tokio::test]
+	async fn read_only() {
+		let (url, _) = serve(None, None).await;
+		let client = reqwest::Client::new();
+
+		let res = client.get(url.join("sessions").unwrap()).send().await.unwrap();
+		assert_eq!(res.status(), StatusCode::OK);
+
+		// Changing the relay or reading session histories requires a token to be configured.
+		let res = client.delete(url.join("sessions/1").unwrap()).send().await.unwrap();
+		assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+		for path in ["sessions/1/history", "failures"] {
+			let res = client.get(url.join(path).unwrap()).send().await.unwrap();
+			assert_eq!(res.status(), StatusCode::FORBIDDEN);
+		}
+	}
+
+	#[tokio::test]
+	async fn cors() {
+		use clap::Parser;
+
+		// The public endpoints allow any origin by default, but the admin API doesn't send CORS headers.
+		let args = crate::CorsArgs::parse_from(["moq-relay"]);
+		assert!(Cors::admin(&args).unwrap().is_none());
+
+		let args = crate::CorsArgs::parse_from(["moq-relay", "--admin-cors-origin", "https://dashboard.example.com"]);
+		let (url, _) = serve(Some("secret"), Cors::admin(&args).unwrap()).await;
+		let client = reqwest::Client::new();
+
+		// Preflights are answered for the configured origin without the token.
+		let preflight = |origin: &'static str| {
+			client
+				.request(Method::OPTIONS, url.join("drain").unwrap())
+				.header(header::ORIGIN, origin)
+				.header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+				.header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization,content-type")
+				.send()
+		};
+
+		let res = preflight("https://dashboard.example.com").await.unwrap();
+		assert!(res.status().is_success());
+		assert_eq!(
+			res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+			"https://dashboard.example.com"
+		);
+
+		let res = preflight("https://evil.example.com").await.unwrap();
+		assert!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+		// The token is still required.
+		let res = client
+			.get(url.join("sessions").unwrap())
+			.header(header::ORIGIN, "https://dashboard.example.com")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+	}
+
+	#[tokio::test]
+	async fn unannounce() {
+		let mut locals = Locals::new();
+		let (_writer, _, reader) = moq_transport::serve::Tracks::new("live/event".to_string()).produce();
+		let mut registration = locals.register(reader).await.unwrap();
+
+		let (url, _) = serve_locals(Some("secret"), None, locals.clone()).await;
+		let client = reqwest::Client::new();
+		let namespace = |namespace: &str| {
+			let mut url = url.join("namespaces/").unwrap();
+			url.path_segments_mut().unwrap().pop_if_empty().push(namespace);
+			url
+		};
+
+		let res = client
+			.delete(namespace("other"))
+			.bearer_auth("secret")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+		let res = client
+			.delete(namespace("live/event"))
+			.bearer_auth("secret")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+		// The namespace's tasks are stopped, which unregisters it.
+		let err = registration.run().await.unwrap_err();
+		assert_eq!(err.to_string(), "namespace killed: live/event");
+		drop(registration);
+		assert!(locals.route("live/event").is_none());
+	}
+}
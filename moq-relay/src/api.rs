@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use url::Url;
 
+use crate::Sessions;
+
 #[derive(Clone)]
 pub struct Api {
 	client: moq_api::Client,
@@ -15,14 +19,41 @@ impl Api {
 	}
 
 	pub async fn set_origin(&self, namespace: String) -> Result<Refresh, moq_api::ApiError> {
-		let refresh = Refresh::new(self.client.clone(), self.origin.clone(), namespace);
-		refresh.update().await?;
-		Ok(refresh)
+		// Register before creating the refresh, since dropping it removes the origin even if it belongs to another node.
+		log::debug!("registering origin: namespace={} url={}", namespace, self.origin.url);
+		self.client.set_origin(&namespace, self.origin.clone()).await?;
+		Ok(Refresh::new(self.client.clone(), self.origin.clone(), namespace))
 	}
 
 	pub async fn get_origin(&self, namespace: &str) -> Result<Option<moq_api::Origin>, moq_api::ApiError> {
 		self.client.get_origin(namespace).await
 	}
+
+	/// Returns true if another node is registered as the origin for the namespace.
+	pub async fn is_remote(&self, namespace: &str) -> Result<bool, moq_api::ApiError> {
+		let origin = self.client.get_origin(namespace).await?;
+		Ok(origin.is_some_and(|origin| origin != self.origin))
+	}
+
+	/// Report the number of sessions forever, so moq-api can send publishers to the least loaded relay.
+	///
+	/// moq-api forgets a relay after 30s, so a failed report is retried on the next interval rather than fatal.
+	pub async fn report_load(self, sessions: Sessions) {
+		let mut interval = tokio::time::interval(Duration::from_secs(10));
+
+		loop {
+			interval.tick().await;
+
+			let load = moq_api::RelayLoad {
+				url: self.origin.url.clone(),
+				sessions: sessions.list().len() as u64,
+			};
+
+			if let Err(err) = self.client.set_relay(&load).await {
+				log::warn!("failed to report load: url={} error={}", load.url, err);
+			}
+		}
+	}
 }
 
 pub struct Refresh {
@@ -0,0 +1,395 @@
+use std::{
+	collections::{BTreeMap, HashSet},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{Bytes, BytesMut};
+use clap::Parser;
+use moq_transport::{
+	serve::{
+		Group, GroupAnnotations, GroupSkip, GroupTimestamp, GroupsWriter, Track, TrackReader, TrackReaderMode,
+		TracksReader,
+	},
+	session::Subscribed,
+};
+use object_store::{path::Path, ObjectStore};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+use url::Url;
+
+#[derive(Parser, Clone)]
+#[group(id = "archive")]
+pub struct ArchiveArgs {
+	/// Upload completed groups for every announced broadcast to this URL, ex. `s3://bucket/prefix` or `gs://bucket/prefix`.
+	/// Credentials are read from the environment, ex. `AWS_ACCESS_KEY_ID` or `GOOGLE_SERVICE_ACCOUNT`.
+	#[arg(id = "archive-url", long = "archive-url")]
+	pub url: Option<Url>,
+
+	/// Rewrite each track's manifest at most this often, and once the track ends.
+	#[arg(long = "archive-manifest-interval-ms", default_value = "5000")]
+	pub manifest_interval_ms: u64,
+}
+
+/// The groups uploaded for a track, stored next to them as `manifest.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+	pub namespace: String,
+	pub track: String,
+	pub groups: Vec<ArchiveGroup>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveGroup {
+	pub id: u64,
+
+	/// The object key containing each object in the group, concatenated.
+	pub key: String,
+
+	/// The size of each object, so they can be split again.
+	pub objects: Vec<usize>,
+
+	#[serde(rename = "mediaTime", skip_serializing_if = "Option::is_none")]
+	pub media_time: Option<u64>,
+
+	#[serde(rename = "wallTime", skip_serializing_if = "Option::is_none")]
+	pub wall_time: Option<u64>,
+
+	/// The group's annotations, with base64 values.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub annotations: BTreeMap<String, String>,
+}
+
+/// Uploads completed groups to object storage, keyed by `<prefix>/<namespace>/<track>/<group>`.
+///
+/// Subscriptions starting at an older group can be replayed from the archive, followed by the live track.
+#[derive(Clone)]
+pub struct Archive {
+	store: Arc<dyn ObjectStore>,
+	prefix: Path,
+	manifest_interval: Duration,
+}
+
+impl Archive {
+	pub fn new(url: &Url, manifest_interval: Duration) -> anyhow::Result<Self> {
+		// Unknown keys are ignored, so pass the whole environment.
+		let env = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+		let (store, prefix) = object_store::parse_url_opts(url, env).context("invalid archive URL")?;
+
+		Ok(Self {
+			store: store.into(),
+			prefix,
+			manifest_interval,
+		})
+	}
+
+	/// Archive the catalog and every track it lists, until the broadcast ends.
+	pub async fn broadcast(&self, mut tracks: TracksReader) -> anyhow::Result<()> {
+		let catalog = tracks.subscribe(".catalog").context("broadcast ended")?;
+
+		let mut groups = match catalog.mode().await? {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => anyhow::bail!("catalog must use groups"),
+		};
+
+		// Aborted on drop, so nothing outlives the broadcast.
+		let mut tasks = JoinSet::new();
+		tasks.spawn(self.clone().run(catalog));
+
+		let mut started = HashSet::new();
+
+		while let Some(mut group) = groups.next().await? {
+			let mut decoder = moq_catalog::CatalogDecoder::new();
+			let mut object_id = 0;
+
+			while let Some(payload) = group.read_next().await? {
+				let root = decoder.decode(object_id, &payload)?;
+				object_id += 1;
+
+				let names = root
+					.tracks
+					.into_iter()
+					.flat_map(|track| [Some(track.name), track.init_track]);
+				for name in names.flatten() {
+					if !started.insert(name.clone()) {
+						continue;
+					}
+
+					if let Some(track) = tracks.subscribe(&name) {
+						tasks.spawn(self.clone().run(track));
+					}
+				}
+
+				// Reap any tracks that already ended.
+				while tasks.try_join_next().is_some() {}
+			}
+		}
+
+		while tasks.join_next().await.is_some() {}
+
+		Ok(())
+	}
+
+	async fn run(self, track: TrackReader) {
+		let info = track.info.clone();
+		if let Err(err) = self.track(track).await {
+			log::warn!("failed archiving track: {:?}, error: {}", info, err);
+		}
+	}
+
+	/// Archive every group in the track until it ends, without skipping any.
+	pub async fn track(&self, track: TrackReader) -> anyhow::Result<()> {
+		let mut groups = match track.mode().await? {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => anyhow::bail!("only tracks using groups are archived"),
+		};
+
+		let dir = self.prefix.child(track.namespace.as_str()).child(track.name.as_str());
+
+		let mut manifest = ArchiveManifest {
+			namespace: track.namespace.clone(),
+			track: track.name.clone(),
+			groups: Vec::new(),
+		};
+		let mut written = Instant::now();
+
+		log::info!("archiving track: {:?} to {}", track.info, dir);
+
+		while let Some(mut group) = groups.next().await? {
+			let mut payload = BytesMut::new();
+			let mut objects = Vec::new();
+
+			while let Some(object) = group.read_next().await? {
+				objects.push(object.len());
+				payload.extend_from_slice(&object);
+			}
+
+			let key = dir.child(group.group_id.to_string());
+			self.store.put(&key, payload.freeze().into()).await?;
+
+			manifest.groups.push(ArchiveGroup {
+				id: group.group_id,
+				key: key.to_string(),
+				objects,
+				media_time: group.timestamp.media,
+				wall_time: group.timestamp.wall,
+				annotations: group
+					.annotations
+					.iter()
+					.map(|(key, value)| (key.clone(), STANDARD.encode(value)))
+					.collect(),
+			});
+
+			if written.elapsed() >= self.manifest_interval {
+				self.write_manifest(&dir, &manifest).await?;
+				written = Instant::now();
+			}
+		}
+
+		self.write_manifest(&dir, &manifest).await
+	}
+
+	/// Returns the manifest for an archived track, or None if it wasn't archived.
+	pub async fn manifest(&self, namespace: &str, name: &str) -> anyhow::Result<Option<ArchiveManifest>> {
+		let path = self.prefix.child(namespace).child(name).child("manifest.json");
+
+		let json = match self.store.get(&path).await {
+			Ok(res) => res.bytes().await?,
+			Err(object_store::Error::NotFound { .. }) => return Ok(None),
+			Err(err) => return Err(err.into()),
+		};
+
+		Ok(Some(serde_json::from_slice(&json)?))
+	}
+
+	/// Serve archived groups from `start` until `end` (inclusive) to the subscriber.
+	///
+	/// Without an end, the live track continues from the last archived group.
+	/// Any groups between the last manifest update and the live cache are skipped.
+	pub async fn replay(
+		&self,
+		subscribe: Subscribed,
+		manifest: ArchiveManifest,
+		start: u64,
+		end: Option<u64>,
+		live: Option<TrackReader>,
+	) -> anyhow::Result<()> {
+		let (writer, reader) = Track::new(manifest.namespace.clone(), manifest.track.clone()).produce();
+		let mut groups = writer.groups()?;
+
+		let serve = subscribe.with_skip(GroupSkip::Never).serve(reader);
+		tokio::pin!(serve);
+
+		let write = async move {
+			let last = self.load(&mut groups, &manifest, start, end).await?;
+
+			if let (None, Some(live)) = (end, live) {
+				Self::follow(&mut groups, live, last).await?;
+			}
+
+			anyhow::Ok(())
+		};
+
+		tokio::select! {
+			// Poll the subscription first, so it starts reading before the first group is written.
+			biased;
+			res = &mut serve => return Ok(res?),
+			res = write => res?,
+		};
+
+		// Wait until the subscriber has every group.
+		Ok(serve.await?)
+	}
+
+	// Write the archived groups in the range, returning the last group ID.
+	async fn load(
+		&self,
+		groups: &mut GroupsWriter,
+		manifest: &ArchiveManifest,
+		start: u64,
+		end: Option<u64>,
+	) -> anyhow::Result<Option<u64>> {
+		let mut last = None;
+
+		let range = manifest
+			.groups
+			.iter()
+			.filter(|group| group.id >= start && end.is_none_or(|end| group.id <= end));
+
+		for archived in range {
+			let objects = self.read(archived).await?;
+
+			let mut annotations = GroupAnnotations::new();
+			for (key, value) in &archived.annotations {
+				annotations.insert(
+					key.as_str(),
+					STANDARD.decode(value).context("invalid archived annotation")?,
+				);
+			}
+
+			let mut group = groups.create(Group {
+				group_id: archived.id,
+				priority: 0,
+				timestamp: GroupTimestamp {
+					media: archived.media_time,
+					wall: archived.wall_time,
+				},
+				annotations,
+				subgroups: 0,
+				transit: None,
+			})?;
+
+			for object in objects {
+				group.write(object)?;
+			}
+
+			last = Some(archived.id);
+		}
+
+		Ok(last)
+	}
+
+	/// Download an archived group, split into its original objects.
+	pub async fn read(&self, archived: &ArchiveGroup) -> anyhow::Result<Vec<Bytes>> {
+		let mut payload = self.store.get(&Path::parse(&archived.key)?).await?.bytes().await?;
+
+		let mut objects = Vec::with_capacity(archived.objects.len());
+		for size in &archived.objects {
+			anyhow::ensure!(*size <= payload.len(), "archived group is truncated");
+			objects.push(payload.split_to(*size));
+		}
+
+		Ok(objects)
+	}
+
+	// Copy live groups after the last archived group.
+	async fn follow(groups: &mut GroupsWriter, live: TrackReader, last: Option<u64>) -> anyhow::Result<()> {
+		let mut live = match live.mode().await? {
+			TrackReaderMode::Groups(live) => live.with_skip(GroupSkip::Never),
+			_ => anyhow::bail!("only tracks using groups can be replayed"),
+		};
+
+		while let Some(mut group) = live.next().await? {
+			if last.is_some_and(|last| group.group_id <= last) {
+				continue;
+			}
+
+			let mut writer = groups.create(Group {
+				group_id: group.group_id,
+				priority: group.priority,
+				timestamp: group.timestamp,
+				annotations: group.annotations.clone(),
+				subgroups: 0,
+				transit: None,
+			})?;
+
+			while let Some(object) = group.read_next().await? {
+				writer.write(object)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn write_manifest(&self, dir: &Path, manifest: &ArchiveManifest) -> anyhow::Result<()> {
+		let json = serde_json::to_vec(manifest)?;
+		self.store.put(&dir.child("manifest.json"), json.into()).await?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use moq_transport::serve::Track;
+
+	#[tokio::test]
+	async fn track() {
+		let archive = Archive::new(&"memory:///archive".parse().unwrap(), Duration::from_secs(60)).unwrap();
+
+		let (writer, reader) = Track::new("live/demo".to_string(), "0.m4s".to_string()).produce();
+		let mut writer = writer.groups().unwrap();
+
+		let task = tokio::spawn({
+			let archive = archive.clone();
+			async move { archive.track(reader).await }
+		});
+
+		let mut group = writer.append(0).unwrap();
+		group.write("moof".into()).unwrap();
+		group.write("mdat".into()).unwrap();
+		drop(group);
+		drop(writer);
+
+		task.await.unwrap().unwrap();
+
+		let dir = Path::from("archive").child("live/demo").child("0.m4s");
+		let group = archive.store.get(&dir.child("0")).await.unwrap().bytes().await.unwrap();
+		assert_eq!(group, "moofmdat");
+
+		let manifest = archive.store.get(&dir.child("manifest.json")).await.unwrap();
+		let manifest: ArchiveManifest = serde_json::from_slice(&manifest.bytes().await.unwrap()).unwrap();
+		assert_eq!(manifest.groups.len(), 1);
+		assert_eq!(manifest.groups[0].objects, vec![4, 4]);
+
+		// Read it back, split into the original objects.
+		let manifest = archive.manifest("live/demo", "0.m4s").await.unwrap().unwrap();
+		let (writer, reader) = Track::new("live/demo".to_string(), "0.m4s".to_string()).produce();
+		let mut writer = writer.groups().unwrap();
+
+		let last = archive.load(&mut writer, &manifest, 0, None).await.unwrap();
+		assert_eq!(last, Some(0));
+
+		let mut groups = match reader.mode().await.unwrap() {
+			TrackReaderMode::Groups(groups) => groups,
+			_ => panic!("expected groups"),
+		};
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "moof");
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "mdat");
+
+		assert!(archive.manifest("live/demo", "missing").await.unwrap().is_none());
+	}
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use clap::Parser;
+use moq_transport::serve::TrackCache;
+
+#[cfg(feature = "disk")]
+use {anyhow::Context, moq_transport::serve::Spill, std::path::PathBuf, std::sync::Arc};
+
+/// Retain older groups of every track, so FETCH and late joiners can start further back.
+#[derive(Parser, Clone, Default)]
+#[group(id = "track-cache")]
+pub struct CacheArgs {
+	/// Retain this many older groups of each track, even if no subscriber needs them.
+	/// Anything older is evicted, even if a subscriber that never skips hasn't read it yet.
+	#[arg(long = "cache-groups")]
+	pub groups: Option<u64>,
+
+	/// Retain the older groups of each track for this long, even if no subscriber needs them.
+	/// Anything older is evicted, like with --cache-groups.
+	#[arg(long = "cache-age-ms")]
+	pub age_ms: Option<u64>,
+
+	/// Spill the retained groups to files in this directory, instead of keeping them in memory.
+	#[cfg(feature = "disk")]
+	#[arg(long = "cache-spill-dir")]
+	pub spill_dir: Option<PathBuf>,
+
+	/// Keep groups in memory instead while this many bytes are spilled, across every namespace.
+	#[cfg(feature = "disk")]
+	#[arg(long = "cache-spill-max-bytes", requires = "spill_dir")]
+	pub spill_max_bytes: Option<u64>,
+}
+
+impl CacheArgs {
+	/// Only retains groups if a limit was configured.
+	pub fn load(&self) -> TrackCache {
+		TrackCache {
+			max_groups: self.groups,
+			max_age: self.age_ms.map(Duration::from_millis),
+			retain: true,
+			..Default::default()
+		}
+	}
+
+	/// Returns None unless a directory was configured, creating it if needed.
+	#[cfg(feature = "disk")]
+	pub fn spill(&self) -> anyhow::Result<Option<Arc<Spill>>> {
+		let dir = match &self.spill_dir {
+			Some(dir) => dir,
+			None => return Ok(None),
+		};
+
+		std::fs::create_dir_all(dir).with_context(|| format!("failed to create spill directory: {}", dir.display()))?;
+
+		let mut spill = Spill::new(dir);
+		if let Some(max) = self.spill_max_bytes {
+			spill = spill.with_max_bytes(max);
+		}
+
+		Ok(Some(Arc::new(spill)))
+	}
+}
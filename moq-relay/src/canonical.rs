@@ -0,0 +1,56 @@
+use moq_transport::serve::{ServeError, TrackName};
+use unicode_normalization::UnicodeNormalization;
+
+/// Canonicalize a namespace, so alternate encodings of the same name share a cache entry and policy decision.
+///
+/// This is the same as [moq_api::canonical_namespace], so claims registered in moq-api are found under the same name.
+pub fn canonical_namespace(namespace: &str) -> Result<String, ServeError> {
+	moq_api::canonical_namespace(namespace).map_err(|err| invalid(&err.to_string()))
+}
+
+/// Canonicalize a track name, like [canonical_namespace] for the path and normalizing any query parameters.
+///
+/// The query is re-encoded with [TrackName], so a decoded path may not contain `?`.
+pub fn canonical_track(name: &str) -> Result<String, ServeError> {
+	let mut name = TrackName::parse(name);
+
+	name.path = canonical_namespace(&name.path)?;
+	if name.path.contains('?') {
+		return Err(invalid("escaped '?' in track name"));
+	}
+
+	for (key, value) in name.params.iter_mut() {
+		*key = key.nfc().collect();
+		*value = value.nfc().collect();
+	}
+
+	Ok(name.to_string())
+}
+
+fn invalid(reason: &str) -> ServeError {
+	ServeError::Rejected(400, reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn namespace() {
+		assert_eq!(canonical_namespace("live//room%2Fa").unwrap(), "live/room/a");
+		assert_eq!(canonical_namespace("caf\u{0065}\u{0301}").unwrap(), "caf\u{00e9}");
+		assert_eq!(canonical_namespace("caf%C3%A9").unwrap(), "caf\u{00e9}");
+
+		assert!(canonical_namespace("%FF").is_err());
+		assert!(canonical_namespace("%2541").is_err());
+	}
+
+	#[test]
+	fn track() {
+		assert_eq!(
+			canonical_track("video%2F%2Fhd.m4s?label=a%26b").unwrap(),
+			"video/hd.m4s?label=a%26b"
+		);
+		assert!(canonical_track("video%3F.m4s").is_err());
+	}
+}
@@ -0,0 +1,392 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	str::FromStr,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use moq_transport::{
+	serve::{ServeError, Track},
+	session::Subscribed,
+};
+use serde::Serialize;
+use tokio::sync::watch;
+
+/// A track served by the relay for each namespace while [Capacity] is enabled, reporting this session's place in the waiting room.
+///
+/// Each group contains a single JSON object, written whenever a position changes:
+/// `{"waiting":[{"track":"video","position":3}]}`, where position 1 is admitted next.
+pub const WAITING_TRACK: &str = ".waiting";
+
+/// Limit the number of subscriptions to each namespace, ex. for capacity-constrained events.
+#[derive(Parser, Clone, Default)]
+#[group(id = "capacity")]
+pub struct CapacityArgs {
+	/// The maximum number of concurrent subscriptions to each namespace, unlimited by default.
+	#[arg(long = "max-subscribers")]
+	pub max: Option<usize>,
+
+	/// Override the limit for a namespace, ex. `live/keynote=5000`. Repeatable.
+	#[arg(long = "max-subscribers-for")]
+	pub namespaces: Vec<NamespaceLimit>,
+
+	/// Accept subscriptions over the limit but defer delivery until a slot frees up, instead of rejecting them.
+	/// Subscribers can follow their position with the `.waiting` track.
+	#[arg(long = "waiting-room")]
+	pub waiting_room: bool,
+}
+
+impl CapacityArgs {
+	/// Returns None unless a limit was configured.
+	pub fn load(&self) -> Option<Capacity> {
+		if self.max.is_none() && self.namespaces.is_empty() {
+			return None;
+		}
+
+		let mut capacity = Capacity::new(self.max).with_waiting_room(self.waiting_room);
+		for limit in &self.namespaces {
+			capacity = capacity.with_limit(&limit.namespace, limit.max);
+		}
+
+		Some(capacity)
+	}
+}
+
+/// The maximum number of subscriptions for a single namespace, parsed from `<namespace>=<max>`.
+#[derive(Clone, Debug)]
+pub struct NamespaceLimit {
+	pub namespace: String,
+	pub max: usize,
+}
+
+impl FromStr for NamespaceLimit {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (namespace, max) = s.rsplit_once('=').context("expected <namespace>=<max>")?;
+
+		Ok(Self {
+			namespace: namespace.to_string(),
+			max: max.parse().context("invalid max")?,
+		})
+	}
+}
+
+#[derive(Default)]
+struct NamespaceState {
+	active: usize,
+	waiting: VecDeque<Ticket>,
+}
+
+struct Ticket {
+	id: u64,
+	session: u64,
+	name: String,
+}
+
+/// Counts the subscriptions to each namespace across every session, admitting them up to a limit.
+///
+/// Subscriptions over the limit are rejected, or held in a first-come first-served waiting room.
+#[derive(Clone)]
+pub struct Capacity {
+	max: Option<usize>,
+	limits: Arc<HashMap<String, usize>>,
+	waiting_room: bool,
+
+	state: Arc<Mutex<HashMap<String, NamespaceState>>>,
+	next: Arc<AtomicU64>,
+
+	// Bumped whenever a subscription is admitted, released, or leaves the waiting room.
+	changed: Arc<watch::Sender<()>>,
+}
+
+/// The result of [Capacity::admit].
+pub enum Admission {
+	Admitted(CapacitySlot),
+	Waiting(CapacityTicket),
+}
+
+impl Capacity {
+	/// Limit each namespace to `max` subscriptions, or None for no default limit.
+	pub fn new(max: Option<usize>) -> Self {
+		Self {
+			max,
+			limits: Default::default(),
+			waiting_room: false,
+			state: Default::default(),
+			next: Default::default(),
+			changed: Arc::new(watch::Sender::new(())),
+		}
+	}
+
+	/// Use a different limit for this namespace.
+	pub fn with_limit(mut self, namespace: &str, max: usize) -> Self {
+		Arc::make_mut(&mut self.limits).insert(namespace.to_string(), max);
+		self
+	}
+
+	/// Hold subscriptions over the limit until a slot frees up, instead of rejecting them.
+	pub fn with_waiting_room(mut self, enabled: bool) -> Self {
+		self.waiting_room = enabled;
+		self
+	}
+
+	fn limit(&self, namespace: &str) -> Option<usize> {
+		self.limits.get(namespace).copied().or(self.max)
+	}
+
+	/// Admit a subscription to the track, queue it in the waiting room, or reject it if the namespace is full.
+	///
+	/// The session identifies the subscriber, so it can follow its own position with [WAITING_TRACK].
+	pub fn admit(&self, namespace: &str, session: u64, name: &str) -> Result<Admission, ServeError> {
+		let mut state = self.state.lock().unwrap();
+		let entry = state.entry(namespace.to_string()).or_default();
+
+		// Don't jump the queue, even if a slot is free while the front of it is being admitted.
+		let full = self.limit(namespace).is_some_and(|max| entry.active >= max);
+		if !full && entry.waiting.is_empty() {
+			entry.active += 1;
+			self.changed.send_replace(());
+
+			return Ok(Admission::Admitted(CapacitySlot {
+				capacity: self.clone(),
+				namespace: namespace.to_string(),
+			}));
+		}
+
+		if !self.waiting_room {
+			if entry.active == 0 && entry.waiting.is_empty() {
+				state.remove(namespace);
+			}
+
+			return Err(ServeError::Rejected(503, "namespace at capacity".to_string()));
+		}
+
+		let id = self.next.fetch_add(1, Ordering::Relaxed);
+		entry.waiting.push_back(Ticket {
+			id,
+			session,
+			name: name.to_string(),
+		});
+		self.changed.send_replace(());
+
+		Ok(Admission::Waiting(CapacityTicket {
+			capacity: self.clone(),
+			namespace: namespace.to_string(),
+			id,
+		}))
+	}
+
+	/// The position of each of the session's subscriptions waiting for the namespace.
+	pub fn waiting(&self, namespace: &str, session: u64) -> Vec<WaitingPosition> {
+		let state = self.state.lock().unwrap();
+		let entry = match state.get(namespace) {
+			Some(entry) => entry,
+			None => return Vec::new(),
+		};
+
+		entry
+			.waiting
+			.iter()
+			.enumerate()
+			.filter(|(_, ticket)| ticket.session == session)
+			.map(|(index, ticket)| WaitingPosition {
+				track: ticket.name.clone(),
+				position: index + 1,
+			})
+			.collect()
+	}
+
+	/// The number of admitted and waiting subscriptions for each namespace.
+	pub fn usage(&self) -> Vec<(String, usize, usize)> {
+		let state = self.state.lock().unwrap();
+		state
+			.iter()
+			.map(|(namespace, entry)| (namespace.clone(), entry.active, entry.waiting.len()))
+			.collect()
+	}
+
+	// Remove the namespace once nothing refers to it, so the map doesn't grow forever.
+	fn release(&self, namespace: &str, update: impl FnOnce(&mut NamespaceState)) {
+		let mut state = self.state.lock().unwrap();
+		if let Some(entry) = state.get_mut(namespace) {
+			update(entry);
+
+			if entry.active == 0 && entry.waiting.is_empty() {
+				state.remove(namespace);
+			}
+		}
+
+		self.changed.send_replace(());
+	}
+}
+
+/// An admitted subscription, releasing its slot when dropped.
+pub struct CapacitySlot {
+	capacity: Capacity,
+	namespace: String,
+}
+
+impl Drop for CapacitySlot {
+	fn drop(&mut self) {
+		self.capacity.release(&self.namespace, |entry| entry.active -= 1);
+	}
+}
+
+/// A subscription in the waiting room, leaving it when dropped.
+pub struct CapacityTicket {
+	capacity: Capacity,
+	namespace: String,
+	id: u64,
+}
+
+impl CapacityTicket {
+	/// The number of subscriptions ahead of this one plus one, or None if it already left the waiting room.
+	pub fn position(&self) -> Option<usize> {
+		let state = self.capacity.state.lock().unwrap();
+		let entry = state.get(&self.namespace)?;
+		entry
+			.waiting
+			.iter()
+			.position(|ticket| ticket.id == self.id)
+			.map(|index| index + 1)
+	}
+
+	/// Wait until this subscription reaches the front of the queue and a slot frees up.
+	pub async fn admitted(self) -> CapacitySlot {
+		let mut changed = self.capacity.changed.subscribe();
+
+		loop {
+			{
+				let mut state = self.capacity.state.lock().unwrap();
+				let max = self.capacity.limit(&self.namespace);
+
+				if let Some(entry) = state.get_mut(&self.namespace) {
+					let front = entry.waiting.front().is_some_and(|ticket| ticket.id == self.id);
+					let full = max.is_some_and(|max| entry.active >= max);
+
+					if front && !full {
+						entry.waiting.pop_front();
+						entry.active += 1;
+						self.capacity.changed.send_replace(());
+
+						return CapacitySlot {
+							capacity: self.capacity.clone(),
+							namespace: self.namespace.clone(),
+						};
+					}
+				}
+			}
+
+			// The sender is owned by the capacity, so this can't fail.
+			changed.changed().await.ok();
+		}
+	}
+}
+
+impl Drop for CapacityTicket {
+	fn drop(&mut self) {
+		let id = self.id;
+		self.capacity
+			.release(&self.namespace, |entry| entry.waiting.retain(|ticket| ticket.id != id));
+	}
+}
+
+/// A subscription's place in the waiting room, see [WAITING_TRACK].
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct WaitingPosition {
+	pub track: String,
+	pub position: usize,
+}
+
+#[derive(Serialize, PartialEq)]
+struct WaitingReport {
+	waiting: Vec<WaitingPosition>,
+}
+
+/// Serve the session's positions in the waiting room for the namespace until the subscriber goes away.
+pub async fn serve_waiting(
+	subscribe: Subscribed,
+	capacity: Capacity,
+	namespace: String,
+	session: u64,
+) -> anyhow::Result<()> {
+	let (writer, reader) = Track::new(namespace.clone(), WAITING_TRACK.to_string()).produce();
+	let mut groups = writer.groups()?;
+
+	let serve = subscribe.serve(reader);
+	tokio::pin!(serve);
+
+	let mut changed = capacity.changed.subscribe();
+	let mut last = None;
+
+	loop {
+		let report = WaitingReport {
+			waiting: capacity.waiting(&namespace, session),
+		};
+
+		if last.as_ref() != Some(&report) {
+			groups.append(0)?.write(serde_json::to_vec(&report)?.into())?;
+			last = Some(report);
+		}
+
+		tokio::select! {
+			res = &mut serve => return Ok(res?),
+			_ = changed.changed() => {},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn waiting_room() {
+		let capacity = Capacity::new(Some(1)).with_waiting_room(true);
+
+		let first = match capacity.admit("live", 0, "video").unwrap() {
+			Admission::Admitted(slot) => slot,
+			Admission::Waiting(_) => panic!("expected admitted"),
+		};
+
+		let second = match capacity.admit("live", 1, "video").unwrap() {
+			Admission::Waiting(ticket) => ticket,
+			Admission::Admitted(_) => panic!("expected waiting"),
+		};
+
+		let third = match capacity.admit("live", 1, "audio").unwrap() {
+			Admission::Waiting(ticket) => ticket,
+			Admission::Admitted(_) => panic!("expected waiting"),
+		};
+
+		assert_eq!(second.position(), Some(1));
+		assert_eq!(third.position(), Some(2));
+		assert_eq!(capacity.waiting("live", 1).len(), 2);
+		assert!(capacity.waiting("live", 0).is_empty());
+
+		// Leaving the queue moves everyone behind it forward.
+		drop(second);
+		assert_eq!(third.position(), Some(1));
+
+		// Releasing the slot admits the front of the queue.
+		drop(first);
+		let slot = third.admitted().await;
+		assert_eq!(capacity.usage(), vec![("live".to_string(), 1, 0)]);
+
+		drop(slot);
+		assert!(capacity.usage().is_empty());
+
+		// Without a waiting room, subscriptions over the limit are rejected.
+		let capacity = Capacity::new(None).with_limit("live", 0);
+		assert!(capacity.admit("live", 0, "video").is_err());
+		assert!(matches!(
+			capacity.admit("other", 0, "video"),
+			Ok(Admission::Admitted(_))
+		));
+	}
+}
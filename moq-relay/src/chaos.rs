@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use clap::Parser;
+use moq_transport::session::{Chaos, ChaosConfig};
+
+/// Randomly impair what the relay sends, for testing that subscribers and other relays recover.
+#[derive(Parser, Clone, Default)]
+#[group(id = "chaos")]
+pub struct ChaosArgs {
+	/// Delay this percentage of data streams and control messages.
+	#[arg(long = "chaos-delay", default_value = "0")]
+	pub delay: f64,
+
+	/// Each delay is random, up to this long.
+	#[arg(long = "chaos-max-delay-ms", default_value = "500")]
+	pub max_delay_ms: u64,
+
+	/// Never send this percentage of data streams.
+	#[arg(long = "chaos-drop", default_value = "0")]
+	pub drop: f64,
+
+	/// Reset this percentage of data streams after sending the header.
+	#[arg(long = "chaos-reset", default_value = "0")]
+	pub reset: f64,
+
+	/// Never send this percentage of control messages, which may leave announces and subscribes unanswered.
+	#[arg(long = "chaos-drop-messages", default_value = "0")]
+	pub drop_messages: f64,
+
+	/// Seed the random choices, so a failing test can be reproduced.
+	#[arg(long = "chaos-seed")]
+	pub seed: Option<u64>,
+}
+
+impl ChaosArgs {
+	/// Returns None unless any impairment is configured.
+	pub fn load(&self) -> anyhow::Result<Option<Chaos>> {
+		let percentages = [self.delay, self.drop, self.reset, self.drop_messages];
+		anyhow::ensure!(
+			percentages.iter().all(|p| (0.0..=100.0).contains(p)),
+			"--chaos-* must be percentages"
+		);
+
+		if percentages.iter().all(|p| *p == 0.0) {
+			return Ok(None);
+		}
+
+		Ok(Some(Chaos::new(ChaosConfig {
+			delay: self.delay / 100.0,
+			max_delay: Duration::from_millis(self.max_delay_ms),
+			drop: self.drop / 100.0,
+			reset: self.reset / 100.0,
+			drop_messages: self.drop_messages / 100.0,
+			seed: self.seed,
+		})))
+	}
+}
@@ -0,0 +1,164 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use moq_transport::{serve::ServeError, session::AnnounceInfo};
+use url::Url;
+
+/// Verify that announces are signed by the key registered for the namespace in moq-api.
+#[derive(Parser, Clone, Default)]
+#[group(id = "claim")]
+pub struct ClaimArgs {
+	/// Reject announces for claimed namespaces unless they're signed by the registered key.
+	/// Requires --api, where keys are registered with `POST /key/<namespace>`.
+	#[arg(long = "claim-verify")]
+	pub verify: bool,
+
+	/// Also reject announces for namespaces without a registered key, so every namespace must be claimed first.
+	/// Implies --claim-verify.
+	#[arg(long = "claim-required")]
+	pub required: bool,
+
+	/// Reject signatures made longer ago than this, so a captured announce can't be replayed later.
+	/// Publishers sign each announce as it's sent, so this only needs to cover clock skew and forwarding.
+	#[arg(long = "claim-max-age-secs", default_value = "10")]
+	pub max_age_secs: u64,
+}
+
+impl ClaimArgs {
+	/// Returns None unless verification is enabled.
+	pub fn load(&self, api: Option<&Url>) -> anyhow::Result<Option<Claims>> {
+		if !self.verify && !self.required {
+			return Ok(None);
+		}
+
+		let api = api.context("--claim-verify requires --api")?;
+
+		Ok(Some(Claims::new(
+			moq_api::Client::new(api.clone()),
+			self.required,
+			Duration::from_secs(self.max_age_secs),
+		)))
+	}
+}
+
+/// Checks each announce against the key claiming its namespace, so only the owner can publish it.
+///
+/// Keys are looked up for every announce rather than cached, so revoking a key takes effect immediately.
+/// Each signature is only accepted once, so a captured announce can't be replayed to this relay while it's fresh.
+#[derive(Clone)]
+pub struct Claims {
+	client: moq_api::Client,
+	required: bool,
+	max_age: Duration,
+
+	// The signatures already accepted, until they're too old to be accepted anyway.
+	seen: Arc<Mutex<HashMap<Vec<u8>, Instant>>>,
+}
+
+impl Claims {
+	pub fn new(client: moq_api::Client, required: bool, max_age: Duration) -> Self {
+		Self {
+			client,
+			required,
+			max_age,
+			seen: Default::default(),
+		}
+	}
+
+	/// Look up the key for the canonical namespace and check the signature over the namespace as announced.
+	pub async fn verify(&self, announce: &AnnounceInfo, namespace: &str) -> Result<(), ServeError> {
+		let key = match self.client.get_key(namespace).await {
+			Ok(key) => key,
+			Err(err) => {
+				log::warn!("failed to look up namespace key: namespace={} error={}", namespace, err);
+				return Err(ServeError::Internal("failed to look up namespace key".to_string()));
+			}
+		};
+
+		let key = match key {
+			Some(key) => key,
+			None if self.required => return Err(ServeError::Rejected(403, "namespace not claimed".to_string())),
+			None => return Ok(()),
+		};
+
+		let signature = announce
+			.signature
+			.as_ref()
+			.ok_or_else(|| ServeError::Rejected(401, "signature required".to_string()))?;
+
+		moq_native::sign::verify(&key.public_key, &announce.namespace, signature, self.max_age)
+			.map_err(|err| ServeError::Rejected(403, err.to_string()))?;
+
+		self.accept_once(&signature.signature)
+	}
+
+	// Remember the signature until it expires, rejecting it if it was already accepted.
+	fn accept_once(&self, signature: &[u8]) -> Result<(), ServeError> {
+		let now = Instant::now();
+		let mut seen = self.seen.lock().unwrap();
+		seen.retain(|_, expires| *expires > now);
+
+		// Signatures are accepted this far into the future too, to allow for clock skew.
+		match seen.insert(signature.to_vec(), now + self.max_age * 2) {
+			Some(_) => Err(ServeError::Rejected(403, "signature already used".to_string())),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{extract::Path, routing::get, Json, Router};
+	use moq_native::sign::SigningKey;
+
+	// Serve a single key for every namespace, like moq-api.
+	async fn api(key: &SigningKey) -> Url {
+		let public_key = key.public_key();
+		let app = Router::new().route(
+			"/key/*namespace",
+			get(move |Path(_): Path<String>| async move { Json(moq_api::NamespaceKey { public_key }) }),
+		);
+
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		tokio::spawn(async move { axum::serve(listener, app).await });
+
+		format!("http://{}/", addr).parse().unwrap()
+	}
+
+	fn announce(signature: Option<moq_transport::message::AnnounceSignature>) -> AnnounceInfo {
+		AnnounceInfo {
+			namespace: "live/alice".to_string(),
+			signature,
+			broadcast_id: None,
+			authorization: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn unsigned_and_replayed() {
+		let key = SigningKey::generate().unwrap();
+		let claims = Claims::new(moq_api::Client::new(api(&key).await), false, Duration::from_secs(10));
+
+		let err = claims.verify(&announce(None), "live/alice").await.unwrap_err();
+		assert_eq!(err, ServeError::Rejected(401, "signature required".to_string()));
+
+		let signed = announce(Some(key.sign("live/alice")));
+		claims.verify(&signed, "live/alice").await.unwrap();
+
+		// The same signature is refused the second time, even from another session.
+		let err = claims.clone().verify(&signed, "live/alice").await.unwrap_err();
+		assert!(matches!(err, ServeError::Rejected(403, _)));
+
+		// A fresh signature is fine, ex. when the publisher reconnects.
+		tokio::time::sleep(Duration::from_millis(2)).await;
+		let resigned = announce(Some(key.sign("live/alice")));
+		claims.verify(&resigned, "live/alice").await.unwrap();
+	}
+}
@@ -0,0 +1,374 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use axum::http::StatusCode;
+use bytes::Bytes;
+use moq_transport::serve::{GroupTimestamp, TrackReaderMode};
+use serde::Deserialize;
+
+use crate::{canonical_namespace, Locals};
+
+/// The track containing the `ftyp` and `moov` atoms, as published by `moq-pub`.
+const INIT_TRACK: &str = "0.mp4";
+
+// Avoid buffering an unbounded clip in memory.
+const MAX_CLIP_GROUPS: u64 = 1000;
+
+// The latest group may still be in progress, so don't wait forever for it to finish.
+const CLIP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The query for `GET /clip`.
+#[derive(Deserialize)]
+pub struct ClipParams {
+	pub namespace: String,
+
+	/// The first and last group ID (inclusive) of each track.
+	pub start: u64,
+	pub end: u64,
+
+	/// A comma separated list of tracks, ex. `1.m4s,2.m4s`, defaulting to every track in the init segment.
+	pub tracks: Option<String>,
+}
+
+/// Assembles a downloadable MP4 from the cached or archived groups of a broadcast, ex. for highlights.
+#[derive(Clone)]
+pub struct Clips {
+	locals: Locals,
+	#[cfg(feature = "archive")]
+	archive: Option<crate::Archive>,
+}
+
+// A group of fragments, each a `moof` and `mdat` pair.
+struct ClipGroup {
+	track: usize,
+	group_id: u64,
+	timestamp: GroupTimestamp,
+	objects: Vec<Bytes>,
+}
+
+impl Clips {
+	pub fn new(locals: Locals) -> Self {
+		Self {
+			locals,
+			#[cfg(feature = "archive")]
+			archive: None,
+		}
+	}
+
+	/// Also read groups that are no longer cached from the archive.
+	#[cfg(feature = "archive")]
+	pub fn with_archive(mut self, archive: Option<crate::Archive>) -> Self {
+		self.archive = archive;
+		self
+	}
+
+	/// Return the init segment with only the selected tracks, followed by every group in the range.
+	pub async fn assemble(&self, params: ClipParams) -> Result<Vec<u8>, (StatusCode, String)> {
+		let bad_request = |reason: &str| (StatusCode::BAD_REQUEST, reason.to_string());
+
+		if params.end < params.start {
+			return Err(bad_request("end must not be before start"));
+		}
+
+		if params.end - params.start >= MAX_CLIP_GROUPS {
+			return Err(bad_request(&format!("at most {} groups per clip", MAX_CLIP_GROUPS)));
+		}
+
+		let namespace = canonical_namespace(&params.namespace).map_err(|err| bad_request(&err.to_string()))?;
+
+		let assemble = self.assemble_inner(&namespace, &params);
+		match tokio::time::timeout(CLIP_TIMEOUT, assemble).await {
+			Ok(res) => res,
+			Err(_) => Err((StatusCode::GATEWAY_TIMEOUT, "timed out reading groups".to_string())),
+		}
+	}
+
+	async fn assemble_inner(&self, namespace: &str, params: &ClipParams) -> Result<Vec<u8>, (StatusCode, String)> {
+		let internal = |err: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+
+		let init = self
+			.groups(namespace, INIT_TRACK, 0, None)
+			.await
+			.map_err(internal)?
+			.pop()
+			.ok_or((StatusCode::NOT_FOUND, "no init segment".to_string()))?;
+		let init: Vec<u8> = init.objects.concat();
+
+		let available = init_track_ids(&init).map_err(internal)?;
+
+		let names: Vec<String> = match &params.tracks {
+			Some(tracks) => tracks.split(',').map(|name| name.trim().to_string()).collect(),
+			None => available.iter().map(|id| format!("{}.m4s", id)).collect(),
+		};
+
+		let mut ids = Vec::with_capacity(names.len());
+		for name in &names {
+			let id = name
+				.strip_suffix(".m4s")
+				.and_then(|id| id.parse::<u32>().ok())
+				.filter(|id| available.contains(id))
+				.ok_or_else(|| (StatusCode::NOT_FOUND, format!("unknown track: {}", name)))?;
+			ids.push(id);
+		}
+
+		let mut out = filter_init(&init, &ids).map_err(internal)?;
+
+		let mut groups = Vec::new();
+		for (index, name) in names.iter().enumerate() {
+			let track = self
+				.groups(namespace, name, params.start, Some(params.end))
+				.await
+				.map_err(internal)?;
+			groups.extend(track.into_iter().map(|group| ClipGroup { track: index, ..group }));
+		}
+
+		if groups.is_empty() {
+			return Err((StatusCode::NOT_FOUND, "no groups in range".to_string()));
+		}
+
+		// Interleave the tracks by timestamp if every group has one, otherwise write each track in turn.
+		match groups.iter().all(|group| group.timestamp.media.is_some()) {
+			true => groups.sort_by_key(|group| (group.timestamp.media, group.track, group.group_id)),
+			false => groups.sort_by_key(|group| (group.track, group.group_id)),
+		}
+
+		for group in groups {
+			for object in group.objects {
+				out.extend_from_slice(&object);
+			}
+		}
+
+		Ok(out)
+	}
+
+	// The groups of a track within the range, preferring the cache over the archive.
+	async fn groups(
+		&self,
+		namespace: &str,
+		name: &str,
+		start: u64,
+		end: Option<u64>,
+	) -> anyhow::Result<Vec<ClipGroup>> {
+		let mut groups = BTreeMap::new();
+
+		#[cfg(feature = "archive")]
+		if let Some(archive) = &self.archive {
+			if let Some(manifest) = archive.manifest(namespace, name).await? {
+				let range = manifest
+					.groups
+					.iter()
+					.filter(|group| group.id >= start && end.is_none_or(|end| group.id <= end));
+
+				for archived in range {
+					let group = ClipGroup {
+						track: 0,
+						group_id: archived.id,
+						timestamp: GroupTimestamp {
+							media: archived.media_time,
+							wall: archived.wall_time,
+						},
+						objects: archive.read(archived).await?,
+					};
+					groups.insert(archived.id, group);
+				}
+			}
+		}
+
+		if let Some(mut local) = self.locals.route(namespace) {
+			if let Some(track) = local.tracks.subscribe(name) {
+				if let TrackReaderMode::Groups(reader) = track.mode().await? {
+					for mut cached in reader.cached(start, end) {
+						let mut objects = Vec::new();
+						while let Some(object) = cached.read_next().await? {
+							objects.push(object);
+						}
+
+						let group = ClipGroup {
+							track: 0,
+							group_id: cached.group_id,
+							timestamp: cached.timestamp,
+							objects,
+						};
+						groups.insert(cached.group_id, group);
+					}
+				}
+			}
+		}
+
+		Ok(groups.into_values().collect())
+	}
+}
+
+// A parsed MP4 atom.
+struct Atom<'a> {
+	kind: [u8; 4],
+
+	// The entire atom, including the header.
+	raw: &'a [u8],
+
+	// The contents after the header.
+	body: &'a [u8],
+}
+
+// Split a buffer into consecutive atoms.
+fn atoms(mut buf: &[u8]) -> anyhow::Result<Vec<Atom<'_>>> {
+	let mut atoms = Vec::new();
+
+	while !buf.is_empty() {
+		anyhow::ensure!(buf.len() >= 8, "truncated atom header");
+
+		let kind: [u8; 4] = buf[4..8].try_into()?;
+		let (size, header) = match u32::from_be_bytes(buf[0..4].try_into()?) {
+			// The atom extends to the end of the buffer.
+			0 => (buf.len(), 8),
+			// A 64-bit size follows the type.
+			1 => {
+				anyhow::ensure!(buf.len() >= 16, "truncated atom header");
+				(u64::from_be_bytes(buf[8..16].try_into()?).try_into()?, 16)
+			}
+			size => (size as usize, 8),
+		};
+
+		anyhow::ensure!(size >= header && size <= buf.len(), "invalid atom size");
+
+		let (raw, rest) = buf.split_at(size);
+		atoms.push(Atom {
+			kind,
+			raw,
+			body: &raw[header..],
+		});
+		buf = rest;
+	}
+
+	Ok(atoms)
+}
+
+// Write a container atom with the given children.
+fn container(kind: &[u8; 4], children: &[&[u8]]) -> anyhow::Result<Vec<u8>> {
+	let size = 8 + children.iter().map(|child| child.len()).sum::<usize>();
+
+	let mut out = Vec::with_capacity(size);
+	out.extend_from_slice(&u32::try_from(size)?.to_be_bytes());
+	out.extend_from_slice(kind);
+	for child in children {
+		out.extend_from_slice(child);
+	}
+
+	Ok(out)
+}
+
+// Returns the track ID from the `tkhd` within a `trak`.
+fn trak_id(trak: &Atom) -> anyhow::Result<u32> {
+	let children = atoms(trak.body)?;
+	let tkhd = children
+		.iter()
+		.find(|atom| &atom.kind == b"tkhd")
+		.ok_or_else(|| anyhow::anyhow!("missing tkhd"))?;
+
+	// Skip the version and flags, followed by the creation and modification times.
+	let offset = match tkhd.body.first() {
+		Some(1) => 4 + 16,
+		_ => 4 + 8,
+	};
+
+	let id = tkhd
+		.body
+		.get(offset..offset + 4)
+		.ok_or_else(|| anyhow::anyhow!("truncated tkhd"))?;
+	Ok(u32::from_be_bytes(id.try_into()?))
+}
+
+// Returns the track ID from a `trex`, following the version and flags.
+fn trex_id(trex: &Atom) -> anyhow::Result<u32> {
+	let id = trex.body.get(4..8).ok_or_else(|| anyhow::anyhow!("truncated trex"))?;
+	Ok(u32::from_be_bytes(id.try_into()?))
+}
+
+// Returns the ID of each track in the init segment.
+fn init_track_ids(init: &[u8]) -> anyhow::Result<Vec<u32>> {
+	let top = atoms(init)?;
+	let moov = top
+		.iter()
+		.find(|atom| &atom.kind == b"moov")
+		.ok_or_else(|| anyhow::anyhow!("missing moov"))?;
+
+	atoms(moov.body)?
+		.iter()
+		.filter(|atom| &atom.kind == b"trak")
+		.map(trak_id)
+		.collect()
+}
+
+// Rewrite the init segment to only contain the selected tracks, so players don't wait for the others.
+fn filter_init(init: &[u8], ids: &[u32]) -> anyhow::Result<Vec<u8>> {
+	let mut out = Vec::with_capacity(init.len());
+
+	for atom in atoms(init)? {
+		if &atom.kind != b"moov" {
+			out.extend_from_slice(atom.raw);
+			continue;
+		}
+
+		let mut children = Vec::new();
+		for child in atoms(atom.body)? {
+			match &child.kind {
+				b"trak" if !ids.contains(&trak_id(&child)?) => {}
+				b"mvex" => {
+					let mut trexs = Vec::new();
+					for trex in atoms(child.body)? {
+						if &trex.kind != b"trex" || ids.contains(&trex_id(&trex)?) {
+							trexs.push(trex.raw);
+						}
+					}
+
+					children.push(container(b"mvex", &trexs)?);
+				}
+				_ => children.push(child.raw.to_vec()),
+			}
+		}
+
+		let children: Vec<&[u8]> = children.iter().map(|child| child.as_slice()).collect();
+		out.extend(container(b"moov", &children)?);
+	}
+
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+		container(kind, &[body]).unwrap()
+	}
+
+	fn trak(id: u32) -> Vec<u8> {
+		// Version 0, flags, creation time, modification time, then the track ID.
+		let mut tkhd = vec![0; 12];
+		tkhd.extend_from_slice(&id.to_be_bytes());
+		atom(b"trak", &atom(b"tkhd", &tkhd))
+	}
+
+	fn trex(id: u32) -> Vec<u8> {
+		let mut body = vec![0; 4];
+		body.extend_from_slice(&id.to_be_bytes());
+		atom(b"trex", &body)
+	}
+
+	#[test]
+	fn filter() {
+		let mvex = [trex(1), trex(2)].concat();
+		let moov = [atom(b"mvhd", &[0; 4]), trak(1), trak(2), atom(b"mvex", &mvex)].concat();
+		let init = [atom(b"ftyp", b"isom"), atom(b"moov", &moov)].concat();
+
+		assert_eq!(init_track_ids(&init).unwrap(), vec![1, 2]);
+
+		let filtered = filter_init(&init, &[2]).unwrap();
+		assert_eq!(init_track_ids(&filtered).unwrap(), vec![2]);
+
+		let mvex = [trex(2)].concat();
+		let moov = [atom(b"mvhd", &[0; 4]), trak(2), atom(b"mvex", &mvex)].concat();
+		assert_eq!(filtered, [atom(b"ftyp", b"isom"), atom(b"moov", &moov)].concat());
+
+		assert!(atoms(&init[..init.len() - 1]).is_err());
+	}
+}
@@ -1,11 +1,13 @@
+use std::sync::Arc;
+
 use anyhow::Context;
-use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures::{stream::FuturesUnordered, StreamExt};
 use moq_transport::{
-	serve::Tracks,
+	serve::{ServeError, Track},
 	session::{Announced, SessionError, Subscriber},
 };
 
-use crate::{Api, Locals, Producer};
+use crate::{canonical_namespace, Api, Claims, Locals, Policy, Producer, Request};
 
 #[derive(Clone)]
 pub struct Consumer {
@@ -13,18 +15,54 @@ pub struct Consumer {
 	locals: Locals,
 	api: Option<Api>,
 	forward: Option<Producer>, // Forward all announcements to this subscriber
+	policy: Arc<dyn Policy>,
+	claims: Option<Claims>,
+	replicas: bool,
+	#[cfg(feature = "archive")]
+	archive: Option<crate::Archive>,
 }
 
 impl Consumer {
-	pub fn new(remote: Subscriber, locals: Locals, api: Option<Api>, forward: Option<Producer>) -> Self {
+	pub fn new(
+		remote: Subscriber,
+		locals: Locals,
+		api: Option<Api>,
+		forward: Option<Producer>,
+		policy: Arc<dyn Policy>,
+	) -> Self {
 		Self {
 			remote,
 			locals,
 			api,
 			forward,
+			policy,
+			claims: None,
+			replicas: false,
+			#[cfg(feature = "archive")]
+			archive: None,
 		}
 	}
 
+	/// Reject announces that aren't signed by the key claiming the namespace.
+	pub fn with_claims(mut self, claims: Option<Claims>) -> Self {
+		self.claims = claims;
+		self
+	}
+
+	/// Serve announces for namespaces whose origin is another relay, without registering as their origin.
+	/// These are pushed by a relay replicating its hot namespaces, see [crate::Replicator].
+	pub fn with_replicas(mut self, enabled: bool) -> Self {
+		self.replicas = enabled;
+		self
+	}
+
+	/// Upload completed groups for every announced broadcast.
+	#[cfg(feature = "archive")]
+	pub fn with_archive(mut self, archive: Option<crate::Archive>) -> Self {
+		self.archive = archive;
+		self
+	}
+
 	pub async fn run(mut self) -> Result<(), SessionError> {
 		let mut tasks = FuturesUnordered::new();
 
@@ -49,28 +87,116 @@ impl Consumer {
 	}
 
 	async fn serve(mut self, mut announce: Announced) -> Result<(), anyhow::Error> {
-		let mut tasks = FuturesUnordered::new();
+		// Reject alternate encodings of the same namespace, so they can't bypass the cache or policy.
+		let namespace = match canonical_namespace(&announce.namespace) {
+			Ok(namespace) => namespace,
+			Err(err) => {
+				log::info!("rejected announce: {:?}, error: {}", announce.info, err);
+				announce.close(err)?;
+				return Ok(());
+			}
+		};
 
-		let (_, mut request, reader) = Tracks::new(announce.namespace.to_string()).produce();
+		if let Some(claims) = &self.claims {
+			if let Err(err) = claims.verify(&announce.info, &namespace).await {
+				log::info!("rejected announce: {:?}, error: {}", announce.info, err);
+				announce.close(err)?;
+				return Ok(());
+			}
+		}
 
-		if let Some(api) = self.api.as_ref() {
-			let mut refresh = api.set_origin(reader.namespace.clone()).await?;
-			tasks.push(async move { refresh.run().await.context("failed refreshing origin") }.boxed());
+		let decision = self
+			.policy
+			.check(Request::Announce {
+				namespace: namespace.clone(),
+				token: announce.authorization.clone(),
+			})
+			.await;
+
+		let namespace = match decision.resolve(&namespace) {
+			Ok(namespace) => namespace,
+			Err(err) => {
+				log::info!("rejected announce: {:?}, error: {}", announce.info, err);
+				announce.close(err)?;
+				return Ok(());
+			}
+		};
+
+		if namespace != announce.namespace {
+			log::info!("rewrote announce: {:?} -> {}", announce.info, namespace);
 		}
 
-		// Register the local tracks, unregister on drop
-		let _register = self.locals.register(reader.clone()).await?;
+		// Keep the original publisher's ID, so the broadcast can be traced across relays.
+		// Older publishers don't send one, so assign it here on their behalf.
+		let broadcast_id = match &announce.broadcast_id {
+			Some(id) => id.clone(),
+			None => {
+				let id = moq_native::broadcast::generate_id();
+				log::info!("assigned broadcast id: {:?} -> {}", announce.info, id);
+				id
+			}
+		};
+
+		let (_, mut request, reader) = self
+			.locals
+			.new_tracks(namespace)
+			.with_broadcast_id(broadcast_id)
+			.produce();
+
+		// Register the local tracks, unregister and abort every task for the namespace on drop.
+		let mut registration = self.locals.register(reader.clone()).await?;
+		let tasks = registration.tasks();
+
+		if let Some(api) = self.api.as_ref() {
+			// The replicating relay stays the origin, so other relays keep fetching from it.
+			if self.replicas && api.is_remote(&reader.namespace).await? {
+				log::info!("serving replica: {:?}", announce.info);
+			} else {
+				let mut refresh = api.set_origin(reader.namespace.clone()).await?;
+				tasks.spawn(async move { refresh.run().await.context("failed refreshing origin") })?;
+			}
+		}
 
 		announce.ok()?;
 
-		if let Some(mut forward) = self.forward {
-			tasks.push(
-				async move {
-					log::info!("forwarding announce: {:?}", reader.info);
-					forward.announce(reader).await.context("failed forwarding announce")
+		// Measure the publisher's clock, so the delay of each group stream can be reported in stats.
+		let mut clock = self.remote.clone();
+		let clock_namespace = announce.namespace.clone();
+		tasks.spawn(async move {
+			match clock.sync_clock(&clock_namespace).await {
+				Err(ServeError::Unsupported(_)) => log::debug!("clock sync unsupported: {}", clock_namespace),
+				Err(err) => log::info!("failed syncing clock: namespace={} error={}", clock_namespace, err),
+				Ok(()) => {}
+			}
+			Ok(())
+		})?;
+
+		#[cfg(feature = "archive")]
+		if let Some(archive) = self.archive.clone() {
+			let reader = reader.clone();
+			tasks.spawn(async move {
+				if let Err(err) = archive.broadcast(reader).await {
+					log::warn!("failed archiving broadcast: {}", err);
 				}
-				.boxed(),
-			);
+				Ok(())
+			})?;
+		}
+
+		if let Some(mut forward) = self.forward {
+			// Pass along the signature so the next relay can verify it too, unless it no longer matches.
+			let signature = match reader.namespace == announce.namespace {
+				true => announce.signature.clone(),
+				false => None,
+			};
+
+			tasks.spawn(async move {
+				log::info!("forwarding announce: {:?}", reader.info);
+				let res = match signature {
+					Some(signature) => forward.announce_signed(reader, signature).await,
+					None => forward.announce(reader).await,
+				};
+				res.context("failed forwarding announce")
+			})?;
 		}
 
 		loop {
@@ -79,10 +205,18 @@ impl Consumer {
 				Err(err) = announce.closed() => return Err(err.into()),
 
 				// Wait for the next subscriber and serve the track.
-				Some(track) = request.next() => {
+				Some(mut track) = request.next() => {
 					let mut remote = self.remote.clone();
 
-					tasks.push(async move {
+					// Subscribe using the namespace the publisher announced, not the rewritten one.
+					if track.namespace != announce.namespace {
+						track.info = Arc::new(Track {
+							namespace: announce.namespace.clone(),
+							..track.info.as_ref().clone()
+						});
+					}
+
+					tasks.spawn(async move {
 						let info = track.clone();
 						log::info!("forwarding subscribe: {:?}", info);
 
@@ -91,9 +225,9 @@ impl Consumer {
 						}
 
 						Ok(())
-					}.boxed());
+					})?;
 				},
-				res = tasks.next(), if !tasks.is_empty() => res.unwrap()?,
+				res = registration.run() => return res,
 				else => return Ok(()),
 			}
 		}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::{
+	http::{header, HeaderValue, Method},
+	Router,
+};
+use clap::Parser;
+use tower_http::{
+	cors::{AllowOrigin, Any, CorsLayer},
+	set_header::SetResponseHeaderLayer,
+};
+
+#[derive(Parser, Clone)]
+#[group(id = "cors")]
+pub struct CorsArgs {
+	/// Allow browsers on this origin to use the HTTP endpoints, ex. `https://example.com`.
+	/// May be repeated; if not provided, any origin is allowed.
+	#[arg(long = "cors-origin")]
+	pub origins: Vec<String>,
+
+	/// Allow these HTTP methods from other origins.
+	#[arg(long = "cors-method", default_value = "GET")]
+	pub methods: Vec<String>,
+
+	/// Let browsers cache the CORS preflight response for this long.
+	#[arg(long = "cors-max-age-ms")]
+	pub max_age_ms: Option<u64>,
+
+	/// Set this Cache-Control header on HTTP responses that don't set their own, ex. `no-store`.
+	#[arg(long = "http-cache-control")]
+	pub cache_control: Option<String>,
+
+	/// Allow browsers on this origin to use the admin API, ex. `https://dashboard.example.com`.
+	/// May be repeated; if not provided, the admin API doesn't send CORS headers.
+	#[arg(long = "admin-cors-origin")]
+	pub admin_origins: Vec<String>,
+}
+
+/// The CORS and caching headers applied to every HTTP route, validated up front.
+#[derive(Clone)]
+pub struct Cors {
+	layer: CorsLayer,
+	cache_control: Option<HeaderValue>,
+}
+
+impl Cors {
+	pub fn new(args: &CorsArgs) -> anyhow::Result<Self> {
+		let origins = parse_origins(&args.origins)?;
+
+		let methods = args
+			.methods
+			.iter()
+			.map(|method| Method::from_bytes(method.as_bytes()).with_context(|| format!("invalid method: {}", method)))
+			.collect::<anyhow::Result<Vec<_>>>()?;
+
+		let origins = match origins.is_empty() {
+			true => AllowOrigin::from(Any),
+			false => AllowOrigin::list(origins),
+		};
+
+		let mut layer = CorsLayer::new().allow_origin(origins).allow_methods(methods);
+		if let Some(max_age) = args.max_age_ms {
+			layer = layer.max_age(Duration::from_millis(max_age));
+		}
+
+		let cache_control = args
+			.cache_control
+			.as_deref()
+			.map(HeaderValue::from_str)
+			.transpose()
+			.context("invalid Cache-Control header")?;
+
+		Ok(Self { layer, cache_control })
+	}
+
+	/// The CORS headers for the admin API, which are only sent to the `--admin-cors-origin` origins.
+	/// Returns None when there are none, so browsers on other origins can't use it.
+	pub fn admin(args: &CorsArgs) -> anyhow::Result<Option<Self>> {
+		let origins = parse_origins(&args.admin_origins)?;
+		if origins.is_empty() {
+			return Ok(None);
+		}
+
+		let mut layer = CorsLayer::new()
+			.allow_origin(AllowOrigin::list(origins))
+			.allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+			.allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]);
+		if let Some(max_age) = args.max_age_ms {
+			layer = layer.max_age(Duration::from_millis(max_age));
+		}
+
+		Ok(Some(Self {
+			layer,
+			cache_control: None,
+		}))
+	}
+
+	/// Apply the headers to every route in the router.
+	pub fn apply<S: Clone + Send + Sync + 'static>(&self, mut router: Router<S>) -> Router<S> {
+		if let Some(value) = &self.cache_control {
+			router = router.layer(SetResponseHeaderLayer::if_not_present(
+				header::CACHE_CONTROL,
+				value.clone(),
+			));
+		}
+
+		router.layer(self.layer.clone())
+	}
+}
+
+fn parse_origins(origins: &[String]) -> anyhow::Result<Vec<HeaderValue>> {
+	origins
+		.iter()
+		.map(|origin| HeaderValue::from_str(origin).with_context(|| format!("invalid CORS origin: {}", origin)))
+		.collect()
+}
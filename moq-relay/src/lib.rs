@@ -0,0 +1,67 @@
+mod acme;
+mod admin;
+mod api;
+#[cfg(feature = "archive")]
+mod archive;
+mod cache;
+mod canonical;
+mod capacity;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod claims;
+mod clip;
+mod consumer;
+mod cors;
+mod local;
+mod policy;
+mod producer;
+#[cfg(feature = "profiling")]
+mod profile;
+mod relay;
+mod remote;
+mod replicate;
+mod service;
+mod session;
+mod splice;
+mod stats;
+mod status;
+mod sticky;
+#[cfg(feature = "systemd")]
+mod systemd;
+#[cfg(feature = "watermark")]
+mod watermark;
+mod web;
+
+pub use acme::*;
+pub use admin::*;
+pub use api::*;
+#[cfg(feature = "archive")]
+pub use archive::*;
+pub use cache::*;
+pub use canonical::*;
+pub use capacity::*;
+#[cfg(feature = "chaos")]
+pub use chaos::*;
+pub use claims::*;
+pub use clip::*;
+pub use consumer::*;
+pub use cors::*;
+pub use local::*;
+pub use policy::*;
+pub use producer::*;
+#[cfg(feature = "profiling")]
+pub use profile::*;
+pub use relay::*;
+pub use remote::*;
+pub use replicate::*;
+pub use service::*;
+pub use session::*;
+pub use splice::*;
+pub use stats::*;
+pub use status::*;
+pub use sticky::*;
+#[cfg(feature = "systemd")]
+pub use systemd::*;
+#[cfg(feature = "watermark")]
+pub use watermark::*;
+pub use web::*;
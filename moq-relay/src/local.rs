@@ -1,13 +1,26 @@
 use std::collections::hash_map;
 use std::collections::HashMap;
+use std::future::Future;
 
 use std::sync::{Arc, Mutex};
 
-use moq_transport::serve::{ServeError, TracksReader};
+use futures::{future::BoxFuture, FutureExt};
+use moq_transport::serve::{ServeError, TrackCache, Tracks, TracksReader, Usage};
+use tokio::{
+	sync::{mpsc, Notify},
+	task::JoinSet,
+};
+
+type Task = BoxFuture<'static, anyhow::Result<()>>;
 
 #[derive(Clone)]
 pub struct Locals {
-	lookup: Arc<Mutex<HashMap<String, TracksReader>>>,
+	lookup: Arc<Mutex<HashMap<String, Local>>>,
+	max_bytes: Option<u64>,
+	cache: TrackCache,
+
+	#[cfg(feature = "disk")]
+	spill: Option<Arc<moq_transport::serve::Spill>>,
 }
 
 impl Default for Locals {
@@ -20,36 +33,222 @@ impl Locals {
 	pub fn new() -> Self {
 		Self {
 			lookup: Default::default(),
+			max_bytes: None,
+			cache: Default::default(),
+			#[cfg(feature = "disk")]
+			spill: None,
 		}
 	}
 
+	/// Limit the bytes retained by each namespace, rejecting new groups while over the limit.
+	pub fn with_max_bytes(mut self, max: Option<u64>) -> Self {
+		self.max_bytes = max;
+		self
+	}
+
+	/// Limit the older groups retained by each track, see [TrackCache].
+	pub fn with_cache(mut self, cache: TrackCache) -> Self {
+		self.cache = cache;
+		self
+	}
+
+	/// Spill the retained groups of every namespace to disk.
+	#[cfg(feature = "disk")]
+	pub fn with_spill(mut self, spill: Option<Arc<moq_transport::serve::Spill>>) -> Self {
+		self.spill = spill;
+		self
+	}
+
+	/// Create the [Usage] for a new namespace, enforcing the configured limit.
+	pub fn new_usage(&self) -> Usage {
+		Usage::new(self.max_bytes)
+	}
+
+	/// Create the [Tracks] for a new namespace, with its usage limit, cache and spill configured.
+	pub fn new_tracks(&self, namespace: String) -> Tracks {
+		let tracks = Tracks::new(namespace)
+			.with_usage(self.new_usage())
+			.with_cache(self.cache);
+
+		#[cfg(feature = "disk")]
+		let tracks = match &self.spill {
+			Some(spill) => tracks.with_spill(spill.clone()),
+			None => tracks,
+		};
+
+		tracks
+	}
+
+	/// The bytes retained by each registered namespace.
+	pub fn usage(&self) -> Vec<(String, Arc<Usage>)> {
+		let lookup = self.lookup.lock().unwrap();
+		lookup
+			.iter()
+			.map(|(namespace, local)| (namespace.clone(), local.tracks.usage.clone()))
+			.collect()
+	}
+
+	/// The static information for each registered namespace, including its usage and broadcast ID.
+	pub fn broadcasts(&self) -> Vec<Arc<Tracks>> {
+		let lookup = self.lookup.lock().unwrap();
+		lookup.values().map(|local| local.tracks.info.clone()).collect()
+	}
+
 	pub async fn register(&mut self, tracks: TracksReader) -> anyhow::Result<Registration> {
 		let namespace = tracks.namespace.clone();
+		let (sender, spawned) = mpsc::unbounded_channel();
+		let kill = Arc::new(Notify::new());
+
+		let handle = NamespaceTasks { sender };
+		let local = Local {
+			tracks,
+			tasks: handle.clone(),
+			kill: kill.clone(),
+		};
+
 		match self.lookup.lock().unwrap().entry(namespace.clone()) {
-			hash_map::Entry::Vacant(entry) => entry.insert(tracks),
+			hash_map::Entry::Vacant(entry) => entry.insert(local),
 			hash_map::Entry::Occupied(_) => return Err(ServeError::Duplicate.into()),
 		};
 
 		let registration = Registration {
 			locals: self.clone(),
 			namespace,
+			handle,
+			tasks: JoinSet::new(),
+			spawned,
+			kill,
 		};
 
 		Ok(registration)
 	}
 
-	pub fn route(&self, namespace: &str) -> Option<TracksReader> {
+	pub fn route(&self, namespace: &str) -> Option<Local> {
 		self.lookup.lock().unwrap().get(namespace).cloned()
 	}
+
+	/// Abort every task serving the namespace, as if it was unannounced.
+	///
+	/// Returns false if the namespace isn't registered.
+	pub fn kill(&self, namespace: &str) -> bool {
+		match self.lookup.lock().unwrap().get(namespace) {
+			Some(local) => {
+				local.kill.notify_one();
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+/// A namespace announced to this relay.
+#[derive(Clone)]
+pub struct Local {
+	pub tracks: TracksReader,
+
+	/// Tasks spawned here are aborted when the namespace goes away.
+	pub tasks: NamespaceTasks,
+
+	kill: Arc<Notify>,
+}
+
+/// A handle used to spawn tasks tied to the lifetime of a namespace.
+#[derive(Clone)]
+pub struct NamespaceTasks {
+	sender: mpsc::UnboundedSender<Task>,
+}
+
+impl NamespaceTasks {
+	/// Run the task until it completes or the namespace is unregistered.
+	///
+	/// An error returned by the task will tear down the entire namespace.
+	pub fn spawn<F>(&self, task: F) -> Result<(), ServeError>
+	where
+		F: Future<Output = anyhow::Result<()>> + Send + 'static,
+	{
+		self.sender.send(task.boxed()).map_err(|_| ServeError::Done)
+	}
 }
 
+/// Owns the tasks for a namespace, unregistering it and aborting them all on drop.
 pub struct Registration {
 	locals: Locals,
 	namespace: String,
+	handle: NamespaceTasks,
+	tasks: JoinSet<anyhow::Result<()>>,
+	spawned: mpsc::UnboundedReceiver<Task>,
+	kill: Arc<Notify>,
+}
+
+impl Registration {
+	/// A handle used to spawn tasks for this namespace.
+	pub fn tasks(&self) -> NamespaceTasks {
+		self.handle.clone()
+	}
+
+	/// Run the namespace's tasks, returning an error if any of them fail or the namespace is killed.
+	///
+	/// A task that panics is logged, without affecting the others.
+	pub async fn run(&mut self) -> anyhow::Result<()> {
+		loop {
+			tokio::select! {
+				Some(task) = self.spawned.recv() => {
+					self.tasks.spawn(task);
+				},
+				Some(res) = self.tasks.join_next() => match res {
+					Ok(res) => res?,
+					Err(err) if err.is_panic() => log::error!("namespace task panicked: {}, error: {}", self.namespace, err),
+					Err(err) => return Err(err.into()),
+				},
+				_ = self.kill.notified() => anyhow::bail!("namespace killed: {}", self.namespace),
+			}
+		}
+	}
 }
 
 impl Drop for Registration {
 	fn drop(&mut self) {
 		self.locals.lookup.lock().unwrap().remove(&self.namespace);
+		log::debug!("aborting {} namespace tasks: {}", self.tasks.len(), self.namespace);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn panic_and_kill() {
+		let mut locals = Locals::new();
+		let (_writer, _, reader) = Tracks::new("live".to_string()).produce();
+		let mut registration = locals.register(reader).await.unwrap();
+		let tasks = registration.tasks();
+
+		// A panicking task doesn't take down the namespace.
+		tasks.spawn(async { panic!("oops") }).unwrap();
+		let (done, finished) = tokio::sync::oneshot::channel();
+		tasks
+			.spawn(async move {
+				done.send(()).ok();
+				Ok(())
+			})
+			.unwrap();
+
+		tokio::select! {
+			res = registration.run() => panic!("namespace stopped: {:?}", res),
+			_ = finished => {},
+		}
+		assert!(locals.route("live").is_some());
+
+		// Killing it stops the namespace, which is unregistered once dropped.
+		assert!(locals.kill("live"));
+		assert!(!locals.kill("other"));
+
+		let err = registration.run().await.unwrap_err();
+		assert_eq!(err.to_string(), "namespace killed: live");
+
+		drop(registration);
+		assert!(locals.route("live").is_none());
+		assert!(!locals.kill("live"));
 	}
 }
@@ -1,24 +1,11 @@
+use anyhow::Context;
 use clap::Parser;
 
-mod api;
-mod consumer;
-mod local;
-mod producer;
-mod relay;
-mod remote;
-mod session;
-mod web;
-
-pub use api::*;
-pub use consumer::*;
-pub use local::*;
-pub use producer::*;
-pub use relay::*;
-pub use remote::*;
-pub use session::*;
-pub use web::*;
-
-use std::net;
+use moq_native::preset::Preset;
+use moq_relay::*;
+use moq_transport::{session::SubscribeIds, setup};
+
+use std::{future::Future, net, path::PathBuf};
 use url::Url;
 
 #[derive(Parser, Clone)]
@@ -27,15 +14,33 @@ pub struct Cli {
 	#[arg(long, default_value = "[::]:443")]
 	pub bind: net::SocketAddr,
 
+	/// Also listen for MoQ over TCP+TLS on this address, for clients that can't use UDP.
+	/// Clients connect using a `moqt+tcp://` URL.
+	#[arg(long)]
+	pub tcp_bind: Option<net::SocketAddr>,
+
 	/// The TLS configuration.
 	#[command(flatten)]
 	pub tls: moq_native::tls::Args,
 
+	/// The log sampling configuration.
+	#[command(flatten)]
+	pub log: moq_native::log::Args,
+
+	/// Automatically provision certificates via ACME.
+	#[command(flatten)]
+	pub acme: AcmeArgs,
+
 	/// Forward all announces to the provided server for authentication/routing.
 	/// If not provided, the relay accepts every unique announce.
 	#[arg(long)]
 	pub announce: Option<Url>,
 
+	/// Ask an external HTTP service about each announce and subscribe.
+	#[cfg(feature = "policy-http")]
+	#[command(flatten)]
+	pub policy: PolicyArgs,
+
 	/// The URL of the moq-api server in order to run a cluster.
 	/// Must be used in conjunction with --node to advertise the origin
 	#[arg(long)]
@@ -46,15 +51,127 @@ pub struct Cli {
 	#[arg(long)]
 	pub node: Option<Url>,
 
+	/// Require announces to be signed by the key claiming the namespace in moq-api.
+	#[command(flatten)]
+	pub claim: ClaimArgs,
+
+	/// Start from coherent defaults for latency or quality, overridden by any flags provided explicitly.
+	/// This chooses whether to skip groups when a subscriber falls behind, and the cache limit.
+	#[arg(long, value_enum)]
+	pub preset: Option<Preset>,
+
+	/// Limit the approximate bytes cached for each namespace, rejecting new groups while over the limit.
+	/// [default: unlimited, or 16/64/256 MiB with --preset latency/balanced/quality]
+	#[arg(long)]
+	pub namespace_max_bytes: Option<u64>,
+
+	/// Serve rolling delivery statistics for each local track, by subscribing to `_stats/<track>`.
+	#[arg(long)]
+	pub stats: bool,
+
+	/// Derive subscribe IDs from a hash of the namespace and track name instead of counting up,
+	/// so the same track has the same ID on every relay, and log each ID's track at debug level.
+	#[arg(long)]
+	pub hashed_subscribe_ids: bool,
+
+	/// Don't send the catalog and init tracks reliably at the highest priority, ahead of media.
+	#[arg(long)]
+	pub no_bootstrap_priority: bool,
+
+	/// Only offer and accept this MoQ draft version, ex. `draft-03`, instead of the default ones. Repeatable.
+	/// This is also how to enable the experimental `draft-03-filter`.
+	/// The highest version in common with the peer is used.
+	#[arg(long = "moq-version")]
+	pub moq_versions: Vec<setup::Version>,
+
+	/// Splice ads into selected tracks during ad breaks marked by group annotations.
+	#[command(flatten)]
+	pub splice: SpliceArgs,
+
+	/// Limit the subscriptions to each namespace, with an optional waiting room.
+	#[command(flatten)]
+	pub capacity: CapacityArgs,
+
+	/// Retain older groups for FETCH and late joiners, optionally spilled to disk.
+	#[command(flatten)]
+	pub cache: CacheArgs,
+
+	/// Replicate hot namespaces onto peer relays, improving join latency during audience spikes.
+	#[command(flatten)]
+	pub replicate: ReplicateArgs,
+
+	/// Serve namespaces replicated by a relay with --replicate-peer, leaving it registered as their origin in moq-api.
+	#[arg(long)]
+	pub accept_replicas: bool,
+
+	/// Redirect sessions with GOAWAY on shutdown, for relays behind anycast.
+	#[command(flatten)]
+	pub sticky: StickyArgs,
+
+	/// Ping each session this often, closing it once the peer is silent for three intervals.
+	/// Dead publishers are otherwise only noticed by the QUIC idle timeout.
+	#[arg(long)]
+	pub keepalive_ms: Option<u64>,
+
+	/// Serve metrics and the admin API over plain HTTP on this address, ex. `127.0.0.1:9090`.
+	#[arg(long)]
+	pub admin_bind: Option<net::SocketAddr>,
+
+	/// Require the bearer token in this file on every admin request.
+	/// Without it, the admin API is read-only.
+	#[arg(long)]
+	pub admin_token_file: Option<PathBuf>,
+
+	/// Upload completed groups to object storage.
+	#[cfg(feature = "archive")]
+	#[command(flatten)]
+	pub archive: ArchiveArgs,
+
+	/// Randomly delay, drop, or reset what the relay sends, for testing.
+	#[cfg(feature = "chaos")]
+	#[command(flatten)]
+	pub chaos: ChaosArgs,
+
+	/// Run unattended with a pid file, log file, or as a Windows service.
+	#[command(flatten)]
+	pub service: ServiceArgs,
+
+	/// The CORS and caching headers for the HTTP endpoints.
+	#[command(flatten)]
+	pub cors: CorsArgs,
+
 	/// Enable development mode.
 	/// This hosts a HTTPS web server via TCP to serve the fingerprint of the certificate.
 	#[arg(long)]
 	pub dev: bool,
+
+	/// Serve a demo web player at `https://<host>/watch/<name>` over TCP, on the same port as --bind.
+	#[arg(long)]
+	pub player: bool,
+}
+
+impl Cli {
+	fn namespace_max_bytes(&self) -> Option<u64> {
+		const MIB: u64 = 1024 * 1024;
+
+		self.namespace_max_bytes.or(match self.preset? {
+			Preset::Latency => Some(16 * MIB),
+			Preset::Balanced => Some(64 * MIB),
+			Preset::Quality => Some(256 * MIB),
+		})
+	}
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-	env_logger::init();
+fn main() -> anyhow::Result<()> {
+	let cli = Cli::parse();
+
+	let log_file = cli.service.log_file.as_deref().map(LogFile::open).transpose()?;
+
+	let mut logger = env_logger::Builder::from_default_env();
+	if let Some(log_file) = &log_file {
+		logger.target(env_logger::Target::Pipe(Box::new(log_file.clone())));
+	}
+	logger.init();
 
 	// Disable tracing so we don't get a bunch of Quinn spam.
 	let tracer = tracing_subscriber::FmtSubscriber::builder()
@@ -62,31 +179,256 @@ async fn main() -> anyhow::Result<()> {
 		.finish();
 	tracing::subscriber::set_global_default(tracer).unwrap();
 
-	let cli = Cli::parse();
-	let tls = cli.tls.load()?;
+	#[cfg(windows)]
+	if cli.service.service {
+		return run_windows_service(move |stop| {
+			let stop = async move {
+				stop.await.ok();
+				Ok(())
+			};
+			tokio::runtime::Runtime::new()?.block_on(run(cli, stop))
+		});
+	}
+
+	tokio::runtime::Runtime::new()?.block_on(run(cli, shutdown_signal(log_file)))
+}
+
+// Run the relay until it fails or `shutdown` resolves.
+async fn run(cli: Cli, shutdown: impl Future<Output = anyhow::Result<()>>) -> anyhow::Result<()> {
+	cli.log.init();
+
+	// Removed when the relay stops, including on error.
+	let _pid_file = cli.service.pid_file.as_deref().map(PidFile::create).transpose()?;
+
+	// Resolved before any fields are moved out of the CLI.
+	let max_bytes = cli.namespace_max_bytes();
+	let mut tls = cli.tls.load()?;
+
+	if !cli.acme.domain.is_empty() {
+		// The other TCP listeners, which would prevent answering challenges when renewing.
+		let mut listeners = Vec::new();
+		listeners.extend(cli.tcp_bind.map(|bind| ("--tcp-bind", bind)));
+		listeners.extend((cli.dev || cli.player).then_some(("--bind", cli.bind)));
+		listeners.extend(cli.admin_bind.map(|bind| ("--admin-bind", bind)));
+		cli.acme.check_bind(&listeners)?;
+
+		tls.enable_server()?;
+
+		// Block until we have a certificate, then renew it in the background.
+		let mut acme = Acme::new(cli.acme.clone(), tls.certs.clone());
+		acme.init().await.context("failed to provision ACME certificate")?;
+
+		tokio::spawn(async move {
+			if let Err(err) = acme.run().await {
+				log::error!("ACME renewal stopped: {:?}", err);
+			}
+		});
+	}
+
+	let cors = Cors::new(&cli.cors)?;
+	let admin_cors = Cors::admin(&cli.cors)?;
 
 	if tls.server.is_none() {
 		anyhow::bail!("missing TLS certificates");
 	}
 
+	#[cfg(feature = "policy-http")]
+	let policy = match HttpPolicy::new(cli.policy.clone())? {
+		Some(policy) => {
+			log::info!("checking requests with {}", policy.url());
+			Some(std::sync::Arc::new(policy) as std::sync::Arc<dyn Policy>)
+		}
+		None => None,
+	};
+
+	#[cfg(not(feature = "policy-http"))]
+	let policy = None;
+
+	#[cfg(feature = "archive")]
+	let archive = match &cli.archive.url {
+		Some(url) => {
+			log::info!("archiving broadcasts to {}", url);
+			let interval = std::time::Duration::from_millis(cli.archive.manifest_interval_ms);
+			Some(Archive::new(url, interval)?)
+		}
+		None => None,
+	};
+
+	let splice = cli.splice.load()?;
+	if splice.is_some() {
+		log::info!("splicing ads into tracks: {:?}", cli.splice.tracks);
+	}
+
+	let claims = cli.claim.load(cli.api.as_ref())?;
+	if claims.is_some() {
+		log::info!("verifying announce signatures");
+	}
+
+	let capacity = cli.capacity.load();
+	if capacity.is_some() {
+		log::info!(
+			"limiting subscribers: max={:?} namespaces={:?} waiting_room={}",
+			cli.capacity.max,
+			cli.capacity.namespaces,
+			cli.capacity.waiting_room
+		);
+	}
+
+	#[cfg(feature = "disk")]
+	let spill = cli.cache.spill()?;
+	#[cfg(feature = "disk")]
+	if let Some(dir) = &cli.cache.spill_dir {
+		log::info!(
+			"spilling retained groups: dir={} max_bytes={:?}",
+			dir.display(),
+			cli.cache.spill_max_bytes
+		);
+	}
+
+	let replicator = cli.replicate.load()?;
+	if replicator.is_some() {
+		log::info!(
+			"replicating hot namespaces: peers={:?} replicas={} above={}",
+			cli.replicate.peers.iter().map(|peer| peer.as_str()).collect::<Vec<_>>(),
+			cli.replicate.replicas,
+			cli.replicate.above
+		);
+	}
+
+	let versions: setup::Versions = match cli.moq_versions.is_empty() {
+		true => setup::Versions::supported(),
+		false => cli.moq_versions.clone().into(),
+	};
+	anyhow::ensure!(
+		versions.iter().any(|v| setup::Versions::implemented().contains(v)),
+		"no supported --moq-version, expected one of {:?}",
+		setup::Versions::implemented()
+			.iter()
+			.map(|v| v.to_string())
+			.collect::<Vec<_>>()
+	);
+
+	#[cfg(feature = "chaos")]
+	let chaos = cli.chaos.load()?;
+	#[cfg(feature = "chaos")]
+	if chaos.is_some() {
+		log::warn!("chaos enabled, randomly impairing what the relay sends");
+	}
+
+	#[cfg(feature = "systemd")]
+	let socket = listen_udp()?;
+	#[cfg(not(feature = "systemd"))]
+	let socket = None;
+
 	// Create a QUIC server for media.
 	let relay = Relay::new(RelayConfig {
 		tls: tls.clone(),
 		bind: cli.bind,
+		socket,
+		tcp_bind: cli.tcp_bind,
 		node: cli.node,
 		api: cli.api,
 		announce: cli.announce,
+		max_bytes,
+		cache: cli.cache.load(),
+		#[cfg(feature = "disk")]
+		spill,
+		stats: cli.stats,
+		skip: cli.preset.map(|preset| preset.skip()).unwrap_or_default(),
+		subscribe_ids: match cli.hashed_subscribe_ids {
+			true => SubscribeIds::Hashed,
+			false => SubscribeIds::Sequential,
+		},
+		bootstrap_priority: !cli.no_bootstrap_priority,
+		splice,
+		claims,
+		capacity,
+		replicator,
+		accept_replicas: cli.accept_replicas,
+		versions,
+		sticky: cli.sticky.token.clone().map(Into::into),
+		drain_timeout: std::time::Duration::from_millis(cli.sticky.grace_ms),
+		keepalive: cli.keepalive_ms.map(std::time::Duration::from_millis),
+		#[cfg(feature = "archive")]
+		archive: archive.clone(),
+		#[cfg(feature = "chaos")]
+		chaos,
+		// Watermarks are compiled in by embedding the relay, there's nothing to configure from the CLI.
+		#[cfg(feature = "watermark")]
+		watermark: None,
+		policy,
 	})?;
 
-	if cli.dev {
+	if let Some(bind) = cli.admin_bind {
+		let admin_token = match &cli.admin_token_file {
+			Some(path) => {
+				let token = std::fs::read_to_string(path)
+					.with_context(|| format!("failed to read admin token: {}", path.display()))?;
+				let token = token.trim().to_string();
+				anyhow::ensure!(!token.is_empty(), "empty admin token: {}", path.display());
+				Some(token)
+			}
+			None => None,
+		};
+
+		let admin = Admin::new(AdminConfig {
+			bind,
+			locals: relay.locals(),
+			sessions: relay.sessions(),
+			go_away: relay.go_away(),
+			go_away_url: cli.sticky.url.clone(),
+			token: admin_token,
+			cors: admin_cors,
+			#[cfg(feature = "archive")]
+			archive,
+			#[cfg(feature = "watermark")]
+			watermark: None,
+		});
+
+		tokio::spawn(async move {
+			if let Err(err) = admin.run().await {
+				log::error!("admin server stopped: {:?}", err);
+			}
+		});
+	}
+
+	if cli.dev || cli.player {
 		// Create a web server too.
-		// Currently this only contains the certificate fingerprint (for development only).
-		let web = Web::new(WebConfig { bind: cli.bind, tls });
+		// This serves the certificate fingerprint (for development only) and/or the demo player.
+		let web = Web::new(WebConfig {
+			bind: cli.bind,
+			tls,
+			dev: cli.dev,
+			player: cli.player,
+			cors,
+		});
 
 		tokio::spawn(async move {
 			web.run().await.expect("failed to run web server");
 		});
 	}
 
-	relay.run().await
+	#[cfg(feature = "systemd")]
+	notify_ready();
+
+	let go_away = relay.go_away();
+	let run = relay.run();
+	tokio::pin!(run);
+
+	tokio::select! {
+		res = &mut run => return res,
+		res = shutdown => res?,
+	}
+
+	// Ask sessions to reconnect elsewhere, and keep serving them until the grace period is over.
+	if let Some(url) = &cli.sticky.url {
+		go_away.send(url);
+		let grace = std::time::Duration::from_millis(cli.sticky.grace_ms);
+		if let Ok(res) = tokio::time::timeout(grace, &mut run).await {
+			return res;
+		}
+	}
+
+	log::info!("shutting down");
+	Ok(())
 }
@@ -0,0 +1,364 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use futures::{future::BoxFuture, FutureExt};
+use moq_transport::{serve::ServeError, setup::AuthToken};
+use serde::{Deserialize, Serialize};
+
+/// An incoming request, passed to the [Policy] before the relay acts on it.
+///
+/// Any token is redacted when debug printed, since requests are logged when the policy fails.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Request {
+	/// A client sent a token in SETUP, checked before the session is accepted.
+	///
+	/// Sessions without a token skip this check, relying on the announce and subscribe checks instead.
+	Session { token: String },
+
+	/// A publisher announced a namespace.
+	Announce {
+		namespace: String,
+
+		/// The publisher's authorization token, if provided.
+		#[serde(skip_serializing_if = "Option::is_none")]
+		token: Option<String>,
+	},
+
+	/// A subscriber requested a track, or renewed its token for one.
+	Subscribe {
+		namespace: String,
+		name: String,
+
+		/// The subscriber's authorization token, if provided.
+		#[serde(skip_serializing_if = "Option::is_none")]
+		token: Option<String>,
+	},
+}
+
+impl fmt::Debug for Request {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let redact = |token: &Option<String>| token.as_deref().map(AuthToken::new);
+
+		match self {
+			Self::Session { token } => f.debug_struct("Session").field("token", &AuthToken::new(token)).finish(),
+			Self::Announce { namespace, token } => f
+				.debug_struct("Announce")
+				.field("namespace", namespace)
+				.field("token", &redact(token))
+				.finish(),
+			Self::Subscribe { namespace, name, token } => f
+				.debug_struct("Subscribe")
+				.field("namespace", namespace)
+				.field("name", name)
+				.field("token", &redact(token))
+				.finish(),
+		}
+	}
+}
+
+impl Request {
+	pub fn namespace(&self) -> &str {
+		match self {
+			Self::Session { .. } => "",
+			Self::Announce { namespace, .. } => namespace,
+			Self::Subscribe { namespace, .. } => namespace,
+		}
+	}
+
+	fn set_namespace(&mut self, value: String) {
+		match self {
+			// Sessions aren't scoped to a namespace, so there's nothing to rewrite.
+			Self::Session { .. } => {}
+			Self::Announce { namespace, .. } => *namespace = value,
+			Self::Subscribe { namespace, .. } => *namespace = value,
+		}
+	}
+}
+
+/// What the relay should do with a [Request].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Decision {
+	/// Continue as normal.
+	Accept,
+
+	/// Return an error to the peer with the given code and reason.
+	Reject { code: u64, reason: String },
+
+	/// Continue, but use this namespace within the relay instead of the requested one.
+	///
+	/// A rewritten announce is registered, forwarded, and subscribed to under the new namespace.
+	/// A rewritten subscribe is routed to the new namespace.
+	Rewrite { namespace: String },
+
+	/// Continue until the subscriber's token expires in this many milliseconds, optionally rewriting the namespace.
+	///
+	/// The subscriber is then asked for a new token, which is checked again, otherwise the subscription is closed.
+	/// Announces are accepted as normal.
+	Expires {
+		#[serde(rename = "expiresMs")]
+		expires_ms: u64,
+
+		#[serde(default)]
+		namespace: Option<String>,
+	},
+}
+
+impl Decision {
+	/// Returns the namespace to use for the request, or the error to return to the peer.
+	pub fn resolve(self, namespace: &str) -> Result<String, ServeError> {
+		match self {
+			Self::Accept => Ok(namespace.to_string()),
+			Self::Reject { code, reason } => Err(ServeError::Rejected(code, reason)),
+			Self::Rewrite { namespace } => Ok(namespace),
+			Self::Expires { namespace: rewrite, .. } => Ok(rewrite.unwrap_or_else(|| namespace.to_string())),
+		}
+	}
+
+	/// Returns how long until the subscriber's token expires, if it does.
+	pub fn expires(&self) -> Option<Duration> {
+		match self {
+			Self::Expires { expires_ms, .. } => Some(Duration::from_millis(*expires_ms)),
+			_ => None,
+		}
+	}
+}
+
+/// Inspects every session token, announce, and subscribe received by the relay.
+///
+/// Implementations are compiled in via [crate::RelayConfig::policy], or the relay can call an external hook with
+/// [HttpPolicy] when built with the `policy-http` feature.
+pub trait Policy: Send + Sync {
+	fn check(&self, request: Request) -> BoxFuture<'_, Decision>;
+}
+
+/// Accepts every request, used when no policy is configured.
+pub struct AcceptAll;
+
+impl Policy for AcceptAll {
+	fn check(&self, _request: Request) -> BoxFuture<'_, Decision> {
+		Box::pin(async { Decision::Accept })
+	}
+}
+
+/// Runs each policy in order, stopping at the first rejection.
+///
+/// Rewrites are applied before calling the next policy, so it sees the rewritten namespace.
+/// If any policy returns an expiry, the earliest one is used.
+pub struct Chain {
+	policies: Vec<Arc<dyn Policy>>,
+}
+
+impl Chain {
+	pub fn new(policies: Vec<Arc<dyn Policy>>) -> Self {
+		Self { policies }
+	}
+}
+
+impl Policy for Chain {
+	fn check(&self, mut request: Request) -> BoxFuture<'_, Decision> {
+		async move {
+			let original = request.namespace().to_string();
+			let mut expires: Option<u64> = None;
+
+			for policy in &self.policies {
+				match policy.check(request.clone()).await {
+					Decision::Accept => {}
+					Decision::Rewrite { namespace } => request.set_namespace(namespace),
+					Decision::Expires { expires_ms, namespace } => {
+						if let Some(namespace) = namespace {
+							request.set_namespace(namespace);
+						}
+						expires = Some(expires.map_or(expires_ms, |expires| expires.min(expires_ms)));
+					}
+					reject => return reject,
+				}
+			}
+
+			let rewrite = (request.namespace() != original).then(|| request.namespace().to_string());
+
+			match (expires, rewrite) {
+				(Some(expires_ms), namespace) => Decision::Expires { expires_ms, namespace },
+				(None, Some(namespace)) => Decision::Rewrite { namespace },
+				(None, None) => Decision::Accept,
+			}
+		}
+		.boxed()
+	}
+}
+
+#[cfg(feature = "policy-http")]
+pub use http::*;
+
+#[cfg(feature = "policy-http")]
+mod http {
+	use std::{
+		collections::HashMap,
+		sync::Mutex,
+		time::{Duration, Instant},
+	};
+
+	use clap::Parser;
+	use futures::{future::BoxFuture, FutureExt};
+	use url::Url;
+
+	use super::{Decision, Policy, Request};
+
+	#[derive(Parser, Clone)]
+	#[group(id = "policy")]
+	pub struct PolicyArgs {
+		/// Ask the HTTP service at this URL whether to accept, reject, or rewrite each announce and subscribe.
+		/// If not provided, every request is accepted.
+		#[arg(id = "policy-url", long = "policy")]
+		pub url: Option<Url>,
+
+		/// How long to wait for the policy service to respond.
+		#[arg(long = "policy-timeout-ms", default_value = "2000")]
+		pub timeout_ms: u64,
+
+		/// Remember each decision for this long, so repeated requests don't hit the policy service.
+		/// Set to 0 to disable caching.
+		#[arg(long = "policy-cache-ms", default_value = "0")]
+		pub cache_ms: u64,
+
+		/// Allow requests when the policy service can't be reached or fails, instead of rejecting them.
+		#[arg(long = "policy-fail-open")]
+		pub fail_open: bool,
+	}
+
+	// The maximum number of cached decisions, at which point expired entries are evicted.
+	const CACHE_MAX: usize = 4096;
+
+	/// Asks an external HTTP service about each request, so existing auth systems can be used without recompiling.
+	///
+	/// The [Request] is POSTed as JSON, ex. `{"kind":"subscribe","namespace":"foo","name":"video","token":"abc"}`,
+	/// and the response body is the JSON [Decision], ex. `{"action":"reject","code":403,"reason":"forbidden"}`
+	/// or `{"action":"expires","expiresMs":60000}`.
+	/// A 401 or 403 status is treated as a rejection, while any other failure depends on [PolicyArgs::fail_open].
+	pub struct HttpPolicy {
+		args: PolicyArgs,
+		client: reqwest::Client,
+		url: Url,
+		cache: Mutex<HashMap<Request, (Instant, Decision)>>,
+	}
+
+	impl HttpPolicy {
+		/// Returns None if no URL was configured.
+		pub fn new(args: PolicyArgs) -> anyhow::Result<Option<Self>> {
+			let url = match &args.url {
+				Some(url) => url.clone(),
+				None => return Ok(None),
+			};
+
+			let client = reqwest::Client::builder()
+				.timeout(Duration::from_millis(args.timeout_ms))
+				.build()?;
+
+			Ok(Some(Self {
+				args,
+				client,
+				url,
+				cache: Default::default(),
+			}))
+		}
+
+		pub fn url(&self) -> &Url {
+			&self.url
+		}
+
+		async fn request(&self, request: &Request) -> anyhow::Result<Decision> {
+			let res = self.client.post(self.url.clone()).json(request).send().await?;
+
+			let status = res.status();
+			if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+				return Ok(Decision::Reject {
+					code: status.as_u16().into(),
+					reason: "unauthorized".to_string(),
+				});
+			}
+
+			Ok(res.error_for_status()?.json().await?)
+		}
+
+		fn cached(&self, request: &Request) -> Option<Decision> {
+			let cache = self.cache.lock().unwrap();
+			let (expires, decision) = cache.get(request)?;
+			(*expires > Instant::now()).then(|| decision.clone())
+		}
+
+		fn store(&self, request: Request, decision: Decision) {
+			// The expiry is relative to now, so it would be wrong if reused later.
+			if self.args.cache_ms == 0 || decision.expires().is_some() {
+				return;
+			}
+
+			let now = Instant::now();
+			let mut cache = self.cache.lock().unwrap();
+
+			if cache.len() >= CACHE_MAX {
+				cache.retain(|_, (expires, _)| *expires > now);
+			}
+
+			if cache.len() < CACHE_MAX {
+				cache.insert(request, (now + Duration::from_millis(self.args.cache_ms), decision));
+			}
+		}
+	}
+
+	impl Policy for HttpPolicy {
+		fn check(&self, request: Request) -> BoxFuture<'_, Decision> {
+			async move {
+				if let Some(decision) = self.cached(&request) {
+					return decision;
+				}
+
+				match self.request(&request).await {
+					Ok(decision) => {
+						self.store(request, decision.clone());
+						decision
+					}
+					Err(err) if self.args.fail_open => {
+						log::warn!("policy hook failed, allowing: request={:?} error={:?}", request, err);
+						Decision::Accept
+					}
+					Err(err) => {
+						log::warn!("policy hook failed, denying: request={:?} error={:?}", request, err);
+						Decision::Reject {
+							code: 503,
+							reason: "policy unavailable".to_string(),
+						}
+					}
+				}
+			}
+			.boxed()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn redact() {
+		let requests = [
+			Request::Session {
+				token: "secret".to_string(),
+			},
+			Request::Announce {
+				namespace: "live".to_string(),
+				token: Some("secret".to_string()),
+			},
+			Request::Subscribe {
+				namespace: "live".to_string(),
+				name: "video".to_string(),
+				token: Some("secret".to_string()),
+			},
+		];
+
+		for request in requests {
+			let debug = format!("{:?}", request);
+			assert!(!debug.contains("secret"), "{}", debug);
+		}
+	}
+}
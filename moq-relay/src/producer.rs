@@ -1,34 +1,130 @@
-use futures::{stream::FuturesUnordered, StreamExt};
+use std::{
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 use moq_transport::{
-	serve::{ServeError, TracksReader},
-	session::{Publisher, SessionError, Subscribed},
+	message::AnnounceSignature,
+	serve::{GroupSkip, ServeError, TrackName, TrackReader, TracksReader},
+	session::{Fetched, Publisher, SessionError, Subscribed, SubscribedRenewal},
+};
+
+use crate::{
+	canonical_namespace, canonical_track, serve_stats, serve_status, serve_waiting, Admission, Capacity, CapacitySlot,
+	Decision, Locals, Policy, RemotesConsumer, Replicator, Request, Splicer, STATS_PREFIX, STATUS_TRACK, WAITING_TRACK,
 };
 
-use crate::{Locals, RemotesConsumer};
+// How long a subscriber has to reply with a new token once the current one expires.
+const RENEW_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Identifies each session's subscriptions in the waiting room.
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Clone)]
 pub struct Producer {
 	remote: Publisher,
 	locals: Locals,
 	remotes: Option<RemotesConsumer>,
+	policy: Arc<dyn Policy>,
+	stats: bool,
+	skip: GroupSkip,
+	splicer: Option<Splicer>,
+	capacity: Option<Capacity>,
+	replicator: Option<Replicator>,
+	session: u64,
+	#[cfg(feature = "archive")]
+	archive: Option<crate::Archive>,
+	#[cfg(feature = "watermark")]
+	watermark: Option<crate::Watermarker>,
 }
 
 impl Producer {
-	pub fn new(remote: Publisher, locals: Locals, remotes: Option<RemotesConsumer>) -> Self {
+	pub fn new(remote: Publisher, locals: Locals, remotes: Option<RemotesConsumer>, policy: Arc<dyn Policy>) -> Self {
 		Self {
 			remote,
 			locals,
 			remotes,
+			policy,
+			stats: false,
+			skip: GroupSkip::default(),
+			splicer: None,
+			capacity: None,
+			replicator: None,
+			session: NEXT_SESSION.fetch_add(1, Ordering::Relaxed),
+			#[cfg(feature = "archive")]
+			archive: None,
+			#[cfg(feature = "watermark")]
+			watermark: None,
 		}
 	}
 
+	/// Choose what happens when a subscriber falls behind, defaulting to [GroupSkip::Latest].
+	pub fn with_skip(mut self, skip: GroupSkip) -> Self {
+		self.skip = skip;
+		self
+	}
+
+	/// Splice ads into the selected tracks during ad breaks.
+	pub fn with_splicer(mut self, splicer: Option<Splicer>) -> Self {
+		self.splicer = splicer;
+		self
+	}
+
+	/// Limit the subscriptions to each namespace, shared by every session.
+	pub fn with_capacity(mut self, capacity: Option<Capacity>) -> Self {
+		self.capacity = capacity;
+		self
+	}
+
+	/// Count the subscribers to each local namespace, replicating the hot ones onto peer relays.
+	pub fn with_replicator(mut self, replicator: Option<Replicator>) -> Self {
+		self.replicator = replicator;
+		self
+	}
+
+	/// Replay subscriptions that start at an older group from the archive.
+	#[cfg(feature = "archive")]
+	pub fn with_archive(mut self, archive: Option<crate::Archive>) -> Self {
+		self.archive = archive;
+		self
+	}
+
+	/// Transform the payloads of the selected tracks for each subscriber.
+	#[cfg(feature = "watermark")]
+	pub fn with_watermark(mut self, watermark: Option<crate::Watermarker>) -> Self {
+		self.watermark = watermark;
+		self
+	}
+
+	/// Serve `_stats/<track>` for each local track.
+	pub fn with_stats(mut self, stats: bool) -> Self {
+		self.stats = stats;
+		self
+	}
+
 	pub async fn announce(&mut self, tracks: TracksReader) -> Result<(), SessionError> {
 		self.remote.announce(tracks).await
 	}
 
+	/// Announce with the signature provided by the original publisher.
+	pub async fn announce_signed(
+		&mut self,
+		tracks: TracksReader,
+		signature: AnnounceSignature,
+	) -> Result<(), SessionError> {
+		self.remote.announce_signed(tracks, signature).await
+	}
+
 	pub async fn run(mut self) -> Result<(), SessionError> {
 		let mut tasks = FuturesUnordered::new();
 
+		// Shares the same queues, so fetches can be accepted alongside subscriptions.
+		let mut fetches = self.remote.clone();
+
 		loop {
 			tokio::select! {
 				Some(subscribe) = self.remote.subscribed() => {
@@ -41,7 +137,19 @@ impl Producer {
 						if let Err(err) = this.serve(subscribe).await {
 							log::warn!("failed serving subscribe: {:?}, error: {}", info, err)
 						}
-					})
+					}.boxed())
+				},
+				Some(fetch) = fetches.fetched() => {
+					let this = self.clone();
+
+					tasks.push(async move {
+						let info = fetch.clone();
+						log::info!("serving fetch: {:?} start={} end={:?}", info, fetch.start_group(), fetch.end_group());
+
+						if let Err(err) = this.serve_fetch(fetch).await {
+							log::warn!("failed serving fetch: {:?}, error: {}", info, err)
+						}
+					}.boxed())
 				},
 				_= tasks.next(), if !tasks.is_empty() => {},
 				else => return Ok(()),
@@ -50,24 +158,329 @@ impl Producer {
 	}
 
 	async fn serve(self, subscribe: Subscribed) -> Result<(), anyhow::Error> {
-		if let Some(mut local) = self.locals.route(&subscribe.namespace) {
-			if let Some(track) = local.subscribe(&subscribe.name) {
-				log::info!("serving from local: {:?}", track.info);
-				return Ok(subscribe.serve(track).await?);
+		let mut subscribe = subscribe.with_skip(self.skip);
+
+		// Reject alternate encodings of the same track, so they can't bypass the cache or policy.
+		// The canonical names are only used as keys; the publisher is asked for the names as requested.
+		let canonical = canonical_namespace(&subscribe.namespace)
+			.and_then(|namespace| Ok((namespace, canonical_track(&subscribe.name)?)));
+
+		let (namespace, name) = match canonical {
+			Ok(canonical) => canonical,
+			Err(err) => {
+				log::info!("rejected subscribe: {:?}, error: {}", subscribe.info, err);
+				subscribe.close(err)?;
+				return Ok(());
+			}
+		};
+
+		let decision = self
+			.policy
+			.check(Request::Subscribe {
+				namespace: namespace.clone(),
+				name: name.clone(),
+				token: subscribe.authorization(),
+			})
+			.await;
+
+		let expires = decision.expires();
+		let requested = namespace.clone();
+
+		let namespace = match decision.resolve(&namespace) {
+			Ok(namespace) => namespace,
+			Err(err) => {
+				log::info!("rejected subscribe: {:?}, error: {}", subscribe.info, err);
+				subscribe.close(err)?;
+				return Ok(());
+			}
+		};
+
+		if let Some(expires) = expires {
+			let renewal = subscribe.renewal();
+			tokio::spawn(self.clone().renew(renewal, requested.clone(), name.clone(), expires));
+		}
+
+		// Held until the subscription is done, so the next one in the waiting room can be admitted.
+		let mut slot = None;
+
+		if let Some(capacity) = &self.capacity {
+			// The relay's own tracks aren't counted, so subscribers can always see their place in line.
+			if name == WAITING_TRACK {
+				return serve_waiting(subscribe, capacity.clone(), namespace, self.session).await;
+			}
+
+			// Key requests are answered by the publisher, but are also needed before the media can be played.
+			let key_request = TrackName::parse(&name).path == moq_native::crypto::KEY_TRACK;
+
+			if name != STATUS_TRACK && !name.starts_with(STATS_PREFIX) && !key_request {
+				match self.admit(capacity, subscribe, &namespace, &name).await? {
+					Some((admitted, held)) => {
+						subscribe = admitted;
+						slot = Some(held);
+					}
+					None => return Ok(()),
+				}
+			}
+		}
+
+		#[cfg(feature = "archive")]
+		if let (Some(archive), Some(start)) = (&self.archive, subscribe.start_group()) {
+			if let Some(manifest) = archive.manifest(&namespace, &name).await? {
+				let live = self
+					.locals
+					.route(&namespace)
+					.and_then(|mut local| local.tracks.subscribe_keyed(&name, &subscribe.name));
+				let end = subscribe.end_group();
+				log::info!(
+					"serving from archive: {:?} start={} end={:?}",
+					subscribe.info,
+					start,
+					end
+				);
+
+				return archive.replay(subscribe, manifest, start, end, live).await;
+			}
+		}
+
+		if let Some(mut local) = self.locals.route(&namespace) {
+			// The relay serves the status itself, rather than asking the publisher.
+			if name == STATUS_TRACK {
+				let usage = local.tracks.usage.clone();
+				local.tasks.spawn(async move {
+					let info = subscribe.clone();
+					if let Err(err) = serve_status(subscribe, namespace, usage).await {
+						log::warn!("failed serving status: {:?}, error: {}", info, err)
+					}
+
+					Ok(())
+				})?;
+
+				return Ok(());
+			}
+
+			if let Some(track) = name.strip_prefix(STATS_PREFIX).filter(|_| self.stats) {
+				if let Some(track) = local.tracks.subscribe(track) {
+					log::info!("serving stats from local: {:?}", track.info);
+
+					local.tasks.spawn(async move {
+						let info = subscribe.clone();
+						if let Err(err) = serve_stats(subscribe, track).await {
+							log::warn!("failed serving stats: {:?}, error: {}", info, err)
+						}
+
+						Ok(())
+					})?;
+
+					return Ok(());
+				}
+			}
+
+			if let Some(track) = local.tracks.subscribe_keyed(&name, &subscribe.name) {
+				log::info!(
+					"serving from local: {:?} broadcast_id={:?}",
+					track.info,
+					local.tracks.broadcast_id
+				);
+
+				let counted = self
+					.replicator
+					.as_ref()
+					.map(|replicator| replicator.subscriber(&namespace));
+
+				// Run as part of the namespace, so it's aborted when the namespace goes away.
+				let this = self.clone();
+				local.tasks.spawn(async move {
+					let info = subscribe.clone();
+					if let Err(err) = this.serve_track(subscribe, track).await {
+						log::warn!("failed serving subscribe: {:?}, error: {}", info, err)
+					}
+
+					drop(slot);
+					drop(counted);
+					Ok(())
+				})?;
+
+				return Ok(());
 			}
 		}
 
 		if let Some(remotes) = &self.remotes {
-			if let Some(remote) = remotes.route(&subscribe.namespace).await? {
-				if let Some(track) = remote.subscribe(subscribe.namespace.clone(), subscribe.name.clone())? {
+			if let Some(remote) = remotes.route(&namespace).await? {
+				// Unless the policy rewrote it, ask for the namespace as requested too.
+				let upstream = match namespace == requested {
+					true => subscribe.namespace.clone(),
+					false => namespace.clone(),
+				};
+
+				if let Some(track) = remote.subscribe_keyed((namespace, name), upstream, subscribe.name.clone())? {
 					log::info!("serving from remote: {:?} {:?}", remote.info, track.info);
 
 					// NOTE: Depends on drop(track) being called afterwards
-					return Ok(subscribe.serve(track.reader).await?);
+					return self.serve_track(subscribe, track.reader).await;
 				}
 			}
 		}
 
 		Err(ServeError::NotFound.into())
 	}
+
+	// Answer a fetch from the local cache, so a late joiner can backfill without waiting for the next group.
+	async fn serve_fetch(self, fetch: Fetched) -> Result<(), anyhow::Error> {
+		let canonical =
+			canonical_namespace(&fetch.namespace).and_then(|namespace| Ok((namespace, canonical_track(&fetch.name)?)));
+
+		let (namespace, name) = match canonical {
+			Ok(canonical) => canonical,
+			Err(err) => {
+				log::info!("rejected fetch: {:?}, error: {}", fetch.info, err);
+				fetch.close(err)?;
+				return Ok(());
+			}
+		};
+
+		// Checked the same as a subscribe, but there's nothing to renew once the cached groups are sent.
+		let decision = self
+			.policy
+			.check(Request::Subscribe {
+				namespace: namespace.clone(),
+				name: name.clone(),
+				token: fetch.authorization(),
+			})
+			.await;
+
+		let namespace = match decision.resolve(&namespace) {
+			Ok(namespace) => namespace,
+			Err(err) => {
+				log::info!("rejected fetch: {:?}, error: {}", fetch.info, err);
+				fetch.close(err)?;
+				return Ok(());
+			}
+		};
+
+		// Remote origins aren't cached by this relay, so there's nothing to fetch from them.
+		let track = self
+			.locals
+			.route(&namespace)
+			.and_then(|mut local| local.tracks.subscribe_keyed(&name, &fetch.name));
+
+		match track {
+			Some(track) => Ok(fetch.serve(track).await?),
+			None => Ok(fetch.close(ServeError::NotFound)?),
+		}
+	}
+
+	// Wait for a slot in the namespace, returning None if the subscription was rejected or cancelled while waiting.
+	async fn admit(
+		&self,
+		capacity: &Capacity,
+		mut subscribe: Subscribed,
+		namespace: &str,
+		name: &str,
+	) -> Result<Option<(Subscribed, CapacitySlot)>, ServeError> {
+		let ticket = match capacity.admit(namespace, self.session, name) {
+			Ok(Admission::Admitted(slot)) => return Ok(Some((subscribe, slot))),
+			Ok(Admission::Waiting(ticket)) => ticket,
+			Err(err) => {
+				log::info!("rejected subscribe: {:?}, error: {}", subscribe.info, err);
+				subscribe.close(err)?;
+				return Ok(None);
+			}
+		};
+
+		log::info!(
+			"waiting for capacity: {:?} position={:?}",
+			subscribe.info,
+			ticket.position()
+		);
+
+		// Accept now so the subscriber doesn't time out, but don't deliver anything until admitted.
+		subscribe.accept();
+
+		tokio::select! {
+			slot = ticket.admitted() => {
+				log::info!("admitted from waiting room: {:?}", subscribe.info);
+				Ok(Some((subscribe, slot)))
+			},
+			res = subscribe.closed() => {
+				log::info!("left waiting room: {:?}, error: {:?}", subscribe.info, res.err());
+				Ok(None)
+			},
+		}
+	}
+
+	// Ask the subscriber for a new token each time the current one expires, closing the subscription if it's not renewed.
+	// The new token is checked against the policy again, but the subscription can't be moved to another namespace.
+	async fn renew(self, mut renewal: SubscribedRenewal, namespace: String, name: String, mut expires: Duration) {
+		loop {
+			tokio::select! {
+				_ = tokio::time::sleep(expires) => {},
+				_ = renewal.closed() => return,
+			}
+
+			let token = match tokio::time::timeout(RENEW_TIMEOUT, renewal.renew(RENEW_TIMEOUT)).await {
+				Ok(Ok(token)) => token,
+				Ok(Err(ServeError::Unsupported(_))) | Err(_) => {
+					log::info!("subscription token expired: namespace={} name={}", namespace, name);
+					renewal
+						.close(ServeError::Rejected(401, "token expired".to_string()))
+						.ok();
+					return;
+				}
+				// The subscription was closed while waiting.
+				Ok(Err(_)) => return,
+			};
+
+			let decision = self
+				.policy
+				.check(Request::Subscribe {
+					namespace: namespace.clone(),
+					name: name.clone(),
+					token: Some(token),
+				})
+				.await;
+
+			if let Decision::Reject { code, reason } = decision {
+				log::info!(
+					"rejected renewed token: namespace={} name={} reason={}",
+					namespace,
+					name,
+					reason
+				);
+				renewal.close(ServeError::Rejected(code, reason)).ok();
+				return;
+			}
+
+			log::debug!("renewed subscription token: namespace={} name={}", namespace, name);
+
+			expires = match decision.expires() {
+				Some(expires) => expires,
+				None => return,
+			};
+		}
+	}
+
+	// Serve the track as-is, unless ads are spliced into it or it's watermarked.
+	async fn serve_track(&self, subscribe: Subscribed, track: TrackReader) -> anyhow::Result<()> {
+		if let Some(splicer) = self.splicer.as_ref().filter(|splicer| splicer.selected(&track.name)) {
+			return splicer.serve(subscribe, &self.locals, track).await;
+		}
+
+		#[cfg(feature = "watermark")]
+		if let Some(watermark) = self
+			.watermark
+			.as_ref()
+			.filter(|watermark| watermark.selected(&track.name))
+		{
+			return watermark.serve(subscribe, self.session, track).await;
+		}
+
+		// Kept to report what was sent, since serving consumes the subscription.
+		let renewal = subscribe.renewal();
+		let info = subscribe.info.clone();
+
+		let res = subscribe.serve(track).await;
+		log::debug!("served subscribe: {:?} {:?}", info, renewal.stats());
+
+		Ok(res?)
+	}
 }
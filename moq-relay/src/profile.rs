@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use axum::{
+	extract::Query,
+	http::{header, StatusCode},
+	response::{IntoResponse, Response},
+	routing::get,
+	Router,
+};
+use pprof::protos::Message;
+use serde::Deserialize;
+
+// Skip frames from the signal handler and the unwinder itself.
+const BLOCKLIST: &[&str] = &["libc", "libgcc", "pthread", "vdso"];
+
+// The longest profile we'll capture, so a typo doesn't tie up the profiler.
+const MAX_SECONDS: u64 = 300;
+
+#[derive(Deserialize)]
+struct ProfileParams {
+	/// How long to sample for, defaulting to 30 seconds like Go's pprof.
+	seconds: Option<u64>,
+
+	/// Samples per second.
+	frequency: Option<i32>,
+}
+
+/// Routes to capture CPU profiles, for `go tool pprof` or as a flamegraph.
+///
+/// Only one profile can be captured at a time; concurrent requests fail with a 409.
+pub fn profile_routes<S: Clone + Send + Sync + 'static>() -> Router<S> {
+	Router::new()
+		.route("/debug/pprof/profile", get(serve_profile))
+		.route("/debug/pprof/flamegraph", get(serve_flamegraph))
+}
+
+// Returns an uncompressed pprof protobuf, which `go tool pprof` accepts as-is.
+async fn serve_profile(Query(params): Query<ProfileParams>) -> Response {
+	let report = match capture(params).await {
+		Ok(report) => report,
+		Err(err) => return err.into_response(),
+	};
+
+	let profile = match report.pprof() {
+		Ok(profile) => profile,
+		Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+	};
+
+	let mut body = Vec::new();
+	if let Err(err) = profile.encode(&mut body) {
+		return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+	}
+
+	(
+		[
+			(header::CONTENT_TYPE, "application/octet-stream"),
+			(header::CONTENT_DISPOSITION, "attachment; filename=\"profile.pb\""),
+		],
+		body,
+	)
+		.into_response()
+}
+
+async fn serve_flamegraph(Query(params): Query<ProfileParams>) -> Response {
+	let report = match capture(params).await {
+		Ok(report) => report,
+		Err(err) => return err.into_response(),
+	};
+
+	let mut body = Vec::new();
+	if let Err(err) = report.flamegraph(&mut body) {
+		return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+	}
+
+	([(header::CONTENT_TYPE, "image/svg+xml")], body).into_response()
+}
+
+async fn capture(params: ProfileParams) -> Result<pprof::Report, (StatusCode, String)> {
+	let seconds = params.seconds.unwrap_or(30);
+	if seconds == 0 || seconds > MAX_SECONDS {
+		return Err((
+			StatusCode::BAD_REQUEST,
+			format!("seconds must be between 1 and {}", MAX_SECONDS),
+		));
+	}
+
+	let frequency = params.frequency.unwrap_or(99);
+	if !(1..=1000).contains(&frequency) {
+		return Err((
+			StatusCode::BAD_REQUEST,
+			"frequency must be between 1 and 1000".to_string(),
+		));
+	}
+
+	let guard = pprof::ProfilerGuardBuilder::default()
+		.frequency(frequency)
+		.blocklist(BLOCKLIST)
+		.build()
+		.map_err(|err| (StatusCode::CONFLICT, err.to_string()))?;
+
+	log::info!("capturing cpu profile: seconds={} frequency={}", seconds, frequency);
+	tokio::time::sleep(Duration::from_secs(seconds)).await;
+
+	guard
+		.report()
+		.build()
+		.map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
@@ -1,17 +1,31 @@
-use std::net;
+use std::{net, sync::Arc, time::Duration};
 
 use anyhow::Context;
 
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
-use moq_native::quic;
+use moq_native::{quic, tcp};
+use moq_transport::{
+	serve::{GroupSkip, ServeError, TrackCache, TracksWriter},
+	session::{SessionError, SetupOptions, SubscribeIds},
+	setup, transport,
+};
 use url::Url;
 
-use crate::{Api, Consumer, Locals, Producer, Remotes, RemotesConsumer, RemotesProducer, Session};
+use crate::{
+	canonical_namespace, AcceptAll, Api, Capacity, Claims, Consumer, GoAway, Locals, Policy, Producer, Remotes,
+	RemotesConsumer, RemotesProducer, Replicator, Request, Session, Sessions, Splicer,
+};
 
 pub struct RelayConfig {
 	/// Listen on this address
 	pub bind: net::SocketAddr,
 
+	/// Use this UDP socket instead of binding to `bind`, ex. one inherited from the service manager.
+	pub socket: Option<net::UdpSocket>,
+
+	/// Also listen for MoQ over TCP+TLS on this address, for clients that can't use UDP.
+	pub tcp_bind: Option<net::SocketAddr>,
+
 	/// The TLS configuration.
 	pub tls: moq_native::tls::Config,
 
@@ -24,23 +38,119 @@ pub struct RelayConfig {
 	/// Our hostname which we advertise to other origins.
 	/// We use QUIC, so the certificate must be valid for this address.
 	pub node: Option<Url>,
+
+	/// Limit the bytes retained by each namespace, rejecting new groups while over the limit.
+	pub max_bytes: Option<u64>,
+
+	/// Retain older groups of each track, ex. for FETCH and late joiners.
+	pub cache: TrackCache,
+
+	/// Spill the retained groups to disk instead of keeping them in memory.
+	#[cfg(feature = "disk")]
+	pub spill: Option<Arc<moq_transport::serve::Spill>>,
+
+	/// Serve `_stats/<track>` for each local track.
+	pub stats: bool,
+
+	/// What to do when a subscriber falls behind.
+	pub skip: GroupSkip,
+
+	/// How to assign subscribe IDs when subscribing to publishers and other origins.
+	pub subscribe_ids: SubscribeIds,
+
+	/// Send and request the catalog and init tracks reliably at the highest priority, ahead of media.
+	pub bootstrap_priority: bool,
+
+	/// Splice ads into the selected tracks during ad breaks.
+	pub splice: Option<Splicer>,
+
+	/// Reject announces that aren't signed by the key claiming the namespace.
+	pub claims: Option<Claims>,
+
+	/// Limit the subscriptions to each namespace, optionally with a waiting room.
+	pub capacity: Option<Capacity>,
+
+	/// Replicate hot namespaces onto peer relays, instead of waiting for them to be fetched on demand.
+	pub replicator: Option<Replicator>,
+
+	/// Serve namespaces replicated by another relay without registering as their origin.
+	pub accept_replicas: bool,
+
+	/// The MoQ draft versions to offer and accept, preferring the highest one in common with the peer.
+	pub versions: setup::Versions,
+
+	/// Identify this relay to clients in SETUP, so they can be redirected back to it.
+	pub sticky: Option<setup::StickyToken>,
+
+	/// How long to keep serving each session after GOAWAY, before closing it with GOAWAY_TIMEOUT.
+	pub drain_timeout: Duration,
+
+	/// Ping each session this often, closing it with [moq_transport::session::SessionError::Timeout] once it's silent.
+	pub keepalive: Option<Duration>,
+
+	/// Upload completed groups for every announced broadcast.
+	#[cfg(feature = "archive")]
+	pub archive: Option<crate::Archive>,
+
+	/// Randomly impair what every session sends, for testing.
+	#[cfg(feature = "chaos")]
+	pub chaos: Option<moq_transport::session::Chaos>,
+
+	/// Transform the payloads of the selected tracks for each subscriber, ex. to embed a forensic watermark.
+	/// Only applied to accepted sessions, not the --announce connection.
+	#[cfg(feature = "watermark")]
+	pub watermark: Option<crate::Watermarker>,
+
+	/// Inspect every announce and subscribe, accepting everything if not provided.
+	pub policy: Option<Arc<dyn Policy>>,
 }
 
 pub struct Relay {
 	quic: quic::Endpoint,
+	tcp: Option<(net::SocketAddr, moq_native::tls::Config)>,
 	announce: Option<Url>,
 	locals: Locals,
 	api: Option<Api>,
 	remotes: Option<(RemotesProducer, RemotesConsumer)>,
+	policy: Arc<dyn Policy>,
+	stats: bool,
+	skip: GroupSkip,
+	subscribe_ids: SubscribeIds,
+	bootstrap_priority: bool,
+	splice: Option<Splicer>,
+	claims: Option<Claims>,
+	capacity: Option<Capacity>,
+	replicator: Option<Replicator>,
+	accept_replicas: bool,
+	versions: setup::Versions,
+	sticky: Option<setup::StickyToken>,
+	drain_timeout: Duration,
+	keepalive: Option<Duration>,
+	go_away: GoAway,
+	sessions: Sessions,
+	#[cfg(feature = "archive")]
+	archive: Option<crate::Archive>,
+	#[cfg(feature = "chaos")]
+	chaos: Option<moq_transport::session::Chaos>,
+	#[cfg(feature = "watermark")]
+	watermark: Option<crate::Watermarker>,
 }
 
 impl Relay {
 	// Create a QUIC endpoint that can be used for both clients and servers.
 	pub fn new(config: RelayConfig) -> anyhow::Result<Self> {
-		let quic = quic::Endpoint::new(quic::Config {
+		let tcp = config.tcp_bind.map(|bind| (bind, config.tls.clone()));
+
+		let quic = quic::Config {
 			bind: config.bind,
 			tls: config.tls,
-		})?;
+			congestion: Default::default(),
+		};
+
+		let quic = match config.socket {
+			Some(socket) => quic::Endpoint::with_udp_socket(quic, socket)?,
+			None => quic::Endpoint::new(quic)?,
+		};
 
 		let api = if let (Some(url), Some(node)) = (config.api, config.node) {
 			log::info!("using moq-api: url={} node={}", url, node);
@@ -49,25 +159,119 @@ impl Relay {
 			None
 		};
 
-		let locals = Locals::new();
+		let locals = Locals::new().with_max_bytes(config.max_bytes).with_cache(config.cache);
+		#[cfg(feature = "disk")]
+		let locals = locals.with_spill(config.spill);
 
 		let remotes = api.clone().map(|api| {
 			Remotes {
 				api,
 				quic: quic.client.clone(),
+				subscribe_ids: config.subscribe_ids,
+				bootstrap_priority: config.bootstrap_priority,
 			}
 			.produce()
 		});
 
 		Ok(Self {
 			quic,
+			tcp,
 			announce: config.announce,
 			api,
 			locals,
 			remotes,
+			policy: config.policy.unwrap_or_else(|| Arc::new(AcceptAll)),
+			stats: config.stats,
+			skip: config.skip,
+			subscribe_ids: config.subscribe_ids,
+			bootstrap_priority: config.bootstrap_priority,
+			splice: config.splice,
+			claims: config.claims,
+			capacity: config.capacity,
+			replicator: config.replicator,
+			accept_replicas: config.accept_replicas,
+			versions: config.versions,
+			sticky: config.sticky.clone(),
+			drain_timeout: config.drain_timeout,
+			keepalive: config.keepalive,
+			go_away: GoAway::new(config.sticky),
+			sessions: Sessions::new(),
+			#[cfg(feature = "archive")]
+			archive: config.archive,
+			#[cfg(feature = "chaos")]
+			chaos: config.chaos,
+			#[cfg(feature = "watermark")]
+			watermark: config.watermark,
 		})
 	}
 
+	/// The namespaces announced to this relay.
+	pub fn locals(&self) -> Locals {
+		self.locals.clone()
+	}
+
+	/// A handle used to redirect every session with GOAWAY, ex. before shutting down.
+	pub fn go_away(&self) -> GoAway {
+		self.go_away.clone()
+	}
+
+	/// The sessions accepted by the relay, used to list or close them.
+	pub fn sessions(&self) -> Sessions {
+		self.sessions.clone()
+	}
+
+	/// Publish a broadcast from within the process, as if it was announced by a session.
+	///
+	/// Tracks created with the returned writer are served to subscribers, and any other track is not found.
+	/// The namespace is unregistered once the writer is dropped.
+	/// Unlike announces, these broadcasts are not checked against the policy or forwarded with --announce.
+	pub async fn publish(&self, namespace: &str) -> anyhow::Result<TracksWriter> {
+		let namespace = canonical_namespace(namespace)?;
+		let (writer, request, reader) = self
+			.locals
+			.new_tracks(namespace)
+			.with_broadcast_id(moq_native::broadcast::generate_id())
+			.produce();
+
+		// Reject requests for unknown tracks immediately.
+		drop(request);
+
+		let mut locals = self.locals.clone();
+		let mut registration = locals.register(reader.clone()).await?;
+		let tasks = registration.tasks();
+
+		if let Some(api) = self.api.as_ref() {
+			let mut refresh = api.set_origin(reader.namespace.clone()).await?;
+			tasks.spawn(async move { refresh.run().await.context("failed refreshing origin") })?;
+		}
+
+		#[cfg(feature = "archive")]
+		if let Some(archive) = self.archive.clone() {
+			let reader = reader.clone();
+			tasks.spawn(async move {
+				if let Err(err) = archive.broadcast(reader).await {
+					log::warn!("failed archiving broadcast: {}", err);
+				}
+				Ok(())
+			})?;
+		}
+
+		log::info!(
+			"publishing local broadcast: {} broadcast_id={:?}",
+			reader.namespace,
+			reader.broadcast_id
+		);
+
+		tokio::spawn(async move {
+			tokio::select! {
+				_ = reader.closed() => log::info!("local broadcast ended: {}", reader.namespace),
+				Err(err) = registration.run() => log::warn!("local broadcast failed: {}, error: {}", reader.namespace, err),
+			}
+		});
+
+		Ok(writer)
+	}
+
 	pub async fn run(self) -> anyhow::Result<()> {
 		let mut tasks = FuturesUnordered::new();
 
@@ -76,6 +280,15 @@ impl Relay {
 			consumer
 		});
 
+		if let Some(replicator) = self.replicator.clone() {
+			let replicate = replicator.run(self.locals.clone(), self.quic.client.clone(), self.versions.clone());
+			tasks.push(async move { replicate.await.context("replication failed") }.boxed());
+		}
+
+		if let Some(api) = self.api.clone() {
+			tasks.push(api.report_load(self.sessions.clone()).map(Ok).boxed());
+		}
+
 		let forward = if let Some(url) = &self.announce {
 			log::info!("forwarding announces to {}", url);
 			let session = self
@@ -84,15 +297,44 @@ impl Relay {
 				.connect(url)
 				.await
 				.context("failed to establish forward connection")?;
-			let (session, publisher, subscriber) = moq_transport::session::Session::connect(session)
-				.await
-				.context("failed to establish forward session")?;
+			let (session, publisher, subscriber) =
+				moq_transport::session::Session::connect_versions(session, setup::Role::Both, self.versions.clone())
+					.await
+					.context("failed to establish forward session")?;
+			log::info!("forward session using {}", session.version());
+
+			// Redirected sessions should land near the relay with the broadcasts warm.
+			self.go_away.set_upstream(session.sticky().cloned());
+
+			let publisher = publisher
+				.context("forward session missing publisher")?
+				.with_bootstrap_priority(self.bootstrap_priority);
+			let subscriber = subscriber
+				.context("forward session missing subscriber")?
+				.with_ids(self.subscribe_ids)
+				.with_bootstrap_priority(self.bootstrap_priority);
+
+			#[cfg(feature = "chaos")]
+			let session = match self.chaos.clone() {
+				Some(chaos) => session.with_chaos(chaos),
+				None => session,
+			};
 
 			// Create a normal looking session, except we never forward or register announces.
 			let session = Session {
 				session,
-				producer: Some(Producer::new(publisher, self.locals.clone(), remotes.clone())),
-				consumer: Some(Consumer::new(subscriber, self.locals.clone(), None, None)),
+				producer: Some(
+					Producer::new(publisher, self.locals.clone(), remotes.clone(), self.policy.clone())
+						.with_stats(self.stats)
+						.with_skip(self.skip)
+						.with_splicer(self.splice.clone())
+						.with_capacity(self.capacity.clone())
+						.with_replicator(self.replicator.clone()),
+				),
+				consumer: Some(
+					Consumer::new(subscriber, self.locals.clone(), None, None, self.policy.clone())
+						.with_claims(self.claims.clone()),
+				),
 			};
 
 			let forward = session.producer.clone();
@@ -107,40 +349,169 @@ impl Relay {
 		let mut server = self.quic.server.context("missing TLS certificate")?;
 		log::info!("listening on {}", server.local_addr()?);
 
+		let mut tcp = match self.tcp {
+			Some((bind, tls)) => {
+				let tcp = tcp::Server::bind(bind, tls).await?;
+				log::info!("listening on tcp {}", tcp.local_addr()?);
+				Some(tcp)
+			}
+			None => None,
+		};
+
 		loop {
-			tokio::select! {
-				res = server.accept() => {
-					let conn = res.context("failed to accept QUIC connection")?;
-
-					let locals = self.locals.clone();
-					let remotes = remotes.clone();
-					let forward = forward.clone();
-					let api = self.api.clone();
-
-					tasks.push(async move {
-						let (session, publisher, subscriber) = match moq_transport::session::Session::accept(conn).await {
-							Ok(session) => session,
-							Err(err) => {
-								log::warn!("failed to accept MoQ session: {}", err);
-								return Ok(());
-							}
-						};
+			let conn = tokio::select! {
+				res = server.accept() => transport::Session::from(res.context("failed to accept QUIC connection")?),
+				Some(conn) = async { tcp.as_mut()?.accept().await } => conn,
+				res = tasks.next(), if !tasks.is_empty() => {
+					res.unwrap()?;
+					continue;
+				}
+			};
+
+			let locals = self.locals.clone();
+			let remotes = remotes.clone();
+			let forward = forward.clone();
+			let api = self.api.clone();
+			let policy = self.policy.clone();
+			let stats = self.stats;
+			let skip = self.skip;
+			let bootstrap_priority = self.bootstrap_priority;
+			let subscribe_ids = self.subscribe_ids;
+			let splice = self.splice.clone();
+			let claims = self.claims.clone();
+			let capacity = self.capacity.clone();
+			let replicator = self.replicator.clone();
+			let accept_replicas = self.accept_replicas;
+			let versions = self.versions.clone();
+			let sticky = self.sticky.clone();
+			let go_away = self.go_away.clone();
+			let sessions = self.sessions.clone();
+			let drain_timeout = self.drain_timeout;
+			let keepalive = self.keepalive;
+			#[cfg(feature = "archive")]
+			let archive = self.archive.clone();
+			#[cfg(feature = "chaos")]
+			let chaos = self.chaos.clone();
+			#[cfg(feature = "watermark")]
+			let watermark = self.watermark.clone();
 
-						let session = Session {
-							session,
-							producer: publisher.map(|publisher| Producer::new(publisher, locals.clone(), remotes)),
-							consumer: subscriber.map(|subscriber| Consumer::new(subscriber, locals, api, forward)),
-						};
+			tasks.push(
+				async move {
+					let mut options = SetupOptions::new(setup::Role::Both)
+						.with_versions(versions)
+						.with_authorize({
+							let policy = policy.clone();
+							move |token| authorize_session(policy.clone(), token)
+						});
+					options.sticky = sticky;
 
-						if let Err(err) = session.run().await {
-							log::warn!("failed to run MoQ session: {}", err);
+					// Kept so the session can be closed from the admin API.
+					let transport = conn.clone();
+
+					let accept = moq_transport::session::Session::accept_with(conn, options);
+					let (session, publisher, subscriber) = match accept.await {
+						Ok(session) => session,
+						Err(err) => {
+							log::warn!("failed to accept MoQ session: {}", err);
+							return Ok(());
 						}
+					};
+					log::debug!(
+						"accepted MoQ session using {} sticky={:?} path={:?}",
+						session.version(),
+						session.sticky(),
+						session.path()
+					);
 
-						Ok(())
-					}.boxed());
-				},
-				res = tasks.next(), if !tasks.is_empty() => res.unwrap()?,
-			}
+					let session = session.with_drain_timeout(drain_timeout);
+					let session = match keepalive {
+						Some(interval) => session.with_keepalive(interval),
+						None => session,
+					};
+
+					#[cfg(feature = "chaos")]
+					let session = match chaos {
+						Some(chaos) => session.with_chaos(chaos),
+						None => session,
+					};
+
+					let session = Session {
+						session,
+						producer: publisher.map(|publisher| {
+							let publisher = publisher.with_bootstrap_priority(bootstrap_priority);
+							let producer = Producer::new(publisher, locals.clone(), remotes, policy.clone())
+								.with_stats(stats)
+								.with_skip(skip)
+								.with_splicer(splice)
+								.with_capacity(capacity)
+								.with_replicator(replicator);
+							#[cfg(feature = "archive")]
+							let producer = producer.with_archive(archive.clone());
+							#[cfg(feature = "watermark")]
+							let producer = producer.with_watermark(watermark);
+							producer
+						}),
+						consumer: subscriber.map(|subscriber| {
+							let subscriber = subscriber
+								.with_ids(subscribe_ids)
+								.with_bootstrap_priority(bootstrap_priority);
+							let consumer = Consumer::new(subscriber, locals, api, forward, policy)
+								.with_claims(claims)
+								.with_replicas(accept_replicas);
+							#[cfg(feature = "archive")]
+							let consumer = consumer.with_archive(archive);
+							consumer
+						}),
+					};
+
+					let registration = sessions.register(&session, transport);
+					log::debug!("registered MoQ session: id={}", registration.id());
+
+					// Keep serving after a GOAWAY, until the client leaves or the drain timeout is over.
+					let mut drain = session.session.drain();
+					let mut redirect = go_away.subscribe();
+
+					// Sessions accepted after the relay started draining are redirected immediately.
+					redirect.mark_changed();
+
+					let run = session.run();
+					tokio::pin!(run);
+
+					loop {
+						tokio::select! {
+							res = &mut run => {
+								match res {
+									Ok(()) => {}
+									Err(SessionError::GoAway) => log::info!("closed MoQ session after GOAWAY"),
+									Err(err) => {
+										log::warn!("failed to run MoQ session: {}", err);
+										registration.failed(&err);
+									}
+								}
+
+								return Ok(());
+							}
+							Ok(()) = redirect.changed() => {
+								if let Some(url) = redirect.borrow_and_update().clone() {
+									drain.go_away(url.to_string());
+								}
+							}
+						}
+					}
+				}
+				.boxed(),
+			);
 		}
 	}
 }
+
+// Check the token sent in SETUP, if any, before accepting the session.
+async fn authorize_session(policy: Arc<dyn Policy>, token: Option<setup::AuthToken>) -> Result<(), ServeError> {
+	let token = match token {
+		Some(token) => token,
+		None => return Ok(()),
+	};
+
+	policy.check(Request::Session { token: token.0 }).await.resolve("")?;
+	Ok(())
+}
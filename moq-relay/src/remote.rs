@@ -11,6 +11,7 @@ use futures::FutureExt;
 use futures::StreamExt;
 use moq_native::quic;
 use moq_transport::serve::{Track, TrackReader, TrackWriter};
+use moq_transport::session::SubscribeIds;
 use moq_transport::watch::State;
 use url::Url;
 
@@ -22,6 +23,12 @@ pub struct Remotes {
 
 	// A QUIC endpoint we'll use to fetch from other origins.
 	pub quic: quic::Client,
+
+	/// How to assign subscribe IDs when fetching from other origins.
+	pub subscribe_ids: SubscribeIds,
+
+	/// Request the catalog and init tracks at the highest priority from other origins.
+	pub bootstrap_priority: bool,
 }
 
 impl Remotes {
@@ -210,6 +217,9 @@ impl RemoteProducer {
 		// TODO reuse QUIC and MoQ sessions
 		let session = self.quic.connect(&self.url).await?;
 		let (session, subscriber) = moq_transport::session::Subscriber::connect(session).await?;
+		let subscriber = subscriber
+			.with_ids(self.subscribe_ids)
+			.with_bootstrap_priority(self.bootstrap_priority);
 
 		// Run the session
 		let mut session = session.run().boxed();
@@ -286,7 +296,17 @@ impl RemoteConsumer {
 
 	/// Request a track from the broadcast.
 	pub fn subscribe(&self, namespace: String, name: String) -> anyhow::Result<Option<RemoteTrackReader>> {
-		let key = (namespace.clone(), name.clone());
+		self.subscribe_keyed((namespace.clone(), name.clone()), namespace, name)
+	}
+
+	/// Like [Self::subscribe], but deduplicated by `key` instead, ex. the canonical namespace and name.
+	/// A new track is requested from the remote with `namespace` and `name`.
+	pub fn subscribe_keyed(
+		&self,
+		key: (String, String),
+		namespace: String,
+		name: String,
+	) -> anyhow::Result<Option<RemoteTrackReader>> {
 		let state = self.state.lock();
 		if let Some(track) = state.tracks.get(&key) {
 			if let Some(track) = track.upgrade() {
@@ -300,7 +320,7 @@ impl RemoteConsumer {
 		};
 
 		let (writer, reader) = Track::new(namespace, name).produce();
-		let reader = RemoteTrackReader::new(reader, self.state.clone());
+		let reader = RemoteTrackReader::new(reader, key.clone(), self.state.clone());
 
 		// Insert the track into our Map so we deduplicate future requests.
 		state.tracks.insert(key, reader.downgrade());
@@ -325,11 +345,8 @@ pub struct RemoteTrackReader {
 }
 
 impl RemoteTrackReader {
-	fn new(reader: TrackReader, parent: State<RemoteState>) -> Self {
-		let drop = Arc::new(RemoteTrackDrop {
-			parent,
-			key: (reader.namespace.clone(), reader.name.clone()),
-		});
+	fn new(reader: TrackReader, key: (String, String), parent: State<RemoteState>) -> Self {
+		let drop = Arc::new(RemoteTrackDrop { parent, key });
 
 		Self { reader, drop }
 	}
@@ -0,0 +1,366 @@
+use std::{
+	collections::{hash_map::DefaultHasher, HashMap},
+	hash::{Hash, Hasher},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use anyhow::Context;
+use clap::Parser;
+use moq_native::quic;
+use moq_transport::{serve::TracksReader, session::Session, setup};
+use tokio::{task::JoinHandle, time::Instant};
+use url::Url;
+
+use crate::Locals;
+
+// How often subscriber counts are checked, which also limits how often a failed replica is retried.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Proactively push hot namespaces to peer relays, instead of waiting for their subscribers to fetch them on demand.
+#[derive(Parser, Clone, Default)]
+#[group(id = "replicate")]
+pub struct ReplicateArgs {
+	/// Replicate hot namespaces onto this peer relay, which must be run with --accept-replicas. Repeatable.
+	#[arg(long = "replicate-peer")]
+	pub peers: Vec<Url>,
+
+	/// The number of peers to replicate each hot namespace onto.
+	#[arg(long = "replicas", default_value = "1")]
+	pub replicas: usize,
+
+	/// Start replicating a namespace once it has at least this many subscribers.
+	#[arg(long = "replicate-above", default_value = "100")]
+	pub above: usize,
+
+	/// Stop replicating a namespace once it has this many subscribers or fewer.
+	/// [default: half of --replicate-above]
+	#[arg(long = "replicate-below")]
+	pub below: Option<usize>,
+
+	/// How long a namespace must stay at or below --replicate-below before its replicas are removed.
+	#[arg(long = "replicate-hold-ms", default_value = "30000")]
+	pub hold_ms: u64,
+}
+
+impl ReplicateArgs {
+	/// Returns None unless a peer was configured.
+	pub fn load(&self) -> anyhow::Result<Option<Replicator>> {
+		if self.peers.is_empty() {
+			return Ok(None);
+		}
+
+		let below = self.below.unwrap_or(self.above / 2);
+		anyhow::ensure!(
+			below < self.above,
+			"--replicate-below must be less than --replicate-above"
+		);
+
+		let replicator = Replicator::new(self.peers.clone())
+			.with_replicas(self.replicas)
+			.with_thresholds(self.above, below)
+			.with_hold(Duration::from_millis(self.hold_ms));
+
+		Ok(Some(replicator))
+	}
+}
+
+/// Counts the subscribers to each local namespace, announcing the hot ones to peer relays.
+///
+/// A namespace is hot once it reaches the upper threshold, and stays hot until it has been at or below the lower
+/// threshold for the hold duration, so replicas aren't repeatedly added and removed as subscribers come and go.
+#[derive(Clone)]
+pub struct Replicator {
+	peers: Arc<Vec<Url>>,
+	replicas: usize,
+	above: usize,
+	below: usize,
+	hold: Duration,
+
+	subscribers: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Replicator {
+	pub fn new(peers: Vec<Url>) -> Self {
+		Self {
+			peers: Arc::new(peers),
+			replicas: 1,
+			above: 100,
+			below: 50,
+			hold: Duration::from_secs(30),
+			subscribers: Default::default(),
+		}
+	}
+
+	/// Replicate each hot namespace onto this many peers, or every peer if there are fewer.
+	pub fn with_replicas(mut self, replicas: usize) -> Self {
+		self.replicas = replicas;
+		self
+	}
+
+	/// Start replicating at `above` subscribers and stop at `below` subscribers or fewer.
+	pub fn with_thresholds(mut self, above: usize, below: usize) -> Self {
+		self.above = above;
+		self.below = below;
+		self
+	}
+
+	/// How long a namespace must stay cool before its replicas are removed.
+	pub fn with_hold(mut self, hold: Duration) -> Self {
+		self.hold = hold;
+		self
+	}
+
+	/// Count a subscriber to the namespace until the returned guard is dropped.
+	pub fn subscriber(&self, namespace: &str) -> ReplicaSubscriber {
+		*self
+			.subscribers
+			.lock()
+			.unwrap()
+			.entry(namespace.to_string())
+			.or_default() += 1;
+
+		ReplicaSubscriber {
+			replicator: self.clone(),
+			namespace: namespace.to_string(),
+		}
+	}
+
+	/// The number of subscribers to the namespace.
+	pub fn subscribers(&self, namespace: &str) -> usize {
+		self.subscribers
+			.lock()
+			.unwrap()
+			.get(namespace)
+			.copied()
+			.unwrap_or_default()
+	}
+
+	/// The peers chosen for the namespace.
+	///
+	/// Each namespace prefers a different order of peers (rendezvous hashing), spreading replicas across the cluster,
+	/// while only moving the namespaces of a peer that is added or removed.
+	pub fn peers(&self, namespace: &str) -> Vec<Url> {
+		let mut peers: Vec<(u64, &Url)> = self
+			.peers
+			.iter()
+			.map(|peer| {
+				let mut hasher = DefaultHasher::new();
+				(namespace, peer.as_str()).hash(&mut hasher);
+				(hasher.finish(), peer)
+			})
+			.collect();
+
+		peers.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+		peers
+			.into_iter()
+			.take(self.replicas)
+			.map(|(_, peer)| peer.clone())
+			.collect()
+	}
+
+	// Returns true if the namespace should be replicated, updating its heat.
+	fn hot(&self, heat: &mut Heat, subscribers: usize, now: Instant) -> bool {
+		if !heat.hot {
+			heat.hot = subscribers >= self.above;
+			return heat.hot;
+		}
+
+		if subscribers > self.below {
+			heat.cool = None;
+			return true;
+		}
+
+		let cool = *heat.cool.get_or_insert(now);
+		if now.duration_since(cool) >= self.hold {
+			*heat = Heat::default();
+		}
+
+		heat.hot
+	}
+
+	/// Announce each hot local namespace to its peers until it cools down or goes away.
+	pub async fn run(self, locals: Locals, quic: quic::Client, versions: setup::Versions) -> anyhow::Result<()> {
+		let mut heats: HashMap<String, Heat> = HashMap::new();
+		let mut replicas: HashMap<String, Replica> = HashMap::new();
+		let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+		loop {
+			interval.tick().await;
+			let now = Instant::now();
+
+			let counts = self.subscribers.lock().unwrap().clone();
+			for namespace in counts.keys() {
+				heats.entry(namespace.clone()).or_default();
+			}
+
+			heats.retain(|namespace, heat| {
+				let subscribers = counts.get(namespace).copied().unwrap_or_default();
+
+				if !self.hot(heat, subscribers, now) {
+					if replicas.remove(namespace).is_some() {
+						log::info!("removing replicas: namespace={} subscribers={}", namespace, subscribers);
+					}
+
+					return subscribers > 0;
+				}
+
+				// Retry on the next check if every replica failed, ex. because a peer was restarting.
+				if replicas.get(namespace).is_some_and(|replica| !replica.is_finished()) {
+					return true;
+				}
+
+				// Only namespaces announced to this relay are replicated.
+				let local = match locals.route(namespace) {
+					Some(local) => local,
+					None => return subscribers > 0,
+				};
+
+				let peers = self.peers(namespace);
+				log::info!(
+					"replicating: namespace={} subscribers={} peers={:?}",
+					namespace,
+					subscribers,
+					peers.iter().map(Url::as_str).collect::<Vec<_>>()
+				);
+
+				let tasks = peers
+					.into_iter()
+					.map(|peer| {
+						let tracks = local.tracks.clone();
+						let quic = quic.clone();
+						let versions = versions.clone();
+
+						tokio::spawn(async move {
+							if let Err(err) = replicate(quic, &peer, tracks, versions).await {
+								log::warn!("failed replicating: peer={} error={}", peer, err);
+							}
+						})
+					})
+					.collect();
+
+				replicas.insert(namespace.clone(), Replica { tasks });
+				true
+			});
+		}
+	}
+}
+
+// Announce the tracks to the peer, serving its subscriptions until the session or the tracks are closed.
+async fn replicate(
+	quic: quic::Client,
+	peer: &Url,
+	tracks: TracksReader,
+	versions: setup::Versions,
+) -> anyhow::Result<()> {
+	let session = quic.connect(peer).await?;
+	let (session, publisher, _) = Session::connect_versions(session, setup::Role::Publisher, versions).await?;
+	let mut publisher = publisher.context("replica session missing publisher")?;
+
+	tokio::select! {
+		res = session.run() => res?,
+		res = publisher.announce(tracks.clone()) => res?,
+		_ = tracks.closed() => {},
+	}
+
+	Ok(())
+}
+
+#[derive(Default)]
+struct Heat {
+	hot: bool,
+
+	// When the namespace last dropped to the lower threshold while hot.
+	cool: Option<Instant>,
+}
+
+// The sessions announcing a namespace to its peers, closed on drop.
+struct Replica {
+	tasks: Vec<JoinHandle<()>>,
+}
+
+impl Replica {
+	fn is_finished(&self) -> bool {
+		self.tasks.iter().all(JoinHandle::is_finished)
+	}
+}
+
+impl Drop for Replica {
+	fn drop(&mut self) {
+		for task in &self.tasks {
+			task.abort();
+		}
+	}
+}
+
+/// A subscriber counted towards replicating its namespace, until dropped.
+pub struct ReplicaSubscriber {
+	replicator: Replicator,
+	namespace: String,
+}
+
+impl Drop for ReplicaSubscriber {
+	fn drop(&mut self) {
+		let mut subscribers = self.replicator.subscribers.lock().unwrap();
+		if let Some(count) = subscribers.get_mut(&self.namespace) {
+			*count -= 1;
+			if *count == 0 {
+				subscribers.remove(&self.namespace);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hysteresis() {
+		let replicator = Replicator::new(Vec::new())
+			.with_thresholds(10, 5)
+			.with_hold(Duration::from_secs(30));
+
+		let mut heat = Heat::default();
+		let start = Instant::now();
+		let at = |secs| start + Duration::from_secs(secs);
+
+		assert!(!replicator.hot(&mut heat, 9, at(0)));
+		assert!(replicator.hot(&mut heat, 10, at(1)));
+
+		// Dropping between the thresholds doesn't remove the replicas.
+		assert!(replicator.hot(&mut heat, 6, at(2)));
+
+		// Neither does a brief dip below the lower threshold.
+		assert!(replicator.hot(&mut heat, 5, at(3)));
+		assert!(replicator.hot(&mut heat, 7, at(20)));
+		assert!(replicator.hot(&mut heat, 4, at(21)));
+		assert!(replicator.hot(&mut heat, 4, at(50)));
+
+		// Staying cool for the hold duration does.
+		assert!(!replicator.hot(&mut heat, 4, at(51)));
+		assert!(!replicator.hot(&mut heat, 9, at(52)));
+	}
+
+	#[test]
+	fn peers() {
+		let peers: Vec<Url> = (0..5)
+			.map(|i| format!("https://relay{}.example.com", i).parse().unwrap())
+			.collect();
+
+		let replicator = Replicator::new(peers.clone()).with_replicas(2);
+		let chosen = replicator.peers("live/keynote");
+		assert_eq!(chosen.len(), 2);
+		assert_eq!(chosen, replicator.peers("live/keynote"));
+
+		// Removing a peer that wasn't chosen doesn't move the namespace.
+		let unused = peers.iter().filter(|peer| !chosen.contains(peer)).cloned();
+		let replicator = Replicator::new(unused.chain(chosen.iter().cloned()).skip(1).collect()).with_replicas(2);
+		assert_eq!(replicator.peers("live/keynote"), chosen);
+
+		let subscriber = replicator.subscriber("live/keynote");
+		assert_eq!(replicator.subscribers("live/keynote"), 1);
+		drop(subscriber);
+		assert_eq!(replicator.subscribers("live/keynote"), 0);
+	}
+}
@@ -0,0 +1,221 @@
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use clap::Parser;
+
+/// Run unattended, without a wrapper script or service manager integration.
+#[derive(Parser, Clone, Default)]
+#[group(id = "service")]
+pub struct ServiceArgs {
+	/// Write the process ID to this file, removing it on exit.
+	#[arg(long = "pid-file")]
+	pub pid_file: Option<PathBuf>,
+
+	/// Append logs to this file instead of stderr.
+	/// On Unix, the file is reopened on SIGHUP so it can be rotated, ex. by logrotate.
+	#[arg(long = "log-file")]
+	pub log_file: Option<PathBuf>,
+
+	/// Run as a Windows service, which must be started by the service control manager.
+	#[cfg(windows)]
+	#[arg(long = "service")]
+	pub service: bool,
+}
+
+/// Writes the process ID to a file, removed when dropped.
+pub struct PidFile {
+	path: PathBuf,
+}
+
+impl PidFile {
+	pub fn create(path: &Path) -> anyhow::Result<Self> {
+		fs::write(path, format!("{}\n", std::process::id()))
+			.with_context(|| format!("failed to write pid file: {}", path.display()))?;
+
+		Ok(Self { path: path.to_owned() })
+	}
+}
+
+impl Drop for PidFile {
+	fn drop(&mut self) {
+		if let Err(err) = fs::remove_file(&self.path) {
+			log::warn!("failed to remove pid file: {}, error: {}", self.path.display(), err);
+		}
+	}
+}
+
+/// A log file that can be reopened after it's been rotated.
+///
+/// Clones share the same file, so one can be given to the logger and another used to reopen it.
+#[derive(Clone)]
+pub struct LogFile {
+	path: PathBuf,
+	file: Arc<Mutex<fs::File>>,
+}
+
+impl LogFile {
+	pub fn open(path: &Path) -> anyhow::Result<Self> {
+		let file = Self::append(path).with_context(|| format!("failed to open log file: {}", path.display()))?;
+
+		Ok(Self {
+			path: path.to_owned(),
+			file: Arc::new(Mutex::new(file)),
+		})
+	}
+
+	fn append(path: &Path) -> io::Result<fs::File> {
+		fs::OpenOptions::new().create(true).append(true).open(path)
+	}
+
+	/// Start writing to a new file at the same path, after the old one was moved away.
+	pub fn reopen(&self) -> io::Result<()> {
+		let file = Self::append(&self.path)?;
+		*self.file.lock().unwrap() = file;
+		Ok(())
+	}
+}
+
+impl io::Write for LogFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.file.lock().unwrap().write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.file.lock().unwrap().flush()
+	}
+}
+
+/// Resolves when the process is asked to stop: SIGTERM or SIGINT on Unix, or Ctrl-C elsewhere.
+///
+/// On Unix, SIGHUP reopens the log file in the meantime.
+#[cfg(unix)]
+pub async fn shutdown_signal(log: Option<LogFile>) -> anyhow::Result<()> {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	let mut terminate = signal(SignalKind::terminate())?;
+	let mut interrupt = signal(SignalKind::interrupt())?;
+	let mut hangup = signal(SignalKind::hangup())?;
+
+	loop {
+		tokio::select! {
+			_ = terminate.recv() => return Ok(()),
+			_ = interrupt.recv() => return Ok(()),
+			_ = hangup.recv() => match &log {
+				Some(log) => match log.reopen() {
+					Ok(()) => log::info!("reopened log file"),
+					Err(err) => log::warn!("failed to reopen log file: {}", err),
+				},
+				None => log::debug!("ignoring SIGHUP"),
+			},
+		}
+	}
+}
+
+/// Resolves when the process is asked to stop: SIGTERM or SIGINT on Unix, or Ctrl-C elsewhere.
+#[cfg(not(unix))]
+pub async fn shutdown_signal(_log: Option<LogFile>) -> anyhow::Result<()> {
+	tokio::signal::ctrl_c().await?;
+	Ok(())
+}
+
+/// Run the provided function as a Windows service, blocking until it returns.
+///
+/// The function is given a future that resolves when the service control manager asks the service to stop.
+#[cfg(windows)]
+pub fn run_windows_service<F>(run: F) -> anyhow::Result<()>
+where
+	F: FnOnce(tokio::sync::oneshot::Receiver<()>) -> anyhow::Result<()> + Send + 'static,
+{
+	windows::run(Box::new(run))
+}
+
+#[cfg(windows)]
+mod windows {
+	use std::{ffi::OsString, sync::Mutex, time::Duration};
+
+	use tokio::sync::oneshot;
+	use windows_service::{
+		define_windows_service,
+		service::{ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType},
+		service_control_handler::{self, ServiceControlHandlerResult},
+		service_dispatcher,
+	};
+
+	// The name is ignored for SERVICE_WIN32_OWN_PROCESS, so it doesn't need to match the registration.
+	const SERVICE_NAME: &str = "moq-relay";
+
+	type Run = Box<dyn FnOnce(oneshot::Receiver<()>) -> anyhow::Result<()> + Send>;
+
+	// The dispatcher calls a plain function, so the closure is handed over via a global.
+	static RUN: Mutex<Option<Run>> = Mutex::new(None);
+
+	define_windows_service!(ffi_service_main, service_main);
+
+	pub fn run(run: Run) -> anyhow::Result<()> {
+		*RUN.lock().unwrap() = Some(run);
+		service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
+		Ok(())
+	}
+
+	fn service_main(_args: Vec<OsString>) {
+		if let Err(err) = serve() {
+			log::error!("windows service failed: {:?}", err);
+		}
+	}
+
+	fn serve() -> anyhow::Result<()> {
+		let run = RUN
+			.lock()
+			.unwrap()
+			.take()
+			.ok_or_else(|| anyhow::anyhow!("service already started"))?;
+
+		let (stop, stopped) = oneshot::channel();
+		let stop = Mutex::new(Some(stop));
+
+		let handler = move |control| match control {
+			ServiceControl::Stop | ServiceControl::Shutdown => {
+				if let Some(stop) = stop.lock().unwrap().take() {
+					stop.send(()).ok();
+				}
+				ServiceControlHandlerResult::NoError
+			}
+			ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+			_ => ServiceControlHandlerResult::NotImplemented,
+		};
+
+		let status = service_control_handler::register(SERVICE_NAME, handler)?;
+
+		let set = |state, accept, exit_code| {
+			status.set_service_status(ServiceStatus {
+				service_type: ServiceType::OWN_PROCESS,
+				current_state: state,
+				controls_accepted: accept,
+				exit_code,
+				checkpoint: 0,
+				wait_hint: Duration::default(),
+				process_id: None,
+			})
+		};
+
+		set(
+			ServiceState::Running,
+			ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+			ServiceExitCode::Win32(0),
+		)?;
+
+		let res = run(stopped);
+
+		let exit_code = match &res {
+			Ok(()) => ServiceExitCode::Win32(0),
+			Err(_) => ServiceExitCode::ServiceSpecific(1),
+		};
+		set(ServiceState::Stopped, ServiceControlAccept::empty(), exit_code)?;
+
+		res
+	}
+}
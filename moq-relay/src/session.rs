@@ -1,5 +1,15 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::{Arc, Mutex},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
-use moq_transport::session::SessionError;
+use moq_transport::{
+	session::{CloseCode, Drain, History, HistoryEvent, SessionError},
+	transport,
+};
+use serde::Serialize;
 
 use crate::{Consumer, Producer};
 
@@ -25,3 +35,227 @@ impl Session {
 		tasks.select_next_some().await
 	}
 }
+
+/// A session accepted by the relay, as listed by the admin API.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionInfo {
+	pub id: u64,
+	pub version: String,
+
+	/// Either `quic` (including WebTransport) or `tcp`.
+	pub transport: &'static str,
+
+	/// Whether the peer can announce, and subscribe, respectively.
+	pub publisher: bool,
+	pub subscriber: bool,
+
+	pub sticky: Option<String>,
+
+	/// The PATH sent in SETUP, only over raw QUIC and TCP.
+	pub path: Option<String>,
+
+	/// When the session was accepted, in milliseconds since the Unix epoch.
+	pub connected_ms: u64,
+
+	/// Whether the session was sent GOAWAY.
+	pub draining: bool,
+}
+
+/// A message or stream event of a session, as listed by the admin API.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionEvent {
+	/// The time since the session was accepted.
+	pub elapsed_ms: u64,
+
+	/// Either `sent`, `recv` or `stream`.
+	pub kind: String,
+	pub detail: String,
+}
+
+impl From<HistoryEvent> for SessionEvent {
+	fn from(event: HistoryEvent) -> Self {
+		Self {
+			elapsed_ms: event.elapsed.as_millis() as u64,
+			kind: event.kind.to_string(),
+			detail: event.detail,
+		}
+	}
+}
+
+/// A session that failed with a protocol violation, along with the events leading up to it.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionFailure {
+	#[serde(flatten)]
+	pub info: SessionInfo,
+
+	pub error: String,
+
+	/// When the session failed, in milliseconds since the Unix epoch.
+	pub failed_ms: u64,
+
+	pub events: Vec<SessionEvent>,
+}
+
+struct Active {
+	info: SessionInfo,
+	transport: transport::Session,
+	drain: Drain,
+	history: History,
+}
+
+#[derive(Default)]
+struct SessionsState {
+	next: u64,
+	active: HashMap<u64, Active>,
+
+	// The latest failures, oldest first.
+	failures: VecDeque<SessionFailure>,
+}
+
+/// The sessions accepted by the relay, so they can be listed and closed by an operator.
+#[derive(Clone, Default)]
+pub struct Sessions {
+	state: Arc<Mutex<SessionsState>>,
+}
+
+impl Sessions {
+	/// The number of failed sessions kept for [Self::failures].
+	pub const MAX_FAILURES: usize = 16;
+
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Track the session until the returned handle is dropped.
+	pub(crate) fn register(&self, session: &Session, transport: transport::Session) -> SessionRegistration {
+		let mut state = self.state.lock().unwrap();
+		let id = state.next;
+		state.next += 1;
+
+		let connected_ms = now_ms();
+
+		let info = SessionInfo {
+			id,
+			version: session.session.version().to_string(),
+			transport: match transport {
+				transport::Session::WebTransport(_) => "quic",
+				transport::Session::Mux(_) => "tcp",
+			},
+			publisher: session.consumer.is_some(),
+			subscriber: session.producer.is_some(),
+			sticky: session.session.sticky().map(|sticky| sticky.as_str().to_string()),
+			path: session.session.path().map(|path| path.as_str().to_string()),
+			connected_ms,
+			draining: false,
+		};
+
+		let drain = session.session.drain();
+		let history = session.session.history();
+		state.active.insert(
+			id,
+			Active {
+				info,
+				transport,
+				drain,
+				history,
+			},
+		);
+
+		SessionRegistration {
+			sessions: self.clone(),
+			id,
+		}
+	}
+
+	/// Every active session, ordered by ID.
+	pub fn list(&self) -> Vec<SessionInfo> {
+		let state = self.state.lock().unwrap();
+		let mut sessions: Vec<_> = state
+			.active
+			.values()
+			.map(|active| SessionInfo {
+				draining: active.drain.is_draining(),
+				..active.info.clone()
+			})
+			.collect();
+
+		sessions.sort_by_key(|info| info.id);
+		sessions
+	}
+
+	/// The latest messages and stream events of an active session.
+	pub fn history(&self, id: u64) -> Option<Vec<SessionEvent>> {
+		let history = self.state.lock().unwrap().active.get(&id)?.history.clone();
+		Some(history.events().into_iter().map(Into::into).collect())
+	}
+
+	/// The latest sessions that failed with a protocol violation, oldest first.
+	pub fn failures(&self) -> Vec<SessionFailure> {
+		self.state.lock().unwrap().failures.iter().cloned().collect()
+	}
+
+	/// Close the session immediately, returning false if there's no such session.
+	pub fn close(&self, id: u64, reason: &str) -> bool {
+		let transport = match self.state.lock().unwrap().active.get(&id) {
+			Some(active) => active.transport.clone(),
+			None => return false,
+		};
+
+		log::info!("closing session {}: {}", id, reason);
+		transport.close(CloseCode::NoError.into(), reason);
+		true
+	}
+}
+
+/// Removes the session from [Sessions] on drop.
+pub(crate) struct SessionRegistration {
+	sessions: Sessions,
+	id: u64,
+}
+
+impl SessionRegistration {
+	pub fn id(&self) -> u64 {
+		self.id
+	}
+
+	/// Keep a report of the session if it failed with a protocol violation, see [Sessions::failures].
+	pub fn failed(&self, err: &SessionError) {
+		if !err.is_protocol_violation() {
+			return;
+		}
+
+		let mut state = self.sessions.state.lock().unwrap();
+		let active = match state.active.get(&self.id) {
+			Some(active) => active,
+			None => return,
+		};
+
+		let failure = SessionFailure {
+			info: SessionInfo {
+				draining: active.drain.is_draining(),
+				..active.info.clone()
+			},
+			error: err.to_string(),
+			failed_ms: now_ms(),
+			events: active.history.events().into_iter().map(Into::into).collect(),
+		};
+
+		if state.failures.len() >= Sessions::MAX_FAILURES {
+			state.failures.pop_front();
+		}
+		state.failures.push_back(failure);
+	}
+}
+
+impl Drop for SessionRegistration {
+	fn drop(&mut self) {
+		self.sessions.state.lock().unwrap().active.remove(&self.id);
+	}
+}
+
+fn now_ms() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|now| now.as_millis() as u64)
+		.unwrap_or_default()
+}
@@ -0,0 +1,433 @@
+use std::{
+	collections::HashSet,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+};
+
+use clap::Parser;
+use futures::future::BoxFuture;
+use moq_transport::{
+	serve::{Group, GroupReader, GroupSkip, GroupsReader, GroupsWriter, Track, TrackReader, TrackReaderMode},
+	session::Subscribed,
+};
+use serde::Serialize;
+
+use crate::Locals;
+
+/// The annotation marking the first group of an ad break, with the break ID as the value.
+pub const SPLICE_OUT: &str = "splice.out";
+
+/// The annotation marking the first group after an ad break.
+pub const SPLICE_IN: &str = "splice.in";
+
+#[derive(Parser, Clone, Default)]
+#[group(id = "splice")]
+pub struct SpliceArgs {
+	/// Splice ads into this track at groups annotated with `splice.out`, returning at `splice.in`.
+	/// Can be repeated, ex. `--splice-track video --splice-track audio`.
+	#[arg(long = "splice-track")]
+	pub tracks: Vec<String>,
+
+	/// Play the same track from this local namespace during every ad break.
+	#[arg(long = "splice-namespace")]
+	pub namespace: Option<String>,
+
+	/// POST each ad break to this URL, which chooses the namespace to play for that subscriber.
+	#[cfg(feature = "policy-http")]
+	#[arg(long = "splice-hook")]
+	pub hook: Option<url::Url>,
+
+	/// How long to wait for the splice hook before continuing without an ad.
+	#[cfg(feature = "policy-http")]
+	#[arg(long = "splice-hook-timeout-ms", default_value = "500")]
+	pub hook_timeout_ms: u64,
+}
+
+impl SpliceArgs {
+	/// Returns None unless any tracks are selected.
+	pub fn load(&self) -> anyhow::Result<Option<Splicer>> {
+		if self.tracks.is_empty() {
+			return Ok(None);
+		}
+
+		#[cfg(feature = "policy-http")]
+		anyhow::ensure!(
+			self.namespace.is_none() || self.hook.is_none(),
+			"--splice-namespace and --splice-hook can't both be used"
+		);
+
+		if let Some(namespace) = &self.namespace {
+			let decider = Arc::new(FixedSplice(namespace.clone()));
+			return Ok(Some(Splicer::new(self.tracks.clone(), decider)));
+		}
+
+		#[cfg(feature = "policy-http")]
+		if let Some(url) = &self.hook {
+			let timeout = std::time::Duration::from_millis(self.hook_timeout_ms);
+			let decider = Arc::new(HttpSplice::new(url.clone(), timeout)?);
+			return Ok(Some(Splicer::new(self.tracks.clone(), decider)));
+		}
+
+		anyhow::bail!("--splice-track requires --splice-namespace or --splice-hook")
+	}
+}
+
+/// An ad break reached by a subscription, passed to the [SpliceDecider].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpliceRequest {
+	/// Identifies the subscription, so repeated breaks can be told apart from new viewers.
+	pub subscription: u64,
+
+	pub namespace: String,
+	pub name: String,
+
+	/// The value of the `splice.out` annotation, ex. the SCTE-35 splice event ID.
+	#[serde(rename = "breakId")]
+	pub break_id: String,
+}
+
+/// Chooses what each subscription plays during an ad break.
+///
+/// Implementations are compiled in via [crate::RelayConfig::splice], or the relay can call an external hook with
+/// [HttpSplice] when built with the `policy-http` feature.
+pub trait SpliceDecider: Send + Sync {
+	/// Returns the local namespace to play during the break, or None to continue with the original track.
+	fn decide(&self, request: SpliceRequest) -> BoxFuture<'_, Option<String>>;
+}
+
+/// Plays the same namespace during every break.
+pub struct FixedSplice(pub String);
+
+impl SpliceDecider for FixedSplice {
+	fn decide(&self, _request: SpliceRequest) -> BoxFuture<'_, Option<String>> {
+		Box::pin(async { Some(self.0.clone()) })
+	}
+}
+
+/// Switches subscribers of selected tracks to an alternate namespace during ad breaks.
+///
+/// A break starts at a group annotated with [SPLICE_OUT] and ends at a group annotated with [SPLICE_IN].
+/// Each subscription asks the [SpliceDecider] which namespace to play, then receives the track with the same name
+/// from that namespace until the break ends, or the ad track does.
+/// Switching only happens at group boundaries, and group IDs are rewritten so they keep increasing.
+///
+/// Ads must be local broadcasts, ex. announced to this relay or created with [crate::Relay::publish].
+/// They are not checked against the [crate::Policy], since the subscriber never asked for them.
+#[derive(Clone)]
+pub struct Splicer {
+	tracks: Arc<HashSet<String>>,
+	decider: Arc<dyn SpliceDecider>,
+	subscriptions: Arc<AtomicU64>,
+}
+
+impl Splicer {
+	pub fn new(tracks: impl IntoIterator<Item = String>, decider: Arc<dyn SpliceDecider>) -> Self {
+		Self {
+			tracks: Arc::new(tracks.into_iter().collect()),
+			decider,
+			subscriptions: Default::default(),
+		}
+	}
+
+	/// Returns true if ads are spliced into tracks with this name.
+	pub fn selected(&self, name: &str) -> bool {
+		self.tracks.contains(name)
+	}
+
+	/// Serve the track to the subscriber, switching to an ad namespace during each break.
+	///
+	/// Tracks that don't use groups are served unchanged.
+	pub async fn serve(&self, subscribe: Subscribed, locals: &Locals, track: TrackReader) -> anyhow::Result<()> {
+		let program = match track.mode().await? {
+			// Don't skip, otherwise a break could be missed.
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => return Ok(subscribe.serve(track).await?),
+		};
+
+		let (writer, reader) = Track::new(track.namespace.clone(), track.name.clone()).produce();
+		let mut output = Output {
+			groups: writer.groups()?,
+			next: 0,
+		};
+
+		let serve = subscribe.serve(reader);
+		tokio::pin!(serve);
+
+		let subscription = self.subscriptions.fetch_add(1, Ordering::Relaxed);
+		let write = self.run(subscription, locals, &track, program, &mut output);
+
+		tokio::select! {
+			// Poll the subscription first, so it starts reading before the first group is written.
+			biased;
+			res = &mut serve => return Ok(res?),
+			res = write => res?,
+		};
+
+		// Wait until the subscriber has every group.
+		Ok(serve.await?)
+	}
+
+	async fn run(
+		&self,
+		subscription: u64,
+		locals: &Locals,
+		track: &Track,
+		mut program: GroupsReader,
+		output: &mut Output,
+	) -> anyhow::Result<()> {
+		let mut next = program.next().await?;
+
+		while let Some(group) = next.take() {
+			if let Some(break_id) = group.annotations.get(SPLICE_OUT) {
+				let request = SpliceRequest {
+					subscription,
+					namespace: track.namespace.clone(),
+					name: track.name.clone(),
+					break_id: String::from_utf8_lossy(break_id).into_owned(),
+				};
+
+				if let Some(ad) = self.ad(locals, request).await? {
+					next = Self::play(ad, &mut program, output).await?;
+					continue;
+				}
+			}
+
+			// Keep the original group ID unless an ad already used it.
+			let group_id = group.group_id.max(output.next);
+			output.copy(group_id, group).await?;
+
+			next = program.next().await?;
+		}
+
+		Ok(())
+	}
+
+	// Returns the ad to play for the break, if any.
+	async fn ad(&self, locals: &Locals, request: SpliceRequest) -> anyhow::Result<Option<GroupsReader>> {
+		let namespace = match self.decider.decide(request.clone()).await {
+			Some(namespace) => namespace,
+			None => return Ok(None),
+		};
+
+		let ad = locals
+			.route(&namespace)
+			.and_then(|mut local| local.tracks.subscribe(&request.name));
+
+		let ad = match ad {
+			Some(ad) => ad,
+			None => {
+				log::warn!("ad not found: namespace={} request={:?}", namespace, request);
+				return Ok(None);
+			}
+		};
+
+		log::info!("splicing ad: namespace={} request={:?}", namespace, request);
+
+		match ad.mode().await? {
+			TrackReaderMode::Groups(groups) => Ok(Some(groups.with_skip(GroupSkip::Never))),
+			_ => {
+				log::warn!("ad doesn't use groups: namespace={} request={:?}", namespace, request);
+				Ok(None)
+			}
+		}
+	}
+
+	// Copy ad groups until the break ends, returning the program group to continue with.
+	async fn play(
+		mut ad: GroupsReader,
+		program: &mut GroupsReader,
+		output: &mut Output,
+	) -> anyhow::Result<Option<GroupReader>> {
+		loop {
+			tokio::select! {
+				res = program.next() => match res? {
+					Some(group) if group.annotations.get(SPLICE_IN).is_some() => return Ok(Some(group)),
+					Some(_) => continue,
+					None => return Ok(None),
+				},
+				res = ad.next() => match res? {
+					Some(group) => output.copy(output.next, group).await?,
+					None => break,
+				},
+			}
+		}
+
+		// The ad ended before the break did, so return to the program early.
+		Ok(program.next().await?)
+	}
+}
+
+// The spliced track served to the subscriber.
+struct Output {
+	groups: GroupsWriter,
+	next: u64,
+}
+
+impl Output {
+	async fn copy(&mut self, group_id: u64, mut group: GroupReader) -> anyhow::Result<()> {
+		let mut writer = self.groups.create(Group {
+			group_id,
+			priority: group.priority,
+			timestamp: group.timestamp,
+			annotations: group.annotations.clone(),
+			subgroups: 0,
+			transit: None,
+		})?;
+		self.next = group_id + 1;
+
+		// Keep each object's flags and extension headers, ex. so integrity hashes still reach the subscriber.
+		while let Some(mut object) = group.next().await? {
+			let payload = group.track.compression()?.decompress(object.read_all().await?)?;
+			writer.write_with(payload, object.independent, object.extensions.clone())?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(feature = "policy-http")]
+pub use http::*;
+
+#[cfg(feature = "policy-http")]
+mod http {
+	use std::time::Duration;
+
+	use futures::{future::BoxFuture, FutureExt};
+	use serde::Deserialize;
+	use url::Url;
+
+	use super::{SpliceDecider, SpliceRequest};
+
+	#[derive(Deserialize)]
+	struct SpliceResponse {
+		#[serde(default)]
+		namespace: Option<String>,
+	}
+
+	/// Asks an external HTTP service what each subscription plays during an ad break.
+	///
+	/// The [SpliceRequest] is POSTed as JSON, ex. `{"subscription":3,"namespace":"live","name":"video","breakId":"42"}`,
+	/// and the response is either `{"namespace":"ads/42"}` or `{}` to skip the ad.
+	/// The original track continues if the hook can't be reached or returns an error.
+	pub struct HttpSplice {
+		client: reqwest::Client,
+		url: Url,
+	}
+
+	impl HttpSplice {
+		pub fn new(url: Url, timeout: Duration) -> anyhow::Result<Self> {
+			let client = reqwest::Client::builder().timeout(timeout).build()?;
+			Ok(Self { client, url })
+		}
+
+		async fn request(&self, request: &SpliceRequest) -> anyhow::Result<Option<String>> {
+			let res = self.client.post(self.url.clone()).json(request).send().await?;
+			let res: SpliceResponse = res.error_for_status()?.json().await?;
+			Ok(res.namespace)
+		}
+	}
+
+	impl SpliceDecider for HttpSplice {
+		fn decide(&self, request: SpliceRequest) -> BoxFuture<'_, Option<String>> {
+			async move {
+				match self.request(&request).await {
+					Ok(namespace) => namespace,
+					Err(err) => {
+						log::warn!("splice hook failed: request={:?} error={:?}", request, err);
+						None
+					}
+				}
+			}
+			.boxed()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use moq_transport::serve::{GroupAnnotations, GroupTimestamp, Tracks};
+
+	#[tokio::test]
+	async fn splice() {
+		let mut locals = Locals::new();
+
+		let (mut ads, _, reader) = Tracks::new("ads".to_string()).produce();
+		let _registration = locals.register(reader).await.unwrap();
+		let mut ad = ads.create("video").unwrap().groups().unwrap();
+
+		let splicer = Splicer::new(["video".to_string()], Arc::new(FixedSplice("ads".to_string())));
+		assert!(splicer.selected("video"));
+		assert!(!splicer.selected("audio"));
+
+		let (writer, reader) = Track::new("live".to_string(), "video".to_string()).produce();
+		let mut program = writer.groups().unwrap();
+
+		let (writer, spliced) = Track::new("live".to_string(), "video".to_string()).produce();
+		let mut output = Output {
+			groups: writer.groups().unwrap(),
+			next: 0,
+		};
+
+		let program_reader = match reader.mode().await.unwrap() {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => panic!("expected groups"),
+		};
+
+		let annotated = |key: &str| {
+			let mut annotations = GroupAnnotations::new();
+			annotations.insert(key, "1");
+			annotations
+		};
+
+		// Program, then a break where the ad replaces two program groups, then back to the program.
+		program.append(0).unwrap().write("program".into()).unwrap();
+		program
+			.append_annotated(0, GroupTimestamp::default(), annotated(SPLICE_OUT))
+			.unwrap()
+			.write("skipped".into())
+			.unwrap();
+
+		let task = tokio::spawn({
+			let locals = locals.clone();
+			let track = reader.info.clone();
+			async move {
+				splicer
+					.run(0, &locals, &track, program_reader, &mut output)
+					.await
+					.unwrap();
+			}
+		});
+
+		let mut spliced = match spliced.mode().await.unwrap() {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => panic!("expected groups"),
+		};
+
+		let mut group = spliced.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 0);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "program");
+
+		ad.append(0).unwrap().write("ad".into()).unwrap();
+
+		let mut group = spliced.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 1);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "ad");
+
+		program.append(0).unwrap().write("skipped".into()).unwrap();
+		program
+			.append_annotated(0, GroupTimestamp::default(), annotated(SPLICE_IN))
+			.unwrap()
+			.write("resumed".into())
+			.unwrap();
+		drop(program);
+
+		let mut group = spliced.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 3);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "resumed");
+
+		task.await.unwrap();
+	}
+}
@@ -0,0 +1,220 @@
+use std::{
+	collections::VecDeque,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use moq_transport::{
+	serve::{Track, TrackReader, TrackReaderMode},
+	session::Subscribed,
+};
+use serde::Serialize;
+
+/// Subscribing to `_stats/<track>` in a local namespace returns delivery statistics for `<track>`.
+///
+/// Each group contains a single JSON [StatsReport], written every [STATS_INTERVAL].
+pub const STATS_PREFIX: &str = "_stats/";
+
+pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+// The number of intervals included in each report.
+const STATS_WINDOW: usize = 10;
+
+/// Delivery statistics for a track over the last few seconds, as received by the relay.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsReport {
+	pub track: String,
+
+	/// The duration covered by the report.
+	pub window_ms: u64,
+
+	/// The groups received during the window.
+	pub groups: u64,
+
+	/// Gaps in the group sequence during the window, either lost upstream or rejected by the cache.
+	pub dropped: u64,
+
+	/// The average time between capture and arrival at the relay, for groups with a wall clock timestamp.
+	/// This relies on the publisher's clock, so any skew is included.
+	pub delay_ms: Option<f64>,
+
+	/// The average one-way delay from the previous hop to the relay, for groups stamped with a send time.
+	/// This is corrected for clock skew once the sender's clock has been measured with the clock-sync track.
+	pub hop_delay_ms: Option<f64>,
+
+	pub total_groups: u64,
+	pub total_dropped: u64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Interval {
+	groups: u64,
+	dropped: u64,
+	delay_sum: u64,
+	delay_count: u64,
+	hop_sum: u64,
+	hop_count: u64,
+}
+
+struct Stats {
+	track: String,
+	last: Option<u64>,
+	current: Interval,
+	window: VecDeque<Interval>,
+	total_groups: u64,
+	total_dropped: u64,
+}
+
+impl Stats {
+	fn new(track: String) -> Self {
+		Self {
+			track,
+			last: None,
+			current: Interval::default(),
+			window: VecDeque::with_capacity(STATS_WINDOW),
+			total_groups: 0,
+			total_dropped: 0,
+		}
+	}
+
+	fn record(&mut self, group_id: u64, wall: Option<u64>, hop: Option<Duration>, now: u64) {
+		// Only count forward gaps; a restart or reordering isn't a drop.
+		let dropped = match self.last {
+			Some(last) if group_id > last + 1 => group_id - last - 1,
+			_ => 0,
+		};
+
+		if self.last.is_none_or(|last| group_id > last) {
+			self.last = Some(group_id);
+		}
+
+		self.current.groups += 1;
+		self.current.dropped += dropped;
+		self.total_groups += 1;
+		self.total_dropped += dropped;
+
+		if let Some(wall) = wall {
+			self.current.delay_sum += now.saturating_sub(wall);
+			self.current.delay_count += 1;
+		}
+
+		if let Some(hop) = hop {
+			self.current.hop_sum += hop.as_micros() as u64;
+			self.current.hop_count += 1;
+		}
+	}
+
+	// Close the current interval and summarize the window.
+	fn report(&mut self) -> StatsReport {
+		if self.window.len() == STATS_WINDOW {
+			self.window.pop_front();
+		}
+		self.window.push_back(std::mem::take(&mut self.current));
+
+		let sum = self.window.iter().fold(Interval::default(), |sum, i| Interval {
+			groups: sum.groups + i.groups,
+			dropped: sum.dropped + i.dropped,
+			delay_sum: sum.delay_sum + i.delay_sum,
+			delay_count: sum.delay_count + i.delay_count,
+			hop_sum: sum.hop_sum + i.hop_sum,
+			hop_count: sum.hop_count + i.hop_count,
+		});
+
+		StatsReport {
+			track: self.track.clone(),
+			window_ms: self.window.len() as u64 * STATS_INTERVAL.as_millis() as u64,
+			groups: sum.groups,
+			dropped: sum.dropped,
+			delay_ms: (sum.delay_count > 0).then(|| sum.delay_sum as f64 / sum.delay_count as f64 / 1000.0),
+			hop_delay_ms: (sum.hop_count > 0).then(|| sum.hop_sum as f64 / sum.hop_count as f64 / 1000.0),
+			total_groups: self.total_groups,
+			total_dropped: self.total_dropped,
+		}
+	}
+}
+
+/// Serve statistics for the track until the subscriber goes away or the track ends.
+pub async fn serve_stats(subscribe: Subscribed, track: TrackReader) -> anyhow::Result<()> {
+	let name = format!("{}{}", STATS_PREFIX, track.name);
+	let (writer, reader) = Track::new(track.namespace.clone(), name).produce();
+	let mut out = writer.groups()?;
+
+	let serve = subscribe.serve(reader);
+	tokio::pin!(serve);
+
+	let mode = tokio::select! {
+		res = &mut serve => return Ok(res?),
+		res = track.mode() => res?,
+	};
+
+	let mut groups = match mode {
+		TrackReaderMode::Groups(groups) => groups,
+		_ => anyhow::bail!("statistics are only available for tracks using groups"),
+	};
+
+	let mut stats = Stats::new(track.name.clone());
+	let mut interval = tokio::time::interval(STATS_INTERVAL);
+	interval.tick().await;
+
+	loop {
+		tokio::select! {
+			res = &mut serve => return Ok(res?),
+			res = groups.next() => match res? {
+				Some(group) => {
+					let hop = group.transit.map(|transit| transit.delay());
+					stats.record(group.group_id, group.timestamp.wall, hop, now())
+				},
+				None => break,
+			},
+			_ = interval.tick() => {
+				let report = serde_json::to_vec(&stats.report())?;
+				out.append(0)?.write(report.into())?;
+			},
+		}
+	}
+
+	// Close the stats track too, once the subscriber has what we've written.
+	drop(out);
+	Ok(serve.await?)
+}
+
+// Microseconds since the UNIX epoch, matching the group timestamps.
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_micros() as u64)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn window() {
+		let mut stats = Stats::new("video".to_string());
+
+		stats.record(0, Some(1_000), Some(Duration::from_micros(500)), 3_000);
+		stats.record(3, Some(2_000), None, 6_000);
+		let report = stats.report();
+		assert_eq!(report.groups, 2);
+		assert_eq!(report.dropped, 2);
+		assert_eq!(report.delay_ms, Some(3.0));
+		assert_eq!(report.hop_delay_ms, Some(0.5));
+
+		// A restart isn't counted as a drop.
+		stats.record(1, None, None, 0);
+		let report = stats.report();
+		assert_eq!(report.window_ms, 2000);
+		assert_eq!((report.groups, report.dropped), (3, 2));
+
+		// Older intervals fall out of the window, but not the totals.
+		for _ in 0..STATS_WINDOW {
+			stats.report();
+		}
+		let report = stats.report();
+		assert_eq!((report.groups, report.dropped, report.delay_ms), (0, 0, None));
+		assert_eq!(report.hop_delay_ms, None);
+		assert_eq!((report.total_groups, report.total_dropped), (3, 2));
+	}
+}
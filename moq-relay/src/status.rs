@@ -0,0 +1,52 @@
+use std::{sync::Arc, time::Duration};
+
+use moq_transport::{
+	serve::{Track, Usage},
+	session::Subscribed,
+};
+use tokio::time::Instant;
+
+/// A track served by the relay for each local namespace, reporting whether objects are flowing.
+///
+/// Each group contains a single object with the state as text: `announced`, `live`, or `stalled`.
+/// A new group is written every [STATUS_INTERVAL], so a subscriber can also treat missing heartbeats as stalled.
+pub const STATUS_TRACK: &str = ".status";
+
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+// The namespace is stalled if no objects arrive for this long.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve the status of a namespace until the subscriber goes away.
+pub async fn serve_status(subscribe: Subscribed, namespace: String, usage: Arc<Usage>) -> anyhow::Result<()> {
+	let (writer, reader) = Track::new(namespace, STATUS_TRACK.to_string()).produce();
+	let mut groups = writer.groups()?;
+
+	let serve = subscribe.serve(reader);
+	tokio::pin!(serve);
+
+	let mut interval = tokio::time::interval(STATUS_INTERVAL);
+	let mut written = 0;
+	let mut last = None;
+
+	loop {
+		tokio::select! {
+			res = &mut serve => return Ok(res?),
+			_ = interval.tick() => {},
+		}
+
+		let now = Instant::now();
+		if usage.written() != written {
+			written = usage.written();
+			last = Some(now);
+		}
+
+		let state = match last {
+			None => "announced",
+			Some(last) if now - last < STALL_TIMEOUT => "live",
+			Some(_) => "stalled",
+		};
+
+		groups.append(0)?.write(state.into())?;
+	}
+}
@@ -0,0 +1,116 @@
+use std::sync::{Arc, Mutex};
+
+use clap::Parser;
+use moq_transport::setup::StickyToken;
+use tokio::sync::watch;
+use url::Url;
+
+/// The query parameter used to embed a [StickyToken] in a GOAWAY redirect URL.
+pub const STICKY_QUERY: &str = "sticky";
+
+/// Redirect sessions elsewhere on shutdown, for relays behind anycast or layer-4 load balancing.
+#[derive(Parser, Clone, Default)]
+#[group(id = "sticky")]
+pub struct StickyArgs {
+	/// Identify this relay with a stickiness token in SETUP, ex. its hostname.
+	#[arg(long = "sticky-token")]
+	pub token: Option<String>,
+
+	/// On shutdown, send GOAWAY redirecting every session to this URL, usually the anycast address.
+	/// The URL gets a `sticky` query parameter with the token of the relay that has the broadcasts warm.
+	#[arg(id = "go-away-url", long = "go-away-url")]
+	pub url: Option<Url>,
+
+	/// How long to keep serving after GOAWAY, giving sessions time to reconnect elsewhere.
+	#[arg(long = "go-away-grace-ms", default_value = "5000")]
+	pub grace_ms: u64,
+}
+
+/// Returns the URL with the token as the `sticky` query parameter, replacing any existing one.
+pub fn sticky_url(url: &Url, token: &StickyToken) -> Url {
+	let mut url = url.clone();
+	let query: Vec<(String, String)> = url
+		.query_pairs()
+		.filter(|(key, _)| key != STICKY_QUERY)
+		.map(|(key, value)| (key.into_owned(), value.into_owned()))
+		.collect();
+
+	url.query_pairs_mut()
+		.clear()
+		.extend_pairs(query)
+		.append_pair(STICKY_QUERY, token.as_str());
+
+	url
+}
+
+/// The stickiness token embedded in a redirect URL, which a client sends back in SETUP when it reconnects.
+pub fn url_sticky(url: &Url) -> Option<StickyToken> {
+	url.query_pairs()
+		.find(|(key, _)| key == STICKY_QUERY)
+		.map(|(_, value)| StickyToken::new(value))
+}
+
+/// Sends GOAWAY to every session accepted by the relay, see [crate::Relay::go_away].
+#[derive(Clone)]
+pub struct GoAway {
+	sender: Arc<watch::Sender<Option<Url>>>,
+
+	// The token advertised by this relay.
+	token: Option<StickyToken>,
+
+	// The token advertised by the --announce relay, which has the broadcasts warm.
+	upstream: Arc<Mutex<Option<StickyToken>>>,
+}
+
+impl GoAway {
+	pub fn new(token: Option<StickyToken>) -> Self {
+		Self {
+			sender: Arc::new(watch::Sender::new(None)),
+			token,
+			upstream: Default::default(),
+		}
+	}
+
+	pub(crate) fn set_upstream(&self, token: Option<StickyToken>) {
+		*self.upstream.lock().unwrap() = token;
+	}
+
+	/// The token embedded in redirect URLs: the upstream relay's if known, otherwise this relay's.
+	pub fn token(&self) -> Option<StickyToken> {
+		self.upstream.lock().unwrap().clone().or_else(|| self.token.clone())
+	}
+
+	/// Redirect every session to the URL, embedding [Self::token] so clients land on a relay with the broadcast warm.
+	pub fn send(&self, url: &Url) {
+		let url = match self.token() {
+			Some(token) => sticky_url(url, &token),
+			None => url.clone(),
+		};
+
+		log::info!("sending GOAWAY: {}", url);
+		self.sender.send_replace(Some(url));
+	}
+
+	pub(crate) fn subscribe(&self) -> watch::Receiver<Option<Url>> {
+		self.sender.subscribe()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn redirect() {
+		let url: Url = "https://relay.example.com/live?sticky=old&region=eu".parse().unwrap();
+		let url = sticky_url(&url, &StickyToken::new("origin 1"));
+		assert_eq!(url.as_str(), "https://relay.example.com/live?region=eu&sticky=origin+1");
+		assert_eq!(url_sticky(&url), Some(StickyToken::new("origin 1")));
+
+		let go_away = GoAway::new(Some("edge-1".into()));
+		assert_eq!(go_away.token(), Some("edge-1".into()));
+
+		go_away.set_upstream(Some("origin-1".into()));
+		assert_eq!(go_away.token(), Some("origin-1".into()));
+	}
+}
@@ -0,0 +1,57 @@
+use std::{net, os::fd::FromRawFd};
+
+use anyhow::Context;
+use sd_notify::NotifyState;
+
+/// Returns the UDP socket passed by systemd socket activation, if any.
+///
+/// Only the first socket is used, which must be a `ListenDatagram=` socket for QUIC.
+pub fn listen_udp() -> anyhow::Result<Option<net::UdpSocket>> {
+	let mut fds = sd_notify::listen_fds().context("invalid LISTEN_FDS")?;
+
+	let fd = match fds.next() {
+		Some(fd) => fd,
+		None => return Ok(None),
+	};
+
+	if fds.len() > 0 {
+		log::warn!("ignoring {} extra sockets passed by systemd", fds.len());
+	}
+
+	// SAFETY: systemd passes ownership of the descriptor, and nothing else uses it.
+	let socket = unsafe { net::UdpSocket::from_raw_fd(fd) };
+	let addr = socket
+		.local_addr()
+		.context("socket passed by systemd is not a UDP socket")?;
+	log::info!("using socket passed by systemd: {}", addr);
+
+	Ok(Some(socket))
+}
+
+/// Tell systemd the relay is ready, and keep the watchdog fed if `WatchdogSec=` is configured.
+///
+/// Does nothing unless running under systemd with `Type=notify`.
+pub fn notify_ready() {
+	if let Err(err) = sd_notify::notify(&[NotifyState::Ready]) {
+		log::warn!("failed to notify systemd: {}", err);
+		return;
+	}
+
+	let timeout = match sd_notify::watchdog_enabled() {
+		Some(timeout) => timeout,
+		None => return,
+	};
+
+	// Ping at half the timeout, per the systemd recommendation.
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(timeout / 2);
+
+		loop {
+			interval.tick().await;
+
+			if let Err(err) = sd_notify::notify(&[NotifyState::Watchdog]) {
+				log::warn!("failed to notify systemd watchdog: {}", err);
+			}
+		}
+	});
+}
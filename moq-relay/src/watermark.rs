@@ -0,0 +1,317 @@
+use std::{
+	collections::HashSet,
+	fmt::Write,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use moq_transport::{
+	serve::{Group, GroupReader, GroupSkip, GroupsReader, GroupsWriter, Track, TrackReader, TrackReaderMode},
+	session::Subscribed,
+};
+
+/// The subscription a payload is being sent to, passed to the [Watermark].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatermarkContext {
+	/// Identifies the subscriber's session, shared by each of its subscriptions.
+	pub session: u64,
+
+	/// Identifies the subscription, unique across every session.
+	pub subscription: u64,
+
+	pub namespace: String,
+	pub name: String,
+
+	/// The subscriber's authorization token, if provided, ex. to embed the viewer's ID.
+	pub token: Option<String>,
+}
+
+/// Transforms each payload before it's sent to a subscriber, ex. to embed a forensic watermark.
+///
+/// Called inline on the subscription's task for every object, so it must be fast and must not block.
+pub trait Watermark: Send + Sync {
+	/// Returns the payload to send to this subscriber instead of the original.
+	fn apply(&self, context: &WatermarkContext, group_id: u64, payload: Bytes) -> anyhow::Result<Bytes>;
+}
+
+/// Applies a [Watermark] to each subscription of the selected tracks.
+///
+/// Intended for low-rate tracks, ex. overlay metadata, since every subscriber receives its own copy of each group.
+/// Each call is timed against a CPU budget; a subscription is closed once it overruns the budget too many times,
+/// or if the watermark returns an error, rather than sending the original payload.
+/// Tracks that are also spliced are served by the [crate::Splicer] without a watermark.
+#[derive(Clone)]
+pub struct Watermarker {
+	tracks: Arc<HashSet<String>>,
+	watermark: Arc<dyn Watermark>,
+	budget: Duration,
+	max_overruns: u64,
+	subscriptions: Arc<AtomicU64>,
+	metrics: Arc<WatermarkMetrics>,
+}
+
+impl Watermarker {
+	/// The default CPU budget for each payload.
+	pub const BUDGET: Duration = Duration::from_millis(1);
+
+	/// The default number of overruns allowed before a subscription is closed.
+	pub const MAX_OVERRUNS: u64 = 10;
+
+	pub fn new(tracks: impl IntoIterator<Item = String>, watermark: Arc<dyn Watermark>) -> Self {
+		Self {
+			tracks: Arc::new(tracks.into_iter().collect()),
+			watermark,
+			budget: Self::BUDGET,
+			max_overruns: Self::MAX_OVERRUNS,
+			subscriptions: Default::default(),
+			metrics: Default::default(),
+		}
+	}
+
+	/// Change how long each payload may take, defaulting to [Self::BUDGET].
+	pub fn with_budget(mut self, budget: Duration) -> Self {
+		self.budget = budget;
+		self
+	}
+
+	/// Change how many overruns each subscription is allowed, defaulting to [Self::MAX_OVERRUNS].
+	pub fn with_max_overruns(mut self, max_overruns: u64) -> Self {
+		self.max_overruns = max_overruns;
+		self
+	}
+
+	/// Returns true if tracks with this name are watermarked.
+	pub fn selected(&self, name: &str) -> bool {
+		self.tracks.contains(name)
+	}
+
+	/// Serve the track to the subscriber, watermarking each payload.
+	///
+	/// Tracks that don't use groups are served unchanged.
+	pub async fn serve(&self, subscribe: Subscribed, session: u64, track: TrackReader) -> anyhow::Result<()> {
+		let input = match track.mode().await? {
+			// Read every group, leaving the subscriber's skip policy to the output.
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => return Ok(subscribe.serve(track).await?),
+		};
+
+		let context = WatermarkContext {
+			session,
+			subscription: self.subscriptions.fetch_add(1, Ordering::Relaxed),
+			namespace: track.namespace.clone(),
+			name: track.name.clone(),
+			token: subscribe.authorization(),
+		};
+
+		let (writer, reader) = Track::new(track.namespace.clone(), track.name.clone()).produce();
+		let output = writer.groups()?;
+
+		let serve = subscribe.serve(reader);
+		tokio::pin!(serve);
+
+		tokio::select! {
+			// Poll the subscription first, so it starts reading before the first group is written.
+			biased;
+			res = &mut serve => return Ok(res?),
+			res = self.run(&context, input, output) => res?,
+		};
+
+		// Wait until the subscriber has every group.
+		Ok(serve.await?)
+	}
+
+	async fn run(
+		&self,
+		context: &WatermarkContext,
+		mut input: GroupsReader,
+		mut output: GroupsWriter,
+	) -> anyhow::Result<()> {
+		let mut overruns = 0;
+
+		while let Some(group) = input.next().await? {
+			self.copy(context, group, &mut output, &mut overruns).await?;
+		}
+
+		Ok(())
+	}
+
+	async fn copy(
+		&self,
+		context: &WatermarkContext,
+		mut group: GroupReader,
+		output: &mut GroupsWriter,
+		overruns: &mut u64,
+	) -> anyhow::Result<()> {
+		let mut writer = output.create(Group {
+			group_id: group.group_id,
+			priority: group.priority,
+			timestamp: group.timestamp,
+			annotations: group.annotations.clone(),
+			subgroups: 0,
+			transit: None,
+		})?;
+
+		while let Some(mut object) = group.next().await? {
+			let payload = group.track.compression()?.decompress(object.read_all().await?)?;
+
+			let start = Instant::now();
+			let res = self.watermark.apply(context, group.group_id, payload);
+			let elapsed = start.elapsed();
+
+			self.metrics.record(elapsed, res.is_err(), elapsed > self.budget);
+
+			let payload = match res {
+				Ok(payload) => payload,
+				Err(err) => {
+					log::warn!("watermark failed: {:?} error={:?}", context, err);
+					return Err(err);
+				}
+			};
+
+			if elapsed > self.budget {
+				*overruns += 1;
+				log::debug!("watermark overran budget: {:?} elapsed={:?}", context, elapsed);
+				anyhow::ensure!(
+					*overruns <= self.max_overruns,
+					"watermark exceeded CPU budget: overruns={}",
+					overruns
+				);
+			}
+
+			// Keep the object's flags and extension headers, so subscribers can still find keyframes.
+			writer.write_with(payload, object.independent, object.extensions.clone())?;
+		}
+
+		Ok(())
+	}
+
+	/// Append the counters in the Prometheus text format.
+	pub fn write_metrics(&self, out: &mut String) {
+		let metrics = &self.metrics;
+
+		out.push_str("# HELP moq_relay_watermark_objects_total Payloads passed to the watermark.\n");
+		out.push_str("# TYPE moq_relay_watermark_objects_total counter\n");
+		writeln!(
+			out,
+			"moq_relay_watermark_objects_total {}",
+			metrics.objects.load(Ordering::Relaxed)
+		)
+		.unwrap();
+
+		out.push_str("# HELP moq_relay_watermark_errors_total Payloads the watermark failed to transform.\n");
+		out.push_str("# TYPE moq_relay_watermark_errors_total counter\n");
+		writeln!(
+			out,
+			"moq_relay_watermark_errors_total {}",
+			metrics.errors.load(Ordering::Relaxed)
+		)
+		.unwrap();
+
+		out.push_str("# HELP moq_relay_watermark_overruns_total Payloads that took longer than the CPU budget.\n");
+		out.push_str("# TYPE moq_relay_watermark_overruns_total counter\n");
+		writeln!(
+			out,
+			"moq_relay_watermark_overruns_total {}",
+			metrics.overruns.load(Ordering::Relaxed)
+		)
+		.unwrap();
+
+		out.push_str("# HELP moq_relay_watermark_seconds_total Time spent in the watermark.\n");
+		out.push_str("# TYPE moq_relay_watermark_seconds_total counter\n");
+		let nanos = metrics.nanos.load(Ordering::Relaxed);
+		writeln!(out, "moq_relay_watermark_seconds_total {}", nanos as f64 / 1e9).unwrap();
+	}
+}
+
+#[derive(Default)]
+struct WatermarkMetrics {
+	objects: AtomicU64,
+	errors: AtomicU64,
+	overruns: AtomicU64,
+	nanos: AtomicU64,
+}
+
+impl WatermarkMetrics {
+	fn record(&self, elapsed: Duration, error: bool, overrun: bool) {
+		self.objects.fetch_add(1, Ordering::Relaxed);
+		self.nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+
+		if error {
+			self.errors.fetch_add(1, Ordering::Relaxed);
+		}
+
+		if overrun {
+			self.overruns.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct Tag;
+
+	impl Watermark for Tag {
+		fn apply(&self, context: &WatermarkContext, _group_id: u64, payload: Bytes) -> anyhow::Result<Bytes> {
+			anyhow::ensure!(payload != "fail", "refused");
+
+			let token = context.token.as_deref().unwrap_or("anonymous");
+			Ok(format!("{}:{}", String::from_utf8_lossy(&payload), token).into())
+		}
+	}
+
+	#[tokio::test]
+	async fn watermark() {
+		let watermarker = Watermarker::new(["overlay".to_string()], Arc::new(Tag)).with_budget(Duration::from_secs(1));
+		assert!(watermarker.selected("overlay"));
+		assert!(!watermarker.selected("video"));
+
+		let context = WatermarkContext {
+			session: 1,
+			subscription: 2,
+			namespace: "live".to_string(),
+			name: "overlay".to_string(),
+			token: Some("viewer".to_string()),
+		};
+
+		let (writer, reader) = Track::new("live".to_string(), "overlay".to_string()).produce();
+		let mut input = writer.groups().unwrap();
+
+		let (writer, marked) = Track::new("live".to_string(), "overlay".to_string()).produce();
+		let output = writer.groups().unwrap();
+
+		let input_reader = match reader.mode().await.unwrap() {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => panic!("expected groups"),
+		};
+
+		let task = tokio::spawn({
+			let watermarker = watermarker.clone();
+			async move { watermarker.run(&context, input_reader, output).await }
+		});
+
+		let mut marked = match marked.mode().await.unwrap() {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => panic!("expected groups"),
+		};
+
+		input.append(0).unwrap().write("hello".into()).unwrap();
+
+		let mut group = marked.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "hello:viewer");
+
+		// A failed watermark closes the subscription instead of sending the original.
+		input.append(0).unwrap().write("fail".into()).unwrap();
+		assert!(task.await.unwrap().is_err());
+
+		let mut metrics = String::new();
+		watermarker.write_metrics(&mut metrics);
+		assert!(metrics.contains("moq_relay_watermark_objects_total 2"));
+		assert!(metrics.contains("moq_relay_watermark_errors_total 1"));
+	}
+}
@@ -1,12 +1,36 @@
 use std::{net, sync::Arc};
 
-use axum::{extract::State, http::Method, response::IntoResponse, routing::get, Router};
+use axum::{
+	extract::{Path, State},
+	http::{header, uri::Authority, HeaderMap, StatusCode, Uri},
+	response::{Html, IntoResponse},
+	routing::get,
+	Router,
+};
 use hyper_serve::tls_rustls::RustlsAcceptor;
-use tower_http::cors::{Any, CorsLayer};
+
+use crate::Cors;
 
 pub struct WebConfig {
 	pub bind: net::SocketAddr,
 	pub tls: moq_native::tls::Config,
+
+	/// Serve the certificate fingerprint at `/fingerprint`, for self-signed certificates.
+	pub dev: bool,
+
+	/// Serve a demo player at `/watch/{name}`.
+	pub player: bool,
+
+	/// The CORS and caching headers for every route.
+	pub cors: Cors,
+}
+
+// The bundled player, with the config substituted at request time.
+const PLAYER: &str = include_str!("player.html");
+
+struct WebState {
+	tls: moq_native::tls::Config,
+	dev: bool,
 }
 
 // Run a HTTP server using Axum
@@ -18,18 +42,22 @@ pub struct Web {
 
 impl Web {
 	pub fn new(config: WebConfig) -> Self {
-		// Get the first certificate's fingerprint.
-		// TODO serve all of them so we can support multiple signature algorithms.
-		let fingerprint = config.tls.fingerprints.first().expect("missing certificate").clone();
-
-		let mut tls = config.tls.server.expect("missing server configuration");
+		let mut tls = config.tls.server.clone().expect("missing server configuration");
 		tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
 		let tls = hyper_serve::tls_rustls::RustlsConfig::from_config(Arc::new(tls));
 
-		let app = Router::new()
-			.route("/fingerprint", get(serve_fingerprint))
-			.layer(CorsLayer::new().allow_origin(Any).allow_methods([Method::GET]))
-			.with_state(fingerprint);
+		let mut app = Router::new();
+		if config.dev {
+			app = app.route("/fingerprint", get(serve_fingerprint));
+		}
+		if config.player {
+			app = app.route("/watch/:name", get(serve_player));
+		}
+
+		let app = config.cors.apply(app).with_state(Arc::new(WebState {
+			tls: config.tls,
+			dev: config.dev,
+		}));
 
 		let server = hyper_serve::bind_rustls(config.bind, tls);
 
@@ -42,6 +70,131 @@ impl Web {
 	}
 }
 
-async fn serve_fingerprint(State(fingerprint): State<String>) -> impl IntoResponse {
-	fingerprint
+// Serve the fingerprint of the certificate for the requested domain, so each tenant gets their own.
+// TODO serve all of them so we can support multiple signature algorithms.
+async fn serve_fingerprint(State(state): State<Arc<WebState>>, headers: HeaderMap, uri: Uri) -> impl IntoResponse {
+	let host = authority(&headers, &uri).map(hostname);
+	state.tls.fingerprint(host).ok_or(StatusCode::NOT_FOUND)
+}
+
+// Serve the player for the requested broadcast, connecting back to the same host and port over WebTransport.
+// The fingerprint is only included in development mode, since browsers reject it for long-lived certificates.
+async fn serve_player(
+	State(state): State<Arc<WebState>>,
+	Path(name): Path<String>,
+	headers: HeaderMap,
+	uri: Uri,
+) -> impl IntoResponse {
+	let host = authority(&headers, &uri).ok_or(StatusCode::BAD_REQUEST)?;
+
+	let fingerprint = match state.dev {
+		true => state.tls.fingerprint(Some(hostname(host))),
+		false => None,
+	};
+
+	let config = serde_json::json!({
+		"url": format!("https://{}/", host),
+		"name": name,
+		"fingerprint": fingerprint,
+	});
+
+	// Escape '<' so the name can't close the script tag.
+	let config = config.to_string().replace('<', "\\u003c");
+
+	Ok::<_, StatusCode>(Html(PLAYER.replace("{{CONFIG}}", &config)))
+}
+
+// Returns the requested host and port, from the Host header or the URI with HTTP/2, which has no Host header.
+fn authority<'a>(headers: &'a HeaderMap, uri: &'a Uri) -> Option<&'a str> {
+	match headers.get(header::HOST) {
+		Some(host) => host.to_str().ok(),
+		None => uri.authority().map(Authority::as_str),
+	}
+}
+
+// Returns the host without the port, or the brackets around an IPv6 literal.
+fn hostname(authority: &str) -> &str {
+	match authority.strip_prefix('[') {
+		Some(ipv6) => ipv6.split_once(']').map_or(authority, |(host, _)| host),
+		None => authority.split_once(':').map_or(authority, |(host, _)| host),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{body::Body, http::Request};
+	use clap::Parser;
+	use tower::ServiceExt;
+
+	#[test]
+	fn hostnames() {
+		assert_eq!(hostname("example.com"), "example.com");
+		assert_eq!(hostname("example.com:4443"), "example.com");
+		assert_eq!(hostname("127.0.0.1:4443"), "127.0.0.1");
+		assert_eq!(hostname("[::1]"), "::1");
+		assert_eq!(hostname("[::1]:4443"), "::1");
+		assert_eq!(hostname("[2001:db8::1]:443"), "2001:db8::1");
+	}
+
+	fn cert(domain: &str) -> Arc<rustls::sign::CertifiedKey> {
+		let cert = rcgen::generate_simple_self_signed(vec![domain.to_string()]).unwrap();
+		moq_native::tls::ServeCerts::parse(cert.cert.pem().as_bytes(), cert.key_pair.serialize_pem().as_bytes())
+			.unwrap()
+	}
+
+	async fn get(app: &Router, uri: &str, host: Option<&str>) -> (StatusCode, String) {
+		let mut req = Request::get(uri);
+		if let Some(host) = host {
+			req = req.header(header::HOST, host);
+		}
+
+		let res = app.clone().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+		let status = res.status();
+		let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+		(status, String::from_utf8(body.to_vec()).unwrap())
+	}
+
+	#[tokio::test]
+	async fn host() {
+		let mut tls = moq_native::tls::Args::default().load().unwrap();
+		let (a, b) = (cert("a.example.com"), cert("b.example.com"));
+		tls.certs.replace(None, b.clone());
+		tls.certs.replace(None, a.clone());
+		tls.enable_server().unwrap();
+
+		let fingerprint =
+			|ck: &rustls::sign::CertifiedKey| hex::encode(ring::digest::digest(&ring::digest::SHA256, &ck.cert[0]));
+
+		let args = crate::CorsArgs::parse_from(["moq-relay"]);
+		let web = Web::new(WebConfig {
+			bind: "127.0.0.1:0".parse().unwrap(),
+			tls,
+			dev: true,
+			player: true,
+			cors: Cors::new(&args).unwrap(),
+		});
+
+		// The Host header selects the certificate, ignoring the port.
+		let res = get(&web.app, "/fingerprint", Some("b.example.com:4443")).await;
+		assert_eq!(res, (StatusCode::OK, fingerprint(&b)));
+		let res = get(&web.app, "/fingerprint", Some("a.example.com")).await;
+		assert_eq!(res, (StatusCode::OK, fingerprint(&a)));
+
+		// HTTP/2 has no Host header, only the authority in the URI.
+		let res = get(&web.app, "https://b.example.com:4443/fingerprint", None).await;
+		assert_eq!(res, (StatusCode::OK, fingerprint(&b)));
+
+		// IPv6 literals don't match either name, so they get the default certificate.
+		let res = get(&web.app, "/fingerprint", Some("[::1]:4443")).await;
+		assert_eq!(res, (StatusCode::OK, fingerprint(&b)));
+
+		// The player connects back to the same host and port.
+		let (status, html) = get(&web.app, "https://[::1]:4443/watch/live", None).await;
+		assert_eq!(status, StatusCode::OK);
+		assert!(html.contains(r#""url":"https://[::1]:4443/""#), "{}", html);
+
+		let (status, _) = get(&web.app, "/watch/live", None).await;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+	}
 }
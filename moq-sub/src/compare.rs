@@ -0,0 +1,279 @@
+//! Compare a track received through two relays, ex. to validate a new relay deployment against an existing one.
+//!
+//! Each group is timed on both paths, from when its stream arrives until it ends, and printed once it ended on both.
+//! Times are relative to when the comparison started, so the subscriptions should be sent at the same time.
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use moq_transport::serve::{GroupReader, GroupSkip, TrackReader, TrackReaderMode};
+use tokio::{sync::Notify, task::JoinSet};
+
+/// Which relay a track was received through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Path {
+	A,
+	B,
+}
+
+// When a group arrived on one path, and what it contained so far.
+#[derive(Debug, Clone, Copy)]
+struct Arrival {
+	first: Duration,
+
+	// When the group ended, or None while it's still arriving.
+	last: Option<Duration>,
+
+	objects: u64,
+	bytes: u64,
+
+	// The group ended cleanly instead of being reset.
+	complete: bool,
+}
+
+impl Arrival {
+	fn json(&self) -> serde_json::Value {
+		serde_json::json!({
+			"first_ms": ms(self.first),
+			"last_ms": self.last.map(ms),
+			"objects": self.objects,
+			"bytes": self.bytes,
+			"complete": self.complete,
+		})
+	}
+}
+
+#[derive(Default)]
+struct Arrivals {
+	a: Option<Arrival>,
+	b: Option<Arrival>,
+}
+
+struct CompareState {
+	start: Instant,
+	groups: BTreeMap<u64, Arrivals>,
+
+	// The groups printed so far, which ended on both paths.
+	reported: u64,
+	limit: Option<u64>,
+}
+
+/// Times each group of a track on two paths, printing a line of JSON to stdout per group and a summary at the end.
+#[derive(Clone)]
+pub struct Compare {
+	state: Arc<Mutex<CompareState>>,
+	done: Arc<Notify>,
+}
+
+impl Compare {
+	/// Start timing now, stopping after `limit` groups have ended on both paths if provided.
+	pub fn new(limit: Option<u64>) -> Self {
+		let state = CompareState {
+			start: Instant::now(),
+			groups: BTreeMap::new(),
+			reported: 0,
+			limit,
+		};
+
+		Self {
+			state: Arc::new(Mutex::new(state)),
+			done: Arc::new(Notify::new()),
+		}
+	}
+
+	/// Read both tracks until they end, or until the limit is reached.
+	pub async fn run(&self, a: TrackReader, b: TrackReader) -> anyhow::Result<()> {
+		tokio::select! {
+			res = async { tokio::try_join!(self.read(Path::A, a), self.read(Path::B, b)) } => res.map(|_| ()),
+			_ = self.done.notified() => Ok(()),
+		}
+	}
+
+	async fn read(&self, path: Path, track: TrackReader) -> anyhow::Result<()> {
+		// Every group is timed, even if we fall behind.
+		let mut groups = match track.mode().await? {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Never),
+			_ => anyhow::bail!("only tracks using groups can be compared"),
+		};
+
+		let mut tasks = JoinSet::new();
+
+		loop {
+			tokio::select! {
+				res = groups.next() => match res? {
+					Some(group) => {
+						let this = self.clone();
+						tasks.spawn(async move { this.read_group(path, group).await });
+					}
+					None => break,
+				},
+				Some(_) = tasks.join_next() => {},
+			}
+		}
+
+		while tasks.join_next().await.is_some() {}
+
+		Ok(())
+	}
+
+	// Record the group as it arrives, so it's included in the summary even if it never ends.
+	async fn read_group(&self, path: Path, mut group: GroupReader) {
+		let group_id = group.group_id;
+		self.update(path, group_id, |_| {});
+
+		loop {
+			match group.read_next().await {
+				Ok(Some(payload)) => self.update(path, group_id, |arrival| {
+					arrival.objects += 1;
+					arrival.bytes += payload.len() as u64;
+				}),
+				Ok(None) => {
+					self.finish(path, group_id, true);
+					return;
+				}
+				Err(err) => {
+					log::debug!("group failed: path={:?} group={} error={}", path, group_id, err);
+					self.finish(path, group_id, false);
+					return;
+				}
+			}
+		}
+	}
+
+	fn update<F: FnOnce(&mut Arrival)>(&self, path: Path, group_id: u64, f: F) {
+		let mut state = self.state.lock().unwrap();
+		let now = state.start.elapsed();
+
+		let arrivals = state.groups.entry(group_id).or_default();
+		let arrival = match path {
+			Path::A => &mut arrivals.a,
+			Path::B => &mut arrivals.b,
+		};
+
+		f(arrival.get_or_insert(Arrival {
+			first: now,
+			last: None,
+			objects: 0,
+			bytes: 0,
+			complete: false,
+		}));
+	}
+
+	// Print the group once it's ended on both paths.
+	fn finish(&self, path: Path, group_id: u64, complete: bool) {
+		let mut state = self.state.lock().unwrap();
+		let now = state.start.elapsed();
+
+		let arrivals = state.groups.entry(group_id).or_default();
+		let arrival = match path {
+			Path::A => &mut arrivals.a,
+			Path::B => &mut arrivals.b,
+		};
+
+		if let Some(arrival) = arrival {
+			arrival.last = Some(now);
+			arrival.complete = complete;
+		}
+
+		let (a, b) = match (&arrivals.a, &arrivals.b) {
+			(Some(a), Some(b)) if a.last.is_some() && b.last.is_some() => (a, b),
+			_ => return,
+		};
+
+		println!(
+			"{}",
+			serde_json::json!({
+				"group": group_id,
+				"a": a.json(),
+				"b": b.json(),
+				"first_delta_ms": delta(a.first, b.first),
+				"last_delta_ms": a.last.zip(b.last).map(|(a, b)| delta(a, b)),
+			})
+		);
+
+		state.reported += 1;
+		if state.limit.is_some_and(|limit| state.reported >= limit) {
+			self.done.notify_one();
+		}
+	}
+
+	/// Print the groups that haven't ended on both paths, then a summary of both paths.
+	///
+	/// A positive delta means the group arrived later through B than through A.
+	pub fn summary(&self) {
+		let state = self.state.lock().unwrap();
+
+		let mut first = Vec::new();
+		let mut last = Vec::new();
+
+		for (group_id, arrivals) in &state.groups {
+			if let (Some(a), Some(b)) = (&arrivals.a, &arrivals.b) {
+				first.push(delta(a.first, b.first));
+			}
+
+			match (arrivals.a.and_then(|a| a.last), arrivals.b.and_then(|b| b.last)) {
+				(Some(a), Some(b)) => last.push(delta(a, b)),
+				_ => println!(
+					"{}",
+					serde_json::json!({
+						"group": group_id,
+						"a": arrivals.a.as_ref().map(Arrival::json),
+						"b": arrivals.b.as_ref().map(Arrival::json),
+					})
+				),
+			}
+		}
+
+		let path = |arrival: fn(&Arrivals) -> Option<&Arrival>| {
+			let received: Vec<_> = state.groups.values().filter_map(arrival).collect();
+			serde_json::json!({
+				"groups": received.len(),
+				"complete": received.iter().filter(|arrival| arrival.complete).count(),
+				"missing": state.groups.len() - received.len(),
+				"objects": received.iter().map(|arrival| arrival.objects).sum::<u64>(),
+				"bytes": received.iter().map(|arrival| arrival.bytes).sum::<u64>(),
+			})
+		};
+
+		println!(
+			"{}",
+			serde_json::json!({
+				"summary": {
+					"groups": state.groups.len(),
+					"a": path(|arrivals| arrivals.a.as_ref()),
+					"b": path(|arrivals| arrivals.b.as_ref()),
+					"first_delta_ms": stats(first),
+					"last_delta_ms": stats(last),
+				}
+			})
+		);
+	}
+}
+
+fn ms(duration: Duration) -> f64 {
+	duration.as_secs_f64() * 1000.0
+}
+
+// How much later B was than A, in milliseconds.
+fn delta(a: Duration, b: Duration) -> f64 {
+	ms(b) - ms(a)
+}
+
+fn stats(mut deltas: Vec<f64>) -> serde_json::Value {
+	if deltas.is_empty() {
+		return serde_json::Value::Null;
+	}
+
+	deltas.sort_by(f64::total_cmp);
+	let percentile = |p: f64| deltas[((deltas.len() - 1) as f64 * p).round() as usize];
+
+	serde_json::json!({
+		"mean": deltas.iter().sum::<f64>() / deltas.len() as f64,
+		"p50": percentile(0.5),
+		"p95": percentile(0.95),
+		"min": deltas[0],
+		"max": deltas[deltas.len() - 1],
+	})
+}
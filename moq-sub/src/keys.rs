@@ -0,0 +1,83 @@
+//! Decrypts end-to-end encrypted objects, requesting the content key from the publisher through the relay.
+use std::collections::HashMap;
+
+use anyhow::Context;
+use moq_native::{
+	crypto::{self, ContentKey, KeyRequester},
+	sign::SigningKey,
+};
+use moq_transport::{
+	serve::{Track, TrackReaderMode},
+	session::Subscriber,
+};
+use tokio::sync::Mutex;
+
+/// Holds the content keys received so far, requesting a new one whenever an object uses an unknown key ID.
+pub struct Decryptor {
+	subscriber: Subscriber,
+	identity: SigningKey,
+	namespace: String,
+
+	// Keys by ID, since objects encrypted with the previous key may arrive after a rotation.
+	// Locked across requests, so concurrent tracks don't all request the same key.
+	keys: Mutex<HashMap<u64, ContentKey>>,
+}
+
+impl Decryptor {
+	pub fn new(subscriber: Subscriber, identity: SigningKey, namespace: String) -> Self {
+		Self {
+			subscriber,
+			identity,
+			namespace,
+			keys: Default::default(),
+		}
+	}
+
+	/// Decrypt the payload, first requesting the key if it hasn't been received yet.
+	pub async fn decrypt(&self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+		let id = crypto::key_id(payload).context("truncated payload")?;
+
+		let mut keys = self.keys.lock().await;
+		if !keys.contains_key(&id) {
+			let key = self.request().await?;
+			log::info!("received content key: id={}", key.id());
+			keys.insert(key.id(), key);
+		}
+
+		let key = keys
+			.get(&id)
+			.with_context(|| format!("content key not available: id={}", id))?;
+		key.decrypt(payload)
+	}
+
+	/// Request the current content key, failing if the publisher doesn't authorize our identity.
+	pub async fn request(&self) -> anyhow::Result<ContentKey> {
+		let requester = KeyRequester::new(&self.identity, &self.namespace)?;
+		let (writer, reader) = Track::new(self.namespace.clone(), requester.track_name()).produce();
+
+		let mut subscriber = self.subscriber.clone();
+		let subscribe = subscriber.subscribe(writer);
+
+		let response = async {
+			let mut group = match reader.mode().await? {
+				TrackReaderMode::Groups(mut groups) => groups.next().await?.context("no key response")?,
+				_ => anyhow::bail!("expected groups for key response"),
+			};
+
+			group.read_next().await?.context("empty key response")
+		};
+		tokio::pin!(response);
+
+		// The response is still readable if the subscription ends first.
+		let response = tokio::select! {
+			biased;
+			res = &mut response => res?,
+			res = subscribe => {
+				res.context("key request rejected")?;
+				response.await?
+			}
+		};
+
+		requester.open(&response)
+	}
+}
@@ -1 +1,7 @@
+pub mod compare;
+pub mod keys;
 pub mod media;
+pub mod mfra;
+pub mod report;
+pub mod resume;
+pub mod sync;
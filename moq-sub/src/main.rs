@@ -1,12 +1,22 @@
-use std::net;
+use std::{net, path::PathBuf, time};
 
 use anyhow::Context;
 use clap::Parser;
 use url::Url;
 
-use moq_native::quic;
-use moq_sub::media::Media;
-use moq_transport::serve::Tracks;
+use moq_native::{preset::Preset, quic};
+use moq_sub::{
+	compare::Compare,
+	media::Media,
+	mfra,
+	report::Receiver,
+	resume::ResumeState,
+	sync::{SyncDrop, TrackSync},
+};
+use moq_transport::{
+	serve::{Track, Tracks},
+	session::{Session, SubscribeIds, Subscriber},
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -18,28 +28,65 @@ async fn main() -> anyhow::Result<()> {
 		.finish();
 	tracing::subscriber::set_global_default(tracer).unwrap();
 
-	let out = tokio::io::stdout();
-
 	let config = Config::parse();
+	config.log.init();
+
+	if let (Some(from), Some(to)) = (config.from_group, config.to_group) {
+		anyhow::ensure!(from <= to, "--from-group must not be after --to-group");
+	}
 	let tls = config.tls.load()?;
-	let quic = quic::Endpoint::new(quic::Config { bind: config.bind, tls })?;
+	let identity = config.crypto.load()?;
+	let quic_config = quic::Config {
+		bind: config.bind,
+		tls,
+		congestion: Default::default(),
+	};
 
-	let session = quic.client.connect(&config.url).await?;
+	#[cfg(feature = "netem")]
+	let quic = quic::Endpoint::with_netem(quic_config, &config.netem)?;
+	#[cfg(not(feature = "netem"))]
+	let quic = quic::Endpoint::new(quic_config)?;
 
-	let (session, subscriber) = moq_transport::session::Subscriber::connect(session)
-		.await
-		.context("failed to create MoQ Transport session")?;
+	let (session, subscriber) = connect(&quic, &config.url, &config).await?;
+
+	if let Some(url) = &config.compare {
+		let (other, other_subscriber) = connect(&quic, url, &config).await?;
+		return compare(&config, (session, subscriber), (other, other_subscriber)).await;
+	}
+
+	let (out, resume) = open_output(&config).await?;
 
 	// Associate empty set of Tracks with provided namespace
-	let tracks = Tracks::new(config.name);
+	let tracks = Tracks::new(config.name.clone());
+
+	let sync = config
+		.sync_window_ms()
+		.map(|window| TrackSync::new(time::Duration::from_millis(window), config.sync_drop()));
+
+	let report = config.report.then(Receiver::new);
 
-	let mut media = Media::new(subscriber, tracks, out).await?;
+	let skip = config.preset.map(|preset| preset.skip()).unwrap_or_default();
+	let mut media = Media::new(subscriber, tracks, out, resume, sync, report)
+		.await?
+		.with_skip(skip)
+		.with_range(config.from_group, config.to_group)
+		.with_decryption(identity);
 
+	// Returns once every track has ended cleanly, or with an error if any of them failed.
 	tokio::select! {
 		res = session.run() => res.context("session error")?,
 		res = media.run() => res.context("media error")?,
 	}
 
+	if config.finalize {
+		let path = config.output.clone().context("missing output")?;
+		tokio::task::spawn_blocking(move || mfra::append(&path))
+			.await?
+			.context("failed to finalize output")?;
+	}
+
+	log::info!("broadcast ended");
+
 	Ok(())
 }
 
@@ -49,7 +96,7 @@ pub struct Config {
 	#[arg(long, default_value = "[::]:0")]
 	pub bind: net::SocketAddr,
 
-	/// Connect to the given URL starting with https://
+	/// Connect to the given URL starting with https:// for WebTransport, moqt:// for raw QUIC, or moqt+tcp:// for TCP
 	#[arg(value_parser = moq_url)]
 	pub url: Url,
 
@@ -60,14 +107,207 @@ pub struct Config {
 	/// The TLS configuration.
 	#[command(flatten)]
 	pub tls: moq_native::tls::Args,
+
+	/// The log sampling configuration.
+	#[command(flatten)]
+	pub log: moq_native::log::Args,
+
+	/// Simulate a degraded network, for testing.
+	#[cfg(feature = "netem")]
+	#[command(flatten)]
+	pub netem: moq_native::netem::Args,
+
+	/// Decrypt end-to-end encrypted media.
+	#[command(flatten)]
+	pub crypto: moq_native::crypto::Args,
+
+	/// Write to the given file instead of stdout.
+	#[arg(long)]
+	pub output: Option<PathBuf>,
+
+	/// Record progress next to the output file and continue from the last complete group when restarted.
+	#[arg(long, requires = "output")]
+	pub resume: bool,
+
+	/// Append an `mfra` index once the broadcast ends cleanly, so the file is seekable.
+	#[arg(long, requires = "output")]
+	pub finalize: bool,
+
+	/// Start from coherent defaults for latency or quality, overridden by any flags provided explicitly.
+	/// This also chooses whether to skip groups when writing the output falls behind.
+	#[arg(long, value_enum)]
+	pub preset: Option<Preset>,
+
+	/// Release groups in timestamp order across tracks, waiting up to this long for a stalled track.
+	/// [default: disabled, or 100/500/2000 with --preset latency/balanced/quality]
+	#[arg(long)]
+	pub sync_window_ms: Option<u64>,
+
+	/// What to do with groups from a track that falls behind the others by more than the sync window.
+	/// [default: late, or never with --preset quality]
+	#[arg(long, value_enum)]
+	pub sync_drop: Option<SyncDrop>,
+
+	/// Compare the publisher's sender reports with what was received, printing each receiver report to stderr as JSON.
+	#[arg(long)]
+	pub report: bool,
+
+	/// Derive subscribe IDs from a hash of the namespace and track name instead of counting up,
+	/// so they match across hosts, and log each ID's track at debug level.
+	#[arg(long)]
+	pub hashed_subscribe_ids: bool,
+
+	/// Start each track at this group ID instead of the latest, ex. to extract a clip from the relay's DVR cache.
+	#[arg(long)]
+	pub from_group: Option<u64>,
+
+	/// Stop after this group ID, fetching the range from the relay's cache instead of subscribing.
+	#[arg(long)]
+	pub to_group: Option<u64>,
+
+	/// Authorize each subscription with the token in this file.
+	/// The file is read again whenever the relay asks for a new token, so it can be refreshed externally.
+	#[arg(long)]
+	pub token_file: Option<PathBuf>,
+
+	/// Don't ask for the catalog and init tracks at the highest priority, ahead of media.
+	#[arg(long)]
+	pub no_bootstrap_priority: bool,
+
+	/// Also subscribe through this relay and compare it with the first one, instead of writing media.
+	/// Each group's arrival time and completeness on both paths is printed to stdout as JSON, then a summary.
+	#[arg(long, value_parser = moq_url, requires = "compare_track", conflicts_with = "output")]
+	pub compare: Option<Url>,
+
+	/// The track to compare, ex. `1.m4s`.
+	#[arg(long, requires = "compare")]
+	pub compare_track: Option<String>,
+
+	/// Stop comparing once this many groups have arrived through both relays.
+	/// [default: until the track ends or Ctrl-C]
+	#[arg(long, requires = "compare")]
+	pub compare_groups: Option<u64>,
+}
+
+// Connect to a relay as a subscriber, applying the flags shared by every session.
+async fn connect(quic: &quic::Endpoint, url: &Url, config: &Config) -> anyhow::Result<(Session, Subscriber)> {
+	let session = quic.client.connect(url).await?;
+
+	let options = quic::setup_options(url, moq_transport::setup::Role::Subscriber);
+	let (session, subscriber) = Subscriber::connect_with(session, options)
+		.await
+		.context("failed to create MoQ Transport session")?;
+
+	let subscriber = match config.hashed_subscribe_ids {
+		true => subscriber.with_ids(SubscribeIds::Hashed),
+		false => subscriber,
+	};
+	let subscriber = subscriber.with_bootstrap_priority(!config.no_bootstrap_priority);
+
+	let subscriber = match config.token_file.clone() {
+		Some(path) => {
+			let token = read_token(&path).await?;
+			subscriber.with_token(token).with_token_refresh(move |info| {
+				let path = path.clone();
+				async move {
+					match read_token(&path).await {
+						Ok(token) => Some(token),
+						Err(err) => {
+							log::warn!("failed to renew token: {:?}, error: {}", info, err);
+							None
+						}
+					}
+				}
+			})
+		}
+		None => subscriber,
+	};
+
+	Ok((session, subscriber))
+}
+
+// Subscribe to the same track through both relays, printing when each group arrives instead of writing media.
+async fn compare(
+	config: &Config,
+	(a, mut a_subscriber): (Session, Subscriber),
+	(b, mut b_subscriber): (Session, Subscriber),
+) -> anyhow::Result<()> {
+	let name = config.compare_track.clone().context("missing track to compare")?;
+	let (a_track, a_reader) = Track::new(config.name.clone(), name.clone()).produce();
+	let (b_track, b_reader) = Track::new(config.name.clone(), name).produce();
+
+	let compare = Compare::new(config.compare_groups);
+
+	let res = tokio::select! {
+		res = a.run() => res.context("session error: a"),
+		res = b.run() => res.context("session error: b"),
+		// Keep reading the other path once a subscription ends cleanly.
+		Err(err) = a_subscriber.subscribe(a_track) => Err(err).context("subscribe error: a"),
+		Err(err) = b_subscriber.subscribe(b_track) => Err(err).context("subscribe error: b"),
+		res = compare.run(a_reader, b_reader) => res,
+		res = tokio::signal::ctrl_c() => res.context("failed to wait for ctrl-c"),
+	};
+
+	// Whatever was received is summarized, even if one of the paths failed.
+	compare.summary();
+
+	res
+}
+
+async fn read_token(path: &std::path::Path) -> anyhow::Result<String> {
+	let token = tokio::fs::read_to_string(path)
+		.await
+		.with_context(|| format!("failed to read token: {}", path.display()))?;
+
+	Ok(token.trim().to_string())
+}
+
+impl Config {
+	fn sync_window_ms(&self) -> Option<u64> {
+		self.sync_window_ms.or(match self.preset? {
+			Preset::Latency => Some(100),
+			Preset::Balanced => Some(500),
+			Preset::Quality => Some(2000),
+		})
+	}
+
+	fn sync_drop(&self) -> SyncDrop {
+		self.sync_drop.unwrap_or(match self.preset {
+			Some(Preset::Quality) => SyncDrop::Never,
+			_ => SyncDrop::Late,
+		})
+	}
+}
+
+type Output = Box<dyn tokio::io::AsyncWrite + Send + Unpin>;
+
+async fn open_output(config: &Config) -> anyhow::Result<(Output, Option<(PathBuf, ResumeState)>)> {
+	let path = match &config.output {
+		Some(path) => path,
+		None => return Ok((Box::new(tokio::io::stdout()), None)),
+	};
+
+	if !config.resume {
+		let file = tokio::fs::File::create(path).await.context("failed to create output")?;
+		return Ok((Box::new(file), None));
+	}
+
+	// Discard anything written after the last complete group, ex. a partial group.
+	let (file, state_path, state) = ResumeState::open(path).await?;
+
+	if state.offset > 0 {
+		log::info!("resuming output: offset={} groups={:?}", state.offset, state.groups);
+	}
+
+	Ok((Box::new(file), Some((state_path, state))))
 }
 
 fn moq_url(s: &str) -> Result<Url, String> {
 	let url = Url::try_from(s).map_err(|e| e.to_string())?;
 
-	// Make sure the scheme is moq
-	if url.scheme() != "https" {
-		return Err("url scheme must be https:// for WebTransport".to_string());
+	// Make sure the scheme is one quic::Client::connect supports
+	if !matches!(url.scheme(), "https" | "moqt" | "moqt+tcp") {
+		return Err("url scheme must be https://, moqt://, or moqt+tcp://".to_string());
 	}
 
 	Ok(url)
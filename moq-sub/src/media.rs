@@ -1,11 +1,12 @@
-use std::{io::Cursor, sync::Arc};
+use std::{collections::HashMap, io::Cursor, path::PathBuf, sync::Arc};
 
 use anyhow::Context;
 use log::{debug, info, trace, warn};
 use moq_transport::serve::{
-	GroupObjectReader, GroupReader, TrackReader, TrackReaderMode, Tracks, TracksReader, TracksWriter,
+	GroupObjectReader, GroupReader, GroupSkip, StreamGroupReader, TrackEntry, TrackReader, TrackReaderMode, Tracks,
+	TracksReader, TracksWriter,
 };
-use moq_transport::session::Subscriber;
+use moq_transport::session::{FetchOptions, SubscribeOptions, Subscriber};
 use mp4::ReadBox;
 use tokio::{
 	io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
@@ -13,39 +14,202 @@ use tokio::{
 	task::JoinSet,
 };
 
+use crate::{keys::Decryptor, report::Receiver, resume::ResumeState, sync::TrackSync};
+
 pub struct Media<O> {
 	subscriber: Subscriber,
 	broadcast: TracksReader,
 	tracks_writer: TracksWriter,
-	output: Arc<Mutex<O>>,
+	output: Arc<Mutex<Output<O>>>,
+	sync: Option<Arc<TrackSync>>,
+	report: Option<Arc<Receiver>>,
+	skip: GroupSkip,
+	from_group: Option<u64>,
+	to_group: Option<u64>,
+	keys: Option<Arc<Decryptor>>,
+}
+
+struct Output<O> {
+	writer: O,
+
+	// The number of bytes in the output, including any from a previous run.
+	written: u64,
+
+	// The sidecar file and progress, if resuming is enabled.
+	resume: Option<(PathBuf, ResumeState)>,
+
+	// The offset where each group being written started, keyed by the ID returned from `start`.
+	// Groups interleave in the output, so resuming can't start after the oldest one.
+	writing: HashMap<u64, u64>,
+	next: u64,
+
+	// Groups that finished but end after the oldest group still being written, along with that end offset.
+	finished: Vec<(String, u64, u64)>,
+}
+
+impl<O: AsyncWrite + Unpin> Output<O> {
+	fn new(writer: O, resume: Option<(PathBuf, ResumeState)>) -> Self {
+		Self {
+			writer,
+			written: resume.as_ref().map(|(_, state)| state.offset).unwrap_or_default(),
+			resume,
+			writing: HashMap::new(),
+			next: 0,
+			finished: Vec::new(),
+		}
+	}
+
+	async fn write(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+		self.writer.write_all(buf).await?;
+		self.written += buf.len() as u64;
+		Ok(())
+	}
+
+	// Returns true if the group was completed by a previous run.
+	fn completed(&self, track: &str, group_id: u64) -> bool {
+		match &self.resume {
+			Some((_, state)) => state.groups.get(track).is_some_and(|last| group_id <= *last),
+			None => false,
+		}
+	}
+
+	// Called before writing a group, returning an ID that must be passed to `complete` or `abort`.
+	fn start(&mut self) -> u64 {
+		let id = self.next;
+		self.next += 1;
+		self.writing.insert(id, self.written);
+		id
+	}
+
+	// The group was written completely, so record it once every earlier group has finished too.
+	async fn complete(&mut self, id: u64, track: &str, group_id: u64) -> anyhow::Result<()> {
+		self.writing.remove(&id);
+		if self.resume.is_some() {
+			self.finished.push((track.to_string(), group_id, self.written));
+		}
+
+		self.save().await
+	}
+
+	// The group wasn't written completely, but it's no longer holding back the resume offset.
+	// The partial group stays in the output, as it would without resuming.
+	async fn abort(&mut self, id: u64) -> anyhow::Result<()> {
+		self.writing.remove(&id);
+		self.save().await
+	}
+
+	// Save the offset where the oldest group being written started, along with the groups that end before it.
+	// Anything after the offset is discarded when resuming, so those groups are received again.
+	async fn save(&mut self) -> anyhow::Result<()> {
+		let (path, state) = match &mut self.resume {
+			Some(resume) => resume,
+			None => return Ok(()),
+		};
+
+		let offset = self.writing.values().copied().min().unwrap_or(self.written);
+		let (done, finished) = std::mem::take(&mut self.finished)
+			.into_iter()
+			.partition::<Vec<_>, _>(|(_, _, end)| *end <= offset);
+		self.finished = finished;
+
+		if offset == state.offset && done.is_empty() {
+			return Ok(());
+		}
+
+		for (track, group_id, _) in done {
+			let last = state.groups.entry(track).or_insert(group_id);
+			*last = group_id.max(*last);
+		}
+
+		// Make sure the data is on disk before we record that it's there.
+		self.writer.flush().await?;
+
+		state.offset = offset;
+		state.save(path).await
+	}
 }
 
 impl<O: AsyncWrite + Send + Unpin + 'static> Media<O> {
-	pub async fn new(subscriber: Subscriber, tracks: Tracks, output: O) -> anyhow::Result<Self> {
+	/// Write the media to the output.
+	///
+	/// When `resume` is provided, progress is saved to the sidecar path after each complete group.
+	/// The output must already be positioned at the state's offset, and groups it has already completed are skipped.
+	///
+	/// When `sync` is provided, groups are released in timestamp order across tracks.
+	///
+	/// When `report` is provided, the publisher's sender reports are compared with the received objects.
+	pub async fn new(
+		subscriber: Subscriber,
+		tracks: Tracks,
+		output: O,
+		resume: Option<(PathBuf, ResumeState)>,
+		sync: Option<TrackSync>,
+		report: Option<Receiver>,
+	) -> anyhow::Result<Self> {
 		let (tracks_writer, _tracks_request, tracks_reader) = tracks.produce();
 		let broadcast = tracks_reader; // breadcrumb for navigating API name changes
+
+		let output = Output::new(output, resume);
+
 		Ok(Self {
 			subscriber,
 			broadcast,
 			tracks_writer,
 			output: Arc::new(Mutex::new(output)),
+			sync: sync.map(Arc::new),
+			report: report.map(Arc::new),
+			skip: GroupSkip::default(),
+			from_group: None,
+			to_group: None,
+			keys: None,
 		})
 	}
 
+	/// Only write the groups within this range of each track, ex. to extract a clip.
+	///
+	/// With an end group, the range is fetched from the relay's cache with FETCH and the tracks end once it's received.
+	/// Otherwise the tracks are subscribed from the start group and continue live.
+	pub fn with_range(mut self, from_group: Option<u64>, to_group: Option<u64>) -> Self {
+		self.from_group = from_group;
+		self.to_group = to_group;
+		self
+	}
+
+	/// Decrypt the objects of each media track, requesting the content key from the publisher with this identity.
+	///
+	/// The init segment isn't encrypted, since it's needed to pick the tracks.
+	pub fn with_decryption(mut self, identity: Option<moq_native::sign::SigningKey>) -> Self {
+		self.keys = identity.map(|identity| {
+			let namespace = self.broadcast.namespace.clone();
+			Arc::new(Decryptor::new(self.subscriber.clone(), identity, namespace))
+		});
+		self
+	}
+
+	/// Choose what happens when writing the output falls behind, defaulting to [GroupSkip::Latest].
+	pub fn with_skip(mut self, skip: GroupSkip) -> Self {
+		self.skip = skip;
+		self
+	}
+
 	pub async fn run(&mut self) -> anyhow::Result<()> {
 		let moov = {
 			let init_track_name = "0.mp4";
-			let track = self
+
+			// Reuse the init track if it was already created, rather than replacing it and its subscription.
+			let entry = self
 				.tracks_writer
-				.create(init_track_name)
+				.get_or_create(init_track_name)
 				.context("failed to create init track")?;
 
-			let mut subscriber = self.subscriber.clone();
-			tokio::task::spawn(async move {
-				subscriber.subscribe(track).await.unwrap_or_else(|err| {
-					warn!("failed to subscribe to init track: {err:?}");
+			if let TrackEntry::Created(track) = entry {
+				let mut subscriber = self.subscriber.clone();
+				tokio::task::spawn(async move {
+					subscriber.subscribe(track).await.unwrap_or_else(|err| {
+						warn!("failed to subscribe to init track: {err:?}");
+					});
 				});
-			});
+			}
 
 			let track = self.broadcast.subscribe(init_track_name).context("no init track")?;
 			let mut group = match track.mode().await? {
@@ -55,7 +219,14 @@ impl<O: AsyncWrite + Send + Unpin + 'static> Media<O> {
 
 			let object = group.next().await?.context("no init fragment")?;
 			let buf = Self::recv_object(object).await?;
-			self.output.lock().await.write_all(&buf).await?;
+
+			// A resumed output already starts with the init segment.
+			let mut output = self.output.lock().await;
+			if output.written == 0 {
+				output.write(&buf).await?;
+			}
+			drop(output);
+
 			let mut reader = Cursor::new(&buf);
 
 			let ftyp = read_atom(&mut reader).await?;
@@ -69,9 +240,17 @@ impl<O: AsyncWrite + Send + Unpin + 'static> Media<O> {
 			mp4::MoovBox::read_box(&mut moov_reader, moov_header.size)?
 		};
 
+		// Reports are optional, so a publisher without them doesn't prevent playback.
+		let reports = match &self.report {
+			Some(report) => Some(self.spawn_reports(report.clone())?),
+			None => None,
+		};
+
 		let mut has_video = false;
 		let mut has_audio = false;
 		let mut tracks = vec![];
+		let mut subscribes = vec![];
+		let mut fetches = JoinSet::new();
 		for trak in &moov.traks {
 			let id = trak.tkhd.track_id;
 			let name = format!("{}.m4s", id);
@@ -88,65 +267,361 @@ impl<O: AsyncWrite + Send + Unpin + 'static> Media<O> {
 				info!("using {name} for audio");
 			}
 			if active {
-				let track = self.tracks_writer.create(&name).context("failed to create track")?;
+				let track = match self
+					.tracks_writer
+					.get_or_create(&name)
+					.context("failed to create track")?
+				{
+					TrackEntry::Created(track) => track,
+					// The same track ID was listed twice, so don't replace the first subscription.
+					TrackEntry::Existing(_) => {
+						warn!("duplicate track {name}, skipping");
+						continue;
+					}
+				};
 
-				let mut subscriber = self.subscriber.clone();
-				tokio::task::spawn(async move {
-					subscriber.subscribe(track).await.unwrap_or_else(|err| {
-						warn!("failed to subscribe to track: {err:?}");
-					});
-				});
+				let resume = self
+					.output
+					.lock()
+					.await
+					.resume
+					.as_ref()
+					.and_then(|(_, state)| state.next_group(&name));
+				let start_group = resume.max(self.from_group);
+
+				match self.to_group {
+					Some(end_group) => {
+						let options = FetchOptions {
+							start_group: start_group.unwrap_or_default(),
+							end_group: Some(end_group),
+						};
 
-				tracks.push(self.broadcast.subscribe(&name).context("no track")?);
+						let mut subscriber = self.subscriber.clone();
+						fetches.spawn(async move {
+							if let Err(err) = subscriber.fetch(track, options).await {
+								warn!("failed to fetch track: {err:?}");
+							}
+						});
+					}
+					None => {
+						let options = SubscribeOptions {
+							start_group,
+							..Default::default()
+						};
+
+						subscribes.push((track, options));
+					}
+				}
+
+				// Register before any track starts, so they wait for each other.
+				if let Some(sync) = &self.sync {
+					sync.register(&name);
+				}
+
+				let timescale = trak.mdia.mdhd.timescale as u64;
+				tracks.push((self.broadcast.subscribe(&name).context("no track")?, timescale));
 			}
 		}
 
+		// Subscribe to every track as a unit, so we don't play a broadcast with only some of its tracks.
+		// The bundle unsubscribes when dropped, so it's held until the tracks are done.
+		let bundle = self.subscriber.subscribe_bundle(subscribes);
+		bundle.ready().await.context("failed to subscribe to tracks")?;
 		info!("playing {} tracks", tracks.len());
 		let mut tasks = JoinSet::new();
-		for track in tracks {
+		let mut failed = 0;
+		for (track, timescale) in tracks {
 			let out = self.output.clone();
+			let sync = self.sync.clone();
+			let report = self.report.clone();
+			let keys = self.keys.clone();
+			let skip = self.skip;
 			tasks.spawn(async move {
 				let name = track.name.clone();
-				if let Err(err) = Self::recv_track(track, out).await {
+				let res = Self::recv_track(track, out, sync.as_deref(), report, keys, skip, timescale).await;
+				if let Err(err) = &res {
 					warn!("failed to play track {name}: {err:?}");
 				}
+
+				// Don't make the other tracks wait for us.
+				if let Some(sync) = sync {
+					sync.remove(&name);
+				}
+
+				res
 			});
 		}
-		while tasks.join_next().await.is_some() {}
+
+		while let Some(res) = tasks.join_next().await {
+			if !matches!(res, Ok(Ok(()))) {
+				failed += 1;
+			}
+		}
+
+		// Unlike the bundle, each fetch fails on its own; dropping them cancels any still running.
+		drop(bundle);
+		drop(fetches);
+
+		if let Some(reports) = reports {
+			reports.abort();
+		}
+
+		// Make sure everything is written before we return, ex. so the file can be finalized.
+		self.output.lock().await.writer.flush().await?;
+
+		anyhow::ensure!(failed == 0, "{} tracks failed", failed);
+		info!("all tracks ended");
+
 		Ok(())
 	}
 
-	async fn recv_track(track: TrackReader, out: Arc<Mutex<O>>) -> anyhow::Result<()> {
+	async fn recv_track(
+		track: TrackReader,
+		out: Arc<Mutex<Output<O>>>,
+		sync: Option<&TrackSync>,
+		report: Option<Arc<Receiver>>,
+		keys: Option<Arc<Decryptor>>,
+		skip: GroupSkip,
+		timescale: u64,
+	) -> anyhow::Result<()> {
 		let name = track.name.clone();
 		debug!("track {name}: start");
-		if let TrackReaderMode::Groups(mut groups) = track.mode().await? {
-			while let Some(group) = groups.next().await? {
+
+		let mut tasks = JoinSet::new();
+
+		let groups = match track.mode().await? {
+			TrackReaderMode::Groups(groups) => Some(groups),
+			// A FETCH is delivered as a single stream, in group order.
+			TrackReaderMode::Stream(mut stream) => {
+				while let Some(group) = stream.next().await? {
+					if out.lock().await.completed(&name, group.group_id) {
+						debug!("track {name}: skipping completed group={}", group.group_id);
+						continue;
+					}
+
+					Self::recv_stream_group(&name, group, out.clone(), report.clone(), keys.clone()).await?;
+				}
+
+				None
+			}
+			_ => None,
+		};
+
+		if let Some(groups) = groups {
+			let mut groups = groups.with_skip(skip);
+			while let Some(mut group) = groups.next().await? {
+				if out.lock().await.completed(&name, group.group_id) {
+					debug!("track {name}: skipping completed group={}", group.group_id);
+					continue;
+				}
+
+				let mut first = None;
+
+				if let Some(sync) = sync {
+					// Prefer the timestamp header, otherwise parse the tfdt from the first fragment.
+					let timestamp = match group.timestamp.media {
+						Some(timestamp) => timestamp,
+						None => {
+							let object = group.next().await?.context("empty group")?;
+							let start = Self::decodable(&object);
+							let buf = Self::recv_object(object).await?;
+							let plain = Self::decrypt(keys.as_deref(), buf.clone()).await?;
+							let timestamp = fragment_timestamp(&plain, timescale)?;
+							first = Some((start, buf));
+							timestamp
+						}
+					};
+
+					if !sync.release(&name, timestamp).await {
+						debug!(
+							"track {name}: dropping late group={} timestamp={}",
+							group.group_id, timestamp
+						);
+						continue;
+					}
+				}
+
 				let out = out.clone();
-				tokio::task::spawn(async move {
-					if let Err(err) = Self::recv_group(group, out).await {
+				let report = report.clone();
+				let keys = keys.clone();
+				tasks.spawn(async move {
+					let res = Self::recv_group(group, first, out, report, keys).await;
+					if let Err(err) = &res {
 						warn!("failed to receive group: {err:?}");
 					}
+					res
 				});
 			}
 		}
+
+		// The track ended cleanly, but wait for any groups still being received.
+		while let Some(res) = tasks.join_next().await {
+			res??;
+		}
+
 		debug!("track {name}: finish");
 		Ok(())
 	}
 
-	async fn recv_group(mut group: GroupReader, out: Arc<Mutex<O>>) -> anyhow::Result<()> {
+	async fn recv_group(
+		group: GroupReader,
+		first: Option<(bool, Vec<u8>)>,
+		out: Arc<Mutex<Output<O>>>,
+		report: Option<Arc<Receiver>>,
+		keys: Option<Arc<Decryptor>>,
+	) -> anyhow::Result<()> {
+		let (name, group_id) = (group.name.clone(), group.group_id);
+
+		let id = out.lock().await.start();
+		let res = Self::write_group(group, first, out.clone(), report, keys).await;
+
+		let mut out = out.lock().await;
+		match res {
+			Ok(()) => out.complete(id, &name, group_id).await,
+			Err(err) => out.abort(id).await.and(Err(err)),
+		}
+	}
+
+	async fn write_group(
+		mut group: GroupReader,
+		first: Option<(bool, Vec<u8>)>,
+		out: Arc<Mutex<Output<O>>>,
+		report: Option<Arc<Receiver>>,
+		keys: Option<Arc<Decryptor>>,
+	) -> anyhow::Result<()> {
 		trace!("group={} start", group.group_id);
+
+		let (name, group_id) = (group.name.clone(), group.group_id);
+		let record = |size: usize| {
+			if let Some(report) = &report {
+				report.record(&name, group_id, size);
+			}
+		};
+
+		// Nothing is written until a fragment that can be decoded on its own, in case we joined mid-group.
+		let mut started = false;
+
+		// The first fragment may have already been read to get the timestamp.
+		if let Some((start, buf)) = first {
+			started = start;
+			if started {
+				record(buf.len());
+				let buf = Self::decrypt(keys.as_deref(), buf).await?;
+				out.lock().await.write(&buf).await?;
+			}
+		}
+
 		while let Some(object) = group.next().await? {
+			started |= Self::decodable(&object);
+			if !started {
+				debug!(
+					"group={} fragment={} skipped until an independent fragment",
+					group.group_id, object.object_id
+				);
+				continue;
+			}
+
 			trace!("group={} fragment={} start", group.group_id, object.object_id);
 			let out = out.clone();
 			let buf = Self::recv_object(object).await?;
+			record(buf.len());
+			let buf = Self::decrypt(keys.as_deref(), buf).await?;
 
 			// TODO: avoid interleaving out of order fragments
-			out.lock().await.write_all(&buf).await?;
+			out.lock().await.write(&buf).await?;
 		}
 
 		Ok(())
 	}
 
+	// The first object of a group, or one the publisher marked as independent, ex. a keyframe.
+	fn decodable(object: &GroupObjectReader) -> bool {
+		object.object_id == 0 || object.independent
+	}
+
+	async fn recv_stream_group(
+		name: &str,
+		group: StreamGroupReader,
+		out: Arc<Mutex<Output<O>>>,
+		report: Option<Arc<Receiver>>,
+		keys: Option<Arc<Decryptor>>,
+	) -> anyhow::Result<()> {
+		let group_id = group.group_id;
+
+		let id = out.lock().await.start();
+		let res = Self::write_stream_group(name, group, out.clone(), report, keys).await;
+
+		let mut out = out.lock().await;
+		match res {
+			Ok(()) => out.complete(id, name, group_id).await,
+			Err(err) => out.abort(id).await.and(Err(err)),
+		}
+	}
+
+	async fn write_stream_group(
+		name: &str,
+		mut group: StreamGroupReader,
+		out: Arc<Mutex<Output<O>>>,
+		report: Option<Arc<Receiver>>,
+		keys: Option<Arc<Decryptor>>,
+	) -> anyhow::Result<()> {
+		trace!("group={} start", group.group_id);
+
+		while let Some(mut object) = group.next().await? {
+			let buf = object.read_all().await?;
+			if let Some(report) = &report {
+				report.record(name, group.group_id, buf.len());
+			}
+
+			let buf = Self::decrypt(keys.as_deref(), buf.to_vec()).await?;
+			out.lock().await.write(&buf).await?;
+		}
+
+		Ok(())
+	}
+
+	// Subscribe to the sender reports in the background, logging if they're unavailable.
+	fn spawn_reports(&mut self, report: Arc<Receiver>) -> anyhow::Result<tokio::task::JoinHandle<()>> {
+		let name = moq_catalog::REPORT_TRACK;
+		let entry = self
+			.tracks_writer
+			.get_or_create(name)
+			.context("failed to create report track")?;
+		let reader = self.broadcast.subscribe(name).context("no report track")?;
+
+		let track = match entry {
+			TrackEntry::Created(track) => track,
+			// Already subscribed, so only read it.
+			TrackEntry::Existing(_) => {
+				return Ok(tokio::task::spawn(async move {
+					if let Err(err) = report.run(reader).await {
+						warn!("failed to read reports: {err:?}");
+					}
+				}))
+			}
+		};
+
+		let mut subscriber = self.subscriber.clone();
+		Ok(tokio::task::spawn(async move {
+			tokio::select! {
+				res = subscriber.subscribe(track) => if let Err(err) = res {
+					warn!("failed to subscribe to reports: {err:?}");
+				},
+				res = report.run(reader) => if let Err(err) = res {
+					warn!("failed to read reports: {err:?}");
+				},
+			}
+		}))
+	}
+
+	// Reports count the bytes as sent, so objects are decrypted after they're recorded.
+	async fn decrypt(keys: Option<&Decryptor>, buf: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+		match keys {
+			Some(keys) => keys.decrypt(&buf).await,
+			None => Ok(buf),
+		}
+	}
+
 	async fn recv_object(mut object: GroupObjectReader) -> anyhow::Result<Vec<u8>> {
 		let mut buf = Vec::with_capacity(object.size);
 		while let Some(chunk) = object.read().await? {
@@ -156,6 +631,25 @@ impl<O: AsyncWrite + Send + Unpin + 'static> Media<O> {
 	}
 }
 
+// Returns the timestamp of a moof fragment in microseconds.
+fn fragment_timestamp(buf: &[u8], timescale: u64) -> anyhow::Result<u64> {
+	anyhow::ensure!(timescale > 0, "invalid timescale");
+
+	let mut reader = Cursor::new(buf);
+	let header = mp4::BoxHeader::read(&mut reader)?;
+	anyhow::ensure!(header.name == mp4::BoxType::MoofBox, "expected moof atom");
+
+	let moof = mp4::MoofBox::read_box(&mut reader, header.size)?;
+	let tfdt = moof
+		.trafs
+		.first()
+		.and_then(|traf| traf.tfdt.as_ref())
+		.context("missing tfdt")?
+		.base_media_decode_time;
+
+	Ok((tfdt as u128 * 1_000_000 / timescale as u128).try_into()?)
+}
+
 // Read a full MP4 atom into a vector.
 async fn read_atom<R: AsyncReadExt + Unpin>(reader: &mut R) -> anyhow::Result<Vec<u8>> {
 	// Read the 8 bytes for the size + type
@@ -192,3 +686,61 @@ async fn read_atom<R: AsyncReadExt + Unpin>(reader: &mut R) -> anyhow::Result<Ve
 
 	Ok(raw)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn concurrent_groups() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("out.mp4.state");
+		let mut out = Output::new(Vec::new(), Some((path.clone(), ResumeState::default())));
+
+		// Groups 1 and 2 are written at the same time, and 2 finishes first.
+		let one = out.start();
+		out.write(b"1111").await.unwrap();
+		let two = out.start();
+		out.write(b"22").await.unwrap();
+		out.complete(two, "1.m4s", 2).await.unwrap();
+
+		// Group 1 is still being written from the start, so neither the offset nor group 2 are recorded.
+		assert!(ResumeState::load(&path).await.unwrap().is_none());
+
+		// Group 3 starts before group 1 finishes, so the offset only advances to where group 3 started.
+		out.write(b"11").await.unwrap();
+		let three = out.start();
+		out.write(b"33").await.unwrap();
+		out.complete(one, "1.m4s", 1).await.unwrap();
+
+		let state = ResumeState::load(&path).await.unwrap().unwrap();
+		assert_eq!(state.offset, 8);
+		assert_eq!(state.groups.get("1.m4s"), Some(&2));
+
+		// A group that fails partway through doesn't stop recording.
+		let four = out.start();
+		out.write(b"4").await.unwrap();
+		out.abort(four).await.unwrap();
+		out.write(b"3").await.unwrap();
+		out.complete(three, "1.m4s", 3).await.unwrap();
+
+		let state = ResumeState::load(&path).await.unwrap().unwrap();
+		assert_eq!(state.offset, 12);
+		assert_eq!(state.groups.get("1.m4s"), Some(&3));
+		assert!(out.finished.is_empty());
+	}
+
+	#[tokio::test]
+	async fn completed() {
+		let state = ResumeState {
+			offset: 10,
+			groups: [("1.m4s".to_string(), 5)].into(),
+		};
+		let out = Output::new(Vec::new(), Some(("unused".into(), state)));
+
+		assert_eq!(out.written, 10);
+		assert!(out.completed("1.m4s", 5));
+		assert!(!out.completed("1.m4s", 6));
+		assert!(!out.completed("2.m4s", 0));
+	}
+}
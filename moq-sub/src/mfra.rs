@@ -0,0 +1,125 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::{self, Read, Seek, Write},
+	path::Path,
+};
+
+use anyhow::Context;
+use mp4::ReadBox;
+
+// A random access point within a fragmented MP4.
+struct Entry {
+	// The decode time of the first sample, in the track's timescale.
+	time: u64,
+
+	// The offset of the moof atom from the start of the file.
+	moof_offset: u64,
+}
+
+/// Append an `mfra` box indexing each keyframe fragment, so players can seek within the finished file.
+///
+/// This scans the top-level atoms of the file rather than tracking what was written, so it also covers resumed output.
+pub fn append(path: &Path) -> anyhow::Result<()> {
+	let mut file = fs::OpenOptions::new()
+		.read(true)
+		.append(true)
+		.open(path)
+		.context("failed to open output")?;
+
+	let entries = scan(&mut file)?;
+	let mfra = encode(&entries);
+
+	file.write_all(&mfra)?;
+	file.sync_all()?;
+
+	Ok(())
+}
+
+// Find the offset and time of each fragment that starts with a sync sample, grouped by track ID.
+fn scan(file: &mut fs::File) -> anyhow::Result<BTreeMap<u32, Vec<Entry>>> {
+	let size = file.metadata()?.len();
+	let mut entries: BTreeMap<u32, Vec<Entry>> = BTreeMap::new();
+	let mut offset = 0;
+
+	while offset < size {
+		file.seek(io::SeekFrom::Start(offset))?;
+		let header = mp4::BoxHeader::read(file).context("failed to read atom")?;
+		anyhow::ensure!(header.size >= 8, "invalid atom size: {}", header.size);
+
+		if header.name == mp4::BoxType::MoofBox {
+			let mut buf = vec![0; header.size as usize];
+			file.seek(io::SeekFrom::Start(offset))?;
+			file.read_exact(&mut buf)?;
+
+			let mut reader = io::Cursor::new(&buf);
+			mp4::BoxHeader::read(&mut reader)?;
+			let moof = mp4::MoofBox::read_box(&mut reader, header.size)?;
+
+			for traf in &moof.trafs {
+				// The flags of the first sample in the fragment.
+				let flags = traf
+					.trun
+					.as_ref()
+					.and_then(|trun| trun.first_sample_flags.or_else(|| trun.sample_flags.first().copied()))
+					.or(traf.tfhd.default_sample_flags)
+					.unwrap_or_default();
+
+				let tfdt = match &traf.tfdt {
+					Some(tfdt) if is_sync(flags) => tfdt,
+					_ => continue,
+				};
+
+				entries.entry(traf.tfhd.track_id).or_default().push(Entry {
+					time: tfdt.base_media_decode_time,
+					moof_offset: offset,
+				});
+			}
+		}
+
+		offset += header.size;
+	}
+
+	Ok(entries)
+}
+
+// Returns true if the sample_is_non_sync_sample flag is not set.
+fn is_sync(flags: u32) -> bool {
+	flags & 0x10000 == 0
+}
+
+fn encode(entries: &BTreeMap<u32, Vec<Entry>>) -> Vec<u8> {
+	let mut body = Vec::new();
+
+	for (track_id, entries) in entries {
+		let mut tfra = Vec::new();
+		tfra.extend_from_slice(&[1, 0, 0, 0]); // version 1, no flags
+		tfra.extend_from_slice(&track_id.to_be_bytes());
+		tfra.extend_from_slice(&0u32.to_be_bytes()); // 1 byte each for the traf, trun, and sample numbers
+		tfra.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+		for entry in entries {
+			tfra.extend_from_slice(&entry.time.to_be_bytes());
+			tfra.extend_from_slice(&entry.moof_offset.to_be_bytes());
+			tfra.extend_from_slice(&[1, 1, 1]); // the first sample of the first trun of the first traf
+		}
+
+		write_box(&mut body, b"tfra", &tfra);
+	}
+
+	// The mfro box contains the size of the entire mfra box, so players can find it from the end of the file.
+	let size = 8 + body.len() as u32 + 16;
+	let mut mfro = vec![0, 0, 0, 0];
+	mfro.extend_from_slice(&size.to_be_bytes());
+	write_box(&mut body, b"mfro", &mfro);
+
+	let mut mfra = Vec::new();
+	write_box(&mut mfra, b"mfra", &body);
+	mfra
+}
+
+fn write_box(buf: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+	buf.extend_from_slice(&(8 + body.len() as u32).to_be_bytes());
+	buf.extend_from_slice(kind);
+	buf.extend_from_slice(body);
+}
@@ -0,0 +1,117 @@
+//! Receiver reports, computed by comparing each sender report with what we actually received.
+use std::{collections::HashMap, sync::Mutex, time};
+
+use anyhow::Context;
+use moq_catalog::{SenderReport, TrackReport};
+use moq_transport::serve::{TrackReader, TrackReaderMode};
+
+#[derive(Default, Clone, Copy)]
+struct Received {
+	objects: u64,
+	bytes: u64,
+	last_group: Option<u64>,
+}
+
+/// Counts what was received for each track, printing a receiver report to stderr for each sender report.
+#[derive(Default)]
+pub struct Receiver {
+	tracks: Mutex<HashMap<String, Received>>,
+}
+
+impl Receiver {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Record an object received for the track.
+	pub fn record(&self, track: &str, group_id: u64, size: usize) {
+		let mut tracks = self.tracks.lock().unwrap();
+		let received = tracks.entry(track.to_string()).or_default();
+		received.objects += 1;
+		received.bytes += size as u64;
+		received.last_group = received.last_group.max(Some(group_id));
+	}
+
+	/// Read sender reports from the track until it ends.
+	pub async fn run(&self, track: TrackReader) -> anyhow::Result<()> {
+		let mut groups = match track.mode().await? {
+			TrackReaderMode::Groups(groups) => groups,
+			_ => anyhow::bail!("expected groups for reports"),
+		};
+
+		// The previous sender report and what we had received at the time.
+		let mut prev: Option<(SenderReport, HashMap<String, Received>)> = None;
+
+		while let Some(mut group) = groups.next().await? {
+			let mut object = group.next().await?.context("empty report")?;
+			let report: SenderReport = serde_json::from_slice(&object.read_all().await?)?;
+
+			let received = self.tracks.lock().unwrap().clone();
+			if let Some((prev_report, prev_received)) = &prev {
+				self.print(prev_report, prev_received, &report, &received);
+			}
+
+			prev = Some((report, received));
+		}
+
+		Ok(())
+	}
+
+	// Print the change since the previous report for each track.
+	fn print(
+		&self,
+		prev_report: &SenderReport,
+		prev_received: &HashMap<String, Received>,
+		report: &SenderReport,
+		received: &HashMap<String, Received>,
+	) {
+		// Depends on the clocks being in sync, so it may be negative.
+		let now = time::SystemTime::now()
+			.duration_since(time::UNIX_EPOCH)
+			.map_or(0, |now| now.as_micros() as i64);
+		let age_ms = (now - report.timestamp as i64) / 1000;
+
+		let tracks: Vec<_> = report
+			.tracks
+			.iter()
+			.map(|sent| {
+				let zero = TrackReport {
+					name: sent.name.clone(),
+					objects: 0,
+					bytes: 0,
+					last_group: None,
+				};
+				let prev_sent = prev_report.tracks.iter().find(|t| t.name == sent.name).unwrap_or(&zero);
+
+				let recv = received.get(&sent.name).copied().unwrap_or_default();
+				let prev_recv = prev_received.get(&sent.name).copied().unwrap_or_default();
+
+				let sent_objects = sent.objects.saturating_sub(prev_sent.objects);
+				let recv_objects = recv.objects.saturating_sub(prev_recv.objects);
+
+				// Objects still in flight are counted as lost until the next report.
+				let loss = match sent_objects {
+					0 => 0.0,
+					sent => 1.0 - (recv_objects.min(sent) as f64 / sent as f64),
+				};
+
+				let lag = match (sent.last_group, recv.last_group) {
+					(Some(sent), Some(recv)) => Some(sent.saturating_sub(recv)),
+					_ => None,
+				};
+
+				serde_json::json!({
+					"name": sent.name,
+					"sent_objects": sent_objects,
+					"received_objects": recv_objects,
+					"sent_bytes": sent.bytes.saturating_sub(prev_sent.bytes),
+					"received_bytes": recv.bytes.saturating_sub(prev_recv.bytes),
+					"loss": loss,
+					"lag_groups": lag,
+				})
+			})
+			.collect();
+
+		eprintln!("{}", serde_json::json!({ "tracks": tracks, "age_ms": age_ms }));
+	}
+}
@@ -0,0 +1,174 @@
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use tokio::io::AsyncSeekExt;
+
+/// Download progress, recorded in a sidecar file next to the output so it can resume after an interruption.
+///
+/// The file is plain text with an `offset <bytes>` line and a `group <track> <id>` line per track.
+#[derive(Debug, Default, Clone)]
+pub struct ResumeState {
+	/// The length of the output before the oldest group that was still being written, so only complete groups are kept.
+	pub offset: u64,
+
+	/// The last complete group ID for each track.
+	pub groups: HashMap<String, u64>,
+}
+
+impl ResumeState {
+	/// The sidecar path for the given output file.
+	pub fn path(output: &Path) -> PathBuf {
+		let mut path = output.as_os_str().to_owned();
+		path.push(".state");
+		path.into()
+	}
+
+	/// Load the state, returning None if the file doesn't exist.
+	pub async fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+		let contents = match tokio::fs::read_to_string(path).await {
+			Ok(contents) => contents,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err).context("failed to read resume state"),
+		};
+
+		let mut state = Self::default();
+
+		for line in contents.lines() {
+			let mut parts = line.split_whitespace();
+			match (parts.next(), parts.next(), parts.next()) {
+				(Some("offset"), Some(offset), None) => state.offset = offset.parse()?,
+				(Some("group"), Some(track), Some(group)) => {
+					state.groups.insert(track.to_string(), group.parse()?);
+				}
+				(None, _, _) => {}
+				_ => anyhow::bail!("invalid resume state: {}", line),
+			}
+		}
+
+		Ok(Some(state))
+	}
+
+	/// Save the state, replacing the file atomically so an interruption can't corrupt it.
+	pub async fn save(&self, path: &Path) -> anyhow::Result<()> {
+		let mut contents = format!("offset {}\n", self.offset);
+		for (track, group) in &self.groups {
+			contents += &format!("group {} {}\n", track, group);
+		}
+
+		let mut tmp = path.as_os_str().to_owned();
+		tmp.push(".tmp");
+
+		tokio::fs::write(&tmp, contents)
+			.await
+			.context("failed to write resume state")?;
+		tokio::fs::rename(&tmp, path)
+			.await
+			.context("failed to replace resume state")?;
+
+		Ok(())
+	}
+
+	/// Open the output file and load its state, creating them if needed.
+	///
+	/// Anything written after the last complete group is discarded, ex. a partial group, and the file is positioned at the end.
+	pub async fn open(output: &Path) -> anyhow::Result<(tokio::fs::File, PathBuf, Self)> {
+		let path = Self::path(output);
+		let state = Self::load(&path).await?.unwrap_or_default();
+
+		let mut file = tokio::fs::OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(false)
+			.open(output)
+			.await
+			.context("failed to open output")?;
+		file.set_len(state.offset).await?;
+		file.seek(std::io::SeekFrom::End(0)).await?;
+
+		Ok((file, path, state))
+	}
+
+	/// Returns the group to request when resubscribing to the track.
+	pub fn next_group(&self, track: &str) -> Option<u64> {
+		self.groups.get(track).map(|group| group + 1)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn load() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = ResumeState::path(&dir.path().join("out.mp4"));
+		assert_eq!(path, dir.path().join("out.mp4.state"));
+
+		assert!(ResumeState::load(&path).await.unwrap().is_none());
+
+		tokio::fs::write(&path, "offset 1234\ngroup 1.m4s 7\n\ngroup 2.m4s 9\n")
+			.await
+			.unwrap();
+		let state = ResumeState::load(&path).await.unwrap().unwrap();
+		assert_eq!(state.offset, 1234);
+		assert_eq!(state.groups.len(), 2);
+		assert_eq!(state.next_group("1.m4s"), Some(8));
+		assert_eq!(state.next_group("2.m4s"), Some(10));
+		assert_eq!(state.next_group("3.m4s"), None);
+
+		// Saving and loading again keeps everything.
+		state.save(&path).await.unwrap();
+		let loaded = ResumeState::load(&path).await.unwrap().unwrap();
+		assert_eq!(loaded.offset, state.offset);
+		assert_eq!(loaded.groups, state.groups);
+
+		for invalid in [
+			"offset",
+			"offset -1",
+			"offset 1 2",
+			"group 1.m4s",
+			"group 1.m4s x",
+			"size 1",
+		] {
+			tokio::fs::write(&path, invalid).await.unwrap();
+			assert!(ResumeState::load(&path).await.is_err(), "{}", invalid);
+		}
+	}
+
+	#[tokio::test]
+	async fn truncate() {
+		let dir = tempfile::tempdir().unwrap();
+		let output = dir.path().join("out.mp4");
+
+		// Nothing has been written yet.
+		let (_, path, state) = ResumeState::open(&output).await.unwrap();
+		assert_eq!(path, ResumeState::path(&output));
+		assert_eq!(state.offset, 0);
+		assert_eq!(tokio::fs::metadata(&output).await.unwrap().len(), 0);
+
+		// A partial group was written after the last complete one.
+		tokio::fs::write(&output, [1u8; 100]).await.unwrap();
+		let state = ResumeState {
+			offset: 40,
+			groups: [("1.m4s".to_string(), 3)].into(),
+		};
+		state.save(&path).await.unwrap();
+
+		let (mut file, _, state) = ResumeState::open(&output).await.unwrap();
+		assert_eq!(state.offset, 40);
+		assert_eq!(file.stream_position().await.unwrap(), 40);
+
+		use tokio::io::AsyncWriteExt;
+		file.write_all(&[2u8; 10]).await.unwrap();
+		file.flush().await.unwrap();
+		drop(file);
+
+		let contents = tokio::fs::read(&output).await.unwrap();
+		assert_eq!(contents.len(), 50);
+		assert_eq!(&contents[..40], &[1u8; 40]);
+		assert_eq!(&contents[40..], &[2u8; 10]);
+	}
+}
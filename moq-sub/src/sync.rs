@@ -0,0 +1,116 @@
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+/// What to do with a group that arrives after the other tracks are already further ahead than the window.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncDrop {
+	/// Write the group anyway; the output will contain a gap followed by a burst for the stalled track.
+	Never,
+
+	/// Skip the group so the stalled track catches up with the others.
+	#[default]
+	Late,
+}
+
+/// Aligns the release of groups across tracks by timestamp, so one track can't run ahead of the others.
+///
+/// Each track calls [TrackSync::release] with the timestamp of its next group, which waits until every other track
+/// has a group at or after that timestamp. A stalled track is waited on for at most the window.
+pub struct TrackSync {
+	window: Duration,
+	drop: SyncDrop,
+	state: Mutex<HashMap<String, TrackPosition>>,
+	notify: Notify,
+}
+
+#[derive(Default)]
+struct TrackPosition {
+	// The timestamp of the group waiting to be released.
+	pending: Option<u64>,
+
+	// The timestamp of the most recently released group.
+	released: Option<u64>,
+}
+
+impl TrackPosition {
+	// Returns true if the track won't release anything before the given timestamp.
+	fn reached(&self, timestamp: u64) -> bool {
+		self.pending.is_some_and(|pending| pending >= timestamp) || self.released.is_some_and(|last| last >= timestamp)
+	}
+}
+
+impl TrackSync {
+	pub fn new(window: Duration, drop: SyncDrop) -> Self {
+		Self {
+			window,
+			drop,
+			state: Default::default(),
+			notify: Notify::new(),
+		}
+	}
+
+	/// Add a track, which the other tracks will wait for until it's removed.
+	pub fn register(&self, track: &str) {
+		self.state.lock().unwrap().insert(track.to_string(), Default::default());
+	}
+
+	/// Remove a track, ex. when it finishes, so the other tracks stop waiting for it.
+	pub fn remove(&self, track: &str) {
+		self.state.lock().unwrap().remove(track);
+		self.notify.notify_waiters();
+	}
+
+	/// Wait until the group with the given timestamp, in microseconds, can be written.
+	///
+	/// Returns false if the group should be dropped because the other tracks are too far ahead.
+	pub async fn release(&self, track: &str, timestamp: u64) -> bool {
+		let deadline = Instant::now() + self.window;
+		let window = self.window.as_micros() as u64;
+
+		loop {
+			// Register for notifications before checking, so we can't miss one.
+			let notified = self.notify.notified();
+			tokio::pin!(notified);
+			notified.as_mut().enable();
+
+			{
+				let mut state = self.state.lock().unwrap();
+
+				let latest = state.values().filter_map(|position| position.released).max();
+				if self.drop == SyncDrop::Late && latest.is_some_and(|latest| timestamp.saturating_add(window) < latest)
+				{
+					return false;
+				}
+
+				let ready = state
+					.iter()
+					.filter(|(name, _)| name.as_str() != track)
+					.all(|(_, position)| position.reached(timestamp));
+
+				let position = state.entry(track.to_string()).or_default();
+
+				if ready || Instant::now() >= deadline {
+					position.pending = None;
+					position.released = Some(timestamp);
+					self.notify.notify_waiters();
+					return true;
+				}
+
+				if position.pending != Some(timestamp) {
+					position.pending = Some(timestamp);
+					self.notify.notify_waiters();
+				}
+			}
+
+			tokio::select! {
+				_ = notified => {},
+				_ = tokio::time::sleep_until(deadline.into()) => {},
+			}
+		}
+	}
+}
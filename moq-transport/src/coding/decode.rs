@@ -14,6 +14,17 @@ pub trait Decode: Sized {
 			Ok(())
 		}
 	}
+
+	/// Decode from a buffer holding exactly one value, ex. a datagram or fuzzer input.
+	///
+	/// Streams instead buffer more data and retry whenever [Self::decode] returns [DecodeError::More].
+	fn decode_exact<B: bytes::Buf>(buf: &mut B) -> Result<Self, DecodeError> {
+		let value = Self::decode(buf)?;
+		match buf.remaining() {
+			0 => Ok(value),
+			trailing => Err(DecodeError::Trailing(trailing)),
+		}
+	}
 }
 
 /// A decode error.
@@ -37,6 +48,9 @@ pub enum DecodeError {
 	#[error("invalid value")]
 	InvalidValue,
 
+	#[error("trailing bytes: {0}")]
+	Trailing(usize),
+
 	#[error("varint bounds exceeded")]
 	BoundsExceeded(#[from] BoundsExceeded),
 
@@ -1,11 +1,32 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::io::Cursor;
 
 use crate::coding::{Decode, DecodeError, Encode, EncodeError};
 
-#[derive(Default, Debug, Clone)]
+/// A parameter with a well-known ID, so it can be read and written with [Params::get_typed] and [Params::set_typed].
+pub trait Param: Encode + Decode {
+	const ID: u64;
+}
+
+/// The value of [Params::AUTH] is redacted when debug printed, since messages are logged.
+#[derive(Default, Clone)]
 pub struct Params(pub HashMap<u64, Vec<u8>>);
 
+impl fmt::Debug for Params {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mut map = f.debug_map();
+		for (kind, value) in &self.0 {
+			match *kind {
+				Self::AUTH => map.entry(kind, &format_args!("redacted(len={})", value.len())),
+				_ => map.entry(kind, value),
+			};
+		}
+
+		map.finish()
+	}
+}
+
 impl Decode for Params {
 	fn decode<R: bytes::Buf>(mut r: &mut R) -> Result<Self, DecodeError> {
 		let mut params = HashMap::new();
@@ -49,6 +70,23 @@ impl Encode for Params {
 }
 
 impl Params {
+	/// The role of the endpoint, sent in SETUP.
+	pub const ROLE: u64 = 0x0;
+
+	/// The path of the URL, sent by the client in SETUP over raw QUIC or TCP, see [crate::setup::Path].
+	/// WebTransport carries the path in its CONNECT request instead, and a server never sends one.
+	pub const PATH: u64 = 0x1;
+
+	/// An authorization token, sent in SETUP, ANNOUNCE, and SUBSCRIBE.
+	pub const AUTH: u64 = 0x2;
+
+	/// The maximum subscribe ID the peer may use, sent in SETUP by later drafts.
+	/// These drafts only send [Self::AUTH] in ANNOUNCE and SUBSCRIBE, which is why the IDs overlap.
+	pub const MAX_SUBSCRIBE_ID: u64 = 0x2;
+
+	/// How long the publisher may keep trying to deliver an object, in milliseconds, sent in SUBSCRIBE by later drafts.
+	pub const DELIVERY_TIMEOUT: u64 = 0x3;
+
 	pub fn new() -> Self {
 		Self::default()
 	}
@@ -73,4 +111,73 @@ impl Params {
 			Ok(None)
 		}
 	}
+
+	/// Same as [Self::set], using the parameter's well-known ID.
+	pub fn set_typed<P: Param>(&mut self, p: P) -> Result<(), EncodeError> {
+		self.set(P::ID, p)
+	}
+
+	/// Same as [Self::get], using the parameter's well-known ID.
+	pub fn get_typed<P: Param>(&mut self) -> Result<Option<P>, DecodeError> {
+		self.get(P::ID)
+	}
+
+	/// The IDs of any parameters not in the list, ex. after the known ones have been read with [Self::get].
+	pub fn unknown<'a>(&'a self, known: &'a [u64]) -> impl Iterator<Item = u64> + 'a {
+		self.0.keys().copied().filter(move |kind| !known.contains(kind))
+	}
+
+	/// Returns an error if any parameter isn't in the list, for messages that don't allow extensions.
+	pub fn deny_unknown(&self, known: &[u64]) -> Result<(), DecodeError> {
+		match self.unknown(known).next() {
+			Some(_) => Err(DecodeError::InvalidParameter),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::setup::{AuthToken, Role};
+
+	#[test]
+	fn typed() {
+		let mut params = Params::new();
+		params.set_typed(Role::Publisher).unwrap();
+		params.set_typed(AuthToken::new("secret")).unwrap();
+		params.set(0x3f, 7u64).unwrap();
+
+		let mut buf = Vec::new();
+		params.encode(&mut buf).unwrap();
+		let mut params = Params::decode(&mut buf.as_slice()).unwrap();
+
+		// The token isn't debug printed, even as bytes.
+		let debug = format!("{:?}", params);
+		assert!(debug.contains("2: redacted(len=7)"), "{}", debug);
+
+		assert!(params.deny_unknown(&[Params::ROLE, Params::AUTH]).is_err());
+		assert_eq!(
+			params.unknown(&[Params::ROLE, Params::AUTH]).collect::<Vec<_>>(),
+			vec![0x3f]
+		);
+
+		assert_eq!(params.get_typed::<Role>().unwrap(), Some(Role::Publisher));
+		assert_eq!(params.get_typed::<AuthToken>().unwrap(), Some(AuthToken::new("secret")));
+		assert_eq!(params.get::<u64>(0x3f).unwrap(), Some(7));
+		assert!(params.deny_unknown(&[]).is_ok());
+
+		// A parameter can't be sent twice.
+		let mut buf = Vec::new();
+		2u64.encode(&mut buf).unwrap();
+		for _ in 0..2 {
+			Params::AUTH.encode(&mut buf).unwrap();
+			"secret".to_string().len().encode(&mut buf).unwrap();
+			buf.extend_from_slice(b"secret");
+		}
+		assert!(matches!(
+			Params::decode(&mut buf.as_slice()),
+			Err(DecodeError::DupliateParameter)
+		));
+	}
 }
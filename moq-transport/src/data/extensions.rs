@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Extension headers attached to an object, keyed by type.
+///
+/// Encoded as a count followed by each type and length prefixed value.
+/// NOTE: This is an extension and must only be sent when the extensions capability was negotiated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Extensions(pub BTreeMap<u64, Bytes>);
+
+impl Extensions {
+	/// Marks an object that can be decoded without the earlier objects in its group, ex. a keyframe.
+	///
+	/// Sent with an empty value, and surfaced as the object's `independent` flag instead of as an extension.
+	pub const INDEPENDENT: u64 = 0x4b;
+
+	/// Add the independent flag to the extensions sent with an object.
+	pub fn with_independent(mut self, independent: bool) -> Self {
+		if independent {
+			self.0.insert(Self::INDEPENDENT, Bytes::new());
+		}
+
+		self
+	}
+
+	/// Remove the independent flag from the received extensions, returning whether it was set.
+	pub fn take_independent(&mut self) -> bool {
+		self.0.remove(&Self::INDEPENDENT).is_some()
+	}
+}
+
+impl Decode for Extensions {
+	fn decode<R: Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let mut extensions = BTreeMap::new();
+
+		let count = u64::decode(r)?;
+		for _ in 0..count {
+			let kind = u64::decode(r)?;
+
+			let size = usize::decode(r)?;
+			Self::decode_remaining(r, size)?;
+			let value = r.copy_to_bytes(size);
+
+			if extensions.insert(kind, value).is_some() {
+				return Err(DecodeError::DupliateParameter);
+			}
+		}
+
+		Ok(Self(extensions))
+	}
+}
+
+impl Encode for Extensions {
+	fn encode<W: BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.0.len().encode(w)?;
+
+		for (kind, value) in &self.0 {
+			kind.encode(w)?;
+			value.len().encode(w)?;
+			Self::encode_remaining(w, value.len())?;
+			w.put_slice(value);
+		}
+
+		Ok(())
+	}
+}
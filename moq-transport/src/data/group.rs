@@ -1,4 +1,10 @@
-use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+use std::collections::BTreeMap;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Params};
+
+use super::Extensions;
 
 #[derive(Clone, Debug)]
 pub struct GroupHeader {
@@ -37,10 +43,166 @@ impl Encode for GroupHeader {
 	}
 }
 
+/// A [GroupHeader] followed by extension parameters.
+///
+/// NOTE: This is an extension and must only be sent when the timestamps capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct GroupExtHeader {
+	// The subscribe ID.
+	pub subscribe_id: u64,
+
+	// The track alias.
+	pub track_alias: u64,
+
+	// The group sequence number
+	pub group_id: u64,
+
+	// The priority, where **smaller** values are sent first.
+	pub send_order: u64,
+
+	// The media timestamp of the first object in microseconds.
+	pub media_time: Option<u64>,
+
+	// The wall clock time when the group was captured, in microseconds since the UNIX epoch.
+	pub wall_time: Option<u64>,
+
+	// Application key/value pairs describing the group, ex. an ad break.
+	pub annotations: BTreeMap<String, Bytes>,
+
+	// The wall clock time when this hop opened the stream, in microseconds since the UNIX epoch.
+	// Only sent when the clock capability was negotiated.
+	pub send_time: Option<u64>,
+}
+
+impl GroupExtHeader {
+	const MEDIA_TIME: u64 = 0x1;
+	const WALL_TIME: u64 = 0x2;
+	const ANNOTATIONS: u64 = 0x3;
+	const SEND_TIME: u64 = 0x4;
+}
+
+impl Decode for GroupExtHeader {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let subscribe_id = u64::decode(r)?;
+		let track_alias = u64::decode(r)?;
+		let group_id = u64::decode(r)?;
+		let send_order = u64::decode(r)?;
+
+		// Unknown extensions are ignored.
+		let mut params = Params::decode(r)?;
+
+		Ok(Self {
+			subscribe_id,
+			track_alias,
+			group_id,
+			send_order,
+			media_time: params.get(Self::MEDIA_TIME)?,
+			wall_time: params.get(Self::WALL_TIME)?,
+			annotations: params
+				.get::<Annotations>(Self::ANNOTATIONS)?
+				.map(|annotations| annotations.0)
+				.unwrap_or_default(),
+			send_time: params.get(Self::SEND_TIME)?,
+		})
+	}
+}
+
+impl Encode for GroupExtHeader {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.subscribe_id.encode(w)?;
+		self.track_alias.encode(w)?;
+		self.group_id.encode(w)?;
+		self.send_order.encode(w)?;
+
+		let mut params = Params::new();
+		if let Some(media_time) = self.media_time {
+			params.set(Self::MEDIA_TIME, media_time)?;
+		}
+		if let Some(wall_time) = self.wall_time {
+			params.set(Self::WALL_TIME, wall_time)?;
+		}
+		if !self.annotations.is_empty() {
+			params.set(Self::ANNOTATIONS, Annotations(self.annotations.clone()))?;
+		}
+		if let Some(send_time) = self.send_time {
+			params.set(Self::SEND_TIME, send_time)?;
+		}
+		params.encode(w)?;
+
+		Ok(())
+	}
+}
+
+// Encoded as a count followed by each key and value, both length prefixed.
+struct Annotations(BTreeMap<String, Bytes>);
+
+impl Decode for Annotations {
+	fn decode<R: Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let mut annotations = BTreeMap::new();
+
+		let count = u64::decode(r)?;
+		for _ in 0..count {
+			let key = String::decode(r)?;
+
+			let size = usize::decode(r)?;
+			Self::decode_remaining(r, size)?;
+			let value = r.copy_to_bytes(size);
+
+			if annotations.insert(key, value).is_some() {
+				return Err(DecodeError::DupliateParameter);
+			}
+		}
+
+		Ok(Self(annotations))
+	}
+}
+
+impl Encode for Annotations {
+	fn encode<W: BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.0.len().encode(w)?;
+
+		for (key, value) in &self.0 {
+			key.encode(w)?;
+			value.len().encode(w)?;
+			Self::encode_remaining(w, value.len())?;
+			w.put_slice(value);
+		}
+
+		Ok(())
+	}
+}
+
 #[derive(Clone, Debug)]
 pub struct GroupObject {
 	pub object_id: u64,
 	pub size: usize,
+
+	// Only sent when the extensions capability was negotiated, see [Self::decode_extended].
+	pub extensions: Extensions,
+}
+
+impl GroupObject {
+	/// Decode an object with its extension headers, used when the extensions capability was negotiated.
+	pub fn decode_extended<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let object_id = u64::decode(r)?;
+		let extensions = Extensions::decode(r)?;
+		let size = usize::decode(r)?;
+
+		Ok(Self {
+			object_id,
+			size,
+			extensions,
+		})
+	}
+
+	/// Encode an object with its extension headers, used when the extensions capability was negotiated.
+	pub fn encode_extended<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.object_id.encode(w)?;
+		self.extensions.encode(w)?;
+		self.size.encode(w)?;
+
+		Ok(())
+	}
 }
 
 impl Decode for GroupObject {
@@ -48,7 +210,11 @@ impl Decode for GroupObject {
 		let object_id = u64::decode(r)?;
 		let size = usize::decode(r)?;
 
-		Ok(Self { object_id, size })
+		Ok(Self {
+			object_id,
+			size,
+			extensions: Default::default(),
+		})
 	}
 }
 
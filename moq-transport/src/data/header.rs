@@ -2,7 +2,7 @@ use crate::coding::{Decode, DecodeError, Encode, EncodeError};
 use paste::paste;
 use std::fmt;
 
-use super::{GroupHeader, ObjectHeader, TrackHeader};
+use super::{GroupExtHeader, GroupHeader, ObjectHeader, SubgroupHeader, TrackHeader};
 
 // Use a macro to generate the message types rather than copy-paste.
 // This implements a decode/encode method that uses the specified type.
@@ -90,4 +90,19 @@ header_types! {
 	//Datagram = 0x1,
 	Group = 0x51,
 	Track = 0x50,
+	GroupExt = 0x52,
+	Subgroup = 0x53,
+}
+
+impl Header {
+	/// The group ID, or None if the stream contains the entire track.
+	pub fn group_id(&self) -> Option<u64> {
+		match self {
+			Self::Object(o) => Some(o.group_id),
+			Self::Group(o) => Some(o.group_id),
+			Self::GroupExt(o) => Some(o.group_id),
+			Self::Subgroup(o) => Some(o.group_id),
+			Self::Track(_) => None,
+		}
+	}
 }
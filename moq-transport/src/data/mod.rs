@@ -1,11 +1,15 @@
 mod datagram;
+mod extensions;
 mod group;
 mod header;
 mod object;
+mod subgroup;
 mod track;
 
 pub use datagram::*;
+pub use extensions::*;
 pub use group::*;
 pub use header::*;
 pub use object::*;
+pub use subgroup::*;
 pub use track::*;
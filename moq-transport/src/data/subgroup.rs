@@ -0,0 +1,53 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// A stream containing a subset of a group's objects, ex. an enhancement layer.
+///
+/// Each subgroup is sent on its own stream with its own priority, followed by [GroupObject](super::GroupObject)s.
+///
+/// NOTE: This is an extension and must only be sent when the subgroups capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct SubgroupHeader {
+	// The subscribe ID.
+	pub subscribe_id: u64,
+
+	// The track alias.
+	pub track_alias: u64,
+
+	// The group sequence number
+	pub group_id: u64,
+
+	// The subgroup ID within the group.
+	pub subgroup_id: u64,
+
+	// The number of subgroups in the group, so the subscriber knows when every stream has arrived.
+	pub subgroups: u64,
+
+	// The priority, where **smaller** values are sent first.
+	pub send_order: u64,
+}
+
+impl Decode for SubgroupHeader {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(Self {
+			subscribe_id: u64::decode(r)?,
+			track_alias: u64::decode(r)?,
+			group_id: u64::decode(r)?,
+			subgroup_id: u64::decode(r)?,
+			subgroups: u64::decode(r)?,
+			send_order: u64::decode(r)?,
+		})
+	}
+}
+
+impl Encode for SubgroupHeader {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.subscribe_id.encode(w)?;
+		self.track_alias.encode(w)?;
+		self.group_id.encode(w)?;
+		self.subgroup_id.encode(w)?;
+		self.subgroups.encode(w)?;
+		self.send_order.encode(w)?;
+
+		Ok(())
+	}
+}
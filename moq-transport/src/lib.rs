@@ -8,7 +8,9 @@ pub mod coding;
 pub mod data;
 pub mod error;
 pub mod message;
+pub mod sample;
 pub mod serve;
 pub mod session;
 pub mod setup;
+pub mod transport;
 pub mod watch;
@@ -1,4 +1,5 @@
 use crate::coding::{Decode, DecodeError, Encode, EncodeError, Params};
+use crate::setup;
 
 /// Sent by the publisher to announce the availability of a group of tracks.
 #[derive(Clone, Debug)]
@@ -10,6 +11,17 @@ pub struct Announce {
 	pub params: Params,
 }
 
+impl Announce {
+	/// The parameter carrying the publisher's [AnnounceSignature], if any.
+	pub const SIGNATURE: u64 = 0x3;
+
+	/// The parameter carrying the broadcast ID chosen by the original publisher, forwarded as-is by relays.
+	pub const BROADCAST_ID: u64 = 0x4;
+
+	/// The parameter carrying the publisher's authorization token, if any.
+	pub const AUTHORIZATION: u64 = setup::AuthToken::PARAM;
+}
+
 impl Decode for Announce {
 	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
 		let namespace = String::decode(r)?;
@@ -27,3 +39,53 @@ impl Encode for Announce {
 		Ok(())
 	}
 }
+
+/// Proves an announce was made by the owner of the namespace, sent as the [Announce::SIGNATURE] parameter.
+///
+/// The signature algorithm and key lookup are left to the application; this only carries the bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnounceSignature {
+	/// When the announce was signed, in milliseconds since the Unix epoch, so old signatures can be refused.
+	pub timestamp: u64,
+
+	/// The signature over [Self::payload].
+	pub signature: Vec<u8>,
+}
+
+impl AnnounceSignature {
+	// Prevents a signature made for something else from being used as an announce signature.
+	const CONTEXT: &'static [u8] = b"moq-announce-v1";
+
+	/// The bytes that are signed: a fixed context, the namespace, and the timestamp.
+	pub fn payload(namespace: &str, timestamp: u64) -> Vec<u8> {
+		let mut payload = Vec::with_capacity(Self::CONTEXT.len() + namespace.len() + 16);
+		payload.extend_from_slice(Self::CONTEXT);
+		// Can't fail, since it's encoded to a Vec.
+		namespace.to_string().encode(&mut payload).ok();
+		payload.extend_from_slice(&timestamp.to_be_bytes());
+		payload
+	}
+}
+
+impl Decode for AnnounceSignature {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let timestamp = u64::decode(r)?;
+
+		// The parameter is already length prefixed, so the signature is the remainder.
+		let mut signature = vec![0; r.remaining()];
+		r.copy_to_slice(&mut signature);
+
+		Ok(Self { timestamp, signature })
+	}
+}
+
+impl Encode for AnnounceSignature {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.timestamp.encode(w)?;
+
+		Self::encode_remaining(w, self.signature.len())?;
+		w.put_slice(&self.signature);
+
+		Ok(())
+	}
+}
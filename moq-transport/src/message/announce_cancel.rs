@@ -5,23 +5,24 @@ use crate::coding::{Decode, DecodeError, Encode, EncodeError};
 pub struct AnnounceCancel {
 	// Echo back the namespace that was reset
 	pub namespace: String,
+
 	// An error code.
-	//pub code: u64,
+	pub code: u64,
 
 	// An optional, human-readable reason.
-	//pub reason: String,
+	pub reason: String,
 }
 
 impl Decode for AnnounceCancel {
 	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
 		let namespace = String::decode(r)?;
-		//let code = u64::decode(r)?;
-		//let reason = String::decode(r)?;
+		let code = u64::decode(r)?;
+		let reason = String::decode(r)?;
 
 		Ok(Self {
 			namespace,
-			//code,
-			//reason,
+			code,
+			reason,
 		})
 	}
 }
@@ -29,8 +30,8 @@ impl Decode for AnnounceCancel {
 impl Encode for AnnounceCancel {
 	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
 		self.namespace.encode(w)?;
-		//self.code.encode(w)?;
-		//self.reason.encode(w)?;
+		self.code.encode(w)?;
+		self.reason.encode(w)?;
 
 		Ok(())
 	}
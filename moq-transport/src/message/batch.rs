@@ -0,0 +1,155 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+use super::{Message, Subscriber};
+
+/// Sent by the subscriber to deliver several control messages at once, ex. the UNSUBSCRIBE and SUBSCRIBE messages
+/// of a player switching renditions across multiple tracks.
+///
+/// The publisher applies the messages in order, rejecting every SUBSCRIBE in the batch if any of them would fail.
+/// The messages always use the draft-03 encoding, regardless of the negotiated version, and can't be nested.
+///
+/// NOTE: This is an extension and must only be sent when the batch capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct Batch {
+	pub messages: Vec<Subscriber>,
+}
+
+impl Decode for Batch {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let count = u64::decode(r)?;
+
+		// Don't preallocate the requested count to avoid a possible attack.
+		let mut messages = Vec::new();
+		for _ in 0..count {
+			// Reject a nested batch before decoding it, otherwise each level would recurse.
+			let id = u64::decode(r)?;
+			if id == 0x3b {
+				return Err(DecodeError::InvalidMessage(id));
+			}
+
+			match Subscriber::try_from(Message::decode_type(id, r)?) {
+				Ok(msg) => messages.push(msg),
+				Err(_) => return Err(DecodeError::InvalidMessage(id)),
+			}
+		}
+
+		Ok(Self { messages })
+	}
+}
+
+impl Encode for Batch {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.messages.len().encode(w)?;
+
+		for msg in &self.messages {
+			Message::from(msg.clone()).encode(w)?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::message::{GoAway, GroupOrder, Subscribe, SubscribeLocation, SubscribePair, Unsubscribe};
+	use crate::setup::Version;
+
+	#[test]
+	fn encode_decode() {
+		let batch = Batch {
+			messages: vec![Unsubscribe { id: 1 }.into(), Unsubscribe { id: 2 }.into()],
+		};
+
+		let mut buf = Vec::new();
+		batch.encode(&mut buf).unwrap();
+
+		let decoded = Batch::decode(&mut buf.as_slice()).unwrap();
+		let ids: Vec<u64> = decoded
+			.messages
+			.iter()
+			.map(|msg| match msg {
+				Subscriber::Unsubscribe(msg) => msg.id,
+				msg => panic!("unexpected message: {:?}", msg),
+			})
+			.collect();
+		assert_eq!(ids, vec![1, 2]);
+
+		// Only subscriber messages can be batched.
+		let mut buf = Vec::new();
+		1u64.encode(&mut buf).unwrap();
+		Message::from(GoAway {
+			url: "https://example.com".to_string(),
+		})
+		.encode(&mut buf)
+		.unwrap();
+		assert!(Batch::decode(&mut buf.as_slice()).is_err());
+	}
+
+	#[test]
+	fn nested() {
+		// Each level of nesting is only two bytes, so this would overflow the stack if decoded recursively.
+		let mut buf = Vec::new();
+		for _ in 0..100_000 {
+			0x3bu64.encode(&mut buf).unwrap();
+			1u64.encode(&mut buf).unwrap();
+		}
+
+		assert!(matches!(
+			Message::decode(&mut buf.as_slice()),
+			Err(DecodeError::InvalidMessage(0x3b))
+		));
+	}
+
+	#[test]
+	fn draft03_encoding() {
+		let subscribe = Subscribe {
+			id: 1,
+			track_alias: 1,
+			track_namespace: "live".to_string(),
+			track_name: "video".to_string(),
+			start: SubscribePair {
+				group: SubscribeLocation::Absolute(1),
+				object: SubscribeLocation::Absolute(0),
+			},
+			end: SubscribePair {
+				group: SubscribeLocation::Absolute(2),
+				object: SubscribeLocation::None,
+			},
+			group_order: GroupOrder::default(),
+			params: Default::default(),
+		};
+
+		let batch = Message::from(Batch {
+			messages: vec![subscribe.clone().into()],
+		});
+
+		let mut buf = Vec::new();
+		batch.encode_version(&mut buf, Version::DRAFT_03_FILTER).unwrap();
+
+		// The SUBSCRIBE inside isn't encoded with the draft-04 filter, unlike a standalone one.
+		let mut expected = Vec::new();
+		0x3bu64.encode(&mut expected).unwrap();
+		1u64.encode(&mut expected).unwrap();
+		Message::from(subscribe.clone()).encode(&mut expected).unwrap();
+		assert_eq!(buf, expected);
+
+		let mut standalone = Vec::new();
+		Message::from(subscribe)
+			.encode_version(&mut standalone, Version::DRAFT_03_FILTER)
+			.unwrap();
+		assert!(!expected.ends_with(&standalone));
+
+		let decoded = Message::decode_version(&mut buf.as_slice(), Version::DRAFT_03_FILTER).unwrap();
+		match decoded {
+			Message::Batch(batch) => match &batch.messages[..] {
+				[Subscriber::Subscribe(msg)] => {
+					assert_eq!(msg.start.group, SubscribeLocation::Absolute(1));
+					assert_eq!(msg.end.group, SubscribeLocation::Absolute(2));
+				}
+				msgs => panic!("unexpected messages: {:?}", msgs),
+			},
+			msg => panic!("unexpected message: {:?}", msg),
+		}
+	}
+}
@@ -0,0 +1,74 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Params};
+
+/// Sent by the subscriber to request a range of past groups for the given track.
+///
+/// Unlike a Subscribe, the publisher only sends what it has cached and then finishes.
+///
+/// NOTE: This must only be sent when the fetch capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct Fetch {
+	/// The fetch ID, sharing the same space as subscription IDs.
+	pub id: u64,
+
+	/// Track properties
+	pub track_namespace: String,
+	pub track_name: String,
+
+	/// The first group to fetch.
+	pub start_group: u64,
+
+	/// The last group to fetch, inclusive, or None for everything up to the latest group.
+	pub end_group: Option<u64>,
+
+	/// Optional parameters
+	pub params: Params,
+}
+
+impl Decode for Fetch {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+		let track_namespace = String::decode(r)?;
+		let track_name = String::decode(r)?;
+		let start_group = u64::decode(r)?;
+
+		Self::decode_remaining(r, 1)?;
+		let end_group = match r.get_u8() {
+			0 => None,
+			1 => Some(u64::decode(r)?),
+			_ => return Err(DecodeError::InvalidValue),
+		};
+
+		let params = Params::decode(r)?;
+
+		Ok(Self {
+			id,
+			track_namespace,
+			track_name,
+			start_group,
+			end_group,
+			params,
+		})
+	}
+}
+
+impl Encode for Fetch {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+		self.track_namespace.encode(w)?;
+		self.track_name.encode(w)?;
+		self.start_group.encode(w)?;
+
+		Self::encode_remaining(w, 1)?;
+		match self.end_group {
+			Some(end_group) => {
+				w.put_u8(1);
+				end_group.encode(w)?;
+			}
+			None => w.put_u8(0),
+		}
+
+		self.params.encode(w)?;
+
+		Ok(())
+	}
+}
@@ -0,0 +1,22 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Sent by the subscriber to abort a Fetch that's no longer needed.
+#[derive(Clone, Debug)]
+pub struct FetchCancel {
+	// The ID for this fetch.
+	pub id: u64,
+}
+
+impl Decode for FetchCancel {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+		Ok(Self { id })
+	}
+}
+
+impl Encode for FetchCancel {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+		Ok(())
+	}
+}
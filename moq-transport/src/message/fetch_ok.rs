@@ -0,0 +1,46 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Sent by the publisher to accept a Fetch.
+///
+/// The cached groups follow on a single track stream, which is finished once they've all been sent.
+#[derive(Clone, Debug)]
+pub struct FetchOk {
+	/// The ID for this fetch.
+	pub id: u64,
+
+	/// The latest group and object for the track.
+	pub latest: Option<(u64, u64)>,
+}
+
+impl Decode for FetchOk {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+
+		Self::decode_remaining(r, 1)?;
+		let latest = match r.get_u8() {
+			0 => None,
+			1 => Some((u64::decode(r)?, u64::decode(r)?)),
+			_ => return Err(DecodeError::InvalidValue),
+		};
+
+		Ok(Self { id, latest })
+	}
+}
+
+impl Encode for FetchOk {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+
+		Self::encode_remaining(w, 1)?;
+		match self.latest {
+			Some((group, object)) => {
+				w.put_u8(1);
+				group.encode(w)?;
+				object.encode(w)?;
+			}
+			None => w.put_u8(0),
+		}
+
+		Ok(())
+	}
+}
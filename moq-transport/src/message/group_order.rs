@@ -0,0 +1,48 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Param};
+
+/// The order in which the subscriber wants pending groups delivered, sent as a parameter in SUBSCRIBE.
+///
+/// NOTE: The draft has a GROUP_ORDER field instead, but a parameter is ignored by peers that don't support it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupOrder {
+	/// Use the publisher's order, sending each group as soon as it's available.
+	#[default]
+	Publisher,
+
+	/// Oldest group first, ex. for VOD where every group is played in order.
+	Ascending,
+
+	/// Newest group first, ex. for live media where older groups are stale.
+	Descending,
+}
+
+impl GroupOrder {
+	/// The parameter ID used for the group order, in the range reserved for extensions.
+	pub const PARAM: u64 = 0x3a;
+}
+
+impl Param for GroupOrder {
+	const ID: u64 = Self::PARAM;
+}
+
+impl Decode for GroupOrder {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		match u64::decode(r)? {
+			0x0 => Ok(Self::Publisher),
+			0x1 => Ok(Self::Ascending),
+			0x2 => Ok(Self::Descending),
+			_ => Err(DecodeError::InvalidValue),
+		}
+	}
+}
+
+impl Encode for GroupOrder {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		let v: u64 = match self {
+			Self::Publisher => 0x0,
+			Self::Ascending => 0x1,
+			Self::Descending => 0x2,
+		};
+		v.encode(w)
+	}
+}
@@ -0,0 +1,23 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Sent by either endpoint to check the peer is still alive, answered with [super::Pong].
+///
+/// NOTE: This is an extension and must only be sent when the keepalive capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct Ping {
+	/// Echoed in the PONG, so a late reply can be told apart.
+	pub sequence: u64,
+}
+
+impl Decode for Ping {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let sequence = u64::decode(r)?;
+		Ok(Self { sequence })
+	}
+}
+
+impl Encode for Ping {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.sequence.encode(w)
+	}
+}
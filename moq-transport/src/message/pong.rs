@@ -0,0 +1,20 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Sent in response to a [super::Ping], echoing its sequence.
+#[derive(Clone, Debug)]
+pub struct Pong {
+	pub sequence: u64,
+}
+
+impl Decode for Pong {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let sequence = u64::decode(r)?;
+		Ok(Self { sequence })
+	}
+}
+
+impl Encode for Pong {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.sequence.encode(w)
+	}
+}
@@ -50,4 +50,7 @@ publisher_msgs! {
 	SubscribeOk,
 	SubscribeError,
 	SubscribeDone,
+	SubscribeRenew,
+	FetchOk,
+	FetchError,
 }
@@ -0,0 +1,46 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Param};
+
+/// Limits how much of a subscription may be in flight, sent as a parameter in SUBSCRIBE.
+///
+/// The publisher only starts a group while fewer groups and bytes than the window are unacknowledged,
+/// and the subscriber acknowledges each group stream with [super::SubscribeAck] once it's been received.
+/// This bounds the memory used by a constrained subscriber, independent of QUIC flow control.
+///
+/// NOTE: This is an extension and must only be sent when the window capability was negotiated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReceiveWindow {
+	/// The maximum number of unacknowledged groups, or None for no limit.
+	pub groups: Option<u64>,
+
+	/// The maximum number of unacknowledged payload bytes, or None for no limit.
+	/// A group is always started once nothing is in flight, so a single group may exceed it.
+	pub bytes: Option<u64>,
+}
+
+impl ReceiveWindow {
+	/// The parameter ID used for the window, in the range reserved for extensions.
+	pub const PARAM: u64 = 0x39;
+}
+
+impl Param for ReceiveWindow {
+	const ID: u64 = Self::PARAM;
+}
+
+impl Decode for ReceiveWindow {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		// Zero means no limit.
+		let groups = Some(u64::decode(r)?).filter(|groups| *groups > 0);
+		let bytes = Some(u64::decode(r)?).filter(|bytes| *bytes > 0);
+
+		Ok(Self { groups, bytes })
+	}
+}
+
+impl Encode for ReceiveWindow {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.groups.unwrap_or(0).encode(w)?;
+		self.bytes.unwrap_or(0).encode(w)?;
+
+		Ok(())
+	}
+}
@@ -1,4 +1,7 @@
 use crate::coding::{Decode, DecodeError, Encode, EncodeError, Params};
+use crate::setup;
+
+use super::GroupOrder;
 
 /// Sent by the subscriber to request all future objects for the given track.
 ///
@@ -17,10 +20,39 @@ pub struct Subscribe {
 	pub start: SubscribePair,
 	pub end: SubscribePair,
 
+	/// The order to deliver pending groups, encoded as a parameter.
+	pub group_order: GroupOrder,
+
 	/// Optional parameters
 	pub params: Params,
 }
 
+impl Subscribe {
+	/// The parameter carrying the subscriber's authorization token, if any.
+	pub const AUTHORIZATION: u64 = setup::AuthToken::PARAM;
+
+	/// The parameter carrying how long the publisher may spend delivering each object, in milliseconds.
+	pub const DELIVERY_TIMEOUT: u64 = Params::DELIVERY_TIMEOUT;
+
+	// Split the group order out of the parameters, so it's only in one place.
+	fn decode_params<R: bytes::Buf>(r: &mut R) -> Result<(GroupOrder, Params), DecodeError> {
+		let mut params = Params::decode(r)?;
+		let group_order = params.get_typed::<GroupOrder>()?.unwrap_or_default();
+		Ok((group_order, params))
+	}
+
+	// Only send the group order when it's not the default, so older peers see the same message.
+	fn encode_params<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		if self.group_order == GroupOrder::Publisher {
+			return self.params.encode(w);
+		}
+
+		let mut params = self.params.clone();
+		params.set_typed(self.group_order)?;
+		params.encode(w)
+	}
+}
+
 impl Decode for Subscribe {
 	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
 		let id = u64::decode(r)?;
@@ -43,7 +75,7 @@ impl Decode for Subscribe {
 
 		// NOTE: There's some more location restrictions in the draft, but they're enforced at a higher level.
 
-		let params = Params::decode(r)?;
+		let (group_order, params) = Self::decode_params(r)?;
 
 		Ok(Self {
 			id,
@@ -52,6 +84,7 @@ impl Decode for Subscribe {
 			track_name,
 			start,
 			end,
+			group_order,
 			params,
 		})
 	}
@@ -67,7 +100,111 @@ impl Encode for Subscribe {
 		self.start.encode(w)?;
 		self.end.encode(w)?;
 
-		self.params.encode(w)?;
+		self.encode_params(w)?;
+
+		Ok(())
+	}
+}
+
+impl Subscribe {
+	/// Decode using the draft-04 format, where the range is a filter type instead of a pair of locations.
+	pub fn decode_draft04<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+		let track_alias = u64::decode(r)?;
+		let track_namespace = String::decode(r)?;
+		let track_name = String::decode(r)?;
+
+		let absolute = |group, object| SubscribePair {
+			group: SubscribeLocation::Absolute(group),
+			object: SubscribeLocation::Absolute(object),
+		};
+
+		let none = SubscribePair {
+			group: SubscribeLocation::None,
+			object: SubscribeLocation::None,
+		};
+
+		let (start, end) = match u64::decode(r)? {
+			// Latest group
+			0x1 => {
+				let start = SubscribePair {
+					group: SubscribeLocation::Latest(0),
+					object: SubscribeLocation::Absolute(0),
+				};
+				(start, none)
+			}
+			// Latest object
+			0x2 => {
+				let start = SubscribePair {
+					group: SubscribeLocation::Latest(0),
+					object: SubscribeLocation::Latest(0),
+				};
+				(start, none)
+			}
+			// Absolute start
+			0x3 => (absolute(u64::decode(r)?, u64::decode(r)?), none),
+			// Absolute range, where the end object is plus one and zero means the entire group.
+			0x4 => {
+				let start = absolute(u64::decode(r)?, u64::decode(r)?);
+				let end = SubscribePair {
+					group: SubscribeLocation::Absolute(u64::decode(r)?),
+					object: match u64::decode(r)? {
+						0 => SubscribeLocation::None,
+						object => SubscribeLocation::Absolute(object - 1),
+					},
+				};
+				(start, end)
+			}
+			_ => return Err(DecodeError::InvalidSubscribeLocation),
+		};
+
+		let (group_order, params) = Self::decode_params(r)?;
+
+		Ok(Self {
+			id,
+			track_alias,
+			track_namespace,
+			track_name,
+			start,
+			end,
+			group_order,
+			params,
+		})
+	}
+
+	/// Encode using the draft-04 format, failing if the range can't be expressed as a filter type.
+	pub fn encode_draft04<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+		self.track_alias.encode(w)?;
+		self.track_namespace.encode(w)?;
+		self.track_name.encode(w)?;
+
+		use SubscribeLocation::*;
+
+		match (&self.start.group, &self.start.object, &self.end.group, &self.end.object) {
+			(Latest(0), Absolute(0), None, None) => 0x1u64.encode(w)?,
+			(Latest(0), Latest(0), None, None) => 0x2u64.encode(w)?,
+			(Absolute(group), Absolute(object), None, None) => {
+				0x3u64.encode(w)?;
+				group.encode(w)?;
+				object.encode(w)?;
+			}
+			(Absolute(group), Absolute(object), Absolute(end), end_object) => {
+				0x4u64.encode(w)?;
+				group.encode(w)?;
+				object.encode(w)?;
+				end.encode(w)?;
+
+				match end_object {
+					None => 0u64.encode(w)?,
+					Absolute(end_object) => (end_object + 1).encode(w)?,
+					_ => return Err(EncodeError::InvalidValue),
+				}
+			}
+			_ => return Err(EncodeError::InvalidValue),
+		}
+
+		self.encode_params(w)?;
 
 		Ok(())
 	}
@@ -142,3 +279,93 @@ impl SubscribeLocation {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn subscribe(start: SubscribePair, end: SubscribePair) -> Subscribe {
+		let mut params = Params::new();
+		params.set(Params::DELIVERY_TIMEOUT, 100u64).unwrap();
+
+		Subscribe {
+			id: 1,
+			track_alias: 2,
+			track_namespace: "live".to_string(),
+			track_name: "video".to_string(),
+			start,
+			end,
+			group_order: GroupOrder::Descending,
+			params,
+		}
+	}
+
+	fn pair(group: SubscribeLocation, object: SubscribeLocation) -> SubscribePair {
+		SubscribePair { group, object }
+	}
+
+	#[test]
+	fn draft04() {
+		use SubscribeLocation::*;
+
+		let none = pair(None, None);
+		let ranges = [
+			// Latest group
+			(pair(Latest(0), Absolute(0)), none.clone(), 0x1),
+			// Latest object
+			(pair(Latest(0), Latest(0)), none.clone(), 0x2),
+			// Absolute start
+			(pair(Absolute(3), Absolute(4)), none.clone(), 0x3),
+			// Absolute range, through the end of the group or a specific object.
+			(pair(Absolute(3), Absolute(4)), pair(Absolute(5), None), 0x4),
+			(pair(Absolute(3), Absolute(4)), pair(Absolute(5), Absolute(0)), 0x4),
+		];
+
+		for (start, end, filter) in ranges {
+			let msg = subscribe(start, end);
+			let mut buf = Vec::new();
+			msg.encode_draft04(&mut buf).unwrap();
+
+			// The filter type follows the ID, alias, namespace and name.
+			assert_eq!(buf[1 + 1 + 5 + 6], filter);
+
+			let mut decoded = Subscribe::decode_draft04(&mut buf.as_slice()).unwrap();
+			assert_eq!(decoded.id, msg.id);
+			assert_eq!(decoded.track_alias, msg.track_alias);
+			assert_eq!(decoded.track_namespace, msg.track_namespace);
+			assert_eq!(decoded.track_name, msg.track_name);
+			assert_eq!(decoded.start, msg.start);
+			assert_eq!(decoded.end, msg.end);
+			assert_eq!(decoded.group_order, msg.group_order);
+			assert_eq!(decoded.params.get::<u64>(Params::DELIVERY_TIMEOUT).unwrap(), Some(100));
+		}
+
+		// Ranges without a filter type can't be encoded.
+		let unsupported = [
+			(pair(None, None), none.clone()),
+			(pair(Latest(1), Absolute(0)), none.clone()),
+			(pair(Future(1), Absolute(0)), none.clone()),
+			(pair(Absolute(3), Absolute(4)), pair(Latest(0), None)),
+			(pair(Latest(0), Absolute(0)), pair(Absolute(5), None)),
+		];
+
+		for (start, end) in unsupported {
+			let msg = subscribe(start, end);
+			assert!(matches!(
+				msg.encode_draft04(&mut Vec::new()),
+				Err(EncodeError::InvalidValue)
+			));
+		}
+
+		// Unknown filter types are rejected.
+		let mut buf = Vec::new();
+		subscribe(pair(Latest(0), Absolute(0)), none)
+			.encode_draft04(&mut buf)
+			.unwrap();
+		buf[1 + 1 + 5 + 6] = 0x5;
+		assert!(matches!(
+			Subscribe::decode_draft04(&mut buf.as_slice()),
+			Err(DecodeError::InvalidSubscribeLocation)
+		));
+	}
+}
@@ -0,0 +1,33 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Sent by the subscriber once a group stream has been received, opening the [super::ReceiveWindow].
+///
+/// A group split into subgroups is acknowledged once for each subgroup stream.
+///
+/// NOTE: This is an extension and must only be sent when the window capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct SubscribeAck {
+	/// The ID for this subscription.
+	pub id: u64,
+
+	/// The group that was received.
+	pub group_id: u64,
+}
+
+impl Decode for SubscribeAck {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+		let group_id = u64::decode(r)?;
+
+		Ok(Self { id, group_id })
+	}
+}
+
+impl Encode for SubscribeAck {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+		self.group_id.encode(w)?;
+
+		Ok(())
+	}
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::coding::{Decode, DecodeError, Encode, EncodeError};
 
 /// Sent by the publisher to cleanly terminate a Subscribe.
@@ -52,3 +54,49 @@ impl Encode for SubscribeDone {
 		Ok(())
 	}
 }
+
+/// Why a subscription ended without an error, carried in the SUBSCRIBE_DONE code.
+///
+/// Any other code is an error, so older subscribers treat [Self::Expired] and [Self::GoingAway] as one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubscribeStatus {
+	/// The track ended, or the requested range was delivered.
+	#[default]
+	Ended,
+
+	/// The subscription expired, ex. its authorization token wasn't renewed.
+	Expired,
+
+	/// The publisher is going away, so the subscriber should resubscribe elsewhere.
+	GoingAway,
+}
+
+impl SubscribeStatus {
+	pub fn code(&self) -> u64 {
+		match self {
+			Self::Ended => 0x0,
+			Self::GoingAway => 0x5,
+			Self::Expired => 0x6,
+		}
+	}
+
+	/// Returns None if the code is an error.
+	pub fn from_code(code: u64) -> Option<Self> {
+		match code {
+			0x0 => Some(Self::Ended),
+			0x5 => Some(Self::GoingAway),
+			0x6 => Some(Self::Expired),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for SubscribeStatus {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Ended => write!(f, "ended"),
+			Self::Expired => write!(f, "expired"),
+			Self::GoingAway => write!(f, "going away"),
+		}
+	}
+}
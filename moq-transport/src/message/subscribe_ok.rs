@@ -11,6 +11,16 @@ pub struct SubscribeOk {
 
 	/// The latest group and object for the track.
 	pub latest: Option<(u64, u64)>,
+
+	/// The track epoch, bumped each time the publisher restarts.
+	///
+	/// NOTE: This is an extension and must only be sent when the epoch capability was negotiated.
+	pub epoch: Option<u64>,
+}
+
+impl SubscribeOk {
+	const LATEST: u8 = 0x1;
+	const EPOCH: u8 = 0x2;
 }
 
 impl Decode for SubscribeOk {
@@ -23,13 +33,28 @@ impl Decode for SubscribeOk {
 
 		Self::decode_remaining(r, 1)?;
 
-		let latest = match r.get_u8() {
+		// The draft only uses 0 or 1, so the extra bits are backwards compatible.
+		let flags = r.get_u8();
+		if flags & !(Self::LATEST | Self::EPOCH) != 0 {
+			return Err(DecodeError::InvalidValue);
+		}
+
+		let latest = match flags & Self::LATEST {
 			0 => None,
-			1 => Some((u64::decode(r)?, u64::decode(r)?)),
-			_ => return Err(DecodeError::InvalidValue),
+			_ => Some((u64::decode(r)?, u64::decode(r)?)),
 		};
 
-		Ok(Self { id, expires, latest })
+		let epoch = match flags & Self::EPOCH {
+			0 => None,
+			_ => Some(u64::decode(r)?),
+		};
+
+		Ok(Self {
+			id,
+			expires,
+			latest,
+			epoch,
+		})
 	}
 }
 
@@ -40,17 +65,50 @@ impl Encode for SubscribeOk {
 
 		Self::encode_remaining(w, 1)?;
 
-		match self.latest {
-			Some((group, object)) => {
-				w.put_u8(1);
-				group.encode(w)?;
-				object.encode(w)?;
-			}
-			None => {
-				w.put_u8(0);
-			}
+		let mut flags = 0;
+		if self.latest.is_some() {
+			flags |= Self::LATEST;
+		}
+		if self.epoch.is_some() {
+			flags |= Self::EPOCH;
+		}
+
+		w.put_u8(flags);
+
+		if let Some((group, object)) = self.latest {
+			group.encode(w)?;
+			object.encode(w)?;
+		}
+
+		if let Some(epoch) = self.epoch {
+			epoch.encode(w)?;
 		}
 
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bytes::BytesMut;
+
+	#[test]
+	fn encode_decode_epoch() {
+		let mut buf = BytesMut::new();
+
+		let msg = SubscribeOk {
+			id: 1,
+			expires: None,
+			latest: Some((2, 3)),
+			epoch: Some(4),
+		};
+
+		msg.encode(&mut buf).unwrap();
+		assert_eq!(buf.to_vec(), vec![0x01, 0x00, 0x03, 0x02, 0x03, 0x04]);
+
+		let decoded = SubscribeOk::decode(&mut buf).unwrap();
+		assert_eq!(decoded.latest, msg.latest);
+		assert_eq!(decoded.epoch, msg.epoch);
+	}
+}
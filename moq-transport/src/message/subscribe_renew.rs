@@ -0,0 +1,32 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Sent by the publisher when a subscription's authorization expires, asking for a new token.
+///
+/// The subscriber replies with [super::SubscribeRenewOk], otherwise the subscription is closed after `expires`.
+/// NOTE: This is an extension and must only be sent when the renewal capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct SubscribeRenew {
+	/// The ID for this subscription.
+	pub id: u64,
+
+	/// The subscription will be closed in this many milliseconds without a new token.
+	pub expires: u64,
+}
+
+impl Decode for SubscribeRenew {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+		let expires = u64::decode(r)?;
+
+		Ok(Self { id, expires })
+	}
+}
+
+impl Encode for SubscribeRenew {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+		self.expires.encode(w)?;
+
+		Ok(())
+	}
+}
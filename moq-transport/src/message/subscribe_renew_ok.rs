@@ -0,0 +1,31 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// Sent by the subscriber in response to [super::SubscribeRenew], with a new authorization token.
+///
+/// NOTE: This is an extension and must only be sent when the renewal capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct SubscribeRenewOk {
+	/// The ID for this subscription.
+	pub id: u64,
+
+	/// The new token, replacing the one sent with the SUBSCRIBE.
+	pub authorization: String,
+}
+
+impl Decode for SubscribeRenewOk {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+		let authorization = String::decode(r)?;
+
+		Ok(Self { id, authorization })
+	}
+}
+
+impl Encode for SubscribeRenewOk {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+		self.authorization.encode(w)?;
+
+		Ok(())
+	}
+}
@@ -0,0 +1,83 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Params};
+
+use super::{SubscribeLocation, SubscribePair};
+
+/// Sent by the subscriber to change an active subscription without resubscribing.
+///
+/// The range and priority replace the ones requested so far.
+///
+/// NOTE: This is an extension and must only be sent when the update capability was negotiated.
+#[derive(Clone, Debug)]
+pub struct SubscribeUpdate {
+	/// The ID for this subscription.
+	pub id: u64,
+
+	/// The new start/end group/object.
+	pub start: SubscribePair,
+	pub end: SubscribePair,
+
+	/// Deliver this subscription's streams at this priority instead of the publisher's.
+	pub priority: Option<u64>,
+
+	/// Optional parameters
+	pub params: Params,
+}
+
+impl Decode for SubscribeUpdate {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let id = u64::decode(r)?;
+
+		let start = SubscribePair::decode(r)?;
+		let end = SubscribePair::decode(r)?;
+
+		// Same as SUBSCRIBE, you can't have an object without a group.
+		if start.group == SubscribeLocation::None && start.object != SubscribeLocation::None {
+			return Err(DecodeError::InvalidSubscribeLocation);
+		}
+
+		if end.group == SubscribeLocation::None && end.object != SubscribeLocation::None {
+			return Err(DecodeError::InvalidSubscribeLocation);
+		}
+
+		Self::decode_remaining(r, 1)?;
+
+		let priority = match r.get_u8() {
+			0 => None,
+			1 => Some(u64::decode(r)?),
+			_ => return Err(DecodeError::InvalidValue),
+		};
+
+		let params = Params::decode(r)?;
+
+		Ok(Self {
+			id,
+			start,
+			end,
+			priority,
+			params,
+		})
+	}
+}
+
+impl Encode for SubscribeUpdate {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.id.encode(w)?;
+
+		self.start.encode(w)?;
+		self.end.encode(w)?;
+
+		Self::encode_remaining(w, 1)?;
+
+		match self.priority {
+			Some(priority) => {
+				w.put_u8(1);
+				priority.encode(w)?;
+			}
+			None => w.put_u8(0),
+		}
+
+		self.params.encode(w)?;
+
+		Ok(())
+	}
+}
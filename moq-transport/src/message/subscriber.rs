@@ -50,4 +50,10 @@ subscriber_msgs! {
 	AnnounceCancel,
 	Subscribe,
 	Unsubscribe,
+	SubscribeRenewOk,
+	SubscribeUpdate,
+	SubscribeAck,
+	Batch,
+	Fetch,
+	FetchCancel,
 }
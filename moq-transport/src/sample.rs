@@ -0,0 +1,148 @@
+//! Sampled logging, so per-object log lines don't explode under load.
+//!
+//! Each call site has a key and is logged on every Nth occurrence, at most once per interval.
+//! The number of suppressed lines since the last one is appended to the message.
+//! Sampling is disabled by default and configured once per process with [configure].
+use std::{
+	collections::HashMap,
+	sync::{Mutex, OnceLock},
+	time::{Duration, Instant},
+};
+
+/// How often a sampled log line is emitted, per key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleConfig {
+	/// Log every Nth occurrence; 1 logs every occurrence.
+	pub every: u64,
+
+	/// Log at most once per interval, if provided.
+	pub interval: Option<Duration>,
+}
+
+impl Default for SampleConfig {
+	fn default() -> Self {
+		Self {
+			every: 1,
+			interval: None,
+		}
+	}
+}
+
+impl SampleConfig {
+	fn disabled(&self) -> bool {
+		self.every <= 1 && self.interval.is_none()
+	}
+}
+
+#[derive(Default)]
+struct Counter {
+	count: u64,
+	suppressed: u64,
+	last: Option<Instant>,
+}
+
+impl Counter {
+	fn sample(&mut self, config: &SampleConfig, now: Instant) -> Option<u64> {
+		self.count += 1;
+
+		let nth = (self.count - 1).is_multiple_of(config.every.max(1));
+		let elapsed = match (config.interval, self.last) {
+			(Some(interval), Some(last)) => now.duration_since(last) >= interval,
+			_ => true,
+		};
+
+		if nth && elapsed {
+			self.last = Some(now);
+			Some(std::mem::take(&mut self.suppressed))
+		} else {
+			self.suppressed += 1;
+			None
+		}
+	}
+}
+
+struct Sampler {
+	config: SampleConfig,
+	counters: Mutex<HashMap<&'static str, Counter>>,
+}
+
+static SAMPLER: OnceLock<Sampler> = OnceLock::new();
+
+/// Set the sampling configuration for the process, returning false if it was already set.
+pub fn configure(config: SampleConfig) -> bool {
+	SAMPLER
+		.set(Sampler {
+			config,
+			counters: Default::default(),
+		})
+		.is_ok()
+}
+
+/// Returns the number of suppressed occurrences if this occurrence of the key should be logged.
+pub fn check(key: &'static str) -> Option<u64> {
+	let sampler = match SAMPLER.get() {
+		Some(sampler) if !sampler.config.disabled() => sampler,
+		_ => return Some(0),
+	};
+
+	let mut counters = sampler.counters.lock().unwrap();
+	counters.entry(key).or_default().sample(&sampler.config, Instant::now())
+}
+
+/// Log a line subject to sampling, keyed by call site, ex. `sampled!(log::Level::Trace, "sent group", "{:?}", header)`.
+#[macro_export]
+macro_rules! sampled {
+	($level:expr, $key:expr) => {
+		if ::log::log_enabled!($level) {
+			if let Some(suppressed) = $crate::sample::check($key) {
+				match suppressed {
+					0 => ::log::log!($level, "{}", $key),
+					n => ::log::log!($level, "{} (suppressed={})", $key, n),
+				}
+			}
+		}
+	};
+	($level:expr, $key:expr, $($arg:tt)+) => {
+		if ::log::log_enabled!($level) {
+			if let Some(suppressed) = $crate::sample::check($key) {
+				match suppressed {
+					0 => ::log::log!($level, "{}: {}", $key, format_args!($($arg)+)),
+					n => ::log::log!($level, "{}: {} (suppressed={})", $key, format_args!($($arg)+), n),
+				}
+			}
+		}
+	};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every() {
+		let config = SampleConfig {
+			every: 3,
+			interval: None,
+		};
+
+		let mut counter = Counter::default();
+		let now = Instant::now();
+		let logged: Vec<_> = (0..7).map(|_| counter.sample(&config, now)).collect();
+		assert_eq!(logged, [Some(0), None, None, Some(2), None, None, Some(2)]);
+	}
+
+	#[test]
+	fn interval() {
+		let config = SampleConfig {
+			every: 1,
+			interval: Some(Duration::from_secs(1)),
+		};
+
+		let mut counter = Counter::default();
+		let start = Instant::now();
+		assert_eq!(counter.sample(&config, start), Some(0));
+		assert_eq!(counter.sample(&config, start + Duration::from_millis(500)), None);
+		assert_eq!(counter.sample(&config, start + Duration::from_millis(999)), None);
+		assert_eq!(counter.sample(&config, start + Duration::from_secs(1)), Some(2));
+	}
+}
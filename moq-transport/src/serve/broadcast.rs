@@ -1,159 +1,258 @@
-//! A broadcast is a collection of tracks, split into two handles: [Writer] and [Reader].
+//! A typed builder for publishing a broadcast, an alternative to [Tracks] and [TracksWriter::create].
 //!
-//! The [Writer] can create tracks, either manually or on request.
-//! It receives all requests by a [Reader] for a tracks that don't exist.
-//! The simplest implementation is to close every unknown track with [ServeError::NotFound].
+//! Each track is declared with a [TrackName] and a [mode], and the writer for that mode is returned directly.
+//! This avoids formatting names by hand and unwrapping the mode of every [TrackWriter].
 //!
-//! A [Reader] can request tracks by name.
-//! If the track already exists, it will be returned.
-//! If the track doesn't exist, it will be sent to [Unknown] to be handled.
-//! A [Reader] can be cloned to create multiple subscriptions.
+//! ```
+//! use moq_transport::serve::{mode, Broadcast};
 //!
-//! The broadcast is automatically closed with [ServeError::Done] when [Writer] is dropped, or all [Reader]s are dropped.
-use std::{
-	collections::{hash_map, HashMap},
-	ops::Deref,
-	sync::Arc,
-};
+//! let (mut broadcast, _request, _reader) = Broadcast::builder().namespace("clock").produce().unwrap();
+//! let mut now = broadcast.track("now").mode(mode::Groups).create().unwrap();
+//! now.append(0).unwrap().write("12:00".into()).unwrap();
+//! ```
+use std::{marker::PhantomData, ops::Deref};
 
-use super::{ServeError, Track, TrackReader, TrackWriter};
-use crate::util::State;
+use super::{
+	DatagramsWriter, GroupsWriter, ObjectsWriter, ServeError, StreamWriter, TrackCache, TrackName, TrackWriter, Tracks,
+	TracksReader, TracksRequest, TracksWriter, Usage,
+};
 
-/// Static information about a broadcast.
-#[derive(Debug)]
+/// Publishes typed tracks for a broadcast, created with [Broadcast::builder].
 pub struct Broadcast {
-	pub namespace: String,
+	tracks: TracksWriter,
+	epoch: Option<u64>,
 }
 
 impl Broadcast {
-	pub fn new(namespace: &str) -> Self {
-		Self {
-			namespace: namespace.to_owned(),
+	pub fn builder() -> BroadcastBuilder {
+		BroadcastBuilder::default()
+	}
+
+	/// Declare a track, created once a [mode] is chosen.
+	///
+	/// NOTE: An existing track is replaced, like [TracksWriter::create].
+	pub fn track(&mut self, name: impl Into<TrackName>) -> BroadcastTrack<'_> {
+		BroadcastTrack {
+			broadcast: self,
+			name: name.into(),
+			mode: PhantomData,
+			priority: 0,
 		}
 	}
 
-	pub fn produce(self) -> (BroadcastWriter, BroadcastReader) {
-		let (send, recv) = State::init();
-		let info = Arc::new(self);
+	pub fn remove(&mut self, name: impl Into<TrackName>) -> bool {
+		self.tracks.remove(&name.into().to_string()).is_some()
+	}
+}
 
-		let writer = BroadcastWriter::new(send, info.clone());
-		let reader = BroadcastReader::new(recv, info);
+impl Deref for Broadcast {
+	type Target = Tracks;
 
-		(writer, reader)
+	fn deref(&self) -> &Self::Target {
+		&self.tracks.info
 	}
 }
 
-/// Dynamic information about the broadcast.
-struct BroadcastState {
-	tracks: HashMap<String, TrackReader>,
-	closed: Result<(), ServeError>,
+/// Configures a [Broadcast] before producing it.
+#[derive(Default)]
+pub struct BroadcastBuilder {
+	namespace: Option<String>,
+	broadcast_id: Option<String>,
+	usage: Option<Usage>,
+	cache: Option<TrackCache>,
+	epoch: Option<u64>,
 }
 
-impl BroadcastState {
-	pub fn get(&self, name: &str) -> Result<Option<TrackReader>, ServeError> {
-		match self.tracks.get(name) {
-			Some(track) => Ok(Some(track.clone())),
-			// Return any error if we couldn't find a track.
-			None => self.closed.clone().map(|_| None),
-		}
+impl BroadcastBuilder {
+	pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+		self.namespace = Some(namespace.into());
+		self
 	}
 
-	pub fn insert(&mut self, track: TrackReader) -> Result<(), ServeError> {
-		match self.tracks.entry(track.name.clone()) {
-			hash_map::Entry::Occupied(_) => return Err(ServeError::Duplicate),
-			hash_map::Entry::Vacant(v) => v.insert(track),
-		};
+	/// See [Tracks::with_broadcast_id].
+	pub fn broadcast_id(mut self, id: impl Into<String>) -> Self {
+		self.broadcast_id = Some(id.into());
+		self
+	}
 
-		Ok(())
+	/// See [Tracks::with_usage].
+	pub fn usage(mut self, usage: Usage) -> Self {
+		self.usage = Some(usage);
+		self
 	}
 
-	pub fn remove(&mut self, name: &str) -> Option<TrackReader> {
-		self.tracks.remove(name)
+	/// See [Tracks::with_cache].
+	pub fn cache(mut self, cache: TrackCache) -> Self {
+		self.cache = Some(cache);
+		self
 	}
-}
 
-impl Default for BroadcastState {
-	fn default() -> Self {
-		Self {
-			tracks: HashMap::new(),
-			closed: Ok(()),
+	/// Set the epoch of every track, see [TrackWriter::set_epoch].
+	pub fn epoch(mut self, epoch: u64) -> Self {
+		self.epoch = Some(epoch);
+		self
+	}
+
+	/// Returns [ServeError::Internal] if the namespace wasn't set.
+	pub fn produce(self) -> Result<(Broadcast, TracksRequest, TracksReader), ServeError> {
+		let namespace = self
+			.namespace
+			.ok_or_else(|| ServeError::Internal("broadcast namespace not set".to_string()))?;
+
+		let mut tracks = Tracks::new(namespace);
+		if let Some(id) = self.broadcast_id {
+			tracks = tracks.with_broadcast_id(id);
+		}
+		if let Some(usage) = self.usage {
+			tracks = tracks.with_usage(usage);
 		}
+		if let Some(cache) = self.cache {
+			tracks = tracks.with_cache(cache);
+		}
+
+		let (writer, request, reader) = tracks.produce();
+		let broadcast = Broadcast {
+			tracks: writer,
+			epoch: self.epoch,
+		};
+
+		Ok((broadcast, request, reader))
 	}
 }
 
-/// Publish new tracks for a broadcast by name.
-pub struct BroadcastWriter {
-	state: State<BroadcastState>,
-	pub info: Arc<Broadcast>,
+/// Marker types choosing how a track is delivered, and so which writer is returned.
+pub mod mode {
+	/// A stream per group, returning a [GroupsWriter](super::GroupsWriter).
+	pub struct Groups;
+
+	/// A stream per object, returning an [ObjectsWriter](super::ObjectsWriter).
+	pub struct Objects;
+
+	/// A datagram per object, returning a [DatagramsWriter](super::DatagramsWriter).
+	pub struct Datagrams;
+
+	/// A single stream for the entire track, returning a [StreamWriter](super::StreamWriter).
+	pub struct Stream;
 }
 
-impl BroadcastWriter {
-	fn new(state: State<BroadcastState>, broadcast: Arc<Broadcast>) -> Self {
-		Self { state, info: broadcast }
-	}
+/// A mode for [BroadcastTrack::mode], see [mode].
+pub trait TrackMode {
+	type Writer;
 
-	/// Create a new track with the given name, inserting it into the broadcast.
-	pub fn create_track(&mut self, track: &str) -> Result<TrackWriter, ServeError> {
-		let (writer, reader) = Track {
-			namespace: self.namespace.clone(),
-			name: track.to_owned(),
-		}
-		.produce();
+	fn produce(track: TrackWriter, priority: u64) -> Result<Self::Writer, ServeError>;
+}
 
-		self.state.lock_mut().ok_or(ServeError::Cancel)?.insert(reader)?;
+impl TrackMode for mode::Groups {
+	type Writer = GroupsWriter;
 
-		Ok(writer)
+	fn produce(track: TrackWriter, _: u64) -> Result<Self::Writer, ServeError> {
+		track.groups()
 	}
+}
 
-	pub fn remove_track(&mut self, track: &str) -> Option<TrackReader> {
-		self.state.lock_mut()?.remove(track)
+impl TrackMode for mode::Objects {
+	type Writer = ObjectsWriter;
+
+	fn produce(track: TrackWriter, _: u64) -> Result<Self::Writer, ServeError> {
+		track.objects()
 	}
+}
 
-	/// Close the broadcast with an error.
-	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
-		let state = self.state.lock();
-		state.closed.clone()?;
+impl TrackMode for mode::Datagrams {
+	type Writer = DatagramsWriter;
 
-		if let Some(mut state) = state.into_mut() {
-			state.closed = Err(err);
-		}
-
-		Ok(())
+	fn produce(track: TrackWriter, _: u64) -> Result<Self::Writer, ServeError> {
+		track.datagrams()
 	}
 }
 
-impl Deref for BroadcastWriter {
-	type Target = Broadcast;
+impl TrackMode for mode::Stream {
+	type Writer = StreamWriter;
 
-	fn deref(&self) -> &Self::Target {
-		&self.info
+	fn produce(track: TrackWriter, priority: u64) -> Result<Self::Writer, ServeError> {
+		track.stream(priority)
 	}
 }
 
-/// Subscribe to a broadcast by requesting tracks.
-///
-/// This can be cloned to create handles.
-#[derive(Clone)]
-pub struct BroadcastReader {
-	state: State<BroadcastState>,
-	pub info: Arc<Broadcast>,
+/// A track declared with [Broadcast::track], created by [Self::create] once the mode is chosen.
+pub struct BroadcastTrack<'a, M = ()> {
+	broadcast: &'a mut Broadcast,
+	name: TrackName,
+	mode: PhantomData<M>,
+	priority: u64,
+}
+
+impl<'a, M> BroadcastTrack<'a, M> {
+	pub fn mode<N: TrackMode>(self, _: N) -> BroadcastTrack<'a, N> {
+		BroadcastTrack {
+			broadcast: self.broadcast,
+			name: self.name,
+			mode: PhantomData,
+			priority: self.priority,
+		}
+	}
 }
 
-impl BroadcastReader {
-	fn new(state: State<BroadcastState>, broadcast: Arc<Broadcast>) -> Self {
-		Self { state, info: broadcast }
+impl BroadcastTrack<'_, mode::Stream> {
+	/// The priority of the track's stream.
+	///
+	/// The other modes set a priority for each group or object instead.
+	pub fn priority(mut self, priority: u64) -> Self {
+		self.priority = priority;
+		self
 	}
+}
+
+impl<M: TrackMode> BroadcastTrack<'_, M> {
+	/// Insert the track into the broadcast and return the writer for its mode.
+	///
+	/// Returns [ServeError::Cancel] if all [TracksReader]s have been dropped.
+	pub fn create(self) -> Result<M::Writer, ServeError> {
+		let name = self.name.to_string();
+		let mut track = self.broadcast.tracks.create(&name).ok_or(ServeError::Cancel)?;
+		if let Some(epoch) = self.broadcast.epoch {
+			track.set_epoch(epoch)?;
+		}
 
-	/// Get a track from the broadcast by name.
-	pub fn get_track(&self, name: &str) -> Result<Option<TrackReader>, ServeError> {
-		self.state.lock().get(name)
+		M::produce(track, self.priority)
 	}
 }
 
-impl Deref for BroadcastReader {
-	type Target = Broadcast;
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::serve::TrackReaderMode;
 
-	fn deref(&self) -> &Self::Target {
-		&self.info
+	#[test]
+	fn typed_tracks() {
+		let res = Broadcast::builder().produce();
+		assert!(matches!(res, Err(ServeError::Internal(_))));
+
+		let (mut broadcast, _request, mut reader) = Broadcast::builder().namespace("test").epoch(3).produce().unwrap();
+		assert_eq!(broadcast.namespace, "test");
+
+		let name = TrackName::new("video.m4s").with("rendition", 720);
+		let mut video = broadcast.track(name.clone()).mode(mode::Groups).create().unwrap();
+		video.append(1).unwrap().write("frame".into()).unwrap();
+		let _audio = broadcast
+			.track("audio")
+			.mode(mode::Stream)
+			.priority(2)
+			.create()
+			.unwrap();
+
+		let track = reader.subscribe("video.m4s?rendition=720").unwrap();
+		assert_eq!(TrackName::parse(&track.name), name);
+		assert_eq!(track.epoch(), Some(3));
+		assert!(matches!(track.try_mode(), Ok(Some(TrackReaderMode::Groups(_)))));
+
+		let track = reader.subscribe("audio").unwrap();
+		match track.try_mode() {
+			Ok(Some(TrackReaderMode::Stream(stream))) => assert_eq!(stream.priority, 2),
+			_ => panic!("expected a stream"),
+		}
+
+		assert!(broadcast.remove("audio"));
+		assert!(!broadcast.remove("audio"));
 	}
 }
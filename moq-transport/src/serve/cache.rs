@@ -0,0 +1,65 @@
+//! Limits on the data a track retains for readers that fall behind or join late.
+//!
+//! Without limits, older groups are retained until every reader that doesn't skip has read them,
+//! and a stream group keeps every object until it's replaced.
+//! With `retain`, older groups are kept up to the limits even if no reader needs them, ex. for FETCH and late joiners.
+//! Data over the limit is evicted, and any reader that hasn't read it yet gets [ServeError::Expired](super::ServeError::Expired).
+//! The latest group is never evicted from a group track, so new subscribers always have somewhere to start.
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrackCache {
+	/// The number of older groups to retain, in addition to the latest group.
+	pub max_groups: Option<u64>,
+
+	/// The number of bytes to retain, including the latest group.
+	/// Unlike a [Usage](super::Usage) limit, older data is evicted instead of rejecting new groups.
+	pub max_bytes: Option<u64>,
+
+	/// How long to retain a group after it was created, or a stream object after it was written.
+	/// An object's `expires` can shorten this for the group containing it.
+	pub max_age: Option<Duration>,
+
+	/// Keep older groups up to the limits, instead of only while a reader needs them.
+	/// Ignored without a limit, otherwise every group would be retained forever.
+	pub retain: bool,
+}
+
+impl TrackCache {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with_max_groups(mut self, max: u64) -> Self {
+		self.max_groups = Some(max);
+		self
+	}
+
+	pub fn with_max_bytes(mut self, max: u64) -> Self {
+		self.max_bytes = Some(max);
+		self
+	}
+
+	pub fn with_max_age(mut self, max: Duration) -> Self {
+		self.max_age = Some(max);
+		self
+	}
+
+	pub fn with_retain(mut self) -> Self {
+		self.retain = true;
+		self
+	}
+
+	// Returns true if older groups are kept even if no reader needs them.
+	pub(super) fn retains(&self) -> bool {
+		self.retain && (self.max_groups.is_some() || self.max_bytes.is_some() || self.max_age.is_some())
+	}
+
+	// Returns true if something created at this time is too old to retain.
+	pub(super) fn is_expired(&self, created: Instant, now: Instant) -> bool {
+		self.max_age
+			.is_some_and(|max| now.saturating_duration_since(created) >= max)
+	}
+}
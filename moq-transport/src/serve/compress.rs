@@ -0,0 +1,111 @@
+//! Optional compression for tracks with compressible payloads, ex. JSON catalogs, listings, chat, or telemetry.
+//!
+//! Compression is negotiated with the `compression` parameter in the [TrackName], ex. `chat.json?compression=zstd`.
+//! A subscriber that supports it requests the parameterized name, and a publisher that supports it produces the track.
+//! Each name is a distinct track, so relays cache and forward the compressed payloads without knowing about it.
+//!
+//! The payloads are compressed by the `write` helpers and decompressed by the `read_next` helpers in group and stream mode.
+//! The lower level object readers and writers use the payloads as-is.
+use bytes::Bytes;
+
+use super::{ServeError, TrackName};
+
+/// The compression used for each payload in a track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+	#[default]
+	None,
+
+	/// Requires the `zstd` feature.
+	Zstd,
+}
+
+impl Compression {
+	/// The track name parameter used to negotiate compression.
+	pub const PARAM: &'static str = "compression";
+
+	/// Parse the compression from a track name, erroring if it's unknown.
+	pub fn from_name(name: &str) -> Result<Self, ServeError> {
+		// Avoid parsing the name for the common case.
+		if !name.contains('?') {
+			return Ok(Self::None);
+		}
+
+		match TrackName::parse(name).get(Self::PARAM) {
+			None | Some("") | Some("none") => Ok(Self::None),
+			Some("zstd") => Ok(Self::Zstd),
+			Some(other) => Err(ServeError::Unsupported(format!("compression={}", other))),
+		}
+	}
+
+	pub fn compress(&self, payload: Bytes) -> Result<Bytes, ServeError> {
+		match self {
+			Self::None => Ok(payload),
+			Self::Zstd => zstd::compress(&payload),
+		}
+	}
+
+	pub fn decompress(&self, payload: Bytes) -> Result<Bytes, ServeError> {
+		match self {
+			Self::None => Ok(payload),
+			Self::Zstd => zstd::decompress(&payload),
+		}
+	}
+}
+
+#[cfg(feature = "zstd")]
+mod zstd {
+	use super::*;
+
+	// Refuse to decompress payloads larger than this, so a small object can't exhaust memory.
+	const MAX_SIZE: usize = 16 * 1024 * 1024;
+
+	pub fn compress(payload: &[u8]) -> Result<Bytes, ServeError> {
+		::zstd::bulk::compress(payload, 0)
+			.map(Bytes::from)
+			.map_err(|err| ServeError::Internal(format!("zstd: {}", err)))
+	}
+
+	pub fn decompress(payload: &[u8]) -> Result<Bytes, ServeError> {
+		::zstd::bulk::decompress(payload, MAX_SIZE)
+			.map(Bytes::from)
+			.map_err(|_| ServeError::Size)
+	}
+}
+
+#[cfg(not(feature = "zstd"))]
+mod zstd {
+	use super::*;
+
+	pub fn compress(_: &[u8]) -> Result<Bytes, ServeError> {
+		Err(ServeError::Unsupported("compression=zstd".to_string()))
+	}
+
+	pub fn decompress(_: &[u8]) -> Result<Bytes, ServeError> {
+		Err(ServeError::Unsupported("compression=zstd".to_string()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn from_name() {
+		assert_eq!(Compression::from_name("catalog.json"), Ok(Compression::None));
+		assert_eq!(
+			Compression::from_name("chat.json?compression=zstd"),
+			Ok(Compression::Zstd)
+		);
+		assert!(Compression::from_name("chat.json?compression=lzma").is_err());
+	}
+
+	#[cfg(feature = "zstd")]
+	#[test]
+	fn round_trip() {
+		let payload = Bytes::from(r#"{"hello":"world","hello2":"world","hello3":"world"}"#);
+		let compressed = Compression::Zstd.compress(payload.clone()).unwrap();
+		assert_ne!(compressed, payload);
+		assert_eq!(Compression::Zstd.decompress(compressed).unwrap(), payload);
+	}
+}
@@ -1,4 +1,4 @@
-use std::{fmt, sync::Arc};
+use std::{fmt, future::Future, sync::Arc};
 
 use crate::watch::State;
 
@@ -26,6 +26,9 @@ struct DatagramsState {
 	// Increased each time datagram changes.
 	epoch: u64,
 
+	// The publisher's epoch.
+	track_epoch: Option<u64>,
+
 	// Set when the writer or all readers are dropped.
 	closed: Result<(), ServeError>,
 }
@@ -35,6 +38,7 @@ impl Default for DatagramsState {
 		Self {
 			latest: None,
 			epoch: 0,
+			track_epoch: None,
 			closed: Ok(()),
 		}
 	}
@@ -59,6 +63,14 @@ impl DatagramsWriter {
 		Ok(())
 	}
 
+	/// Set the track epoch, ex. when SUBSCRIBE_OK arrives after the first datagram.
+	///
+	/// Datagrams aren't ordered, so this is only reported to readers.
+	pub fn set_epoch(&mut self, epoch: u64) -> Result<(), ServeError> {
+		self.state.lock_mut().ok_or(ServeError::Cancel)?.track_epoch = Some(epoch);
+		Ok(())
+	}
+
 	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
 		let state = self.state.lock();
 		state.closed.clone()?;
@@ -68,6 +80,11 @@ impl DatagramsWriter {
 
 		Ok(())
 	}
+
+	/// Resolves when all readers have been dropped, without keeping the writer alive.
+	pub fn unused(&self) -> impl Future<Output = ()> + Send + 'static {
+		self.state.dropped()
+	}
 }
 
 #[derive(Clone)]
@@ -110,6 +127,11 @@ impl DatagramsReader {
 			.as_ref()
 			.map(|datagram| (datagram.group_id, datagram.object_id))
 	}
+
+	/// The track epoch, if known, see [DatagramsWriter::set_epoch].
+	pub fn epoch(&self) -> Option<u64> {
+		self.state.lock().track_epoch
+	}
 }
 
 /// Static information about the datagram.
@@ -22,6 +22,26 @@ pub enum ServeError {
 	#[error("wrong size")]
 	Size,
 
+	/// A group ID went backwards and the track's [TrackRestart](super::TrackRestart) policy rejects it.
+	#[error("group restarted")]
+	Restart,
+
+	/// The broadcast retains more bytes than its [Usage](super::Usage) limit allows.
+	#[error("full")]
+	Full,
+
+	/// The data was evicted from the cache before it was read, see [TrackCache](super::TrackCache).
+	#[error("expired")]
+	Expired,
+
+	/// The track requires a feature that isn't supported, ex. an unknown compression.
+	#[error("unsupported: {0}")]
+	Unsupported(String),
+
+	/// Refused by the application with a code and reason, which are sent to the peer as-is.
+	#[error("{1}")]
+	Rejected(u64, String),
+
 	#[error("internal error: {0}")]
 	Internal(String),
 }
@@ -36,6 +56,11 @@ impl ServeError {
 			Self::Duplicate => 409,
 			Self::Mode => 400,
 			Self::Size => 413,
+			Self::Restart => 409,
+			Self::Full => 507,
+			Self::Expired => 410,
+			Self::Unsupported(_) => 415,
+			Self::Rejected(code, _) => *code,
 			Self::Internal(_) => 500,
 		}
 	}
@@ -8,11 +8,29 @@
 //!
 //! The stream is closed with [ServeError::Closed] when all writers or readers are dropped.
 use bytes::Bytes;
-use std::{cmp, ops::Deref, sync::Arc};
-
-use crate::watch::State;
-
-use super::{ServeError, Track};
+use std::{
+	cmp,
+	collections::{btree_map, BTreeMap, VecDeque},
+	future::Future,
+	ops::Deref,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Weak,
+	},
+	time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::watch::{State, StateObserver};
+
+use super::{
+	ObjectExtensions, Reservation, ServeError, Subgroup, SubgroupInfo, SubgroupReader, SubgroupWriter, Track,
+	TrackCache, TrackRestart,
+};
+
+#[cfg(feature = "disk")]
+use super::Spill;
 
 pub struct Groups {
 	pub track: Arc<Track>,
@@ -37,11 +55,101 @@ impl Deref for Groups {
 	}
 }
 
+/// What a [GroupsReader] does when it falls behind the writer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupSkip {
+	/// Jump to the latest group, skipping any in between.
+	#[default]
+	Latest,
+
+	/// Return every group in order, unless more than this many groups behind the latest.
+	/// Older groups are skipped to stay within the bound.
+	Lag(u64),
+
+	/// Return every group in order, retaining them until read.
+	/// Use a [Usage](super::Usage) limit to bound the memory held for a slow reader.
+	Never,
+}
+
+// The position of a reader that doesn't always skip, so the writer knows which groups to retain.
+struct GroupsCursor {
+	epoch: AtomicU64,
+	lag: Option<u64>,
+}
+
 // State shared between the writer and reader.
 struct GroupsState {
 	latest: Option<GroupReader>,
-	epoch: u64, // Updated each time latest changes
+	epoch: u64,    // Updated each time latest changes
+	restarts: u64, // Updated each time the group ID goes backwards
 	closed: Result<(), ServeError>,
+
+	// The publisher's epoch, and whether it changed since the latest group.
+	track_epoch: Option<u64>,
+	reset: bool,
+
+	// Groups replaced by latest, along with their epoch, retained for readers that don't skip.
+	history: VecDeque<(u64, GroupReader)>,
+	cursors: Vec<Weak<GroupsCursor>>,
+}
+
+impl GroupsState {
+	// Drop any groups that every cursor has already read or skipped, unless the cache retains them.
+	fn prune(&mut self, retain: bool) {
+		let latest = self.epoch;
+		let mut needed = latest;
+
+		self.cursors.retain(|cursor| match cursor.upgrade() {
+			Some(cursor) => {
+				let next = cursor.epoch.load(Ordering::Relaxed) + 1;
+				let next = match cursor.lag {
+					Some(lag) => cmp::max(next, latest.saturating_sub(lag)),
+					None => next,
+				};
+				needed = cmp::min(needed, next);
+				true
+			}
+			None => false,
+		});
+
+		if retain {
+			return;
+		}
+
+		while self.history.front().is_some_and(|(epoch, _)| *epoch < needed) {
+			self.history.pop_front();
+		}
+	}
+
+	// Evict the oldest groups over the cache limits, waking any readers with [ServeError::Expired].
+	fn evict(&mut self, cache: &TrackCache) {
+		if *cache == TrackCache::default() {
+			return;
+		}
+
+		let now = Instant::now();
+		let mut bytes = self.latest.as_ref().map(GroupReader::reserved).unwrap_or_default();
+		let mut retained = VecDeque::new();
+
+		while let Some((epoch, group)) = self.history.pop_back() {
+			if group.is_expired(cache, now) {
+				group.expire();
+				continue;
+			}
+
+			// Once a group is over the limits, so is everything older.
+			bytes += group.reserved();
+			let full = cache.max_groups.is_some_and(|max| retained.len() as u64 >= max)
+				|| cache.max_bytes.is_some_and(|max| bytes > max);
+
+			match full {
+				true => group.expire(),
+				false => retained.push_front((epoch, group)),
+			}
+		}
+
+		self.history = retained;
+	}
 }
 
 impl Default for GroupsState {
@@ -49,7 +157,12 @@ impl Default for GroupsState {
 		Self {
 			latest: None,
 			epoch: 0,
+			restarts: 0,
 			closed: Ok(()),
+			track_epoch: None,
+			reset: false,
+			history: VecDeque::new(),
+			cursors: Vec::new(),
 		}
 	}
 }
@@ -71,38 +184,117 @@ impl GroupsWriter {
 
 	// Helper to increment the group by one.
 	pub fn append(&mut self, priority: u64) -> Result<GroupWriter, ServeError> {
+		self.append_timed(priority, GroupTimestamp::default())
+	}
+
+	// Helper to increment the group by one, attaching timestamps to the header.
+	pub fn append_timed(&mut self, priority: u64, timestamp: GroupTimestamp) -> Result<GroupWriter, ServeError> {
+		self.append_annotated(priority, timestamp, GroupAnnotations::default())
+	}
+
+	// Helper to increment the group by one, attaching timestamps and annotations to the header.
+	pub fn append_annotated(
+		&mut self,
+		priority: u64,
+		timestamp: GroupTimestamp,
+		annotations: GroupAnnotations,
+	) -> Result<GroupWriter, ServeError> {
+		self.create(Group {
+			group_id: self.next,
+			priority,
+			timestamp,
+			annotations,
+			subgroups: 0,
+			transit: None,
+		})
+	}
+
+	// Helper to increment the group by one, splitting its objects across the given number of subgroups.
+	pub fn append_split(&mut self, priority: u64, subgroups: u64) -> Result<GroupWriter, ServeError> {
 		self.create(Group {
 			group_id: self.next,
 			priority,
+			timestamp: Default::default(),
+			annotations: Default::default(),
+			subgroups,
+			transit: None,
 		})
 	}
 
+	/// Create a group with the given ID, which may be out of order.
+	///
+	/// Returns [ServeError::Full] if the track's [Usage](super::Usage) is over its limit.
 	pub fn create(&mut self, group: Group) -> Result<GroupWriter, ServeError> {
 		let group = GroupInfo {
 			track: self.info.clone(),
 			group_id: group.group_id,
 			priority: group.priority,
+			timestamp: group.timestamp,
+			annotations: group.annotations,
+			subgroups: group.subgroups,
+			transit: group.transit,
 		};
 		let (writer, reader) = group.produce();
 
 		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
 
-		if let Some(latest) = &state.latest {
+		// The latest group is released when replaced, so it doesn't count towards the limit.
+		// That's not true when it's retained for a reader that doesn't skip.
+		let retain = self.info.cache.retains();
+		state.prune(retain);
+		let replaced = match state.cursors.is_empty() && !retain {
+			true => state.latest.as_ref().map(GroupReader::reserved).unwrap_or_default(),
+			false => 0,
+		};
+		self.info.usage.check(replaced)?;
+
+		// The first group after a new epoch is unrelated to the previous one, so accept it regardless of ID.
+		if let Some(latest) = state.latest.as_ref().filter(|_| !state.reset) {
 			match writer.group_id.cmp(&latest.group_id) {
-				cmp::Ordering::Less => return Ok(writer), // dropped immediately, lul
+				cmp::Ordering::Less => match self.info.restart {
+					TrackRestart::Drop => return Ok(writer), // dropped immediately, lul
+					TrackRestart::Reject => return Err(ServeError::Restart),
+					TrackRestart::Accept => state.restarts += 1,
+				},
 				cmp::Ordering::Equal => return Err(ServeError::Duplicate),
-				cmp::Ordering::Greater => state.latest = Some(reader),
+				cmp::Ordering::Greater => {}
+			}
+		}
+
+		let epoch = state.epoch;
+		if let Some(replaced) = state.latest.replace(reader) {
+			if !state.cursors.is_empty() || retain {
+				#[cfg(feature = "disk")]
+				if let Some(spill) = &self.info.spill {
+					replaced.spill(spill);
+				}
+
+				state.history.push_back((epoch, replaced));
 			}
-		} else {
-			state.latest = Some(reader);
 		}
 
 		self.next = state.latest.as_ref().unwrap().group_id + 1;
 		state.epoch += 1;
+		state.reset = false;
+		state.evict(&self.info.cache);
 
 		Ok(writer)
 	}
 
+	/// Set the track epoch, ex. when SUBSCRIBE_OK arrives after the first group.
+	///
+	/// A different epoch than before means the publisher restarted, so the next group is accepted regardless of its ID
+	/// and readers see the discontinuity via [GroupsReader::restarts].
+	pub fn set_epoch(&mut self, epoch: u64) -> Result<(), ServeError> {
+		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		if state.track_epoch.replace(epoch).is_some_and(|prev| prev != epoch) {
+			state.restarts += 1;
+			state.reset = true;
+		}
+
+		Ok(())
+	}
+
 	/// Close the segment with an error.
 	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
 		let state = self.state.lock();
@@ -113,6 +305,11 @@ impl GroupsWriter {
 
 		Ok(())
 	}
+
+	/// Resolves when all readers have been dropped, without keeping the writer alive.
+	pub fn unused(&self) -> impl Future<Output = ()> + Send + 'static {
+		self.state.dropped()
+	}
 }
 
 impl Deref for GroupsWriter {
@@ -123,11 +320,12 @@ impl Deref for GroupsWriter {
 	}
 }
 
-#[derive(Clone)]
 pub struct GroupsReader {
 	pub info: Arc<Track>,
 	state: State<GroupsState>,
 	epoch: u64,
+	skip: GroupSkip,
+	cursor: Option<Arc<GroupsCursor>>,
 }
 
 impl GroupsReader {
@@ -136,17 +334,55 @@ impl GroupsReader {
 			info: track,
 			state,
 			epoch: 0,
+			skip: GroupSkip::Latest,
+			cursor: None,
 		}
 	}
 
+	/// Choose what happens when this reader falls behind, defaulting to [GroupSkip::Latest].
+	///
+	/// Groups are only retained from this point onwards, starting with the latest group.
+	pub fn with_skip(mut self, skip: GroupSkip) -> Self {
+		self.skip = skip;
+		self.cursor = None;
+
+		let lag = match skip {
+			GroupSkip::Latest => return self,
+			GroupSkip::Lag(lag) => Some(lag),
+			GroupSkip::Never => None,
+		};
+
+		if let Some(mut state) = self.state.lock_mut() {
+			// Start with the latest group, like a reader that skips.
+			if self.epoch == 0 {
+				self.epoch = state.epoch.saturating_sub(1);
+			}
+
+			let cursor = Arc::new(GroupsCursor {
+				epoch: AtomicU64::new(self.epoch),
+				lag,
+			});
+			state.cursors.push(Arc::downgrade(&cursor));
+			self.cursor = Some(cursor);
+		}
+
+		self
+	}
+
 	pub async fn next(&mut self) -> Result<Option<GroupReader>, ServeError> {
 		loop {
 			{
 				let state = self.state.lock();
 
 				if self.epoch != state.epoch {
-					self.epoch = state.epoch;
-					return Ok(state.latest.clone());
+					let (epoch, group) = self.next_group(&state);
+					self.epoch = epoch;
+
+					if let Some(cursor) = &self.cursor {
+						cursor.epoch.store(epoch, Ordering::Relaxed);
+					}
+
+					return Ok(group);
 				}
 
 				state.closed.clone()?;
@@ -159,11 +395,79 @@ impl GroupsReader {
 		}
 	}
 
+	// Returns the next group to read, based on the skip policy.
+	fn next_group(&self, state: &GroupsState) -> (u64, Option<GroupReader>) {
+		let next = match self.skip {
+			GroupSkip::Latest => state.epoch,
+			GroupSkip::Lag(lag) => cmp::max(self.epoch + 1, state.epoch.saturating_sub(lag)),
+			GroupSkip::Never => self.epoch + 1,
+		};
+
+		// Fall back to the latest group if the next one wasn't retained, or has expired since it was.
+		let now = Instant::now();
+		let retained = state
+			.history
+			.iter()
+			.find(|(epoch, group)| *epoch >= next && !group.is_expired(&self.info.cache, now));
+
+		match retained {
+			Some((epoch, group)) => (*epoch, Some(group.clone())),
+			None => (state.epoch, state.latest.clone()),
+		}
+	}
+
 	// Returns the largest group/sequence
 	pub fn latest(&self) -> Option<(u64, u64)> {
 		let state = self.state.lock();
 		state.latest.as_ref().map(|group| (group.group_id, group.latest()))
 	}
+
+	/// The cached groups within the range, oldest first, ex. to backfill a subscriber that just joined.
+	///
+	/// This is the latest group, plus any older groups retained for readers that don't skip and not yet expired.
+	pub fn cached(&self, start: u64, end: Option<u64>) -> Vec<GroupReader> {
+		let state = self.state.lock();
+		let now = Instant::now();
+
+		state
+			.history
+			.iter()
+			.map(|(_, group)| group)
+			.filter(|group| !group.is_expired(&self.info.cache, now))
+			.chain(state.latest.as_ref())
+			.filter(|group| group.group_id >= start && end.is_none_or(|end| group.group_id <= end))
+			.cloned()
+			.collect()
+	}
+
+	/// The number of times the publisher restarted group numbering, signalling a discontinuity.
+	pub fn restarts(&self) -> u64 {
+		self.state.lock().restarts
+	}
+
+	/// The track epoch, if known, see [GroupsWriter::set_epoch].
+	pub fn epoch(&self) -> Option<u64> {
+		self.state.lock().track_epoch
+	}
+}
+
+impl Clone for GroupsReader {
+	// Each clone gets its own position, starting from the same place.
+	fn clone(&self) -> Self {
+		let mut reader = Self {
+			info: self.info.clone(),
+			state: self.state.clone(),
+			epoch: self.epoch,
+			skip: GroupSkip::Latest,
+			cursor: None,
+		};
+
+		if self.cursor.is_some() {
+			reader = reader.with_skip(self.skip);
+		}
+
+		reader
+	}
 }
 
 impl Deref for GroupsReader {
@@ -183,6 +487,108 @@ pub struct Group {
 
 	// The priority of the group within the track.
 	pub priority: u64,
+
+	// Optional timestamps for the group, only sent if the subscriber supports them.
+	pub timestamp: GroupTimestamp,
+
+	// Optional annotations for the group, only sent if the subscriber supports timestamps.
+	pub annotations: GroupAnnotations,
+
+	// The number of subgroups the objects are split across, or 0 to send them on the group's stream.
+	// NOTE: Timestamps and annotations aren't sent for groups split into subgroups.
+	pub subgroups: u64,
+
+	// When the group arrived from the previous hop, set by the session that received it.
+	pub transit: Option<GroupTransit>,
+}
+
+/// Timestamps describing when a group starts, so applications don't need to parse the media.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupTimestamp {
+	/// The media (presentation) timestamp of the first object, in microseconds.
+	pub media: Option<u64>,
+
+	/// The wall clock time when the first object was captured, in microseconds since the UNIX epoch.
+	pub wall: Option<u64>,
+}
+
+impl GroupTimestamp {
+	pub fn is_empty(&self) -> bool {
+		self.media.is_none() && self.wall.is_none()
+	}
+}
+
+/// When a group's stream was sent by the previous hop and when it arrived, in microseconds since the UNIX epoch.
+///
+/// Each hop stamps the streams it sends with its own clock, so this only covers the last hop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupTransit {
+	/// The sender's wall clock when it opened the stream.
+	pub sent: u64,
+
+	/// Our wall clock when the stream arrived, converted to the sender's clock when `synced`.
+	pub received: u64,
+
+	/// True if the offset between the clocks was measured, see [ClockSync](crate::session::ClockSync).
+	/// Otherwise the clocks are assumed to be in sync, ex. via NTP.
+	pub synced: bool,
+}
+
+impl GroupTransit {
+	/// The one-way delay of the last hop, or zero if the clocks disagree.
+	pub fn delay(&self) -> Duration {
+		Duration::from_micros(self.received.saturating_sub(self.sent))
+	}
+}
+
+/// Small key/value pairs attached to a group, ex. to mark an ad break.
+///
+/// These are sent in the header of each group stream and forwarded by relays, so keep them small.
+/// The values are opaque; keys should be namespaced by the application, ex. `scte35.splice`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupAnnotations(BTreeMap<String, Bytes>);
+
+impl GroupAnnotations {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Set the value for a key, returning the previous value.
+	pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Bytes>) -> Option<Bytes> {
+		self.0.insert(key.into(), value.into())
+	}
+
+	pub fn get(&self, key: &str) -> Option<&Bytes> {
+		self.0.get(key)
+	}
+
+	pub fn remove(&mut self, key: &str) -> Option<Bytes> {
+		self.0.remove(key)
+	}
+
+	pub fn iter(&self) -> btree_map::Iter<'_, String, Bytes> {
+		self.0.iter()
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+}
+
+impl From<BTreeMap<String, Bytes>> for GroupAnnotations {
+	fn from(annotations: BTreeMap<String, Bytes>) -> Self {
+		Self(annotations)
+	}
+}
+
+impl From<GroupAnnotations> for BTreeMap<String, Bytes> {
+	fn from(annotations: GroupAnnotations) -> Self {
+		annotations.0
+	}
 }
 
 /// Static information about the group
@@ -196,6 +602,18 @@ pub struct GroupInfo {
 
 	// The priority of the group within the track.
 	pub priority: u64,
+
+	// Timestamps for the group, if provided by the publisher.
+	pub timestamp: GroupTimestamp,
+
+	// Annotations for the group, if provided by the publisher.
+	pub annotations: GroupAnnotations,
+
+	// The number of subgroups the objects are split across, or 0 if they're sent on the group's stream.
+	pub subgroups: u64,
+
+	// When the group arrived from the previous hop, if received by a session.
+	pub transit: Option<GroupTransit>,
 }
 
 impl GroupInfo {
@@ -218,19 +636,59 @@ impl Deref for GroupInfo {
 	}
 }
 
-struct GroupState {
+pub(super) struct GroupState {
 	// The data that has been received thus far.
 	objects: Vec<GroupObjectReader>,
 
+	// The subgroups created thus far, each with their own objects.
+	subgroups: Vec<SubgroupReader>,
+
 	// Set when the writer or all readers are dropped.
 	closed: Result<(), ServeError>,
+
+	// The bytes reserved for the objects, released when the group is dropped.
+	reserved: Option<Reservation>,
+
+	// The number of objects written to a transport stream (or dropped) by the furthest reader.
+	written: usize,
+
+	// When the group was created, for the track's cache max_age.
+	created: Instant,
+
+	// How long new objects may be cached, and the earliest time an object expires.
+	expires: Option<Duration>,
+	deadline: Option<Instant>,
+}
+
+impl GroupState {
+	// Returns an error if the group was evicted, so the writer stops writing to it.
+	pub(super) fn check_expired(&self) -> Result<(), ServeError> {
+		match self.closed {
+			Err(ServeError::Expired) => Err(ServeError::Expired),
+			_ => Ok(()),
+		}
+	}
+
+	// Record an object that expires, returning how long it may be cached.
+	pub(super) fn object_expires(&mut self) -> Option<Duration> {
+		let expires = self.expires?;
+		let deadline = Instant::now() + expires;
+		self.deadline = Some(self.deadline.map_or(deadline, |existing| existing.min(deadline)));
+		Some(expires)
+	}
 }
 
 impl Default for GroupState {
 	fn default() -> Self {
 		Self {
 			objects: Vec::new(),
+			subgroups: Vec::new(),
 			closed: Ok(()),
+			reserved: None,
+			written: 0,
+			created: Instant::now(),
+			expires: None,
+			deadline: None,
 		}
 	}
 }
@@ -256,32 +714,131 @@ impl GroupWriter {
 		}
 	}
 
-	/// Create the next object ID with the given payload.
+	/// Create the next object ID with the given payload, compressed if the track requires it.
 	pub fn write(&mut self, payload: bytes::Bytes) -> Result<(), ServeError> {
-		let mut object = self.create(payload.len())?;
+		self.write_extended(payload, ObjectExtensions::default())
+	}
+
+	/// Like [Self::write], but with extension headers that are forwarded with the object.
+	pub fn write_extended(&mut self, payload: bytes::Bytes, extensions: ObjectExtensions) -> Result<(), ServeError> {
+		self.write_with(payload, false, extensions)
+	}
+
+	/// Like [Self::write], but marks the object as decodable without the earlier objects in the group, ex. a keyframe.
+	pub fn write_independent(&mut self, payload: bytes::Bytes) -> Result<(), ServeError> {
+		self.write_with(payload, true, ObjectExtensions::default())
+	}
+
+	/// Like [Self::write], with every per-object field, ex. to copy an object from another group.
+	pub fn write_with(
+		&mut self,
+		payload: bytes::Bytes,
+		independent: bool,
+		extensions: ObjectExtensions,
+	) -> Result<(), ServeError> {
+		let payload = self.info.track.compression()?.compress(payload)?;
+		let mut object = self.create_with(payload.len(), independent, extensions)?;
 		object.write(payload)?;
 		Ok(())
 	}
 
+	/// Start numbering objects from this ID, ex. when the earlier objects in the group weren't requested.
+	pub fn skip_to(&mut self, object_id: u64) {
+		self.next = self.next.max(object_id);
+	}
+
 	/// Write an object over multiple writes.
 	///
+	/// Returns [ServeError::Mode] if the group is split into subgroups, which must be written instead.
+	///
 	/// BAD STUFF will happen if the size is wrong; this is an advanced feature.
 	pub fn create(&mut self, size: usize) -> Result<GroupObjectWriter, ServeError> {
+		self.create_extended(size, ObjectExtensions::default())
+	}
+
+	/// Like [Self::create], but with extension headers that are forwarded with the object.
+	pub fn create_extended(
+		&mut self,
+		size: usize,
+		extensions: ObjectExtensions,
+	) -> Result<GroupObjectWriter, ServeError> {
+		self.create_with(size, false, extensions)
+	}
+
+	/// Like [Self::create], with every per-object field, see [GroupObject].
+	pub fn create_with(
+		&mut self,
+		size: usize,
+		independent: bool,
+		extensions: ObjectExtensions,
+	) -> Result<GroupObjectWriter, ServeError> {
+		if self.info.subgroups > 0 {
+			return Err(ServeError::Mode);
+		}
+
+		let state = self.state.lock();
+		state.check_expired()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Cancel)?;
 		let (writer, reader) = GroupObject {
 			group: self.info.clone(),
 			object_id: self.next,
 			size,
+			independent,
+			extensions,
+			expires: state.object_expires(),
 		}
 		.produce();
 
 		self.next += 1;
 
-		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
 		state.objects.push(reader);
+		state
+			.reserved
+			.get_or_insert_with(|| Reservation::new(self.info.track.usage.clone()))
+			.add(size as u64);
+
+		Ok(writer)
+	}
+
+	/// Create a subgroup, which is sent on its own stream with its own priority.
+	///
+	/// Returns [ServeError::NotFound] unless the ID is less than the group's number of subgroups.
+	/// The group isn't finished until every subgroup writer has also been dropped.
+	pub fn subgroup(&mut self, subgroup: Subgroup) -> Result<SubgroupWriter, ServeError> {
+		if subgroup.subgroup_id >= self.info.subgroups {
+			return Err(ServeError::NotFound);
+		}
+
+		let (writer, reader) = SubgroupInfo {
+			group: self.info.clone(),
+			subgroup_id: subgroup.subgroup_id,
+			priority: subgroup.priority,
+		}
+		.produce(self.state.clone());
+
+		let state = self.state.lock();
+		state.check_expired()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Cancel)?;
+		if state.subgroups.iter().any(|s| s.subgroup_id == subgroup.subgroup_id) {
+			return Err(ServeError::Duplicate);
+		}
+
+		state.subgroups.push(reader);
 
 		Ok(writer)
 	}
 
+	/// Evict the group from the cache once this long has passed since any later object was created.
+	///
+	/// This applies to objects created afterwards, including in subgroups, and only shortens the track's max_age.
+	/// It's not sent to subscribers, so it only applies to this cache.
+	pub fn set_expires(&mut self, expires: Option<Duration>) -> Result<(), ServeError> {
+		self.state.lock_mut().ok_or(ServeError::Cancel)?.expires = expires;
+		Ok(())
+	}
+
 	/// Close the stream with an error.
 	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
 		let state = self.state.lock();
@@ -292,6 +849,37 @@ impl GroupWriter {
 		Ok(())
 	}
 
+	/// Block until every object created so far has been written to a transport stream, or dropped.
+	///
+	/// A session marks each object once it's handed to QUIC, skipped, or abandoned, so this resolves when any reader gets that far.
+	/// It also resolves once every reader is dropped, although a cached group may not be dropped until it's replaced.
+	/// Use a timeout when pacing, since nobody may be subscribed.
+	pub async fn written(&self) {
+		let target = self.state.lock().objects.len();
+
+		loop {
+			{
+				let state = self.state.lock();
+				if state.written >= target {
+					return;
+				}
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return,
+				}
+			}
+			.await;
+		}
+	}
+
+	/// Returns a handle reporting the objects not yet written to a transport stream, even after this writer is dropped.
+	pub fn backlog(&self) -> GroupBacklog {
+		GroupBacklog {
+			state: self.state.observe(),
+		}
+	}
+
 	pub fn len(&self) -> usize {
 		self.state.lock().objects.len()
 	}
@@ -309,6 +897,25 @@ impl Deref for GroupWriter {
 	}
 }
 
+/// Reports the objects of a group that haven't been written to a transport stream, without keeping the group alive.
+#[derive(Clone)]
+pub struct GroupBacklog {
+	state: StateObserver<GroupState>,
+}
+
+impl GroupBacklog {
+	/// The number of objects and bytes not yet written by the furthest reader, see [GroupWriter::written].
+	/// Objects in subgroups aren't counted.
+	///
+	/// Returns None once the writer, every reader, and any cache released the group, since it can't be sent anymore.
+	pub fn unwritten(&self) -> Option<(usize, usize)> {
+		self.state.read(|state| {
+			let objects = state.objects.get(state.written..).unwrap_or_default();
+			(objects.len(), objects.iter().map(|object| object.info.size).sum())
+		})
+	}
+}
+
 /// Notified when a stream has new data available.
 #[derive(Clone)]
 pub struct GroupReader {
@@ -321,6 +928,9 @@ pub struct GroupReader {
 	// The number of chunks that we've read.
 	// NOTE: Cloned readers inherit this index, but then run in parallel.
 	index: usize,
+
+	// The number of subgroups that we've read.
+	subgroup: usize,
 }
 
 impl GroupReader {
@@ -329,6 +939,7 @@ impl GroupReader {
 			state,
 			info: group,
 			index: 0,
+			subgroup: 0,
 		}
 	}
 
@@ -337,10 +948,71 @@ impl GroupReader {
 		state.objects.last().map(|o| o.object_id).unwrap_or_default()
 	}
 
+	// The number of bytes reserved for the group's objects, including any subgroups.
+	fn reserved(&self) -> u64 {
+		let state = self.state.lock();
+		let subgroups: u64 = state.subgroups.iter().map(SubgroupReader::reserved).sum();
+
+		state.reserved.as_ref().map(Reservation::bytes).unwrap_or_default() + subgroups
+	}
+
+	// Returns true if the group is older than the cache allows, or an object within it has expired.
+	fn is_expired(&self, cache: &TrackCache, now: Instant) -> bool {
+		let state = self.state.lock();
+		cache.is_expired(state.created, now) || state.deadline.is_some_and(|deadline| deadline <= now)
+	}
+
+	// Move the complete objects to disk in the background, including any subgroups, releasing their memory.
+	#[cfg(feature = "disk")]
+	fn spill(&self, spill: &Arc<Spill>) {
+		let group = self.clone();
+		let spill = spill.clone();
+		Spill::run(move || group.spill_now(&spill));
+	}
+
+	// Write the objects without holding any locks, only locking to swap in the mapped chunks.
+	#[cfg(feature = "disk")]
+	fn spill_now(&self, spill: &Arc<Spill>) {
+		let (objects, subgroups) = {
+			let state = self.state.lock();
+			(state.objects.clone(), state.subgroups.clone())
+		};
+
+		let spilled = spill_objects(&objects, spill);
+		if let Some(reserved) = &mut self.state.lock_mut_force().reserved {
+			reserved.release(spilled);
+		}
+
+		for subgroup in subgroups {
+			subgroup.spill_now(spill);
+		}
+	}
+
+	// Drop the cached objects and close the group, so any readers still reading it get [ServeError::Expired].
+	// Readers that already have an object can finish reading it.
+	fn expire(&self) {
+		let mut state = self.state.lock_mut_force();
+		if state.check_expired().is_err() {
+			return;
+		}
+
+		for subgroup in state.subgroups.drain(..) {
+			subgroup.expire();
+		}
+
+		state.objects.clear();
+		state.reserved = None;
+		state.closed = Err(ServeError::Expired);
+	}
+
+	/// Read the next object in full, decompressed if the track requires it.
 	pub async fn read_next(&mut self) -> Result<Option<Bytes>, ServeError> {
 		let object = self.next().await?;
 		match object {
-			Some(mut object) => Ok(Some(object.read_all().await?)),
+			Some(mut object) => {
+				let payload = object.read_all().await?;
+				Ok(Some(self.info.track.compression()?.decompress(payload)?))
+			}
 			None => Ok(None),
 		}
 	}
@@ -366,6 +1038,43 @@ impl GroupReader {
 		}
 	}
 
+	/// Block until the next subgroup is created, returning None once the group is finished.
+	///
+	/// Objects within a subgroup aren't returned by [Self::next]; they're read from the [SubgroupReader].
+	pub async fn next_subgroup(&mut self) -> Result<Option<SubgroupReader>, ServeError> {
+		loop {
+			{
+				let state = self.state.lock();
+
+				if self.subgroup < state.subgroups.len() {
+					let subgroup = state.subgroups[self.subgroup].clone();
+					self.subgroup += 1;
+					return Ok(Some(subgroup));
+				}
+
+				state.closed.clone()?;
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Ok(None),
+				}
+			}
+			.await; // Try again when the state changes
+		}
+	}
+
+	/// Mark every object returned by [Self::next] as written to a transport stream (or dropped), see [GroupWriter::written].
+	pub fn mark_written(&self) {
+		let state = self.state.lock();
+		if state.written >= self.index {
+			return;
+		}
+		drop(state);
+
+		// Recorded even once the writer is dropped, for any [GroupBacklog].
+		let mut state = self.state.lock_mut_force();
+		state.written = cmp::max(state.written, self.index);
+	}
+
 	pub fn pos(&self) -> usize {
 		self.index
 	}
@@ -396,6 +1105,18 @@ pub struct GroupObject {
 
 	// The size of the object.
 	pub size: usize,
+
+	/// The object can be decoded without the earlier objects in the group, ex. a keyframe.
+	/// Subscribers joining mid-group can start here, and relays can avoid dropping it.
+	/// Only sent if the subscriber supports extensions, otherwise it's false.
+	pub independent: bool,
+
+	// Metadata forwarded with the object, only sent if the subscriber supports extensions.
+	pub extensions: ObjectExtensions,
+
+	/// How long the object may be cached after it was created, see [GroupWriter::set_expires].
+	/// The group is evicted once any of its objects expire.
+	pub expires: Option<Duration>,
 }
 
 impl GroupObject {
@@ -561,6 +1282,40 @@ impl GroupObjectReader {
 	}
 }
 
+// Move the payloads of the complete objects to disk, returning the number of bytes spilled.
+#[cfg(feature = "disk")]
+pub(super) fn spill_objects(objects: &[GroupObjectReader], spill: &Arc<Spill>) -> u64 {
+	let mut complete = Vec::new();
+	let mut chunks = Vec::new();
+
+	for object in objects {
+		let state = object.state.lock();
+		let size: usize = state.chunks.iter().map(Bytes::len).sum();
+		if state.closed.is_ok() && size == object.size {
+			complete.push(object);
+			chunks.push(state.chunks.clone());
+		}
+	}
+
+	// Written without holding any locks; the objects are complete, so their chunks can't change in the meantime.
+	let spilled = match spill.write(&chunks) {
+		Ok(Some(spilled)) => spilled,
+		Ok(None) => return 0,
+		Err(err) => {
+			log::warn!("failed to spill group: {}", err);
+			return 0;
+		}
+	};
+
+	let mut bytes = 0;
+	for (object, chunks) in complete.into_iter().zip(spilled) {
+		bytes += object.size as u64;
+		object.state.lock_mut_force().chunks = chunks;
+	}
+
+	bytes
+}
+
 impl Deref for GroupObjectReader {
 	type Target = GroupObject;
 
@@ -568,3 +1323,167 @@ impl Deref for GroupObjectReader {
 		&self.info
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::serve::TrackReaderMode;
+
+	fn groups() -> (GroupsWriter, GroupsReader) {
+		let track = Track::new("test".to_string(), "video".to_string());
+		Groups { track: Arc::new(track) }.produce()
+	}
+
+	fn group(group_id: u64) -> Group {
+		Group {
+			group_id,
+			priority: 0,
+			timestamp: Default::default(),
+			annotations: Default::default(),
+			subgroups: 0,
+			transit: None,
+		}
+	}
+
+	async fn ids(reader: &mut GroupsReader, count: usize) -> Vec<u64> {
+		let mut ids = Vec::new();
+		for _ in 0..count {
+			ids.push(reader.next().await.unwrap().unwrap().group_id);
+		}
+		ids
+	}
+
+	#[tokio::test]
+	async fn skip() {
+		let (mut writer, reader) = groups();
+		writer.append(0).unwrap();
+
+		let mut latest = reader.clone();
+		let mut lag = reader.clone().with_skip(GroupSkip::Lag(2));
+		let mut never = reader.with_skip(GroupSkip::Never);
+
+		for _ in 0..5 {
+			writer.append(0).unwrap();
+		}
+
+		assert_eq!(ids(&mut latest, 1).await, vec![5]);
+		assert_eq!(ids(&mut lag, 3).await, vec![3, 4, 5]);
+		assert_eq!(ids(&mut never, 6).await, vec![0, 1, 2, 3, 4, 5]);
+
+		// Clones read independently from the same position.
+		writer.append(0).unwrap();
+		writer.append(0).unwrap();
+		let mut cloned = never.clone();
+		assert_eq!(ids(&mut never, 2).await, vec![6, 7]);
+		assert_eq!(ids(&mut cloned, 2).await, vec![6, 7]);
+
+		// Nothing is retained once every reader has caught up or gone away.
+		drop((never, cloned, lag));
+		writer.append(0).unwrap();
+		assert!(writer.state.lock().history.is_empty());
+	}
+
+	#[tokio::test]
+	async fn evict() {
+		let mut track = Track::new("test".to_string(), "video".to_string());
+		track.cache = TrackCache::new().with_max_groups(1);
+		let (mut writer, reader) = Groups { track: Arc::new(track) }.produce();
+
+		writer.append(0).unwrap();
+		let mut never = reader.with_skip(GroupSkip::Never);
+		assert_eq!(ids(&mut never, 1).await, vec![0]);
+
+		writer.append(0).unwrap().write("a".into()).unwrap();
+		writer.append(0).unwrap();
+		let mut held = never.cached(1, Some(1)).remove(0);
+
+		// Only one older group is retained, so group 1 is evicted while it's still being read.
+		writer.append(0).unwrap();
+		assert_eq!(held.next().await.err(), Some(ServeError::Expired));
+		assert_eq!(ids(&mut never, 2).await, vec![2, 3]);
+
+		// An object's expiry evicts the group containing it, although the latest group is always kept.
+		let mut group = writer.append(0).unwrap();
+		group.set_expires(Some(Duration::ZERO)).unwrap();
+		group.write("b".into()).unwrap();
+		writer.append(0).unwrap();
+
+		let cached: Vec<_> = never.cached(0, None).iter().map(|group| group.group_id).collect();
+		assert_eq!(cached, vec![5]);
+		assert_eq!(group.write("c".into()), Err(ServeError::Expired));
+	}
+
+	#[tokio::test]
+	async fn shared() {
+		let (writer, reader) = Track::new("test".to_string(), "video".to_string()).produce();
+		assert!(reader.try_mode().unwrap().is_none());
+
+		let mut writer = writer.groups().unwrap();
+		writer.append(0).unwrap().write("hello".into()).unwrap();
+
+		// Two consumers of the same track, ex. a recorder and a forwarder, each read every object.
+		let mut recorder = match reader.try_mode().unwrap() {
+			Some(TrackReaderMode::Groups(groups)) => groups.with_skip(GroupSkip::Never),
+			_ => panic!("expected groups"),
+		};
+		let mut forwarder = match reader.mode().await.unwrap() {
+			TrackReaderMode::Groups(groups) => groups,
+			_ => panic!("expected groups"),
+		};
+
+		for groups in [&mut recorder, &mut forwarder] {
+			let mut group = groups.next().await.unwrap().unwrap();
+			assert_eq!(group.read_next().await.unwrap().unwrap(), "hello");
+		}
+	}
+
+	#[tokio::test]
+	async fn epoch() {
+		let (mut writer, mut reader) = groups();
+		writer.set_epoch(1).unwrap();
+		writer.create(group(5)).unwrap();
+
+		// A lower ID within the same epoch arrived late, so it's dropped.
+		writer.create(group(3)).unwrap();
+		writer.set_epoch(1).unwrap();
+		assert_eq!(reader.latest(), Some((5, 0)));
+		assert_eq!(reader.restarts(), 0);
+
+		// A new epoch means the publisher restarted, so the lower ID is the new latest.
+		writer.set_epoch(2).unwrap();
+		assert_eq!(reader.restarts(), 1);
+		writer.create(group(0)).unwrap();
+		assert_eq!(reader.latest(), Some((0, 0)));
+		assert_eq!(reader.epoch(), Some(2));
+		assert_eq!(ids(&mut reader, 1).await, vec![0]);
+
+		// Only the first group after the new epoch skips the ordering check.
+		writer.create(group(1)).unwrap();
+		writer.create(group(0)).unwrap();
+		assert_eq!(reader.latest(), Some((1, 0)));
+		assert_eq!(reader.restarts(), 1);
+	}
+
+	#[tokio::test]
+	async fn backlog() {
+		let (mut writer, mut reader) = groups();
+		let mut group = writer.append(0).unwrap();
+		let backlog = group.backlog();
+
+		group.write("hello".into()).unwrap();
+		group.write("world!".into()).unwrap();
+		assert_eq!(backlog.unwritten(), Some((2, 11)));
+
+		// Only objects marked written by a reader leave the backlog, even after the writer is dropped.
+		drop(group);
+		let mut group = reader.next().await.unwrap().unwrap();
+		group.next().await.unwrap().unwrap();
+		group.mark_written();
+		assert_eq!(backlog.unwritten(), Some((1, 6)));
+
+		// Nothing is left to send once the group is released.
+		drop(group);
+		writer.append(0).unwrap();
+		assert_eq!(backlog.unwritten(), None);
+	}
+}
@@ -1,15 +1,31 @@
+mod broadcast;
+mod cache;
+mod compress;
 mod datagram;
 mod error;
 mod group;
+mod name;
 mod object;
+#[cfg(feature = "disk")]
+mod spill;
 mod stream;
+mod subgroup;
 mod track;
 mod tracks;
+mod usage;
 
+pub use broadcast::*;
+pub use cache::*;
+pub use compress::*;
 pub use datagram::*;
 pub use error::*;
 pub use group::*;
+pub use name::*;
 pub use object::*;
+#[cfg(feature = "disk")]
+pub use spill::*;
 pub use stream::*;
+pub use subgroup::*;
 pub use track::*;
 pub use tracks::*;
+pub use usage::*;
@@ -0,0 +1,182 @@
+//! A convention for parameterized track names, ex. `video.m4s?rendition=720&fit=cover`.
+//!
+//! A track name may contain a path followed by `?` and a query, split into `key=value` pairs by `&`.
+//! Keys and values escape `%`, `&`, `=`, and `?` as `%XX`, so any string can be used.
+//! A key without a value (ex. `?preview`) has an empty value, and the first occurrence of a duplicate key wins.
+//!
+//! Each unique name is still a distinct track, so the relay caches `thumb.jpg?interval=5` and `thumb.jpg?interval=10`
+//! separately. The publisher uses [TrackName::parse] on each requested track to decide what to produce.
+use std::{fmt, str::FromStr};
+
+/// The track describing every other track in the broadcast.
+pub const CATALOG_TRACK: &str = ".catalog";
+
+/// The query parameter marking a track as [TrackName::is_static], ex. `config.json?static`.
+pub const STATIC_PARAM: &str = "static";
+
+/// The priority of [TrackName::is_bootstrap] tracks, sent before anything else.
+pub const BOOTSTRAP_PRIORITY: u64 = 0;
+
+/// A track name split into a path and query parameters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackName {
+	pub path: String,
+	pub params: Vec<(String, String)>,
+}
+
+impl TrackName {
+	pub fn new(path: &str) -> Self {
+		Self {
+			path: path.to_string(),
+			params: Vec::new(),
+		}
+	}
+
+	/// Add a query parameter, ex. `TrackName::new("video.m4s").with("rendition", 720)`.
+	pub fn with<T: ToString>(mut self, key: &str, value: T) -> Self {
+		self.params.push((key.to_string(), value.to_string()));
+		self
+	}
+
+	/// Split the name at the first `?` and decode the query, if any.
+	pub fn parse(name: &str) -> Self {
+		let (path, query) = match name.split_once('?') {
+			Some((path, query)) => (path, query),
+			None => return Self::new(name),
+		};
+
+		let params = query
+			.split('&')
+			.filter(|pair| !pair.is_empty())
+			.map(|pair| {
+				let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+				(decode(key), decode(value))
+			})
+			.collect();
+
+		Self {
+			path: path.to_string(),
+			params,
+		}
+	}
+
+	/// Return the raw value of the query parameter, if present.
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.params.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+	}
+
+	/// Parse the query parameter as a `T`, returning None if it's missing.
+	pub fn param<T: FromStr>(&self, key: &str) -> Result<Option<T>, T::Err> {
+		self.get(key).map(str::parse).transpose()
+	}
+
+	/// Returns true for tracks needed before anything can be played: the catalog and CMAF init segments (`*.mp4`).
+	///
+	/// Losing or delaying these delays every join, so they're sent reliably at [BOOTSTRAP_PRIORITY] by default.
+	pub fn is_bootstrap(&self) -> bool {
+		self.path == CATALOG_TRACK || self.path.ends_with(".mp4")
+	}
+
+	/// Returns true for tracks whose latest group is their entire state, ex. `config.json?static`.
+	///
+	/// Every new subscriber receives the latest group, even if it's older than the requested start.
+	/// [Self::is_bootstrap] tracks are always static, so a late joiner gets the init segment.
+	pub fn is_static(&self) -> bool {
+		self.is_bootstrap() || self.get(STATIC_PARAM).is_some()
+	}
+}
+
+impl fmt::Display for TrackName {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.path)?;
+
+		for (i, (key, value)) in self.params.iter().enumerate() {
+			let sep = if i == 0 { '?' } else { '&' };
+			write!(f, "{}{}={}", sep, encode(key), encode(value))?;
+		}
+
+		Ok(())
+	}
+}
+
+impl From<&str> for TrackName {
+	fn from(name: &str) -> Self {
+		Self::parse(name)
+	}
+}
+
+fn encode(s: &str) -> String {
+	let mut out = String::with_capacity(s.len());
+	for c in s.chars() {
+		match c {
+			'%' | '&' | '=' | '?' => out.push_str(&format!("%{:02X}", c as u8)),
+			c => out.push(c),
+		}
+	}
+	out
+}
+
+// Invalid escapes are passed through unchanged rather than rejected.
+fn decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut out = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+
+	while i < bytes.len() {
+		let escaped = (bytes[i] == b'%')
+			.then(|| s.get(i + 1..i + 3))
+			.flatten()
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+		match escaped {
+			Some(b) => {
+				out.push(b);
+				i += 3;
+			}
+			None => {
+				out.push(bytes[i]);
+				i += 1;
+			}
+		}
+	}
+
+	String::from_utf8(out).unwrap_or_else(|_| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse() {
+		let name = TrackName::parse("thumb.jpg?interval=5&preview&interval=10");
+		assert_eq!(name.path, "thumb.jpg");
+		assert_eq!(name.param::<u64>("interval"), Ok(Some(5)));
+		assert_eq!(name.get("preview"), Some(""));
+		assert_eq!(name.get("missing"), None);
+		assert!(name.param::<u64>("preview").is_err());
+
+		let name = TrackName::parse("video.m4s");
+		assert_eq!(name, TrackName::new("video.m4s"));
+		assert_eq!(name.to_string(), "video.m4s");
+		assert!(!name.is_bootstrap());
+
+		assert!(TrackName::parse(".catalog").is_bootstrap());
+		assert!(TrackName::parse("0.mp4?rendition=720").is_bootstrap());
+
+		assert!(!name.is_static());
+		assert!(TrackName::parse("0.mp4").is_static());
+		assert!(TrackName::parse("config.json?static").is_static());
+	}
+
+	#[test]
+	fn round_trip() {
+		let name = TrackName::new("video.m4s")
+			.with("rendition", 720)
+			.with("label", "a&b=c?100%");
+
+		let encoded = name.to_string();
+		assert_eq!(encoded, "video.m4s?rendition=720&label=a%26b%3Dc%3F100%25");
+		assert_eq!(TrackName::parse(&encoded), name);
+	}
+}
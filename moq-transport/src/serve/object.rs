@@ -8,9 +8,15 @@
 //! You can clone the [Reader] and each will read a copy of of all future chunks. (fanout)
 //!
 //! The fragment is closed with [ServeError::Closed] when all writers or readers are dropped.
-use std::{cmp, collections::BinaryHeap, ops::Deref, sync::Arc};
-
-use super::{ServeError, Track};
+use std::{
+	cmp,
+	collections::{btree_map, BTreeMap, BinaryHeap},
+	future::Future,
+	ops::Deref,
+	sync::Arc,
+};
+
+use super::{ServeError, Track, TrackRestart};
 use crate::watch::State;
 use bytes::Bytes;
 
@@ -39,6 +45,12 @@ struct ObjectsState {
 	// Incremented each time we push an object.
 	epoch: usize,
 
+	// Incremented each time the group ID goes backwards.
+	restarts: u64,
+
+	// The publisher's epoch.
+	track_epoch: Option<u64>,
+
 	// Can be sent by the writer with an explicit error code.
 	closed: Result<(), ServeError>,
 }
@@ -48,6 +60,8 @@ impl Default for ObjectsState {
 		Self {
 			objects: Vec::new(),
 			epoch: 0,
+			restarts: 0,
+			track_epoch: None,
 			closed: Ok(()),
 		}
 	}
@@ -71,6 +85,8 @@ impl ObjectsWriter {
 			group_id: object.group_id,
 			object_id: object.object_id,
 			priority: object.priority,
+			independent: object.independent,
+			extensions: object.extensions,
 		};
 
 		let (writer, reader) = object.produce();
@@ -79,8 +95,15 @@ impl ObjectsWriter {
 
 		if let Some(first) = state.objects.first() {
 			match writer.group_id.cmp(&first.group_id) {
-				// Drop this old group
-				cmp::Ordering::Less => return Ok(writer),
+				cmp::Ordering::Less => match self.track.restart {
+					// Drop this old group
+					TrackRestart::Drop => return Ok(writer),
+					TrackRestart::Reject => return Err(ServeError::Restart),
+					TrackRestart::Accept => {
+						state.objects.clear();
+						state.restarts += 1;
+					}
+				},
 				cmp::Ordering::Greater => state.objects.clear(),
 				cmp::Ordering::Equal => {}
 			}
@@ -92,6 +115,20 @@ impl ObjectsWriter {
 		Ok(writer)
 	}
 
+	/// Set the track epoch, ex. when SUBSCRIBE_OK arrives after the first object.
+	///
+	/// A different epoch than before means the publisher restarted, so the current group is discarded and readers see
+	/// the discontinuity via [ObjectsReader::restarts].
+	pub fn set_epoch(&mut self, epoch: u64) -> Result<(), ServeError> {
+		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		if state.track_epoch.replace(epoch).is_some_and(|prev| prev != epoch) {
+			state.objects.clear();
+			state.restarts += 1;
+		}
+
+		Ok(())
+	}
+
 	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
 		let state = self.state.lock();
 		state.closed.clone()?;
@@ -101,6 +138,11 @@ impl ObjectsWriter {
 
 		Ok(())
 	}
+
+	/// Resolves when all readers have been dropped, without keeping the writer alive.
+	pub fn unused(&self) -> impl Future<Output = ()> + Send + 'static {
+		self.state.dropped()
+	}
 }
 
 impl Deref for ObjectsWriter {
@@ -116,6 +158,7 @@ pub struct ObjectsReader {
 	state: State<ObjectsState>,
 	pub info: Arc<Track>,
 	epoch: usize,
+	restarts: u64,
 
 	// The objects ready to be returned
 	pending: BinaryHeap<ObjectReader>,
@@ -127,6 +170,7 @@ impl ObjectsReader {
 			state,
 			info,
 			epoch: 0,
+			restarts: 0,
 			pending: BinaryHeap::new(),
 		}
 	}
@@ -135,6 +179,13 @@ impl ObjectsReader {
 		loop {
 			{
 				let state = self.state.lock();
+
+				// Objects from before a restart would otherwise be prioritized due to their larger group ID.
+				if self.restarts != state.restarts {
+					self.restarts = state.restarts;
+					self.pending.clear();
+				}
+
 				if self.epoch < state.epoch {
 					// Add all of the new objects from the current group to our priority queue.
 					let index = state.objects.len().saturating_sub(state.epoch - self.epoch);
@@ -168,6 +219,16 @@ impl ObjectsReader {
 			.max_by_key(|a| (a.group_id, a.object_id))
 			.map(|a| (a.group_id, a.object_id))
 	}
+
+	/// The number of times the publisher restarted group numbering, signalling a discontinuity.
+	pub fn restarts(&self) -> u64 {
+		self.state.lock().restarts
+	}
+
+	/// The track epoch, if known, see [ObjectsWriter::set_epoch].
+	pub fn epoch(&self) -> Option<u64> {
+		self.state.lock().track_epoch
+	}
 }
 
 impl Deref for ObjectsReader {
@@ -191,6 +252,13 @@ pub struct ObjectInfo {
 
 	// The priority of the stream.
 	pub priority: u64,
+
+	/// The object can be decoded without the earlier objects in the group, ex. a keyframe.
+	/// Only sent if the subscriber supports extensions, otherwise it's false.
+	pub independent: bool,
+
+	// Metadata forwarded with the object, only sent if the subscriber supports extensions.
+	pub extensions: ObjectExtensions,
 }
 
 impl Deref for ObjectInfo {
@@ -222,6 +290,63 @@ pub struct Object {
 
 	// The priority of the stream.
 	pub priority: u64,
+
+	/// The object can be decoded without the earlier objects in the group, ex. a keyframe.
+	/// Only sent if the subscriber supports extensions, otherwise it's false.
+	pub independent: bool,
+
+	// Metadata forwarded with the object, only sent if the subscriber supports extensions.
+	pub extensions: ObjectExtensions,
+}
+
+/// Extension headers attached to an object, ex. a capture timestamp or an integrity hash.
+///
+/// These are sent before each object's payload and forwarded untouched by relays, so keep them small.
+/// The values are opaque; types should be agreed on by the application.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectExtensions(BTreeMap<u64, Bytes>);
+
+impl ObjectExtensions {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Set the value for a type, returning the previous value.
+	pub fn insert(&mut self, kind: u64, value: impl Into<Bytes>) -> Option<Bytes> {
+		self.0.insert(kind, value.into())
+	}
+
+	pub fn get(&self, kind: u64) -> Option<&Bytes> {
+		self.0.get(&kind)
+	}
+
+	pub fn remove(&mut self, kind: u64) -> Option<Bytes> {
+		self.0.remove(&kind)
+	}
+
+	pub fn iter(&self) -> btree_map::Iter<'_, u64, Bytes> {
+		self.0.iter()
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+}
+
+impl From<BTreeMap<u64, Bytes>> for ObjectExtensions {
+	fn from(extensions: BTreeMap<u64, Bytes>) -> Self {
+		Self(extensions)
+	}
+}
+
+impl From<ObjectExtensions> for BTreeMap<u64, Bytes> {
+	fn from(extensions: ObjectExtensions) -> Self {
+		extensions.0
+	}
 }
 
 struct ObjectState {
@@ -0,0 +1,200 @@
+//! Spill the payloads of older groups to disk, so a track can retain a long history without holding it in memory.
+//!
+//! A group is spilled when it's replaced by a newer group and retained, see [TrackCache](super::TrackCache).
+//! Its complete objects are written to a file, which is unlinked immediately and memory-mapped.
+//! Each chunk is replaced with a slice of the mapping at the same offset, so readers are unaffected,
+//! and the file is removed once every chunk has been dropped.
+//! Objects that are still being written stay in memory.
+//!
+//! The write happens once per group on a blocking thread, so the track isn't locked while waiting for the disk.
+//! The group stays in memory until its chunks are swapped for the mapping.
+use std::{
+	io::{self, Write},
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+};
+
+use bytes::Bytes;
+use memmap2::Mmap;
+
+#[derive(Debug)]
+pub struct Spill {
+	dir: PathBuf,
+	bytes: AtomicU64,
+
+	// Keep groups in memory instead while more than this many bytes are on disk.
+	max: Option<u64>,
+}
+
+impl Spill {
+	/// Spill to files in the given directory, ex. a fast local disk.
+	pub fn new(dir: impl Into<PathBuf>) -> Self {
+		Self {
+			dir: dir.into(),
+			bytes: AtomicU64::new(0),
+			max: None,
+		}
+	}
+
+	/// Keep groups in memory instead while this many bytes are on disk.
+	pub fn with_max_bytes(mut self, max: u64) -> Self {
+		self.max = Some(max);
+		self
+	}
+
+	/// The number of bytes currently on disk.
+	pub fn bytes(&self) -> u64 {
+		self.bytes.load(Ordering::Relaxed)
+	}
+
+	// Run the write on a blocking thread if there's a runtime, otherwise immediately.
+	pub(super) fn run(f: impl FnOnce() + Send + 'static) {
+		match tokio::runtime::Handle::try_current() {
+			Ok(handle) => drop(handle.spawn_blocking(f)),
+			Err(_) => f(),
+		}
+	}
+
+	// Write each object's chunks to a new file, returning them sliced from a mapping of it.
+	// Returns None if the limit would be exceeded.
+	pub(super) fn write(self: &Arc<Self>, objects: &[Vec<Bytes>]) -> io::Result<Option<Vec<Vec<Bytes>>>> {
+		let size = objects.iter().flatten().map(Bytes::len).sum::<usize>() as u64;
+		if size == 0 {
+			return Ok(None);
+		}
+
+		// Reserve the space up front, since other groups may be written at the same time.
+		let reserved = self
+			.bytes
+			.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bytes| match self.max {
+				Some(max) if bytes + size > max => None,
+				_ => Some(bytes + size),
+			});
+		if reserved.is_err() {
+			return Ok(None);
+		}
+
+		let mmap = match self.map(objects) {
+			Ok(mmap) => mmap,
+			Err(err) => {
+				self.bytes.fetch_sub(size, Ordering::Relaxed);
+				return Err(err);
+			}
+		};
+
+		let mapped = Bytes::from_owner(Mapped {
+			mmap,
+			spill: self.clone(),
+		});
+
+		let mut offset = 0;
+		let objects = objects
+			.iter()
+			.map(|chunks| {
+				chunks
+					.iter()
+					.map(|chunk| {
+						let slice = mapped.slice(offset..offset + chunk.len());
+						offset += chunk.len();
+						slice
+					})
+					.collect()
+			})
+			.collect();
+
+		Ok(Some(objects))
+	}
+
+	fn map(&self, objects: &[Vec<Bytes>]) -> io::Result<Mmap> {
+		let mut file = tempfile::tempfile_in(&self.dir)?;
+		for chunk in objects.iter().flatten() {
+			file.write_all(chunk)?;
+		}
+
+		// SAFETY: The file was created unlinked, so nothing else can modify it while it's mapped.
+		unsafe { Mmap::map(&file) }
+	}
+}
+
+// Compared by identity, so tracks sharing a directory are equal.
+impl PartialEq for Spill {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+// A mapped file, released from the disk usage once every slice is dropped.
+struct Mapped {
+	mmap: Mmap,
+	spill: Arc<Spill>,
+}
+
+impl AsRef<[u8]> for Mapped {
+	fn as_ref(&self) -> &[u8] {
+		&self.mmap
+	}
+}
+
+impl Drop for Mapped {
+	fn drop(&mut self) {
+		self.spill.bytes.fetch_sub(self.mmap.len() as u64, Ordering::Relaxed);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::serve::{GroupSkip, TrackCache, TrackReaderMode, Tracks};
+
+	#[tokio::test]
+	async fn spill() {
+		let spill = Arc::new(Spill::new(std::env::temp_dir()));
+		let (mut writer, _, mut reader) = Tracks::new("test".to_string())
+			.with_cache(TrackCache::new().with_max_groups(2).with_retain())
+			.with_spill(spill.clone())
+			.produce();
+
+		let mut groups = writer.create("video").unwrap().groups().unwrap();
+		let track = reader.subscribe("video").unwrap();
+
+		// Nobody is reading, but older groups are retained for FETCH and spilled once replaced.
+		// Only two older groups are retained, so group 0 is evicted and its file removed.
+		for payload in ["zero", "one", "two", "three"] {
+			let mut group = groups.append(0).unwrap();
+			group.write(payload.into()).unwrap();
+			group.write("!".into()).unwrap();
+		}
+
+		// The groups are written in the background, so the track isn't locked.
+		settle(|| spill.bytes() == 8 && reader.usage.bytes() == 6).await;
+
+		let cached = match track.mode().await.unwrap() {
+			TrackReaderMode::Groups(groups) => groups.with_skip(GroupSkip::Latest),
+			_ => panic!("expected groups"),
+		};
+
+		let mut payloads = Vec::new();
+		for mut group in cached.cached(0, None) {
+			while let Some(payload) = group.read_next().await.unwrap() {
+				payloads.push(payload);
+			}
+		}
+		assert_eq!(payloads, vec!["one", "!", "two", "!", "three", "!"]);
+
+		// Group 1 is evicted next, removing its file.
+		groups.append(0).unwrap().write("four".into()).unwrap();
+		settle(|| spill.bytes() == 10).await;
+	}
+
+	// Wait for the background writes to finish.
+	async fn settle(done: impl Fn() -> bool) {
+		let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+		while !done() {
+			assert!(tokio::time::Instant::now() < deadline, "spill didn't finish");
+			tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+		}
+	}
+}
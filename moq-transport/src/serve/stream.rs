@@ -1,9 +1,10 @@
 use bytes::Bytes;
-use std::{ops::Deref, sync::Arc};
+use std::{collections::VecDeque, future::Future, ops::Deref, sync::Arc};
+use tokio::time::Instant;
 
 use crate::watch::State;
 
-use super::{ServeError, Track};
+use super::{ServeError, Track, TrackCache, TrackRestart};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Stream {
@@ -38,6 +39,13 @@ struct StreamState {
 	// Updated each time objects changes.
 	epoch: usize,
 
+	// Updated each time the group ID goes backwards.
+	restarts: u64,
+
+	// The publisher's epoch, and whether it changed since the latest group.
+	track_epoch: Option<u64>,
+	reset: bool,
+
 	// Set when the writer is dropped.
 	closed: Result<(), ServeError>,
 }
@@ -47,6 +55,9 @@ impl Default for StreamState {
 		Self {
 			latest: None,
 			epoch: 0,
+			restarts: 0,
+			track_epoch: None,
+			reset: false,
 			closed: Ok(()),
 		}
 	}
@@ -72,9 +83,14 @@ impl StreamWriter {
 	pub fn create(&mut self, group_id: u64) -> Result<StreamGroupWriter, ServeError> {
 		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
 
-		if let Some(latest) = &state.latest {
+		// The first group after a new epoch is unrelated to the previous one, so accept it regardless of ID.
+		if let Some(latest) = state.latest.as_ref().filter(|_| !state.reset) {
 			if latest.group_id > group_id {
-				return Err(ServeError::Duplicate);
+				match self.info.track.restart {
+					TrackRestart::Accept => state.restarts += 1,
+					TrackRestart::Reject => return Err(ServeError::Restart),
+					TrackRestart::Drop => return Err(ServeError::Duplicate),
+				}
 			}
 		}
 
@@ -90,6 +106,7 @@ impl StreamWriter {
 
 		state.latest = Some(reader);
 		state.epoch += 1;
+		state.reset = false;
 
 		Ok(writer)
 	}
@@ -105,6 +122,20 @@ impl StreamWriter {
 		self.create(next)
 	}
 
+	/// Set the track epoch, ex. when SUBSCRIBE_OK arrives after the stream.
+	///
+	/// A different epoch than before means the publisher restarted, so the next group is accepted regardless of its ID
+	/// and readers see the discontinuity via [StreamReader::restarts].
+	pub fn set_epoch(&mut self, epoch: u64) -> Result<(), ServeError> {
+		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		if state.track_epoch.replace(epoch).is_some_and(|prev| prev != epoch) {
+			state.restarts += 1;
+			state.reset = true;
+		}
+
+		Ok(())
+	}
+
 	/// Close the stream with an error.
 	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
 		let state = self.state.lock();
@@ -115,6 +146,11 @@ impl StreamWriter {
 
 		Ok(())
 	}
+
+	/// Resolves when all readers have been dropped, without keeping the writer alive.
+	pub fn unused(&self) -> impl Future<Output = ()> + Send + 'static {
+		self.state.dropped()
+	}
 }
 
 impl Deref for StreamWriter {
@@ -170,6 +206,16 @@ impl StreamReader {
 		let state = self.state.lock();
 		state.latest.as_ref().map(|group| (group.group_id, group.latest()))
 	}
+
+	/// The number of times the publisher restarted group numbering, signalling a discontinuity.
+	pub fn restarts(&self) -> u64 {
+		self.state.lock().restarts
+	}
+
+	/// The track epoch, if known, see [StreamWriter::set_epoch].
+	pub fn epoch(&self) -> Option<u64> {
+		self.state.lock().track_epoch
+	}
 }
 
 impl Deref for StreamReader {
@@ -195,16 +241,43 @@ impl Deref for StreamGroup {
 }
 
 struct StreamGroupState {
-	// The objects that have been received thus far.
-	objects: Vec<StreamObjectReader>,
+	// The objects that have been received thus far and not evicted, along with when they were created.
+	objects: VecDeque<(Instant, StreamObjectReader)>,
 	closed: Result<(), ServeError>,
+
+	// The number of objects evicted from the front, see [TrackCache].
+	evicted: usize,
+
+	// The size of the retained objects.
+	bytes: u64,
+}
+
+impl StreamGroupState {
+	// Evict the oldest objects over the cache limits, always keeping the newest object.
+	fn evict(&mut self, cache: &TrackCache) {
+		let now = Instant::now();
+
+		while self.objects.len() > 1 {
+			let (created, _) = &self.objects[0];
+			if !cache.is_expired(*created, now) && cache.max_bytes.is_none_or(|max| self.bytes <= max) {
+				break;
+			}
+
+			if let Some((_, object)) = self.objects.pop_front() {
+				self.bytes -= object.size as u64;
+				self.evicted += 1;
+			}
+		}
+	}
 }
 
 impl Default for StreamGroupState {
 	fn default() -> Self {
 		Self {
-			objects: Vec::new(),
+			objects: VecDeque::new(),
 			closed: Ok(()),
+			evicted: 0,
+			bytes: 0,
 		}
 	}
 }
@@ -220,8 +293,9 @@ impl StreamGroupWriter {
 		Self { state, info, next: 0 }
 	}
 
-	/// Add a new object to the group.
+	/// Add a new object to the group, compressed if the track requires it.
 	pub fn write(&mut self, payload: Bytes) -> Result<(), ServeError> {
+		let payload = self.info.compression()?.compress(payload)?;
 		let mut writer = self.create(payload.len())?;
 		writer.write(payload)?;
 		Ok(())
@@ -237,7 +311,10 @@ impl StreamGroupWriter {
 		}
 		.produce();
 
-		state.objects.push(reader);
+		state.objects.push_back((Instant::now(), reader));
+		state.bytes += size as u64;
+		state.evict(&self.info.track.cache);
+		self.next += 1;
 
 		Ok(writer)
 	}
@@ -274,9 +351,11 @@ impl StreamGroupReader {
 		Self { state, info, index: 0 }
 	}
 
+	/// Read the next object in full, decompressed if the track requires it.
 	pub async fn read_next(&mut self) -> Result<Option<Bytes>, ServeError> {
 		if let Some(mut reader) = self.next().await? {
-			Ok(Some(reader.read_all().await?))
+			let payload = reader.read_all().await?;
+			Ok(Some(self.info.compression()?.decompress(payload)?))
 		} else {
 			Ok(None)
 		}
@@ -286,9 +365,17 @@ impl StreamGroupReader {
 		loop {
 			{
 				let state = self.state.lock();
-				if self.index < state.objects.len() {
+
+				// Fell behind and the next object was evicted, so skip to the oldest retained object if called again.
+				if self.index < state.evicted {
+					self.index = state.evicted;
+					return Err(ServeError::Expired);
+				}
+
+				if let Some((_, object)) = state.objects.get(self.index - state.evicted) {
+					let object = object.clone();
 					self.index += 1;
-					return Ok(Some(state.objects[self.index].clone()));
+					return Ok(Some(object));
 				}
 
 				state.closed.clone()?;
@@ -303,7 +390,7 @@ impl StreamGroupReader {
 
 	pub fn latest(&self) -> u64 {
 		let state = self.state.lock();
-		state.objects.last().map(|o| o.object_id).unwrap_or_default()
+		state.objects.back().map(|(_, o)| o.object_id).unwrap_or_default()
 	}
 }
 
@@ -0,0 +1,363 @@
+//! A subgroup is a subset of a group's objects, split into a [SubgroupWriter] and [SubgroupReader] handle.
+//!
+//! A [SubgroupWriter] is created from a [GroupWriter](super::GroupWriter) and writes an ordered stream of objects.
+//! Each subgroup is sent on its own stream with its own priority, ex. so an enhancement layer can be dropped first.
+//!
+//! A [SubgroupReader] is returned by [GroupReader::next_subgroup](super::GroupReader::next_subgroup).
+//! The reader can be cloned, in which case each reader receives a copy of each object. (fanout)
+use bytes::Bytes;
+use std::{ops::Deref, sync::Arc};
+
+use crate::watch::State;
+
+use super::{
+	group::GroupState, GroupInfo, GroupObject, GroupObjectReader, GroupObjectWriter, ObjectExtensions, Reservation,
+	ServeError,
+};
+
+#[cfg(feature = "disk")]
+use super::Spill;
+
+/// Parameters that can be specified by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subgroup {
+	// The ID of the subgroup within the group.
+	pub subgroup_id: u64,
+
+	// The priority of the subgroup, used instead of the group's priority.
+	pub priority: u64,
+}
+
+/// Static information about the subgroup
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubgroupInfo {
+	pub group: Arc<GroupInfo>,
+
+	// The ID of the subgroup within the group.
+	pub subgroup_id: u64,
+
+	// The priority of the subgroup, used instead of the group's priority.
+	pub priority: u64,
+}
+
+impl SubgroupInfo {
+	pub(super) fn produce(self, group: State<GroupState>) -> (SubgroupWriter, SubgroupReader) {
+		let (writer, reader) = State::default().split();
+		let info = Arc::new(self);
+
+		let writer = SubgroupWriter::new(writer, group, info.clone());
+		let reader = SubgroupReader::new(reader, info);
+
+		(writer, reader)
+	}
+}
+
+impl Deref for SubgroupInfo {
+	type Target = GroupInfo;
+
+	fn deref(&self) -> &Self::Target {
+		&self.group
+	}
+}
+
+struct SubgroupState {
+	// The data that has been received thus far.
+	objects: Vec<GroupObjectReader>,
+
+	// Set when the writer or all readers are dropped.
+	closed: Result<(), ServeError>,
+
+	// The bytes reserved for the objects, released when the subgroup is dropped.
+	reserved: Option<Reservation>,
+
+	// The number of objects written to a transport stream (or dropped) by the furthest reader.
+	written: usize,
+}
+
+impl Default for SubgroupState {
+	fn default() -> Self {
+		Self {
+			objects: Vec::new(),
+			closed: Ok(()),
+			reserved: None,
+			written: 0,
+		}
+	}
+}
+
+/// Used to write data to a subgroup and notify readers.
+///
+/// The group isn't finished until its writer and every subgroup writer have been dropped.
+pub struct SubgroupWriter {
+	// Mutable subgroup state.
+	state: State<SubgroupState>,
+
+	// Keeps the group open until the subgroup is done.
+	group: State<GroupState>,
+
+	// Immutable subgroup state.
+	pub info: Arc<SubgroupInfo>,
+
+	// The next object sequence number to use.
+	next: u64,
+}
+
+impl SubgroupWriter {
+	fn new(state: State<SubgroupState>, group: State<GroupState>, info: Arc<SubgroupInfo>) -> Self {
+		Self {
+			state,
+			group,
+			info,
+			next: 0,
+		}
+	}
+
+	/// Create the next object ID with the given payload, compressed if the track requires it.
+	pub fn write(&mut self, payload: Bytes) -> Result<(), ServeError> {
+		self.write_extended(payload, ObjectExtensions::default())
+	}
+
+	/// Like [Self::write], but with extension headers that are forwarded with the object.
+	pub fn write_extended(&mut self, payload: Bytes, extensions: ObjectExtensions) -> Result<(), ServeError> {
+		self.write_with(payload, false, extensions)
+	}
+
+	/// Like [Self::write], but marks the object as decodable without the earlier objects in the group, ex. a keyframe.
+	pub fn write_independent(&mut self, payload: Bytes) -> Result<(), ServeError> {
+		self.write_with(payload, true, ObjectExtensions::default())
+	}
+
+	/// Like [Self::write], with every per-object field, ex. to copy an object from another group.
+	pub fn write_with(
+		&mut self,
+		payload: Bytes,
+		independent: bool,
+		extensions: ObjectExtensions,
+	) -> Result<(), ServeError> {
+		let payload = self.info.track.compression()?.compress(payload)?;
+		let mut object = self.create_with(payload.len(), independent, extensions)?;
+		object.write(payload)?;
+		Ok(())
+	}
+
+	/// Start numbering objects from this ID, ex. when the earlier objects in the subgroup weren't requested.
+	pub fn skip_to(&mut self, object_id: u64) {
+		self.next = self.next.max(object_id);
+	}
+
+	/// Write an object over multiple writes.
+	///
+	/// BAD STUFF will happen if the size is wrong; this is an advanced feature.
+	pub fn create(&mut self, size: usize) -> Result<GroupObjectWriter, ServeError> {
+		self.create_extended(size, ObjectExtensions::default())
+	}
+
+	/// Like [Self::create], but with extension headers that are forwarded with the object.
+	pub fn create_extended(
+		&mut self,
+		size: usize,
+		extensions: ObjectExtensions,
+	) -> Result<GroupObjectWriter, ServeError> {
+		self.create_with(size, false, extensions)
+	}
+
+	/// Like [Self::create], with every per-object field, see [GroupObject].
+	pub fn create_with(
+		&mut self,
+		size: usize,
+		independent: bool,
+		extensions: ObjectExtensions,
+	) -> Result<GroupObjectWriter, ServeError> {
+		// The expiry is configured on the group, see [GroupWriter::set_expires](super::GroupWriter::set_expires).
+		let group = self.group.lock();
+		group.check_expired()?;
+
+		let expires = match group.into_mut() {
+			Some(mut group) => group.object_expires(),
+			None => None,
+		};
+
+		let (writer, reader) = GroupObject {
+			group: self.info.group.clone(),
+			object_id: self.next,
+			size,
+			independent,
+			extensions,
+			expires,
+		}
+		.produce();
+
+		self.next += 1;
+
+		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		state.objects.push(reader);
+		state
+			.reserved
+			.get_or_insert_with(|| Reservation::new(self.info.track.usage.clone()))
+			.add(size as u64);
+
+		Ok(writer)
+	}
+
+	/// Close the subgroup with an error.
+	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		state.closed.clone()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Cancel)?;
+		state.closed = Err(err);
+		Ok(())
+	}
+
+	/// Block until every object created so far has been written to a transport stream, or dropped.
+	///
+	/// See [GroupWriter::written](super::GroupWriter::written), including the need for a timeout.
+	pub async fn written(&self) {
+		let target = self.state.lock().objects.len();
+
+		loop {
+			{
+				let state = self.state.lock();
+				if state.written >= target {
+					return;
+				}
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return,
+				}
+			}
+			.await;
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.state.lock().objects.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+impl Deref for SubgroupWriter {
+	type Target = SubgroupInfo;
+
+	fn deref(&self) -> &Self::Target {
+		&self.info
+	}
+}
+
+/// Notified when a subgroup has new data available.
+#[derive(Clone)]
+pub struct SubgroupReader {
+	// Modify the subgroup state.
+	state: State<SubgroupState>,
+
+	// Immutable subgroup state.
+	pub info: Arc<SubgroupInfo>,
+
+	// The number of objects that we've read.
+	// NOTE: Cloned readers inherit this index, but then run in parallel.
+	index: usize,
+}
+
+impl SubgroupReader {
+	fn new(state: State<SubgroupState>, info: Arc<SubgroupInfo>) -> Self {
+		Self { state, info, index: 0 }
+	}
+
+	// The number of bytes reserved for the subgroup's objects.
+	pub(super) fn reserved(&self) -> u64 {
+		self.state
+			.lock()
+			.reserved
+			.as_ref()
+			.map(Reservation::bytes)
+			.unwrap_or_default()
+	}
+
+	// Move the complete objects to disk when the group is spilled, releasing their memory.
+	// Called from a blocking task, so the objects are written without holding the lock.
+	#[cfg(feature = "disk")]
+	pub(super) fn spill_now(&self, spill: &Arc<Spill>) {
+		let objects = self.state.lock().objects.clone();
+		let spilled = super::group::spill_objects(&objects, spill);
+		if let Some(reserved) = &mut self.state.lock_mut_force().reserved {
+			reserved.release(spilled);
+		}
+	}
+
+	// Drop the cached objects and close the subgroup when the group is evicted, see [TrackCache](super::TrackCache).
+	pub(super) fn expire(&self) {
+		let mut state = self.state.lock_mut_force();
+		state.objects.clear();
+		state.reserved = None;
+		state.closed = Err(ServeError::Expired);
+	}
+
+	/// Read the next object in full, decompressed if the track requires it.
+	pub async fn read_next(&mut self) -> Result<Option<Bytes>, ServeError> {
+		let object = self.next().await?;
+		match object {
+			Some(mut object) => {
+				let payload = object.read_all().await?;
+				Ok(Some(self.info.track.compression()?.decompress(payload)?))
+			}
+			None => Ok(None),
+		}
+	}
+
+	pub async fn next(&mut self) -> Result<Option<GroupObjectReader>, ServeError> {
+		loop {
+			{
+				let state = self.state.lock();
+
+				if self.index < state.objects.len() {
+					let object = state.objects[self.index].clone();
+					self.index += 1;
+					return Ok(Some(object));
+				}
+
+				state.closed.clone()?;
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Ok(None),
+				}
+			}
+			.await; // Try again when the state changes
+		}
+	}
+
+	/// Mark every object returned by [Self::next] as written to a transport stream (or dropped), see [SubgroupWriter::written].
+	pub fn mark_written(&self) {
+		let state = self.state.lock();
+		if state.written >= self.index {
+			return;
+		}
+
+		if let Some(mut state) = state.into_mut() {
+			state.written = self.index;
+		}
+	}
+
+	pub fn pos(&self) -> usize {
+		self.index
+	}
+
+	pub fn len(&self) -> usize {
+		self.state.lock().objects.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+impl Deref for SubgroupReader {
+	type Target = SubgroupInfo;
+
+	fn deref(&self) -> &Self::Target {
+		&self.info
+	}
+}
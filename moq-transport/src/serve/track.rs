@@ -14,23 +14,57 @@
 
 use crate::watch::State;
 
+#[cfg(feature = "disk")]
+use super::Spill;
 use super::{
-	Datagrams, DatagramsReader, DatagramsWriter, Groups, GroupsReader, GroupsWriter, Objects, ObjectsReader,
-	ObjectsWriter, ServeError, Stream, StreamReader, StreamWriter,
+	Compression, Datagrams, DatagramsReader, DatagramsWriter, Groups, GroupsReader, GroupsWriter, Objects,
+	ObjectsReader, ObjectsWriter, ServeError, Stream, StreamReader, StreamWriter, TrackCache, TrackName, Usage,
 };
+use futures::future::{BoxFuture, FutureExt};
 use paste::paste;
-use std::{ops::Deref, sync::Arc};
+use std::{future::Future, ops::Deref, sync::Arc};
 
 /// Static information about a track.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Track {
 	pub namespace: String,
 	pub name: String,
+
+	/// What to do when a group ID goes backwards, ex. the publisher restarted its encoder.
+	pub restart: TrackRestart,
+
+	/// The bytes retained by the track, usually shared with the rest of the broadcast.
+	pub usage: Arc<Usage>,
+
+	/// Limits on the older data retained for readers, evicting anything over them.
+	pub cache: TrackCache,
+
+	/// Serve the latest group to every new subscriber, even if it's before the requested start.
+	/// Defaults to [TrackName::is_static], so each relay along the way agrees.
+	pub is_static: bool,
+
+	/// Move older groups to disk instead of keeping them in memory, usually shared with the rest of the broadcast.
+	#[cfg(feature = "disk")]
+	pub spill: Option<Arc<Spill>>,
 }
 
 impl Track {
 	pub fn new(namespace: String, name: String) -> Self {
-		Self { namespace, name }
+		Self {
+			is_static: TrackName::parse(&name).is_static(),
+			namespace,
+			name,
+			restart: Default::default(),
+			usage: Default::default(),
+			cache: Default::default(),
+			#[cfg(feature = "disk")]
+			spill: None,
+		}
+	}
+
+	/// The compression negotiated by the track name, applied by the `write` and `read_next` helpers.
+	pub fn compression(&self) -> Result<Compression, ServeError> {
+		Compression::from_name(&self.name)
 	}
 
 	pub fn produce(self) -> (TrackWriter, TrackReader) {
@@ -44,8 +78,26 @@ impl Track {
 	}
 }
 
+/// How a writer handles a group ID lower than the latest group.
+///
+/// Groups may legitimately arrive late, but a publisher that restarts will also reset numbering back to zero.
+/// Stream mode can't receive late groups, so anything other than [TrackRestart::Accept] is an error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrackRestart {
+	/// Drop the group, assuming it arrived late.
+	#[default]
+	Drop,
+
+	/// Return [ServeError::Restart].
+	Reject,
+
+	/// Accept the group as the new latest and bump the reader's `restarts()` counter to signal a discontinuity.
+	Accept,
+}
+
 struct TrackState {
 	mode: Option<TrackReaderMode>,
+	epoch: Option<u64>,
 	closed: Result<(), ServeError>,
 }
 
@@ -53,6 +105,7 @@ impl Default for TrackState {
 	fn default() -> Self {
 		Self {
 			mode: None,
+			epoch: None,
 			closed: Ok(()),
 		}
 	}
@@ -70,47 +123,72 @@ impl TrackWriter {
 		Self { state, info }
 	}
 
+	/// Set the track epoch, which should be bumped each time the publisher restarts.
+	///
+	/// Subscribers use this to distinguish a restart from reordering.
+	/// It's passed on to the mode once chosen, see [TrackWriterMode::set_epoch] to set it afterwards.
+	pub fn set_epoch(&mut self, epoch: u64) -> Result<(), ServeError> {
+		self.state.lock_mut().ok_or(ServeError::Cancel)?.epoch = Some(epoch);
+		Ok(())
+	}
+
 	pub fn stream(self, priority: u64) -> Result<StreamWriter, ServeError> {
-		let (writer, reader) = Stream {
+		let (mut writer, reader) = Stream {
 			track: self.info.clone(),
 			priority,
 		}
 		.produce();
 
 		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		if let Some(epoch) = state.epoch {
+			writer.set_epoch(epoch)?;
+		}
+
 		state.mode = Some(reader.into());
 		Ok(writer)
 	}
 
 	pub fn groups(self) -> Result<GroupsWriter, ServeError> {
-		let (writer, reader) = Groups {
+		let (mut writer, reader) = Groups {
 			track: self.info.clone(),
 		}
 		.produce();
 
 		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		if let Some(epoch) = state.epoch {
+			writer.set_epoch(epoch)?;
+		}
+
 		state.mode = Some(reader.into());
 		Ok(writer)
 	}
 
 	pub fn objects(self) -> Result<ObjectsWriter, ServeError> {
-		let (writer, reader) = Objects {
+		let (mut writer, reader) = Objects {
 			track: self.info.clone(),
 		}
 		.produce();
 
 		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		if let Some(epoch) = state.epoch {
+			writer.set_epoch(epoch)?;
+		}
+
 		state.mode = Some(reader.into());
 		Ok(writer)
 	}
 
 	pub fn datagrams(self) -> Result<DatagramsWriter, ServeError> {
-		let (writer, reader) = Datagrams {
+		let (mut writer, reader) = Datagrams {
 			track: self.info.clone(),
 		}
 		.produce();
 
 		let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+		if let Some(epoch) = state.epoch {
+			writer.set_epoch(epoch)?;
+		}
+
 		state.mode = Some(reader.into());
 		Ok(writer)
 	}
@@ -124,6 +202,11 @@ impl TrackWriter {
 		state.closed = Err(err);
 		Ok(())
 	}
+
+	/// Resolves when all readers have been dropped, without keeping the writer alive.
+	pub fn unused(&self) -> impl Future<Output = ()> + Send + 'static {
+		self.state.dropped()
+	}
 }
 
 impl Deref for TrackWriter {
@@ -146,6 +229,9 @@ impl TrackReader {
 		Self { state, info }
 	}
 
+	/// Wait until the publisher chooses a mode, returning a reader for it.
+	///
+	/// Each call returns an independent reader, so multiple consumers can share the same subscription.
 	pub async fn mode(&self) -> Result<TrackReaderMode, ServeError> {
 		loop {
 			{
@@ -164,6 +250,27 @@ impl TrackReader {
 		}
 	}
 
+	/// Like [Self::mode], but returns None instead of waiting if the mode isn't known yet.
+	pub fn try_mode(&self) -> Result<Option<TrackReaderMode>, ServeError> {
+		let state = self.state.lock();
+		if let Some(mode) = &state.mode {
+			return Ok(Some(mode.clone()));
+		}
+
+		state.closed.clone()?;
+		Ok(None)
+	}
+
+	// Returns false if the track was closed, or the writer was dropped before choosing a mode.
+	pub(super) fn is_active(&self) -> bool {
+		let state = self.state.lock();
+		if state.closed.is_err() {
+			return false;
+		}
+
+		state.mode.is_some() || state.modified().is_some()
+	}
+
 	// Returns the largest group/sequence
 	pub fn latest(&self) -> Option<(u64, u64)> {
 		// We don't even know the mode yet.
@@ -171,6 +278,13 @@ impl TrackReader {
 		None
 	}
 
+	/// The track epoch, if known.
+	/// A different epoch than a previous subscription means the publisher restarted, so any group IDs are unrelated.
+	pub fn epoch(&self) -> Option<u64> {
+		let state = self.state.lock();
+		state.mode.as_ref().and_then(TrackReaderMode::epoch).or(state.epoch)
+	}
+
 	pub async fn closed(&self) -> Result<(), ServeError> {
 		loop {
 			{
@@ -215,6 +329,12 @@ macro_rules! track_readers {
 						$(Self::$name(reader) => reader.latest(),)*
 					}
 				}
+
+				pub fn epoch(&self) -> Option<u64> {
+					match self {
+						$(Self::$name(reader) => reader.epoch(),)*
+					}
+				}
 			}
 		}
 	}
@@ -241,6 +361,19 @@ macro_rules! track_writers {
 						$(Self::$name(writer) => writer.close(err),)*
 					}
 				}
+
+				pub fn unused(&self) -> BoxFuture<'static, ()> {
+					match self {
+						$(Self::$name(writer) => writer.unused().boxed(),)*
+					}
+				}
+
+				/// Set the track epoch, even if the mode was already chosen.
+				pub fn set_epoch(&mut self, epoch: u64) -> Result<(), ServeError> {
+					match self {
+						$(Self::$name(writer) => writer.set_epoch(epoch),)*
+					}
+				}
 			}
 		}
 	}
@@ -12,18 +12,66 @@
 //! The broadcast is automatically closed with [ServeError::Done] when [Writer] is dropped, or all [Reader]s are dropped.
 use std::{collections::HashMap, ops::Deref, sync::Arc};
 
-use super::{ServeError, Track, TrackReader, TrackWriter};
+use super::{ServeError, Track, TrackCache, TrackName, TrackReader, TrackWriter, Usage};
 use crate::watch::{Queue, State};
 
+#[cfg(feature = "disk")]
+use super::Spill;
+
 /// Static information about a broadcast.
 #[derive(Debug)]
 pub struct Tracks {
 	pub namespace: String,
+
+	/// The bytes retained by every track in the broadcast.
+	pub usage: Arc<Usage>,
+
+	/// Identifies the broadcast end-to-end, even if the namespace is rewritten by a relay along the way.
+	pub broadcast_id: Option<String>,
+
+	/// The cache limits applied to each track in the broadcast.
+	pub cache: TrackCache,
+
+	/// Where each track in the broadcast spills older groups, if anywhere.
+	#[cfg(feature = "disk")]
+	pub spill: Option<Arc<Spill>>,
 }
 
 impl Tracks {
 	pub fn new(namespace: String) -> Self {
-		Self { namespace }
+		Self {
+			namespace,
+			usage: Default::default(),
+			broadcast_id: None,
+			cache: Default::default(),
+			#[cfg(feature = "disk")]
+			spill: None,
+		}
+	}
+
+	/// Send this ID with the announce, so the broadcast can be traced across relays.
+	pub fn with_broadcast_id(mut self, id: impl Into<String>) -> Self {
+		self.broadcast_id = Some(id.into());
+		self
+	}
+
+	/// Track the retained bytes with the provided [Usage], ex. to enforce a limit.
+	pub fn with_usage(mut self, usage: Usage) -> Self {
+		self.usage = Arc::new(usage);
+		self
+	}
+
+	/// Limit the data each track retains for readers, see [TrackCache].
+	pub fn with_cache(mut self, cache: TrackCache) -> Self {
+		self.cache = cache;
+		self
+	}
+
+	/// Move the retained older groups of each track to disk, see [Spill].
+	#[cfg(feature = "disk")]
+	pub fn with_spill(mut self, spill: Arc<Spill>) -> Self {
+		self.spill = Some(spill);
+		self
 	}
 
 	pub fn produce(self) -> (TracksWriter, TracksRequest, TracksReader) {
@@ -57,10 +105,19 @@ impl TracksWriter {
 
 	/// Create a new track with the given name, inserting it into the broadcast.
 	/// None is returned if all [TracksReader]s have been dropped.
+	///
+	/// NOTE: An existing track is replaced, so new readers won't receive anything written to it.
+	/// Use [Self::get_or_create] when the track may have already been created.
 	pub fn create(&mut self, track: &str) -> Option<TrackWriter> {
 		let (writer, reader) = Track {
 			namespace: self.namespace.clone(),
 			name: track.to_owned(),
+			restart: Default::default(),
+			usage: self.usage.clone(),
+			cache: self.cache,
+			is_static: TrackName::parse(track).is_static(),
+			#[cfg(feature = "disk")]
+			spill: self.spill.clone(),
 		}
 		.produce();
 
@@ -70,11 +127,35 @@ impl TracksWriter {
 		Some(writer)
 	}
 
+	/// Create the track unless it already exists, in which case the existing track is returned.
+	/// None is returned if all [TracksReader]s have been dropped.
+	///
+	/// A track that was closed, or whose writer was dropped before choosing a mode, is replaced.
+	pub fn get_or_create(&mut self, track: &str) -> Option<TrackEntry> {
+		{
+			let state = self.state.lock();
+			if let Some(existing) = state.tracks.get(track).filter(|existing| existing.is_active()) {
+				return Some(TrackEntry::Existing(existing.clone()));
+			}
+		}
+
+		self.create(track).map(TrackEntry::Created)
+	}
+
 	pub fn remove(&mut self, track: &str) -> Option<TrackReader> {
 		self.state.lock_mut()?.tracks.remove(track)
 	}
 }
 
+/// A track returned by [TracksWriter::get_or_create].
+pub enum TrackEntry {
+	/// The track was created, so the caller is responsible for writing it.
+	Created(TrackWriter),
+
+	/// The track already exists and is written elsewhere.
+	Existing(TrackReader),
+}
+
 impl Deref for TracksWriter {
 	type Target = Tracks;
 
@@ -141,9 +222,15 @@ impl TracksReader {
 	/// Get or request a track from the broadcast by name.
 	/// None is returned if [TracksWriter] or [TracksRequest] cannot fufill the request.
 	pub fn subscribe(&mut self, name: &str) -> Option<TrackReader> {
+		self.subscribe_keyed(name, name)
+	}
+
+	/// Like [Self::subscribe], but deduplicated by `key` instead of the name, ex. a canonical form of it.
+	/// A new track is requested with `name`, so the publisher is asked for the name the subscriber used.
+	pub fn subscribe_keyed(&mut self, key: &str, name: &str) -> Option<TrackReader> {
 		let state = self.state.lock();
 
-		if let Some(track) = state.tracks.get(name) {
+		if let Some(track) = state.tracks.get(key) {
 			return Some(track.clone());
 		}
 
@@ -151,6 +238,12 @@ impl TracksReader {
 		let track = Track {
 			namespace: self.namespace.clone(),
 			name: name.to_owned(),
+			restart: Default::default(),
+			usage: self.usage.clone(),
+			cache: self.cache,
+			is_static: TrackName::parse(name).is_static(),
+			#[cfg(feature = "disk")]
+			spill: self.spill.clone(),
 		}
 		.produce();
 
@@ -159,10 +252,24 @@ impl TracksReader {
 		}
 
 		// We requested the track sucessfully so we can deduplicate it.
-		state.tracks.insert(name.to_owned(), track.1.clone());
+		state.tracks.insert(key.to_owned(), track.1.clone());
 
 		Some(track.1.clone())
 	}
+
+	/// Resolves when the [TracksWriter] and [TracksRequest] have been dropped, ex. the broadcast has ended.
+	pub async fn closed(&self) {
+		loop {
+			{
+				let state = self.state.lock();
+				match state.modified() {
+					Some(notify) => notify,
+					None => return,
+				}
+			}
+			.await;
+		}
+	}
 }
 
 impl Deref for TracksReader {
@@ -172,3 +279,50 @@ impl Deref for TracksReader {
 		&self.info
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn get_or_create() {
+		let (mut writer, _, mut reader) = Tracks::new("test".to_string()).produce();
+
+		let track = match writer.get_or_create("0.mp4").unwrap() {
+			TrackEntry::Created(track) => track,
+			TrackEntry::Existing(_) => panic!("expected a new track"),
+		};
+		let _groups = track.groups().unwrap();
+
+		// The second call returns the same track rather than replacing it.
+		let existing = match writer.get_or_create("0.mp4").unwrap() {
+			TrackEntry::Existing(existing) => existing,
+			TrackEntry::Created(_) => panic!("expected the existing track"),
+		};
+		assert!(Arc::ptr_eq(&existing.info, &reader.subscribe("0.mp4").unwrap().info));
+		assert!(existing.try_mode().unwrap().is_some());
+
+		// A track abandoned before choosing a mode is replaced.
+		drop(writer.get_or_create("1.m4s").unwrap());
+		assert!(matches!(writer.get_or_create("1.m4s"), Some(TrackEntry::Created(_))));
+	}
+
+	#[tokio::test]
+	async fn subscribe_keyed() {
+		let (_writer, mut request, mut reader) = Tracks::new("test".to_string()).produce();
+
+		// Alternate spellings share a track, requested with the first name used.
+		let first = reader.subscribe_keyed("caf\u{e9}.m4s", "cafe\u{301}.m4s").unwrap();
+		let second = reader.subscribe_keyed("caf\u{e9}.m4s", "caf%C3%A9.m4s").unwrap();
+		assert!(Arc::ptr_eq(&first.info, &second.info));
+
+		let requested = request.next().await.unwrap();
+		assert_eq!(requested.name, "cafe\u{301}.m4s");
+		assert!(Arc::ptr_eq(&requested.info, &first.info));
+
+		// The key is used for lookups, not the name.
+		assert!(reader.subscribe_keyed("caf\u{e9}.m4s", "other").is_some());
+		let other = reader.subscribe("cafe\u{301}.m4s").unwrap();
+		assert!(!Arc::ptr_eq(&other.info, &first.info));
+	}
+}
@@ -0,0 +1,154 @@
+//! Approximate accounting of the bytes retained by a broadcast, shared by all of its tracks.
+//!
+//! Bytes are reserved when a group object is created and released once the group is no longer referenced,
+//! covering both the cached latest group and any older groups still being read.
+//! Bytes spilled to disk are released, see [Spill](super::Spill).
+//! Only group mode is tracked, as that's what's used for media.
+use std::sync::{
+	atomic::{AtomicU64, Ordering},
+	Arc,
+};
+
+use super::ServeError;
+
+#[derive(Debug)]
+pub struct Usage {
+	bytes: AtomicU64,
+	written: AtomicU64,
+
+	// Reject new groups with [ServeError::Full] while more than this many bytes are retained.
+	// u64::MAX means unlimited, so the limit can be changed while groups are being created.
+	max: AtomicU64,
+}
+
+impl Default for Usage {
+	fn default() -> Self {
+		Self::new(None)
+	}
+}
+
+impl Usage {
+	pub fn new(max: Option<u64>) -> Self {
+		Self {
+			bytes: AtomicU64::new(0),
+			written: AtomicU64::new(0),
+			max: AtomicU64::new(max.unwrap_or(u64::MAX)),
+		}
+	}
+
+	/// The number of retained bytes above which new groups are rejected with [ServeError::Full].
+	pub fn max(&self) -> Option<u64> {
+		match self.max.load(Ordering::Relaxed) {
+			u64::MAX => None,
+			max => Some(max),
+		}
+	}
+
+	/// Change the limit at runtime, applying to the next group created.
+	pub fn set_max(&self, max: Option<u64>) {
+		self.max.store(max.unwrap_or(u64::MAX), Ordering::Relaxed);
+	}
+
+	/// The number of bytes currently retained.
+	pub fn bytes(&self) -> u64 {
+		self.bytes.load(Ordering::Relaxed)
+	}
+
+	/// The total number of bytes ever reserved, which only changes while objects are flowing.
+	pub fn written(&self) -> u64 {
+		self.written.load(Ordering::Relaxed)
+	}
+
+	// Returns an error if a new group would exceed the limit, ignoring bytes that are about to be released.
+	pub(super) fn check(&self, releasing: u64) -> Result<(), ServeError> {
+		match self.max() {
+			Some(max) if self.bytes().saturating_sub(releasing) >= max => Err(ServeError::Full),
+			_ => Ok(()),
+		}
+	}
+}
+
+// Compared by identity, so tracks sharing a broadcast are equal.
+impl PartialEq for Usage {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+// Releases the reserved bytes when dropped.
+pub(super) struct Reservation {
+	usage: Arc<Usage>,
+	bytes: u64,
+}
+
+impl Reservation {
+	pub fn new(usage: Arc<Usage>) -> Self {
+		Self { usage, bytes: 0 }
+	}
+
+	pub fn bytes(&self) -> u64 {
+		self.bytes
+	}
+
+	pub fn add(&mut self, bytes: u64) {
+		self.usage.bytes.fetch_add(bytes, Ordering::Relaxed);
+		self.usage.written.fetch_add(bytes, Ordering::Relaxed);
+		self.bytes += bytes;
+	}
+
+	// Release bytes early, ex. once they've been spilled to disk.
+	#[cfg(feature = "disk")]
+	pub fn release(&mut self, bytes: u64) {
+		let bytes = bytes.min(self.bytes);
+		self.usage.bytes.fetch_sub(bytes, Ordering::Relaxed);
+		self.bytes -= bytes;
+	}
+}
+
+impl Drop for Reservation {
+	fn drop(&mut self) {
+		self.usage.bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::serve::Tracks;
+
+	#[test]
+	fn limit() {
+		let (mut writer, _, reader) = Tracks::new("test".to_string())
+			.with_usage(Usage::new(Some(10)))
+			.produce();
+
+		let mut groups = writer.create("video").unwrap().groups().unwrap();
+
+		// The latest group is cached even after the writer is dropped.
+		groups.append(0).unwrap().write(vec![0; 16].into()).unwrap();
+		assert_eq!(reader.usage.bytes(), 16);
+
+		// Replacing the latest group releases it, so it doesn't count towards the limit.
+		let mut held = groups.append(0).unwrap();
+		assert_eq!(reader.usage.bytes(), 0);
+		held.write(vec![0; 12].into()).unwrap();
+
+		// But an older group that's still referenced does.
+		groups.append(0).unwrap().write(vec![0; 1].into()).unwrap();
+		assert_eq!(reader.usage.bytes(), 13);
+		assert_eq!(groups.append(0).err(), Some(ServeError::Full));
+
+		drop(held);
+		assert_eq!(reader.usage.bytes(), 1);
+		assert!(groups.append(0).is_ok());
+
+		// Releasing bytes doesn't change the total written.
+		assert_eq!(reader.usage.written(), 29);
+
+		// Lowering the limit applies to the next group.
+		reader.usage.set_max(Some(0));
+		assert_eq!(groups.append(0).err(), Some(ServeError::Full));
+		reader.usage.set_max(None);
+		assert!(groups.append(0).is_ok());
+	}
+}
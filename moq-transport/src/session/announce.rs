@@ -1,17 +1,28 @@
 use std::{collections::VecDeque, ops};
 
+use crate::coding::Params;
 use crate::watch::State;
 use crate::{message, serve::ServeError};
 
-use super::{Publisher, Subscribed};
+use super::{Fetched, Publisher, Subscribed};
 
 #[derive(Debug, Clone)]
 pub struct AnnounceInfo {
 	pub namespace: String,
+
+	/// Proof that the namespace is owned by the publisher, if it was signed.
+	pub signature: Option<message::AnnounceSignature>,
+
+	/// The ID chosen by the original publisher, see [crate::serve::Tracks::broadcast_id].
+	pub broadcast_id: Option<String>,
+
+	/// The token sent by the publisher to authorize the announce, see [super::Publisher::with_token].
+	pub authorization: Option<String>,
 }
 
 struct AnnounceState {
 	subscribers: VecDeque<Subscribed>,
+	fetches: VecDeque<Fetched>,
 	ok: bool,
 	closed: Result<(), ServeError>,
 }
@@ -20,6 +31,7 @@ impl Default for AnnounceState {
 	fn default() -> Self {
 		Self {
 			subscribers: Default::default(),
+			fetches: Default::default(),
 			ok: false,
 			closed: Ok(()),
 		}
@@ -31,6 +43,10 @@ impl Drop for AnnounceState {
 		for subscriber in self.subscribers.drain(..) {
 			subscriber.close(ServeError::NotFound).ok();
 		}
+
+		for fetch in self.fetches.drain(..) {
+			fetch.close(ServeError::NotFound).ok();
+		}
 	}
 }
 
@@ -43,14 +59,24 @@ pub struct Announce {
 }
 
 impl Announce {
-	pub(super) fn new(mut publisher: Publisher, namespace: String) -> (Announce, AnnounceRecv) {
-		let info = AnnounceInfo {
-			namespace: namespace.clone(),
-		};
+	pub(super) fn new(mut publisher: Publisher, info: AnnounceInfo) -> (Announce, AnnounceRecv) {
+		// Can't fail, since they're encoded to a Vec.
+		let mut params = Params::new();
+		if let Some(signature) = &info.signature {
+			params.set(message::Announce::SIGNATURE, signature.clone()).ok();
+		}
+
+		if let Some(id) = &info.broadcast_id {
+			params.set(message::Announce::BROADCAST_ID, id.clone()).ok();
+		}
+
+		if let Some(token) = &info.authorization {
+			params.set(message::Announce::AUTHORIZATION, token.clone()).ok();
+		}
 
 		publisher.send_message(message::Announce {
-			namespace,
-			params: Default::default(),
+			namespace: info.namespace.clone(),
+			params,
 		});
 
 		let (send, recv) = State::default().split();
@@ -81,7 +107,7 @@ impl Announce {
 		}
 	}
 
-	pub async fn subscribed(&mut self) -> Result<Option<Subscribed>, ServeError> {
+	pub async fn subscribed(&self) -> Result<Option<Subscribed>, ServeError> {
 		loop {
 			{
 				let state = self.state.lock();
@@ -99,6 +125,24 @@ impl Announce {
 		}
 	}
 
+	pub async fn fetched(&self) -> Result<Option<Fetched>, ServeError> {
+		loop {
+			{
+				let state = self.state.lock();
+				if !state.fetches.is_empty() {
+					return Ok(state.into_mut().and_then(|mut state| state.fetches.pop_front()));
+				}
+
+				state.closed.clone()?;
+				match state.modified() {
+					Some(notified) => notified,
+					None => return Ok(None),
+				}
+			}
+			.await;
+		}
+	}
+
 	// Wait until an OK is received
 	pub async fn ok(&self) -> Result<(), ServeError> {
 		loop {
@@ -172,4 +216,11 @@ impl AnnounceRecv {
 
 		Ok(())
 	}
+
+	pub fn recv_fetch(&mut self, fetch: Fetched) -> Result<(), ServeError> {
+		let mut state = self.state.lock_mut().ok_or(ServeError::Done)?;
+		state.fetches.push_back(fetch);
+
+		Ok(())
+	}
 }
@@ -21,9 +21,7 @@ pub struct Announced {
 }
 
 impl Announced {
-	pub(super) fn new(session: Subscriber, namespace: String) -> (Announced, AnnouncedRecv) {
-		let info = AnnounceInfo { namespace };
-
+	pub(super) fn new(session: Subscriber, info: AnnounceInfo) -> (Announced, AnnouncedRecv) {
 		let (send, recv) = State::default().split();
 		let send = Self {
 			session,
@@ -64,6 +62,13 @@ impl Announced {
 		self.error = Some(err);
 		Ok(())
 	}
+
+	/// Tell the publisher to stop announcing the namespace, ex. when it's no longer routed.
+	///
+	/// Sends an ANNOUNCE_CANCEL with the code and reason, or an ANNOUNCE_ERROR if [Self::ok] was never called.
+	pub fn cancel(self, code: u64, reason: impl Into<String>) -> Result<(), ServeError> {
+		self.close(ServeError::Rejected(code, reason.into()))
+	}
 }
 
 impl ops::Deref for Announced {
@@ -81,6 +86,8 @@ impl Drop for Announced {
 		if self.ok {
 			self.session.send_message(message::AnnounceCancel {
 				namespace: self.namespace.clone(),
+				code: err.code(),
+				reason: err.to_string(),
 			});
 		} else {
 			self.session.send_message(message::AnnounceError {
@@ -0,0 +1,57 @@
+use futures::future::try_join_all;
+
+use crate::serve::ServeError;
+
+use super::{Subscribe, SubscribeInfo, Subscriber};
+
+/// A set of subscriptions that succeed or fail as a unit, ex. the audio and video tracks of a broadcast.
+///
+/// Dropping the bundle unsubscribes from every track, so on error the caller only needs to drop it.
+#[must_use = "unsubscribe on drop"]
+pub struct SubscribeBundle {
+	subscribes: Vec<Subscribe>,
+}
+
+impl SubscribeBundle {
+	pub(super) fn new(subscribes: Vec<Subscribe>) -> Self {
+		Self { subscribes }
+	}
+
+	/// Block until the publisher accepts every subscription, returning the first error.
+	pub async fn ready(&self) -> Result<(), ServeError> {
+		try_join_all(self.subscribes.iter().map(Subscribe::ready)).await?;
+		Ok(())
+	}
+
+	/// Block until every subscription is closed, returning the first error.
+	pub async fn closed(&self) -> Result<(), ServeError> {
+		try_join_all(self.subscribes.iter().map(Subscribe::closed)).await?;
+		Ok(())
+	}
+
+	/// The tracks in the bundle.
+	pub fn tracks(&self) -> impl Iterator<Item = &SubscribeInfo> {
+		self.subscribes.iter().map(|subscribe| &subscribe.info)
+	}
+}
+
+/// Sends the subscription messages held back by [Subscriber::batch] when dropped.
+#[must_use = "sends the batch on drop"]
+pub struct SubscriberBatch {
+	// None when nested inside another batch, which sends the messages instead.
+	subscriber: Option<Subscriber>,
+}
+
+impl SubscriberBatch {
+	pub(super) fn new(subscriber: Option<Subscriber>) -> Self {
+		Self { subscriber }
+	}
+}
+
+impl Drop for SubscriberBatch {
+	fn drop(&mut self) {
+		if let Some(mut subscriber) = self.subscriber.take() {
+			subscriber.send_batch();
+		}
+	}
+}
@@ -0,0 +1,135 @@
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The fraction of what a session sends to randomly delay, drop, or reset, for testing recovery.
+///
+/// Each fraction is between 0 and 1, and they are rolled independently in the order: drop, reset, delay.
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+	/// Delay this fraction of data streams and control messages.
+	pub delay: f64,
+
+	/// Each delay is chosen uniformly up to this duration.
+	/// Control messages are sent in order, so a delayed message also delays those behind it.
+	pub max_delay: Duration,
+
+	/// Never send this fraction of data streams, as if the group was lost.
+	pub drop: f64,
+
+	/// Reset this fraction of data streams immediately after the header is sent.
+	pub reset: f64,
+
+	/// Never send this fraction of control messages, which can leave announces and subscribes unanswered.
+	pub drop_messages: f64,
+
+	/// Seed the random choices so a test is reproducible, otherwise the current time is used.
+	pub seed: Option<u64>,
+}
+
+pub(super) enum ChaosAction {
+	Pass,
+	Delay(Duration),
+	Drop,
+	Reset,
+}
+
+/// Randomly impairs the data streams and control messages sent by a session, see [super::Session::with_chaos].
+///
+/// Clones share the same random state.
+#[derive(Debug, Clone)]
+pub struct Chaos {
+	config: Arc<ChaosConfig>,
+	rng: Arc<Mutex<u64>>,
+}
+
+impl Chaos {
+	pub fn new(config: ChaosConfig) -> Self {
+		let seed = config.seed.unwrap_or_else(|| {
+			SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map(|d| d.as_nanos() as u64)
+				.unwrap_or_default()
+		});
+
+		Self {
+			config: Arc::new(config),
+			// xorshift gets stuck at zero.
+			rng: Arc::new(Mutex::new(seed.max(1))),
+		}
+	}
+
+	// xorshift64*, which is plenty random for picking what to break.
+	fn random(&self) -> f64 {
+		let mut state = self.rng.lock().unwrap();
+		*state ^= *state >> 12;
+		*state ^= *state << 25;
+		*state ^= *state >> 27;
+
+		let value = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+		(value >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	fn roll(&self, fraction: f64) -> bool {
+		fraction > 0.0 && self.random() < fraction
+	}
+
+	fn delay(&self) -> ChaosAction {
+		if self.roll(self.config.delay) {
+			ChaosAction::Delay(self.config.max_delay.mul_f64(self.random()))
+		} else {
+			ChaosAction::Pass
+		}
+	}
+
+	pub(super) fn stream(&self) -> ChaosAction {
+		if self.roll(self.config.drop) {
+			ChaosAction::Drop
+		} else if self.roll(self.config.reset) {
+			ChaosAction::Reset
+		} else {
+			self.delay()
+		}
+	}
+
+	pub(super) fn message(&self) -> ChaosAction {
+		if self.roll(self.config.drop_messages) {
+			ChaosAction::Drop
+		} else {
+			self.delay()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn seeded() {
+		let config = ChaosConfig {
+			drop: 0.5,
+			seed: Some(42),
+			..Default::default()
+		};
+
+		let a = Chaos::new(config.clone());
+		let b = Chaos::new(config);
+
+		let mut dropped = 0;
+		for _ in 0..1000 {
+			let (x, y) = (a.random(), b.random());
+			assert_eq!(x, y);
+			assert!((0.0..1.0).contains(&x));
+
+			if matches!(a.stream(), ChaosAction::Drop) {
+				dropped += 1;
+			}
+			b.stream();
+		}
+
+		// Roughly half, without being flaky.
+		assert!((350..650).contains(&dropped), "dropped={}", dropped);
+	}
+}
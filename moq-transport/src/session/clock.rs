@@ -0,0 +1,125 @@
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::serve::{self, ServeError, TrackReaderMode};
+
+use super::{SessionError, Subscribed};
+
+/// The clock-sync track, answered by any publisher with the clock capability regardless of the namespace.
+///
+/// Each subscription receives a single group containing the publisher's wall clock, then ends.
+pub const CLOCK_TRACK: &str = ".clock";
+
+/// How often [super::Subscriber::sync_clock] takes a sample.
+pub const CLOCK_INTERVAL: Duration = Duration::from_secs(10);
+
+// The number of recent samples kept, so an old offset ages out if the clocks drift.
+const CLOCK_SAMPLES: usize = 8;
+
+/// Estimates the offset between our wall clock and the publisher's, using the clock-sync track.
+///
+/// Each sample times a subscription to [CLOCK_TRACK], assuming the publisher read its clock halfway through.
+/// The sample with the shortest round trip had the least queuing, so its offset is used.
+#[derive(Clone, Default)]
+pub struct ClockSync {
+	samples: Arc<Mutex<VecDeque<ClockSample>>>,
+}
+
+#[derive(Clone, Copy)]
+struct ClockSample {
+	rtt: u64,
+	offset: i64,
+}
+
+impl ClockSync {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Record a sample given when we subscribed, the publisher's clock, and when it arrived, all in microseconds.
+	pub fn sample(&self, sent: u64, remote: u64, received: u64) {
+		let rtt = received.saturating_sub(sent);
+		let offset = remote as i64 - (sent + rtt / 2) as i64;
+
+		let mut samples = self.samples.lock().unwrap();
+		if samples.len() == CLOCK_SAMPLES {
+			samples.pop_front();
+		}
+		samples.push_back(ClockSample { rtt, offset });
+	}
+
+	/// The publisher's clock minus ours in microseconds, or None until a sample is recorded.
+	pub fn offset(&self) -> Option<i64> {
+		let samples = self.samples.lock().unwrap();
+		samples
+			.iter()
+			.min_by_key(|sample| sample.rtt)
+			.map(|sample| sample.offset)
+	}
+
+	/// Convert our wall clock to the publisher's, returning true if the offset was known.
+	pub(super) fn remote(&self, local: u64) -> (u64, bool) {
+		match self.offset() {
+			Some(offset) => (local.saturating_add_signed(offset), true),
+			None => (local, false),
+		}
+	}
+}
+
+/// Microseconds since the UNIX epoch.
+pub(super) fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_micros() as u64)
+		.unwrap_or_default()
+}
+
+// Answer a subscription to the clock-sync track with our wall clock.
+pub(super) async fn serve_clock(subscribed: Subscribed) -> Result<(), SessionError> {
+	let (writer, reader) = serve::Track::new(subscribed.namespace.clone(), CLOCK_TRACK.to_string()).produce();
+
+	// Dropping the writer ends the track, and with it the subscription, once the group is sent.
+	writer.groups()?.append(0)?.write(now().to_string().into())?;
+
+	subscribed.serve(reader).await
+}
+
+// Read the publisher's wall clock from the clock-sync track.
+pub(super) async fn read_clock(track: serve::TrackReader) -> Result<u64, ServeError> {
+	let mut groups = match track.mode().await? {
+		TrackReaderMode::Groups(groups) => groups,
+		_ => return Err(ServeError::Mode),
+	};
+
+	let mut group = groups.next().await?.ok_or(ServeError::Done)?;
+	let payload = group.read_next().await?.ok_or(ServeError::Done)?;
+
+	std::str::from_utf8(&payload)
+		.ok()
+		.and_then(|clock| clock.parse().ok())
+		.ok_or_else(|| ServeError::Internal("invalid clock".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn offset() {
+		let clock = ClockSync::new();
+		assert_eq!(clock.offset(), None);
+		assert_eq!(clock.remote(1_000), (1_000, false));
+
+		// The publisher is 5ms ahead, read halfway through a 2ms round trip.
+		clock.sample(10_000, 16_000, 12_000);
+		assert_eq!(clock.offset(), Some(5_000));
+
+		// A sample delayed by queuing is ignored in favor of the shorter round trip.
+		clock.sample(20_000, 30_000, 30_000);
+		assert_eq!(clock.offset(), Some(5_000));
+		assert_eq!(clock.remote(1_000), (6_000, true));
+	}
+}
@@ -0,0 +1,34 @@
+use crate::message::{self, Message};
+use crate::watch::{Queue, State};
+
+/// Sends GOAWAY to the peer, asking it to reconnect elsewhere, ex. before the server shuts down.
+///
+/// Returned by [super::Session::drain] so it can be used while the session is running.
+/// The peer's session ends with [super::SessionError::Redirect] once the GOAWAY is received.
+#[derive(Clone)]
+pub struct Drain {
+	pub(super) outgoing: Queue<Message>,
+
+	// Set once GOAWAY is sent, shared with the publisher and the running session.
+	pub(super) draining: State<bool>,
+}
+
+impl Drain {
+	/// Ask the peer to reconnect to the URL, or the same URL if it's empty.
+	///
+	/// Any new SUBSCRIBE or FETCH is rejected from now on, while existing subscriptions are still served.
+	/// [super::Session::run] returns [super::SessionError::GoAway] once the drain timeout is over, closing the session
+	/// with GOAWAY_TIMEOUT if the peer hasn't left by then, see [super::Session::with_drain_timeout].
+	pub fn go_away(&mut self, url: impl Into<String>) {
+		self.outgoing.push(message::GoAway { url: url.into() }.into()).ok();
+
+		if let Some(mut draining) = self.draining.lock_mut() {
+			*draining = true;
+		}
+	}
+
+	/// Returns true once GOAWAY was sent.
+	pub fn is_draining(&self) -> bool {
+		*self.draining.lock()
+	}
+}
@@ -1,4 +1,4 @@
-use crate::{coding, serve, setup};
+use crate::{coding, serve, setup, transport};
 
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum SessionError {
@@ -11,6 +11,9 @@ pub enum SessionError {
 	#[error("webtransport read: {0}")]
 	Read(#[from] web_transport::ReadError),
 
+	#[error("mux error: {0}")]
+	Mux(#[from] transport::mux::MuxError),
+
 	#[error("encode error: {0}")]
 	Encode(#[from] coding::EncodeError),
 
@@ -40,11 +43,39 @@ pub enum SessionError {
 	#[error("internal error")]
 	Internal,
 
+	/// The peer was not allowed to perform the requested action.
+	#[error("unauthorized")]
+	Unauthorized,
+
+	/// The session is being drained, ex. the server is shutting down.
+	#[error("going away")]
+	GoAway,
+
+	/// The peer sent a GOAWAY, asking us to reconnect to this URL.
+	#[error("redirected: {0}")]
+	Redirect(String),
+
 	#[error("serve error: {0}")]
 	Serve(#[from] serve::ServeError),
 
 	#[error("wrong size")]
 	WrongSize,
+
+	/// An object arrived further out of order than the reordering window allows.
+	#[error("out of order: expected={0} received={1}")]
+	OutOfOrder(u64, u64),
+
+	/// The peer opened more concurrent streams than we're willing to serve.
+	#[error("too many streams: max={0}")]
+	TooManyStreams(usize),
+
+	/// An object took longer to deliver than the subscriber's DELIVERY_TIMEOUT allows.
+	#[error("delivery timeout: {0:?}")]
+	DeliveryTimeout(std::time::Duration),
+
+	/// Nothing was received on the control stream for this long, despite sending PING, see [super::Session::with_keepalive].
+	#[error("keepalive timeout: {0:?}")]
+	Timeout(std::time::Duration),
 }
 
 impl SessionError {
@@ -56,16 +87,152 @@ impl SessionError {
 			Self::Session(_) => 503,
 			Self::Read(_) => 500,
 			Self::Write(_) => 500,
+			Self::Mux(_) => 500,
 			Self::Version(..) => 406,
 			Self::Decode(_) => 400,
 			Self::Encode(_) => 500,
 			Self::BoundsExceeded(_) => 500,
 			Self::Duplicate => 409,
 			Self::Internal => 500,
+			Self::Unauthorized => 401,
+			Self::GoAway => 503,
+			Self::Redirect(_) => 307,
 			Self::WrongSize => 400,
+			Self::OutOfOrder(..) => 400,
+			Self::TooManyStreams(_) => 429,
+			Self::DeliveryTimeout(_) => 408,
+			Self::Timeout(_) => 408,
 			Self::Serve(err) => err.code(),
 		}
 	}
+
+	/// The code used to close the session, as defined by the error registry.
+	pub fn close_code(&self) -> CloseCode {
+		match self {
+			Self::Unauthorized => CloseCode::Unauthorized,
+			Self::GoAway => CloseCode::GoawayTimeout,
+			Self::Redirect(_) => CloseCode::NoError,
+			Self::RoleIncompatible(..)
+			| Self::RoleViolation
+			| Self::Version(..)
+			| Self::Decode(_)
+			| Self::Duplicate
+			| Self::WrongSize
+			| Self::OutOfOrder(..)
+			| Self::TooManyStreams(_) => CloseCode::ProtocolViolation,
+			Self::Session(_)
+			| Self::Read(_)
+			| Self::Write(_)
+			| Self::Mux(_)
+			| Self::Encode(_)
+			| Self::BoundsExceeded(_)
+			| Self::Internal
+			| Self::DeliveryTimeout(_)
+			| Self::Timeout(_)
+			| Self::Serve(_) => CloseCode::Internal,
+		}
+	}
+
+	/// Returns true if either side closed the session because the other violated the protocol.
+	pub fn is_protocol_violation(&self) -> bool {
+		let code = match self.peer_closed() {
+			Some((code, _)) => code,
+			None => self.close_code(),
+		};
+
+		code == CloseCode::ProtocolViolation
+	}
+
+	/// Returns the code and reason provided by the peer if it closed the session.
+	pub fn peer_closed(&self) -> Option<(CloseCode, String)> {
+		if let Self::Mux(transport::mux::MuxError::Closed(code, reason)) = self {
+			return Some(((*code).into(), reason.clone()));
+		}
+
+		// web-transport doesn't expose the close frame, only the message of the underlying connection error.
+		let mut err: Option<&dyn std::error::Error> = Some(self);
+		while let Some(inner) = err {
+			if let Some((code, reason)) = parse_peer_closed(&inner.to_string()) {
+				return Some((code.into(), reason));
+			}
+			err = inner.source();
+		}
+
+		None
+	}
+}
+
+// Parses "closed by peer: <reason> (code <code>)", or "closed by peer: <code>" without a reason.
+fn parse_peer_closed(message: &str) -> Option<(u32, String)> {
+	let close = message.strip_prefix("closed by peer: ")?;
+
+	let (reason, code) = match close.strip_suffix(')').and_then(|close| close.rsplit_once(" (code ")) {
+		Some((reason, code)) => (reason, code),
+		None => ("", close),
+	};
+
+	let code: u64 = code.parse().ok()?;
+
+	// WebTransport maps the code into the HTTP/3 error space, while raw QUIC uses it as-is.
+	let code = error_from_http3(code).unwrap_or(code as u32);
+
+	Some((code, reason.to_string()))
+}
+
+const HTTP3_ERROR_FIRST: u64 = 0x52e4a40fa8db;
+const HTTP3_ERROR_LAST: u64 = 0x52e5ac983162;
+
+// The inverse of the WebTransport mapping, which skips every reserved 0x1f * N + 0x21 codepoint.
+fn error_from_http3(code: u64) -> Option<u32> {
+	if !(HTTP3_ERROR_FIRST..=HTTP3_ERROR_LAST).contains(&code) || (code - 0x21).is_multiple_of(0x1f) {
+		return None;
+	}
+
+	let shifted = code - HTTP3_ERROR_FIRST;
+	(shifted - shifted / 0x1f).try_into().ok()
+}
+
+/// Codes used to terminate the session, as defined in the draft's error registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCode {
+	NoError,
+	Internal,
+	Unauthorized,
+	ProtocolViolation,
+	DuplicateTrackAlias,
+	ParameterLengthMismatch,
+	GoawayTimeout,
+	Unknown(u32),
+}
+
+impl From<CloseCode> for u32 {
+	fn from(code: CloseCode) -> Self {
+		match code {
+			CloseCode::NoError => 0x0,
+			CloseCode::Internal => 0x1,
+			CloseCode::Unauthorized => 0x2,
+			CloseCode::ProtocolViolation => 0x3,
+			CloseCode::DuplicateTrackAlias => 0x4,
+			CloseCode::ParameterLengthMismatch => 0x5,
+			CloseCode::GoawayTimeout => 0x10,
+			CloseCode::Unknown(code) => code,
+		}
+	}
+}
+
+impl From<u32> for CloseCode {
+	fn from(code: u32) -> Self {
+		match code {
+			0x0 => Self::NoError,
+			0x1 => Self::Internal,
+			0x2 => Self::Unauthorized,
+			0x3 => Self::ProtocolViolation,
+			0x4 => Self::DuplicateTrackAlias,
+			0x5 => Self::ParameterLengthMismatch,
+			0x10 => Self::GoawayTimeout,
+			code => Self::Unknown(code),
+		}
+	}
 }
 
 impl From<SessionError> for serve::ServeError {
@@ -76,3 +243,35 @@ impl From<SessionError> for serve::ServeError {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn peer_closed_message() {
+		assert_eq!(parse_peer_closed("closed by peer: 3"), Some((3, String::new())));
+		assert_eq!(
+			parse_peer_closed("closed by peer: bad (code 1) (code 2)"),
+			Some((2, "bad (code 1)".to_string()))
+		);
+		assert_eq!(parse_peer_closed("timed out"), None);
+
+		// WebTransport codes are mapped back out of the HTTP/3 error space.
+		let message = format!("closed by peer: oops (code {})", HTTP3_ERROR_FIRST + 0x21);
+		assert_eq!(parse_peer_closed(&message), Some((0x20, "oops".to_string())));
+	}
+
+	#[test]
+	fn http3_codes() {
+		for code in [0, 1, 0x1d, 0x1e, 0x1f, 0x3c, 0x3d, 1000, u32::MAX] {
+			let http3 = HTTP3_ERROR_FIRST + code as u64 + code as u64 / 0x1e;
+			assert_eq!(error_from_http3(http3), Some(code));
+		}
+
+		// Reserved codepoints and codes outside the range are not WebTransport codes.
+		assert_eq!(error_from_http3(HTTP3_ERROR_FIRST + 0x1e), None);
+		assert_eq!(error_from_http3(HTTP3_ERROR_FIRST - 1), None);
+		assert_eq!(error_from_http3(HTTP3_ERROR_LAST + 1), None);
+	}
+}
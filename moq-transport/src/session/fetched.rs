@@ -0,0 +1,221 @@
+use std::ops;
+
+use crate::serve::{ServeError, TrackName, TrackReaderMode, BOOTSTRAP_PRIORITY};
+use crate::watch::State;
+use crate::{data, message, serve};
+
+use super::{supervise, Publisher, SessionError, SubscribeInfo};
+
+#[derive(Debug)]
+struct FetchedState {
+	closed: Result<(), ServeError>,
+}
+
+impl Default for FetchedState {
+	fn default() -> Self {
+		Self { closed: Ok(()) }
+	}
+}
+
+/// A request for past groups of a track, answered from whatever is cached.
+///
+/// Unlike [super::Subscribed], the fetch is finished once the cached groups have been sent.
+pub struct Fetched {
+	publisher: Publisher,
+	state: State<FetchedState>,
+	msg: message::Fetch,
+	done: bool,
+
+	pub info: SubscribeInfo,
+}
+
+impl Fetched {
+	pub(super) fn new(publisher: Publisher, msg: message::Fetch) -> (Self, FetchedRecv) {
+		let (send, recv) = State::default().split();
+		let info = SubscribeInfo {
+			namespace: msg.track_namespace.clone(),
+			name: msg.track_name.clone(),
+		};
+
+		let send = Self {
+			publisher,
+			state: send,
+			msg,
+			info,
+			done: false,
+		};
+
+		let recv = FetchedRecv { state: recv };
+
+		(send, recv)
+	}
+
+	/// The first group requested by the subscriber.
+	pub fn start_group(&self) -> u64 {
+		self.msg.start_group
+	}
+
+	/// The last group requested by the subscriber, or None for everything up to the latest group.
+	pub fn end_group(&self) -> Option<u64> {
+		self.msg.end_group
+	}
+
+	/// The token sent by the subscriber to authorize this fetch.
+	pub fn authorization(&self) -> Option<String> {
+		self.msg
+			.params
+			.clone()
+			.get(message::Subscribe::AUTHORIZATION)
+			.ok()
+			.flatten()
+	}
+
+	/// Send the cached groups within the requested range, returning once they've all been written.
+	///
+	/// Only tracks delivered as groups can be fetched, since other modes aren't cached.
+	pub async fn serve(mut self, track: serve::TrackReader) -> Result<(), SessionError> {
+		// A panic only closes this fetch, reporting the message to the subscriber.
+		let res = supervise(self.serve_inner(track)).await;
+		if let Err(err) = &res {
+			self.close(err.clone().into())?;
+		}
+
+		res
+	}
+
+	async fn serve_inner(&mut self, track: serve::TrackReader) -> Result<(), SessionError> {
+		let groups = match track.mode().await? {
+			TrackReaderMode::Groups(groups) => groups,
+			_ => return Err(ServeError::Unsupported("fetch".to_string()).into()),
+		};
+
+		let cached = groups.cached(self.msg.start_group, self.msg.end_group);
+
+		self.publisher.send_message(message::FetchOk {
+			id: self.msg.id,
+			latest: groups.latest(),
+		});
+
+		// Every group is sent on a single stream, finished once the final group is complete.
+		let priority =
+			match self.publisher.bootstrap_priority() && TrackName::parse(&self.msg.track_name).is_bootstrap() {
+				true => BOOTSTRAP_PRIORITY,
+				false => cached.first().map(|group| group.priority).unwrap_or_default(),
+			};
+		let header: data::Header = data::TrackHeader {
+			subscribe_id: self.msg.id,
+			track_alias: self.msg.id,
+			send_order: priority,
+		}
+		.into();
+
+		let mut writer = match self.publisher.open_stream(priority, &header).await? {
+			Some(writer) => writer,
+			None => return Ok(()),
+		};
+
+		crate::sampled!(log::Level::Trace, "sent fetch header", "{:?}", header);
+
+		for mut group in cached {
+			loop {
+				// The latest group may still be written, so stop waiting if the fetch is cancelled.
+				let mut object = tokio::select! {
+					res = group.next() => match res? {
+						Some(object) => object,
+						None => break,
+					},
+					res = self.closed() => return Ok(res?),
+				};
+
+				let header = data::TrackObject {
+					group_id: group.group_id,
+					object_id: object.object_id,
+					size: object.size,
+				};
+
+				writer.encode(&header).await?;
+
+				crate::sampled!(log::Level::Trace, "sent fetch object", "{:?}", header);
+
+				while let Some(chunk) = object.read().await? {
+					writer.write(&chunk).await?;
+					crate::sampled!(log::Level::Trace, "sent fetch payload", "{:?}", chunk.len());
+				}
+			}
+		}
+
+		self.done = true;
+
+		Ok(())
+	}
+
+	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		state.closed.clone()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Done)?;
+		state.closed = Err(err);
+
+		Ok(())
+	}
+
+	/// Resolves when the fetch is cancelled by the subscriber.
+	pub async fn closed(&self) -> Result<(), ServeError> {
+		loop {
+			{
+				let state = self.state.lock();
+				state.closed.clone()?;
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Ok(()),
+				}
+			}
+			.await;
+		}
+	}
+}
+
+impl ops::Deref for Fetched {
+	type Target = SubscribeInfo;
+
+	fn deref(&self) -> &Self::Target {
+		&self.info
+	}
+}
+
+impl Drop for Fetched {
+	fn drop(&mut self) {
+		let err = self.state.lock().closed.as_ref().err().cloned();
+
+		if self.done {
+			self.publisher.drop_fetch(self.msg.id);
+			return;
+		}
+
+		// Also sent after FETCH_OK, ex. when the fetch was cancelled or the track errored partway through.
+		let err = err.unwrap_or(ServeError::Done);
+		self.publisher.send_message(message::FetchError {
+			id: self.msg.id,
+			code: err.code(),
+			reason: err.to_string(),
+		});
+	}
+}
+
+pub(super) struct FetchedRecv {
+	state: State<FetchedState>,
+}
+
+impl FetchedRecv {
+	pub fn recv_cancel(&mut self) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		state.closed.clone()?;
+
+		if let Some(mut state) = state.into_mut() {
+			state.closed = Err(ServeError::Cancel);
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,163 @@
+use std::{
+	collections::VecDeque,
+	fmt,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+// Longer descriptions are truncated, since some messages carry large parameters.
+const MAX_DETAIL: usize = 256;
+
+/// A bounded record of the latest control messages and stream events of a session, for diagnosing failures.
+///
+/// Messages and stream events are kept in separate rings, so a burst of streams doesn't evict the messages.
+/// The history is logged when the session fails with a protocol violation, and can be read with [Self::events].
+#[derive(Clone)]
+pub struct History {
+	state: Arc<Mutex<HistoryState>>,
+}
+
+struct HistoryState {
+	start: Instant,
+	capacity: usize,
+	messages: VecDeque<HistoryEvent>,
+	streams: VecDeque<HistoryEvent>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryEvent {
+	/// The time since the session was created.
+	pub elapsed: Duration,
+	pub kind: HistoryKind,
+	pub detail: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryKind {
+	/// A control message sent to the peer.
+	Sent,
+
+	/// A control message received from the peer.
+	Received,
+
+	/// A data stream received from the peer, or why it failed.
+	Stream,
+}
+
+impl fmt::Display for HistoryKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Sent => write!(f, "sent"),
+			Self::Received => write!(f, "recv"),
+			Self::Stream => write!(f, "stream"),
+		}
+	}
+}
+
+impl fmt::Display for HistoryEvent {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"+{}.{:03}s {} {}",
+			self.elapsed.as_secs(),
+			self.elapsed.subsec_millis(),
+			self.kind,
+			self.detail
+		)
+	}
+}
+
+impl History {
+	/// The default number of messages, and separately stream events, that are kept.
+	pub const CAPACITY: usize = 32;
+
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			state: Arc::new(Mutex::new(HistoryState {
+				start: Instant::now(),
+				capacity,
+				messages: VecDeque::with_capacity(capacity),
+				streams: VecDeque::with_capacity(capacity),
+			})),
+		}
+	}
+
+	pub(super) fn set_capacity(&self, capacity: usize) {
+		let mut state = self.state.lock().unwrap();
+		state.capacity = capacity;
+
+		let HistoryState { messages, streams, .. } = &mut *state;
+		for events in [messages, streams] {
+			while events.len() > capacity {
+				events.pop_front();
+			}
+		}
+	}
+
+	// The detail is only formatted if it's going to be kept.
+	pub(super) fn record<F: FnOnce() -> String>(&self, kind: HistoryKind, detail: F) {
+		let mut state = self.state.lock().unwrap();
+		if state.capacity == 0 {
+			return;
+		}
+
+		let mut detail = detail();
+		if detail.len() > MAX_DETAIL {
+			let mut end = MAX_DETAIL;
+			while !detail.is_char_boundary(end) {
+				end -= 1;
+			}
+
+			detail.truncate(end);
+			detail.push_str("...");
+		}
+
+		let event = HistoryEvent {
+			elapsed: state.start.elapsed(),
+			kind,
+			detail,
+		};
+
+		let capacity = state.capacity;
+		let events = match kind {
+			HistoryKind::Stream => &mut state.streams,
+			_ => &mut state.messages,
+		};
+
+		if events.len() >= capacity {
+			events.pop_front();
+		}
+		events.push_back(event);
+	}
+
+	/// The recorded messages and stream events, oldest first.
+	pub fn events(&self) -> Vec<HistoryEvent> {
+		let state = self.state.lock().unwrap();
+		let mut events: Vec<_> = state.messages.iter().chain(state.streams.iter()).cloned().collect();
+		events.sort_by_key(|event| event.elapsed);
+		events
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bounded() {
+		let history = History::new(2);
+		for i in 0..3 {
+			history.record(HistoryKind::Sent, || format!("message {}", i));
+		}
+		history.record(HistoryKind::Stream, || "x".repeat(1000));
+
+		let events = history.events();
+		let details: Vec<_> = events.iter().map(|event| &event.detail[..9]).collect();
+		assert_eq!(details, ["message 1", "message 2", "xxxxxxxxx"]);
+		assert_eq!(events[2].detail.len(), MAX_DETAIL + 3);
+
+		history.set_capacity(0);
+		history.record(HistoryKind::Received, || unreachable!());
+		assert!(history.events().is_empty());
+	}
+}
@@ -1,33 +1,59 @@
 mod announce;
 mod announced;
+mod bundle;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod clock;
+mod drain;
 mod error;
+mod fetched;
+mod history;
+mod options;
+mod priority;
 mod publisher;
+mod raw;
 mod reader;
 mod subscribe;
 mod subscribed;
 mod subscriber;
+mod supervise;
 mod writer;
 
 pub use announce::*;
 pub use announced::*;
+pub use bundle::*;
+#[cfg(feature = "chaos")]
+pub use chaos::*;
+pub use clock::*;
+pub use drain::*;
 pub use error::*;
+pub use fetched::*;
+pub use history::*;
+pub use options::*;
+pub use priority::*;
 pub use publisher::*;
+pub use raw::*;
 pub use subscribe::*;
 pub use subscribed::*;
 pub use subscriber::*;
+pub use supervise::panics;
 
 use reader::*;
+use supervise::*;
 use writer::*;
 
+use std::time::Duration;
+
 use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::time::Instant;
 
 use crate::message::Message;
-use crate::watch::Queue;
-use crate::{message, setup};
+use crate::watch::{Queue, State};
+use crate::{message, setup, transport};
 
 #[must_use = "run() must be called"]
 pub struct Session {
-	webtransport: web_transport::Session,
+	transport: transport::Session,
 
 	sender: Writer,
 	recver: Reader,
@@ -36,52 +62,153 @@ pub struct Session {
 	subscriber: Option<Subscriber>,
 
 	outgoing: Queue<Message>,
+
+	// The negotiated version, which decides the wire format of some messages.
+	version: setup::Version,
+
+	// The negotiated capabilities, which decide whether PING may be sent.
+	capabilities: setup::Capabilities,
+
+	// Send PING this often and fail if the peer goes quiet, see with_keepalive.
+	keepalive: Option<Duration>,
+
+	// The token sent by the client during SETUP, if we're the server.
+	token: Option<setup::AuthToken>,
+
+	// The stickiness token sent by the peer during SETUP.
+	sticky: Option<setup::StickyToken>,
+
+	// The path sent by the client during SETUP, if we're the server.
+	path: Option<setup::Path>,
+
+	// The maximum number of incoming streams served concurrently.
+	max_streams: usize,
+
+	// Set once GOAWAY is sent, after which the session ends when the drain timeout is over.
+	draining: State<bool>,
+	drain_timeout: Duration,
+
+	// The latest messages and stream events, logged if the session fails with a protocol violation.
+	history: History,
+
+	#[cfg(feature = "chaos")]
+	chaos: Option<Chaos>,
 }
 
 impl Session {
+	/// The default maximum number of incoming streams served concurrently.
+	pub const MAX_STREAMS: usize = 1024;
+
+	/// The default time to keep serving after GOAWAY, before closing the session with GOAWAY_TIMEOUT.
+	pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+	/// The number of keepalive intervals without receiving anything before [SessionError::Timeout].
+	pub const KEEPALIVE_MISSED: u32 = 3;
+
 	fn new(
-		webtransport: web_transport::Session,
+		transport: transport::Session,
 		sender: Writer,
 		recver: Reader,
 		role: setup::Role,
+		version: setup::Version,
+		capabilities: setup::Capabilities,
+		token: Option<setup::AuthToken>,
 	) -> (Self, Option<Publisher>, Option<Subscriber>) {
 		let outgoing = Queue::default().split();
+		let draining = State::new(false);
 		let publisher = role
 			.is_publisher()
-			.then(|| Publisher::new(outgoing.0.clone(), webtransport.clone()));
-		let subscriber = role.is_subscriber().then(|| Subscriber::new(outgoing.0));
+			.then(|| Publisher::new(outgoing.0.clone(), transport.clone(), capabilities, draining.clone()));
+		let subscriber = role.is_subscriber().then(|| Subscriber::new(outgoing.0, capabilities));
 
 		let session = Self {
-			webtransport,
+			transport,
 			sender,
 			recver,
 			publisher: publisher.clone(),
 			subscriber: subscriber.clone(),
 			outgoing: outgoing.1,
+			version,
+			capabilities,
+			keepalive: None,
+			token,
+			sticky: None,
+			path: None,
+			max_streams: Self::MAX_STREAMS,
+			draining,
+			drain_timeout: Self::DRAIN_TIMEOUT,
+			history: History::new(History::CAPACITY),
+			#[cfg(feature = "chaos")]
+			chaos: None,
 		};
 
 		(session, publisher, subscriber)
 	}
 
-	pub async fn connect(session: web_transport::Session) -> Result<(Session, Publisher, Subscriber), SessionError> {
-		Self::connect_role(session, setup::Role::Both)
+	pub async fn connect(
+		session: impl Into<transport::Session>,
+	) -> Result<(Session, Publisher, Subscriber), SessionError> {
+		Self::connect_role(session.into(), setup::Role::Both)
 			.await
 			.map(|(session, publisher, subscriber)| (session, publisher.unwrap(), subscriber.unwrap()))
 	}
 
 	pub async fn connect_role(
-		mut session: web_transport::Session,
+		session: impl Into<transport::Session>,
+		role: setup::Role,
+	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		Self::connect_versions(session, role, setup::Versions::supported()).await
+	}
+
+	/// Connect offering only the provided versions, ex. to interoperate with a peer on an older draft.
+	///
+	/// The server chooses the highest common version; any not in [setup::Versions::implemented] are ignored.
+	pub async fn connect_versions(
+		session: impl Into<transport::Session>,
 		role: setup::Role,
+		versions: setup::Versions,
+	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		Self::connect_with(session, SetupOptions::new(role).with_versions(versions)).await
+	}
+
+	/// Connect using the provided options, ex. to send a token with [SetupOptions::with_token].
+	pub async fn connect_with(
+		session: impl Into<transport::Session>,
+		options: SetupOptions,
+	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		let session = session.into();
+		match Self::connect_setup(session.clone(), options).await {
+			Ok(res) => Ok(res),
+			Err(err) => Err(Self::close(session, err)),
+		}
+	}
+
+	async fn connect_setup(
+		mut session: transport::Session,
+		options: SetupOptions,
 	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		let role = options.role;
+
 		let control = session.open_bi().await?;
 		let mut sender = Writer::new(control.0);
 		let mut recver = Reader::new(control.1);
 
-		let versions: setup::Versions = [setup::Version::DRAFT_03].into();
+		let implemented = setup::Versions::implemented();
+		let versions: setup::Versions = options
+			.versions
+			.iter()
+			.filter(|v| implemented.contains(v))
+			.copied()
+			.collect::<Vec<_>>()
+			.into();
 
 		let client = setup::Client {
 			role,
 			versions: versions.clone(),
+			capabilities: setup::Capabilities::supported(),
+			token: options.token,
+			sticky: options.sticky,
+			path: options.path,
 			params: Default::default(),
 		};
 
@@ -91,6 +218,10 @@ impl Session {
 		let server: setup::Server = recver.decode().await?;
 		log::debug!("received server SETUP: {:?}", server);
 
+		if !versions.contains(&server.version) {
+			return Err(SessionError::Version(versions, [server.version].into()));
+		}
+
 		// Downgrade our role based on the server's role.
 		let role = match server.role {
 			setup::Role::Both => role,
@@ -106,19 +237,63 @@ impl Session {
 			},
 		};
 
-		Ok(Session::new(session, sender, recver, role))
+		// The server echoes the shared subset, but intersect again in case it echoed something unexpected.
+		let capabilities = client.capabilities.intersect(&server.capabilities);
+		log::debug!(
+			"negotiated version: {} capabilities: {:?}",
+			server.version,
+			capabilities
+		);
+
+		let (mut session, publisher, subscriber) =
+			Session::new(session, sender, recver, role, server.version, capabilities, None);
+		session.sticky = server.sticky;
+
+		Ok((session, publisher, subscriber))
 	}
 
 	pub async fn accept(
-		session: web_transport::Session,
+		session: impl Into<transport::Session>,
 	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
-		Self::accept_role(session, setup::Role::Both).await
+		Self::accept_role(session.into(), setup::Role::Both).await
 	}
 
 	pub async fn accept_role(
-		mut session: web_transport::Session,
+		session: impl Into<transport::Session>,
+		role: setup::Role,
+	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		Self::accept_versions(session, role, setup::Versions::supported()).await
+	}
+
+	/// Accept only the provided versions, choosing the highest one also offered by the client.
+	///
+	/// Any versions not in [setup::Versions::implemented] are ignored.
+	pub async fn accept_versions(
+		session: impl Into<transport::Session>,
 		role: setup::Role,
+		versions: setup::Versions,
+	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		Self::accept_with(session, SetupOptions::new(role).with_versions(versions)).await
+	}
+
+	/// Accept using the provided options, ex. to check the client's token with [SetupOptions::with_authorize].
+	pub async fn accept_with(
+		session: impl Into<transport::Session>,
+		options: SetupOptions,
+	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		let session = session.into();
+		match Self::accept_setup(session.clone(), options).await {
+			Ok(res) => Ok(res),
+			Err(err) => Err(Self::close(session, err)),
+		}
+	}
+
+	async fn accept_setup(
+		mut session: transport::Session,
+		options: SetupOptions,
 	) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
+		let role = options.role;
+
 		let control = session.accept_bi().await?;
 		let mut sender = Writer::new(control.0);
 		let mut recver = Reader::new(control.1);
@@ -126,13 +301,25 @@ impl Session {
 		let client: setup::Client = recver.decode().await?;
 		log::debug!("received client SETUP: {:?}", client);
 
-		if !client.versions.contains(&setup::Version::DRAFT_03) {
-			return Err(SessionError::Version(
-				client.versions,
-				[setup::Version::DRAFT_03].into(),
-			));
+		if let Err(err) = options.authorize(client.token.clone()).await {
+			log::info!("rejected client SETUP: {}", err);
+			return Err(SessionError::Unauthorized);
 		}
 
+		let implemented = setup::Versions::implemented();
+		let versions: setup::Versions = options
+			.versions
+			.iter()
+			.filter(|v| implemented.contains(v))
+			.copied()
+			.collect::<Vec<_>>()
+			.into();
+
+		let version = match versions.negotiate(&client.versions) {
+			Some(version) => version,
+			None => return Err(SessionError::Version(client.versions, versions)),
+		};
+
 		// Downgrade our role based on the client's role.
 		let role = match client.role {
 			setup::Role::Both => role,
@@ -148,31 +335,223 @@ impl Session {
 			},
 		};
 
+		// Echo the capabilities supported by both sides.
+		let capabilities = setup::Capabilities::supported().intersect(&client.capabilities);
+		log::debug!("negotiated version: {} capabilities: {:?}", version, capabilities);
+
 		let server = setup::Server {
 			role,
-			version: setup::Version::DRAFT_03,
+			version,
+			capabilities,
+			sticky: options.sticky.clone(),
 			params: Default::default(),
 		};
 
 		log::debug!("sending server SETUP: {:?}", server);
 		sender.encode(&server).await?;
 
-		Ok(Session::new(session, sender, recver, role))
+		let (mut session, publisher, subscriber) =
+			Session::new(session, sender, recver, role, version, capabilities, client.token);
+		session.sticky = client.sticky;
+		session.path = client.path;
+
+		Ok((session, publisher, subscriber))
+	}
+
+	/// The version negotiated during the setup.
+	pub fn version(&self) -> setup::Version {
+		self.version
+	}
+
+	/// The token sent by the client during SETUP, if we accepted the session.
+	pub fn token(&self) -> Option<&setup::AuthToken> {
+		self.token.as_ref()
+	}
+
+	/// The stickiness token sent by the peer during SETUP, identifying the server or the relay the client was sent to.
+	pub fn sticky(&self) -> Option<&setup::StickyToken> {
+		self.sticky.as_ref()
+	}
+
+	/// The path sent by the client during SETUP over raw QUIC or TCP, if we accepted the session.
+	pub fn path(&self) -> Option<&setup::Path> {
+		self.path.as_ref()
+	}
+
+	/// A handle used to send GOAWAY once the session is running, see [Drain].
+	pub fn drain(&self) -> Drain {
+		Drain {
+			outgoing: self.outgoing.clone(),
+			draining: self.draining.clone(),
+		}
+	}
+
+	/// Send GOAWAY with the URL of the new session as soon as the session runs, see [Drain::go_away].
+	///
+	/// Use [Self::drain] to send it while the session is already running.
+	pub fn go_away(&self, url: &str) {
+		self.drain().go_away(url);
+	}
+
+	/// Keep serving existing subscriptions for this long after GOAWAY, giving the peer time to reconnect elsewhere.
+	///
+	/// Defaults to [Self::DRAIN_TIMEOUT].
+	pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+		self.drain_timeout = timeout;
+		self
+	}
+
+	/// Send PING every interval, failing with [SessionError::Timeout] if nothing is received on the control stream
+	/// for [Self::KEEPALIVE_MISSED] intervals, instead of waiting minutes for the QUIC idle timeout.
+	///
+	/// The peer answers with PONG even without a keepalive of its own.
+	/// Ignored unless the keepalive capability was negotiated.
+	pub fn with_keepalive(mut self, interval: Duration) -> Self {
+		self.keepalive = Some(interval);
+		self
+	}
+
+	/// A handle used to read the latest messages and stream events, ex. to report why the session failed.
+	pub fn history(&self) -> History {
+		self.history.clone()
+	}
+
+	/// Keep this many of the latest messages, and separately stream events, or none if zero.
+	///
+	/// Defaults to [History::CAPACITY].
+	pub fn with_history(self, capacity: usize) -> Self {
+		self.history.set_capacity(capacity);
+		self
+	}
+
+	/// Limit the number of incoming streams served concurrently, protecting memory from a misbehaving peer.
+	///
+	/// Any streams over the limit are stopped with [SessionError::TooManyStreams].
+	pub fn with_max_streams(mut self, max: usize) -> Self {
+		self.max_streams = max;
+		self
+	}
+
+	/// Randomly delay, drop, or reset the data streams and control messages sent by this session.
+	///
+	/// This is only for testing that the peer recovers, and nothing received is affected.
+	#[cfg(feature = "chaos")]
+	pub fn with_chaos(mut self, chaos: Chaos) -> Self {
+		if let Some(publisher) = &self.publisher {
+			publisher.set_chaos(chaos.clone());
+		}
+
+		self.chaos = Some(chaos);
+		self
+	}
+
+	/// Take over the control stream instead of running the session, for conformance testing.
+	///
+	/// The [Publisher] and [Subscriber] returned by the setup stop working, since nothing dispatches their messages.
+	pub fn into_raw(self) -> RawSession {
+		let capabilities = match (&self.publisher, &self.subscriber) {
+			(Some(publisher), _) => publisher.capabilities(),
+			(_, Some(subscriber)) => subscriber.capabilities(),
+			_ => Default::default(),
+		};
+
+		RawSession::new(self.transport, self.sender, self.recver, self.version, capabilities)
 	}
 
 	pub async fn run(self) -> Result<(), SessionError> {
-		tokio::select! {
-			res = Self::run_recv(self.recver, self.publisher, self.subscriber.clone()) => res,
-			res = Self::run_send(self.sender, self.outgoing) => res,
-			res = Self::run_streams(self.webtransport.clone(), self.subscriber.clone()) => res,
-			res = Self::run_datagrams(self.webtransport, self.subscriber) => res,
+		let transport = self.transport.clone();
+
+		let history = self.history;
+
+		// Updated whenever a control message arrives, so the keepalive knows the peer is alive.
+		let received = State::new(Instant::now());
+		let keepalive = match self.keepalive {
+			Some(_) if !self.capabilities.keepalive => {
+				log::warn!("keepalive not supported by peer");
+				None
+			}
+			keepalive => keepalive,
+		};
+		let keepalive = Self::run_keepalive(self.outgoing.clone(), received.clone(), keepalive);
+		let recv = Self::run_recv(
+			self.recver,
+			self.publisher,
+			self.subscriber.clone(),
+			self.outgoing.clone(),
+			received,
+			self.version,
+			history.clone(),
+		);
+
+		#[cfg(feature = "chaos")]
+		let send = Self::run_send(self.sender, self.outgoing, self.version, history.clone(), self.chaos);
+		#[cfg(not(feature = "chaos"))]
+		let send = Self::run_send(self.sender, self.outgoing, self.version, history.clone());
+
+		let res = tokio::select! {
+			res = recv => res,
+			res = send => res,
+			res = keepalive => res,
+			res = Self::run_streams(self.transport.clone(), self.subscriber.clone(), self.max_streams, history.clone()) => res,
+			res = Self::run_datagrams(self.transport, self.subscriber.clone()) => res,
+			res = Self::run_renewals(self.subscriber) => res,
+			res = Self::run_drain(self.draining, self.drain_timeout) => res,
+		};
+
+		res.map_err(|err| {
+			Self::log_history(&history, &err);
+			Self::close(transport, err)
+		})
+	}
+
+	// Log the latest events of a session that failed with a protocol violation, since it's likely a bug on one side.
+	fn log_history(history: &History, err: &SessionError) {
+		if !err.is_protocol_violation() {
+			return;
+		}
+
+		let events = history.events();
+		let lines: String = events.iter().map(|event| format!("\n  {}", event)).collect();
+		log::warn!("session failed: {}, last {} events:{}", err, events.len(), lines);
+	}
+
+	// Close the session with the error's code, unless the peer already closed it.
+	fn close(transport: transport::Session, err: SessionError) -> SessionError {
+		if let Some((code, reason)) = err.peer_closed() {
+			log::info!("session closed by peer: code={:?} reason={}", code, reason);
+			return err;
 		}
+
+		let code = err.close_code();
+		let reason = err.to_string();
+
+		log::info!("closing session: code={:?} reason={}", code, reason);
+		transport.close(code.into(), &reason);
+
+		err
 	}
 
-	async fn run_send(mut sender: Writer, mut outgoing: Queue<message::Message>) -> Result<(), SessionError> {
+	async fn run_send(
+		mut sender: Writer,
+		mut outgoing: Queue<message::Message>,
+		version: setup::Version,
+		history: History,
+		#[cfg(feature = "chaos")] chaos: Option<Chaos>,
+	) -> Result<(), SessionError> {
 		while let Some(msg) = outgoing.pop().await {
+			#[cfg(feature = "chaos")]
+			match chaos.as_ref().map(|chaos| chaos.message()) {
+				Some(ChaosAction::Drop) => {
+					log::debug!("chaos dropped message: {:?}", msg);
+					continue;
+				}
+				Some(ChaosAction::Delay(delay)) => tokio::time::sleep(delay).await,
+				_ => {}
+			}
+
 			log::debug!("sending message: {:?}", msg);
-			sender.encode(&msg).await?;
+			history.record(HistoryKind::Sent, || format!("{:?}", msg));
+			sender.encode_with(|buffer| msg.encode_version(buffer, version)).await?;
 		}
 
 		Ok(())
@@ -182,10 +561,21 @@ impl Session {
 		mut recver: Reader,
 		mut publisher: Option<Publisher>,
 		mut subscriber: Option<Subscriber>,
+		mut outgoing: Queue<Message>,
+		received: State<Instant>,
+		version: setup::Version,
+		history: History,
 	) -> Result<(), SessionError> {
 		loop {
-			let msg: message::Message = recver.decode().await?;
+			let msg = recver
+				.decode_with(|cursor| message::Message::decode_version(cursor, version))
+				.await?;
 			log::debug!("received message: {:?}", msg);
+			history.record(HistoryKind::Received, || format!("{:?}", msg));
+
+			if let Some(mut received) = received.lock_mut() {
+				*received = Instant::now();
+			}
 
 			let msg = match TryInto::<message::Publisher>::try_into(msg) {
 				Ok(msg) => {
@@ -209,26 +599,47 @@ impl Session {
 				Err(msg) => msg,
 			};
 
-			// TODO GOAWAY
-			unimplemented!("unknown message context: {:?}", msg)
+			match msg {
+				Message::GoAway(msg) => return Err(SessionError::Redirect(msg.url)),
+				Message::Ping(msg) => {
+					outgoing
+						.push(message::Pong { sequence: msg.sequence }.into())
+						.map_err(|_| SessionError::Internal)?;
+				}
+				Message::Pong(_) => {}
+				msg => unimplemented!("unknown message context: {:?}", msg),
+			}
 		}
 	}
 
 	async fn run_streams(
-		mut webtransport: web_transport::Session,
+		mut transport: transport::Session,
 		subscriber: Option<Subscriber>,
+		max_streams: usize,
+		history: History,
 	) -> Result<(), SessionError> {
 		let mut tasks = FuturesUnordered::new();
 
 		loop {
 			tokio::select! {
-				res = webtransport.accept_uni() => {
+				res = transport.accept_uni() => {
 					let stream = res?;
 					let subscriber = subscriber.clone().ok_or(SessionError::RoleViolation)?;
 
+					// Stop the stream instead of buffering an unbounded number of tasks.
+					if tasks.len() >= max_streams {
+						let err = SessionError::TooManyStreams(max_streams);
+						log::warn!("stopping stream: {}", err);
+						history.record(HistoryKind::Stream, || format!("stopped: {}", err));
+						stream.stop(err.code() as u32);
+						continue;
+					}
+
+					let history = history.clone();
 					tasks.push(async move {
-						if let Err(err) = Subscriber::recv_stream(subscriber, stream).await {
+						if let Err(err) = Subscriber::recv_stream(subscriber, stream, history.clone()).await {
 							log::warn!("failed to serve stream: {}", err);
+							history.record(HistoryKind::Stream, || format!("failed: {}", err));
 						};
 					});
 				},
@@ -237,12 +648,71 @@ impl Session {
 		}
 	}
 
+	// Resolves once the drain timeout is over after GOAWAY was sent.
+	async fn run_drain(draining: State<bool>, timeout: Duration) -> Result<(), SessionError> {
+		loop {
+			let notify = {
+				let state = draining.lock();
+				if *state {
+					break;
+				}
+
+				state.modified()
+			};
+
+			match notify {
+				Some(notify) => notify.await,
+				None => return futures::future::pending().await,
+			}
+		}
+
+		tokio::time::sleep(timeout).await;
+		Err(SessionError::GoAway)
+	}
+
+	// Send PING every interval, failing once nothing has been received for several intervals.
+	async fn run_keepalive(
+		mut outgoing: Queue<Message>,
+		received: State<Instant>,
+		interval: Option<Duration>,
+	) -> Result<(), SessionError> {
+		let interval = match interval {
+			Some(interval) => interval,
+			None => return futures::future::pending().await,
+		};
+
+		let timeout = interval * Self::KEEPALIVE_MISSED;
+		let mut ticker = tokio::time::interval(interval);
+		ticker.tick().await;
+
+		let mut sequence = 0;
+		loop {
+			ticker.tick().await;
+
+			if received.lock().elapsed() >= timeout {
+				return Err(SessionError::Timeout(timeout));
+			}
+
+			outgoing
+				.push(message::Ping { sequence }.into())
+				.map_err(|_| SessionError::Internal)?;
+			sequence += 1;
+		}
+	}
+
+	async fn run_renewals(subscriber: Option<Subscriber>) -> Result<(), SessionError> {
+		match subscriber {
+			Some(subscriber) => subscriber.run_renewals().await,
+			None => futures::future::pending().await,
+		}
+	}
+
 	async fn run_datagrams(
-		mut webtransport: web_transport::Session,
+		mut transport: transport::Session,
 		mut subscriber: Option<Subscriber>,
 	) -> Result<(), SessionError> {
 		loop {
-			let datagram = webtransport.recv_datagram().await?;
+			let datagram = transport.recv_datagram().await?;
 			subscriber
 				.as_mut()
 				.ok_or(SessionError::RoleViolation)?
@@ -250,3 +720,1470 @@ impl Session {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data;
+	use crate::serve;
+	use crate::transport::memory;
+
+	async fn pair() -> (
+		(Session, Publisher, Subscriber),
+		(Session, Option<Publisher>, Option<Subscriber>),
+	) {
+		let (client, server) = memory::pair();
+		let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+		(client.unwrap(), server.unwrap())
+	}
+
+	// Announces a "video" track with the groups written by `write`, then subscribes to it from the other side.
+	async fn serve_groups(write: impl FnOnce(&mut serve::GroupsWriter)) -> (serve::GroupsWriter, serve::GroupsReader) {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (mut tracks, _, reader) = serve::Tracks::new("test".to_string()).produce();
+		let mut groups = tracks.create("video").unwrap().groups().unwrap();
+		write(&mut groups);
+
+		// Both tasks hold on to the broadcast and the announce for the rest of the test.
+		tokio::spawn(async move {
+			let _tracks = tracks;
+			publisher.announce(reader).await
+		});
+
+		let mut announced = subscriber.announced().await.unwrap();
+		assert_eq!(announced.namespace, "test");
+		announced.ok().unwrap();
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		tokio::spawn(async move {
+			let _announced = announced;
+			subscriber.subscribe(writer).await
+		});
+
+		(groups, subscribed_groups(&reader).await)
+	}
+
+	// Waits for the subscription to start delivering groups.
+	async fn subscribed_groups(reader: &serve::TrackReader) -> serve::GroupsReader {
+		match reader.mode().await.unwrap() {
+			serve::TrackReaderMode::Groups(groups) => groups,
+			_ => panic!("expected groups"),
+		}
+	}
+
+	#[tokio::test]
+	async fn handshake() {
+		let ((_, publisher, subscriber), (_, server_publisher, server_subscriber)) = pair().await;
+
+		assert!(server_publisher.is_some());
+		assert!(server_subscriber.is_some());
+		assert_eq!(publisher.capabilities(), setup::Capabilities::supported());
+		assert_eq!(subscriber.capabilities(), setup::Capabilities::supported());
+	}
+
+	#[tokio::test]
+	async fn announce_subscribe() {
+		let (_groups, mut groups) = serve_groups(|groups| {
+			groups.append(0).unwrap().write("hello".into()).unwrap();
+		})
+		.await;
+
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "hello");
+	}
+
+	#[tokio::test]
+	async fn announce_signature() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let signature = message::AnnounceSignature {
+			timestamp: 1_700_000_000_000,
+			signature: vec![1, 2, 3],
+		};
+
+		let (_tracks, _, reader) = serve::Tracks::new("test".to_string()).produce();
+		let sent = signature.clone();
+		tokio::spawn(async move { publisher.announce_signed(reader, sent).await });
+
+		let announced = subscriber.announced().await.unwrap();
+		assert_eq!(announced.signature, Some(signature));
+	}
+
+	#[tokio::test]
+	async fn announce_broadcast_id() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (_tracks, _, reader) = serve::Tracks::new("test".to_string())
+			.with_broadcast_id("8d3e2c4a")
+			.produce();
+		tokio::spawn(async move { publisher.announce(reader).await });
+
+		let announced = subscriber.announced().await.unwrap();
+		assert_eq!(announced.broadcast_id.as_deref(), Some("8d3e2c4a"));
+	}
+
+	#[tokio::test]
+	async fn group_timestamp() {
+		let timestamp = serve::GroupTimestamp {
+			media: Some(1_000_000),
+			wall: Some(1_700_000_000_000_000),
+		};
+
+		let (_groups, mut groups) = serve_groups(|groups| {
+			groups
+				.append_timed(0, timestamp)
+				.unwrap()
+				.write("hello".into())
+				.unwrap();
+		})
+		.await;
+
+		let group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.timestamp, timestamp);
+	}
+
+	#[tokio::test]
+	async fn group_annotations() {
+		let mut annotations = serve::GroupAnnotations::new();
+		annotations.insert("scte35.splice", "out");
+
+		let (_groups, mut groups) = serve_groups(|groups| {
+			groups
+				.append_annotated(0, Default::default(), annotations.clone())
+				.unwrap()
+				.write("hello".into())
+				.unwrap();
+		})
+		.await;
+
+		let group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.annotations, annotations);
+		assert!(group.timestamp.is_empty());
+	}
+
+	#[tokio::test]
+	async fn object_extensions() {
+		let mut extensions = serve::ObjectExtensions::new();
+		extensions.insert(0x2, "capture");
+		extensions.insert(0x4, vec![0xde, 0xad]);
+
+		let (_groups, mut groups) = serve_groups(|groups| {
+			let mut group = groups.append(0).unwrap();
+			group.write_extended("first".into(), extensions.clone()).unwrap();
+			group.write("second".into()).unwrap();
+		})
+		.await;
+
+		let mut group = groups.next().await.unwrap().unwrap();
+
+		let mut object = group.next().await.unwrap().unwrap();
+		assert_eq!(object.extensions, extensions);
+		assert_eq!(object.read_all().await.unwrap(), "first");
+
+		let mut object = group.next().await.unwrap().unwrap();
+		assert!(object.extensions.is_empty());
+		assert_eq!(object.read_all().await.unwrap(), "second");
+	}
+
+	#[tokio::test]
+	async fn independent_objects() {
+		let mut extensions = serve::ObjectExtensions::new();
+		extensions.insert(0x2, "capture");
+
+		let (_groups, mut groups) = serve_groups(|groups| {
+			let mut group = groups.append(0).unwrap();
+			group.write_with("key".into(), true, extensions.clone()).unwrap();
+			group.write("delta".into()).unwrap();
+			group.write_independent("key".into()).unwrap();
+		})
+		.await;
+
+		let mut group = groups.next().await.unwrap().unwrap();
+
+		// The flag is carried as an extension header, but not surfaced as one.
+		let object = group.next().await.unwrap().unwrap();
+		assert!(object.independent);
+		assert_eq!(object.extensions, extensions);
+
+		let object = group.next().await.unwrap().unwrap();
+		assert!(!object.independent);
+		assert!(object.extensions.is_empty());
+
+		let object = group.next().await.unwrap().unwrap();
+		assert!(object.independent);
+		assert!(object.extensions.is_empty());
+	}
+
+	#[tokio::test]
+	async fn written() {
+		// Cached, but nobody has subscribed.
+		let (track, _reader) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		let mut group = track.groups().unwrap().append(0).unwrap();
+		group.write("hello".into()).unwrap();
+
+		let pending = tokio::time::timeout(Duration::from_millis(50), group.written()).await;
+		assert!(pending.is_err());
+
+		let mut written = None;
+		let (_groups, mut groups) = serve_groups(|groups| {
+			let mut group = groups.append(0).unwrap();
+			group.write("hello".into()).unwrap();
+			written = Some(group);
+		})
+		.await;
+
+		let mut group = written.unwrap();
+		tokio::time::timeout(Duration::from_secs(1), group.written())
+			.await
+			.unwrap();
+
+		let mut received = groups.next().await.unwrap().unwrap();
+		assert_eq!(received.read_next().await.unwrap().unwrap(), "hello");
+
+		// Only objects created before the call are waited on.
+		group.write("world".into()).unwrap();
+		tokio::time::timeout(Duration::from_secs(1), group.written())
+			.await
+			.unwrap();
+		assert_eq!(received.read_next().await.unwrap().unwrap(), "world");
+	}
+
+	#[tokio::test]
+	async fn subscribe_stats() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		let subscribe = subscriber.subscribe_start(writer, Default::default());
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		let renewal = subscribed.renewal();
+		assert_eq!(subscribed.stats(), SubscribeStats::default());
+
+		let (track, served) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		let mut group = groups.append(0).unwrap();
+		group.write("hello".into()).unwrap();
+		group.write("world".into()).unwrap();
+		tokio::spawn(subscribed.serve(served));
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		let mut received = reader.next().await.unwrap().unwrap();
+		assert_eq!(received.read_next().await.unwrap().unwrap(), "hello");
+		assert_eq!(received.read_next().await.unwrap().unwrap(), "world");
+
+		groups.append(0).unwrap().write("again".into()).unwrap();
+		let mut received = reader.next().await.unwrap().unwrap();
+		assert_eq!(received.read_next().await.unwrap().unwrap(), "again");
+
+		let expected = SubscribeStats {
+			groups: 2,
+			objects: 3,
+			bytes: 15,
+			last_group: 1,
+		};
+		assert_eq!(renewal.stats(), expected);
+		assert_eq!(subscribe.stats(), expected);
+	}
+
+	#[tokio::test]
+	async fn epoch_after_data() {
+		let ((client, _, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+		let mut raw = client.into_raw();
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		let subscribe = subscriber.subscribe_start(writer, Default::default());
+
+		let id = match raw.recv().await.unwrap() {
+			Message::Subscribe(msg) => msg.id,
+			msg => panic!("unexpected message: {:?}", msg),
+		};
+
+		// The group arrives before SUBSCRIBE_OK, so the mode is chosen before the epoch is known.
+		let mut stream = raw.open_uni().await.unwrap();
+		let header = data::Header::Group(data::GroupHeader {
+			subscribe_id: id,
+			track_alias: id,
+			group_id: 5,
+			send_order: 0,
+		});
+		stream.encode(&header).await.unwrap();
+
+		let mut groups = subscribed_groups(&reader).await;
+		assert_eq!(groups.next().await.unwrap().unwrap().group_id, 5);
+		assert_eq!(reader.epoch(), None);
+
+		raw.send(message::SubscribeOk {
+			id,
+			expires: None,
+			latest: None,
+			epoch: Some(7),
+		})
+		.await
+		.unwrap();
+		subscribe.ready().await.unwrap();
+
+		assert_eq!(reader.epoch(), Some(7));
+		assert_eq!(groups.epoch(), Some(7));
+		assert_eq!(groups.restarts(), 0);
+	}
+
+	#[tokio::test]
+	async fn subgroups() {
+		let (_groups, mut groups) = serve_groups(|groups| {
+			let mut group = groups.append_split(2, 2).unwrap();
+			assert_eq!(group.write("direct".into()), Err(serve::ServeError::Mode));
+
+			for (subgroup_id, priority, payload) in [(0, 2, "base"), (1, 5, "enhance")] {
+				let subgroup = serve::Subgroup { subgroup_id, priority };
+				group.subgroup(subgroup).unwrap().write(payload.into()).unwrap();
+			}
+
+			let extra = serve::Subgroup {
+				subgroup_id: 2,
+				priority: 0,
+			};
+			assert!(group.subgroup(extra).is_err());
+		})
+		.await;
+
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.subgroups, 2);
+
+		// Each subgroup arrives on its own stream with its own priority, in any order.
+		let mut received = Vec::new();
+		while let Some(mut subgroup) = group.next_subgroup().await.unwrap() {
+			let payload = subgroup.read_next().await.unwrap().unwrap();
+			received.push((subgroup.subgroup_id, subgroup.priority, payload));
+		}
+
+		received.sort_by_key(|(subgroup_id, _, _)| *subgroup_id);
+		assert_eq!(received, vec![(0, 2, "base".into()), (1, 5, "enhance".into())]);
+		assert!(group.next().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn token_renewal() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber
+			.unwrap()
+			.with_token("first")
+			.with_token_refresh(|info| async move {
+				assert_eq!(info.name, "clock");
+				Some("second".to_string())
+			});
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let subscribe = tokio::spawn(async move { subscriber.subscribe(writer).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		assert_eq!(subscribed.authorization().as_deref(), Some("first"));
+
+		let mut renewal = subscribed.renewal();
+
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		groups.append(0).unwrap().write("hello".into()).unwrap();
+		tokio::spawn(subscribed.serve(served));
+
+		let token = renewal.renew(std::time::Duration::from_secs(10)).await.unwrap();
+		assert_eq!(token, "second");
+
+		// Closing the subscription, ex. because the new token was rejected, reports the error to the subscriber.
+		renewal
+			.close(serve::ServeError::Rejected(401, "expired".to_string()))
+			.unwrap();
+		let err = subscribe.await.unwrap().unwrap_err();
+		assert_eq!(err.code(), 401);
+
+		drop(reader);
+	}
+
+	#[tokio::test]
+	async fn subscribe_update() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut updater = subscriber.clone();
+		let subscribe = tokio::spawn(async move { subscriber.subscribe(writer).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		assert_eq!(subscribed.end_group(), None);
+
+		let update = SubscribeUpdate {
+			end_group: Some(1),
+			priority: Some(7),
+			..Default::default()
+		};
+		updater.update("test", "clock", update).unwrap();
+		subscribed.updated().await.unwrap();
+
+		assert_eq!(subscribed.end_group(), Some(1));
+		assert_eq!(subscribed.priority(), Some(7));
+
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		groups.append(0).unwrap().write("zero".into()).unwrap();
+		tokio::spawn(subscribed.serve(served));
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "zero");
+
+		groups.append(0).unwrap().write("one".into()).unwrap();
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "one");
+
+		// The subscription ends once the track moves past the new end group.
+		groups.append(0).unwrap().write("two".into()).unwrap();
+		assert!(reader.next().await.unwrap().is_none());
+		subscribe.await.unwrap().unwrap();
+
+		assert_eq!(
+			updater.update("test", "clock", Default::default()),
+			Err(serve::ServeError::NotFound)
+		);
+	}
+
+	#[tokio::test]
+	async fn subscribe_range() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let options = SubscribeOptions {
+			start_group: Some(1),
+			start_object: Some(1),
+			end_group: Some(2),
+			..Default::default()
+		};
+		let subscribe = tokio::spawn(async move { subscriber.subscribe_with(writer, options).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		assert_eq!(subscribed.start_group(), Some(1));
+		assert_eq!(subscribed.end_group(), Some(2));
+
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		tokio::spawn(subscribed.with_skip(serve::GroupSkip::Never).serve(served));
+
+		let mut append = |payloads: [&str; 2]| {
+			let mut group = groups.append(0).unwrap();
+			for payload in payloads {
+				group.write(payload.to_string().into()).unwrap();
+			}
+		};
+
+		append(["0a", "0b"]);
+		append(["1a", "1b"]);
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		// Objects before the start object are skipped, keeping their IDs.
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 1);
+		let object = group.next().await.unwrap().unwrap();
+		assert_eq!(object.object_id, 1);
+		assert_eq!(group.read_next().await.unwrap(), None);
+
+		append(["2a", "2b"]);
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 2);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "2a");
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "2b");
+
+		// Nothing is sent past the end group.
+		append(["3a", "3b"]);
+		assert!(reader.next().await.unwrap().is_none());
+		subscribe.await.unwrap().unwrap();
+	}
+
+	#[tokio::test]
+	async fn static_track() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let name = "config.json?static".to_string();
+		let (writer, reader) = serve::Track::new("test".to_string(), name.clone()).produce();
+		let options = SubscribeOptions {
+			start_group: Some(5),
+			..Default::default()
+		};
+		tokio::spawn(async move { subscriber.subscribe_with(writer, options).await });
+
+		let (track, served) = serve::Track::new("test".to_string(), name).produce();
+		let mut groups = track.groups().unwrap();
+		groups.append(0).unwrap().write("v1".into()).unwrap();
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		tokio::spawn(subscribed.serve(served));
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		// The latest group is the track's state, so it's served even though it's before the start.
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 0);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "v1");
+
+		// Later groups still honor the requested start.
+		for payload in ["v2", "v3", "v4", "v5", "v6"] {
+			groups.append(0).unwrap().write(payload.into()).unwrap();
+		}
+
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 5);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "v6");
+	}
+
+	#[tokio::test]
+	async fn delivery_timeout() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let options = SubscribeOptions {
+			delivery_timeout: Some(std::time::Duration::from_millis(50)),
+			..Default::default()
+		};
+		tokio::spawn(async move { subscriber.subscribe_with(writer, options).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		assert_eq!(
+			subscribed.delivery_timeout(),
+			Some(std::time::Duration::from_millis(50))
+		);
+
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		tokio::spawn(subscribed.with_skip(serve::GroupSkip::Never).serve(served));
+
+		// The object is never finished, so its stream is reset instead of blocking forever.
+		let mut stalled = groups.append(0).unwrap();
+		let mut object = stalled.create(10).unwrap();
+		object.write("stall".into()).unwrap();
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 0);
+		assert!(group.read_next().await.is_err());
+
+		// Later groups are still delivered.
+		groups.append(0).unwrap().write("next".into()).unwrap();
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 1);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "next");
+	}
+
+	#[tokio::test]
+	async fn clock_sync() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		// The clock-sync track is answered by the session, so the namespace doesn't need to be announced.
+		let clock = subscriber.clock();
+		let mut syncing = subscriber.clone();
+		tokio::spawn(async move { syncing.sync_clock("test").await });
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		tokio::spawn(async move { subscriber.subscribe(writer).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		assert_eq!(subscribed.name, "clock");
+
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		tokio::spawn(subscribed.serve(served));
+		groups.append(0).unwrap().write("tick".into()).unwrap();
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		// Each group stream is stamped with the time it was sent.
+		let group = reader.next().await.unwrap().unwrap();
+		let transit = group.transit.unwrap();
+		assert!(transit.delay() < std::time::Duration::from_secs(1));
+
+		// Both ends share a clock, so the measured offset is close to zero.
+		while clock.offset().is_none() {
+			tokio::task::yield_now().await;
+		}
+		assert!(clock.offset().unwrap().abs() < 1_000_000);
+	}
+
+	#[tokio::test]
+	async fn group_order() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "vod".to_string()).produce();
+		let options = SubscribeOptions {
+			group_order: message::GroupOrder::Ascending,
+			..Default::default()
+		};
+		tokio::spawn(async move { subscriber.subscribe_with(writer, options).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		assert_eq!(subscribed.group_order(), message::GroupOrder::Ascending);
+
+		let (track, served) = serve::Track::new("test".to_string(), "vod".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		tokio::spawn(subscribed.with_skip(serve::GroupSkip::Never).serve(served));
+
+		let mut first = groups.append(0).unwrap();
+		first.write("0".into()).unwrap();
+
+		let mut reader = subscribed_groups(&reader).await.with_skip(serve::GroupSkip::Never);
+
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "0");
+
+		// Later groups wait until the earlier group is finished.
+		groups.append(0).unwrap().write("1".into()).unwrap();
+		groups.append(0).unwrap().write("2".into()).unwrap();
+		let next = tokio::time::timeout(std::time::Duration::from_millis(50), reader.next()).await;
+		assert!(next.is_err());
+
+		drop(first);
+		for expected in ["1", "2"] {
+			let mut group = reader.next().await.unwrap().unwrap();
+			assert_eq!(group.read_next().await.unwrap().unwrap(), expected);
+		}
+	}
+
+	#[tokio::test]
+	async fn receive_window() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let window = message::ReceiveWindow {
+			groups: Some(1),
+			bytes: None,
+		};
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		let options = SubscribeOptions {
+			receive_window: Some(window),
+			..Default::default()
+		};
+		tokio::spawn(async move { subscriber.subscribe_with(writer, options).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		assert_eq!(subscribed.receive_window(), Some(window));
+
+		let (track, served) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		tokio::spawn(subscribed.with_skip(serve::GroupSkip::Never).serve(served));
+
+		let mut first = groups.append(0).unwrap();
+		first.write("0".into()).unwrap();
+
+		let mut reader = subscribed_groups(&reader).await.with_skip(serve::GroupSkip::Never);
+
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "0");
+
+		// The next group isn't sent until the first one is acknowledged.
+		groups.append(0).unwrap().write("1".into()).unwrap();
+		let next = tokio::time::timeout(std::time::Duration::from_millis(50), reader.next()).await;
+		assert!(next.is_err());
+
+		drop(first);
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "1");
+	}
+
+	#[tokio::test]
+	async fn receive_window_pending() {
+		// The skip policy, the groups produced, the groups received, and the latest group read by the publisher.
+		for (skip, count, expected, queued) in [
+			(serve::GroupSkip::Latest, 10, 10..11, 10),
+			(serve::GroupSkip::Lag(2), 10, 8..11, 10),
+			(serve::GroupSkip::Never, 100, 1..101, 64),
+		] {
+			let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+			let mut subscriber = server_subscriber.unwrap();
+
+			tokio::spawn(client.run());
+			tokio::spawn(server.run());
+
+			let (writer, reader) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+			let options = SubscribeOptions {
+				receive_window: Some(message::ReceiveWindow {
+					groups: Some(1),
+					bytes: None,
+				}),
+				..Default::default()
+			};
+			tokio::spawn(async move { subscriber.subscribe_with(writer, options).await });
+
+			let subscribed = publisher.subscribed().await.unwrap();
+			let renewal = subscribed.renewal();
+			let (track, served) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+			let mut groups = track.groups().unwrap();
+			tokio::spawn(subscribed.with_skip(skip).serve(served));
+
+			let mut first = groups.append(0).unwrap();
+			first.write("0".into()).unwrap();
+
+			let mut reader = subscribed_groups(&reader).await.with_skip(serve::GroupSkip::Never);
+
+			let mut group = reader.next().await.unwrap().unwrap();
+			assert_eq!(group.read_next().await.unwrap().unwrap(), "0");
+
+			// The first group is never acknowledged while more are produced, so only the newest are queued.
+			// Nothing is skipped with GroupSkip::Never; the publisher stops reading the track instead.
+			for id in 1..=count {
+				groups.append(0).unwrap().write(id.to_string().into()).unwrap();
+			}
+
+			// Wait for the publisher to queue (and drop) the groups.
+			while renewal.queued() != Some(queued) {
+				tokio::task::yield_now().await;
+			}
+
+			drop(first);
+			drop(group);
+			for id in expected.clone() {
+				let mut group = reader.next().await.unwrap().unwrap();
+				assert_eq!(group.read_next().await.unwrap().unwrap(), id.to_string(), "{:?}", skip);
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn versions() {
+		// The experimental version is only used when both sides request it.
+		let ((client, _, _), (server, _, _)) = pair().await;
+		assert_eq!(client.version(), setup::Version::DRAFT_03);
+		assert_eq!(server.version(), setup::Version::DRAFT_03);
+
+		let (client, server) = memory::pair();
+		let (client, server) = tokio::join!(
+			Session::connect_versions(client, setup::Role::Both, setup::Versions::implemented()),
+			Session::accept(server),
+		);
+		assert_eq!(client.unwrap().0.version(), setup::Version::DRAFT_03);
+		assert_eq!(server.unwrap().0.version(), setup::Version::DRAFT_03);
+
+		// The range round-trips in either format.
+		for version in [setup::Version::DRAFT_03, setup::Version::DRAFT_03_FILTER] {
+			let (client, server) = memory::pair();
+			let (client, server) = tokio::join!(
+				Session::connect_versions(client, setup::Role::Both, setup::Versions::implemented()),
+				Session::accept_versions(server, setup::Role::Both, [version].into()),
+			);
+			let (client, _, subscriber) = client.unwrap();
+			let (server, publisher, _) = server.unwrap();
+			assert_eq!(client.version(), version);
+			assert_eq!(server.version(), version);
+
+			tokio::spawn(client.run());
+			tokio::spawn(server.run());
+
+			let (writer, _reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+			let options = SubscribeOptions {
+				start_group: Some(1),
+				end_group: Some(2),
+				..Default::default()
+			};
+			let mut subscriber = subscriber.unwrap();
+			tokio::spawn(async move { subscriber.subscribe_with(writer, options).await });
+
+			let subscribed = publisher.unwrap().subscribed().await.unwrap();
+			assert_eq!(subscribed.start_group(), Some(1));
+			assert_eq!(subscribed.end_group(), Some(2));
+		}
+
+		// Without a version in common, the setup fails.
+		let (client, server) = memory::pair();
+		let (client, server) = tokio::join!(
+			Session::connect_versions(client, setup::Role::Both, [setup::Version::DRAFT_03].into()),
+			Session::accept_versions(server, setup::Role::Both, [setup::Version::DRAFT_03_FILTER].into()),
+		);
+		assert!(client.is_err());
+		assert!(server.is_err());
+	}
+
+	#[tokio::test]
+	async fn tokens() {
+		let authorize = |token: Option<setup::AuthToken>| async move {
+			match token {
+				Some(token) if token.as_str() == "secret" => Ok(()),
+				_ => Err(serve::ServeError::Rejected(403, "forbidden".to_string())),
+			}
+		};
+
+		// The server only accepts clients with the right token.
+		let (client, server) = memory::pair();
+		let (client, server) = tokio::join!(
+			Session::connect_with(client, SetupOptions::default().with_token("wrong")),
+			Session::accept_with(server, SetupOptions::default().with_authorize(authorize)),
+		);
+		assert!(client.is_err());
+		assert!(matches!(server, Err(SessionError::Unauthorized)));
+
+		let (client, server) = memory::pair();
+		let (client, server) = tokio::join!(
+			Session::connect_with(client, SetupOptions::default().with_token("secret")),
+			Session::accept_with(server, SetupOptions::default().with_authorize(authorize)),
+		);
+		let (client, publisher, _) = client.unwrap();
+		let (server, _, subscriber) = server.unwrap();
+		assert_eq!(server.token().map(|token| token.as_str()), Some("secret"));
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		// Announces carry their own token.
+		let (_tracks, _, reader) = serve::Tracks::new("test".to_string()).produce();
+		let mut publisher = publisher.unwrap().with_token("announce");
+		tokio::spawn(async move { publisher.announce(reader).await });
+
+		let announced = subscriber.unwrap().announced().await.unwrap();
+		assert_eq!(announced.authorization.as_deref(), Some("announce"));
+	}
+
+	#[tokio::test]
+	async fn path() {
+		let path = setup::Path::new("/live?region=eu").unwrap();
+
+		let (client, server) = memory::pair();
+		let (client, server) = tokio::join!(
+			Publisher::connect_with(client, SetupOptions::default().with_path(path.clone())),
+			Session::accept(server),
+		);
+		let (client, _) = client.unwrap();
+		let (server, _, subscriber) = server.unwrap();
+
+		// Only the server sees the path, and the client's role is still publisher.
+		assert_eq!(server.path(), Some(&path));
+		assert_eq!(client.path(), None);
+		assert!(subscriber.is_some());
+	}
+
+	#[tokio::test]
+	async fn go_away() {
+		let (client, server) = memory::pair();
+		let (client, server) = tokio::join!(
+			Session::connect_with(client, SetupOptions::default().with_sticky("edge-1")),
+			Session::accept_with(server, SetupOptions::default().with_sticky("edge-2")),
+		);
+		let (client, _, _) = client.unwrap();
+		let (server, _, _) = server.unwrap();
+
+		// Each side sees the other's token.
+		assert_eq!(client.sticky().map(|sticky| sticky.as_str()), Some("edge-2"));
+		assert_eq!(server.sticky().map(|sticky| sticky.as_str()), Some("edge-1"));
+
+		let mut drain = server.drain();
+		let client = tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		drain.go_away("https://relay.example.com/?sticky=origin-1");
+
+		let err = client.await.unwrap().unwrap_err();
+		assert!(matches!(err, SessionError::Redirect(url) if url == "https://relay.example.com/?sticky=origin-1"));
+	}
+
+	#[tokio::test]
+	async fn keepalive() {
+		let interval = Duration::from_millis(20);
+
+		// The server answers PING without a keepalive of its own, so the client stays up.
+		let ((client, _, _), (server, _, _)) = pair().await;
+		let client = tokio::spawn(client.with_keepalive(interval).run());
+		tokio::spawn(server.run());
+
+		tokio::time::sleep(interval * Session::KEEPALIVE_MISSED * 3).await;
+		assert!(!client.is_finished());
+
+		// A raw client never answers, so the server gives up on it.
+		let ((client, _, _), (server, _, _)) = pair().await;
+		let mut raw = client.into_raw();
+		let server = tokio::spawn(server.with_keepalive(interval).run());
+
+		let err = server.await.unwrap().unwrap_err();
+		assert!(matches!(err, SessionError::Timeout(timeout) if timeout == interval * Session::KEEPALIVE_MISSED));
+		let err = raw.recv().await.unwrap_err();
+		assert!(matches!(err.peer_closed(), Some((CloseCode::Internal, _))));
+	}
+
+	#[tokio::test]
+	async fn go_away_drain() {
+		let ((client, _, _), (server, _, _)) = pair().await;
+
+		// A raw client ignores the GOAWAY, so the server has to give up on it.
+		let mut raw = client.into_raw();
+		let server = server.with_drain_timeout(Duration::from_millis(50));
+		server.go_away("https://relay.example.com/");
+		let server = tokio::spawn(server.run());
+
+		let msg = raw.recv().await.unwrap();
+		assert!(matches!(msg, Message::GoAway(msg) if msg.url == "https://relay.example.com/"));
+
+		raw.send(message::Subscribe {
+			id: 0,
+			track_alias: 0,
+			track_namespace: "test".to_string(),
+			track_name: "clock".to_string(),
+			start: message::SubscribePair {
+				group: message::SubscribeLocation::Latest(0),
+				object: message::SubscribeLocation::Absolute(0),
+			},
+			end: message::SubscribePair {
+				group: message::SubscribeLocation::None,
+				object: message::SubscribeLocation::None,
+			},
+			group_order: Default::default(),
+			params: Default::default(),
+		})
+		.await
+		.unwrap();
+
+		// New subscriptions are rejected while draining.
+		let msg = raw.recv().await.unwrap();
+		assert!(matches!(msg, Message::SubscribeError(msg) if msg.id == 0 && msg.code == 503));
+
+		let err = server.await.unwrap().unwrap_err();
+		assert!(matches!(err, SessionError::GoAway), "err={}", err);
+
+		let closed = raw.closed().await.peer_closed().map(|(code, _)| code);
+		assert_eq!(closed, Some(CloseCode::GoawayTimeout));
+	}
+
+	#[tokio::test]
+	async fn datagrams() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		tokio::spawn(async move { subscriber.subscribe(writer).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut datagrams = track.datagrams().unwrap();
+		datagrams
+			.write(serve::Datagram {
+				group_id: 1,
+				object_id: 2,
+				priority: 0,
+				payload: "hello".into(),
+			})
+			.unwrap();
+		tokio::spawn(subscribed.serve(served));
+
+		let mut reader = match reader.mode().await.unwrap() {
+			serve::TrackReaderMode::Datagrams(datagrams) => datagrams,
+			_ => panic!("expected datagrams"),
+		};
+
+		let datagram = reader.read().await.unwrap().unwrap();
+		assert_eq!((datagram.group_id, datagram.object_id), (1, 2));
+		assert_eq!(datagram.payload, "hello");
+	}
+
+	#[tokio::test]
+	async fn bootstrap() {
+		let ((client, publisher, _), (server, _, server_subscriber)) = pair().await;
+		let subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		// Subscribe and serve a track, returning what the subscriber receives.
+		let subscribe = |mut publisher: Publisher, name: &str| {
+			let mut subscriber = subscriber.clone();
+			let (writer, reader) = serve::Track::new("test".to_string(), name.to_string()).produce();
+			let (track, served) = serve::Track::new("test".to_string(), name.to_string()).produce();
+
+			tokio::spawn(async move { subscriber.subscribe(writer).await });
+			tokio::spawn(async move { publisher.subscribed().await.unwrap().serve(served).await });
+
+			(track, reader)
+		};
+
+		// The init track is sent at the highest priority, regardless of the publisher's.
+		let (track, reader) = subscribe(publisher.clone(), "0.mp4");
+		let mut groups = track.groups().unwrap();
+		groups.append(7).unwrap().write("init".into()).unwrap();
+
+		let group = subscribed_groups(&reader).await.next().await.unwrap().unwrap();
+		assert_eq!(group.priority, serve::BOOTSTRAP_PRIORITY);
+
+		// Datagrams are sent as objects instead, so they can't be lost.
+		let (track, reader) = subscribe(publisher.clone(), serve::CATALOG_TRACK);
+		let mut datagrams = track.datagrams().unwrap();
+		datagrams
+			.write(serve::Datagram {
+				group_id: 1,
+				object_id: 2,
+				priority: 7,
+				payload: "catalog".into(),
+			})
+			.unwrap();
+
+		let mut object = match reader.mode().await.unwrap() {
+			serve::TrackReaderMode::Objects(mut objects) => objects.next().await.unwrap().unwrap(),
+			_ => panic!("expected objects"),
+		};
+		assert_eq!((object.group_id, object.object_id), (1, 2));
+		assert_eq!(object.priority, serve::BOOTSTRAP_PRIORITY);
+		assert_eq!(object.read_all().await.unwrap(), "catalog");
+
+		// The publisher's priority is kept when opted out.
+		let publisher = publisher.with_bootstrap_priority(false);
+		let (track, reader) = subscribe(publisher, "1.mp4");
+		let mut groups = track.groups().unwrap();
+		groups.append(7).unwrap().write("init".into()).unwrap();
+
+		let group = subscribed_groups(&reader).await.next().await.unwrap().unwrap();
+		assert_eq!(group.priority, 7);
+	}
+
+	#[tokio::test]
+	async fn fetch() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (mut tracks, _, mut reader) = serve::Tracks::new("test".to_string()).produce();
+		let mut groups = tracks.create("clock").unwrap().groups().unwrap();
+
+		// Retain every group, like a reader that never skips.
+		let _retain = match reader.subscribe("clock").unwrap().mode().await.unwrap() {
+			serve::TrackReaderMode::Groups(groups) => groups.with_skip(serve::GroupSkip::Never),
+			_ => panic!("expected groups"),
+		};
+
+		for payload in ["zero", "one", "two"] {
+			groups.append(0).unwrap().write(payload.into()).unwrap();
+		}
+
+		tokio::spawn(async move { publisher.announce(reader).await });
+
+		let mut announced = subscriber.announced().await.unwrap();
+		announced.ok().unwrap();
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let options = FetchOptions {
+			start_group: 1,
+			end_group: Some(1),
+		};
+		subscriber.fetch(writer, options).await.unwrap();
+
+		let mut stream = match reader.mode().await.unwrap() {
+			serve::TrackReaderMode::Stream(stream) => stream,
+			_ => panic!("expected stream"),
+		};
+
+		let mut group = stream.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 1);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "one");
+		assert!(stream.next().await.unwrap().is_none());
+
+		// Unknown tracks are rejected.
+		let (writer, _reader) = serve::Track::new("test".to_string(), "missing".to_string()).produce();
+		assert!(subscriber.fetch(writer, options).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn announce_done_on_close() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, request, reader) = serve::Tracks::new("test".to_string()).produce();
+		let announce = tokio::spawn(async move { publisher.announce(reader).await });
+
+		let mut announced = subscriber.announced().await.unwrap();
+		announced.ok().unwrap();
+
+		drop(writer);
+		drop(request);
+
+		announce.await.unwrap().unwrap();
+	}
+
+	#[tokio::test]
+	async fn announce_cancel() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (_writer, _request, reader) = serve::Tracks::new("test".to_string()).produce();
+		let announce = tokio::spawn(async move { publisher.announce(reader).await });
+
+		let mut announced = subscriber.announced().await.unwrap();
+		announced.ok().unwrap();
+		announced.cancel(404, "no route").unwrap();
+
+		let err = announce.await.unwrap().unwrap_err();
+		assert_eq!(err.code(), 404);
+	}
+
+	#[tokio::test]
+	async fn subscribe_done_clean() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (mut tracks, _, reader) = serve::Tracks::new("test".to_string()).produce();
+		let mut groups = tracks.create("clock").unwrap().groups().unwrap();
+
+		tokio::spawn(async move { publisher.announce(reader).await });
+
+		let mut announced = subscriber.announced().await.unwrap();
+		announced.ok().unwrap();
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let subscribe = tokio::spawn(async move { subscriber.subscribe(writer).await });
+
+		// Write the final group and end the track after the subscription is established.
+		tokio::task::yield_now().await;
+		groups.append(0).unwrap().write("goodbye".into()).unwrap();
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		drop(groups);
+		drop(tracks);
+
+		let mut group = reader.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "goodbye");
+
+		assert!(reader.next().await.unwrap().is_none());
+		subscribe.await.unwrap().unwrap();
+	}
+
+	#[tokio::test]
+	async fn subscribe_done_status() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let subscribe = tokio::spawn(async move { subscriber.subscribe_with(writer, Default::default()).await });
+
+		let subscribed = publisher.subscribed().await.unwrap();
+		let renewal = subscribed.renewal();
+
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		tokio::spawn(subscribed.serve(served));
+
+		groups.append(0).unwrap().write("first".into()).unwrap();
+		groups.append(0).unwrap().write("last".into()).unwrap();
+
+		let mut reader = subscribed_groups(&reader).await;
+
+		// Wait for the final group, since the reader may skip ahead to it.
+		while reader.next().await.unwrap().unwrap().group_id != 1 {}
+
+		renewal.end(message::SubscribeStatus::GoingAway).unwrap();
+
+		let closed = subscribe.await.unwrap().unwrap().unwrap();
+		assert_eq!(closed.status, message::SubscribeStatus::GoingAway);
+		assert_eq!(closed.last, Some((1, 0)));
+		assert!(!closed.missed());
+	}
+
+	#[tokio::test]
+	async fn max_streams() {
+		let (client, server) = memory::pair();
+		let mut transport = client.clone();
+
+		let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+		let (client, _, _) = client.unwrap();
+		let (server, _, _) = server.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.with_max_streams(1).run());
+
+		// The first stream never sends a header, so it occupies the only slot.
+		let mut first = transport.open_uni().await.unwrap();
+		first.write(&[0x51]).await.unwrap();
+
+		let mut second = transport.open_uni().await.unwrap();
+		second.write(&[0x51]).await.unwrap();
+
+		let code = SessionError::TooManyStreams(1).code() as u32;
+		loop {
+			match second.write(&[0]).await {
+				Ok(_) => tokio::task::yield_now().await,
+				Err(transport::mux::MuxError::Stopped(stopped)) => break assert_eq!(stopped, code),
+				Err(err) => panic!("unexpected error: {}", err),
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn unsubscribe_on_reader_drop() {
+		let ((client, _, mut subscriber), (server, publisher, _)) = pair().await;
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		drop(reader);
+
+		subscriber.subscribe(writer).await.unwrap();
+
+		// The publisher sees the UNSUBSCRIBE, cancelling the subscription.
+		let subscribed = publisher.unwrap().subscribed().await.unwrap();
+		assert_eq!(subscribed.closed().await, Err(serve::ServeError::Cancel));
+	}
+
+	#[tokio::test]
+	async fn unsubscribe_on_groups_drop() {
+		let ((client, _, mut subscriber), (server, publisher, _)) = pair().await;
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		tokio::spawn(async move { subscriber.subscribe(writer).await });
+
+		let subscribed = publisher.unwrap().subscribed().await.unwrap();
+		let renewal = subscribed.renewal();
+
+		let (track, served) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let mut groups = track.groups().unwrap();
+		groups.append(0).unwrap().write("hello".into()).unwrap();
+		tokio::spawn(subscribed.serve(served));
+
+		let mut received = subscribed_groups(&reader).await;
+		let mut group = received.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), "hello");
+
+		// The track writer was replaced by the groups writer, so it's the last GroupsReader that matters.
+		drop(reader);
+		drop(group);
+		drop(received);
+
+		assert_eq!(renewal.closed().await, Err(serve::ServeError::Cancel));
+	}
+
+	#[tokio::test]
+	async fn subscribe_bundle() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (mut tracks, _, reader) = serve::Tracks::new("test".to_string()).produce();
+		let _audio = tracks.create("audio").unwrap();
+		let _video = tracks.create("video").unwrap();
+
+		tokio::spawn(async move { publisher.announce(reader).await });
+
+		let mut announced = subscriber.announced().await.unwrap();
+		announced.ok().unwrap();
+
+		let track = |name: &str| {
+			let (writer, reader) = serve::Track::new("test".to_string(), name.to_string()).produce();
+			((writer, SubscribeOptions::default()), reader)
+		};
+
+		let (audio, _audio) = track("audio");
+		let (video, _video) = track("video");
+		let bundle = subscriber.subscribe_bundle([audio, video]);
+		bundle.ready().await.unwrap();
+		assert_eq!(bundle.tracks().count(), 2);
+
+		// A single missing track fails the entire bundle.
+		let (audio, _audio) = track("audio");
+		let (missing, _missing) = track("missing");
+		let bundle = subscriber.subscribe_bundle([audio, missing]);
+		assert_eq!(bundle.ready().await, Err(serve::ServeError::Closed(404)));
+	}
+
+	#[tokio::test]
+	async fn subscribe_batch() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+		assert!(subscriber.capabilities().batch);
+
+		tokio::spawn(client.run());
+		tokio::spawn(server.run());
+
+		let (mut tracks, _, reader) = serve::Tracks::new("test".to_string()).produce();
+		let _low = tracks.create("480p").unwrap();
+		let _high = tracks.create("1080p").unwrap();
+
+		tokio::spawn(async move { publisher.announce(reader).await });
+
+		let mut announced = subscriber.announced().await.unwrap();
+		announced.ok().unwrap();
+
+		let track = |name: &str| {
+			let (writer, reader) = serve::Track::new("test".to_string(), name.to_string()).produce();
+			((writer, SubscribeOptions::default()), reader)
+		};
+
+		let (low, _low) = track("480p");
+		let bundle = subscriber.subscribe_bundle([low]);
+		bundle.ready().await.unwrap();
+
+		// Switch renditions, sending the UNSUBSCRIBE and SUBSCRIBE together.
+		let batch = subscriber.batch();
+		drop(bundle);
+		let (high, _high) = track("1080p");
+		let bundle = subscriber.subscribe_bundle([high]);
+		drop(batch);
+
+		bundle.ready().await.unwrap();
+		assert_eq!(bundle.tracks().next().unwrap().name, "1080p");
+	}
+
+	#[tokio::test]
+	async fn raw_invalid_message() {
+		let ((client, _, _), (server, _, _)) = pair().await;
+		let mut raw = client.into_raw();
+		let server = tokio::spawn(server.run());
+
+		// An unknown message type can't be skipped, since control messages aren't length prefixed.
+		raw.send_bytes(&[0x2f]).await.unwrap();
+
+		let err = server.await.unwrap().unwrap_err();
+		assert!(matches!(err, SessionError::Decode(_)), "err={}", err);
+
+		let closed = raw.closed().await.peer_closed().map(|(code, _)| code);
+		assert_eq!(closed, Some(CloseCode::ProtocolViolation));
+	}
+
+	#[tokio::test]
+	async fn history() {
+		let ((client, _, _), (server, _, _)) = pair().await;
+		let history = server.history();
+		let mut raw = client.into_raw();
+		let server = tokio::spawn(server.run());
+
+		raw.send(message::Unsubscribe { id: 7 }).await.unwrap();
+		raw.send_bytes(&[0x2f]).await.unwrap();
+
+		let err = server.await.unwrap().unwrap_err();
+		assert!(err.is_protocol_violation());
+
+		// The messages leading up to the failure are kept for the report.
+		let events = history.events();
+		assert!(events
+			.iter()
+			.any(|event| event.kind == HistoryKind::Received && event.detail == "Unsubscribe { id: 7 }"));
+	}
+
+	#[cfg(feature = "chaos")]
+	#[tokio::test]
+	async fn chaos_recovers() {
+		let ((client, mut publisher, _), (server, _, server_subscriber)) = pair().await;
+		let mut subscriber = server_subscriber.unwrap();
+
+		let chaos = Chaos::new(ChaosConfig {
+			delay: 0.3,
+			max_delay: std::time::Duration::from_millis(10),
+			drop: 0.3,
+			reset: 0.3,
+			seed: Some(7),
+			..Default::default()
+		});
+
+		tokio::spawn(client.with_chaos(chaos).run());
+		tokio::spawn(server.run());
+
+		let (mut tracks, _, reader) = serve::Tracks::new("test".to_string()).produce();
+		let mut groups = tracks.create("clock").unwrap().groups().unwrap();
+
+		tokio::spawn(async move { publisher.announce(reader).await });
+
+		let mut announced = subscriber.announced().await.unwrap();
+		announced.ok().unwrap();
+
+		let (writer, reader) = serve::Track::new("test".to_string(), "clock".to_string()).produce();
+		let subscribe = tokio::spawn(async move { subscriber.subscribe(writer).await });
+
+		// Keep writing until the track ends, since the subscription is only ready once a group arrives.
+		tokio::spawn(async move {
+			for _ in 0..20 {
+				groups.append(0).unwrap().write("tick".into()).unwrap();
+				tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+			}
+
+			drop(groups);
+			drop(tracks);
+		});
+
+		// Some groups are lost or reset, but the subscription still ends instead of wedging.
+		let received = tokio::time::timeout(std::time::Duration::from_secs(5), async move {
+			let mut reader = subscribed_groups(&reader).await.with_skip(serve::GroupSkip::Never);
+
+			let mut received = 0;
+			while reader.next().await.unwrap().is_some() {
+				received += 1;
+			}
+
+			subscribe.await.unwrap().unwrap();
+			received
+		})
+		.await
+		.unwrap();
+
+		assert!((1..20).contains(&received), "received={}", received);
+	}
+}
@@ -0,0 +1,91 @@
+use std::{future::Future, sync::Arc};
+
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{serve::ServeError, setup};
+
+type Authorize = Arc<dyn Fn(Option<setup::AuthToken>) -> BoxFuture<'static, Result<(), ServeError>> + Send + Sync>;
+
+/// Options for the SETUP handshake, see [super::Session::connect_with] and [super::Session::accept_with].
+#[derive(Clone)]
+pub struct SetupOptions {
+	/// Whether we publish, subscribe, or both, downgraded based on the peer's role.
+	pub role: setup::Role,
+
+	/// The versions we offer or accept, preferring the highest one in common.
+	pub versions: setup::Versions,
+
+	/// Sent by the client to authorize the session.
+	pub token: Option<setup::AuthToken>,
+
+	/// Sent by the client to return to a relay, or by the server to identify itself.
+	pub sticky: Option<setup::StickyToken>,
+
+	/// Sent by the client over raw QUIC, since there's no WebTransport URL.
+	pub path: Option<setup::Path>,
+
+	authorize: Option<Authorize>,
+}
+
+impl SetupOptions {
+	pub fn new(role: setup::Role) -> Self {
+		Self {
+			role,
+			versions: setup::Versions::supported(),
+			token: None,
+			sticky: None,
+			path: None,
+			authorize: None,
+		}
+	}
+
+	/// Only offer or accept these versions, ignoring any not in [setup::Versions::implemented].
+	/// This is also how to opt into experimental versions, ex. [setup::Version::DRAFT_03_FILTER].
+	pub fn with_versions(mut self, versions: setup::Versions) -> Self {
+		self.versions = versions;
+		self
+	}
+
+	/// Send this token to authorize the session, when connecting.
+	pub fn with_token(mut self, token: impl Into<setup::AuthToken>) -> Self {
+		self.token = Some(token.into());
+		self
+	}
+
+	/// Send this stickiness token in SETUP, see [setup::StickyToken].
+	pub fn with_sticky(mut self, sticky: impl Into<setup::StickyToken>) -> Self {
+		self.sticky = Some(sticky.into());
+		self
+	}
+
+	/// Send the path of the URL in SETUP, when connecting over raw QUIC or TCP, see [setup::Path].
+	pub fn with_path(mut self, path: setup::Path) -> Self {
+		self.path = Some(path);
+		self
+	}
+
+	/// Called with the client's token, if any, before accepting the session.
+	///
+	/// Returning an error closes the session with [super::SessionError::Unauthorized] instead of responding.
+	pub fn with_authorize<F, Fut>(mut self, authorize: F) -> Self
+	where
+		F: Fn(Option<setup::AuthToken>) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<(), ServeError>> + Send + 'static,
+	{
+		self.authorize = Some(Arc::new(move |token| authorize(token).boxed()));
+		self
+	}
+
+	pub(super) async fn authorize(&self, token: Option<setup::AuthToken>) -> Result<(), ServeError> {
+		match &self.authorize {
+			Some(authorize) => authorize(token).await,
+			None => Ok(()),
+		}
+	}
+}
+
+impl Default for SetupOptions {
+	fn default() -> Self {
+		Self::new(setup::Role::Both)
+	}
+}
@@ -0,0 +1,73 @@
+use crate::data;
+
+/// Maps each data stream onto a transport priority, see [super::Publisher::with_priority].
+///
+/// The transport sends streams with **higher** values first, while MoQ sends **smaller** send orders first.
+pub trait StreamPriority: Send + Sync {
+	/// Returns the transport priority for a new stream.
+	///
+	/// The send order is the subscriber's priority if it provided one, otherwise the one in the header.
+	fn priority(&self, send_order: u64, header: &data::Header) -> i32;
+}
+
+/// Uses the send order as-is, so streams with the same send order share the bandwidth.
+///
+/// Send orders beyond u32 are treated the same as u32::MAX.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SendOrder;
+
+impl StreamPriority for SendOrder {
+	fn priority(&self, send_order: u64, _header: &data::Header) -> i32 {
+		let send_order = send_order.min(u32::MAX as u64) as i64;
+		(i32::MAX as i64 - send_order) as i32
+	}
+}
+
+/// Sends newer groups first when they have the same send order, ex. for live media where older groups are stale.
+///
+/// Only the lowest 8 bits of the send order are used, saturating beyond that, followed by the lowest 23 bits of the group ID.
+/// The order of two groups is reversed when the group ID wraps, which takes 97 days at 1 group per second.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NewestFirst;
+
+impl StreamPriority for NewestFirst {
+	fn priority(&self, send_order: u64, header: &data::Header) -> i32 {
+		let send_order = send_order.min(0xff) as i32;
+		let group_id = (header.group_id().unwrap_or_default() & 0x7f_ffff) as i32;
+		((0xff - send_order) << 23) | group_id
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn group(group_id: u64, send_order: u64) -> data::Header {
+		data::GroupHeader {
+			subscribe_id: 0,
+			track_alias: 0,
+			group_id,
+			send_order,
+		}
+		.into()
+	}
+
+	#[test]
+	fn send_order() {
+		let priority = |send_order| SendOrder.priority(send_order, &group(0, send_order));
+		assert!(priority(0) > priority(1));
+		assert_eq!(priority(u32::MAX as u64), priority(u64::MAX));
+		assert_eq!(priority(u64::MAX), i32::MIN);
+	}
+
+	#[test]
+	fn newest_first() {
+		let priority = |group_id, send_order| NewestFirst.priority(send_order, &group(group_id, send_order));
+
+		// Newer groups win ties, but never over a smaller send order.
+		assert!(priority(2, 1) > priority(1, 1));
+		assert!(priority(1, 0) > priority(2, 1));
+		assert!(priority(0x7f_ffff, 0) > 0);
+		assert_eq!(priority(1, 0xff), priority(1, u64::MAX));
+	}
+}
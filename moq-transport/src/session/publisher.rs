@@ -1,60 +1,173 @@
 use std::{
-	collections::{hash_map, HashMap},
-	sync::{Arc, Mutex},
+	collections::{hash_map, HashMap, HashSet},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
 };
 
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 
 use crate::{
+	data,
 	message::{self, Message},
 	serve::{ServeError, TracksReader},
-	setup,
+	setup, transport,
 };
 
-use crate::watch::Queue;
+use crate::watch::{Queue, State};
 
-use super::{Announce, AnnounceRecv, Session, SessionError, Subscribed, SubscribedRecv};
+use super::clock::{serve_clock, CLOCK_TRACK};
+use super::{
+	Announce, AnnounceInfo, AnnounceRecv, Fetched, FetchedRecv, SendOrder, Session, SessionError, SetupOptions,
+	StreamPriority, Subscribed, SubscribedRecv, Writer,
+};
+#[cfg(feature = "chaos")]
+use super::{Chaos, ChaosAction};
 
 // TODO remove Clone.
 #[derive(Clone)]
 pub struct Publisher {
-	webtransport: web_transport::Session,
+	transport: transport::Session,
 
 	announces: Arc<Mutex<HashMap<String, AnnounceRecv>>>,
 	subscribed: Arc<Mutex<HashMap<u64, SubscribedRecv>>>,
 	unknown: Queue<Subscribed>,
 
+	fetched: Arc<Mutex<HashMap<u64, FetchedRecv>>>,
+	unknown_fetches: Queue<Fetched>,
+
 	outgoing: Queue<Message>,
+	capabilities: setup::Capabilities,
+
+	// Sent with each ANNOUNCE.
+	token: Arc<Mutex<Option<String>>>,
+
+	// Send bootstrap tracks reliably at the highest priority.
+	bootstrap: Arc<AtomicBool>,
+
+	// Maps each data stream onto a transport priority.
+	priority: Arc<Mutex<Arc<dyn StreamPriority>>>,
+
+	// Set once GOAWAY is sent, after which new subscriptions are rejected.
+	draining: State<bool>,
+
+	#[cfg(feature = "chaos")]
+	chaos: Arc<std::sync::OnceLock<Chaos>>,
 }
 
 impl Publisher {
-	pub(crate) fn new(outgoing: Queue<Message>, webtransport: web_transport::Session) -> Self {
+	pub(crate) fn new(
+		outgoing: Queue<Message>,
+		transport: transport::Session,
+		capabilities: setup::Capabilities,
+		draining: State<bool>,
+	) -> Self {
 		Self {
-			webtransport,
+			transport,
+			capabilities,
 			announces: Default::default(),
 			subscribed: Default::default(),
 			unknown: Default::default(),
+			fetched: Default::default(),
+			unknown_fetches: Default::default(),
 			outgoing,
+			token: Default::default(),
+			bootstrap: Arc::new(AtomicBool::new(true)),
+			priority: Arc::new(Mutex::new(Arc::new(SendOrder))),
+			draining,
+			#[cfg(feature = "chaos")]
+			chaos: Default::default(),
 		}
 	}
 
-	pub async fn accept(session: web_transport::Session) -> Result<(Session, Publisher), SessionError> {
+	pub async fn accept(session: impl Into<transport::Session>) -> Result<(Session, Publisher), SessionError> {
 		let (session, publisher, _) = Session::accept_role(session, setup::Role::Publisher).await?;
 		Ok((session, publisher.unwrap()))
 	}
 
-	pub async fn connect(session: web_transport::Session) -> Result<(Session, Publisher), SessionError> {
+	pub async fn connect(session: impl Into<transport::Session>) -> Result<(Session, Publisher), SessionError> {
 		let (session, publisher, _) = Session::connect_role(session, setup::Role::Publisher).await?;
 		Ok((session, publisher.unwrap()))
 	}
 
+	/// Connect using the provided options, ex. to send a [setup::Path], although the role is always publisher.
+	pub async fn connect_with(
+		session: impl Into<transport::Session>,
+		mut options: SetupOptions,
+	) -> Result<(Session, Publisher), SessionError> {
+		options.role = setup::Role::Publisher;
+		let (session, publisher, _) = Session::connect_with(session, options).await?;
+		Ok((session, publisher.unwrap()))
+	}
+
+	/// The optional extensions supported by both endpoints, negotiated during SETUP.
+	pub fn capabilities(&self) -> setup::Capabilities {
+		self.capabilities
+	}
+
+	/// Send this authorization token with each ANNOUNCE, applying to every clone.
+	pub fn with_token(self, token: impl Into<String>) -> Self {
+		*self.token.lock().unwrap() = Some(token.into());
+		self
+	}
+
+	/// Send the catalog and init tracks reliably at the highest priority, applying to every clone.
+	///
+	/// Enabled by default, see [crate::serve::TrackName::is_bootstrap].
+	pub fn with_bootstrap_priority(self, enabled: bool) -> Self {
+		self.bootstrap.store(enabled, Ordering::Relaxed);
+		self
+	}
+
+	pub(super) fn bootstrap_priority(&self) -> bool {
+		self.bootstrap.load(Ordering::Relaxed)
+	}
+
+	/// Choose how data streams are prioritized by the transport, applying to every clone.
+	///
+	/// Defaults to [SendOrder]; use [super::NewestFirst] for live media when the send order doesn't favor newer groups.
+	pub fn with_priority(self, priority: impl StreamPriority + 'static) -> Self {
+		*self.priority.lock().unwrap() = Arc::new(priority);
+		self
+	}
+
 	/// Announce a namespace and serve tracks using the provided [serve::TracksReader].
 	/// The caller uses [serve::TracksWriter] for static tracks and [serve::TracksRequest] for dynamic tracks.
+	///
+	/// Returns once both are dropped and every subscription has been served, sending an UNANNOUNCE.
 	pub async fn announce(&mut self, tracks: TracksReader) -> Result<(), SessionError> {
-		let mut announce = match self.announces.lock().unwrap().entry(tracks.namespace.clone()) {
+		self.announce_inner(tracks, None).await
+	}
+
+	/// Same as [Self::announce], but proves ownership of the namespace with a signature.
+	///
+	/// The signature must be over [message::AnnounceSignature::payload] for this namespace.
+	pub async fn announce_signed(
+		&mut self,
+		tracks: TracksReader,
+		signature: message::AnnounceSignature,
+	) -> Result<(), SessionError> {
+		self.announce_inner(tracks, Some(signature)).await
+	}
+
+	async fn announce_inner(
+		&mut self,
+		tracks: TracksReader,
+		signature: Option<message::AnnounceSignature>,
+	) -> Result<(), SessionError> {
+		let announce = match self.announces.lock().unwrap().entry(tracks.namespace.clone()) {
 			hash_map::Entry::Occupied(_) => return Err(ServeError::Duplicate.into()),
 			hash_map::Entry::Vacant(entry) => {
-				let (send, recv) = Announce::new(self.clone(), tracks.namespace.clone());
+				let info = AnnounceInfo {
+					namespace: tracks.namespace.clone(),
+					signature,
+					broadcast_id: tracks.broadcast_id.clone(),
+					authorization: self.token.lock().unwrap().clone(),
+				};
+
+				let (send, recv) = Announce::new(self.clone(), info);
 				entry.insert(recv);
 				send
 			}
@@ -79,8 +192,26 @@ impl Publisher {
 						if let Err(err) = Self::serve_subscribe(subscribe, tracks).await {
 							log::warn!("failed serving subscribe: {:?}, error: {}", info, err)
 						}
-					});
+					}.boxed());
+				},
+				fetch = announce.fetched(), if done.is_none() => {
+					let fetch = match fetch {
+						Ok(Some(fetch)) => fetch,
+						Ok(None) => { done = Some(Ok(())); continue },
+						Err(err) => { done = Some(Err(err)); continue },
+					};
+
+					let tracks = tracks.clone();
+
+					tasks.push(async move {
+						let info = fetch.info.clone();
+						if let Err(err) = Self::serve_fetch(fetch, tracks).await {
+							log::warn!("failed serving fetch: {:?}, error: {}", info, err)
+						}
+					}.boxed());
 				},
+				// Stop accepting subscriptions once the broadcast ends, but finish serving the existing ones.
+				_ = tracks.closed(), if done.is_none() => done = Some(Ok(())),
 				_ = tasks.next(), if !tasks.is_empty() => {},
 				else => return Ok(done.unwrap()?)
 			}
@@ -97,11 +228,26 @@ impl Publisher {
 		Ok(())
 	}
 
+	pub async fn serve_fetch(fetch: Fetched, mut tracks: TracksReader) -> Result<(), SessionError> {
+		if let Some(track) = tracks.subscribe(&fetch.name) {
+			fetch.serve(track).await?;
+		} else {
+			fetch.close(ServeError::NotFound)?;
+		}
+
+		Ok(())
+	}
+
 	// Returns subscriptions that do not map to an active announce.
 	pub async fn subscribed(&mut self) -> Option<Subscribed> {
 		self.unknown.pop().await
 	}
 
+	// Returns fetches that do not map to an active announce.
+	pub async fn fetched(&mut self) -> Option<Fetched> {
+		self.unknown_fetches.pop().await
+	}
+
 	pub(crate) fn recv_message(&mut self, msg: message::Subscriber) -> Result<(), SessionError> {
 		let res = match msg {
 			message::Subscriber::AnnounceOk(msg) => self.recv_announce_ok(msg),
@@ -109,6 +255,12 @@ impl Publisher {
 			message::Subscriber::AnnounceCancel(msg) => self.recv_announce_cancel(msg),
 			message::Subscriber::Subscribe(msg) => self.recv_subscribe(msg),
 			message::Subscriber::Unsubscribe(msg) => self.recv_unsubscribe(msg),
+			message::Subscriber::SubscribeRenewOk(msg) => self.recv_subscribe_renew_ok(msg),
+			message::Subscriber::SubscribeUpdate(msg) => self.recv_subscribe_update(msg),
+			message::Subscriber::SubscribeAck(msg) => self.recv_subscribe_ack(msg),
+			message::Subscriber::Batch(msg) => self.recv_batch(msg),
+			message::Subscriber::Fetch(msg) => self.recv_fetch(msg),
+			message::Subscriber::FetchCancel(msg) => self.recv_fetch_cancel(msg),
 		};
 
 		if let Err(err) = res {
@@ -136,7 +288,7 @@ impl Publisher {
 
 	fn recv_announce_cancel(&mut self, msg: message::AnnounceCancel) -> Result<(), SessionError> {
 		if let Some(announce) = self.announces.lock().unwrap().remove(&msg.namespace) {
-			announce.recv_error(ServeError::Cancel)?;
+			announce.recv_error(ServeError::Closed(msg.code))?;
 		}
 
 		Ok(())
@@ -145,6 +297,16 @@ impl Publisher {
 	fn recv_subscribe(&mut self, msg: message::Subscribe) -> Result<(), SessionError> {
 		let namespace = msg.track_namespace.clone();
 
+		// Malformed parameters are treated as missing, like the authorization token.
+		let delivery_timeout = msg
+			.params
+			.clone()
+			.get::<u64>(message::Subscribe::DELIVERY_TIMEOUT)
+			.ok()
+			.flatten()
+			.map(Duration::from_millis);
+		let window = msg.params.clone().get_typed::<message::ReceiveWindow>().ok().flatten();
+
 		let subscribe = {
 			let mut subscribes = self.subscribed.lock().unwrap();
 
@@ -154,12 +316,29 @@ impl Publisher {
 				hash_map::Entry::Vacant(entry) => entry,
 			};
 
-			let (send, recv) = Subscribed::new(self.clone(), msg);
+			let (send, recv) = Subscribed::new(self.clone(), msg, delivery_timeout, window);
 			entry.insert(recv);
 
 			send
 		};
 
+		// The subscriber should subscribe on the session it was redirected to instead.
+		if let Some(err) = self.draining() {
+			subscribe.close(err)?;
+			return Ok(());
+		}
+
+		// Answer the clock-sync track ourselves, whatever the namespace.
+		if self.capabilities.clock && subscribe.name == CLOCK_TRACK {
+			tokio::spawn(async move {
+				if let Err(err) = serve_clock(subscribe).await {
+					log::debug!("failed serving clock: {}", err);
+				}
+			});
+
+			return Ok(());
+		}
+
 		// If we have an announce, route the subscribe to it.
 		if let Some(announce) = self.announces.lock().unwrap().get_mut(&namespace) {
 			return announce.recv_subscribe(subscribe).map_err(Into::into);
@@ -183,11 +362,129 @@ impl Publisher {
 		Ok(())
 	}
 
+	fn recv_subscribe_renew_ok(&mut self, msg: message::SubscribeRenewOk) -> Result<(), SessionError> {
+		if let Some(subscribed) = self.subscribed.lock().unwrap().get_mut(&msg.id) {
+			subscribed.recv_renew(msg.authorization)?;
+		}
+
+		Ok(())
+	}
+
+	fn recv_subscribe_update(&mut self, msg: message::SubscribeUpdate) -> Result<(), SessionError> {
+		if let Some(subscribed) = self.subscribed.lock().unwrap().get_mut(&msg.id) {
+			subscribed.recv_update(msg)?;
+		}
+
+		Ok(())
+	}
+
+	fn recv_subscribe_ack(&mut self, msg: message::SubscribeAck) -> Result<(), SessionError> {
+		if let Some(subscribed) = self.subscribed.lock().unwrap().get_mut(&msg.id) {
+			subscribed.recv_ack(msg.group_id)?;
+		}
+
+		Ok(())
+	}
+
+	// Apply the messages in order, but only if every SUBSCRIBE can be accepted, so a rendition switch isn't half applied.
+	fn recv_batch(&mut self, msg: message::Batch) -> Result<(), SessionError> {
+		let subscribes: Vec<u64> = msg
+			.messages
+			.iter()
+			.filter_map(|msg| match msg {
+				message::Subscriber::Subscribe(msg) => Some(msg.id),
+				_ => None,
+			})
+			.collect();
+
+		// IDs that are already in use, or used twice in the batch.
+		let duplicates: HashSet<u64> = {
+			let subscribed = self.subscribed.lock().unwrap();
+			let mut ids = HashSet::new();
+			subscribes
+				.iter()
+				.filter(|id| subscribed.contains_key(id) || !ids.insert(**id))
+				.copied()
+				.collect()
+		};
+
+		if !duplicates.is_empty() {
+			// Only the other subscriptions can be told they were rejected, since the duplicate IDs are in use.
+			for id in subscribes.into_iter().filter(|id| !duplicates.contains(id)) {
+				self.send_message(message::SubscribeError {
+					id,
+					alias: 0,
+					code: SessionError::Duplicate.code(),
+					reason: format!("batch rejected: duplicate subscribe ids={:?}", duplicates),
+				});
+			}
+
+			return Err(SessionError::Duplicate);
+		}
+
+		for msg in msg.messages {
+			self.recv_message(msg)?;
+		}
+
+		Ok(())
+	}
+
+	// The error used to reject new subscriptions once GOAWAY was sent.
+	fn draining(&self) -> Option<ServeError> {
+		let err = SessionError::GoAway;
+		self.draining
+			.lock()
+			.then(|| ServeError::Rejected(err.code(), err.to_string()))
+	}
+
+	fn recv_fetch(&mut self, msg: message::Fetch) -> Result<(), SessionError> {
+		let namespace = msg.track_namespace.clone();
+
+		let fetch = {
+			let mut fetched = self.fetched.lock().unwrap();
+
+			let entry = match fetched.entry(msg.id) {
+				hash_map::Entry::Occupied(_) => return Err(SessionError::Duplicate),
+				hash_map::Entry::Vacant(entry) => entry,
+			};
+
+			let (send, recv) = Fetched::new(self.clone(), msg);
+			entry.insert(recv);
+
+			send
+		};
+
+		if let Some(err) = self.draining() {
+			fetch.close(err)?;
+			return Ok(());
+		}
+
+		// Same as a subscribe, route the fetch to the announce if we have one.
+		if let Some(announce) = self.announces.lock().unwrap().get_mut(&namespace) {
+			return announce.recv_fetch(fetch).map_err(Into::into);
+		}
+
+		if let Err(err) = self.unknown_fetches.push(fetch) {
+			err.close(ServeError::NotFound)?;
+		}
+
+		Ok(())
+	}
+
+	fn recv_fetch_cancel(&mut self, msg: message::FetchCancel) -> Result<(), SessionError> {
+		if let Some(fetched) = self.fetched.lock().unwrap().get_mut(&msg.id) {
+			fetched.recv_cancel()?;
+		}
+
+		Ok(())
+	}
+
 	pub(super) fn send_message<T: Into<message::Publisher> + Into<Message>>(&mut self, msg: T) {
 		let msg = msg.into();
 		match &msg {
 			message::Publisher::SubscribeDone(msg) => self.drop_subscribe(msg.id),
 			message::Publisher::SubscribeError(msg) => self.drop_subscribe(msg.id),
+			message::Publisher::FetchError(msg) => self.drop_fetch(msg.id),
 			message::Publisher::Unannounce(msg) => self.drop_announce(msg.namespace.as_str()),
 			_ => (),
 		};
@@ -199,15 +496,59 @@ impl Publisher {
 		self.subscribed.lock().unwrap().remove(&id);
 	}
 
+	pub(super) fn drop_fetch(&mut self, id: u64) {
+		self.fetched.lock().unwrap().remove(&id);
+	}
+
 	fn drop_announce(&mut self, namespace: &str) {
 		self.announces.lock().unwrap().remove(namespace);
 	}
 
-	pub(super) async fn open_uni(&mut self) -> Result<web_transport::SendStream, SessionError> {
-		Ok(self.webtransport.open_uni().await?)
+	#[cfg(feature = "chaos")]
+	pub(super) fn set_chaos(&self, chaos: Chaos) {
+		self.chaos.set(chaos).ok();
+	}
+
+	/// Open a data stream and write its header.
+	///
+	/// Returns None if the stream was dropped or reset by [Chaos](super::Chaos), in which case nothing more should be sent.
+	pub(super) async fn open_stream(
+		&mut self,
+		priority: u64,
+		header: &data::Header,
+	) -> Result<Option<Writer>, SessionError> {
+		#[cfg(feature = "chaos")]
+		let action = self.chaos.get().map(|chaos| chaos.stream());
+
+		#[cfg(feature = "chaos")]
+		match action {
+			Some(ChaosAction::Drop) => {
+				log::debug!("chaos dropped stream: {:?}", header);
+				return Ok(None);
+			}
+			Some(ChaosAction::Delay(delay)) => tokio::time::sleep(delay).await,
+			_ => {}
+		}
+
+		let mut stream = self.transport.open_uni().await?;
+
+		let priority = self.priority.lock().unwrap().priority(priority, header);
+		stream.set_priority(priority);
+
+		let mut writer = Writer::new(stream);
+		writer.encode(header).await?;
+
+		#[cfg(feature = "chaos")]
+		if let Some(ChaosAction::Reset) = action {
+			log::debug!("chaos reset stream: {:?}", header);
+			writer.reset(SessionError::Internal.code() as u32);
+			return Ok(None);
+		}
+
+		Ok(Some(writer))
 	}
 
 	pub(super) async fn send_datagram(&mut self, data: bytes::Bytes) -> Result<(), SessionError> {
-		Ok(self.webtransport.send_datagram(data).await?)
+		self.transport.send_datagram(data).await
 	}
 }
@@ -0,0 +1,124 @@
+use crate::coding::Encode;
+use crate::data;
+use crate::message::Message;
+use crate::{setup, transport};
+
+use super::{Reader, SessionError, Writer};
+
+/// Direct access to the control stream after SETUP, see [super::Session::into_raw].
+///
+/// Nothing is validated or answered automatically, so this can send messages a well-behaved endpoint never would,
+/// ex. to test that the peer rejects them. It's not meant for anything else.
+pub struct RawSession {
+	transport: transport::Session,
+	sender: Writer,
+	recver: Reader,
+	version: setup::Version,
+	capabilities: setup::Capabilities,
+}
+
+impl RawSession {
+	pub(super) fn new(
+		transport: transport::Session,
+		sender: Writer,
+		recver: Reader,
+		version: setup::Version,
+		capabilities: setup::Capabilities,
+	) -> Self {
+		Self {
+			transport,
+			sender,
+			recver,
+			version,
+			capabilities,
+		}
+	}
+
+	/// The version negotiated during the setup, which decides the wire format of some messages.
+	pub fn version(&self) -> setup::Version {
+		self.version
+	}
+
+	/// The optional extensions supported by both endpoints, which decide the messages the peer understands.
+	pub fn capabilities(&self) -> setup::Capabilities {
+		self.capabilities
+	}
+
+	/// Send a control message using the wire format of the negotiated version.
+	pub async fn send(&mut self, msg: impl Into<Message>) -> Result<(), SessionError> {
+		let msg = msg.into();
+		log::debug!("sending raw message: {:?}", msg);
+
+		let version = self.version;
+		self.sender
+			.encode_with(|buffer| msg.encode_version(buffer, version))
+			.await
+	}
+
+	/// Write arbitrary bytes to the control stream, ex. a truncated or unknown message.
+	pub async fn send_bytes(&mut self, buf: &[u8]) -> Result<(), SessionError> {
+		log::debug!("sending raw bytes: {:?}", buf);
+		self.sender.write(buf).await
+	}
+
+	/// Receive the next control message, or the error that closed the session.
+	pub async fn recv(&mut self) -> Result<Message, SessionError> {
+		let version = self.version;
+		let msg = self
+			.recver
+			.decode_with(|cursor| Message::decode_version(cursor, version))
+			.await?;
+		log::debug!("received raw message: {:?}", msg);
+
+		Ok(msg)
+	}
+
+	/// Open a data stream, ex. to write objects out of order.
+	pub async fn open_uni(&mut self) -> Result<RawStream, SessionError> {
+		let stream = self.transport.open_uni().await?;
+		Ok(RawStream {
+			writer: Writer::new(stream),
+			capabilities: self.capabilities,
+		})
+	}
+
+	/// Block until the session is closed, returning the reason.
+	pub async fn closed(&self) -> SessionError {
+		self.transport.closed().await
+	}
+
+	pub fn close(self, code: u32, reason: &str) {
+		self.transport.close(code, reason)
+	}
+}
+
+/// A data stream opened by [RawSession::open_uni], finished when dropped.
+pub struct RawStream {
+	writer: Writer,
+	capabilities: setup::Capabilities,
+}
+
+impl RawStream {
+	/// Encode a header or object header.
+	pub async fn encode<T: Encode>(&mut self, msg: &T) -> Result<(), SessionError> {
+		self.writer.encode(msg).await
+	}
+
+	/// Encode an object on a group or subgroup stream, with its extension headers if they were negotiated.
+	pub async fn object(&mut self, object: &data::GroupObject) -> Result<(), SessionError> {
+		match self.capabilities.extensions {
+			true => self.writer.encode_with(|buffer| object.encode_extended(buffer)).await,
+			false => self.writer.encode(object).await,
+		}
+	}
+
+	/// Write arbitrary bytes, ex. an object payload.
+	pub async fn write(&mut self, buf: &[u8]) -> Result<(), SessionError> {
+		self.writer.write(buf).await
+	}
+
+	/// Reset the stream, abandoning anything not yet delivered.
+	pub fn reset(self, code: u32) {
+		self.writer.reset(code)
+	}
+}
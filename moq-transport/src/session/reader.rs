@@ -3,16 +3,17 @@ use std::{cmp, io};
 use bytes::{Buf, Bytes, BytesMut};
 
 use crate::coding::{Decode, DecodeError};
+use crate::transport;
 
 use super::SessionError;
 
 pub struct Reader {
-	stream: web_transport::RecvStream,
+	stream: transport::RecvStream,
 	buffer: BytesMut,
 }
 
 impl Reader {
-	pub fn new(stream: web_transport::RecvStream) -> Self {
+	pub fn new(stream: transport::RecvStream) -> Self {
 		Self {
 			stream,
 			buffer: Default::default(),
@@ -20,11 +21,19 @@ impl Reader {
 	}
 
 	pub async fn decode<T: Decode>(&mut self) -> Result<T, SessionError> {
+		self.decode_with(|cursor| T::decode(cursor)).await
+	}
+
+	/// Decode using the provided function, ex. for a format that depends on the negotiated version.
+	pub async fn decode_with<T, F>(&mut self, decode: F) -> Result<T, SessionError>
+	where
+		F: Fn(&mut io::Cursor<&BytesMut>) -> Result<T, DecodeError>,
+	{
 		loop {
 			let mut cursor = io::Cursor::new(&self.buffer);
 
 			// Try to decode with the current buffer.
-			let required = match T::decode(&mut cursor) {
+			let required = match decode(&mut cursor) {
 				Ok(msg) => {
 					self.buffer.advance(cursor.position() as usize);
 					return Ok(msg);
@@ -54,7 +63,7 @@ impl Reader {
 			return Ok(Some(data));
 		}
 
-		Ok(self.stream.read_chunk(max).await?)
+		self.stream.read_chunk(max).await
 	}
 
 	pub async fn done(&mut self) -> Result<bool, SessionError> {
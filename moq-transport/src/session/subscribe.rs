@@ -1,8 +1,11 @@
-use std::ops;
+use std::{collections::HashMap, ops, str::FromStr, time::Duration};
+
+use futures::future::{BoxFuture, FutureExt};
 
 use crate::{
+	coding::Params,
 	data,
-	message::{self, SubscribeLocation, SubscribePair},
+	message::{self, GroupOrder, SubscribeLocation, SubscribePair},
 	serve::{self, ServeError, TrackWriter, TrackWriterMode},
 };
 
@@ -16,9 +19,183 @@ pub struct SubscribeInfo {
 	pub name: String,
 }
 
+impl SubscribeInfo {
+	/// Split the track name into a path and query parameters, ex. `video.m4s?rendition=720`.
+	pub fn track_name(&self) -> serve::TrackName {
+		serve::TrackName::parse(&self.name)
+	}
+
+	/// Parse a query parameter from the track name, returning None if it's missing.
+	pub fn param<T: FromStr>(&self, key: &str) -> Result<Option<T>, T::Err> {
+		self.track_name().param(key)
+	}
+}
+
+/// A snapshot of a subscription's delivery progress, see [Subscribe::stats] and [super::Subscribed::stats].
+///
+/// The publisher counts what it sent and the subscriber counts what it received, so comparing them shows what was lost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubscribeStats {
+	/// The number of groups started, counting a group split into subgroups once.
+	pub groups: u64,
+
+	/// The number of objects, or datagrams.
+	pub objects: u64,
+
+	/// The payload bytes of those objects.
+	pub bytes: u64,
+
+	/// The largest group ID so far, or zero if nothing was delivered.
+	pub last_group: u64,
+}
+
+impl SubscribeStats {
+	pub(super) fn group(&mut self, group_id: u64) {
+		self.groups += 1;
+		self.last_group = self.last_group.max(group_id);
+	}
+
+	pub(super) fn object(&mut self, group_id: u64, size: u64) {
+		self.objects += 1;
+		self.bytes += size;
+		self.last_group = self.last_group.max(group_id);
+	}
+
+	// Count an object that isn't part of a group stream, along with its group if it's newer than the last one.
+	pub(super) fn ungrouped(&mut self, group_id: u64, size: u64) {
+		if self.objects == 0 || group_id > self.last_group {
+			self.groups += 1;
+		}
+
+		self.object(group_id, size);
+	}
+}
+
+/// Options controlling how a subscription is received.
+#[derive(Debug, Clone, Copy)]
+pub struct SubscribeOptions {
+	/// The maximum number of object IDs that may be skipped within a group stream.
+	///
+	/// Objects arriving ahead of the expected ID are buffered until the gap is filled.
+	/// The stream errors if an object is further ahead than this, or if a gap remains when the stream ends.
+	pub reorder_window: u64,
+
+	/// Request this group ID instead of the latest group, ex. to resume after a disconnect.
+	///
+	/// NOTE: The publisher may not have the group cached, in which case it will start at the latest group anyway.
+	pub start_group: Option<u64>,
+
+	/// Skip objects before this ID within [Self::start_group], ex. to resume partway through a group.
+	///
+	/// Ignored unless a start group is provided.
+	pub start_object: Option<u64>,
+
+	/// Stop after this group ID, ex. to fetch a range of past groups along with [Self::start_group].
+	pub end_group: Option<u64>,
+
+	/// Ask the publisher to reset a group stream when an object takes longer than this to deliver,
+	/// instead of falling further behind.
+	pub delivery_timeout: Option<Duration>,
+
+	/// Ask the publisher to deliver pending groups in this order, ex. [GroupOrder::Ascending] for VOD.
+	pub group_order: GroupOrder,
+
+	/// Limit the groups and bytes in flight, acknowledging each group stream once it's received.
+	///
+	/// Ignored unless the window capability was negotiated.
+	pub receive_window: Option<message::ReceiveWindow>,
+}
+
+impl Default for SubscribeOptions {
+	fn default() -> Self {
+		Self {
+			reorder_window: 4,
+			start_group: None,
+			start_object: None,
+			end_group: None,
+			delivery_timeout: None,
+			group_order: GroupOrder::Publisher,
+			receive_window: None,
+		}
+	}
+}
+
+/// The range of past groups to retrieve, see [Subscriber::fetch].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchOptions {
+	/// The first group to fetch.
+	pub start_group: u64,
+
+	/// The last group to fetch, or None for everything up to the latest group.
+	pub end_group: Option<u64>,
+}
+
+/// Changes to an active subscription, see [Subscriber::update].
+///
+/// Each field replaces what was requested so far, rather than only the ones that are set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubscribeUpdate {
+	/// Skip groups before this ID, or None to continue from the latest group.
+	pub start_group: Option<u64>,
+
+	/// Stop after this group ID, or None to continue indefinitely.
+	pub end_group: Option<u64>,
+
+	/// Deliver this subscription at this priority instead of the one chosen by the publisher.
+	pub priority: Option<u64>,
+}
+
+// The SUBSCRIBE range for the requested start group and object, and end group.
+pub(super) fn subscribe_range(
+	start_group: Option<u64>,
+	start_object: Option<u64>,
+	end_group: Option<u64>,
+) -> (SubscribePair, SubscribePair) {
+	let start = SubscribePair {
+		group: match start_group {
+			Some(group) => SubscribeLocation::Absolute(group),
+			None => SubscribeLocation::Latest(0),
+		},
+		object: SubscribeLocation::Absolute(start_group.and(start_object).unwrap_or(0)),
+	};
+
+	let end = SubscribePair {
+		group: match end_group {
+			Some(group) => SubscribeLocation::Absolute(group),
+			None => SubscribeLocation::None,
+		},
+		object: SubscribeLocation::None,
+	};
+
+	(start, end)
+}
+
+/// How a subscription ended without an error, from the publisher's SUBSCRIBE_DONE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscribeClosed {
+	pub status: message::SubscribeStatus,
+
+	/// The final group/object sent by the publisher.
+	pub last: Option<(u64, u64)>,
+
+	/// The largest group/object received.
+	pub received: Option<(u64, u64)>,
+}
+
+impl SubscribeClosed {
+	/// Returns true if the final group/object never arrived, ex. because its stream was reset.
+	///
+	/// Only the end of the subscription is checked; gaps earlier in the track aren't detected.
+	pub fn missed(&self) -> bool {
+		self.last > self.received
+	}
+}
+
 struct SubscribeState {
 	ok: bool,
 	closed: Result<(), ServeError>,
+	done: Option<SubscribeClosed>,
+	stats: SubscribeStats,
 }
 
 impl Default for SubscribeState {
@@ -26,6 +203,8 @@ impl Default for SubscribeState {
 		Self {
 			ok: Default::default(),
 			closed: Ok(()),
+			done: None,
+			stats: Default::default(),
 		}
 	}
 }
@@ -36,29 +215,97 @@ pub struct Subscribe {
 	state: State<SubscribeState>,
 	subscriber: Subscriber,
 	id: u64,
+	fetch: bool,
 
 	pub info: SubscribeInfo,
 }
 
 impl Subscribe {
-	pub(super) fn new(mut subscriber: Subscriber, id: u64, track: TrackWriter) -> (Subscribe, SubscribeRecv) {
+	pub(super) fn new(
+		mut subscriber: Subscriber,
+		id: u64,
+		track: TrackWriter,
+		mut options: SubscribeOptions,
+	) -> (Subscribe, SubscribeRecv) {
+		let mut params = Params::new();
+		if let Some(token) = subscriber.token() {
+			// Can't fail, since it's encoded to a Vec.
+			params.set(message::Subscribe::AUTHORIZATION, token).ok();
+		}
+
+		if let Some(timeout) = options.delivery_timeout {
+			params
+				.set(message::Subscribe::DELIVERY_TIMEOUT, timeout.as_millis() as u64)
+				.ok();
+		}
+
+		// Otherwise the publisher wouldn't enforce the window, nor understand our acknowledgements.
+		if !subscriber.capabilities().window {
+			options.receive_window = None;
+		}
+
+		if let Some(window) = options.receive_window {
+			params.set_typed(window).ok();
+		}
+
+		let (start, end) = subscribe_range(options.start_group, options.start_object, options.end_group);
+
 		subscriber.send_message(message::Subscribe {
 			id,
 			track_alias: id,
 			track_namespace: track.namespace.clone(),
 			track_name: track.name.clone(),
-			// TODO add these to the publisher.
-			start: SubscribePair {
-				group: SubscribeLocation::Latest(0),
-				object: SubscribeLocation::Absolute(0),
-			},
-			end: SubscribePair {
-				group: SubscribeLocation::None,
-				object: SubscribeLocation::None,
-			},
-			params: Default::default(),
+			start: start.clone(),
+			end: end.clone(),
+			group_order: options.group_order,
+			params,
+		});
+
+		// SUBSCRIBE has no priority, so update it immediately in case the publisher doesn't prioritize the track itself.
+		if subscriber.bootstrap_priority(&track.name) {
+			subscriber.send_message(message::SubscribeUpdate {
+				id,
+				start,
+				end,
+				priority: Some(serve::BOOTSTRAP_PRIORITY),
+				params: Default::default(),
+			});
+		}
+
+		Self::split(subscriber, id, track, options, false)
+	}
+
+	pub(super) fn fetch(
+		mut subscriber: Subscriber,
+		id: u64,
+		track: TrackWriter,
+		options: FetchOptions,
+	) -> (Subscribe, SubscribeRecv) {
+		let mut params = Params::new();
+		if let Some(token) = subscriber.token() {
+			// Same as SUBSCRIBE, so fetches are authorized the same way.
+			params.set(message::Subscribe::AUTHORIZATION, token).ok();
+		}
+
+		subscriber.send_message(message::Fetch {
+			id,
+			track_namespace: track.namespace.clone(),
+			track_name: track.name.clone(),
+			start_group: options.start_group,
+			end_group: options.end_group,
+			params,
 		});
 
+		Self::split(subscriber, id, track, Default::default(), true)
+	}
+
+	fn split(
+		subscriber: Subscriber,
+		id: u64,
+		track: TrackWriter,
+		options: SubscribeOptions,
+		fetch: bool,
+	) -> (Subscribe, SubscribeRecv) {
 		let info = SubscribeInfo {
 			namespace: track.namespace.clone(),
 			name: track.name.clone(),
@@ -66,40 +313,102 @@ impl Subscribe {
 
 		let (send, recv) = State::default().split();
 
+		let recv = SubscribeRecv {
+			info: info.clone(),
+			state: recv,
+			writer: Some(track.into()),
+			options,
+			fetch,
+			received: None,
+			last: None,
+			subgroups: HashMap::new(),
+		};
+
 		let send = Subscribe {
 			state: send,
 			subscriber,
 			id,
+			fetch,
 			info,
 		};
 
-		let recv = SubscribeRecv {
-			state: recv,
-			writer: Some(track.into()),
-		};
-
 		(send, recv)
 	}
 
-	pub async fn closed(&self) -> Result<(), ServeError> {
+	/// Block until the publisher accepts the subscription with SUBSCRIBE_OK.
+	pub async fn ready(&self) -> Result<(), ServeError> {
 		loop {
 			{
 				let state = self.state.lock();
 				state.closed.clone()?;
 
+				if state.ok {
+					return Ok(());
+				}
+
 				match state.modified() {
 					Some(notify) => notify,
-					None => return Ok(()),
+					None => return Err(ServeError::Cancel),
 				}
 			}
 			.await;
 		}
 	}
+
+	/// The groups, objects and bytes received so far.
+	pub fn stats(&self) -> SubscribeStats {
+		self.state.lock().stats
+	}
+
+	/// Block until the subscription is closed, or until every reader of the track has been dropped.
+	///
+	/// Returns how the publisher ended the subscription, or None if it was closed locally or was a FETCH.
+	pub async fn closed(&self) -> Result<Option<SubscribeClosed>, ServeError> {
+		tokio::select! {
+			res = self.closed_inner() => res,
+			_ = self.unused() => Ok(None),
+		}
+	}
+
+	async fn closed_inner(&self) -> Result<Option<SubscribeClosed>, ServeError> {
+		loop {
+			{
+				let state = self.state.lock();
+				state.closed.clone()?;
+
+				let done = state.done;
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Ok(done),
+				}
+			}
+			.await;
+		}
+	}
+
+	async fn unused(&self) {
+		loop {
+			let mut unused = match self.subscriber.subscribe_unused(self.id) {
+				Some(unused) => unused,
+				None => return futures::future::pending().await,
+			};
+
+			// The future also fires when the writer is replaced (ex. the mode is chosen), so check the current writer.
+			if (&mut unused).now_or_never().is_some() {
+				return;
+			}
+
+			unused.await;
+		}
+	}
 }
 
 impl Drop for Subscribe {
 	fn drop(&mut self) {
-		self.subscriber.send_message(message::Unsubscribe { id: self.id });
+		match self.fetch {
+			true => self.subscriber.send_message(message::FetchCancel { id: self.id }),
+			false => self.subscriber.send_message(message::Unsubscribe { id: self.id }),
+		}
 	}
 }
 
@@ -112,17 +421,45 @@ impl ops::Deref for Subscribe {
 }
 
 pub(super) struct SubscribeRecv {
+	pub info: SubscribeInfo,
 	state: State<SubscribeState>,
 	writer: Option<TrackWriterMode>,
+	options: SubscribeOptions,
+
+	// A FETCH rather than a SUBSCRIBE, finished once its stream ends.
+	pub fetch: bool,
+
+	// The largest group/object received so far.
+	received: Option<(u64, u64)>,
+
+	// The final group/object from SUBSCRIBE_DONE, while we wait for it to arrive.
+	last: Option<(u64, u64)>,
+
+	// Groups split into subgroups, kept open until each subgroup stream has arrived.
+	// Each entry holds the group's writer and the number of subgroup streams still expected.
+	subgroups: HashMap<u64, (serve::GroupWriter, u64)>,
 }
 
 impl SubscribeRecv {
-	pub fn ok(&mut self) -> Result<(), ServeError> {
+	pub fn options(&self) -> SubscribeOptions {
+		self.options
+	}
+
+	pub fn unused(&self) -> Option<BoxFuture<'static, ()>> {
+		self.writer.as_ref().map(|writer| writer.unused())
+	}
+
+	pub fn ok(&mut self, epoch: Option<u64>) -> Result<(), ServeError> {
 		let state = self.state.lock();
 		if state.ok {
 			return Err(ServeError::Duplicate);
 		}
 
+		// Data may arrive before SUBSCRIBE_OK, in which case the mode was already chosen.
+		if let (Some(epoch), Some(writer)) = (epoch, &mut self.writer) {
+			writer.set_epoch(epoch)?;
+		}
+
 		if let Some(mut state) = state.into_mut() {
 			state.ok = true;
 		}
@@ -130,6 +467,49 @@ impl SubscribeRecv {
 		Ok(())
 	}
 
+	/// Handle a SUBSCRIBE_DONE without an error, returning true if the subscription is finished.
+	///
+	/// The final group may still be in flight, in which case we keep accepting streams until it arrives.
+	pub fn done(&mut self, status: message::SubscribeStatus, last: Option<(u64, u64)>) -> bool {
+		// The received group/object is filled in on drop, once nothing else can arrive.
+		if let Some(mut state) = self.state.lock_mut() {
+			state.done = Some(SubscribeClosed {
+				status,
+				last,
+				received: None,
+			});
+		}
+
+		// A single stream carries the entire track, so there's nothing left to wait for.
+		if let Some(TrackWriterMode::Stream(_)) = self.writer {
+			return true;
+		}
+
+		match last {
+			Some(last) if self.received < Some(last) => {
+				self.last = Some(last);
+				false
+			}
+			_ => true,
+		}
+	}
+
+	/// Returns true if the final group/object from SUBSCRIBE_DONE has arrived.
+	pub fn finished(&self) -> bool {
+		self.last.is_some() && self.received >= self.last
+	}
+
+	fn receive(&mut self, group_id: u64, object_id: u64) {
+		self.received = self.received.max(Some((group_id, object_id)));
+	}
+
+	/// A handle used to count the objects on a stream as they're read, see [Subscribe::stats].
+	pub fn stats(&self) -> SubscribeCounter {
+		SubscribeCounter {
+			state: self.state.clone(),
+		}
+	}
+
 	pub fn error(mut self, err: ServeError) -> Result<(), ServeError> {
 		if let Some(writer) = self.writer.take() {
 			writer.close(err.clone())?;
@@ -157,7 +537,7 @@ impl SubscribeRecv {
 		Ok(stream)
 	}
 
-	pub fn group(&mut self, header: data::GroupHeader) -> Result<serve::GroupWriter, ServeError> {
+	pub fn group(&mut self, group: serve::Group) -> Result<serve::GroupWriter, ServeError> {
 		let writer = self.writer.take().ok_or(ServeError::Done)?;
 
 		let mut groups = match writer {
@@ -166,17 +546,58 @@ impl SubscribeRecv {
 			_ => return Err(ServeError::Mode),
 		};
 
-		let writer = groups.create(serve::Group {
-			group_id: header.group_id,
-			priority: header.send_order,
-		})?;
+		let group_id = group.group_id;
+		let writer = groups.create(group)?;
+
+		if let Some(mut state) = self.state.lock_mut() {
+			state.stats.group(group_id);
+		}
 
 		self.writer = Some(groups.into());
 
+		// The stream contains every object in the group.
+		self.receive(group_id, u64::MAX);
+
 		Ok(writer)
 	}
 
-	pub fn object(&mut self, header: data::ObjectHeader) -> Result<serve::ObjectWriter, ServeError> {
+	pub fn subgroup(&mut self, header: data::SubgroupHeader) -> Result<serve::SubgroupWriter, ServeError> {
+		let (mut group, remain) = match self.subgroups.remove(&header.group_id) {
+			Some(entry) => entry,
+			// The first subgroup stream creates the group, using the priority of that subgroup.
+			None => {
+				let group = self.group(serve::Group {
+					group_id: header.group_id,
+					priority: header.send_order,
+					timestamp: Default::default(),
+					annotations: Default::default(),
+					subgroups: header.subgroups,
+					transit: None,
+				})?;
+
+				(group, header.subgroups)
+			}
+		};
+
+		let subgroup = group.subgroup(serve::Subgroup {
+			subgroup_id: header.subgroup_id,
+			priority: header.send_order,
+		})?;
+
+		// Once every subgroup has arrived, the group is finished when their writers are dropped.
+		let remain = remain.saturating_sub(1);
+		if remain > 0 {
+			self.subgroups.insert(header.group_id, (group, remain));
+		}
+
+		Ok(subgroup)
+	}
+
+	pub fn object(
+		&mut self,
+		header: data::ObjectHeader,
+		mut extensions: data::Extensions,
+	) -> Result<serve::ObjectWriter, ServeError> {
 		let writer = self.writer.take().ok_or(ServeError::Done)?;
 
 		let mut objects = match writer {
@@ -189,9 +610,12 @@ impl SubscribeRecv {
 			group_id: header.group_id,
 			object_id: header.object_id,
 			priority: header.send_order,
+			independent: extensions.take_independent(),
+			extensions: extensions.0.into(),
 		})?;
 
 		self.writer = Some(objects.into());
+		self.receive(header.group_id, header.object_id);
 
 		Ok(writer)
 	}
@@ -202,9 +626,14 @@ impl SubscribeRecv {
 		let mut datagrams = match writer {
 			TrackWriterMode::Track(init) => init.datagrams()?,
 			TrackWriterMode::Datagrams(datagrams) => datagrams,
-			_ => return Err(ServeError::Mode),
+			writer => {
+				// Keep the writer so the error is reported to the reader.
+				self.writer = Some(writer);
+				return Err(ServeError::Mode);
+			}
 		};
 
+		let size = datagram.payload.len();
 		datagrams.write(serve::Datagram {
 			group_id: datagram.group_id,
 			object_id: datagram.object_id,
@@ -212,6 +641,58 @@ impl SubscribeRecv {
 			payload: datagram.payload,
 		})?;
 
+		self.writer = Some(datagrams.into());
+		self.receive(datagram.group_id, datagram.object_id);
+
+		if let Some(mut state) = self.state.lock_mut() {
+			state.stats.ungrouped(datagram.group_id, size as u64);
+		}
+
 		Ok(())
 	}
 }
+
+// Counts what arrives on a stream, since the objects are read without holding the subscription.
+#[derive(Clone)]
+pub(super) struct SubscribeCounter {
+	state: State<SubscribeState>,
+}
+
+impl SubscribeCounter {
+	pub fn group(&self, group_id: u64) {
+		if let Some(mut state) = self.state.lock_mut() {
+			state.stats.group(group_id);
+		}
+	}
+
+	pub fn object(&self, group_id: u64, size: usize) {
+		if let Some(mut state) = self.state.lock_mut() {
+			state.stats.object(group_id, size as u64);
+		}
+	}
+
+	pub fn ungrouped(&self, group_id: u64, size: usize) {
+		if let Some(mut state) = self.state.lock_mut() {
+			state.stats.ungrouped(group_id, size as u64);
+		}
+	}
+}
+
+impl Drop for SubscribeRecv {
+	fn drop(&mut self) {
+		let state = self.state.lock();
+		if state.done.is_none() {
+			return;
+		}
+
+		if let Some(mut state) = state.into_mut() {
+			if let Some(done) = &mut state.done {
+				done.received = match self.writer {
+					// A single stream isn't tracked, but it's reliable and errors the track if cut short.
+					Some(TrackWriterMode::Stream(_)) => done.last,
+					_ => self.received,
+				};
+			}
+		}
+	}
+}
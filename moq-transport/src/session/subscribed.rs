@@ -1,30 +1,168 @@
-use std::ops;
-
+use std::{
+	collections::{HashMap, VecDeque},
+	future::Future,
+	ops,
+	time::Duration,
+};
+
+use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
 
 use crate::coding::Encode;
-use crate::serve::{ServeError, TrackReaderMode};
+use crate::serve::{GroupSkip, ServeError, TrackName, TrackReaderMode, BOOTSTRAP_PRIORITY};
 use crate::watch::State;
 use crate::{data, message, serve};
 
-use super::{Publisher, SessionError, SubscribeInfo, Writer};
+use super::{clock, supervise, Publisher, SessionError, SubscribeInfo, SubscribeStats};
 
 #[derive(Debug)]
 struct SubscribedState {
 	max: Option<(u64, u64)>,
 	closed: Result<(), ServeError>,
+
+	// Set when the subscription was ended without an error, sent in SUBSCRIBE_DONE with the final group/object.
+	status: Option<message::SubscribeStatus>,
+
+	// The subscriber's latest authorization token, and the number of times it was renewed.
+	authorization: Option<String>,
+	renewed: u64,
+
+	// The requested range and priority, replaced by each SUBSCRIBE_UPDATE.
+	start: Option<u64>,
+	start_object: u64,
+	end: Option<u64>,
+	priority: Option<u64>,
+	updated: u64,
+
+	// The first group of a static track, served regardless of the requested start.
+	pinned: Option<u64>,
+
+	// How long each object may take to deliver before its stream is reset, requested in SUBSCRIBE.
+	delivery_timeout: Option<Duration>,
+
+	// The groups and bytes that may be unacknowledged, requested in SUBSCRIBE.
+	window: Option<message::ReceiveWindow>,
+
+	// The groups started but not yet acknowledged, with their remaining streams and the bytes sent so far.
+	inflight: HashMap<u64, (u64, u64)>,
+
+	// What was sent so far, see Subscribed::stats.
+	stats: SubscribeStats,
+
+	// The latest group read from the track, which may still be queued, see SubscribedRenewal::queued.
+	queued: Option<u64>,
 }
 
 impl SubscribedState {
 	fn update_max(&mut self, group_id: u64, object_id: u64) -> Result<(), ServeError> {
-		if let Some((max_group, max_object)) = self.max {
-			if group_id >= max_group && object_id >= max_object {
-				self.max = Some((group_id, object_id));
+		self.max = self.max.max(Some((group_id, object_id)));
+		Ok(())
+	}
+
+	// Returns an error if closed, or Ok(true) if ended without an error.
+	fn done(&self) -> Result<bool, ServeError> {
+		self.closed.clone()?;
+		Ok(self.status.is_some())
+	}
+
+	// Returns true if the group is outside of the requested range.
+	fn skip(&self, group_id: u64) -> bool {
+		self.pinned != Some(group_id) && self.start.is_some_and(|start| group_id < start)
+	}
+
+	// Returns true if the object is before the requested start, including earlier objects in the start group.
+	fn skip_object(&self, group_id: u64, object_id: u64) -> bool {
+		let partial = self.pinned != Some(group_id) && self.start == Some(group_id) && object_id < self.start_object;
+		self.skip(group_id) || partial
+	}
+
+	// Serve the first group of a static track in full, as it's the track's current state.
+	fn pin(&mut self, group_id: u64) {
+		self.pinned = Some(group_id);
+	}
+
+	fn past_end(&self, group_id: u64) -> bool {
+		self.end.is_some_and(|end| group_id > end)
+	}
+
+	// Returns true if another group may be started without exceeding the receive window.
+	fn window_open(&self) -> bool {
+		let window = match self.window {
+			Some(window) if !self.inflight.is_empty() => window,
+			_ => return true,
+		};
+
+		let groups = self.inflight.len() as u64;
+		let bytes: u64 = self.inflight.values().map(|(_, bytes)| bytes).sum();
+
+		window.groups.is_none_or(|max| groups < max) && window.bytes.is_none_or(|max| bytes < max)
+	}
+
+	// Count the group against the receive window until each of its streams is acknowledged.
+	fn open_group(&mut self, group_id: u64, streams: u64) {
+		if self.window.is_some() {
+			self.inflight.insert(group_id, (streams, 0));
+		}
+	}
+
+	fn sent_bytes(&mut self, group_id: u64, size: u64) {
+		if let Some((_, bytes)) = self.inflight.get_mut(&group_id) {
+			*bytes += size;
+		}
+	}
+
+	fn ack_group(&mut self, group_id: u64) {
+		if let Some((streams, _)) = self.inflight.get_mut(&group_id) {
+			*streams = streams.saturating_sub(1);
+			if *streams == 0 {
+				self.inflight.remove(&group_id);
 			}
 		}
+	}
+}
 
-		Ok(())
+// A group waiting to be served, along with the number of streams it will use.
+type PendingGroup = (u64, u64, BoxFuture<'static, ()>);
+
+// The most groups queued for a subscriber that isn't acknowledging them.
+// With [GroupSkip::Never], no more groups are read from the track until there's room, so the track retains them instead.
+const MAX_PENDING: usize = 64;
+
+// The group ID requested by a SUBSCRIBE or SUBSCRIBE_UPDATE, if it's absolute.
+fn absolute_group(pair: &message::SubscribePair) -> Option<u64> {
+	match pair.group {
+		message::SubscribeLocation::Absolute(group) => Some(group),
+		_ => None,
+	}
+}
+
+// The first object ID within the start group, if the group is absolute.
+fn absolute_object(pair: &message::SubscribePair) -> u64 {
+	match (&pair.group, &pair.object) {
+		(message::SubscribeLocation::Absolute(_), message::SubscribeLocation::Absolute(object)) => *object,
+		_ => 0,
+	}
+}
+
+// Deliver an object, failing if it takes longer than the subscriber's delivery timeout.
+async fn deliver<F>(timeout: Option<Duration>, deliver: F) -> Result<(), SessionError>
+where
+	F: Future<Output = Result<(), SessionError>>,
+{
+	match timeout {
+		Some(timeout) => tokio::time::timeout(timeout, deliver)
+			.await
+			.map_err(|_| SessionError::DeliveryTimeout(timeout))?,
+		None => deliver.await,
+	}
+}
+
+// The priority sent to the subscriber, overriding the publisher's for bootstrap tracks.
+fn send_order(bootstrap: bool, priority: u64) -> u64 {
+	match bootstrap {
+		true => BOOTSTRAP_PRIORITY,
+		false => priority,
 	}
 }
 
@@ -33,6 +171,20 @@ impl Default for SubscribedState {
 		Self {
 			max: None,
 			closed: Ok(()),
+			status: None,
+			authorization: None,
+			renewed: 0,
+			start: None,
+			start_object: 0,
+			end: None,
+			priority: None,
+			updated: 0,
+			pinned: None,
+			delivery_timeout: None,
+			window: None,
+			inflight: HashMap::new(),
+			stats: Default::default(),
+			queued: None,
 		}
 	}
 }
@@ -42,24 +194,46 @@ pub struct Subscribed {
 	state: State<SubscribedState>,
 	msg: message::Subscribe,
 	ok: bool,
+	skip: GroupSkip,
+
+	// Send the track reliably at the highest priority, see [TrackName::is_bootstrap].
+	bootstrap: bool,
 
 	pub info: SubscribeInfo,
 }
 
 impl Subscribed {
-	pub(super) fn new(publisher: Publisher, msg: message::Subscribe) -> (Self, SubscribedRecv) {
-		let (send, recv) = State::default().split();
+	pub(super) fn new(
+		publisher: Publisher,
+		msg: message::Subscribe,
+		delivery_timeout: Option<Duration>,
+		window: Option<message::ReceiveWindow>,
+	) -> (Self, SubscribedRecv) {
+		let state = SubscribedState {
+			authorization: msg.params.clone().get(message::Subscribe::AUTHORIZATION).ok().flatten(),
+			start: absolute_group(&msg.start),
+			start_object: absolute_object(&msg.start),
+			end: absolute_group(&msg.end),
+			delivery_timeout,
+			window,
+			..Default::default()
+		};
+		let (send, recv) = State::new(state).split();
 		let info = SubscribeInfo {
 			namespace: msg.track_namespace.clone(),
 			name: msg.track_name.clone(),
 		};
 
+		let bootstrap = publisher.bootstrap_priority() && TrackName::parse(&msg.track_name).is_bootstrap();
+
 		let send = Self {
 			publisher,
 			state: send,
 			msg,
 			info,
 			ok: false,
+			skip: GroupSkip::default(),
+			bootstrap,
 		};
 
 		// Prevents updates after being closed
@@ -68,8 +242,115 @@ impl Subscribed {
 		(send, recv)
 	}
 
+	/// The first group requested by the subscriber, if not the latest.
+	pub fn start_group(&self) -> Option<u64> {
+		self.state.lock().start
+	}
+
+	/// The last group requested by the subscriber, if the subscription should end.
+	pub fn end_group(&self) -> Option<u64> {
+		self.state.lock().end
+	}
+
+	/// The priority requested by the subscriber, used instead of the publisher's when set.
+	pub fn priority(&self) -> Option<u64> {
+		self.state.lock().priority
+	}
+
+	/// How long each object may take to deliver, requested by the subscriber.
+	///
+	/// A group stream is reset when an object exceeds it, so a slow subscriber skips ahead instead of falling behind.
+	pub fn delivery_timeout(&self) -> Option<Duration> {
+		self.state.lock().delivery_timeout
+	}
+
+	/// The order the subscriber wants pending groups delivered.
+	///
+	/// Unless it's [message::GroupOrder::Publisher], only one group is sent at a time, choosing the oldest or newest
+	/// of any that are pending. The others are queued until then, holding their objects in memory.
+	pub fn group_order(&self) -> message::GroupOrder {
+		self.msg.group_order
+	}
+
+	/// The groups and bytes the subscriber allows in flight, see [message::ReceiveWindow].
+	pub fn receive_window(&self) -> Option<message::ReceiveWindow> {
+		self.state.lock().window
+	}
+
+	/// Block until the subscriber changes the range or priority with SUBSCRIBE_UPDATE.
+	///
+	/// Groups outside of the new range are skipped when served, so this is only needed to react in other ways.
+	pub async fn updated(&self) -> Result<(), ServeError> {
+		let updated = self.state.lock().updated;
+
+		loop {
+			{
+				let state = self.state.lock();
+				state.closed.clone()?;
+
+				if state.updated != updated {
+					return Ok(());
+				}
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Err(ServeError::Done),
+				}
+			}
+			.await;
+		}
+	}
+
+	/// The token sent by the subscriber to authorize this subscription, updated each time it's renewed.
+	pub fn authorization(&self) -> Option<String> {
+		self.state.lock().authorization.clone()
+	}
+
+	/// The groups, objects and bytes sent so far, see [SubscribedRenewal::stats] while it's being served.
+	pub fn stats(&self) -> SubscribeStats {
+		self.state.lock().stats
+	}
+
+	/// A handle to ask the subscriber for a new token while the subscription is being served.
+	pub fn renewal(&self) -> SubscribedRenewal {
+		SubscribedRenewal {
+			publisher: self.publisher.clone(),
+			state: self.state.clone(),
+			id: self.msg.id,
+		}
+	}
+
+	/// Choose what happens when the subscriber falls behind a track using groups, defaulting to [GroupSkip::Latest].
+	///
+	/// This also bounds the groups queued while waiting for the receive window or the previous group, dropping the
+	/// oldest: only the newest with [GroupSkip::Latest] and the lag plus one with [GroupSkip::Lag].
+	/// With [GroupSkip::Never], nothing is dropped: no more groups are read from the track once 64 are queued.
+	pub fn with_skip(mut self, skip: GroupSkip) -> Self {
+		self.skip = skip;
+		self
+	}
+
+	/// Accept the subscription with SUBSCRIBE_OK before it's served, ex. while it waits for capacity.
+	///
+	/// The track isn't known yet, so the latest group and epoch are omitted.
+	pub fn accept(&mut self) {
+		if self.ok {
+			return;
+		}
+
+		self.publisher.send_message(message::SubscribeOk {
+			id: self.msg.id,
+			expires: None,
+			latest: None,
+			epoch: None,
+		});
+
+		self.ok = true;
+	}
+
 	pub async fn serve(mut self, track: serve::TrackReader) -> Result<(), SessionError> {
-		let res = self.serve_inner(track).await;
+		// A panic only closes this subscription, reporting the message to the subscriber.
+		let res = supervise(self.serve_inner(track)).await;
 		if let Err(err) = &res {
 			self.close(err.clone().into())?;
 		}
@@ -81,11 +362,21 @@ impl Subscribed {
 		let latest = track.latest();
 		self.state.lock_mut().ok_or(ServeError::Cancel)?.max = latest;
 
-		self.publisher.send_message(message::SubscribeOk {
-			id: self.msg.id,
-			expires: None,
-			latest,
-		});
+		// Only send the epoch when the subscriber knows how to decode it.
+		let epoch = match self.publisher.capabilities().epoch {
+			true => track.epoch(),
+			false => None,
+		};
+
+		// Unless it was already accepted, ex. before waiting for capacity.
+		if !self.ok {
+			self.publisher.send_message(message::SubscribeOk {
+				id: self.msg.id,
+				expires: None,
+				latest,
+				epoch,
+			});
+		}
 
 		self.ok = true; // So we sent SubscribeDone on drop
 
@@ -94,10 +385,22 @@ impl Subscribed {
 			TrackReaderMode::Stream(stream) => self.serve_track(stream).await,
 			TrackReaderMode::Groups(groups) => self.serve_groups(groups).await,
 			TrackReaderMode::Objects(objects) => self.serve_objects(objects).await,
+			TrackReaderMode::Datagrams(datagrams) if self.bootstrap => self.serve_datagram_streams(datagrams).await,
 			TrackReaderMode::Datagrams(datagrams) => self.serve_datagrams(datagrams).await,
 		}
 	}
 
+	// Serve the group regardless of the requested start, see [serve::Track::is_static].
+	fn pin(&self, group_id: u64) {
+		if let Some(mut state) = self.state.lock_mut() {
+			state.pin(group_id);
+		}
+	}
+
+	fn send_order(&self, priority: u64) -> u64 {
+		send_order(self.bootstrap, priority)
+	}
+
 	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
 		let state = self.state.lock();
 		state.closed.clone()?;
@@ -112,7 +415,9 @@ impl Subscribed {
 		loop {
 			{
 				let state = self.state.lock();
-				state.closed.clone()?;
+				if state.done()? {
+					return Ok(());
+				}
 
 				match state.modified() {
 					Some(notify) => notify,
@@ -135,18 +440,25 @@ impl ops::Deref for Subscribed {
 impl Drop for Subscribed {
 	fn drop(&mut self) {
 		let state = self.state.lock();
-		let err = state.closed.as_ref().err().cloned().unwrap_or(ServeError::Done);
+		let err = state.closed.as_ref().err().cloned();
+		let status = state.status.unwrap_or_default();
 		let max = state.max;
 		drop(state); // Important to avoid a deadlock
 
 		if self.ok {
+			let (code, reason) = match &err {
+				Some(err) => (err.code(), err.to_string()),
+				None => (status.code(), status.to_string()),
+			};
+
 			self.publisher.send_message(message::SubscribeDone {
 				id: self.msg.id,
 				last: max,
-				code: err.code(),
-				reason: err.to_string(),
+				code,
+				reason,
 			});
 		} else {
+			let err = err.unwrap_or(ServeError::Done);
 			self.publisher.send_message(message::SubscribeError {
 				id: self.msg.id,
 				alias: 0,
@@ -159,133 +471,460 @@ impl Drop for Subscribed {
 
 impl Subscribed {
 	async fn serve_track(&mut self, mut track: serve::StreamReader) -> Result<(), SessionError> {
-		let mut stream = self.publisher.open_uni().await?;
-
-		// TODO figure out u32 vs u64 priority
-		stream.set_priority(track.priority as i32);
-
-		let mut writer = Writer::new(stream);
-
+		let send_order = self.send_order(track.priority);
 		let header: data::Header = data::TrackHeader {
 			subscribe_id: self.msg.id,
 			track_alias: self.msg.track_alias,
-			send_order: track.priority,
+			send_order,
 		}
 		.into();
 
-		writer.encode(&header).await?;
+		let priority = self.priority().unwrap_or(send_order);
+		let mut writer = match self.publisher.open_stream(priority, &header).await? {
+			Some(writer) => writer,
+			None => return Ok(()),
+		};
+
+		crate::sampled!(log::Level::Trace, "sent track header", "{:?}", header);
 
-		log::trace!("sent track header: {:?}", header);
+		let mut pin = track.track.is_static;
 
 		while let Some(mut group) = track.next().await? {
+			if std::mem::take(&mut pin) {
+				self.pin(group.group_id);
+			}
+
+			let (past_end, skip) = {
+				let state = self.state.lock();
+				(state.past_end(group.group_id), state.skip(group.group_id))
+			};
+
+			if past_end {
+				break;
+			}
+
+			if skip {
+				continue;
+			}
+
+			if let Some(mut state) = self.state.lock_mut() {
+				state.stats.group(group.group_id);
+			}
+
 			while let Some(mut object) = group.next().await? {
+				if self.state.lock().skip_object(object.group_id, object.object_id) {
+					continue;
+				}
+
 				let header = data::TrackObject {
 					group_id: object.group_id,
 					object_id: object.object_id,
 					size: object.size,
 				};
 
-				self.state
-					.lock_mut()
-					.ok_or(ServeError::Done)?
-					.update_max(object.group_id, object.object_id)?;
+				{
+					let mut state = self.state.lock_mut().ok_or(ServeError::Done)?;
+					state.update_max(object.group_id, object.object_id)?;
+					state.stats.object(object.group_id, object.size as u64);
+				}
 
 				writer.encode(&header).await?;
 
-				log::trace!("sent track object: {:?}", header);
+				crate::sampled!(log::Level::Trace, "sent track object", "{:?}", header);
 
 				while let Some(chunk) = object.read().await? {
 					writer.write(&chunk).await?;
-					log::trace!("sent track payload: {:?}", chunk.len());
+					crate::sampled!(log::Level::Trace, "sent track payload", "{:?}", chunk.len());
 				}
 
-				log::trace!("sent track done");
+				crate::sampled!(log::Level::Trace, "sent track done");
 			}
 		}
 
 		Ok(())
 	}
 
-	async fn serve_groups(&mut self, mut groups: serve::GroupsReader) -> Result<(), SessionError> {
+	async fn serve_groups(&mut self, groups: serve::GroupsReader) -> Result<(), SessionError> {
+		let mut pin = groups.info.is_static;
+		let mut groups = groups.with_skip(self.skip);
 		let mut tasks = FuturesUnordered::new();
+		let mut pending = VecDeque::new();
 		let mut done: Option<Result<(), ServeError>> = None;
 
 		loop {
+			self.start_pending(&mut pending, &mut tasks);
+
+			// Anything still pending is waiting for the previous group or for the receive window.
+			let serial = self.group_order() != message::GroupOrder::Publisher;
+			let blocked = !pending.is_empty() && (!serial || tasks.is_empty());
+
+			// Groups can't be skipped, so stop reading the track until there's room.
+			let full = self.skip == GroupSkip::Never && pending.len() >= MAX_PENDING;
+
 			tokio::select! {
-				res = groups.next(), if done.is_none() => match res {
+				res = groups.next(), if done.is_none() && !full => match res {
 					Ok(Some(group)) => {
-						let header = data::GroupHeader {
-							subscribe_id: self.msg.id,
-							track_alias: self.msg.track_alias,
-							group_id: group.group_id,
-							send_order: group.priority,
+						if let Some(mut state) = self.state.lock_mut() {
+							state.queued = Some(group.group_id);
+						}
+
+						if std::mem::take(&mut pin) {
+							self.pin(group.group_id);
+						}
+
+						// The range may have been changed by SUBSCRIBE_UPDATE.
+						let (past_end, skip) = {
+							let state = self.state.lock();
+							(state.past_end(group.group_id), state.skip(group.group_id))
+						};
+
+						if past_end {
+							done = Some(Ok(()));
+							continue;
+						}
+
+						if skip {
+							continue;
+						}
+
+						let send_order = self.send_order(group.priority);
+
+						// Only send the extension header when the subscriber knows how to decode it.
+						let capabilities = self.publisher.capabilities();
+						let ext = !group.timestamp.is_empty() || !group.annotations.is_empty() || capabilities.clock;
+						let header: data::Header = match capabilities.timestamps && ext {
+							true => data::GroupExtHeader {
+								subscribe_id: self.msg.id,
+								track_alias: self.msg.track_alias,
+								group_id: group.group_id,
+								send_order,
+								media_time: group.timestamp.media,
+								wall_time: group.timestamp.wall,
+								annotations: group.annotations.clone().into(),
+								send_time: capabilities.clock.then(clock::now),
+							}.into(),
+							false => data::GroupHeader {
+								subscribe_id: self.msg.id,
+								track_alias: self.msg.track_alias,
+								group_id: group.group_id,
+								send_order,
+							}.into(),
+						};
+
+						// Each subgroup is sent on its own stream instead, or dropped if the subscriber doesn't support them.
+						let split = match group.subgroups > 0 && self.publisher.capabilities().subgroups {
+							true => Some(data::SubgroupHeader {
+								subscribe_id: self.msg.id,
+								track_alias: self.msg.track_alias,
+								group_id: group.group_id,
+								subgroup_id: 0,
+								subgroups: group.subgroups,
+								send_order,
+							}),
+							false => None,
 						};
 
 						let publisher = self.publisher.clone();
 						let state = self.state.clone();
 						let info = group.info.clone();
+						let bootstrap = self.bootstrap;
+						let group_id = group.group_id;
+						let streams = split.as_ref().map(|split| split.subgroups).unwrap_or(1);
+
+						pending.push_back((group_id, streams, async move {
+							let res = match split {
+								Some(template) => Self::serve_subgroups(template, bootstrap, group, publisher, state.clone()).await,
+								None => Self::serve_group(header, send_order, group, publisher, state.clone()).await,
+							};
 
-						tasks.push(async move {
-							if let Err(err) = Self::serve_group(header, group, publisher, state).await {
+							if let Err(err) = res {
 								log::warn!("failed to serve group: {:?}, error: {}", info, err);
+
+								// The subscriber may never see the stream, so don't wait for it to be acknowledged.
+								if let Some(mut state) = state.lock_mut() {
+									state.inflight.remove(&group_id);
+								}
 							}
-						});
+						}.boxed()));
+
+						// Don't queue groups without bound for a subscriber that isn't acknowledging them.
+						// This never drops a group with GroupSkip::Never, since the track isn't read while full.
+						while pending.len() > self.max_pending() {
+							if let Some((group_id, ..)) = pending.pop_front() {
+								log::debug!("skipping pending group: id={} group={}", self.msg.id, group_id);
+							}
+						}
 					},
 					Ok(None) => done = Some(Ok(())),
 					Err(err) => done = Some(Err(err)),
 				},
 				res = self.closed(), if done.is_none() => done = Some(res),
+				res = self.window_opened(), if blocked => if res.is_err() {
+					pending.clear();
+				},
 				_ = tasks.next(), if !tasks.is_empty() => {},
 				else => return Ok(done.unwrap()?),
 			}
 		}
 	}
 
+	// The most groups queued before the oldest are dropped, following the skip policy.
+	fn max_pending(&self) -> usize {
+		match self.skip {
+			GroupSkip::Latest => 1,
+			GroupSkip::Lag(lag) => usize::try_from(lag)
+				.unwrap_or(usize::MAX)
+				.saturating_add(1)
+				.min(MAX_PENDING),
+			GroupSkip::Never => MAX_PENDING,
+		}
+	}
+
+	// Start the pending groups in the order requested by the subscriber, while the receive window allows.
+	fn start_pending(
+		&self,
+		pending: &mut VecDeque<PendingGroup>,
+		tasks: &mut FuturesUnordered<BoxFuture<'static, ()>>,
+	) {
+		let order = self.group_order();
+
+		// Unless the publisher's order is used, only one group is served at a time.
+		while order == message::GroupOrder::Publisher || tasks.is_empty() {
+			if !self.state.lock().window_open() {
+				return;
+			}
+
+			let next = match order {
+				message::GroupOrder::Descending => pending.pop_back(),
+				_ => pending.pop_front(),
+			};
+
+			let (group_id, streams, task) = match next {
+				Some(next) => next,
+				None => return,
+			};
+
+			if let Some(mut state) = self.state.lock_mut() {
+				state.open_group(group_id, streams);
+			}
+
+			tasks.push(task);
+		}
+	}
+
+	// Resolves once the receive window allows another group, or errors when the subscription is closed.
+	async fn window_opened(&self) -> Result<(), ServeError> {
+		loop {
+			{
+				let state = self.state.lock();
+				state.closed.clone()?;
+
+				if state.window_open() {
+					return Ok(());
+				}
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Err(ServeError::Done),
+				}
+			}
+			.await;
+		}
+	}
+
 	async fn serve_group(
-		header: data::GroupHeader,
+		header: data::Header,
+		send_order: u64,
 		mut group: serve::GroupReader,
 		mut publisher: Publisher,
 		state: State<SubscribedState>,
 	) -> Result<(), SessionError> {
-		let mut stream = publisher.open_uni().await?;
+		let priority = state.lock().priority.unwrap_or(send_order);
+		let mut writer = match publisher.open_stream(priority, &header).await? {
+			Some(writer) => writer,
+			None => return Ok(()),
+		};
+
+		crate::sampled!(log::Level::Trace, "sent group", "{:?}", header);
+
+		if let Some(mut state) = state.lock_mut() {
+			state.stats.group(group.group_id);
+		}
+
+		let extended = publisher.capabilities().extensions;
+
+		while let Some(mut object) = group.next().await? {
+			if state.lock().skip_object(group.group_id, object.object_id) {
+				group.mark_written();
+				continue;
+			}
+
+			let header = data::GroupObject {
+				object_id: object.object_id,
+				size: object.size,
+				extensions: data::Extensions(object.extensions.clone().into()).with_independent(object.independent),
+			};
+
+			let timeout = state.lock().delivery_timeout;
+			let res = deliver(timeout, async {
+				match extended {
+					true => writer.encode_with(|buffer| header.encode_extended(buffer)).await?,
+					false => writer.encode(&header).await?,
+				};
+
+				{
+					let mut state = state.lock_mut().ok_or(ServeError::Done)?;
+					state.update_max(group.group_id, object.object_id)?;
+					state.sent_bytes(group.group_id, object.size as u64);
+					state.stats.object(group.group_id, object.size as u64);
+				}
+
+				crate::sampled!(log::Level::Trace, "sent group object", "{:?}", header);
+
+				while let Some(chunk) = object.read().await? {
+					writer.write(&chunk).await?;
+					crate::sampled!(log::Level::Trace, "sent group payload", "{:?}", chunk.len());
+				}
+
+				Ok(())
+			})
+			.await;
+
+			// Delivered or abandoned, either way the publisher can stop waiting for it.
+			group.mark_written();
+
+			if let Err(err) = res {
+				// Abandon the rest of the group rather than blocking on a slow subscriber.
+				if let SessionError::DeliveryTimeout(_) = err {
+					writer.reset(err.code() as u32);
+				}
+
+				return Err(err);
+			}
+
+			crate::sampled!(log::Level::Trace, "sent group done");
+		}
+
+		Ok(())
+	}
+
+	// Serve each subgroup on its own stream as it's created.
+	async fn serve_subgroups(
+		template: data::SubgroupHeader,
+		bootstrap: bool,
+		mut group: serve::GroupReader,
+		publisher: Publisher,
+		state: State<SubscribedState>,
+	) -> Result<(), SessionError> {
+		let mut tasks = FuturesUnordered::new();
+		let mut done = false;
+
+		// Counted once, even though each subgroup has its own stream.
+		if let Some(mut state) = state.lock_mut() {
+			state.stats.group(group.group_id);
+		}
+
+		loop {
+			tokio::select! {
+				res = group.next_subgroup(), if !done => match res? {
+					Some(subgroup) => {
+						let header = data::SubgroupHeader {
+							subgroup_id: subgroup.subgroup_id,
+							send_order: send_order(bootstrap, subgroup.priority),
+							..template.clone()
+						};
 
-		// TODO figure out u32 vs u64 priority
-		stream.set_priority(group.priority as i32);
+						let info = subgroup.info.clone();
+						let serve = Self::serve_subgroup(header, subgroup, publisher.clone(), state.clone());
 
-		let mut writer = Writer::new(stream);
+						tasks.push(async move {
+							if let Err(err) = serve.await {
+								log::warn!("failed to serve subgroup: {:?}, error: {}", info, err);
+							}
+						});
+					}
+					None => done = true,
+				},
+				_ = tasks.next(), if !tasks.is_empty() => {},
+				else => return Ok(()),
+			}
+		}
+	}
 
+	async fn serve_subgroup(
+		header: data::SubgroupHeader,
+		mut subgroup: serve::SubgroupReader,
+		mut publisher: Publisher,
+		state: State<SubscribedState>,
+	) -> Result<(), SessionError> {
+		let priority = state.lock().priority.unwrap_or(header.send_order);
 		let header: data::Header = header.into();
-		writer.encode(&header).await?;
+		let mut writer = match publisher.open_stream(priority, &header).await? {
+			Some(writer) => writer,
+			None => return Ok(()),
+		};
 
-		log::trace!("sent group: {:?}", header);
+		crate::sampled!(log::Level::Trace, "sent subgroup", "{:?}", header);
+
+		let extended = publisher.capabilities().extensions;
+
+		while let Some(mut object) = subgroup.next().await? {
+			if state.lock().skip_object(subgroup.group_id, object.object_id) {
+				subgroup.mark_written();
+				continue;
+			}
 
-		while let Some(mut object) = group.next().await? {
 			let header = data::GroupObject {
 				object_id: object.object_id,
 				size: object.size,
+				extensions: data::Extensions(object.extensions.clone().into()).with_independent(object.independent),
 			};
 
-			writer.encode(&header).await?;
+			let timeout = state.lock().delivery_timeout;
+			let res = deliver(timeout, async {
+				match extended {
+					true => writer.encode_with(|buffer| header.encode_extended(buffer)).await?,
+					false => writer.encode(&header).await?,
+				};
+
+				{
+					let mut state = state.lock_mut().ok_or(ServeError::Done)?;
+					state.update_max(subgroup.group_id, object.object_id)?;
+					state.sent_bytes(subgroup.group_id, object.size as u64);
+					state.stats.object(subgroup.group_id, object.size as u64);
+				}
+
+				crate::sampled!(log::Level::Trace, "sent subgroup object", "{:?}", header);
+
+				while let Some(chunk) = object.read().await? {
+					writer.write(&chunk).await?;
+					crate::sampled!(log::Level::Trace, "sent subgroup payload", "{:?}", chunk.len());
+				}
+
+				Ok(())
+			})
+			.await;
 
-			state
-				.lock_mut()
-				.ok_or(ServeError::Done)?
-				.update_max(group.group_id, object.object_id)?;
+			// Delivered or abandoned, either way the publisher can stop waiting for it.
+			subgroup.mark_written();
 
-			log::trace!("sent group object: {:?}", header);
+			if let Err(err) = res {
+				// Abandon the rest of the subgroup rather than blocking on a slow subscriber.
+				if let SessionError::DeliveryTimeout(_) = err {
+					writer.reset(err.code() as u32);
+				}
 
-			while let Some(chunk) = object.read().await? {
-				writer.write(&chunk).await?;
-				log::trace!("sent group payload: {:?}", chunk.len());
+				return Err(err);
 			}
 
-			log::trace!("sent group done");
+			crate::sampled!(log::Level::Trace, "sent subgroup done");
 		}
 
 		Ok(())
 	}
 
 	pub async fn serve_objects(&mut self, mut objects: serve::ObjectsReader) -> Result<(), SessionError> {
+		let mut pin = objects.info.is_static;
 		let mut tasks = FuturesUnordered::new();
 		let mut done = None;
 
@@ -293,12 +932,30 @@ impl Subscribed {
 			tokio::select! {
 				res = objects.next(), if done.is_none() => match res {
 					Ok(Some(object)) => {
+						if std::mem::take(&mut pin) {
+							self.pin(object.group_id);
+						}
+
+						let (past_end, skip) = {
+							let state = self.state.lock();
+							(state.past_end(object.group_id), state.skip_object(object.group_id, object.object_id))
+						};
+
+						if past_end {
+							done = Some(Ok(()));
+							continue;
+						}
+
+						if skip {
+							continue;
+						}
+
 						let header = data::ObjectHeader {
 							subscribe_id: self.msg.id,
 							track_alias: self.msg.track_alias,
 							group_id: object.group_id,
 							object_id: object.object_id,
-							send_order: object.priority,
+							send_order: self.send_order(object.priority),
 						};
 
 						let publisher = self.publisher.clone();
@@ -332,30 +989,56 @@ impl Subscribed {
 			.ok_or(ServeError::Done)?
 			.update_max(object.group_id, object.object_id)?;
 
-		let mut stream = publisher.open_uni().await?;
-
-		// TODO figure out u32 vs u64 priority
-		stream.set_priority(object.priority as i32);
-
-		let mut writer = Writer::new(stream);
-
+		let priority = state.lock().priority.unwrap_or(header.send_order);
 		let header: data::Header = header.into();
-		writer.encode(&header).await?;
+		let mut writer = match publisher.open_stream(priority, &header).await? {
+			Some(writer) => writer,
+			None => return Ok(()),
+		};
 
-		log::trace!("sent object: {:?}", header);
+		// The extension headers follow the stream header when negotiated, even if there are none.
+		if publisher.capabilities().extensions {
+			writer
+				.encode(&data::Extensions(object.extensions.clone().into()).with_independent(object.independent))
+				.await?;
+		}
+
+		crate::sampled!(log::Level::Trace, "sent object", "{:?}", header);
 
+		let mut size = 0;
 		while let Some(chunk) = object.read().await? {
 			writer.write(&chunk).await?;
-			log::trace!("sent object payload: {:?}", chunk.len());
+			size += chunk.len();
+			crate::sampled!(log::Level::Trace, "sent object payload", "{:?}", chunk.len());
+		}
+
+		if let Some(mut state) = state.lock_mut() {
+			state.stats.ungrouped(object.group_id, size as u64);
 		}
 
-		log::trace!("sent object done");
+		crate::sampled!(log::Level::Trace, "sent object done");
 
 		Ok(())
 	}
 
 	async fn serve_datagrams(&mut self, mut datagrams: serve::DatagramsReader) -> Result<(), SessionError> {
 		while let Some(datagram) = datagrams.read().await? {
+			let (past_end, skip) = {
+				let state = self.state.lock();
+				(
+					state.past_end(datagram.group_id),
+					state.skip_object(datagram.group_id, datagram.object_id),
+				)
+			};
+
+			if past_end {
+				break;
+			}
+
+			if skip {
+				continue;
+			}
+
 			let datagram = data::Datagram {
 				subscribe_id: self.msg.id,
 				track_alias: self.msg.track_alias,
@@ -369,16 +1052,164 @@ impl Subscribed {
 			datagram.encode(&mut buffer)?;
 
 			self.publisher.send_datagram(buffer.into()).await?;
-			log::trace!("sent datagram: {:?}", datagram);
+			crate::sampled!(log::Level::Trace, "sent datagram", "{:?}", datagram);
+
+			let mut state = self.state.lock_mut().ok_or(ServeError::Done)?;
+			state.update_max(datagram.group_id, datagram.object_id)?;
+			state.stats.ungrouped(datagram.group_id, datagram.payload.len() as u64);
+		}
+
+		Ok(())
+	}
+
+	// Send each datagram on its own stream instead, so bootstrap tracks can't be lost.
+	async fn serve_datagram_streams(&mut self, mut datagrams: serve::DatagramsReader) -> Result<(), SessionError> {
+		while let Some(datagram) = datagrams.read().await? {
+			let (past_end, skip) = {
+				let state = self.state.lock();
+				(
+					state.past_end(datagram.group_id),
+					state.skip_object(datagram.group_id, datagram.object_id),
+				)
+			};
+
+			if past_end {
+				break;
+			}
+
+			if skip {
+				continue;
+			}
 
-			self.state
-				.lock_mut()
-				.ok_or(ServeError::Done)?
-				.update_max(datagram.group_id, datagram.object_id)?;
+			let send_order = self.send_order(datagram.priority);
+			let header: data::Header = data::ObjectHeader {
+				subscribe_id: self.msg.id,
+				track_alias: self.msg.track_alias,
+				group_id: datagram.group_id,
+				object_id: datagram.object_id,
+				send_order,
+			}
+			.into();
+
+			let priority = self.priority().unwrap_or(send_order);
+			let mut writer = match self.publisher.open_stream(priority, &header).await? {
+				Some(writer) => writer,
+				None => continue,
+			};
+
+			// Datagrams don't have extension headers, but the subscriber still expects them on an object stream.
+			if self.publisher.capabilities().extensions {
+				writer.encode(&data::Extensions::default()).await?;
+			}
+
+			writer.write(&datagram.payload).await?;
+			crate::sampled!(log::Level::Trace, "sent datagram as object", "{:?}", header);
+
+			let mut state = self.state.lock_mut().ok_or(ServeError::Done)?;
+			state.update_max(datagram.group_id, datagram.object_id)?;
+			state.stats.ungrouped(datagram.group_id, datagram.payload.len() as u64);
+		}
+
+		Ok(())
+	}
+}
+
+/// Asks the subscriber for a new authorization token, see [Subscribed::renewal].
+///
+/// Renewal requires the capability to be negotiated, and the publisher is responsible for enforcing any expiry.
+pub struct SubscribedRenewal {
+	publisher: Publisher,
+	state: State<SubscribedState>,
+	id: u64,
+}
+
+impl SubscribedRenewal {
+	/// Send a SUBSCRIBE_RENEW and wait for the new token.
+	///
+	/// The subscriber is told the subscription will close after `expires`, so the caller should use a timeout.
+	pub async fn renew(&mut self, expires: Duration) -> Result<String, ServeError> {
+		if !self.publisher.capabilities().renewal {
+			return Err(ServeError::Unsupported("renewal".to_string()));
+		}
+
+		let renewed = self.state.lock().renewed;
+
+		self.publisher.send_message(message::SubscribeRenew {
+			id: self.id,
+			expires: expires.as_millis() as u64,
+		});
+
+		loop {
+			{
+				let state = self.state.lock();
+				state.closed.clone()?;
+
+				if state.renewed != renewed {
+					return Ok(state.authorization.clone().unwrap_or_default());
+				}
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Err(ServeError::Done),
+				}
+			}
+			.await;
+		}
+	}
+
+	/// The groups, objects and bytes sent so far, since [Subscribed] is consumed while it's served.
+	pub fn stats(&self) -> SubscribeStats {
+		self.state.lock().stats
+	}
+
+	/// The latest group read from the track, which may still be queued while waiting for the receive window.
+	pub fn queued(&self) -> Option<u64> {
+		self.state.lock().queued
+	}
+
+	/// Close the subscription with an error, ex. because the token expired or the new one was rejected.
+	pub fn close(&self, err: ServeError) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		state.closed.clone()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Done)?;
+		state.closed = Err(err);
+
+		Ok(())
+	}
+
+	/// End the subscription without an error once the groups in flight are sent, ex. [message::SubscribeStatus::Expired].
+	///
+	/// The subscriber is told the status and the final group/object, so it can tell if anything was missed.
+	pub fn end(&self, status: message::SubscribeStatus) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		if state.done()? {
+			return Err(ServeError::Done);
 		}
 
+		let mut state = state.into_mut().ok_or(ServeError::Done)?;
+		state.status = Some(status);
+
 		Ok(())
 	}
+
+	/// Resolves when the subscription is closed or ended.
+	pub async fn closed(&self) -> Result<(), ServeError> {
+		loop {
+			{
+				let state = self.state.lock();
+				if state.done()? {
+					return Ok(());
+				}
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return Ok(()),
+				}
+			}
+			.await;
+		}
+	}
 }
 
 pub(super) struct SubscribedRecv {
@@ -396,4 +1227,39 @@ impl SubscribedRecv {
 
 		Ok(())
 	}
+
+	pub fn recv_renew(&mut self, authorization: String) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		state.closed.clone()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Done)?;
+		state.authorization = Some(authorization);
+		state.renewed += 1;
+
+		Ok(())
+	}
+
+	pub fn recv_ack(&mut self, group_id: u64) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		state.closed.clone()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Done)?;
+		state.ack_group(group_id);
+
+		Ok(())
+	}
+
+	pub fn recv_update(&mut self, msg: message::SubscribeUpdate) -> Result<(), ServeError> {
+		let state = self.state.lock();
+		state.closed.clone()?;
+
+		let mut state = state.into_mut().ok_or(ServeError::Done)?;
+		state.start = absolute_group(&msg.start);
+		state.start_object = absolute_object(&msg.start);
+		state.end = absolute_group(&msg.end);
+		state.priority = msg.priority;
+		state.updated += 1;
+
+		Ok(())
+	}
 }
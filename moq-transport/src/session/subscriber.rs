@@ -1,20 +1,124 @@
 use std::{
-	collections::{hash_map, HashMap},
+	collections::{btree_map, hash_map, BTreeMap, HashMap},
+	future::Future,
 	io,
-	sync::{atomic, Arc, Mutex},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use bytes::Bytes;
+use futures::{
+	future::{self, BoxFuture},
+	stream::FuturesUnordered,
+	FutureExt, StreamExt,
 };
 
 use crate::{
-	coding::Decode,
+	coding::{Decode, VarInt},
 	data,
 	message::{self, Message},
 	serve::{self, ServeError},
-	setup,
+	setup, transport,
 };
 
 use crate::watch::Queue;
 
-use super::{Announced, AnnouncedRecv, Reader, Session, SessionError, Subscribe, SubscribeRecv};
+use super::{
+	supervise, AnnounceInfo, Announced, AnnouncedRecv, FetchOptions, History, HistoryKind, Reader, Session,
+	SessionError, SetupOptions, Subscribe, SubscribeBundle, SubscribeClosed, SubscribeCounter, SubscribeInfo,
+	SubscribeOptions, SubscribeRecv, SubscribeUpdate, SubscriberBatch,
+};
+
+use super::clock::{self, ClockSync, CLOCK_INTERVAL, CLOCK_TRACK};
+use super::subscribe::subscribe_range;
+
+/// How a [Subscriber] assigns subscribe IDs, which are also used as track aliases.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SubscribeIds {
+	/// Count up from zero in the order subscriptions are made.
+	#[default]
+	Sequential,
+
+	/// Derive each ID from a hash of the namespace and track name, so a track has the same ID on every host.
+	/// On a collision, including resubscribing to the same track, the next unused ID is chosen instead.
+	/// Each assignment is logged at debug level, building a table to correlate IDs with names.
+	Hashed,
+}
+
+#[derive(Default)]
+struct SubscribeIdState {
+	mode: SubscribeIds,
+
+	// Every sequential ID below this may have been used.
+	next: u64,
+
+	// Every hashed ID that has been used, and the track it was used for.
+	// IDs are never reused, since the publisher may not have forgotten them yet.
+	hashed: BTreeMap<u64, String>,
+}
+
+impl SubscribeIdState {
+	fn assign(&mut self, namespace: &str, name: &str) -> u64 {
+		if self.mode == SubscribeIds::Sequential {
+			loop {
+				let id = self.next;
+				self.next += 1;
+
+				if !self.hashed.contains_key(&id) {
+					return id;
+				}
+			}
+		}
+
+		let mut id = hash_id(namespace, name);
+
+		loop {
+			if id >= self.next {
+				if let btree_map::Entry::Vacant(entry) = self.hashed.entry(id) {
+					entry.insert(format!("{}/{}", namespace, name));
+					break;
+				}
+			}
+
+			log::debug!(
+				"subscribe id collision: id={} namespace={} name={}",
+				id,
+				namespace,
+				name
+			);
+			id = (id + 1) & u64::from(VarInt::MAX);
+		}
+
+		log::debug!("subscribe id: {} => {}/{}", id, namespace, name);
+
+		id
+	}
+}
+
+// FNV-1a, which unlike the std hasher is stable across hosts and releases.
+fn hash_id(namespace: &str, name: &str) -> u64 {
+	let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+	// Separate the two so ("a", "bc") and ("ab", "c") don't collide.
+	for byte in namespace.bytes().chain([0]).chain(name.bytes()) {
+		hash ^= byte as u64;
+		hash = hash.wrapping_mul(0x0100_0000_01b3);
+	}
+
+	// Fit in a varint.
+	hash & u64::from(VarInt::MAX)
+}
+
+type TokenRefresh = Arc<dyn Fn(SubscribeInfo) -> BoxFuture<'static, Option<String>> + Send + Sync>;
+
+#[derive(Default)]
+struct SubscriberAuth {
+	// Sent with each SUBSCRIBE, replaced whenever it's renewed.
+	token: Option<String>,
+	refresh: Option<TokenRefresh>,
+}
 
 // TODO remove Clone.
 #[derive(Clone)]
@@ -23,43 +127,276 @@ pub struct Subscriber {
 	announced_queue: Queue<Announced>,
 
 	subscribes: Arc<Mutex<HashMap<u64, SubscribeRecv>>>,
-	subscribe_next: Arc<atomic::AtomicU64>,
+	subscribe_ids: Arc<Mutex<SubscribeIdState>>,
+
+	auth: Arc<Mutex<SubscriberAuth>>,
+	renewals: Queue<message::SubscribeRenew>,
+
+	// Ask for bootstrap tracks at the highest priority.
+	bootstrap: Arc<AtomicBool>,
+
+	// Subscription messages held back until they're sent together, see [Self::batch].
+	batch: Arc<Mutex<Option<Vec<message::Subscriber>>>>,
+
+	// The offset between our clock and the publisher's, see [Self::sync_clock].
+	clock: ClockSync,
 
 	outgoing: Queue<Message>,
+	capabilities: setup::Capabilities,
 }
 
 impl Subscriber {
-	pub(super) fn new(outgoing: Queue<Message>) -> Self {
+	pub(super) fn new(outgoing: Queue<Message>, capabilities: setup::Capabilities) -> Self {
 		Self {
+			capabilities,
 			announced: Default::default(),
 			announced_queue: Default::default(),
 			subscribes: Default::default(),
-			subscribe_next: Default::default(),
+			subscribe_ids: Default::default(),
+			auth: Default::default(),
+			renewals: Default::default(),
+			bootstrap: Arc::new(AtomicBool::new(true)),
+			batch: Default::default(),
+			clock: Default::default(),
 			outgoing,
 		}
 	}
 
-	pub async fn accept(session: web_transport::Session) -> Result<(Session, Self), SessionError> {
+	pub async fn accept(session: impl Into<transport::Session>) -> Result<(Session, Self), SessionError> {
 		let (session, _, subscriber) = Session::accept_role(session, setup::Role::Subscriber).await?;
 		Ok((session, subscriber.unwrap()))
 	}
 
-	pub async fn connect(session: web_transport::Session) -> Result<(Session, Self), SessionError> {
+	pub async fn connect(session: impl Into<transport::Session>) -> Result<(Session, Self), SessionError> {
 		let (session, _, subscriber) = Session::connect_role(session, setup::Role::Subscriber).await?;
 		Ok((session, subscriber.unwrap()))
 	}
 
+	/// Connect using the provided options, ex. to send a [setup::Path], although the role is always subscriber.
+	pub async fn connect_with(
+		session: impl Into<transport::Session>,
+		mut options: SetupOptions,
+	) -> Result<(Session, Self), SessionError> {
+		options.role = setup::Role::Subscriber;
+		let (session, _, subscriber) = Session::connect_with(session, options).await?;
+		Ok((session, subscriber.unwrap()))
+	}
+
+	/// Choose how subscribe IDs are assigned for this session, applying to every clone.
+	pub fn with_ids(self, ids: SubscribeIds) -> Self {
+		self.subscribe_ids.lock().unwrap().mode = ids;
+		self
+	}
+
+	/// Send this authorization token with each SUBSCRIBE, applying to every clone.
+	pub fn with_token(self, token: impl Into<String>) -> Self {
+		self.auth.lock().unwrap().token = Some(token.into());
+		self
+	}
+
+	/// Called when the publisher asks for a new token, ex. because the current one is about to expire.
+	///
+	/// The new token is sent for that subscription and used for any future ones.
+	/// Returning None leaves the subscription to be closed by the publisher.
+	pub fn with_token_refresh<F, Fut>(self, refresh: F) -> Self
+	where
+		F: Fn(SubscribeInfo) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Option<String>> + Send + 'static,
+	{
+		self.auth.lock().unwrap().refresh = Some(Arc::new(move |info| refresh(info).boxed()));
+		self
+	}
+
+	pub(super) fn token(&self) -> Option<String> {
+		self.auth.lock().unwrap().token.clone()
+	}
+
+	/// Ask the publisher to send the catalog and init tracks at the highest priority, applying to every clone.
+	///
+	/// Enabled by default, but only sent when the publisher supports SUBSCRIBE_UPDATE.
+	/// See [serve::TrackName::is_bootstrap].
+	pub fn with_bootstrap_priority(self, enabled: bool) -> Self {
+		self.bootstrap.store(enabled, Ordering::Relaxed);
+		self
+	}
+
+	// Returns true if the track should be requested at the highest priority.
+	pub(super) fn bootstrap_priority(&self, name: &str) -> bool {
+		self.capabilities.update
+			&& self.bootstrap.load(Ordering::Relaxed)
+			&& serve::TrackName::parse(name).is_bootstrap()
+	}
+
+	/// The hashed subscribe IDs assigned so far and the `namespace/name` of each, for debugging.
+	///
+	/// This is empty unless [SubscribeIds::Hashed] is used.
+	pub fn subscribe_ids(&self) -> BTreeMap<u64, String> {
+		self.subscribe_ids.lock().unwrap().hashed.clone()
+	}
+
+	/// The optional extensions supported by both endpoints, negotiated during SETUP.
+	pub fn capabilities(&self) -> setup::Capabilities {
+		self.capabilities
+	}
+
+	/// The offset between our clock and the publisher's, used to measure the delay of each group stream.
+	pub fn clock(&self) -> ClockSync {
+		self.clock.clone()
+	}
+
+	/// Sample the offset between our clock and the publisher's every [CLOCK_INTERVAL] until an error.
+	///
+	/// The namespace only routes the subscription, so any namespace announced by the publisher works.
+	/// Until a sample is taken, each [serve::GroupTransit] assumes the clocks are in sync.
+	pub async fn sync_clock(&mut self, namespace: &str) -> Result<(), ServeError> {
+		if !self.capabilities.clock {
+			return Err(ServeError::Unsupported("clock".to_string()));
+		}
+
+		loop {
+			let (writer, reader) = serve::Track::new(namespace.to_string(), CLOCK_TRACK.to_string()).produce();
+
+			let sent = clock::now();
+			let _subscribe = self.start(writer, Default::default());
+			let remote = clock::read_clock(reader).await?;
+			self.clock.sample(sent, remote, clock::now());
+
+			tokio::time::sleep(CLOCK_INTERVAL).await;
+		}
+	}
+
+	/// Change the range or priority of every active subscription to this track, without resubscribing.
+	///
+	/// Fails with [ServeError::NotFound] if there's no such subscription, or [ServeError::Unsupported] if the publisher can't be updated.
+	pub fn update(&mut self, namespace: &str, name: &str, update: SubscribeUpdate) -> Result<(), ServeError> {
+		if !self.capabilities.update {
+			return Err(ServeError::Unsupported("update".to_string()));
+		}
+
+		let ids: Vec<u64> = self
+			.subscribes
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|(_, subscribe)| subscribe.info.namespace == namespace && subscribe.info.name == name)
+			.map(|(id, _)| *id)
+			.collect();
+
+		if ids.is_empty() {
+			return Err(ServeError::NotFound);
+		}
+
+		let (start, end) = subscribe_range(update.start_group, None, update.end_group);
+
+		for id in ids {
+			self.send_message(message::SubscribeUpdate {
+				id,
+				start: start.clone(),
+				end: end.clone(),
+				priority: update.priority,
+				params: Default::default(),
+			});
+		}
+
+		Ok(())
+	}
+
 	pub async fn announced(&mut self) -> Option<Announced> {
 		self.announced_queue.pop().await
 	}
 
 	pub async fn subscribe(&mut self, track: serve::TrackWriter) -> Result<(), ServeError> {
-		let id = self.subscribe_next.fetch_add(1, atomic::Ordering::Relaxed);
+		self.subscribe_with(track, Default::default()).await?;
+		Ok(())
+	}
+
+	/// Subscribe to a track, using the provided options instead of the defaults.
+	///
+	/// Returns how the publisher ended the subscription, see [Subscribe::closed].
+	pub async fn subscribe_with(
+		&mut self,
+		track: serve::TrackWriter,
+		options: SubscribeOptions,
+	) -> Result<Option<SubscribeClosed>, ServeError> {
+		self.start(track, options).closed().await
+	}
+
+	/// Subscribe to a track without waiting, returning a handle to follow it, ex. with [Subscribe::stats].
+	///
+	/// Dropping the handle unsubscribes.
+	pub fn subscribe_start(&mut self, track: serve::TrackWriter, options: SubscribeOptions) -> Subscribe {
+		self.start(track, options)
+	}
+
+	/// Subscribe to several tracks as a unit, see [SubscribeBundle].
+	pub fn subscribe_bundle<I>(&mut self, tracks: I) -> SubscribeBundle
+	where
+		I: IntoIterator<Item = (serve::TrackWriter, SubscribeOptions)>,
+	{
+		let _batch = self.batch();
+		let subscribes = tracks
+			.into_iter()
+			.map(|(track, options)| self.start(track, options))
+			.collect();
+
+		SubscribeBundle::new(subscribes)
+	}
 
-		let (send, recv) = Subscribe::new(self.clone(), id, track);
+	/// Hold back SUBSCRIBE, SUBSCRIBE_UPDATE, and UNSUBSCRIBE messages until the returned guard is dropped,
+	/// then send them together, ex. dropping one [SubscribeBundle] and creating another to switch renditions.
+	///
+	/// The messages are sent as a single BATCH if the capability was negotiated, otherwise one at a time.
+	/// Don't wait for a subscription while batching, since it isn't sent until the guard is dropped.
+	pub fn batch(&self) -> SubscriberBatch {
+		let mut batch = self.batch.lock().unwrap();
+
+		// Nested batches are sent by the outermost guard.
+		if batch.is_some() {
+			return SubscriberBatch::new(None);
+		}
+
+		*batch = Some(Vec::new());
+		SubscriberBatch::new(Some(self.clone()))
+	}
+
+	pub(super) fn send_batch(&mut self) {
+		let messages = self.batch.lock().unwrap().take().unwrap_or_default();
+
+		if self.capabilities.batch && messages.len() > 1 {
+			let _ = self.outgoing.push(message::Batch { messages }.into());
+			return;
+		}
+
+		for msg in messages {
+			let _ = self.outgoing.push(msg.into());
+		}
+	}
+
+	/// Retrieve a range of past groups from the publisher's cache, returning once they've all arrived.
+	///
+	/// The groups are written to the track as a single stream, see [serve::StreamReader].
+	/// Only groups still cached by the publisher are sent, so the range may be incomplete.
+	pub async fn fetch(&mut self, track: serve::TrackWriter, options: FetchOptions) -> Result<(), ServeError> {
+		if !self.capabilities.fetch {
+			return Err(ServeError::Unsupported("fetch".to_string()));
+		}
+
+		let id = self.subscribe_ids.lock().unwrap().assign(&track.namespace, &track.name);
+
+		let (send, recv) = Subscribe::fetch(self.clone(), id, track, options);
+		self.subscribes.lock().unwrap().insert(id, recv);
+
+		send.closed().await?;
+		Ok(())
+	}
+
+	fn start(&mut self, track: serve::TrackWriter, options: SubscribeOptions) -> Subscribe {
+		let id = self.subscribe_ids.lock().unwrap().assign(&track.namespace, &track.name);
+
+		let (send, recv) = Subscribe::new(self.clone(), id, track, options);
 		self.subscribes.lock().unwrap().insert(id, recv);
 
-		send.closed().await
+		send
 	}
 
 	pub(super) fn send_message<M: Into<message::Subscriber>>(&mut self, msg: M) {
@@ -69,9 +406,25 @@ impl Subscriber {
 		match &msg {
 			message::Subscriber::AnnounceCancel(msg) => self.drop_announce(&msg.namespace),
 			message::Subscriber::AnnounceError(msg) => self.drop_announce(&msg.namespace),
+			message::Subscriber::Unsubscribe(msg) => self.drop_subscribe(msg.id),
+			message::Subscriber::FetchCancel(msg) => self.drop_subscribe(msg.id),
 			_ => {}
 		}
 
+		let batched = matches!(
+			msg,
+			message::Subscriber::Subscribe(_)
+				| message::Subscriber::SubscribeUpdate(_)
+				| message::Subscriber::Unsubscribe(_)
+		);
+
+		if batched {
+			if let Some(batch) = self.batch.lock().unwrap().as_mut() {
+				batch.push(msg);
+				return;
+			}
+		}
+
 		// TODO report dropped messages?
 		let _ = self.outgoing.push(msg.into());
 	}
@@ -83,6 +436,11 @@ impl Subscriber {
 			message::Publisher::SubscribeOk(msg) => self.recv_subscribe_ok(msg),
 			message::Publisher::SubscribeError(msg) => self.recv_subscribe_error(msg),
 			message::Publisher::SubscribeDone(msg) => self.recv_subscribe_done(msg),
+			message::Publisher::SubscribeRenew(msg) => {
+				self.renewals.push(msg.clone()).map_err(|_| ServeError::Done.into())
+			}
+			message::Publisher::FetchOk(msg) => self.recv_fetch_ok(msg),
+			message::Publisher::FetchError(msg) => self.recv_fetch_error(msg),
 		};
 
 		if let Err(SessionError::Serve(err)) = res {
@@ -101,7 +459,15 @@ impl Subscriber {
 			hash_map::Entry::Vacant(entry) => entry,
 		};
 
-		let (announced, recv) = Announced::new(self.clone(), msg.namespace.to_string());
+		// Malformed parameters are treated as missing, leaving it to the application to require them.
+		let info = AnnounceInfo {
+			namespace: msg.namespace.clone(),
+			signature: msg.params.clone().get(message::Announce::SIGNATURE).ok().flatten(),
+			broadcast_id: msg.params.clone().get(message::Announce::BROADCAST_ID).ok().flatten(),
+			authorization: msg.params.clone().get(message::Announce::AUTHORIZATION).ok().flatten(),
+		};
+
+		let (announced, recv) = Announced::new(self.clone(), info);
 		if let Err(announced) = self.announced_queue.push(announced) {
 			announced.close(ServeError::Cancel)?;
 			return Ok(());
@@ -122,7 +488,7 @@ impl Subscriber {
 
 	fn recv_subscribe_ok(&mut self, msg: &message::SubscribeOk) -> Result<(), SessionError> {
 		if let Some(subscribe) = self.subscribes.lock().unwrap().get_mut(&msg.id) {
-			subscribe.ok()?;
+			subscribe.ok(msg.epoch)?;
 		}
 
 		Ok(())
@@ -137,6 +503,37 @@ impl Subscriber {
 	}
 
 	fn recv_subscribe_done(&mut self, msg: &message::SubscribeDone) -> Result<(), SessionError> {
+		let mut subscribes = self.subscribes.lock().unwrap();
+
+		// A clean close, so finish after the final group arrives instead of discarding anything in flight.
+		if let Some(status) = message::SubscribeStatus::from_code(msg.code) {
+			if let Some(subscribe) = subscribes.get_mut(&msg.id) {
+				if !subscribe.done(status, msg.last) {
+					return Ok(());
+				}
+			}
+
+			// Dropping the writer ends the track without an error.
+			subscribes.remove(&msg.id);
+			return Ok(());
+		}
+
+		if let Some(subscribe) = subscribes.remove(&msg.id) {
+			subscribe.error(ServeError::Closed(msg.code))?;
+		}
+
+		Ok(())
+	}
+
+	fn recv_fetch_ok(&mut self, msg: &message::FetchOk) -> Result<(), SessionError> {
+		if let Some(subscribe) = self.subscribes.lock().unwrap().get_mut(&msg.id) {
+			subscribe.ok(None)?;
+		}
+
+		Ok(())
+	}
+
+	fn recv_fetch_error(&mut self, msg: &message::FetchError) -> Result<(), SessionError> {
 		if let Some(subscribe) = self.subscribes.lock().unwrap().remove(&msg.id) {
 			subscribe.error(ServeError::Closed(msg.code))?;
 		}
@@ -144,17 +541,90 @@ impl Subscriber {
 		Ok(())
 	}
 
+	// Ask the application for a new token each time the publisher requests one.
+	pub(super) async fn run_renewals(mut self) -> Result<(), SessionError> {
+		let mut tasks = FuturesUnordered::new();
+
+		loop {
+			tokio::select! {
+				Some(msg) = self.renewals.pop() => {
+					let refresh = self.auth.lock().unwrap().refresh.clone();
+					let info = self.subscribes.lock().unwrap().get(&msg.id).map(|subscribe| subscribe.info.clone());
+
+					match (refresh, info) {
+						(Some(refresh), Some(info)) => tasks.push(self.clone().renew(msg.id, info, refresh)),
+						_ => log::debug!("ignoring SUBSCRIBE_RENEW: {:?}", msg),
+					}
+				},
+				_ = tasks.next(), if !tasks.is_empty() => {},
+				else => return future::pending().await,
+			}
+		}
+	}
+
+	async fn renew(mut self, id: u64, info: SubscribeInfo, refresh: TokenRefresh) {
+		let token = match refresh(info.clone()).await {
+			Some(token) => token,
+			None => {
+				log::info!("no token to renew subscription: {:?}", info);
+				return;
+			}
+		};
+
+		self.auth.lock().unwrap().token = Some(token.clone());
+		self.send_message(message::SubscribeRenewOk {
+			id,
+			authorization: token,
+		});
+	}
+
 	fn drop_announce(&mut self, namespace: &str) {
 		self.announced.lock().unwrap().remove(namespace);
 	}
 
-	pub(super) async fn recv_stream(mut self, stream: web_transport::RecvStream) -> Result<(), SessionError> {
+	fn drop_subscribe(&mut self, id: u64) {
+		self.subscribes.lock().unwrap().remove(&id);
+	}
+
+	// Resolves when the subscription's current writer has no readers, or when the writer is replaced.
+	pub(super) fn subscribe_unused(&self, id: u64) -> Option<BoxFuture<'static, ()>> {
+		self.subscribes.lock().unwrap().get(&id)?.unused()
+	}
+
+	pub(super) async fn recv_stream(
+		mut self,
+		stream: transport::RecvStream,
+		history: History,
+	) -> Result<(), SessionError> {
 		let mut reader = Reader::new(stream);
 		let header: data::Header = reader.decode().await?;
+		history.record(HistoryKind::Stream, || format!("{:?}", header));
 
 		let id = header.subscribe_id();
 
-		let res = self.recv_stream_inner(reader, header).await;
+		// Acknowledge each group stream once it's done, however it ended, if the subscription has a receive window.
+		let ack = match &header {
+			data::Header::Group(_) | data::Header::GroupExt(_) | data::Header::Subgroup(_) => header.group_id(),
+			_ => None,
+		}
+		.filter(|_| {
+			let subscribes = self.subscribes.lock().unwrap();
+			subscribes
+				.get(&id)
+				.is_some_and(|subscribe| subscribe.options().receive_window.is_some())
+		});
+
+		// A panic, ex. from malformed data, only closes this subscription.
+		let res = supervise(self.recv_stream_inner(reader, header)).await;
+
+		if let Some(group_id) = ack {
+			self.send_message(message::SubscribeAck { id, group_id });
+		}
+		if let Err(SessionError::Serve(ServeError::Full)) = &res {
+			// Drop the group but keep the subscription, which recovers once the cache is released.
+			return res;
+		}
+
 		if let Err(SessionError::Serve(err)) = &res {
 			// The writer is closed, so we should teriminate.
 			// TODO it would be nice to do this immediately when the Writer is closed.
@@ -166,38 +636,98 @@ impl Subscriber {
 		res
 	}
 
-	async fn recv_stream_inner(&mut self, reader: Reader, header: data::Header) -> Result<(), SessionError> {
+	async fn recv_stream_inner(&mut self, mut reader: Reader, header: data::Header) -> Result<(), SessionError> {
 		let id = header.subscribe_id();
+		let extended = self.capabilities.extensions;
+
+		// An object stream's extension headers follow its header, since the rest of the stream is the payload.
+		let extensions = match &header {
+			data::Header::Object(_) if extended => reader.decode::<data::Extensions>().await?,
+			_ => data::Extensions::default(),
+		};
 
 		// This is super silly, but I couldn't figure out a way to avoid the mutex guard across awaits.
 		enum Writer {
 			Track(serve::StreamWriter),
 			Group(serve::GroupWriter),
+			Subgroup(serve::SubgroupWriter),
 			Object(serve::ObjectWriter),
 		}
 
-		let writer = {
+		let (writer, options, fetch, stats) = {
 			let mut subscribes = self.subscribes.lock().unwrap();
 			let subscribe = subscribes.get_mut(&id).ok_or(ServeError::NotFound)?;
 
-			match header {
+			let writer = match header {
 				data::Header::Track(track) => Writer::Track(subscribe.track(track)?),
-				data::Header::Group(group) => Writer::Group(subscribe.group(group)?),
-				data::Header::Object(object) => Writer::Object(subscribe.object(object)?),
+				data::Header::Group(group) => Writer::Group(subscribe.group(serve::Group {
+					group_id: group.group_id,
+					priority: group.send_order,
+					timestamp: Default::default(),
+					annotations: Default::default(),
+					subgroups: 0,
+					transit: None,
+				})?),
+				data::Header::GroupExt(group) => Writer::Group(subscribe.group(serve::Group {
+					group_id: group.group_id,
+					priority: group.send_order,
+					timestamp: serve::GroupTimestamp {
+						media: group.media_time,
+						wall: group.wall_time,
+					},
+					annotations: group.annotations.into(),
+					subgroups: 0,
+					transit: group.send_time.map(|sent| {
+						let (received, synced) = self.clock.remote(clock::now());
+						serve::GroupTransit { sent, received, synced }
+					}),
+				})?),
+				data::Header::Subgroup(subgroup) => Writer::Subgroup(subscribe.subgroup(subgroup)?),
+				data::Header::Object(object) => Writer::Object(subscribe.object(object, extensions)?),
+			};
+
+			let options = subscribe.options();
+			let fetch = subscribe.fetch;
+			let stats = subscribe.stats();
+
+			// This was the final group, so end the track once it's been received.
+			if subscribe.finished() {
+				subscribes.remove(&id);
 			}
+
+			(writer, options, fetch, stats)
 		};
 
 		match writer {
-			Writer::Track(track) => Self::recv_track(track, reader).await?,
-			Writer::Group(group) => Self::recv_group(group, reader).await?,
-			Writer::Object(object) => Self::recv_object(object, reader).await?,
+			Writer::Track(track) => {
+				Self::recv_track(track, reader, stats).await?;
+
+				// A fetch is sent on a single stream, so it's complete once the stream ends.
+				if fetch {
+					self.subscribes.lock().unwrap().remove(&id);
+				}
+			}
+			Writer::Group(group) => match Self::recv_group(group, reader, options, extended, stats).await {
+				// Every reader released the group, ex. it arrived late or was skipped, but later groups are still wanted.
+				Err(SessionError::Serve(ServeError::Cancel)) => return Ok(()),
+				res => res?,
+			},
+			Writer::Subgroup(subgroup) => match Self::recv_subgroup(subgroup, reader, options, extended, stats).await {
+				Err(SessionError::Serve(ServeError::Cancel)) => return Ok(()),
+				res => res?,
+			},
+			Writer::Object(object) => Self::recv_object(object, reader, stats).await?,
 		};
 
 		Ok(())
 	}
 
-	async fn recv_track(mut track: serve::StreamWriter, mut reader: Reader) -> Result<(), SessionError> {
-		log::trace!("received track: {:?}", track.info);
+	async fn recv_track(
+		mut track: serve::StreamWriter,
+		mut reader: Reader,
+		stats: SubscribeCounter,
+	) -> Result<(), SessionError> {
+		crate::sampled!(log::Level::Trace, "received track", "{:?}", track.info);
 
 		let mut prev: Option<serve::StreamGroupWriter> = None;
 
@@ -206,16 +736,20 @@ impl Subscriber {
 
 			let mut group = match prev {
 				Some(group) if group.group_id == chunk.group_id => group,
-				_ => track.create(chunk.group_id)?,
+				_ => {
+					stats.group(chunk.group_id);
+					track.create(chunk.group_id)?
+				}
 			};
 
 			let mut object = group.create(chunk.size)?;
+			stats.object(chunk.group_id, chunk.size);
 
 			let mut remain = chunk.size;
 			while remain > 0 {
 				let chunk = reader.read_chunk(remain).await?.ok_or(SessionError::WrongSize)?;
 
-				log::trace!("received track payload: {:?}", chunk.len());
+				crate::sampled!(log::Level::Trace, "received track payload", "{:?}", chunk.len());
 				remain -= chunk.len();
 				object.write(chunk)?;
 			}
@@ -226,35 +760,151 @@ impl Subscriber {
 		Ok(())
 	}
 
-	async fn recv_group(mut group: serve::GroupWriter, mut reader: Reader) -> Result<(), SessionError> {
-		log::trace!("received group: {:?}", group.info);
+	async fn recv_group(
+		mut group: serve::GroupWriter,
+		reader: Reader,
+		options: SubscribeOptions,
+		extended: bool,
+		stats: SubscribeCounter,
+	) -> Result<(), SessionError> {
+		crate::sampled!(log::Level::Trace, "received group", "{:?}", group.info);
+
+		// The publisher skips objects before the requested start object.
+		let mut expected = 0;
+		if options.start_group == Some(group.group_id) {
+			expected = options.start_object.unwrap_or(0);
+			group.skip_to(expected);
+		}
+
+		let group_id = group.group_id;
+		Self::recv_objects(reader, expected, options, extended, |size, independent, extensions| {
+			stats.object(group_id, size);
+			group.create_with(size, independent, extensions)
+		})
+		.await
+	}
+
+	async fn recv_subgroup(
+		mut subgroup: serve::SubgroupWriter,
+		reader: Reader,
+		options: SubscribeOptions,
+		extended: bool,
+		stats: SubscribeCounter,
+	) -> Result<(), SessionError> {
+		crate::sampled!(log::Level::Trace, "received subgroup", "{:?}", subgroup.info);
+
+		// Each subgroup is numbered separately, so the start object applies to each of them.
+		let mut expected = 0;
+		if options.start_group == Some(subgroup.group_id) {
+			expected = options.start_object.unwrap_or(0);
+			subgroup.skip_to(expected);
+		}
+
+		let group_id = subgroup.group_id;
+		Self::recv_objects(reader, expected, options, extended, |size, independent, extensions| {
+			stats.object(group_id, size);
+			subgroup.create_with(size, independent, extensions)
+		})
+		.await
+	}
+
+	// Read the objects on a group or subgroup stream, creating each one in order.
+	async fn recv_objects<F>(
+		mut reader: Reader,
+		mut expected: u64,
+		options: SubscribeOptions,
+		extended: bool,
+		mut create: F,
+	) -> Result<(), SessionError>
+	where
+		F: FnMut(usize, bool, serve::ObjectExtensions) -> Result<serve::GroupObjectWriter, ServeError>,
+	{
+		// Objects that arrived ahead of the expected ID, buffered until the gap is filled.
+		let mut pending: BTreeMap<u64, (usize, bool, serve::ObjectExtensions, Vec<Bytes>)> = BTreeMap::new();
 
 		while !reader.done().await? {
-			let object: data::GroupObject = reader.decode().await?;
+			let object: data::GroupObject = match extended {
+				true => {
+					reader
+						.decode_with(|cursor| data::GroupObject::decode_extended(cursor))
+						.await?
+				}
+				false => reader.decode().await?,
+			};
+			crate::sampled!(log::Level::Trace, "received group object", "{:?}", object);
+
+			let mut extensions = object.extensions;
+			let independent = extensions.take_independent();
+			let extensions = serve::ObjectExtensions::from(extensions.0);
+
+			if object.object_id == expected {
+				let mut remain = object.size;
+				let mut object = create(object.size, independent, extensions)?;
+
+				while remain > 0 {
+					let data = reader.read_chunk(remain).await?.ok_or(SessionError::WrongSize)?;
+					crate::sampled!(log::Level::Trace, "received group payload", "{:?}", data.len());
+					remain -= data.len();
+					object.write(data)?;
+				}
+
+				expected += 1;
+			} else if object.object_id > expected && object.object_id - expected <= options.reorder_window {
+				let mut chunks = Vec::new();
+				let mut remain = object.size;
+
+				while remain > 0 {
+					let data = reader.read_chunk(remain).await?.ok_or(SessionError::WrongSize)?;
+					crate::sampled!(log::Level::Trace, "buffered group payload", "{:?}", data.len());
+					remain -= data.len();
+					chunks.push(data);
+				}
+
+				if pending
+					.insert(object.object_id, (object.size, independent, extensions, chunks))
+					.is_some()
+				{
+					return Err(SessionError::Duplicate);
+				}
+			} else {
+				return Err(SessionError::OutOfOrder(expected, object.object_id));
+			}
 
-			log::trace!("received group object: {:?}", object);
-			let mut remain = object.size;
-			let mut object = group.create(object.size)?;
+			// Flush any buffered objects that are now in order.
+			while let Some((size, independent, extensions, chunks)) = pending.remove(&expected) {
+				let mut object = create(size, independent, extensions)?;
+				for data in chunks {
+					object.write(data)?;
+				}
 
-			while remain > 0 {
-				let data = reader.read_chunk(remain).await?.ok_or(SessionError::WrongSize)?;
-				log::trace!("received group payload: {:?}", data.len());
-				remain -= data.len();
-				object.write(data)?;
+				expected += 1;
 			}
 		}
 
+		if let Some(&object_id) = pending.keys().next() {
+			return Err(SessionError::OutOfOrder(expected, object_id));
+		}
+
 		Ok(())
 	}
 
-	async fn recv_object(mut object: serve::ObjectWriter, mut reader: Reader) -> Result<(), SessionError> {
-		log::trace!("received object: {:?}", object.info);
+	async fn recv_object(
+		mut object: serve::ObjectWriter,
+		mut reader: Reader,
+		stats: SubscribeCounter,
+	) -> Result<(), SessionError> {
+		crate::sampled!(log::Level::Trace, "received object", "{:?}", object.info);
 
+		// The size isn't known until the stream ends.
+		let mut size = 0;
 		while let Some(data) = reader.read_chunk(usize::MAX).await? {
-			log::trace!("received object payload: {:?}", data.len());
+			crate::sampled!(log::Level::Trace, "received object payload", "{:?}", data.len());
+			size += data.len();
 			object.write(data)?;
 		}
 
+		stats.ungrouped(object.info.group_id, size);
+
 		Ok(())
 	}
 
@@ -262,10 +912,57 @@ impl Subscriber {
 		let mut cursor = io::Cursor::new(datagram);
 		let datagram = data::Datagram::decode(&mut cursor)?;
 
-		if let Some(subscribe) = self.subscribes.lock().unwrap().get_mut(&datagram.subscribe_id) {
-			subscribe.datagram(datagram)?;
+		let id = datagram.subscribe_id;
+		let mut subscribes = self.subscribes.lock().unwrap();
+
+		// Ignore datagrams that arrive after the subscription ended, since they can be reordered.
+		let subscribe = match subscribes.get_mut(&id) {
+			Some(subscribe) => subscribe,
+			None => return Ok(()),
+		};
+
+		// Like a stream, a datagram that can't be delivered only closes this subscription.
+		if let Err(err) = subscribe.datagram(datagram) {
+			crate::sampled!(log::Level::Debug, "failed to deliver datagram", "id={} err={}", id, err);
+			if let Some(subscribe) = subscribes.remove(&id) {
+				subscribe.error(err)?;
+			}
+
+			return Ok(());
+		}
+
+		if subscribe.finished() {
+			subscribes.remove(&id);
 		}
 
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hashed_ids() {
+		let mut ids = SubscribeIdState {
+			mode: SubscribeIds::Hashed,
+			..Default::default()
+		};
+
+		let video = ids.assign("live/demo", "video");
+		assert_eq!(video, hash_id("live/demo", "video"));
+		assert!(video <= u64::from(VarInt::MAX));
+		assert_ne!(hash_id("live/demo", "video"), hash_id("live/demov", "ideo"));
+
+		// Resubscribing picks the next unused ID.
+		assert_eq!(ids.assign("live/demo", "video"), video + 1);
+		assert_eq!(ids.hashed.len(), 2);
+		assert_eq!(ids.hashed[&video], "live/demo/video");
+
+		// Sequential IDs skip any hashed IDs.
+		ids.mode = SubscribeIds::Sequential;
+		ids.next = video;
+		assert_eq!(ids.assign("live/demo", "audio"), video + 2);
+	}
+}
@@ -0,0 +1,66 @@
+use std::{
+	any::Any,
+	future::Future,
+	panic::AssertUnwindSafe,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use futures::FutureExt;
+
+use super::SessionError;
+use crate::serve::ServeError;
+
+static PANICS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of panics caught in per-stream and per-subscription tasks since the process started.
+pub fn panics() -> u64 {
+	PANICS.load(Ordering::Relaxed)
+}
+
+// Run a per-stream or per-subscription task, converting a panic into an error so only that task fails.
+// The caller is responsible for closing the stream or subscription with the error.
+pub(super) async fn supervise<T, F>(fut: F) -> Result<T, SessionError>
+where
+	F: Future<Output = Result<T, SessionError>>,
+{
+	match AssertUnwindSafe(fut).catch_unwind().await {
+		Ok(res) => res,
+		Err(panic) => {
+			PANICS.fetch_add(1, Ordering::Relaxed);
+
+			let msg = panic_message(panic.as_ref());
+			log::error!("task panicked: {}", msg);
+
+			Err(ServeError::Internal(format!("panic: {}", msg)).into())
+		}
+	}
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+	if let Some(msg) = panic.downcast_ref::<&str>() {
+		msg
+	} else if let Some(msg) = panic.downcast_ref::<String>() {
+		msg
+	} else {
+		"unknown"
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn panic() {
+		let before = panics();
+
+		let res: Result<(), _> = supervise(async { panic!("malformed data") }).await;
+		match res {
+			Err(SessionError::Serve(ServeError::Internal(msg))) => assert_eq!(msg, "panic: malformed data"),
+			res => panic!("unexpected result: {:?}", res),
+		}
+
+		assert_eq!(panics(), before + 1);
+		assert!(supervise(async { Ok(()) }).await.is_ok());
+	}
+}
@@ -1,17 +1,18 @@
 use std::io;
 
 use crate::coding::{Encode, EncodeError};
+use crate::transport;
 
 use super::SessionError;
 use bytes::Buf;
 
 pub struct Writer {
-	stream: web_transport::SendStream,
+	stream: transport::SendStream,
 	buffer: bytes::BytesMut,
 }
 
 impl Writer {
-	pub fn new(stream: web_transport::SendStream) -> Self {
+	pub fn new(stream: transport::SendStream) -> Self {
 		Self {
 			stream,
 			buffer: Default::default(),
@@ -19,8 +20,16 @@ impl Writer {
 	}
 
 	pub async fn encode<T: Encode>(&mut self, msg: &T) -> Result<(), SessionError> {
+		self.encode_with(|buffer| msg.encode(buffer)).await
+	}
+
+	/// Encode using the provided function, ex. for a format that depends on the negotiated version.
+	pub async fn encode_with<F>(&mut self, encode: F) -> Result<(), SessionError>
+	where
+		F: FnOnce(&mut bytes::BytesMut) -> Result<(), EncodeError>,
+	{
 		self.buffer.clear();
-		msg.encode(&mut self.buffer)?;
+		encode(&mut self.buffer)?;
 
 		while !self.buffer.is_empty() {
 			self.stream.write_buf(&mut self.buffer).await?;
@@ -29,6 +38,11 @@ impl Writer {
 		Ok(())
 	}
 
+	/// Reset the stream, abandoning anything not yet delivered.
+	pub fn reset(self, code: u32) {
+		self.stream.reset(code)
+	}
+
 	pub async fn write(&mut self, buf: &[u8]) -> Result<(), SessionError> {
 		let mut cursor = io::Cursor::new(buf);
 
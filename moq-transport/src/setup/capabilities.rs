@@ -0,0 +1,200 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Param};
+
+/// Optional extensions supported by an endpoint, advertised during SETUP.
+///
+/// Encoded as a varint bitmask. Unknown bits are ignored so newer peers can advertise more.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+	/// OBJECT datagrams are supported.
+	pub datagrams: bool,
+
+	/// The FETCH message is supported.
+	pub fetch: bool,
+
+	/// The SUBSCRIBE_NAMESPACE message is supported.
+	pub subscribe_namespace: bool,
+
+	/// Forward error correction is supported.
+	pub fec: bool,
+
+	/// SUBSCRIBE_OK may carry the track epoch.
+	pub epoch: bool,
+
+	/// Group streams may carry timestamps and annotations via the GROUP_EXT header.
+	pub timestamps: bool,
+
+	/// The publisher may ask for a new authorization token with SUBSCRIBE_RENEW.
+	pub renewal: bool,
+
+	/// The subscriber may change an active subscription with SUBSCRIBE_UPDATE.
+	pub update: bool,
+
+	/// A group's objects may be split across multiple SUBGROUP streams.
+	pub subgroups: bool,
+
+	/// The subscriber may limit the groups in flight with a receive window, acknowledging each with SUBSCRIBE_ACK.
+	pub window: bool,
+
+	/// The subscriber may send several control messages at once with BATCH.
+	pub batch: bool,
+
+	/// The publisher answers SUBSCRIBE for the clock-sync track and stamps group streams with the send time.
+	pub clock: bool,
+
+	/// Objects on group, subgroup and object streams may carry extension headers.
+	pub extensions: bool,
+
+	/// Either endpoint may check the other is alive with PING, which must be answered with PONG.
+	pub keepalive: bool,
+}
+
+impl Capabilities {
+	/// The SETUP parameter used to advertise capabilities.
+	// NOTE: This is not (yet) part of the draft, so the ID may change.
+	pub const PARAM: u64 = 0x3c;
+
+	const DATAGRAMS: u64 = 0x1;
+	const FETCH: u64 = 0x2;
+	const SUBSCRIBE_NAMESPACE: u64 = 0x4;
+	const FEC: u64 = 0x8;
+	const EPOCH: u64 = 0x10;
+	const TIMESTAMPS: u64 = 0x20;
+	const RENEWAL: u64 = 0x40;
+	const UPDATE: u64 = 0x80;
+	const SUBGROUPS: u64 = 0x100;
+	const WINDOW: u64 = 0x200;
+	const BATCH: u64 = 0x400;
+	const CLOCK: u64 = 0x800;
+	const EXTENSIONS: u64 = 0x1000;
+	const KEEPALIVE: u64 = 0x2000;
+
+	/// The capabilities implemented by this library.
+	pub fn supported() -> Self {
+		Self {
+			datagrams: true,
+			fetch: true,
+			epoch: true,
+			timestamps: true,
+			renewal: true,
+			update: true,
+			subgroups: true,
+			window: true,
+			batch: true,
+			clock: true,
+			extensions: true,
+			keepalive: true,
+			..Default::default()
+		}
+	}
+
+	/// Returns the capabilities supported by both endpoints.
+	pub fn intersect(&self, other: &Self) -> Self {
+		Self {
+			datagrams: self.datagrams && other.datagrams,
+			fetch: self.fetch && other.fetch,
+			subscribe_namespace: self.subscribe_namespace && other.subscribe_namespace,
+			fec: self.fec && other.fec,
+			epoch: self.epoch && other.epoch,
+			timestamps: self.timestamps && other.timestamps,
+			renewal: self.renewal && other.renewal,
+			update: self.update && other.update,
+			subgroups: self.subgroups && other.subgroups,
+			window: self.window && other.window,
+			batch: self.batch && other.batch,
+			clock: self.clock && other.clock,
+			extensions: self.extensions && other.extensions,
+			keepalive: self.keepalive && other.keepalive,
+		}
+	}
+
+	/// Returns true if no capabilities are advertised.
+	pub fn is_empty(&self) -> bool {
+		*self == Self::default()
+	}
+}
+
+impl From<Capabilities> for u64 {
+	fn from(c: Capabilities) -> Self {
+		let mut v = 0;
+		if c.datagrams {
+			v |= Capabilities::DATAGRAMS;
+		}
+		if c.fetch {
+			v |= Capabilities::FETCH;
+		}
+		if c.subscribe_namespace {
+			v |= Capabilities::SUBSCRIBE_NAMESPACE;
+		}
+		if c.fec {
+			v |= Capabilities::FEC;
+		}
+		if c.epoch {
+			v |= Capabilities::EPOCH;
+		}
+		if c.timestamps {
+			v |= Capabilities::TIMESTAMPS;
+		}
+		if c.renewal {
+			v |= Capabilities::RENEWAL;
+		}
+		if c.update {
+			v |= Capabilities::UPDATE;
+		}
+		if c.subgroups {
+			v |= Capabilities::SUBGROUPS;
+		}
+		if c.window {
+			v |= Capabilities::WINDOW;
+		}
+		if c.batch {
+			v |= Capabilities::BATCH;
+		}
+		if c.clock {
+			v |= Capabilities::CLOCK;
+		}
+		if c.extensions {
+			v |= Capabilities::EXTENSIONS;
+		}
+		if c.keepalive {
+			v |= Capabilities::KEEPALIVE;
+		}
+		v
+	}
+}
+
+impl From<u64> for Capabilities {
+	fn from(v: u64) -> Self {
+		Self {
+			datagrams: v & Self::DATAGRAMS != 0,
+			fetch: v & Self::FETCH != 0,
+			subscribe_namespace: v & Self::SUBSCRIBE_NAMESPACE != 0,
+			fec: v & Self::FEC != 0,
+			epoch: v & Self::EPOCH != 0,
+			timestamps: v & Self::TIMESTAMPS != 0,
+			renewal: v & Self::RENEWAL != 0,
+			update: v & Self::UPDATE != 0,
+			subgroups: v & Self::SUBGROUPS != 0,
+			window: v & Self::WINDOW != 0,
+			batch: v & Self::BATCH != 0,
+			clock: v & Self::CLOCK != 0,
+			extensions: v & Self::EXTENSIONS != 0,
+			keepalive: v & Self::KEEPALIVE != 0,
+		}
+	}
+}
+
+impl Param for Capabilities {
+	const ID: u64 = Self::PARAM;
+}
+
+impl Decode for Capabilities {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(u64::decode(r)?.into())
+	}
+}
+
+impl Encode for Capabilities {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		u64::from(*self).encode(w)
+	}
+}
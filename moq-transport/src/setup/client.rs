@@ -1,4 +1,4 @@
-use super::{Role, Versions};
+use super::{AuthToken, Capabilities, Path, Role, StickyToken, Versions};
 use crate::coding::{Decode, DecodeError, Encode, EncodeError, Params};
 
 /// Sent by the client to setup the session.
@@ -12,6 +12,18 @@ pub struct Client {
 	/// Indicate if the client is a publisher, a subscriber, or both.
 	pub role: Role,
 
+	/// Optional extensions supported by the client.
+	pub capabilities: Capabilities,
+
+	/// Authorizes the session, checked by the server before it responds.
+	pub token: Option<AuthToken>,
+
+	/// The token of the relay the client was redirected to, if any.
+	pub sticky: Option<StickyToken>,
+
+	/// The path of the URL, only sent over raw QUIC or TCP.
+	pub path: Option<Path>,
+
 	/// Unknown parameters.
 	pub params: Params,
 }
@@ -27,15 +39,21 @@ impl Decode for Client {
 		let versions = Versions::decode(r)?;
 		let mut params = Params::decode(r)?;
 
-		let role = params.get::<Role>(0)?.ok_or(DecodeError::MissingParameter)?;
-
-		// Make sure the PATH parameter isn't used
-		// TODO: This assumes WebTransport support only
-		if params.has(1) {
-			return Err(DecodeError::InvalidParameter);
-		}
-
-		Ok(Self { versions, role, params })
+		let role = params.get_typed::<Role>()?.ok_or(DecodeError::MissingParameter)?;
+		let capabilities = params.get_typed::<Capabilities>()?.unwrap_or_default();
+		let token = params.get_typed::<AuthToken>()?;
+		let sticky = params.get_typed::<StickyToken>()?;
+		let path = params.get_typed::<Path>()?;
+
+		Ok(Self {
+			versions,
+			role,
+			capabilities,
+			token,
+			sticky,
+			path,
+			params,
+		})
 	}
 }
 
@@ -46,7 +64,24 @@ impl Encode for Client {
 		self.versions.encode(w)?;
 
 		let mut params = self.params.clone();
-		params.set(0, self.role)?;
+		params.set_typed(self.role)?;
+
+		// Omitted when empty, which is indistinguishable from an older peer.
+		if !self.capabilities.is_empty() {
+			params.set_typed(self.capabilities)?;
+		}
+
+		if let Some(token) = &self.token {
+			params.set_typed(token.clone())?;
+		}
+
+		if let Some(sticky) = &self.sticky {
+			params.set_typed(sticky.clone())?;
+		}
+
+		if let Some(path) = &self.path {
+			params.set_typed(path.clone())?;
+		}
 
 		params.encode(w)?;
 
@@ -66,6 +101,10 @@ mod tests {
 		let client = Client {
 			versions: [Version::DRAFT_03].into(),
 			role: Role::Both,
+			capabilities: Capabilities::default(),
+			token: None,
+			sticky: None,
+			path: None,
 			params: Params::default(),
 		};
 
@@ -80,4 +119,48 @@ mod tests {
 		assert_eq!(decoded.role, client.role);
 		//assert_eq!(decoded.params, client.params);
 	}
+
+	#[test]
+	fn encode_decode_capabilities() {
+		let mut buf = BytesMut::new();
+		let client = Client {
+			versions: [Version::DRAFT_03].into(),
+			role: Role::Publisher,
+			capabilities: Capabilities {
+				datagrams: true,
+				fec: true,
+				..Default::default()
+			},
+			token: Some("secret".into()),
+			sticky: Some("relay-3".into()),
+			path: Path::new("/live?region=eu"),
+			params: Params::default(),
+		};
+
+		client.encode(&mut buf).unwrap();
+
+		let decoded = Client::decode(&mut buf).unwrap();
+		assert_eq!(decoded.capabilities, client.capabilities);
+		assert_eq!(decoded.token, client.token);
+		assert_eq!(decoded.sticky, client.sticky);
+		assert_eq!(decoded.path, client.path);
+		assert!(!decoded.params.has(Capabilities::PARAM));
+		assert!(!decoded.params.has(AuthToken::PARAM));
+		assert!(!decoded.params.has(StickyToken::PARAM));
+		assert!(!decoded.params.has(Path::PARAM));
+	}
+
+	#[test]
+	fn relative_path() {
+		let mut params = Params::default();
+		params.set_typed(Role::Both).unwrap();
+		params.set(Path::PARAM, "live".to_string()).unwrap();
+
+		let mut buf = BytesMut::new();
+		0x40_u64.encode(&mut buf).unwrap();
+		Versions::from([Version::DRAFT_03]).encode(&mut buf).unwrap();
+		params.encode(&mut buf).unwrap();
+
+		assert!(matches!(Client::decode(&mut buf), Err(DecodeError::InvalidParameter)));
+	}
 }
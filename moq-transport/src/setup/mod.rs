@@ -2,16 +2,27 @@
 //!
 //! After establishing the WebTransport session, the client creates a bidirectional QUIC stream.
 //! The client sends the [Client] message and the server responds with the [Server] message.
-//! Both sides negotate the [Version] and [Role].
+//! Both sides negotate the [Version] and [Role], and advertise optional [Capabilities].
+//! The client may also send an [AuthToken] for the server to check before accepting the session.
+//! Either side may send a [StickyToken], identifying the relay that a client should return to.
+//! Over raw QUIC, the client sends the [Path] of the URL, which WebTransport carries in its CONNECT request instead.
 
+mod capabilities;
 mod client;
+mod path;
 mod role;
 mod server;
+mod sticky;
+mod token;
 mod version;
 
+pub use capabilities::*;
 pub use client::*;
+pub use path::*;
 pub use role::*;
 pub use server::*;
+pub use sticky::*;
+pub use token::*;
 pub use version::*;
 
 pub const ALPN: &[u8] = b"moq-00";
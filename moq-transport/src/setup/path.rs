@@ -0,0 +1,42 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Param, Params};
+
+/// The path and query of the URL the client connected to, sent as a parameter in SETUP over raw QUIC or TCP.
+///
+/// WebTransport carries the path in its CONNECT request instead, but raw QUIC and TCP have nowhere else to put it.
+/// The server can use it to pick an application or route namespaces, ex. `/live?region=eu`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Path(String);
+
+impl Path {
+	/// The parameter ID used for the path, matching PATH in the draft.
+	pub const PARAM: u64 = Params::PATH;
+
+	/// Returns None unless the path is absolute, ex. `/` or `/live?region=eu`.
+	pub fn new(path: impl Into<String>) -> Option<Self> {
+		let path = path.into();
+		match path.starts_with('/') {
+			true => Some(Self(path)),
+			false => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl Param for Path {
+	const ID: u64 = Self::PARAM;
+}
+
+impl Decode for Path {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Self::new(String::decode(r)?).ok_or(DecodeError::InvalidParameter)
+	}
+}
+
+impl Encode for Path {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.0.encode(w)
+	}
+}
@@ -1,4 +1,4 @@
-use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Param, Params};
 
 /// Indicates the endpoint is a publisher, subscriber, or both.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -54,6 +54,10 @@ impl TryFrom<u64> for Role {
 	}
 }
 
+impl Param for Role {
+	const ID: u64 = Params::ROLE;
+}
+
 impl Decode for Role {
 	/// Decode the role.
 	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
@@ -1,4 +1,4 @@
-use super::{Role, Version};
+use super::{Capabilities, Role, StickyToken, Version};
 use crate::coding::{Decode, DecodeError, Encode, EncodeError, Params};
 
 /// Sent by the server in response to a client setup.
@@ -13,6 +13,12 @@ pub struct Server {
 	// Proposal: moq-wg/moq-transport#151
 	pub role: Role,
 
+	/// Optional extensions supported by the server.
+	pub capabilities: Capabilities,
+
+	/// Identifies the server, so a client can be redirected back to it.
+	pub sticky: Option<StickyToken>,
+
 	/// Unknown parameters.
 	pub params: Params,
 }
@@ -28,14 +34,22 @@ impl Decode for Server {
 		let version = Version::decode(r)?;
 		let mut params = Params::decode(r)?;
 
-		let role = params.get::<Role>(0)?.ok_or(DecodeError::MissingParameter)?;
+		let role = params.get_typed::<Role>()?.ok_or(DecodeError::MissingParameter)?;
+		let capabilities = params.get_typed::<Capabilities>()?.unwrap_or_default();
+		let sticky = params.get_typed::<StickyToken>()?;
 
 		// Make sure the PATH parameter isn't used
-		if params.has(1) {
+		if params.has(Params::PATH) {
 			return Err(DecodeError::InvalidParameter);
 		}
 
-		Ok(Self { version, role, params })
+		Ok(Self {
+			version,
+			role,
+			capabilities,
+			sticky,
+			params,
+		})
 	}
 }
 
@@ -45,7 +59,17 @@ impl Encode for Server {
 		self.version.encode(w)?;
 
 		let mut params = self.params.clone();
-		params.set(0, self.role)?;
+		params.set_typed(self.role)?;
+
+		// Omitted when empty, which is indistinguishable from an older peer.
+		if !self.capabilities.is_empty() {
+			params.set_typed(self.capabilities)?;
+		}
+
+		if let Some(sticky) = &self.sticky {
+			params.set_typed(sticky.clone())?;
+		}
+
 		params.encode(w)?;
 
 		Ok(())
@@ -64,6 +88,8 @@ mod tests {
 		let client = Server {
 			version: Version::DRAFT_03,
 			role: Role::Both,
+			capabilities: Capabilities::default(),
+			sticky: None,
 			params: Params::default(),
 		};
 
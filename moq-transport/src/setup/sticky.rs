@@ -0,0 +1,49 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Param};
+
+/// An opaque token identifying a relay behind anycast or layer-4 load balancing, sent as a parameter in SETUP.
+///
+/// The server advertises its own token, and a reconnecting client sends back the token it was given, ex. in a
+/// GOAWAY redirect URL, so it can be routed to a relay that already has its broadcast warm.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StickyToken(pub String);
+
+impl StickyToken {
+	/// The parameter ID used for the token, in the range reserved for extensions.
+	pub const PARAM: u64 = 0x3b;
+
+	pub fn new(token: impl Into<String>) -> Self {
+		Self(token.into())
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl From<String> for StickyToken {
+	fn from(token: String) -> Self {
+		Self(token)
+	}
+}
+
+impl From<&str> for StickyToken {
+	fn from(token: &str) -> Self {
+		Self(token.to_string())
+	}
+}
+
+impl Param for StickyToken {
+	const ID: u64 = Self::PARAM;
+}
+
+impl Decode for StickyToken {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(Self(String::decode(r)?))
+	}
+}
+
+impl Encode for StickyToken {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.0.encode(w)
+	}
+}
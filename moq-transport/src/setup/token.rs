@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, Param, Params};
+
+/// An opaque authorization token, ex. a JWT, sent as a parameter in SETUP, ANNOUNCE, and SUBSCRIBE.
+///
+/// The token is redacted when debug printed, since messages are logged.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct AuthToken(pub String);
+
+impl AuthToken {
+	/// The parameter ID used for the token, matching AUTHORIZATION_INFO in the draft.
+	pub const PARAM: u64 = Params::AUTH;
+
+	pub fn new(token: impl Into<String>) -> Self {
+		Self(token.into())
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl fmt::Debug for AuthToken {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "AuthToken(len={})", self.0.len())
+	}
+}
+
+impl From<String> for AuthToken {
+	fn from(token: String) -> Self {
+		Self(token)
+	}
+}
+
+impl From<&str> for AuthToken {
+	fn from(token: &str) -> Self {
+		Self(token.to_string())
+	}
+}
+
+impl Param for AuthToken {
+	const ID: u64 = Self::PARAM;
+}
+
+impl Decode for AuthToken {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(Self(String::decode(r)?))
+	}
+}
+
+impl Encode for AuthToken {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		self.0.encode(w)
+	}
+}
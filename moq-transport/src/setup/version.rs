@@ -1,6 +1,6 @@
 use crate::coding::{Decode, DecodeError, Encode, EncodeError};
 
-use std::ops::Deref;
+use std::{fmt, ops::Deref, str::FromStr};
 
 /// A version number negotiated during the setup.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -18,6 +18,45 @@ impl Version {
 
 	/// https://www.ietf.org/archive/id/draft-ietf-moq-transport-03.html
 	pub const DRAFT_03: Version = Version(0xff000003);
+
+	/// Experimental: [Self::DRAFT_03], except SUBSCRIBE uses the draft-04 encoding with the range as a filter type.
+	/// This is a private version number instead of draft-04's, since every other message is still encoded as draft-03.
+	/// It only interoperates with this crate, and is only negotiated when requested explicitly.
+	pub const DRAFT_03_FILTER: Version = Version(0xff00f003);
+}
+
+impl fmt::Display for Version {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if *self == Self::DRAFT_03_FILTER {
+			return write!(f, "draft-03-filter");
+		}
+
+		match self.0.checked_sub(0xff000000) {
+			Some(draft) if draft <= 0xff => write!(f, "draft-{:02}", draft),
+			_ => write!(f, "{:#x}", self.0),
+		}
+	}
+}
+
+impl FromStr for Version {
+	type Err = String;
+
+	/// Parse a draft version, ex. `draft-03`, or a hex version number, ex. `0xff000003`.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s == "draft-03-filter" {
+			return Ok(Self::DRAFT_03_FILTER);
+		}
+
+		if let Some(draft) = s.strip_prefix("draft-") {
+			let draft: u64 = draft.parse().map_err(|_| format!("invalid draft: {}", s))?;
+			return Ok(Self(0xff000000 + draft));
+		}
+
+		let hex = s.strip_prefix("0x").ok_or_else(|| format!("invalid version: {}", s))?;
+		u64::from_str_radix(hex, 16)
+			.map(Self)
+			.map_err(|_| format!("invalid version: {}", s))
+	}
 }
 
 impl From<u64> for Version {
@@ -51,6 +90,24 @@ impl Encode for Version {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Versions(Vec<Version>);
 
+impl Versions {
+	/// Every version offered and accepted by default.
+	pub fn supported() -> Self {
+		[Version::DRAFT_03].into()
+	}
+
+	/// Every version with a wire format implemented by this crate, including experimental ones like
+	/// [Version::DRAFT_03_FILTER] that are only used when requested explicitly.
+	pub fn implemented() -> Self {
+		[Version::DRAFT_03_FILTER, Version::DRAFT_03].into()
+	}
+
+	/// The highest version in both lists, if any.
+	pub fn negotiate(&self, other: &Versions) -> Option<Version> {
+		self.iter().filter(|version| other.contains(version)).max().copied()
+	}
+}
+
 impl Decode for Versions {
 	/// Decode the version list.
 	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
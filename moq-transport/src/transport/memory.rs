@@ -0,0 +1,14 @@
+//! An in-process transport for tests, avoiding sockets and certificates.
+
+use super::mux;
+
+/// The amount of data buffered in each direction before writes block.
+const BUFFER: usize = 64 * 1024;
+
+/// Create a connected (client, server) pair of sessions.
+///
+/// Both sessions run in background tasks, so this must be called within a tokio runtime.
+pub fn pair() -> (mux::Session, mux::Session) {
+	let (client, server) = tokio::io::duplex(BUFFER);
+	(mux::Session::new(client, false), mux::Session::new(server, true))
+}
@@ -0,0 +1,223 @@
+//! The transport underneath a [Session](crate::session::Session).
+//!
+//! This is normally WebTransport or raw QUIC via [web_transport].
+//! The [mux] module provides a fallback over any reliable byte stream, ex. TCP+TLS, for when UDP is blocked.
+//! The `memory` module (requires the `test-util` feature) connects two sessions in-process for tests.
+
+pub mod mux;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod memory;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::session::SessionError;
+
+/// A session able to accept/create streams and send/recv datagrams.
+///
+/// The session can be cloned to create multiple handles.
+#[derive(Clone)]
+pub enum Session {
+	WebTransport(web_transport::Session),
+	Mux(mux::Session),
+}
+
+impl Session {
+	/// Block until the peer creates a new unidirectional stream.
+	pub async fn accept_uni(&mut self) -> Result<RecvStream, SessionError> {
+		Ok(match self {
+			Self::WebTransport(session) => session.accept_uni().await?.into(),
+			Self::Mux(session) => session.accept_uni().await?.into(),
+		})
+	}
+
+	/// Block until the peer creates a new bidirectional stream.
+	pub async fn accept_bi(&mut self) -> Result<(SendStream, RecvStream), SessionError> {
+		Ok(match self {
+			Self::WebTransport(session) => {
+				let (send, recv) = session.accept_bi().await?;
+				(send.into(), recv.into())
+			}
+			Self::Mux(session) => {
+				let (send, recv) = session.accept_bi().await?;
+				(send.into(), recv.into())
+			}
+		})
+	}
+
+	/// Open a new bidirectional stream, which may block when there are too many concurrent streams.
+	pub async fn open_bi(&mut self) -> Result<(SendStream, RecvStream), SessionError> {
+		Ok(match self {
+			Self::WebTransport(session) => {
+				let (send, recv) = session.open_bi().await?;
+				(send.into(), recv.into())
+			}
+			Self::Mux(session) => {
+				let (send, recv) = session.open_bi().await?;
+				(send.into(), recv.into())
+			}
+		})
+	}
+
+	/// Open a new unidirectional stream, which may block when there are too many concurrent streams.
+	pub async fn open_uni(&mut self) -> Result<SendStream, SessionError> {
+		Ok(match self {
+			Self::WebTransport(session) => session.open_uni().await?.into(),
+			Self::Mux(session) => session.open_uni().await?.into(),
+		})
+	}
+
+	/// Send a datagram, which may be dropped for any reason.
+	pub async fn send_datagram(&mut self, payload: Bytes) -> Result<(), SessionError> {
+		match self {
+			Self::WebTransport(session) => session.send_datagram(payload).await?,
+			Self::Mux(session) => session.send_datagram(payload).await?,
+		};
+
+		Ok(())
+	}
+
+	/// The maximum size of a datagram that can be sent.
+	pub async fn max_datagram_size(&self) -> usize {
+		match self {
+			Self::WebTransport(session) => session.max_datagram_size().await,
+			Self::Mux(session) => session.max_datagram_size().await,
+		}
+	}
+
+	/// Receive a datagram.
+	pub async fn recv_datagram(&mut self) -> Result<Bytes, SessionError> {
+		Ok(match self {
+			Self::WebTransport(session) => session.recv_datagram().await?,
+			Self::Mux(session) => session.recv_datagram().await?,
+		})
+	}
+
+	/// Close the session immediately with a code and reason.
+	pub fn close(self, code: u32, reason: &str) {
+		match self {
+			Self::WebTransport(session) => session.close(code, reason),
+			Self::Mux(session) => session.close(code, reason),
+		}
+	}
+
+	/// Block until the session is closed.
+	pub async fn closed(&self) -> SessionError {
+		match self {
+			Self::WebTransport(session) => session.closed().await.into(),
+			Self::Mux(session) => session.closed().await.into(),
+		}
+	}
+}
+
+impl From<web_transport::Session> for Session {
+	fn from(session: web_transport::Session) -> Self {
+		Self::WebTransport(session)
+	}
+}
+
+impl From<mux::Session> for Session {
+	fn from(session: mux::Session) -> Self {
+		Self::Mux(session)
+	}
+}
+
+/// An outgoing stream of bytes to the peer.
+///
+/// The stream will be closed with a graceful FIN when dropped.
+pub enum SendStream {
+	WebTransport(web_transport::SendStream),
+	Mux(mux::SendStream),
+}
+
+impl SendStream {
+	/// Write some of the given buffer to the stream, potentially blocking on flow control.
+	pub async fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Result<usize, SessionError> {
+		Ok(match self {
+			Self::WebTransport(stream) => stream.write_buf(buf).await?,
+			Self::Mux(stream) => stream.write_buf(buf).await?,
+		})
+	}
+
+	/// Write the entire chunk of bytes to the stream.
+	pub async fn write_chunk(&mut self, buf: Bytes) -> Result<(), SessionError> {
+		match self {
+			Self::WebTransport(stream) => stream.write_chunk(buf).await?,
+			Self::Mux(stream) => stream.write_chunk(buf).await?,
+		};
+
+		Ok(())
+	}
+
+	/// Set the stream's priority, where higher values are sent first.
+	pub fn set_priority(&mut self, order: i32) {
+		match self {
+			Self::WebTransport(stream) => stream.set_priority(order),
+			Self::Mux(stream) => stream.set_priority(order),
+		}
+	}
+
+	/// Send an immediate reset code, closing the stream.
+	pub fn reset(self, code: u32) {
+		match self {
+			Self::WebTransport(stream) => stream.reset(code),
+			Self::Mux(stream) => stream.reset(code),
+		}
+	}
+}
+
+impl From<web_transport::SendStream> for SendStream {
+	fn from(stream: web_transport::SendStream) -> Self {
+		Self::WebTransport(stream)
+	}
+}
+
+impl From<mux::SendStream> for SendStream {
+	fn from(stream: mux::SendStream) -> Self {
+		Self::Mux(stream)
+	}
+}
+
+/// An incoming stream of bytes from the peer.
+pub enum RecvStream {
+	WebTransport(web_transport::RecvStream),
+	Mux(mux::RecvStream),
+}
+
+impl RecvStream {
+	/// Read some data into the provided buffer, returning false when the stream is finished.
+	pub async fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Result<bool, SessionError> {
+		Ok(match self {
+			Self::WebTransport(stream) => stream.read_buf(buf).await?,
+			Self::Mux(stream) => stream.read_buf(buf).await?,
+		})
+	}
+
+	/// Read the next chunk of data with the provided maximum size.
+	pub async fn read_chunk(&mut self, max: usize) -> Result<Option<Bytes>, SessionError> {
+		Ok(match self {
+			Self::WebTransport(stream) => stream.read_chunk(max).await?,
+			Self::Mux(stream) => stream.read_chunk(max).await?,
+		})
+	}
+
+	/// Send a STOP_SENDING code, closing the stream.
+	pub fn stop(self, code: u32) {
+		match self {
+			Self::WebTransport(stream) => stream.stop(code),
+			Self::Mux(stream) => stream.stop(code),
+		}
+	}
+}
+
+impl From<web_transport::RecvStream> for RecvStream {
+	fn from(stream: web_transport::RecvStream) -> Self {
+		Self::WebTransport(stream)
+	}
+}
+
+impl From<mux::RecvStream> for RecvStream {
+	fn from(stream: mux::RecvStream) -> Self {
+		Self::Mux(stream)
+	}
+}
@@ -0,0 +1,941 @@
+//! A stream multiplexer over any reliable, ordered byte stream, ex. TCP+TLS.
+//!
+//! This is a fallback for networks that block UDP and is primarily meant for debugging.
+//! There's no per-stream flow control or prioritization, so expect head-of-line blocking.
+//! Instead there's a hard cap on the data buffered for each stream and the session, and the number of streams waiting
+//! to be accepted; a peer that exceeds them is misbehaving and the session is closed.
+//!
+//! Each frame starts with a type, followed by the fields:
+//! - STREAM: id, size, payload
+//! - FIN: id
+//! - RESET: id, code
+//! - STOP: id, code
+//! - DATAGRAM: size, payload
+//! - CLOSE: code, reason
+//!
+//! Streams are opened implicitly by the first frame.
+//! Like QUIC, the lowest bit of the ID indicates the initiator (1 = server) and the second bit a unidirectional stream.
+
+use std::{
+	cmp,
+	collections::HashMap,
+	io,
+	sync::{Arc, Mutex},
+	time,
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, VarInt};
+use crate::watch::{Queue, State};
+
+/// The maximum payload size of a STREAM or DATAGRAM frame.
+const MAX_FRAME: usize = 16 * 1024;
+
+/// The maximum number of bytes queued for the socket before writes block.
+const MAX_QUEUED: usize = 1024 * 1024;
+
+/// The maximum number of streams that can be implicitly opened by a single frame.
+const MAX_OPEN: u64 = 1024;
+
+/// The maximum number of streams opened by the peer that haven't been accepted yet.
+const MAX_PENDING: usize = 1024;
+
+/// The maximum number of unread bytes buffered for a single stream.
+const MAX_STREAM_BUFFERED: usize = 4 * 1024 * 1024;
+
+/// The maximum number of unread bytes buffered across all streams and datagrams.
+const MAX_BUFFERED: usize = 16 * 1024 * 1024;
+
+/// The maximum size of the reason in a CLOSE frame.
+const MAX_REASON: usize = 1024;
+
+/// The code used to close the session when the peer exceeds a limit, matching a protocol violation.
+const FLOW_CONTROL_CODE: u32 = 0x3;
+
+/// How long to wait for the CLOSE frame to be written after the peer exceeds a limit.
+const CLOSE_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum MuxError {
+	#[error("closed by peer: code={0} reason={1}")]
+	Closed(u32, String),
+
+	#[error("locally closed")]
+	LocallyClosed,
+
+	#[error("stream reset: code={0}")]
+	Reset(u32),
+
+	#[error("stream stopped: code={0}")]
+	Stopped(u32),
+
+	#[error("invalid stream: {0}")]
+	InvalidStream(u64),
+
+	#[error("flow control: {0}")]
+	FlowControl(&'static str),
+
+	#[error("decode error: {0}")]
+	Decode(#[from] DecodeError),
+
+	#[error("encode error: {0}")]
+	Encode(#[from] EncodeError),
+
+	#[error("io error: {0}")]
+	Io(Arc<io::Error>),
+}
+
+impl From<io::Error> for MuxError {
+	fn from(err: io::Error) -> Self {
+		Self::Io(Arc::new(err))
+	}
+}
+
+#[derive(Debug)]
+enum Frame {
+	Stream { id: u64, payload: Bytes },
+	Fin { id: u64 },
+	Reset { id: u64, code: u32 },
+	Stop { id: u64, code: u32 },
+	Datagram { payload: Bytes },
+	Close { code: u32, reason: String },
+}
+
+impl Frame {
+	fn decode_payload<R: Buf>(r: &mut R) -> Result<Bytes, DecodeError> {
+		let size = usize::decode(r)?;
+		if size > MAX_FRAME {
+			return Err(DecodeError::InvalidValue);
+		}
+
+		Self::decode_remaining(r, size)?;
+		Ok(r.copy_to_bytes(size))
+	}
+
+	fn decode_reason<R: Buf>(r: &mut R) -> Result<String, DecodeError> {
+		let size = usize::decode(r)?;
+		if size > MAX_REASON {
+			return Err(DecodeError::InvalidValue);
+		}
+
+		Self::decode_remaining(r, size)?;
+		let mut buf = vec![0; size];
+		r.copy_to_slice(&mut buf);
+		Ok(String::from_utf8(buf)?)
+	}
+
+	fn encode_payload<W: BufMut>(w: &mut W, payload: &Bytes) -> Result<(), EncodeError> {
+		payload.len().encode(w)?;
+		Self::encode_remaining(w, payload.len())?;
+		w.put_slice(payload);
+		Ok(())
+	}
+}
+
+impl Decode for Frame {
+	fn decode<R: Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let typ = u64::decode(r)?;
+		Ok(match typ {
+			0x0 => Self::Stream {
+				id: u64::decode(r)?,
+				payload: Self::decode_payload(r)?,
+			},
+			0x1 => Self::Fin { id: u64::decode(r)? },
+			0x2 => Self::Reset {
+				id: u64::decode(r)?,
+				code: VarInt::decode(r)?.try_into()?,
+			},
+			0x3 => Self::Stop {
+				id: u64::decode(r)?,
+				code: VarInt::decode(r)?.try_into()?,
+			},
+			0x4 => Self::Datagram {
+				payload: Self::decode_payload(r)?,
+			},
+			0x5 => Self::Close {
+				code: VarInt::decode(r)?.try_into()?,
+				reason: Self::decode_reason(r)?,
+			},
+			_ => return Err(DecodeError::InvalidMessage(typ)),
+		})
+	}
+}
+
+impl Encode for Frame {
+	fn encode<W: BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+		match self {
+			Self::Stream { id, payload } => {
+				0x0u64.encode(w)?;
+				id.encode(w)?;
+				Self::encode_payload(w, payload)
+			}
+			Self::Fin { id } => {
+				0x1u64.encode(w)?;
+				id.encode(w)
+			}
+			Self::Reset { id, code } => {
+				0x2u64.encode(w)?;
+				id.encode(w)?;
+				VarInt::from(*code).encode(w)
+			}
+			Self::Stop { id, code } => {
+				0x3u64.encode(w)?;
+				id.encode(w)?;
+				VarInt::from(*code).encode(w)
+			}
+			Self::Datagram { payload } => {
+				0x4u64.encode(w)?;
+				Self::encode_payload(w, payload)
+			}
+			Self::Close { code, reason } => {
+				0x5u64.encode(w)?;
+				VarInt::from(*code).encode(w)?;
+				reason.encode(w)
+			}
+		}
+	}
+}
+
+// Events delivered to a RecvStream.
+enum Recv {
+	Data(Bytes),
+	Fin,
+	Reset(u32),
+}
+
+// A frame waiting to be written, holding onto its share of the queue budget.
+type Outgoing = (Frame, Option<OwnedSemaphorePermit>);
+
+struct SharedState {
+	server: bool,
+
+	// The number of streams we've opened of each type.
+	next_bi: u64,
+	next_uni: u64,
+
+	// Incoming data for each stream and the number of unread bytes, removed when the RecvStream is dropped.
+	recv: HashMap<u64, (Queue<Recv>, usize)>,
+
+	// The number of unread bytes across all streams and datagrams.
+	buffered: usize,
+
+	// The number of streams opened by the peer that haven't been accepted yet.
+	pending: usize,
+
+	// Set when the peer sends STOP for one of our streams.
+	stopped: HashMap<u64, Option<u32>>,
+
+	outgoing: Queue<Outgoing>,
+}
+
+struct Shared {
+	state: Mutex<SharedState>,
+	queued: Arc<Semaphore>,
+	closed: State<Option<MuxError>>,
+}
+
+impl Shared {
+	fn send(&self, frame: Frame, permit: Option<OwnedSemaphorePermit>) {
+		self.state.lock().unwrap().outgoing.push((frame, permit)).ok();
+	}
+
+	fn error(&self) -> Option<MuxError> {
+		self.closed.lock().clone()
+	}
+
+	fn check(&self) -> Result<(), MuxError> {
+		match self.error() {
+			Some(err) => Err(err),
+			None => Ok(()),
+		}
+	}
+
+	fn set_error(&self, err: MuxError) -> bool {
+		let state = self.closed.lock();
+		if state.is_some() {
+			return false;
+		}
+
+		match state.into_mut() {
+			Some(mut state) => {
+				*state = Some(err);
+				true
+			}
+			None => false,
+		}
+	}
+
+	fn close(&self, code: u32, reason: &str) {
+		if self.set_error(MuxError::LocallyClosed) {
+			self.send_close(code, reason);
+		}
+	}
+
+	fn send_close(&self, code: u32, reason: &str) {
+		// Truncate the reason so the peer doesn't reject it.
+		let mut size = cmp::min(reason.len(), MAX_REASON);
+		while !reason.is_char_boundary(size) {
+			size -= 1;
+		}
+
+		let reason = reason[..size].to_string();
+		self.send(Frame::Close { code, reason }, None);
+	}
+
+	// Mark some data as read, freeing up buffer space.
+	fn consumed(&self, id: Option<u64>, size: usize) {
+		let mut state = self.state.lock().unwrap();
+		state.buffered -= size;
+
+		if let Some((_, buffered)) = id.and_then(|id| state.recv.get_mut(&id)) {
+			*buffered -= size;
+		}
+	}
+
+	fn stream(self: &Arc<Self>, id: u64, bi: bool) -> (Option<SendStream>, RecvStream) {
+		let mut state = self.state.lock().unwrap();
+		let (send, recv) = Queue::default().split();
+
+		state.recv.insert(id, (send, 0));
+		if bi {
+			state.stopped.insert(id, None);
+		}
+
+		drop(state);
+
+		let send = bi.then(|| SendStream::new(self, id));
+		let recv = RecvStream::new(self, id, recv);
+
+		(send, recv)
+	}
+}
+
+/// A multiplexed session over a byte stream, mirroring the [web_transport::Session] API.
+///
+/// The session can be cloned to create multiple handles.
+/// The session will be closed on drop.
+#[derive(Clone)]
+pub struct Session {
+	shared: Arc<Shared>,
+
+	accept_bi: Queue<(SendStream, RecvStream)>,
+	accept_uni: Queue<RecvStream>,
+	datagrams: Queue<Bytes>,
+
+	_close: Arc<SessionClose>,
+}
+
+impl Session {
+	/// Run the multiplexer over the provided byte stream in a background task.
+	///
+	/// Each side must provide a different value for `server` so stream IDs don't collide.
+	pub fn new<T: AsyncRead + AsyncWrite + Send + 'static>(io: T, server: bool) -> Self {
+		let (outgoing_send, outgoing_recv) = Queue::default().split();
+		let (accept_bi_send, accept_bi_recv) = Queue::default().split();
+		let (accept_uni_send, accept_uni_recv) = Queue::default().split();
+		let (datagrams_send, datagrams_recv) = Queue::default().split();
+
+		let shared = Arc::new(Shared {
+			state: Mutex::new(SharedState {
+				server,
+				next_bi: 0,
+				next_uni: 0,
+				recv: Default::default(),
+				buffered: 0,
+				pending: 0,
+				stopped: Default::default(),
+				outgoing: outgoing_send,
+			}),
+			queued: Arc::new(Semaphore::new(MAX_QUEUED)),
+			closed: Default::default(),
+		});
+
+		let driver = Driver {
+			shared: shared.clone(),
+			accept_bi: accept_bi_send,
+			accept_uni: accept_uni_send,
+			datagrams: datagrams_send,
+			peer_bi: 0,
+			peer_uni: 0,
+		};
+
+		tokio::spawn(driver.run(io, outgoing_recv));
+
+		Self {
+			_close: Arc::new(SessionClose { shared: shared.clone() }),
+			shared,
+			accept_bi: accept_bi_recv,
+			accept_uni: accept_uni_recv,
+			datagrams: datagrams_recv,
+		}
+	}
+
+	/// Block until the peer creates a new unidirectional stream.
+	pub async fn accept_uni(&mut self) -> Result<RecvStream, MuxError> {
+		match self.accept_uni.pop().await {
+			Some(stream) => {
+				self.shared.state.lock().unwrap().pending -= 1;
+				Ok(stream)
+			}
+			None => Err(self.shared.error().unwrap_or(MuxError::LocallyClosed)),
+		}
+	}
+
+	/// Block until the peer creates a new bidirectional stream.
+	pub async fn accept_bi(&mut self) -> Result<(SendStream, RecvStream), MuxError> {
+		match self.accept_bi.pop().await {
+			Some(stream) => {
+				self.shared.state.lock().unwrap().pending -= 1;
+				Ok(stream)
+			}
+			None => Err(self.shared.error().unwrap_or(MuxError::LocallyClosed)),
+		}
+	}
+
+	/// Open a new bidirectional stream.
+	pub async fn open_bi(&mut self) -> Result<(SendStream, RecvStream), MuxError> {
+		self.shared.check()?;
+
+		let id = {
+			let mut state = self.shared.state.lock().unwrap();
+			let id = state.next_bi << 2 | state.server as u64;
+			state.next_bi += 1;
+			id
+		};
+
+		let (send, recv) = self.shared.stream(id, true);
+		Ok((send.unwrap(), recv))
+	}
+
+	/// Open a new unidirectional stream.
+	pub async fn open_uni(&mut self) -> Result<SendStream, MuxError> {
+		self.shared.check()?;
+
+		let id = {
+			let mut state = self.shared.state.lock().unwrap();
+			let id = state.next_uni << 2 | 0x2 | state.server as u64;
+			state.next_uni += 1;
+			state.stopped.insert(id, None);
+			id
+		};
+
+		Ok(SendStream::new(&self.shared, id))
+	}
+
+	/// Send a datagram, which is dropped if it's too large or too much data is queued.
+	pub async fn send_datagram(&mut self, payload: Bytes) -> Result<(), MuxError> {
+		self.shared.check()?;
+
+		if payload.len() > MAX_FRAME {
+			return Ok(());
+		}
+
+		if let Ok(permit) = self.shared.queued.clone().try_acquire_many_owned(payload.len() as u32) {
+			self.shared.send(Frame::Datagram { payload }, Some(permit));
+		}
+
+		Ok(())
+	}
+
+	/// The maximum size of a datagram that can be sent.
+	pub async fn max_datagram_size(&self) -> usize {
+		MAX_FRAME
+	}
+
+	/// Receive a datagram.
+	pub async fn recv_datagram(&mut self) -> Result<Bytes, MuxError> {
+		match self.datagrams.pop().await {
+			Some(datagram) => {
+				self.shared.consumed(None, datagram.len());
+				Ok(datagram)
+			}
+			None => Err(self.shared.error().unwrap_or(MuxError::LocallyClosed)),
+		}
+	}
+
+	/// Close the session immediately with a code and reason.
+	pub fn close(self, code: u32, reason: &str) {
+		self.shared.close(code, reason);
+	}
+
+	/// Block until the session is closed.
+	pub async fn closed(&self) -> MuxError {
+		loop {
+			{
+				let state = self.shared.closed.lock();
+				if let Some(err) = state.clone() {
+					return err;
+				}
+
+				match state.modified() {
+					Some(notify) => notify,
+					None => return MuxError::LocallyClosed,
+				}
+			}
+			.await;
+		}
+	}
+}
+
+struct SessionClose {
+	shared: Arc<Shared>,
+}
+
+impl Drop for SessionClose {
+	fn drop(&mut self) {
+		self.shared.close(0, "");
+	}
+}
+
+struct Driver {
+	shared: Arc<Shared>,
+
+	accept_bi: Queue<(SendStream, RecvStream)>,
+	accept_uni: Queue<RecvStream>,
+	datagrams: Queue<Bytes>,
+
+	// The number of streams the peer has opened of each type.
+	peer_bi: u64,
+	peer_uni: u64,
+}
+
+impl Driver {
+	async fn run<T: AsyncRead + AsyncWrite + Send + 'static>(mut self, io: T, outgoing: Queue<Outgoing>) {
+		let (reader, writer) = tokio::io::split(io);
+		let shared = self.shared.clone();
+
+		let write = Self::run_write(writer, outgoing);
+		tokio::pin!(write);
+
+		let err = tokio::select! {
+			err = self.run_read(reader) => err,
+			err = &mut write => err,
+		};
+
+		// Tell the peer why we're closing, giving up if it's not reading either.
+		if let MuxError::FlowControl(reason) = err {
+			shared.send_close(FLOW_CONTROL_CODE, reason);
+			tokio::time::timeout(CLOSE_TIMEOUT, write).await.ok();
+		}
+
+		if shared.set_error(err.clone()) {
+			log::debug!("mux session closed: {}", err);
+		}
+
+		// Wake up any readers.
+		shared.state.lock().unwrap().recv.clear();
+	}
+
+	async fn run_read<R: AsyncRead + Unpin>(&mut self, mut reader: R) -> MuxError {
+		let mut buffer = BytesMut::new();
+
+		loop {
+			let mut cursor = io::Cursor::new(&buffer);
+
+			let frame = match Frame::decode(&mut cursor) {
+				Ok(frame) => {
+					buffer.advance(cursor.position() as usize);
+					frame
+				}
+				Err(DecodeError::More(_)) => {
+					match reader.read_buf(&mut buffer).await {
+						Ok(0) => return io::Error::from(io::ErrorKind::UnexpectedEof).into(),
+						Ok(_) => continue,
+						Err(err) => return err.into(),
+					};
+				}
+				Err(err) => return err.into(),
+			};
+
+			if let Err(err) = self.recv_frame(frame) {
+				return err;
+			}
+		}
+	}
+
+	fn recv_frame(&mut self, frame: Frame) -> Result<(), MuxError> {
+		match frame {
+			Frame::Stream { id, payload } => self.recv_stream(id, Recv::Data(payload)),
+			Frame::Fin { id } => self.recv_stream(id, Recv::Fin),
+			Frame::Reset { id, code } => self.recv_stream(id, Recv::Reset(code)),
+			Frame::Stop { id, code } => {
+				if let Some(stopped) = self.shared.state.lock().unwrap().stopped.get_mut(&id) {
+					*stopped = Some(code);
+				}
+				Ok(())
+			}
+			Frame::Datagram { payload } => {
+				// Datagrams are unreliable, so drop them instead of closing the session.
+				let mut state = self.shared.state.lock().unwrap();
+				if state.buffered + payload.len() <= MAX_BUFFERED {
+					state.buffered += payload.len();
+					self.datagrams.push(payload).ok();
+				}
+
+				Ok(())
+			}
+			Frame::Close { code, reason } => Err(MuxError::Closed(code, reason)),
+		}
+	}
+
+	fn recv_stream(&mut self, id: u64, event: Recv) -> Result<(), MuxError> {
+		self.open_streams(id)?;
+
+		let mut state = self.shared.state.lock().unwrap();
+		let state = &mut *state;
+
+		// Otherwise the stream was already stopped, so ignore any late frames.
+		// NOTE: The entry is removed by the RecvStream, as dropping the queue would discard any unread events.
+		let Some((recv, buffered)) = state.recv.get_mut(&id) else {
+			return Ok(());
+		};
+
+		if let Recv::Data(data) = &event {
+			if *buffered + data.len() > MAX_STREAM_BUFFERED {
+				return Err(MuxError::FlowControl("stream buffer exceeded"));
+			}
+
+			if state.buffered + data.len() > MAX_BUFFERED {
+				return Err(MuxError::FlowControl("session buffer exceeded"));
+			}
+
+			*buffered += data.len();
+			state.buffered += data.len();
+		}
+
+		recv.push(event).ok();
+
+		Ok(())
+	}
+
+	// Implicitly open any streams created by the peer, up to and including the given ID.
+	fn open_streams(&mut self, id: u64) -> Result<(), MuxError> {
+		let server = self.shared.state.lock().unwrap().server;
+		if (id & 0x1 == 1) == server {
+			// We opened the stream; the peer can only send on it if it's bidirectional.
+			return match id & 0x2 {
+				0 => Ok(()),
+				_ => Err(MuxError::InvalidStream(id)),
+			};
+		}
+
+		let uni = id & 0x2 != 0;
+		let index = id >> 2;
+		let next = match uni {
+			true => &mut self.peer_uni,
+			false => &mut self.peer_bi,
+		};
+
+		if index < *next {
+			return Ok(());
+		}
+
+		if index - *next >= MAX_OPEN {
+			return Err(MuxError::InvalidStream(id));
+		}
+
+		let start = *next;
+		*next = index + 1;
+
+		let mut state = self.shared.state.lock().unwrap();
+		state.pending += (index + 1 - start) as usize;
+		if state.pending > MAX_PENDING {
+			return Err(MuxError::FlowControl("too many pending streams"));
+		}
+
+		drop(state);
+
+		for index in start..=index {
+			let id = index << 2 | id & 0x3;
+
+			match self.shared.stream(id, !uni) {
+				(Some(send), recv) => self.accept_bi.push((send, recv)).ok(),
+				(None, recv) => self.accept_uni.push(recv).ok(),
+			};
+		}
+
+		Ok(())
+	}
+
+	async fn run_write<W: AsyncWrite + Unpin>(mut writer: W, mut outgoing: Queue<Outgoing>) -> MuxError {
+		let mut buffer = BytesMut::new();
+
+		while let Some((frame, _permit)) = outgoing.pop().await {
+			buffer.clear();
+
+			if let Err(err) = frame.encode(&mut buffer) {
+				return err.into();
+			}
+
+			if let Err(err) = writer.write_all(&buffer).await {
+				return err.into();
+			}
+
+			if let Err(err) = writer.flush().await {
+				return err.into();
+			}
+
+			if let Frame::Close { .. } = frame {
+				writer.shutdown().await.ok();
+				break;
+			}
+		}
+
+		MuxError::LocallyClosed
+	}
+}
+
+/// An outgoing stream of bytes to the peer.
+///
+/// The stream will be closed with a graceful FIN when dropped.
+pub struct SendStream {
+	shared: Arc<Shared>,
+	id: u64,
+	done: bool,
+}
+
+impl SendStream {
+	fn new(shared: &Arc<Shared>, id: u64) -> Self {
+		Self {
+			shared: shared.clone(),
+			id,
+			done: false,
+		}
+	}
+
+	fn check(&self) -> Result<(), MuxError> {
+		self.shared.check()?;
+
+		match self.shared.state.lock().unwrap().stopped.get(&self.id) {
+			Some(Some(code)) => Err(MuxError::Stopped(*code)),
+			_ => Ok(()),
+		}
+	}
+
+	/// Write some of the buffer to the stream, blocking if too much data is queued.
+	pub async fn write(&mut self, buf: &[u8]) -> Result<usize, MuxError> {
+		let size = cmp::min(buf.len(), MAX_FRAME);
+		self.write_chunk(Bytes::copy_from_slice(&buf[..size])).await?;
+		Ok(size)
+	}
+
+	/// Write some of the given buffer to the stream, blocking if too much data is queued.
+	pub async fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Result<usize, MuxError> {
+		let size = cmp::min(buf.chunk().len(), MAX_FRAME);
+		self.write_chunk(buf.copy_to_bytes(size)).await?;
+		Ok(size)
+	}
+
+	/// Write the entire chunk of bytes to the stream.
+	pub async fn write_chunk(&mut self, mut buf: Bytes) -> Result<(), MuxError> {
+		while !buf.is_empty() {
+			self.check()?;
+
+			let payload = buf.split_to(cmp::min(buf.len(), MAX_FRAME));
+			let permit = self
+				.shared
+				.queued
+				.clone()
+				.acquire_many_owned(payload.len() as u32)
+				.await
+				.map_err(|_| MuxError::LocallyClosed)?;
+
+			self.shared.send(Frame::Stream { id: self.id, payload }, Some(permit));
+		}
+
+		Ok(())
+	}
+
+	/// Does nothing, as all streams share the same TCP connection.
+	pub fn set_priority(&mut self, _order: i32) {}
+
+	/// Send an immediate reset code, closing the stream.
+	pub fn reset(mut self, code: u32) {
+		self.done = true;
+		self.shared.send(Frame::Reset { id: self.id, code }, None);
+	}
+}
+
+impl Drop for SendStream {
+	fn drop(&mut self) {
+		self.shared.state.lock().unwrap().stopped.remove(&self.id);
+
+		if !self.done {
+			self.shared.send(Frame::Fin { id: self.id }, None);
+		}
+	}
+}
+
+/// An incoming stream of bytes from the peer.
+///
+/// The stream will be closed with STOP code=0 when dropped.
+pub struct RecvStream {
+	shared: Arc<Shared>,
+	id: u64,
+	incoming: Queue<Recv>,
+	buffer: Bytes,
+	done: bool,
+}
+
+impl RecvStream {
+	fn new(shared: &Arc<Shared>, id: u64, incoming: Queue<Recv>) -> Self {
+		Self {
+			shared: shared.clone(),
+			id,
+			incoming,
+			buffer: Bytes::new(),
+			done: false,
+		}
+	}
+
+	/// Read some data into the provided buffer.
+	pub async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, MuxError> {
+		Ok(self.read_chunk(buf.len()).await?.map(|chunk| {
+			buf[..chunk.len()].copy_from_slice(&chunk);
+			chunk.len()
+		}))
+	}
+
+	/// Read some data into the provided buffer.
+	pub async fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Result<bool, MuxError> {
+		match self.read_chunk(buf.remaining_mut()).await? {
+			Some(chunk) => {
+				buf.put(chunk);
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	/// Read the next chunk of data with the provided maximum size.
+	pub async fn read_chunk(&mut self, max: usize) -> Result<Option<Bytes>, MuxError> {
+		while self.buffer.is_empty() {
+			if self.done {
+				return Ok(None);
+			}
+
+			match self.incoming.pop().await {
+				Some(Recv::Data(data)) => {
+					self.shared.consumed(Some(self.id), data.len());
+					self.buffer = data;
+				}
+				Some(Recv::Fin) => self.done = true,
+				Some(Recv::Reset(code)) => {
+					self.done = true;
+					return Err(MuxError::Reset(code));
+				}
+				None => return Err(self.shared.error().unwrap_or(MuxError::LocallyClosed)),
+			}
+		}
+
+		let size = cmp::min(max, self.buffer.len());
+		Ok(Some(self.buffer.split_to(size)))
+	}
+
+	/// Send a STOP code, closing the stream.
+	pub fn stop(mut self, code: u32) {
+		self.stop_code(code);
+	}
+
+	fn stop_code(&mut self, code: u32) {
+		// Free up any data that will never be read.
+		let mut state = self.shared.state.lock().unwrap();
+		if let Some((_, buffered)) = state.recv.remove(&self.id) {
+			state.buffered -= buffered;
+		}
+
+		drop(state);
+
+		if !self.done {
+			self.done = true;
+			self.shared.send(Frame::Stop { id: self.id, code }, None);
+		}
+	}
+}
+
+impl Drop for RecvStream {
+	fn drop(&mut self) {
+		self.stop_code(0);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A session along with the raw byte stream of its peer.
+	fn raw() -> (Session, tokio::io::DuplexStream) {
+		let (client, server) = tokio::io::duplex(64 * 1024);
+		(Session::new(server, true), client)
+	}
+
+	async fn send<W: AsyncWrite + Unpin>(writer: &mut W, frame: Frame) {
+		let mut buf = BytesMut::new();
+		frame.encode(&mut buf).unwrap();
+		writer.write_all(&buf).await.unwrap();
+	}
+
+	#[tokio::test]
+	async fn stream_buffer() {
+		let (mut session, peer) = raw();
+		let (mut reader, mut writer) = tokio::io::split(peer);
+
+		// Read everything the session sends so it's never blocked.
+		let closed = tokio::spawn(async move {
+			let mut buf = Vec::new();
+			reader.read_to_end(&mut buf).await.ok();
+			Frame::decode(&mut buf.as_slice()).ok().map(|frame| format!("{:?}", frame))
+		});
+
+		// Open a unidirectional stream that's accepted but never read.
+		send(&mut writer, Frame::Fin { id: 0x2 }).await;
+		let _stream = session.accept_uni().await.unwrap();
+
+		let payload = Bytes::from(vec![0; MAX_FRAME]);
+		for _ in 0..=MAX_STREAM_BUFFERED / MAX_FRAME {
+			let frame = Frame::Stream {
+				id: 0x6,
+				payload: payload.clone(),
+			};
+
+			// The session may close before we finish writing.
+			let mut buf = BytesMut::new();
+			frame.encode(&mut buf).unwrap();
+			if writer.write_all(&buf).await.is_err() {
+				break;
+			}
+		}
+
+		assert!(matches!(session.closed().await, MuxError::FlowControl(_)));
+		drop(writer);
+
+		let frame = closed.await.unwrap().unwrap();
+		assert!(frame.starts_with("Close { code: 3"), "{}", frame);
+	}
+
+	#[tokio::test]
+	async fn pending_streams() {
+		let (session, mut peer) = raw();
+
+		// Each frame opens every unidirectional stream up to its ID, none of which are accepted.
+		send(&mut peer, Frame::Fin { id: (MAX_OPEN - 1) << 2 | 0x2 }).await;
+		send(&mut peer, Frame::Fin { id: MAX_OPEN << 2 | 0x2 }).await;
+
+		assert!(matches!(session.closed().await, MuxError::FlowControl(_)));
+	}
+
+	#[test]
+	fn close_reason() {
+		let frame = Frame::Close {
+			code: 0,
+			reason: "x".repeat(MAX_REASON + 1),
+		};
+
+		let mut buf = BytesMut::new();
+		frame.encode(&mut buf).unwrap();
+		assert!(matches!(Frame::decode(&mut buf), Err(DecodeError::InvalidValue)));
+	}
+}
@@ -64,7 +64,7 @@ impl<T> State<T> {
 		}
 	}
 
-	pub fn lock(&self) -> StateRef<T> {
+	pub fn lock(&self) -> StateRef<'_, T> {
 		StateRef {
 			state: self.state.clone(),
 			drop: self.drop.clone(),
@@ -72,7 +72,7 @@ impl<T> State<T> {
 		}
 	}
 
-	pub fn lock_mut(&self) -> Option<StateMut<T>> {
+	pub fn lock_mut(&self) -> Option<StateMut<'_, T>> {
 		let lock = self.state.lock().unwrap();
 		lock.dropped?;
 		Some(StateMut {
@@ -81,6 +81,28 @@ impl<T> State<T> {
 		})
 	}
 
+	// Like lock_mut, but succeeds even if the other half was dropped, ex. to evict data that won't change again.
+	pub fn lock_mut_force(&self) -> StateMut<'_, T> {
+		StateMut {
+			lock: self.state.lock().unwrap(),
+			_drop: self.drop.clone(),
+		}
+	}
+
+	// Returns a future that resolves when either half is dropped, without keeping this half alive.
+	pub fn dropped(&self) -> StateDropped<T> {
+		StateDropped {
+			state: self.state.clone(),
+		}
+	}
+
+	// Returns a handle that can read the value without keeping either half alive, or closing it when dropped.
+	pub fn observe(&self) -> StateObserver<T> {
+		StateObserver {
+			state: Arc::downgrade(&self.state),
+		}
+	}
+
 	pub fn downgrade(&self) -> StateWeak<T> {
 		StateWeak {
 			state: Arc::downgrade(&self.state),
@@ -217,6 +239,25 @@ impl<T> Future for StateChanged<T> {
 	}
 }
 
+pub struct StateDropped<T> {
+	state: Arc<Mutex<StateInner<T>>>,
+}
+
+impl<T> Future for StateDropped<T> {
+	type Output = ();
+
+	fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+		let mut state = self.state.lock().unwrap();
+
+		if state.dropped.is_none() {
+			task::Poll::Ready(())
+		} else {
+			state.register(cx.waker());
+			task::Poll::Pending
+		}
+	}
+}
+
 pub struct StateWeak<T> {
 	state: Weak<Mutex<StateInner<T>>>,
 	drop: Weak<StateDrop<T>>,
@@ -241,6 +282,27 @@ impl<T> Clone for StateWeak<T> {
 	}
 }
 
+pub struct StateObserver<T> {
+	state: Weak<Mutex<StateInner<T>>>,
+}
+
+impl<T> StateObserver<T> {
+	// Returns None once every other handle to the state was dropped.
+	pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+		let state = self.state.upgrade()?;
+		let lock = state.lock().unwrap();
+		Some(f(&lock.value))
+	}
+}
+
+impl<T> Clone for StateObserver<T> {
+	fn clone(&self) -> Self {
+		Self {
+			state: self.state.clone(),
+		}
+	}
+}
+
 struct StateDrop<T> {
 	state: Arc<Mutex<StateInner<T>>>,
 }